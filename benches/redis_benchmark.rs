@@ -1,8 +1,15 @@
 use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use redis_imitate::storage::geo::GeoAddOptions;
 use redis_imitate::storage::memory::MemoryStorage;
-use redis_imitate::commands::parser::Command;
+use redis_imitate::storage::zset::ZAddOptions;
+use redis_imitate::commands::parser::{Command, CommandParser};
 use redis_imitate::commands::executor::CommandExecutor;
+use redis_imitate::network::connection::Connection;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
 use std::sync::{Arc, Mutex};
+use std::thread;
 
 fn bench_set(c: &mut Criterion) {
     let storage = Arc::new(Mutex::new(MemoryStorage::new()));
@@ -52,5 +59,82 @@ fn bench_rpop(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, bench_set, bench_get, bench_lpush, bench_rpop);
+fn bench_pipelined_get(c: &mut Criterion) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+
+    let storage = Arc::new(Mutex::new(MemoryStorage::new()));
+    let executor = Arc::new(CommandExecutor::new(Arc::clone(&storage)));
+    executor.execute_command(Command::Set("test_key".to_string(), "test_value".to_string()));
+    let mut connection = Connection::new(server, executor);
+    thread::spawn(move || connection.process());
+
+    let pipeline = "GET test_key\n".repeat(10);
+    let mut writer = client.try_clone().unwrap();
+    let mut reader = BufReader::new(client);
+    let mut line = String::new();
+
+    c.bench_function("PIPELINED GET x10", |b| {
+        b.iter(|| {
+            writer.write_all(pipeline.as_bytes()).unwrap();
+            for _ in 0..10 {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+            }
+        })
+    });
+}
+
+fn bench_zrangebyscore_narrow(c: &mut Criterion) {
+    let storage = Arc::new(Mutex::new(MemoryStorage::new()));
+    let executor = CommandExecutor::new(Arc::clone(&storage));
+
+    // A narrow range over a million members: the B-tree range scan should
+    // cost about as much as it does on a small set
+    let pairs: Vec<(f64, String)> = (0..1_000_000).map(|i| (i as f64, format!("member{}", i))).collect();
+    storage.lock().unwrap().zadd("board", &pairs, &ZAddOptions::default()).unwrap();
+
+    c.bench_function("ZRANGEBYSCORE 1M members, 10 in range", |b| {
+        b.iter(|| {
+            executor.execute_command(CommandParser::parse("ZRANGEBYSCORE board 500000 (500010"))
+        })
+    });
+}
+
+fn bench_geosearch_count_any(c: &mut Criterion) {
+    let storage = Arc::new(Mutex::new(MemoryStorage::new()));
+    let executor = CommandExecutor::new(Arc::clone(&storage));
+
+    // A million members across 10 by 10 degrees, a few hundred of them
+    // within 10 km of the centre: ANY stops at the first one it finds,
+    // COUNT alone has to find them all to return the closest
+    let mut rng = StdRng::seed_from_u64(1);
+    let items: Vec<(f64, f64, String)> = (0..1_000_000)
+        .map(|i| (rng.gen_range(-5.0..5.0), rng.gen_range(-5.0..5.0), format!("member{}", i)))
+        .collect();
+    storage.lock().unwrap().geoadd("points", &items, &GeoAddOptions::default()).unwrap();
+
+    c.bench_function("GEOSEARCH 1M members, COUNT 1 ANY", |b| {
+        b.iter(|| {
+            executor.execute_command(CommandParser::parse("GEOSEARCH points FROMLONLAT 0 0 BYRADIUS 10 km COUNT 1 ANY"))
+        })
+    });
+    c.bench_function("GEOSEARCH 1M members, COUNT 1", |b| {
+        b.iter(|| {
+            executor.execute_command(CommandParser::parse("GEOSEARCH points FROMLONLAT 0 0 BYRADIUS 10 km COUNT 1"))
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_set,
+    bench_get,
+    bench_lpush,
+    bench_rpop,
+    bench_pipelined_get,
+    bench_zrangebyscore_narrow,
+    bench_geosearch_count_any
+);
 criterion_main!(benches);
\ No newline at end of file
@@ -43,8 +43,8 @@ impl RedisClient {
                 Ok(0) => break,
                 Ok(n) => {
                     response.push_str(&String::from_utf8_lossy(&buf[..n]));
-                    if response.ends_with("\r\n") {
-                        break;
+                    if let Some(reply) = Self::complete_reply(&response) {
+                        return Ok(reply);
                     }
                 },
                 Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
@@ -61,6 +61,20 @@ impl RedisClient {
 
         Ok(response.trim().to_string())
     }
+
+    /// Returns the reply once all of it has arrived, one line per value.
+    ///
+    /// Multi-line replies start with a `*<count>` header giving the number of
+    /// lines that follow; anything else is a single line.
+    fn complete_reply(response: &str) -> Option<String> {
+        let body = response.strip_suffix("\r\n")?;
+        let lines: Vec<&str> = body.split("\r\n").collect();
+        match lines[0].strip_prefix('*').and_then(|count| count.parse::<usize>().ok()) {
+            Some(count) if lines.len() > count => Some(lines[1..=count].join("\n")),
+            Some(_) => None,
+            None => Some(lines[0].to_string()),
+        }
+    }
 }
 
 fn main() -> io::Result<()> {
@@ -79,7 +93,14 @@ fn main() -> io::Result<()> {
             break;
         }
 
+        let is_shutdown = input.split_whitespace().next()
+            .is_some_and(|command| command.eq_ignore_ascii_case("shutdown"));
         match client.send_command(input) {
+            // A successful SHUTDOWN closes the connection without replying
+            Ok(response) if is_shutdown && response.is_empty() => {
+                println!("Server shut down.");
+                break;
+            },
             Ok(response) => println!("{}", response),
             Err(e) => eprintln!("Error: {}", e),
         }
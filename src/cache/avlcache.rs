@@ -22,6 +22,9 @@ struct Node<K: Ord + Clone, V> {
     timestamp: Instant,
 }
 
+/// An owned, possibly empty subtree
+type Link<K, V> = Option<Box<Node<K, V>>>;
+
 pub struct AVLCache<K: Ord + Clone, V: Clone> {
     root: Option<Box<Node<K, V>>>,
     capacity: usize,
@@ -215,7 +218,7 @@ impl<K: Ord + Clone, V: Clone> AVLCache<K, V> {
         }
     }
 
-    fn remove_min(mut node: Box<Node<K, V>>) -> (Option<Box<Node<K, V>>>, Box<Node<K, V>>) {
+    fn remove_min(mut node: Box<Node<K, V>>) -> (Link<K, V>, Box<Node<K, V>>) {
         if node.left.is_none() {
             (node.right.take(), node)
         } else {
@@ -272,6 +275,25 @@ impl<K: Ord + Clone, V: Clone> AVLCache<K, V> {
         self.root.as_ref().map(|node| min_node(node))
     }
 
+    /// Removes every item older than the TTL, returning how many were removed
+    pub fn purge_expired(&mut self) -> usize {
+        fn collect<K: Ord + Clone, V>(node: &Link<K, V>, ttl: Duration, now: Instant, expired: &mut Vec<K>) {
+            if let Some(node) = node {
+                if now.duration_since(node.timestamp) >= ttl {
+                    expired.push(node.key.clone());
+                }
+                collect(&node.left, ttl, now, expired);
+                collect(&node.right, ttl, now, expired);
+            }
+        }
+        let mut expired = Vec::new();
+        collect(&self.root, self.ttl, Instant::now(), &mut expired);
+        for key in &expired {
+            self.remove(key);
+        }
+        expired.len()
+    }
+
     /// Removes all items from the cache
     pub fn clear(&mut self) {
         self.root = None;
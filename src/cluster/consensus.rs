@@ -1,41 +1,83 @@
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::sync::Mutex;
 use tokio::time::{sleep, Duration};
 
 use super::error::{RaftError, RaftResult};
-use super::message::{RaftMessage, LogEntry};
+use super::message::{ConfigChange, RaftMessage, LogEntry};
 use super::state::{RaftState, NodeRole};
 use super::transport::Transport;
-use super::log_store::LogStore;
+use super::log_store::{LogStore, Snapshot, SnapshotMetadata};
+use super::metrics::RaftMetrics;
+use super::replication::ReplicationAcks;
 
 pub struct RaftConsensus<T: Transport + 'static, L: LogStore + 'static> {
     pub state: Arc<Mutex<RaftState>>,
     pub transport: Arc<T>,
     pub log_store: Arc<Mutex<L>>,
-    pub cluster: Arc<HashMap<String, String>>, // node_id -> address
+    // node_id -> address of every peer in the committed configuration
+    pub cluster: Arc<Mutex<HashMap<String, String>>>,
+    // Set once this node commits its own removal; it then never stands for election
+    pub removed: Arc<AtomicBool>,
+    // Highest committed index whose membership change, if any, has been applied
+    applied_config_index: Arc<Mutex<u64>>,
     
     pub next_index: Arc<Mutex<HashMap<String, u64>>>,   
     pub match_index: Arc<Mutex<HashMap<String, u64>>>,  
+
+    pub metrics: Arc<RaftMetrics>,
+    pub acks: Arc<ReplicationAcks>,
 }
 
 impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
+    /// Creates the consensus module of a node
+    ///
+    /// If the log store keeps its files in a directory, the node's term and
+    /// vote are persisted there too and loaded from it here, so a restarted
+    /// node can't vote twice in the same term. `cluster` is the membership
+    /// the node starts with; committed `ConfigChange` entries change it.
     pub fn new(
         node_id: String,
         transport: Arc<T>,
         log_store: Arc<Mutex<L>>,
-        cluster: HashMap<String, String>,
-    ) -> Arc<Self> {
-        let consensus = Arc::new(RaftConsensus {
-            state: Arc::new(Mutex::new(RaftState::new(node_id, None))),
+        mut cluster: HashMap<String, String>,
+    ) -> RaftResult<Arc<Self>> {
+        // The cluster map only tracks peers; a node never replicates to itself
+        cluster.remove(&node_id);
+
+        let dir = log_store
+            .try_lock()
+            .map_err(|_| RaftError::State("log store is in use".to_string()))?
+            .dir()
+            .map(Path::to_path_buf);
+        let state = match dir {
+            Some(dir) => RaftState::open(node_id, None, &dir)?,
+            None => RaftState::new(node_id, None),
+        };
+
+        Ok(Arc::new(RaftConsensus {
+            state: Arc::new(Mutex::new(state)),
             transport,
             log_store,
-            cluster: Arc::new(cluster),
+            cluster: Arc::new(Mutex::new(cluster)),
+            removed: Arc::new(AtomicBool::new(false)),
+            applied_config_index: Arc::new(Mutex::new(0)),
             next_index: Arc::new(Mutex::new(HashMap::new())),
             match_index: Arc::new(Mutex::new(HashMap::new())),
-        });
+            metrics: Arc::new(RaftMetrics::new()),
+            acks: Arc::new(ReplicationAcks::new()),
+        }))
+    }
 
-        consensus
+    pub fn metrics(&self) -> Arc<RaftMetrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Ids of the peers in the committed configuration
+    pub async fn peers(&self) -> Vec<String> {
+        self.cluster.lock().await.keys().cloned().collect()
     }
 
     pub async fn start(self: Arc<Self>) -> RaftResult<()> {
@@ -51,24 +93,30 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
     async fn initialize_leader_state(&self) -> RaftResult<()> {
         let last_log_index = self.log_store.lock().await.last_index()?;
         
+        let peers = self.peers().await;
         let mut next_index = self.next_index.lock().await;
         let mut match_index = self.match_index.lock().await;
         
-        for peer_id in self.cluster.keys() {
+        for peer_id in peers {
             next_index.insert(peer_id.clone(), last_log_index + 1);
-            match_index.insert(peer_id.clone(), 0);
+            match_index.insert(peer_id, 0);
         }
         
         Ok(())
     }
 
     async fn handle_election_timeout(&self) -> RaftResult<()> {
+        if self.removed.load(Ordering::Relaxed) {
+            return Ok(());
+        }
         let should_begin;
         {
             let mut state = self.state.lock().await;
             should_begin = state.should_begin_election();
             if should_begin {
-                state.begin_election();
+                state.begin_election()?;
+                self.metrics.elections_started.fetch_add(1, Ordering::Relaxed);
+                self.metrics.current_term.store(state.current_term, Ordering::Relaxed);
             }
         }
         
@@ -87,14 +135,13 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
                 last_log_term,
             };
             
-            for peer_id in self.cluster.keys() {
+            for peer_id in self.peers().await {
                 let transport = Arc::clone(&self.transport);
                 let request = request.clone();
-                let peer_id = peer_id.clone();
                 
                 tokio::spawn(async move {
                     if let Err(e) = transport.send(&peer_id, request).await {
-                        eprintln!("Failed to send vote request to {}: {}", peer_id, e);
+                        tracing::warn!(peer_id = %peer_id, error = %e, "failed to send vote request");
                     }
                 });
             }
@@ -110,7 +157,7 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
         last_log_index: u64,
         last_log_term: u64
     ) -> RaftResult<()> {
-        let (vote_granted, current_term) = {
+        let (vote_granted, current_term, node_id) = {
             let mut state = self.state.lock().await;
             let vote_granted = state.handle_vote_request(
                 &candidate_id,
@@ -118,10 +165,11 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
                 last_log_index,
                 last_log_term
             )?;
-            (vote_granted, state.current_term)
+            (vote_granted, state.current_term, state.node_id.clone())
         };
         
         let response = RaftMessage::RequestVoteResponse {
+            from: node_id,
             term: current_term,
             vote_granted,
         };
@@ -133,9 +181,12 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
 
     pub async fn handle_vote_response(
         &self,
+        voter_id: String,
         term: u64,
         vote_granted: bool
     ) -> RaftResult<()> {
+        tracing::debug!(voter_id = %voter_id, term, vote_granted, "vote response");
+        let cluster_size = self.cluster.lock().await.len() + 1;
         let need_initialize = {
             let mut state = self.state.lock().await;
             
@@ -146,8 +197,10 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
                 state.receive_vote(vote_granted);
                 
                 // 检查是否获得多数票
-                if state.check_election_won(self.cluster.len() + 1) {
+                if state.check_election_won(cluster_size) {
                     state.become_leader();
+                    self.metrics.elections_won.fetch_add(1, Ordering::Relaxed);
+                    self.metrics.current_term.store(state.current_term, Ordering::Relaxed);
                     true
                 } else {
                     false
@@ -179,14 +232,14 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
             }
         };
         
-        for peer_id in self.cluster.keys() {
+        for peer_id in self.peers().await {
             let transport = Arc::clone(&self.transport);
             let heartbeat = heartbeat.clone();
-            let peer_id = peer_id.clone();
+            self.metrics.heartbeats_sent.fetch_add(1, Ordering::Relaxed);
             
             tokio::spawn(async move {
                 if let Err(e) = transport.send(&peer_id, heartbeat).await {
-                    eprintln!("Failed to send heartbeat to {}: {}", peer_id, e);
+                    tracing::warn!(peer_id = %peer_id, error = %e, "failed to send heartbeat");
                 }
             });
         }
@@ -201,7 +254,7 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
             loop {
                 sleep(Duration::from_millis(100)).await;
                 if let Err(e) = consensus.handle_election_timeout().await {
-                    eprintln!("Error in election timer: {}", e);
+                    tracing::error!(error = %e, "error in election timer");
                 }
             }
         });
@@ -223,7 +276,7 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
                 
                 if should_send {
                     if let Err(e) = consensus.replicate_logs().await {
-                        eprintln!("Error in log replication: {}", e);
+                        tracing::error!(error = %e, "error in log replication");
                     }
                 }
             }
@@ -241,22 +294,24 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
             (state.current_term, state.node_id.clone())
         };
 
-        for peer_id in self.cluster.keys() {
+        for peer_id in self.peers().await {
             let next_index = {
                 let next_indices = self.next_index.lock().await;
-                next_indices.get(peer_id).cloned().unwrap_or(1)
+                next_indices.get(&peer_id).cloned().unwrap_or(1)
             };
 
+            // The entry the peer needs next was compacted away; only the snapshot can catch it up
+            if next_index < self.log_store.lock().await.first_index()? {
+                self.send_snapshot(&peer_id, term, &node_id).await?;
+                continue;
+            }
+
             let prev_log_index = next_index - 1;
-            let prev_log_term = if prev_log_index == 0 {
-                0
-            } else {
-                match self.log_store.lock().await.get(prev_log_index)? {
-                    Some(entry) => entry.term,
-                    None => {
-                        eprintln!("Previous log entry not found at index {}", prev_log_index);
-                        continue;
-                    }
+            let prev_log_term = match self.log_store.lock().await.term_at(prev_log_index)? {
+                Some(term) => term,
+                None => {
+                    tracing::warn!(index = prev_log_index, "previous log entry not found");
+                    continue;
                 }
             };
 
@@ -279,49 +334,64 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
             };
 
             let transport = Arc::clone(&self.transport);
-            let peer_id = peer_id.clone();
             let next_index_ref = Arc::clone(&self.next_index);
-            let match_index_ref = Arc::clone(&self.match_index);
-            let consensus = Arc::new(RaftConsensus {
-                state: Arc::clone(&self.state),
-                transport: Arc::clone(&self.transport),
-                log_store: Arc::clone(&self.log_store),
-                cluster: Arc::clone(&self.cluster),
-                next_index: Arc::clone(&self.next_index),
-                match_index: Arc::clone(&self.match_index),
-            });
-            let entries_len = entries.len();
+            self.metrics.record_replication(&entries);
 
+            // The peer's indices move on its AppendEntriesResponse
             tokio::spawn(async move {
-                match transport.send(&peer_id, request).await {
-                    Ok(_) => {
-                        // Wait for response after successful sending...
-                        // In actual implementation, the response should be received through some mechanism
-                        // Here, the process is simplified and it is assumed that it is always successful
-                        if entries_len > 0 {
-                            let mut next_indices = next_index_ref.lock().await;
-                            let mut match_indices = match_index_ref.lock().await;
-                            
-                            let new_next_index = next_index + entries_len as u64;
-                            next_indices.insert(peer_id.clone(), new_next_index);
-                            match_indices.insert(peer_id.clone(), new_next_index - 1);
-
-                            consensus.update_commit_index().await.unwrap_or_else(|e| {
-                                eprintln!("Failed to update commit index: {}", e);
-                            });
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to send AppendEntries to {}: {}", peer_id, e);
-                        let mut next_indices = next_index_ref.lock().await;
-                        if let Some(index) = next_indices.get_mut(&peer_id) {
-                            *index = (*index).saturating_sub(1);
-                        }
+                if let Err(e) = transport.send(&peer_id, request).await {
+                    tracing::warn!(peer_id = %peer_id, error = %e, "failed to send AppendEntries");
+                    let mut next_indices = next_index_ref.lock().await;
+                    if let Some(index) = next_indices.get_mut(&peer_id) {
+                        *index = (*index).saturating_sub(1).max(1);
                     }
                 }
             });
         }
 
+        self.update_replication_lag().await?;
+
+        Ok(())
+    }
+
+    /// Sends the log store's latest snapshot to a peer whose next entry was compacted
+    ///
+    /// The peer's indices move on its `InstallSnapshotResponse`, not here.
+    async fn send_snapshot(&self, peer_id: &str, term: u64, node_id: &str) -> RaftResult<()> {
+        let request = match self.log_store.lock().await.latest_snapshot() {
+            Some(snapshot) => RaftMessage::InstallSnapshot {
+                term,
+                leader_id: node_id.to_string(),
+                last_included_index: snapshot.metadata.last_index,
+                last_included_term: snapshot.metadata.last_term,
+                data: snapshot.data.clone(),
+                done: true,
+            },
+            None => {
+                tracing::warn!(peer_id = %peer_id, "log was compacted but no snapshot is held");
+                return Ok(());
+            }
+        };
+
+        let transport = Arc::clone(&self.transport);
+        let peer_id = peer_id.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = transport.send(&peer_id, request).await {
+                tracing::warn!(peer_id = %peer_id, error = %e, "failed to send InstallSnapshot");
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn update_replication_lag(&self) -> RaftResult<()> {
+        let committed_index = self.log_store.lock().await.committed_index()?;
+        let max_entries_behind = self.match_index.lock().await
+            .values()
+            .map(|&match_idx| committed_index.saturating_sub(match_idx))
+            .max()
+            .unwrap_or(0);
+        self.metrics.update_replication_lag(max_entries_behind);
         Ok(())
     }
 
@@ -336,9 +406,11 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
     ) -> RaftResult<()> {
         let mut success = false;
         let current_term;
+        let node_id;
 
         {
             let mut state = self.state.lock().await;
+            node_id = state.node_id.clone();
             
             if term < state.current_term {
                 current_term = state.current_term;
@@ -347,17 +419,10 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
                 state.role = NodeRole::Follower;
                 current_term = term;
 
-                let log_ok = if prev_log_index == 0 {
-                    true
-                } else {
-                    match self.log_store.lock().await.get(prev_log_index)? {
-                        Some(entry) => entry.term == prev_log_term,
-                        None => false
-                    }
-                };
+                let log_ok = self.log_store.lock().await.term_at(prev_log_index)? == Some(prev_log_term);
 
                 if log_ok {
-                    if prev_log_index + 1 <= self.log_store.lock().await.last_index()? {
+                    if prev_log_index < self.log_store.lock().await.last_index()? {
                         self.log_store.lock().await.delete_from(prev_log_index + 1)?;
                     }
 
@@ -377,7 +442,12 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
             }
         }
 
+        if success {
+            self.apply_committed_config().await?;
+        }
+
         let response = RaftMessage::AppendEntriesResponse {
+            from: node_id,
             term: current_term,
             success,
             match_index: if success {
@@ -392,6 +462,80 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
         Ok(())
     }
 
+    /// Installs a leader's snapshot on a follower too far behind for AppendEntries
+    ///
+    /// The snapshot is restored into the log store, leaving the follower's
+    /// log committed up to `last_included_index`; the node applies the
+    /// entries it holds like any other committed ones. The response carries
+    /// the follower's commit index, so a snapshot no newer than it, or one
+    /// not marked `done`, is answered without being restored.
+    pub async fn handle_install_snapshot(
+        &self,
+        term: u64,
+        leader_id: String,
+        last_included_index: u64,
+        last_included_term: u64,
+        data: Vec<u8>,
+        done: bool
+    ) -> RaftResult<()> {
+        let current_term;
+        let node_id;
+        let mut match_index = 0;
+
+        {
+            let mut state = self.state.lock().await;
+            node_id = state.node_id.clone();
+
+            if term < state.current_term {
+                current_term = state.current_term;
+            } else {
+                state.update_term(term)?;
+                state.role = NodeRole::Follower;
+                current_term = term;
+
+                let mut log_store = self.log_store.lock().await;
+                if done && last_included_index > log_store.committed_index()? {
+                    let snapshot = Snapshot {
+                        metadata: SnapshotMetadata {
+                            last_index: last_included_index,
+                            last_term: last_included_term,
+                            timestamp: std::time::SystemTime::now()
+                                .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                                .unwrap()
+                                .as_secs(),
+                        },
+                        data,
+                    };
+                    log_store.restore_snapshot(bincode::serialize(&snapshot)?)?;
+                }
+                match_index = log_store.committed_index()?;
+            }
+        }
+
+        self.apply_committed_config().await?;
+
+        let response = RaftMessage::InstallSnapshotResponse {
+            from: node_id,
+            term: current_term,
+            match_index,
+        };
+
+        self.transport.send(&leader_id, response).await?;
+
+        Ok(())
+    }
+
+    /// Moves a follower's indices past the snapshot it acknowledged
+    pub async fn handle_install_snapshot_response(
+        &self,
+        follower_id: String,
+        term: u64,
+        match_index: u64
+    ) -> RaftResult<()> {
+        // Committed entries match the leader's, so the follower's log does up to its commit index
+        self.handle_append_entries_response(follower_id, term, true, match_index).await
+    }
+
     pub async fn handle_append_entries_response(
         &self,
         follower_id: String,
@@ -418,20 +562,22 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
                 next_indices.insert(follower_id.clone(), match_index + 1);
                 match_indices.insert(follower_id.clone(), match_index);
             }
+            self.acks.ack(&follower_id, match_index);
 
             drop(state);
             self.update_commit_index().await?;
         } else {
+            // Sending from index 1 always matches, so rejections of earlier requests stop there
             let mut next_indices = self.next_index.lock().await;
             if let Some(index) = next_indices.get_mut(&follower_id) {
-                *index = (*index).saturating_sub(1);
+                *index = (*index).saturating_sub(1).max(1);
             }
         }
 
         Ok(())
     }
 
-    async fn update_commit_index(&self) -> RaftResult<()> {
+    pub async fn update_commit_index(&self) -> RaftResult<()> {
         let (current_term, is_leader) = {
             let state = self.state.lock().await;
             (state.current_term, state.role == NodeRole::Leader)
@@ -441,12 +587,14 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
             return Ok(());
         }
 
-        let last_log_index = self.log_store.lock().await.last_index()?;
+        let (last_log_index, committed_index) = {
+            let log_store = self.log_store.lock().await;
+            (log_store.last_index()?, log_store.committed_index()?)
+        };
+        let peers = self.peers().await;
         let match_indices = self.match_index.lock().await;
         
-        for index in (self.log_store.lock().await.committed_index()?..=last_log_index).rev() {
-            let mut count = 1;
-            
+        for index in (committed_index..=last_log_index).rev() {
             let log_term = match self.log_store.lock().await.get(index)? {
                 Some(entry) => entry.term,
                 None => continue,
@@ -456,18 +604,128 @@ impl<T: Transport + 'static, L: LogStore + 'static> RaftConsensus<T, L> {
                 continue;
             }
 
-            for &match_idx in match_indices.values() {
-                if match_idx >= index {
-                    count += 1;
-                }
-            }
+            // The leader, and every peer of the committed configuration that has the entry
+            let count = 1 + peers
+                .iter()
+                .filter(|peer_id| match_indices.get(*peer_id).is_some_and(|&match_idx| match_idx >= index))
+                .count();
 
-            if count > (self.cluster.len() + 1) / 2 {
+            let cluster_size = peers.len() + 1;
+            if count > cluster_size / 2 {
                 self.log_store.lock().await.commit(index)?;
                 break;
             }
         }
+        drop(match_indices);
+
+        self.apply_committed_config().await
+    }
+
+    /// Appends a membership change to the leader's log
+    ///
+    /// Only one change may be in flight: another is refused until the last
+    /// one has been committed and applied. The change takes effect, and
+    /// counts toward quorum, once `apply_committed_config` sees it committed.
+    ///
+    /// # Returns
+    ///
+    /// The index of the new entry
+    pub async fn propose_config_change(&self, change: ConfigChange) -> RaftResult<u64> {
+        let (term, node_id) = {
+            let state = self.state.lock().await;
+            if state.role != NodeRole::Leader {
+                return Err(RaftError::NotLeader);
+            }
+            (state.current_term, state.node_id.clone())
+        };
+
+        let is_member = match &change {
+            ConfigChange::AddNode { id, .. } | ConfigChange::RemoveNode { id } => {
+                *id == node_id || self.cluster.lock().await.contains_key(id)
+            }
+        };
+        match &change {
+            ConfigChange::AddNode { id, .. } if is_member => {
+                return Err(RaftError::State(format!("node {} is already a member", id)));
+            }
+            ConfigChange::RemoveNode { id } if !is_member => return Err(RaftError::NodeNotFound(id.clone())),
+            _ => {}
+        }
+
+        let applied = *self.applied_config_index.lock().await;
+        let mut log_store = self.log_store.lock().await;
+        let last_index = log_store.last_index()?;
+        let start = (applied + 1).max(log_store.first_index()?);
+        if start <= last_index
+            && log_store.get_range(start, last_index)?.iter().any(|entry| entry.as_config_change().is_some())
+        {
+            return Err(RaftError::State("a membership change is already in progress".to_string()));
+        }
+
+        let index = last_index + 1;
+        log_store.append(vec![LogEntry::config_change(term, index, &change)])?;
+        Ok(index)
+    }
+
+    /// Applies the membership changes committed since the last call
+    ///
+    /// A node that joins gets a connection and replication indices, and one
+    /// that leaves loses them. A node that commits its own removal never
+    /// stands for election again, and steps down if it was leading.
+    pub async fn apply_committed_config(&self) -> RaftResult<()> {
+        let mut applied = self.applied_config_index.lock().await;
+        let changes = {
+            let log_store = self.log_store.lock().await;
+            let committed = log_store.committed_index()?;
+            let start = (*applied + 1).max(log_store.first_index()?);
+            let entries = if start <= committed {
+                log_store.get_range(start, committed)?
+            } else {
+                Vec::new()
+            };
+            *applied = (*applied).max(committed);
+            entries.iter().filter_map(LogEntry::as_config_change).collect::<Vec<_>>()
+        };
+
+        for change in changes {
+            self.apply_config_change(change).await?;
+        }
+        Ok(())
+    }
 
+    async fn apply_config_change(&self, change: ConfigChange) -> RaftResult<()> {
+        let node_id = self.state.lock().await.node_id.clone();
+        tracing::info!(node_id = %node_id, change = ?change, "applying membership change");
+        match change {
+            ConfigChange::AddNode { id, .. } if id == node_id => {
+                self.removed.store(false, Ordering::Relaxed);
+            }
+            ConfigChange::AddNode { id, addr } => {
+                self.cluster.lock().await.insert(id.clone(), addr.clone());
+                let last_log_index = self.log_store.lock().await.last_index()?;
+                self.next_index.lock().await.entry(id.clone()).or_insert(last_log_index + 1);
+                self.match_index.lock().await.entry(id.clone()).or_insert(0);
+                if let Err(e) = self.transport.add_node(id.clone(), addr).await {
+                    tracing::warn!(peer_id = %id, error = %e, "failed to connect to new member");
+                }
+            }
+            ConfigChange::RemoveNode { id } if id == node_id => {
+                self.removed.store(true, Ordering::Relaxed);
+                let mut state = self.state.lock().await;
+                if state.role == NodeRole::Leader {
+                    tracing::info!(node_id = %node_id, "removed from the cluster, stepping down");
+                }
+                state.role = NodeRole::Follower;
+            }
+            ConfigChange::RemoveNode { id } => {
+                self.cluster.lock().await.remove(&id);
+                self.next_index.lock().await.remove(&id);
+                self.match_index.lock().await.remove(&id);
+                if let Err(e) = self.transport.remove_node(&id).await {
+                    tracing::warn!(peer_id = %id, error = %e, "failed to disconnect removed member");
+                }
+            }
+        }
         Ok(())
     }
 }
@@ -478,7 +736,7 @@ pub(crate) mod tests {
     use std::collections::HashMap;
     use std::sync::Arc;
     use tokio::sync::Mutex;
-    use crate::cluster::{log_store::MockLogStore, message::{LogEntry, RaftMessage}, transport::MockTransport};
+    use crate::cluster::{log_store::{MemLogStore, MockLogStore}, message::{LogEntry, RaftMessage}, transport::MockTransport};
     //use async_trait::async_trait;
 
     async fn setup_consensus() -> (Arc<RaftConsensus<MockTransport, MockLogStore>>, Arc<MockTransport>) {
@@ -494,7 +752,7 @@ pub(crate) mod tests {
             Arc::clone(&transport),
             log_store,
             cluster
-        );
+        ).unwrap();
 
         (consensus, transport)
     }
@@ -526,9 +784,45 @@ pub(crate) mod tests {
         assert_eq!(store.snapshots.len(), 1);
     }
 
+    #[tokio::test]
+    async fn test_cluster_map_leaves_out_the_node_itself() {
+        let transport = Arc::new(MockTransport::new("node1".to_string()));
+        let log_store = Arc::new(Mutex::new(MockLogStore::new()));
+
+        let mut cluster = HashMap::new();
+        cluster.insert("node1".to_string(), "addr1".to_string());
+        cluster.insert("node2".to_string(), "addr2".to_string());
+
+        let consensus = RaftConsensus::new("node1".to_string(), transport, log_store, cluster).unwrap();
+        assert_eq!(consensus.peers().await, vec!["node2".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_commit_index_needs_a_majority() {
+        let (consensus, _) = setup_consensus().await;
+        let term = {
+            let mut state = consensus.state.lock().await;
+            state.begin_election().unwrap();
+            state.become_leader();
+            state.current_term
+        };
+        consensus.log_store.lock().await
+            .append(vec![LogEntry::new(term, 1, b"test1".to_vec())])
+            .unwrap();
+
+        // The leader alone is one of three nodes
+        consensus.update_commit_index().await.unwrap();
+        assert_eq!(consensus.log_store.lock().await.committed_index().unwrap(), 0);
+
+        // With one follower it is two of three
+        consensus.match_index.lock().await.insert("node2".to_string(), 1);
+        consensus.update_commit_index().await.unwrap();
+        assert_eq!(consensus.log_store.lock().await.committed_index().unwrap(), 1);
+    }
+
     #[tokio::test]
     async fn test_transport_operations() {
-        let mut transport = MockTransport::new("node1".to_string());
+        let transport = MockTransport::new("node1".to_string());
         
         transport.add_node("node2".to_string(), "addr2".to_string()).await.unwrap();
         
@@ -546,4 +840,190 @@ pub(crate) mod tests {
         let connections = transport.connections.lock().await;
         assert!(connections.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_metrics_count_elections_and_heartbeats() {
+        let (consensus, _transport) = setup_consensus().await;
+        let metrics = consensus.metrics();
+
+        // Not timed out yet, so no election starts
+        consensus.handle_election_timeout().await.unwrap();
+        assert_eq!(metrics.elections_started.load(Ordering::Relaxed), 0);
+
+        consensus.state.lock().await.last_election_time -= Duration::from_secs(1);
+        consensus.handle_election_timeout().await.unwrap();
+        assert_eq!(metrics.elections_started.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.current_term.load(Ordering::Relaxed), 1);
+
+        // One more vote gives a majority of the three-node cluster
+        consensus.handle_vote_response("node2".to_string(), 1, true).await.unwrap();
+        assert_eq!(metrics.elections_won.load(Ordering::Relaxed), 1);
+        // Winning broadcasts a heartbeat to both peers
+        assert_eq!(metrics.heartbeats_sent.load(Ordering::Relaxed), 2);
+
+        consensus.broadcast_heartbeat().await.unwrap();
+        assert_eq!(metrics.heartbeats_sent.load(Ordering::Relaxed), 4);
+        assert!(metrics.render().contains("raft_heartbeats_sent 4\n"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_replicated_entries() {
+        let (consensus, _transport) = setup_consensus().await;
+        let metrics = consensus.metrics();
+        {
+            let mut state = consensus.state.lock().await;
+            state.begin_election().unwrap();
+            state.become_leader();
+        }
+        consensus.log_store.lock().await.append(vec![
+            LogEntry::new(1, 1, b"test1".to_vec()),
+            LogEntry::new(1, 2, b"test2".to_vec()),
+        ]).unwrap();
+
+        // Both entries go to both peers
+        consensus.replicate_logs().await.unwrap();
+        assert_eq!(metrics.log_entries_replicated.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn test_replication_lag_estimate() {
+        let metrics = RaftMetrics::new();
+        metrics.update_replication_lag(10);
+        assert_eq!(metrics.replication_lag_ms.load(Ordering::Relaxed), 0);
+
+        metrics.record_replication(&[LogEntry::new(1, 1, vec![0; 100])]);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        metrics.update_replication_lag(0);
+        assert_eq!(metrics.replication_lag_ms.load(Ordering::Relaxed), 0);
+
+        // 100 bytes took at least 20ms, so 10 more entries take at least 200ms
+        metrics.update_replication_lag(10);
+        assert!(metrics.replication_lag_ms.load(Ordering::Relaxed) >= 200);
+    }
+
+    #[tokio::test]
+    async fn test_append_entries_response_acks_replica() {
+        let (consensus, _transport) = setup_consensus().await;
+        let term = {
+            let mut state = consensus.state.lock().await;
+            state.begin_election().unwrap();
+            state.become_leader();
+            state.current_term
+        };
+
+        consensus.initialize_leader_state().await.unwrap();
+
+        consensus.handle_append_entries_response("node2".to_string(), term, true, 3).await.unwrap();
+        assert_eq!(consensus.acks.acked_count(3), 1);
+        assert_eq!(consensus.next_index.lock().await.get("node2"), Some(&4));
+        assert_eq!(consensus.match_index.lock().await.get("node2"), Some(&3));
+        // Only the sender's indices move
+        assert_eq!(consensus.next_index.lock().await.get("node3"), Some(&1));
+        assert_eq!(consensus.match_index.lock().await.get("node3"), Some(&0));
+
+        consensus.next_index.lock().await.insert("node3".to_string(), 3);
+        consensus.handle_append_entries_response("node3".to_string(), term, false, 0).await.unwrap();
+        assert_eq!(consensus.acks.acked_count(1), 1);
+        assert_eq!(consensus.next_index.lock().await.get("node3"), Some(&2));
+        assert_eq!(consensus.next_index.lock().await.get("node2"), Some(&4));
+    }
+
+    #[tokio::test]
+    async fn test_responses_name_their_sender() {
+        let (consensus, transport) = setup_consensus().await;
+        consensus.handle_vote_request("node2".to_string(), 1, 0, 0).await.unwrap();
+        consensus.handle_append_entries(1, "node2".to_string(), 0, 0, Vec::new(), 0).await.unwrap();
+
+        let messages = transport.get_messages().await;
+        assert_eq!(messages.len(), 2);
+        for (to, message) in &messages {
+            assert_eq!(to, "node2");
+            assert_eq!(message.sender(), "node1");
+        }
+        assert!(matches!(messages[0].1, RaftMessage::RequestVoteResponse { vote_granted: true, .. }));
+        assert!(matches!(messages[1].1, RaftMessage::AppendEntriesResponse { success: true, .. }));
+    }
+    type MemNode = (Arc<RaftConsensus<MockTransport, MemLogStore>>, Arc<MockTransport>);
+
+    fn mem_consensus(node_id: &str, peer_id: &str) -> MemNode {
+        let dir = std::env::temp_dir().join(format!("test_snapshots_{}", uuid::Uuid::new_v4()));
+        let transport = Arc::new(MockTransport::new(node_id.to_string()));
+        let log_store = Arc::new(Mutex::new(MemLogStore::new(dir).unwrap()));
+        let cluster = HashMap::from([(peer_id.to_string(), format!("{}:7000", peer_id))]);
+        let consensus = RaftConsensus::new(node_id.to_string(), Arc::clone(&transport), log_store, cluster).unwrap();
+        (consensus, transport)
+    }
+
+    /// Lets the spawned sends run, then hands every message sent to the other node
+    async fn exchange(nodes: [&MemNode; 2]) {
+        sleep(Duration::from_millis(10)).await;
+        for (from, to) in [(0, 1), (1, 0)] {
+            let messages: Vec<_> = nodes[from].1.messages.lock().await.drain(..).collect();
+            let consensus = &nodes[to].0;
+            for (_, message) in messages {
+                match message {
+                    RaftMessage::AppendEntries { term, leader_id, prev_log_index, prev_log_term, entries, leader_commit } => {
+                        consensus.handle_append_entries(term, leader_id, prev_log_index, prev_log_term, entries, leader_commit).await
+                    }
+                    RaftMessage::AppendEntriesResponse { from, term, success, match_index } => {
+                        consensus.handle_append_entries_response(from, term, success, match_index).await
+                    }
+                    RaftMessage::InstallSnapshot { term, leader_id, last_included_index, last_included_term, data, done } => {
+                        consensus.handle_install_snapshot(term, leader_id, last_included_index, last_included_term, data, done).await
+                    }
+                    RaftMessage::InstallSnapshotResponse { from, term, match_index } => {
+                        consensus.handle_install_snapshot_response(from, term, match_index).await
+                    }
+                    other => panic!("unexpected message {:?}", other),
+                }
+                .unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_install_snapshot_catches_up_an_empty_follower() {
+        let leader = mem_consensus("node1", "node2");
+        let follower = mem_consensus("node2", "node1");
+        {
+            let mut state = leader.0.state.lock().await;
+            state.begin_election().unwrap();
+            state.become_leader();
+        }
+        {
+            let mut log_store = leader.0.log_store.lock().await;
+            log_store.append((1..=3).map(|i| LogEntry::new(1, i, vec![i as u8])).collect()).unwrap();
+            log_store.commit(3).unwrap();
+            log_store.snapshot().unwrap();
+            assert_eq!(log_store.first_index().unwrap(), 4);
+        }
+        leader.0.initialize_leader_state().await.unwrap();
+
+        // The follower refuses entries after 3, and 3 is only in the snapshot
+        leader.0.replicate_logs().await.unwrap();
+        exchange([&leader, &follower]).await;
+        assert_eq!(leader.0.next_index.lock().await.get("node2"), Some(&3));
+
+        leader.0.replicate_logs().await.unwrap();
+        exchange([&leader, &follower]).await;
+        assert_eq!(leader.0.next_index.lock().await.get("node2"), Some(&4));
+        assert_eq!(leader.0.match_index.lock().await.get("node2"), Some(&3));
+        {
+            let log_store = follower.0.log_store.lock().await;
+            assert_eq!(log_store.last_index().unwrap(), 3);
+            assert_eq!(log_store.committed_index().unwrap(), 3);
+            assert_eq!(log_store.get(2).unwrap().unwrap().data, vec![2]);
+        }
+
+        // Replication carries on from the snapshot
+        leader.0.log_store.lock().await.append(vec![LogEntry::new(1, 4, vec![4])]).unwrap();
+        for _ in 0..2 {
+            leader.0.replicate_logs().await.unwrap();
+            exchange([&leader, &follower]).await;
+        }
+        assert_eq!(leader.0.log_store.lock().await.committed_index().unwrap(), 4);
+        let log_store = follower.0.log_store.lock().await;
+        assert_eq!(log_store.get(4).unwrap().unwrap().data, vec![4]);
+        assert_eq!(log_store.committed_index().unwrap(), 4);
+    }
 }
\ No newline at end of file
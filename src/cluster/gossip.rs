@@ -0,0 +1,134 @@
+// src/cluster/gossip.rs
+//! Cluster membership, spread by gossip from a single CLUSTER MEET
+//!
+//! Meeting a node connects to its address and sends it this node's whole
+//! table as `RaftMessage::ClusterGossip`. A node receiving gossip merges
+//! the table into its own, connects to every node it hadn't known and
+//! sends each of them its table in turn, and answers the sender with its
+//! table if the sender's was missing anything. Connecting one node to one
+//! member of an existing cluster is enough for every node to learn every
+//! other; the exchange stops once the tables agree.
+use super::error::RaftResult;
+use super::message::{NodeInfo, RaftMessage};
+use super::state::NodeRole;
+use super::transport::Transport;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// The nodes this node knows of, itself included
+pub struct Topology {
+    node_id: String,
+    nodes: RwLock<HashMap<String, NodeInfo>>,
+    // Addresses met before their node's id is known; the transport knows
+    // each by its address until the node's gossip names it
+    met: RwLock<Vec<String>>,
+}
+
+impl Topology {
+    /// Creates a table holding only this node, reachable at `addr`
+    pub fn new(node_id: String, addr: String) -> Self {
+        let this = NodeInfo { id: node_id.clone(), addr, role: NodeRole::Follower, epoch: 0 };
+        Topology {
+            node_id: node_id.clone(),
+            nodes: RwLock::new(HashMap::from([(node_id, this)])),
+            met: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// Records this node's current role and term, which its gossip reports
+    pub fn update_self(&self, role: NodeRole, epoch: u64) {
+        if let Some(this) = self.nodes.write().unwrap().get_mut(&self.node_id) {
+            this.role = role;
+            this.epoch = epoch;
+        }
+    }
+
+    /// Returns every known node, ordered by id
+    pub fn nodes(&self) -> Vec<NodeInfo> {
+        let mut nodes: Vec<NodeInfo> = self.nodes.read().unwrap().values().cloned().collect();
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+        nodes
+    }
+
+    /// Builds the message carrying this node's whole table
+    pub fn gossip(&self) -> RaftMessage {
+        RaftMessage::ClusterGossip { sender_id: self.node_id.clone(), nodes: self.nodes() }
+    }
+
+    /// Merges a peer's `ClusterGossip` into the table; other messages are ignored
+    ///
+    /// A node already known is updated only if the gossip reports a newer
+    /// epoch, and this node's own entry is never taken from gossip.
+    ///
+    /// # Returns
+    ///
+    /// The nodes the table didn't have, and whether the sender's table was
+    /// missing any node this one knows
+    pub fn merge(&self, message: &RaftMessage) -> (Vec<NodeInfo>, bool) {
+        let RaftMessage::ClusterGossip { nodes: gossiped, .. } = message else {
+            return (Vec::new(), false);
+        };
+        let mut nodes = self.nodes.write().unwrap();
+        let mut learned = Vec::new();
+        for node in gossiped.iter().filter(|node| node.id != self.node_id) {
+            match nodes.get_mut(&node.id) {
+                Some(known) if node.epoch > known.epoch => *known = node.clone(),
+                Some(_) => {}
+                None => {
+                    nodes.insert(node.id.clone(), node.clone());
+                    learned.push(node.clone());
+                }
+            }
+        }
+        let sender_behind = nodes.keys().any(|id| !gossiped.iter().any(|node| node.id == *id));
+        (learned, sender_behind)
+    }
+
+    /// Handles CLUSTER MEET: connects to `addr` and sends it this node's table
+    ///
+    /// The transport knows the connection by `addr` until the node's own
+    /// gossip says which id it has.
+    pub async fn meet<T: Transport>(&self, transport: &T, addr: &str) -> RaftResult<()> {
+        let known = self.nodes.read().unwrap().values().any(|node| node.addr == addr);
+        if known || self.met.read().unwrap().iter().any(|met| met == addr) {
+            return Ok(());
+        }
+        transport.add_node(addr.to_string(), addr.to_string()).await?;
+        self.met.write().unwrap().push(addr.to_string());
+        transport.send(addr, self.gossip()).await
+    }
+
+    /// Handles a peer's `ClusterGossip`
+    ///
+    /// Connects to every node the gossip introduced and sends each of them
+    /// this node's table, then sends the table back to the sender if it was
+    /// missing anything. A node at an address met earlier takes over that
+    /// connection under its id instead of getting a second one.
+    pub async fn handle_gossip<T: Transport>(&self, transport: &T, message: &RaftMessage) -> RaftResult<()> {
+        let (learned, sender_behind) = self.merge(message);
+        for node in &learned {
+            let was_met = {
+                let mut met = self.met.write().unwrap();
+                let position = met.iter().position(|addr| *addr == node.addr);
+                position.map(|position| met.remove(position)).is_some()
+            };
+            if was_met {
+                transport.remove_node(&node.addr).await?;
+            }
+            transport.add_node(node.id.clone(), node.addr.clone()).await?;
+        }
+        let gossip = self.gossip();
+        for node in &learned {
+            transport.send(&node.id, gossip.clone()).await?;
+        }
+        let sender = message.sender();
+        if sender_behind && !learned.iter().any(|node| node.id == sender) {
+            transport.send(sender, gossip).await?;
+        }
+        Ok(())
+    }
+}
@@ -2,10 +2,13 @@
 //use std::sync::{Arc, RwLock};
 use super::message::LogEntry;
 use super::error::{RaftError, RaftResult};
-use std::fs;
-use std::path::PathBuf;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::{self, JoinHandle};
 use serde::{Serialize, Deserialize};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SnapshotMetadata {
@@ -22,9 +25,12 @@ pub struct Snapshot {
 
 pub struct MemLogStore {
     logs: Vec<LogEntry>,
+    // Index of `logs[0]`; the entries before it were compacted into the snapshot
+    first_index: u64,
     committed_index: u64,
     snapshot_dir: PathBuf,
     current_snapshot: Option<Snapshot>,
+    wal: Arc<Mutex<File>>,
 }
 
 pub trait LogStore: Send + Sync {
@@ -40,29 +46,226 @@ pub trait LogStore: Send + Sync {
     
     fn snapshot(&mut self) -> RaftResult<()>;
     fn restore_snapshot(&mut self, data: Vec<u8>) -> RaftResult<()>;
+
+    /// The index of the first entry still in the log; the ones before it
+    /// were compacted into `latest_snapshot`. 1 for a store that never compacts
+    fn first_index(&self) -> RaftResult<u64> {
+        Ok(1)
+    }
+
+    /// The snapshot the log was last compacted into, in the form
+    /// `restore_snapshot` takes once serialized
+    fn latest_snapshot(&self) -> Option<&Snapshot> {
+        None
+    }
+
+    /// The term of the entry at `index`, which for the last entry compacted
+    /// into the snapshot is the snapshot's term; `None` if the log doesn't
+    /// cover `index`
+    fn term_at(&self, index: u64) -> RaftResult<Option<u64>> {
+        if index == 0 {
+            return Ok(Some(0));
+        }
+        if let Some(entry) = self.get(index)? {
+            return Ok(Some(entry.term));
+        }
+        Ok(self.latest_snapshot()
+            .filter(|snapshot| snapshot.metadata.last_index == index)
+            .map(|snapshot| snapshot.metadata.last_term))
+    }
+
+    /// The directory the store keeps its files in, where Raft's hard state
+    /// is kept too; `None` for a store that keeps nothing on disk
+    fn dir(&self) -> Option<&Path> {
+        None
+    }
 }
 
 impl MemLogStore {
     pub fn new(snapshot_dir: PathBuf) -> RaftResult<Self> {
         fs::create_dir_all(&snapshot_dir)?;
+
+        // Replay entries that were appended but never made it into a snapshot
+        let current_snapshot = load_snapshot(&snapshot_dir.join("snapshot.dat"))?;
+        let wal_path = snapshot_dir.join("raft.wal");
+        let logs = replay_wal(&wal_path)?;
+        let wal = OpenOptions::new().create(true).append(true).open(&wal_path)?;
+
+        let snapshot_index = current_snapshot.as_ref().map_or(0, |snapshot| snapshot.metadata.last_index);
+        let first_index = logs.first().map_or(snapshot_index + 1, |entry| entry.index);
         
         Ok(MemLogStore {
-            logs: Vec::new(),
-            committed_index: 0,
+            logs,
+            first_index,
+            // Everything in a snapshot was committed
+            committed_index: snapshot_index,
             snapshot_dir,
-            current_snapshot: None,
+            current_snapshot,
+            wal: Arc::new(Mutex::new(wal)),
         })
     }
 
     fn snapshot_path(&self) -> PathBuf {
         self.snapshot_dir.join("snapshot.dat")
     }
+
+    fn wal_path(&self) -> PathBuf {
+        self.snapshot_dir.join("raft.wal")
+    }
+
+    /// Writes `snapshot` to a temporary file and renames it into place
+    fn write_snapshot(&self, snapshot: &Snapshot) -> RaftResult<()> {
+        let snapshot_bytes = bincode::serialize(snapshot)?;
+        let temp_path = self.snapshot_path().with_extension("tmp");
+        fs::write(&temp_path, snapshot_bytes)?;
+        fs::rename(temp_path, self.snapshot_path())?;
+        Ok(())
+    }
+
+    /// The entries held by the current snapshot, if any
+    fn snapshot_entries(&self) -> RaftResult<Vec<LogEntry>> {
+        match &self.current_snapshot {
+            Some(snapshot) => bincode::deserialize(&snapshot.data)
+                .map_err(|e| RaftError::State(format!("Failed to deserialize logs: {}", e))),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Forces every WAL record written so far onto disk
+    pub fn flush_wal(&self) -> RaftResult<()> {
+        sync_wal(&self.wal)
+    }
+
+    /// Spawns a thread that calls `flush_wal` every `interval`
+    ///
+    /// The interval normally comes from `Config::raft_wal_flush_interval_ms`.
+    /// The thread exits on its own once the store is dropped.
+    pub fn spawn_wal_flusher(&self, interval: Duration) -> JoinHandle<()> {
+        let wal = Arc::downgrade(&self.wal);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            let Some(wal) = Weak::upgrade(&wal) else {
+                return;
+            };
+            if let Err(e) = sync_wal(&wal) {
+                tracing::error!(error = %e, "failed to flush raft WAL");
+            }
+        })
+    }
+
+    /// Writes one WAL record for `entries` before they are applied in memory
+    fn write_wal(&self, entries: &[LogEntry]) -> RaftResult<()> {
+        let record = encode_wal_record(entries)?;
+        let mut wal = self.wal.lock().unwrap();
+        wal.write_all(&record)?;
+        Ok(())
+    }
+
+    /// Replaces the WAL with a single record holding the current in-memory log
+    ///
+    /// Used after compaction so entries already captured by a snapshot are not replayed.
+    fn rewrite_wal(&self) -> RaftResult<()> {
+        let temp_path = self.wal_path().with_extension("tmp");
+        let mut file = File::create(&temp_path)?;
+        if !self.logs.is_empty() {
+            file.write_all(&encode_wal_record(&self.logs)?)?;
+        }
+        file.sync_all()?;
+        fs::rename(&temp_path, self.wal_path())?;
+
+        let reopened = OpenOptions::new().append(true).open(self.wal_path())?;
+        *self.wal.lock().unwrap() = reopened;
+        Ok(())
+    }
+}
+
+/// Reads the snapshot at `path`, written by `MemLogStore::write_snapshot`
+fn load_snapshot(path: &Path) -> RaftResult<Option<Snapshot>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn sync_wal(wal: &Mutex<File>) -> RaftResult<()> {
+    wal.lock().unwrap().sync_all()?;
+    Ok(())
+}
+
+/// Encodes `entries` as `length (u32 LE) | bincode payload | CRC32 (u32 LE)`
+///
+/// The whole record is written with one `write_all`, and the checksum lets
+/// replay detect a record torn by a crash mid-write.
+fn encode_wal_record(entries: &[LogEntry]) -> RaftResult<Vec<u8>> {
+    let payload = bincode::serialize(entries)?;
+    let mut record = Vec::with_capacity(payload.len() + 8);
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&payload);
+    record.extend_from_slice(&crc32(&payload).to_le_bytes());
+    Ok(record)
+}
+
+/// Rebuilds the log from the WAL at `path`
+///
+/// Each record replaces any entries at or after its first index, mirroring
+/// `append`. Replay stops at the first truncated or corrupt record, and the
+/// file is cut back to the last good record so new appends follow it cleanly.
+fn replay_wal(path: &Path) -> RaftResult<Vec<LogEntry>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut logs: Vec<LogEntry> = Vec::new();
+    let mut offset = 0;
+    while offset + 4 <= bytes.len() {
+        let len = u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        let end = offset + 4 + len + 4;
+        if end > bytes.len() {
+            break;
+        }
+        let payload = &bytes[offset + 4..offset + 4 + len];
+        let checksum = u32::from_le_bytes(bytes[end - 4..end].try_into().unwrap());
+        if crc32(payload) != checksum {
+            break;
+        }
+        let entries: Vec<LogEntry> = match bincode::deserialize(payload) {
+            Ok(entries) => entries,
+            Err(_) => break,
+        };
+        if let Some(first) = entries.first() {
+            let first_index = first.index;
+            logs.retain(|entry| entry.index < first_index);
+            logs.extend(entries);
+        }
+        offset = end;
+    }
+
+    if offset < bytes.len() {
+        OpenOptions::new().write(true).open(path)?.set_len(offset as u64)?;
+    }
+    Ok(logs)
+}
+
+/// CRC-32 (IEEE 802.3) checksum
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
 }
 
 impl LogStore for MemLogStore {
     fn append(&mut self, entries: Vec<LogEntry>) -> RaftResult<u64> {
         if entries.is_empty() {
-            return Ok(self.last_index()?);
+            return self.last_index();
         }
 
         // Verify log continuity
@@ -75,27 +278,30 @@ impl LogStore for MemLogStore {
             return Err(RaftError::LogNotFound(self.last_index()? + 1));
         }
 
+        // Persist to the WAL before the entries become visible in memory
+        self.write_wal(&entries)?;
+
         // Append new log
         self.logs.extend(entries);
-        Ok(self.last_index()?)
+        self.last_index()
     }
 
     fn get(&self, index: u64) -> RaftResult<Option<LogEntry>> {
-        if index == 0 || index > self.last_index()? {
+        if index < self.first_index || index > self.last_index()? {
             return Ok(None);
         }
         
-        // Since the log index starts at 1, the array index should be reduced by 1.
-        Ok(self.logs.get(index as usize - 1).cloned())
+        // logs[0] holds the entry at first_index
+        Ok(self.logs.get((index - self.first_index) as usize).cloned())
     }
 
     fn get_range(&self, start: u64, end: u64) -> RaftResult<Vec<LogEntry>> {
-        if start == 0 || start > end || start > self.last_index()? {
+        if start < self.first_index || start > end || start > self.last_index()? {
             return Ok(Vec::new());
         }
 
         let end_idx = std::cmp::min(end, self.last_index()?);
-        Ok(self.logs[(start - 1) as usize..end_idx as usize].to_vec())
+        Ok(self.logs[(start - self.first_index) as usize..(end_idx - self.first_index + 1) as usize].to_vec())
     }
 
     fn delete_from(&mut self, index: u64) -> RaftResult<()> {
@@ -104,7 +310,7 @@ impl LogStore for MemLogStore {
         }
 
         // Truncate all logs starting from index
-        self.logs.truncate((index - 1) as usize);
+        self.logs.truncate(index.saturating_sub(self.first_index) as usize);
         
         // If the committed log is deleted, committed_index needs to be updated
         if self.committed_index > self.last_index()? {
@@ -115,11 +321,14 @@ impl LogStore for MemLogStore {
     }
 
     fn last_index(&self) -> RaftResult<u64> {
-        Ok(self.logs.len() as u64)
+        Ok(self.first_index - 1 + self.logs.len() as u64)
     }
 
     fn last_term(&self) -> RaftResult<u64> {
-        Ok(self.logs.last().map_or(0, |entry| entry.term))
+        match self.logs.last() {
+            Some(entry) => Ok(entry.term),
+            None => Ok(self.current_snapshot.as_ref().map_or(0, |snapshot| snapshot.metadata.last_term)),
+        }
     }
 
     fn commit(&mut self, index: u64) -> RaftResult<()> {
@@ -149,13 +358,14 @@ impl LogStore for MemLogStore {
 
     fn snapshot(&mut self) -> RaftResult<()> {
         let snapshot_index = self.committed_index;
-        if snapshot_index == 0 {
+        // Nothing committed since the last compaction
+        if snapshot_index < self.first_index {
             return Ok(());
         }
 
         // Get the term of the last commit log
         let last_term = self.get(snapshot_index)?
-            .ok_or_else(|| RaftError::LogNotFound(snapshot_index))?.term;
+            .ok_or(RaftError::LogNotFound(snapshot_index))?.term;
 
         // Create snapshot metadata
         let metadata = SnapshotMetadata {
@@ -167,8 +377,11 @@ impl LogStore for MemLogStore {
                 .as_secs(),
         };
 
-        // Serialize the committed log
-        let snapshot_data = self.logs[..snapshot_index as usize].to_vec();
+        // Serialize the committed log, including what earlier snapshots compacted
+        let compacted = (snapshot_index - self.first_index + 1) as usize;
+        let mut snapshot_data = self.snapshot_entries()?;
+        snapshot_data.retain(|entry| entry.index < self.first_index);
+        snapshot_data.extend_from_slice(&self.logs[..compacted]);
         let data = bincode::serialize(&snapshot_data)?;
 
         // Creating a snapshot
@@ -178,17 +391,15 @@ impl LogStore for MemLogStore {
         };
 
         // Persistent snapshots
-        let snapshot_bytes = bincode::serialize(&snapshot)?;
-        let temp_path = self.snapshot_path().with_extension("tmp");
-        fs::write(&temp_path, snapshot_bytes)?;
-        fs::rename(temp_path, self.snapshot_path())?;
+        self.write_snapshot(&snapshot)?;
 
         // Update memory status
         self.current_snapshot = Some(snapshot);
 
         // Compress logs
-        let remaining_logs = self.logs[snapshot_index as usize..].to_vec();
-        self.logs = remaining_logs;
+        self.logs.drain(..compacted);
+        self.first_index = snapshot_index + 1;
+        self.rewrite_wal()?;
 
         Ok(())
     }
@@ -206,20 +417,39 @@ impl LogStore for MemLogStore {
         }
 
         // Parsing log data
-        let snapshot_logs: Vec<LogEntry> = bincode::deserialize(&snapshot.data)
+        let mut snapshot_logs: Vec<LogEntry> = bincode::deserialize(&snapshot.data)
             .map_err(|e| RaftError::State(format!("Failed to deserialize logs: {}", e)))?;
 
+        // Entries past the snapshot survive if the log agrees with its last entry
+        let last_index = snapshot.metadata.last_index;
+        if self.term_at(last_index)? == Some(snapshot.metadata.last_term) {
+            snapshot_logs.extend(self.get_range(last_index + 1, self.last_index()?)?);
+        }
+
         // Update Status
+        self.first_index = snapshot_logs.first().map_or(last_index + 1, |entry| entry.index);
         self.logs = snapshot_logs;
-        self.committed_index = snapshot.metadata.last_index;
-        self.current_snapshot = Some(snapshot);
+        self.committed_index = last_index;
 
         // Persistent snapshots
-        let snapshot_bytes = bincode::serialize(&self.current_snapshot)?;
-        fs::write(self.snapshot_path(), snapshot_bytes)?;
+        self.write_snapshot(&snapshot)?;
+        self.current_snapshot = Some(snapshot);
+        self.rewrite_wal()?;
 
         Ok(())
     }
+
+    fn first_index(&self) -> RaftResult<u64> {
+        Ok(self.first_index)
+    }
+
+    fn latest_snapshot(&self) -> Option<&Snapshot> {
+        self.current_snapshot.as_ref()
+    }
+
+    fn dir(&self) -> Option<&Path> {
+        Some(&self.snapshot_dir)
+    }
 }
 
 
@@ -229,6 +459,12 @@ pub struct MockLogStore {
     pub snapshots: Vec<Vec<u8>>,
 }
 
+impl Default for MockLogStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MockLogStore {
     pub fn new() -> Self {
         MockLogStore {
@@ -307,6 +543,7 @@ impl LogStore for MockLogStore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cluster::message::EntryKind;
     use std::fs;
     use std::path::PathBuf;
 
@@ -315,18 +552,25 @@ mod tests {
         LogEntry {
             index,
             term,
+            kind: EntryKind::Command,
             data: data.to_vec(),
             timestamp,
         }
     }
 
-    // Set up a MemLogStore for testing
+    // Set up a MemLogStore for testing, in a directory of its own so the WAL
+    // of one test is never replayed by another
     fn setup_test_log_store() -> MemLogStore {
-        let snapshot_dir = PathBuf::from("test_snapshots");
+        MemLogStore::new(test_snapshot_dir()).unwrap()
+    }
+
+    fn test_snapshot_dir() -> PathBuf {
+        let snapshot_dir = std::env::temp_dir()
+            .join(format!("test_snapshots_{}", uuid::Uuid::new_v4()));
         if snapshot_dir.exists() {
             fs::remove_dir_all(&snapshot_dir).unwrap();
         }
-        MemLogStore::new(snapshot_dir).unwrap()
+        snapshot_dir
     }
 
     #[test]
@@ -372,4 +616,99 @@ mod tests {
         assert_eq!(range[0], create_test_log_entry(2, 1, b"log2", 200));
         assert_eq!(range[1], create_test_log_entry(3, 1, b"log3", 300));
     }
+
+    #[test]
+    fn test_wal_replay_restores_entries() {
+        let snapshot_dir = test_snapshot_dir();
+        {
+            let mut store = MemLogStore::new(snapshot_dir.clone()).unwrap();
+            store.append(vec![
+                create_test_log_entry(1, 1, b"log1", 100),
+                create_test_log_entry(2, 1, b"log2", 200),
+            ]).unwrap();
+            // Overwrites the conflicting entry 2
+            store.append(vec![create_test_log_entry(2, 2, b"log2b", 300)]).unwrap();
+            store.flush_wal().unwrap();
+        }
+
+        let store = MemLogStore::new(snapshot_dir).unwrap();
+        assert_eq!(store.last_index().unwrap(), 2);
+        assert_eq!(store.get(2).unwrap().unwrap(), create_test_log_entry(2, 2, b"log2b", 300));
+    }
+
+    #[test]
+    fn test_wal_replay_stops_at_torn_record() {
+        let snapshot_dir = test_snapshot_dir();
+        {
+            let mut store = MemLogStore::new(snapshot_dir.clone()).unwrap();
+            store.append(vec![create_test_log_entry(1, 1, b"log1", 100)]).unwrap();
+            store.append(vec![create_test_log_entry(2, 1, b"log2", 200)]).unwrap();
+        }
+
+        // Simulate a crash partway through writing the second record
+        let wal_path = snapshot_dir.join("raft.wal");
+        let len = fs::metadata(&wal_path).unwrap().len();
+        fs::OpenOptions::new().write(true).open(&wal_path).unwrap().set_len(len - 3).unwrap();
+
+        let mut store = MemLogStore::new(snapshot_dir.clone()).unwrap();
+        assert_eq!(store.last_index().unwrap(), 1);
+
+        // The torn tail is dropped, so new appends replay cleanly
+        store.append(vec![create_test_log_entry(2, 2, b"log2", 300)]).unwrap();
+        drop(store);
+        let store = MemLogStore::new(snapshot_dir).unwrap();
+        assert_eq!(store.get(2).unwrap().unwrap(), create_test_log_entry(2, 2, b"log2", 300));
+    }
+
+    #[test]
+    fn test_wal_flusher_stops_with_store() {
+        let mut store = setup_test_log_store();
+        let flusher = store.spawn_wal_flusher(Duration::from_millis(10));
+        store.append(vec![create_test_log_entry(1, 1, b"log1", 100)]).unwrap();
+        std::thread::sleep(Duration::from_millis(30));
+
+        drop(store);
+        flusher.join().unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_compacts_the_log() {
+        let snapshot_dir = test_snapshot_dir();
+        let mut store = MemLogStore::new(snapshot_dir.clone()).unwrap();
+        store.append((1..=3).map(|i| create_test_log_entry(i, 1, b"log", i * 100)).collect()).unwrap();
+        store.commit(2).unwrap();
+        store.snapshot().unwrap();
+
+        assert_eq!((store.first_index().unwrap(), store.last_index().unwrap()), (3, 3));
+        assert!(store.get(2).unwrap().is_none());
+        assert_eq!(store.get(3).unwrap().unwrap().timestamp, 300);
+        assert_eq!(store.term_at(2).unwrap(), Some(1));
+        assert_eq!(store.term_at(1).unwrap(), None);
+
+        // A second snapshot holds the entries the first compacted too
+        store.append(vec![create_test_log_entry(4, 2, b"log", 400)]).unwrap();
+        store.commit(4).unwrap();
+        store.snapshot().unwrap();
+        assert_eq!((store.first_index().unwrap(), store.last_term().unwrap()), (5, 2));
+        let snapshot = store.latest_snapshot().unwrap().clone();
+        let entries: Vec<LogEntry> = bincode::deserialize(&snapshot.data).unwrap();
+        assert_eq!(entries.iter().map(|entry| entry.index).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+
+        // The snapshot and the compacted log survive a restart
+        drop(store);
+        let store = MemLogStore::new(snapshot_dir).unwrap();
+        assert_eq!((store.first_index().unwrap(), store.last_index().unwrap()), (5, 4));
+        assert_eq!(store.committed_index().unwrap(), 4);
+
+        // Restoring it elsewhere brings back every entry it holds
+        let mut other = setup_test_log_store();
+        other.restore_snapshot(bincode::serialize(&snapshot).unwrap()).unwrap();
+        assert_eq!((other.first_index().unwrap(), other.last_index().unwrap()), (1, 4));
+        assert_eq!(other.get(3).unwrap().unwrap().timestamp, 300);
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
 }
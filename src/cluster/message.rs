@@ -1,12 +1,31 @@
 // src/cluster/message.rs
 use serde::{Serialize, Deserialize};
 use std::time::SystemTime;
+use super::state::NodeRole;
 //use crate::cluster::error::RaftResult;
 
+/// What a log entry's `data` holds
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum EntryKind {
+    /// A client command for the state machine
+    #[default]
+    Command,
+    /// A `ConfigChange`, applied by the consensus module once committed
+    ConfigChange,
+}
+
+/// A single-server change to the cluster's membership
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ConfigChange {
+    AddNode { id: String, addr: String },
+    RemoveNode { id: String },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct LogEntry {
     pub term: u64,
     pub index: u64,
+    pub kind: EntryKind,
     pub data: Vec<u8>,
     pub timestamp: u64,
 }
@@ -21,10 +40,38 @@ impl LogEntry {
         LogEntry {
             term,
             index,
+            kind: EntryKind::Command,
             data,
             timestamp,
         }
     }
+
+    /// Creates an entry carrying a membership change
+    pub fn config_change(term: u64, index: u64, change: &ConfigChange) -> Self {
+        LogEntry {
+            kind: EntryKind::ConfigChange,
+            ..LogEntry::new(term, index, bincode::serialize(change).unwrap())
+        }
+    }
+
+    /// Returns the membership change the entry carries, if it is one
+    pub fn as_config_change(&self) -> Option<ConfigChange> {
+        match self.kind {
+            EntryKind::ConfigChange => bincode::deserialize(&self.data).ok(),
+            EntryKind::Command => None,
+        }
+    }
+}
+
+/// One node of the cluster, as gossip describes it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NodeInfo {
+    pub id: String,
+    /// `host:port` of the node's cluster transport
+    pub addr: String,
+    pub role: NodeRole,
+    /// The Raft term the node last reported; newer information wins a merge
+    pub epoch: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,6 +85,7 @@ pub enum RaftMessage {
     },
     
     RequestVoteResponse {
+        from: String,
         term: u64,
         vote_granted: bool,
     },
@@ -53,6 +101,7 @@ pub enum RaftMessage {
     },
     
     AppendEntriesResponse {
+        from: String,
         term: u64,
         success: bool,
         match_index: u64,
@@ -62,4 +111,52 @@ pub enum RaftMessage {
         term: u64,
         leader_id: String,
     },
-}
\ No newline at end of file
+
+    // 快照安装
+    // Sent to a follower whose next entry was compacted away; the whole
+    // snapshot goes in one message, so `done` is always true
+    InstallSnapshot {
+        term: u64,
+        leader_id: String,
+        last_included_index: u64,
+        last_included_term: u64,
+        data: Vec<u8>,
+        done: bool,
+    },
+
+    InstallSnapshotResponse {
+        from: String,
+        term: u64,
+        /// The follower's commit index once the snapshot is installed
+        match_index: u64,
+    },
+
+    // 集群槽位分配
+    SlotAssignments {
+        node_id: String,
+        slots: Vec<u16>,
+    },
+
+    // 集群节点发现
+    ClusterGossip {
+        sender_id: String,
+        nodes: Vec<NodeInfo>,
+    },
+}
+
+impl RaftMessage {
+    /// Returns the id of the node that sent the message
+    pub fn sender(&self) -> &str {
+        match self {
+            RaftMessage::RequestVote { candidate_id, .. } => candidate_id,
+            RaftMessage::RequestVoteResponse { from, .. }
+            | RaftMessage::AppendEntriesResponse { from, .. }
+            | RaftMessage::InstallSnapshotResponse { from, .. } => from,
+            RaftMessage::AppendEntries { leader_id, .. }
+            | RaftMessage::Heartbeat { leader_id, .. }
+            | RaftMessage::InstallSnapshot { leader_id, .. } => leader_id,
+            RaftMessage::SlotAssignments { node_id, .. } => node_id,
+            RaftMessage::ClusterGossip { sender_id, .. } => sender_id,
+        }
+    }
+}
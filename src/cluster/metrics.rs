@@ -0,0 +1,84 @@
+// src/cluster/metrics.rs
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use super::message::LogEntry;
+
+// Counters describing the consensus module's activity, shared through `Arc`
+// so they can be read without taking any of the consensus locks
+pub struct RaftMetrics {
+    pub elections_started: AtomicU64,
+    pub elections_won: AtomicU64,
+    pub heartbeats_sent: AtomicU64,
+    pub log_entries_replicated: AtomicU64,
+    pub replication_lag_ms: AtomicU64,
+    pub current_term: AtomicU64,
+
+    // Inputs for the replication lag estimate
+    bytes_replicated: AtomicU64,
+    started_at: Instant,
+}
+
+impl Default for RaftMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RaftMetrics {
+    pub fn new() -> Self {
+        RaftMetrics {
+            elections_started: AtomicU64::new(0),
+            elections_won: AtomicU64::new(0),
+            heartbeats_sent: AtomicU64::new(0),
+            log_entries_replicated: AtomicU64::new(0),
+            replication_lag_ms: AtomicU64::new(0),
+            current_term: AtomicU64::new(0),
+            bytes_replicated: AtomicU64::new(0),
+            started_at: Instant::now(),
+        }
+    }
+
+    // Record entries handed to the transport for one follower
+    pub fn record_replication(&self, entries: &[LogEntry]) {
+        let bytes: usize = entries.iter().map(|entry| entry.data.len()).sum();
+        self.log_entries_replicated.fetch_add(entries.len() as u64, Ordering::Relaxed);
+        self.bytes_replicated.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    // Estimate how long the slowest follower needs to catch up:
+    // entries behind * average entry size / observed replication throughput
+    pub fn update_replication_lag(&self, max_entries_behind: u64) {
+        let entries = self.log_entries_replicated.load(Ordering::Relaxed);
+        let bytes = self.bytes_replicated.load(Ordering::Relaxed);
+        let elapsed_ms = self.started_at.elapsed().as_millis() as u64;
+
+        let lag_ms = if max_entries_behind == 0 || entries == 0 || bytes == 0 || elapsed_ms == 0 {
+            0
+        } else {
+            // bytes behind / (bytes per ms), rearranged to stay in integers
+            let avg_entry_size = bytes / entries;
+            max_entries_behind * avg_entry_size * elapsed_ms / bytes
+        };
+        self.replication_lag_ms.store(lag_ms, Ordering::Relaxed);
+    }
+
+    // Render the counters in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let counters = [
+            ("raft_elections_started", "counter", &self.elections_started),
+            ("raft_elections_won", "counter", &self.elections_won),
+            ("raft_heartbeats_sent", "counter", &self.heartbeats_sent),
+            ("raft_log_entries_replicated", "counter", &self.log_entries_replicated),
+            ("raft_replication_lag_ms", "gauge", &self.replication_lag_ms),
+            ("raft_current_term", "gauge", &self.current_term),
+        ];
+        for (name, kind, value) in counters {
+            let _ = writeln!(output, "# TYPE {} {}", name, kind);
+            let _ = writeln!(output, "{} {}", name, value.load(Ordering::Relaxed));
+        }
+        output
+    }
+}
@@ -5,4 +5,8 @@ pub mod transport;
 pub mod consensus;
 pub mod log_store;
 pub mod state;
-pub mod error;
\ No newline at end of file
+pub mod error;
+pub mod metrics;
+pub mod replication;
+pub mod slots;
+pub mod gossip;
\ No newline at end of file
@@ -5,12 +5,14 @@ use tokio::time::{sleep, Duration};
 use std::collections::HashMap;
 use std::time::SystemTime;
 
+#[cfg(test)]
 use crate::cluster::log_store::MockLogStore;
+#[cfg(test)]
 use crate::cluster::transport::MockTransport;
 
 use super::error::{RaftError, RaftResult};
-use super::message::{RaftMessage, LogEntry};
-use super::state::{RaftState, NodeRole};
+use super::message::{ConfigChange, EntryKind, RaftMessage, LogEntry};
+use super::state::NodeRole;
 use super::consensus::RaftConsensus;
 use super::transport::Transport;
 use super::log_store::{LogStore, Snapshot};
@@ -54,12 +56,14 @@ pub trait StateMachine: Send + Sync {
 }
 
 pub struct RaftNode<T: Transport + 'static, L: LogStore + 'static, S: StateMachine + 'static> {
+    #[allow(dead_code)]
     node_id: String,
     consensus: Arc<RaftConsensus<T, L>>,
     state_machine: Arc<Mutex<S>>,
     applied_index: Arc<Mutex<u64>>,
     snapshot_threshold: u64,  // Number of logs before taking a snapshot
     last_snapshot_index: Arc<Mutex<u64>>,
+    pending_responses: Arc<Mutex<HashMap<u64, Option<Response>>>>,  // Log index -> response for in-flight client commands
 }
 
 impl<T: Transport + 'static, L: LogStore + 'static, S: StateMachine + 'static> RaftNode<T, L, S> {
@@ -70,22 +74,23 @@ impl<T: Transport + 'static, L: LogStore + 'static, S: StateMachine + 'static> R
         state_machine: Arc<Mutex<S>>,
         cluster: HashMap<String, String>,
         snapshot_threshold: u64,
-    ) -> Arc<Self> {
+    ) -> RaftResult<Arc<Self>> {
         let consensus = RaftConsensus::new(
             node_id.clone(),
             transport,
             log_store,
             cluster,
-        );
+        )?;
 
-        Arc::new(RaftNode {
+        Ok(Arc::new(RaftNode {
             node_id,
             consensus,
             state_machine,
             applied_index: Arc::new(Mutex::new(0)),
             snapshot_threshold,
             last_snapshot_index: Arc::new(Mutex::new(0)),
-        })
+            pending_responses: Arc::new(Mutex::new(HashMap::new())),
+        }))
     }
 
     pub async fn start(node: Arc<Self>) -> RaftResult<()> {
@@ -96,7 +101,7 @@ impl<T: Transport + 'static, L: LogStore + 'static, S: StateMachine + 'static> R
         let apply_node = Arc::clone(&node);
         tokio::spawn(async move {
             if let Err(e) = apply_node.run_apply_loop().await {
-                eprintln!("Error in apply loop: {}", e);
+                tracing::error!(error = %e, "error in apply loop");
             }
         });
         
@@ -104,7 +109,7 @@ impl<T: Transport + 'static, L: LogStore + 'static, S: StateMachine + 'static> R
         let snapshot_node = Arc::clone(&node);
         tokio::spawn(async move {
             if let Err(e) = snapshot_node.run_snapshot_manager().await {
-                eprintln!("Error in snapshot manager: {}", e);
+                tracing::error!(error = %e, "error in snapshot manager");
             }
         });
         
@@ -113,37 +118,81 @@ impl<T: Transport + 'static, L: LogStore + 'static, S: StateMachine + 'static> R
 
     // Process client request
     pub async fn process_command(&self, command: Command) -> RaftResult<Response> {
-        let state = self.consensus.state.lock().await;
-        
-        // Only leader can process writes
-        if state.role != NodeRole::Leader {
-            return Err(RaftError::NotLeader);
-        }
-        
-        // Create log entry
-        let last_index = self.consensus.log_store.lock().await.last_index()?;
-        let entry = LogEntry::new(
-            state.current_term,
-            last_index + 1,
-            bincode::serialize(&command)
-                .map_err(|e| RaftError::Serialization(e))?,
-        );
+        let current_term = {
+            let state = self.consensus.state.lock().await;
+
+            // Only leader can process writes
+            if state.role != NodeRole::Leader {
+                return Err(RaftError::NotLeader);
+            }
+            state.current_term
+        };
         
-        // Append to local log
-        {
+        // Create log entry and append to local log
+        let entry = {
             let mut log_store = self.consensus.log_store.lock().await;
+            let entry = LogEntry::new(
+                current_term,
+                log_store.last_index()? + 1,
+                bincode::serialize(&command)
+                    .map_err(RaftError::Serialization)?,
+            );
             log_store.append(vec![entry.clone()])?;
-        }
+            entry
+        };
+        self.consensus.acks.record_write(entry.index);
+        self.pending_responses.lock().await.insert(entry.index, None);
+        
+        // Replicate to peers; a single-node cluster commits right away
+        self.consensus.replicate_logs().await?;
+        self.consensus.update_commit_index().await?;
         
         // Wait for replication
         let committed = self.wait_for_commit(entry.index).await?;
         if !committed {
+            self.pending_responses.lock().await.remove(&entry.index);
             return Err(RaftError::ReplicationTimeout);
         }
         
-        // Apply command
-        let mut state_machine = self.state_machine.lock().await;
-        state_machine.apply(&command)
+        // Apply command (the apply loop may already have done so)
+        self.apply_committed_entries().await?;
+        self.check_snapshot().await?;
+
+        self.pending_responses.lock().await
+            .remove(&entry.index)
+            .flatten()
+            .ok_or_else(|| RaftError::State(format!("Entry {} was not applied", entry.index)))
+    }
+
+    /// Adds a node to the cluster through the Raft log
+    ///
+    /// Only the leader can change the membership, one change at a time. It
+    /// returns once the change is committed, from when the new node counts
+    /// toward quorum. The node itself should start with the current members
+    /// as its cluster, and catches up from the leader's log.
+    pub async fn add_member(&self, id: String, addr: String) -> RaftResult<()> {
+        self.change_membership(ConfigChange::AddNode { id, addr }).await
+    }
+
+    /// Removes a node from the cluster through the Raft log
+    ///
+    /// Like `add_member`. A leader removing itself steps down once the
+    /// change is committed.
+    pub async fn remove_member(&self, id: String) -> RaftResult<()> {
+        self.change_membership(ConfigChange::RemoveNode { id }).await
+    }
+
+    async fn change_membership(&self, change: ConfigChange) -> RaftResult<()> {
+        let index = self.consensus.propose_config_change(change).await?;
+
+        // Replicate to peers; a single-node cluster commits right away
+        self.consensus.replicate_logs().await?;
+        self.consensus.update_commit_index().await?;
+
+        if !self.wait_for_commit(index).await? {
+            return Err(RaftError::ReplicationTimeout);
+        }
+        self.consensus.apply_committed_config().await
     }
 
     // Wait for log entry to be committed
@@ -169,7 +218,7 @@ impl<T: Transport + 'static, L: LogStore + 'static, S: StateMachine + 'static> R
         tokio::spawn(async move {
             loop {
                 if let Err(e) = node.apply_committed_entries().await {
-                    eprintln!("Error applying committed entries: {}", e);
+                    tracing::error!(error = %e, "error applying committed entries");
                 }
                 sleep(Duration::from_millis(10)).await;
             }
@@ -190,14 +239,22 @@ impl<T: Transport + 'static, L: LogStore + 'static, S: StateMachine + 'static> R
                 Some(entry) => entry,
                 None => break,
             };
+
+            // Membership changes are applied by the consensus module
+            if entry.kind == EntryKind::ConfigChange {
+                *applied_index = next_index;
+                continue;
+            }
             
             // Deserialize command
             let command: Command = bincode::deserialize(&entry.data)
-                .map_err(|e| RaftError::Serialization(e))?;
+                .map_err(RaftError::Serialization)?;
             
             // Apply to state machine
-            let mut state_machine = self.state_machine.lock().await;
-            state_machine.apply(&command)?;
+            let response = self.state_machine.lock().await.apply(&command)?;
+            if let Some(slot) = self.pending_responses.lock().await.get_mut(&next_index) {
+                *slot = Some(response);
+            }
             
             *applied_index = next_index;
         }
@@ -212,7 +269,7 @@ impl<T: Transport + 'static, L: LogStore + 'static, S: StateMachine + 'static> R
         tokio::spawn(async move {
             loop {
                 if let Err(e) = node.check_snapshot().await {
-                    eprintln!("Error managing snapshots: {}", e);
+                    tracing::error!(error = %e, "error managing snapshots");
                 }
                 sleep(Duration::from_secs(60)).await;
             }
@@ -236,7 +293,7 @@ impl<T: Transport + 'static, L: LogStore + 'static, S: StateMachine + 'static> R
         let applied_index = *self.applied_index.lock().await;
         
         // Take state machine snapshot
-        let state_machine_data = {
+        let _state_machine_data = {
             let mut state_machine = self.state_machine.lock().await;
             state_machine.snapshot()?
         };
@@ -264,7 +321,7 @@ impl<T: Transport + 'static, L: LogStore + 'static, S: StateMachine + 'static> R
         // Restore log store
         {
             let log_data = bincode::serialize(&snapshot)
-                .map_err(|e| RaftError::Serialization(e))?;
+                .map_err(RaftError::Serialization)?;
                 
             let mut log_store = self.consensus.log_store.lock().await;
             log_store.restore_snapshot(log_data)?;
@@ -293,8 +350,8 @@ impl<T: Transport + 'static, L: LogStore + 'static, S: StateMachine + 'static> R
                 ).await
             }
             
-            RaftMessage::RequestVoteResponse { term, vote_granted } => {
-                self.consensus.handle_vote_response(term, vote_granted).await
+            RaftMessage::RequestVoteResponse { from, term, vote_granted } => {
+                self.consensus.handle_vote_response(from, term, vote_granted).await
             }
             
             RaftMessage::AppendEntries { term, leader_id, prev_log_index, prev_log_term, entries, leader_commit } => {
@@ -308,16 +365,31 @@ impl<T: Transport + 'static, L: LogStore + 'static, S: StateMachine + 'static> R
                 ).await
             }
             
-            RaftMessage::AppendEntriesResponse { term, success, match_index } => {
+            RaftMessage::AppendEntriesResponse { from, term, success, match_index } => {
                 self.consensus.handle_append_entries_response(
-                    self.node_id.clone(),
+                    from,
                     term,
                     success,
                     match_index
                 ).await
             }
             
-            RaftMessage::Heartbeat { term, leader_id } => {
+            RaftMessage::InstallSnapshot { term, leader_id, last_included_index, last_included_term, data, done } => {
+                self.consensus.handle_install_snapshot(
+                    term,
+                    leader_id,
+                    last_included_index,
+                    last_included_term,
+                    data,
+                    done
+                ).await
+            }
+
+            RaftMessage::InstallSnapshotResponse { from, term, match_index } => {
+                self.consensus.handle_install_snapshot_response(from, term, match_index).await
+            }
+
+            RaftMessage::Heartbeat { term, leader_id: _ } => {
                 let mut state = self.consensus.state.lock().await;
                 state.update_term(term)?;
                 if term >= state.current_term {
@@ -325,16 +397,24 @@ impl<T: Transport + 'static, L: LogStore + 'static, S: StateMachine + 'static> R
                 }
                 Ok(())
             }
+
+            // Slot ownership is kept by the server's `SlotTable`, not by Raft
+            RaftMessage::SlotAssignments { .. } => Ok(()),
+
+            // Membership is kept by `gossip::Topology`, not by Raft
+            RaftMessage::ClusterGossip { .. } => Ok(()),
         }
     }
 }
 
 // Mock state machine implementation for testing
+#[cfg(test)]
 #[derive(Default)]
 struct MockStateMachine {
     data: HashMap<String, Vec<u8>>,
 }
 
+#[cfg(test)]
 impl StateMachine for MockStateMachine {
     fn apply(&mut self, command: &Command) -> RaftResult<Response> {
         match command.operation.as_str() {
@@ -396,7 +476,7 @@ async fn test_node_startup() {
         state_machine,
         cluster,
         1000, // snapshot threshold
-    );
+    ).unwrap();
     
     assert!(RaftNode::start(node).await.is_ok());
 }
@@ -418,11 +498,12 @@ async fn test_process_command() {
         state_machine.clone(),
         cluster,
         1000,
-    );
+    ).unwrap();
     
     // Make node the leader
     {
         let mut state = node.consensus.state.lock().await;
+        state.begin_election().unwrap();
         state.become_leader();
     }
     
@@ -439,8 +520,10 @@ async fn test_process_command() {
     assert!(response.success);
     
     // Verify log entry was created
-    let log_store = node.consensus.log_store.lock().await;
-    assert_eq!(log_store.last_index().unwrap(), 1);
+    {
+        let log_store = node.consensus.log_store.lock().await;
+        assert_eq!(log_store.last_index().unwrap(), 1);
+    }
     
     // Test GET command
     let cmd = Command::new(
@@ -473,11 +556,12 @@ async fn test_snapshot_creation() {
         state_machine.clone(),
         cluster,
         5, // Low snapshot threshold for testing
-    );
+    ).unwrap();
     
     // Make node the leader
     {
         let mut state = node.consensus.state.lock().await;
+        state.begin_election().unwrap();
         state.become_leader();
     }
     
@@ -518,7 +602,7 @@ async fn test_message_handling() {
         state_machine.clone(),
         cluster,
         1000,
-    );
+    ).unwrap();
     
     // Test vote request handling
     let msg = RaftMessage::RequestVote {
@@ -553,7 +637,7 @@ async fn test_error_conditions() {
         state_machine.clone(),
         cluster,
         1000,
-    );
+    ).unwrap();
     
     // Test processing command when not leader
     let cmd = Command::new(
@@ -564,4 +648,129 @@ async fn test_error_conditions() {
     
     let result = node.process_command(cmd).await;
     assert!(matches!(result, Err(RaftError::NotLeader)));
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+type TestNode = Arc<RaftNode<MockTransport, MockLogStore, MockStateMachine>>;
+
+#[cfg(test)]
+fn test_node(node_id: &str, peers: &[&str]) -> TestNode {
+    let cluster = peers.iter().map(|peer| (peer.to_string(), format!("{}:7000", peer))).collect();
+    RaftNode::new(
+        node_id.to_string(),
+        Arc::new(MockTransport::new(node_id.to_string())),
+        Arc::new(Mutex::new(MockLogStore::new())),
+        Arc::new(Mutex::new(MockStateMachine::default())),
+        cluster,
+        1000,
+    ).unwrap()
+}
+
+/// Lets the spawned sends run, then hands each message to the node it's
+/// addressed to if `deliver(from, to)` lets it through
+#[cfg(test)]
+async fn exchange(nodes: &[&TestNode], deliver: impl Fn(&str, &str) -> bool) {
+    sleep(Duration::from_millis(10)).await;
+    for node in nodes {
+        let messages: Vec<_> = node.consensus.transport.messages.lock().await.drain(..).collect();
+        for (to, message) in messages {
+            if let Some(peer) = nodes.iter().find(|peer| peer.node_id == to) {
+                if deliver(&node.node_id, &to) {
+                    peer.handle_message(message).await.unwrap();
+                }
+            }
+        }
+    }
+}
+
+/// Runs a membership change on `leader` while replicating to `nodes` until it returns
+#[cfg(test)]
+async fn change_membership(leader: &TestNode, nodes: &[&TestNode], change: ConfigChange) -> RaftResult<()> {
+    let changing = tokio::spawn({
+        let leader = Arc::clone(leader);
+        async move { leader.change_membership(change).await }
+    });
+    while !changing.is_finished() {
+        leader.consensus.replicate_logs().await.unwrap();
+        exchange(nodes, |_, _| true).await;
+    }
+    changing.await.unwrap()
+}
+
+#[tokio::test]
+async fn test_membership_grows_from_one_node_to_three() {
+    let node1 = test_node("node1", &[]);
+    {
+        let mut state = node1.consensus.state.lock().await;
+        state.begin_election().unwrap();
+        state.become_leader();
+    }
+
+    // Alone, the leader commits the change by itself
+    node1.add_member("node2".to_string(), "node2:7000".to_string()).await.unwrap();
+    assert_eq!(node1.consensus.peers().await, vec!["node2".to_string()]);
+    assert!(node1.consensus.transport.connections.lock().await.contains_key("node2"));
+
+    // Now node2 must acknowledge the next change before it commits
+    let node2 = test_node("node2", &["node1"]);
+    let add_node3 = ConfigChange::AddNode { id: "node3".to_string(), addr: "node3:7000".to_string() };
+    change_membership(&node1, &[&node1, &node2], add_node3).await.unwrap();
+    let node3 = test_node("node3", &["node1", "node2"]);
+    let nodes = [&node1, &node2, &node3];
+    for _ in 0..5 {
+        node1.consensus.replicate_logs().await.unwrap();
+        exchange(&nodes, |_, _| true).await;
+    }
+    for node in nodes {
+        assert_eq!(node.consensus.log_store.lock().await.committed_index().unwrap(), 2, "{}", node.node_id);
+    }
+    let mut peers = node2.consensus.peers().await;
+    peers.sort();
+    assert_eq!(peers, vec!["node1".to_string(), "node3".to_string()]);
+
+    // With three members, a commit takes the leader and one follower
+    let command = Command::new("SET".to_string(), "key".to_string(), Some(b"value".to_vec()));
+    let entry = LogEntry::new(1, 3, bincode::serialize(&command).unwrap());
+    node1.consensus.log_store.lock().await.append(vec![entry]).unwrap();
+    node1.consensus.replicate_logs().await.unwrap();
+    exchange(&nodes, |_, _| false).await;
+    node1.consensus.update_commit_index().await.unwrap();
+    assert_eq!(node1.consensus.log_store.lock().await.committed_index().unwrap(), 2);
+
+    node1.consensus.replicate_logs().await.unwrap();
+    exchange(&nodes, |from, to| from == "node3" || to == "node3").await;
+    assert_eq!(node1.consensus.log_store.lock().await.committed_index().unwrap(), 3);
+}
+
+#[tokio::test]
+async fn test_removing_the_leader_steps_it_down() {
+    let node1 = test_node("node1", &["node2"]);
+    let node2 = test_node("node2", &["node1"]);
+    {
+        let mut state = node1.consensus.state.lock().await;
+        state.begin_election().unwrap();
+        state.become_leader();
+    }
+
+    // One change at a time, and only to nodes that can be added or removed
+    let add_node3 = ConfigChange::AddNode { id: "node3".to_string(), addr: "node3:7000".to_string() };
+    node1.consensus.propose_config_change(add_node3.clone()).await.unwrap();
+    let refused = node1.consensus.propose_config_change(ConfigChange::RemoveNode { id: "node2".to_string() }).await;
+    assert!(matches!(refused, Err(RaftError::State(_))), "{:?}", refused);
+    // node3 never answers, but node1 and node2 are a majority of three
+    for _ in 0..3 {
+        node1.consensus.replicate_logs().await.unwrap();
+        exchange(&[&node1, &node2], |_, _| true).await;
+    }
+    assert_eq!(node1.consensus.peers().await.len(), 2);
+    let refused = node1.consensus.propose_config_change(add_node3).await;
+    assert!(matches!(refused, Err(RaftError::State(_))), "{:?}", refused);
+    let refused = node1.remove_member("node4".to_string()).await;
+    assert!(matches!(refused, Err(RaftError::NodeNotFound(_))), "{:?}", refused);
+
+    change_membership(&node1, &[&node1, &node2], ConfigChange::RemoveNode { id: "node1".to_string() }).await.unwrap();
+    assert_eq!(node1.consensus.state.lock().await.role, NodeRole::Follower);
+    assert!(node1.consensus.removed.load(std::sync::atomic::Ordering::Relaxed));
+    let refused = node1.add_member("node4".to_string(), "node4:7000".to_string()).await;
+    assert!(matches!(refused, Err(RaftError::NotLeader)), "{:?}", refused);
+}
@@ -0,0 +1,196 @@
+// src/cluster/replication.rs
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+// Tracks how far each replica has acknowledged the write stream, so WAIT can
+// block until enough replicas have caught up with the latest write
+pub struct ReplicationAcks {
+    pub replication_offset: Arc<AtomicU64>,
+    pub replica_offsets: Arc<RwLock<HashMap<String, u64>>>,
+    // Names this write stream, like Redis's master_replid
+    replication_id: RwLock<String>,
+
+    // Woken on every acknowledgement; the mutex only pairs with the condvar
+    ack_lock: Mutex<()>,
+    ack_received: Condvar,
+}
+
+impl Default for ReplicationAcks {
+    fn default() -> Self {
+        ReplicationAcks {
+            replication_offset: Arc::default(),
+            replica_offsets: Arc::default(),
+            replication_id: RwLock::new(new_replication_id()),
+            ack_lock: Mutex::default(),
+            ack_received: Condvar::default(),
+        }
+    }
+}
+
+// 40 random hex characters, the length Redis uses
+fn new_replication_id() -> String {
+    let mut id = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+    id.truncate(40);
+    id
+}
+
+impl ReplicationAcks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn replication_id(&self) -> String {
+        self.replication_id.read().unwrap().clone()
+    }
+
+    // Start naming the stream with a fresh random id (DEBUG CHANGE-REPL-ID)
+    pub fn change_replication_id(&self) {
+        *self.replication_id.write().unwrap() = new_replication_id();
+    }
+
+    // Record that the write stream has reached `offset` (e.g. a log index)
+    pub fn record_write(&self, offset: u64) {
+        self.replication_offset.fetch_max(offset, Ordering::Relaxed);
+    }
+
+    // Record that `replica` has everything up to `offset` and wake waiters
+    pub fn ack(&self, replica: &str, offset: u64) {
+        {
+            let mut offsets = self.replica_offsets.write().unwrap();
+            let acked = offsets.entry(replica.to_string()).or_insert(0);
+            *acked = (*acked).max(offset);
+        }
+        let _guard = self.ack_lock.lock().unwrap();
+        self.ack_received.notify_all();
+    }
+
+    pub fn remove_replica(&self, replica: &str) {
+        self.replica_offsets.write().unwrap().remove(replica);
+    }
+
+    // Number of replicas that have acknowledged `offset`
+    pub fn acked_count(&self, offset: u64) -> usize {
+        self.replica_offsets.read().unwrap()
+            .values()
+            .filter(|&&acked| acked >= offset)
+            .count()
+    }
+
+    // The offset the write stream has reached
+    pub fn offset(&self) -> u64 {
+        self.replication_offset.load(Ordering::Relaxed)
+    }
+
+    // Block until `num_replicas` replicas acknowledge the current write offset
+    // or `timeout` passes (`None` waits forever). Returns how many had acknowledged.
+    pub fn wait(&self, num_replicas: usize, timeout: Option<Duration>) -> usize {
+        self.wait_for_offset(self.offset(), num_replicas, timeout)
+    }
+
+    // Like `wait`, but for a given offset, such as that of a client's last
+    // write. Returns 0 at once when no replica has ever connected.
+    pub fn wait_for_offset(&self, target: u64, num_replicas: usize, timeout: Option<Duration>) -> usize {
+        if self.replica_offsets.read().unwrap().is_empty() {
+            return 0;
+        }
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        let mut guard = self.ack_lock.lock().unwrap();
+        loop {
+            let acked = self.acked_count(target);
+            if acked >= num_replicas {
+                return acked;
+            }
+            guard = match deadline {
+                None => self.ack_received.wait(guard).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return acked;
+                    }
+                    self.ack_received.wait_timeout(guard, deadline - now).unwrap().0
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_wait_returns_once_enough_replicas_ack() {
+        let acks = Arc::new(ReplicationAcks::new());
+        acks.record_write(5);
+        acks.ack("replica1", 5);
+
+        let acker = Arc::clone(&acks);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            acker.ack("replica2", 3);
+            acker.ack("replica2", 5);
+        });
+
+        assert_eq!(acks.wait(2, Some(Duration::from_secs(5))), 2);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_wait_times_out_with_partial_acks() {
+        let acks = ReplicationAcks::new();
+        acks.record_write(2);
+        acks.ack("replica1", 2);
+        acks.ack("replica2", 1);
+
+        let start = Instant::now();
+        assert_eq!(acks.wait(2, Some(Duration::from_millis(50))), 1);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(acks.wait(0, None), 1);
+    }
+
+    #[test]
+    fn test_wait_without_replicas_returns_immediately() {
+        let acks = ReplicationAcks::new();
+        acks.record_write(3);
+
+        let start = Instant::now();
+        assert_eq!(acks.wait(1, None), 0);
+        assert_eq!(acks.wait_for_offset(3, 1, Some(Duration::from_secs(5))), 0);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_wait_for_offset_with_delayed_replica() {
+        // Acks 50ms in, well within the one second timeout
+        let acks = Arc::new(ReplicationAcks::new());
+        acks.ack("replica1", 0);
+        acks.record_write(7);
+        let acker = Arc::clone(&acks);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            acker.ack("replica1", 7);
+        });
+        let start = Instant::now();
+        assert_eq!(acks.wait_for_offset(7, 1, Some(Duration::from_secs(1))), 1);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        handle.join().unwrap();
+
+        // Acks 300ms in, after the 100ms timeout has given up
+        acks.record_write(8);
+        let acker = Arc::clone(&acks);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            acker.ack("replica1", 8);
+        });
+        let start = Instant::now();
+        assert_eq!(acks.wait_for_offset(8, 1, Some(Duration::from_millis(100))), 0);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(100) && elapsed < Duration::from_millis(300));
+        handle.join().unwrap();
+        assert_eq!(acks.wait_for_offset(8, 1, Some(Duration::from_millis(100))), 1);
+    }
+}
@@ -0,0 +1,164 @@
+// src/cluster/slots.rs
+//! Hash slot ownership, as set by CLUSTER ADDSLOTS / DELSLOTS
+//!
+//! Every node keeps the whole slot → node id map. A node announces the
+//! slots it serves with `RaftMessage::SlotAssignments`, and a peer that
+//! applies the message replaces whatever it had recorded for that node.
+use super::message::RaftMessage;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+/// Number of hash slots the key space is split into
+pub const CLUSTER_SLOTS: u16 = 16384;
+
+/// Returns the slot a key belongs to: CRC16 (XMODEM) of the key, mod 16384
+///
+/// If the key has a non-empty `{hash tag}`, only the tag is hashed, so
+/// `{user1}.name` and `{user1}.email` land in the same slot.
+pub fn hash_slot(key: &str) -> u16 {
+    let bytes = key.as_bytes();
+    let hashed = bytes
+        .iter()
+        .position(|&b| b == b'{')
+        .and_then(|open| {
+            let tag = &bytes[open + 1..];
+            tag.iter().position(|&b| b == b'}').filter(|&len| len > 0).map(|len| &tag[..len])
+        })
+        .unwrap_or(bytes);
+    crc16(hashed) % CLUSTER_SLOTS
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ (u16::from(byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 }
+        })
+    })
+}
+
+/// The slot map shared by every connection of a server
+pub struct SlotTable {
+    node_id: String,
+    assignments: Arc<RwLock<HashMap<u16, String>>>,
+}
+
+impl Default for SlotTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SlotTable {
+    /// Creates an empty map for a node with a freshly generated id
+    pub fn new() -> Self {
+        SlotTable {
+            node_id: uuid::Uuid::new_v4().simple().to_string(),
+            assignments: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// Assigns `slots` to this node, or none of them if any is invalid or taken
+    pub fn add_slots(&self, slots: &[u16]) -> Result<(), String> {
+        let mut assignments = self.assignments.write().unwrap();
+        Self::check_slots(slots)?;
+        if let Some(slot) = slots.iter().find(|slot| assignments.contains_key(slot)) {
+            return Err(format!("ERR Slot {} is already busy", slot));
+        }
+        for &slot in slots {
+            assignments.insert(slot, self.node_id.clone());
+        }
+        Ok(())
+    }
+
+    /// Assigns every slot of the inclusive `(start, end)` ranges, as ADDSLOTSRANGE
+    pub fn add_slot_ranges(&self, ranges: &[(u16, u16)]) -> Result<(), String> {
+        if let Some((start, end)) = ranges.iter().find(|(start, end)| start > end) {
+            return Err(format!("ERR start slot number {} is greater than end slot number {}", start, end));
+        }
+        let slots: Vec<u16> = ranges.iter().flat_map(|&(start, end)| start..=end).collect();
+        self.add_slots(&slots)
+    }
+
+    /// Removes the assignments of `slots`, whichever node holds them
+    pub fn del_slots(&self, slots: &[u16]) -> Result<(), String> {
+        let mut assignments = self.assignments.write().unwrap();
+        Self::check_slots(slots)?;
+        if let Some(slot) = slots.iter().find(|slot| !assignments.contains_key(slot)) {
+            return Err(format!("ERR Slot {} is already unassigned", slot));
+        }
+        for slot in slots {
+            assignments.remove(slot);
+        }
+        Ok(())
+    }
+
+    fn check_slots(slots: &[u16]) -> Result<(), String> {
+        if slots.iter().any(|&slot| slot >= CLUSTER_SLOTS) {
+            return Err("ERR Invalid or out of range slot".to_string());
+        }
+        let mut seen = HashSet::new();
+        match slots.iter().find(|slot| !seen.insert(**slot)) {
+            Some(slot) => Err(format!("ERR Slot {} specified multiple times", slot)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns how many slots have an owner
+    pub fn assigned_count(&self) -> usize {
+        self.assignments.read().unwrap().len()
+    }
+
+    /// Returns the CLUSTER INFO lines
+    ///
+    /// The state is `ok` only once every slot is served. Known nodes are
+    /// this one plus every node owning a slot.
+    pub fn info(&self) -> String {
+        let assignments = self.assignments.read().unwrap();
+        let owners: HashSet<&str> = assignments.values().map(String::as_str).collect();
+        let known_nodes = owners.len() + usize::from(!owners.contains(self.node_id.as_str()));
+        let state = if assignments.len() == usize::from(CLUSTER_SLOTS) { "ok" } else { "fail" };
+        [
+            format!("cluster_state:{}", state),
+            format!("cluster_slots_assigned:{}", assignments.len()),
+            format!("cluster_slots_ok:{}", assignments.len()),
+            "cluster_slots_pfail:0".to_string(),
+            "cluster_slots_fail:0".to_string(),
+            format!("cluster_known_nodes:{}", known_nodes),
+            format!("cluster_size:{}", owners.len()),
+        ]
+        .join("\n")
+    }
+
+    /// Builds the message announcing the slots this node serves
+    pub fn announcement(&self) -> RaftMessage {
+        let mut slots: Vec<u16> = self
+            .assignments
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, owner)| **owner == self.node_id)
+            .map(|(slot, _)| *slot)
+            .collect();
+        slots.sort_unstable();
+        RaftMessage::SlotAssignments { node_id: self.node_id.clone(), slots }
+    }
+
+    /// Records a peer's `SlotAssignments`; other messages and our own are ignored
+    pub fn apply(&self, message: &RaftMessage) {
+        let RaftMessage::SlotAssignments { node_id, slots } = message else {
+            return;
+        };
+        if *node_id == self.node_id {
+            return;
+        }
+        let mut assignments = self.assignments.write().unwrap();
+        assignments.retain(|_, owner| owner != node_id);
+        for &slot in slots.iter().filter(|&&slot| slot < CLUSTER_SLOTS) {
+            assignments.insert(slot, node_id.clone());
+        }
+    }
+}
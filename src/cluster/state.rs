@@ -1,8 +1,43 @@
 use std::time::{Duration, Instant};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use serde::{Serialize, Deserialize};
 use rand::Rng;
 use super::error::{RaftError, RaftResult};
 
+/// Name of the hard state file, kept next to the log store's files
+pub const HARD_STATE_FILE: &str = "hard_state";
+
+/// What a node must remember across restarts to never vote twice in a term
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HardState {
+    pub current_term: u64,
+    pub voted_for: Option<String>,
+}
+
+impl HardState {
+    /// Reads the hard state at `path`; `None` if the file doesn't exist yet
+    pub fn load(path: &Path) -> RaftResult<Option<HardState>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes the hard state to `path` durably: to a temporary file first,
+    /// synced to disk, then renamed over the old one
+    pub fn save(&self, path: &Path) -> RaftResult<()> {
+        let temp_path = path.with_extension("tmp");
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&bincode::serialize(self)?)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+}
+
 // Raft node role
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum NodeRole {
@@ -54,6 +89,9 @@ pub struct RaftState {
     
     // Configuration
     config: RaftConfig,
+
+    // Where current_term and voted_for are persisted; None keeps them in memory only
+    hard_state_path: Option<PathBuf>,
 }
 
 impl RaftState {
@@ -80,8 +118,35 @@ impl RaftState {
             last_applied: 0,
             
             config,
+            hard_state_path: None,
         }
     }
+
+    /// Creates the state of a node that persists its hard state in `dir`
+    ///
+    /// The term and vote are loaded from the hard state file if it exists,
+    /// and every change to them is written to it before taking effect.
+    pub fn open(node_id: String, config: Option<RaftConfig>, dir: &Path) -> RaftResult<Self> {
+        let path = dir.join(HARD_STATE_FILE);
+        let mut state = Self::new(node_id, config);
+        if let Some(hard_state) = HardState::load(&path)? {
+            state.current_term = hard_state.current_term;
+            state.voted_for = hard_state.voted_for;
+        }
+        state.hard_state_path = Some(path);
+        Ok(state)
+    }
+
+    // Make a term and vote current, persisting them first when there is a hard state file
+    fn set_hard_state(&mut self, current_term: u64, voted_for: Option<String>) -> RaftResult<()> {
+        let hard_state = HardState { current_term, voted_for };
+        if let Some(path) = &self.hard_state_path {
+            hard_state.save(path)?;
+        }
+        self.current_term = hard_state.current_term;
+        self.voted_for = hard_state.voted_for;
+        Ok(())
+    }
     
     // Generate a random election timeout
     fn random_election_timeout(config: &RaftConfig) -> Duration {
@@ -108,8 +173,7 @@ impl RaftState {
         }
         
         if term > self.current_term {
-            self.current_term = term;
-            self.voted_for = None;
+            self.set_hard_state(term, None)?;
             self.role = NodeRole::Follower;
         }
         
@@ -123,12 +187,12 @@ impl RaftState {
     }
     
     // Start an election
-    pub fn begin_election(&mut self) {
+    pub fn begin_election(&mut self) -> RaftResult<()> {
+        self.set_hard_state(self.current_term + 1, Some(self.node_id.clone()))?;
         self.role = NodeRole::Candidate;
-        self.current_term += 1;
-        self.voted_for = Some(self.node_id.clone());
         self.votes_received = 1;
         self.reset_election_timeout();
+        Ok(())
     }
     
     // Handle a vote request
@@ -153,7 +217,7 @@ impl RaftState {
             (last_log_term == self.last_log_term && last_log_index >= self.last_log_index);
             
         if can_vote && log_is_current {
-            self.voted_for = Some(candidate_id.to_string());
+            self.set_hard_state(self.current_term, Some(candidate_id.to_string()))?;
             self.reset_election_timeout();
             Ok(true)
         } else {
@@ -171,7 +235,7 @@ impl RaftState {
     // Check if the election is won
     pub fn check_election_won(&self, cluster_size: usize) -> bool {
         self.role == NodeRole::Candidate && 
-        self.votes_received > ((cluster_size / 2)).try_into().unwrap()
+        self.votes_received > (cluster_size / 2).try_into().unwrap()
     }
     
     // Transition to Leader
@@ -220,7 +284,7 @@ mod tests {
     #[test]
     fn test_begin_election() {
         let mut state = setup_test_state();
-        state.begin_election();
+        state.begin_election().unwrap();
         
         assert_eq!(state.role, NodeRole::Candidate);
         assert_eq!(state.current_term, 1);
@@ -242,4 +306,25 @@ mod tests {
         assert!(!result);
         assert_eq!(state.voted_for, Some("node2".to_string()));
     }
+
+    #[test]
+    fn test_hard_state_survives_a_restart() {
+        let dir = std::env::temp_dir().join(format!("test_hard_state_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut state = RaftState::open("node1".to_string(), None, &dir).unwrap();
+        assert_eq!((state.current_term, state.voted_for.clone()), (0, None));
+        assert!(state.handle_vote_request("node2", 1, 0, 0).unwrap());
+
+        // A restarted node remembers its vote and won't grant another in the same term
+        let mut state = RaftState::open("node1".to_string(), None, &dir).unwrap();
+        assert_eq!((state.current_term, state.voted_for.clone()), (1, Some("node2".to_string())));
+        assert!(!state.handle_vote_request("node3", 1, 0, 0).unwrap());
+
+        state.begin_election().unwrap();
+        let state = RaftState::open("node1".to_string(), None, &dir).unwrap();
+        assert_eq!((state.current_term, state.voted_for.clone()), (2, Some("node1".to_string())));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
\ No newline at end of file
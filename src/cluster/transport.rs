@@ -8,6 +8,17 @@ use std::time::Duration;
 use super::message::RaftMessage;
 use super::error::{RaftError, RaftResult};
 
+/// Version of the message encoding, sent at the start of every frame; a peer
+/// speaking another version is disconnected rather than misread
+pub const PROTOCOL_VERSION: u8 = 3;
+
+/// Largest message frame a peer may send, in bytes; a longer length prefix
+/// closes the connection instead of allocating the buffer
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Where `RaftTransport::new` listens
+const DEFAULT_LISTEN_ADDR: &str = "0.0.0.0:5000";
+
 pub trait Transport: Send + Sync {
     /// Send message to specified node
     fn send(&self, to: &str, msg: RaftMessage) -> impl std::future::Future<Output = RaftResult<()>> + Send;
@@ -21,7 +32,10 @@ pub trait Transport: Send + Sync {
 
 pub struct RaftTransport {
     /// Current node ID
+    #[allow(dead_code)]
     node_id: String,
+    /// Address `start` listens on
+    listen_addr: String,
     /// Node connection pool
     connections: Arc<RwLock<HashMap<String, Arc<Mutex<TcpStream>>>>>,
     /// Message broadcast channel
@@ -34,11 +48,26 @@ impl RaftTransport {
     pub fn new(
         node_id: String, 
         msg_callback: Arc<dyn Fn(RaftMessage) -> RaftResult<()> + Send + Sync>
+    ) -> Self {
+        Self::with_listen_addr(node_id, DEFAULT_LISTEN_ADDR.to_string(), msg_callback)
+    }
+
+    /// Creates a transport whose `start` listens on `listen_addr` rather than
+    /// the default `0.0.0.0:5000`
+    ///
+    /// Every message goes out as a frame: `PROTOCOL_VERSION`, the length of
+    /// the message's bincode encoding as a 4-byte big-endian integer, then
+    /// the encoding.
+    pub fn with_listen_addr(
+        node_id: String,
+        listen_addr: String,
+        msg_callback: Arc<dyn Fn(RaftMessage) -> RaftResult<()> + Send + Sync>
     ) -> Self {
         let (tx, mut rx) = mpsc::channel(1000);
         
         let transport = RaftTransport {
             node_id,
+            listen_addr,
             connections: Arc::new(RwLock::new(HashMap::new())),
             broadcast_tx: tx,
             msg_callback,
@@ -50,21 +79,30 @@ impl RaftTransport {
                 let stream_clone = {
                     connections.read()
                         .get(&to)
-                        .map(|s| Arc::clone(s)) // Clone the Arc
+                        .map(Arc::clone) // Clone the Arc
                 };
 
                 if let Some(stream) = stream_clone {
                     match bincode::serialize(&msg) {
+                        Ok(msg_data) if msg_data.len() > MAX_FRAME_SIZE => {
+                            tracing::error!(bytes = msg_data.len(), "message too large to send");
+                        }
                         Ok(msg_data) => {
+                            let mut frame = Vec::with_capacity(5 + msg_data.len());
+                            frame.push(PROTOCOL_VERSION);
+                            frame.extend_from_slice(&(msg_data.len() as u32).to_be_bytes());
+                            frame.extend_from_slice(&msg_data);
                             let mut stream = stream.lock().await; // Lock the Mutex
-                            if let Err(e) = tokio::time::timeout(
+                            match tokio::time::timeout(
                                 Duration::from_secs(5),
-                                stream.write_all(&msg_data)
+                                stream.write_all(&frame)
                             ).await {
-                                eprintln!("Failed to send message: {}", e);
+                                Ok(Ok(())) => {}
+                                Ok(Err(e)) => tracing::warn!(error = %e, "failed to send message"),
+                                Err(e) => tracing::warn!(error = %e, "timed out sending message"),
                             }
                         }
-                        Err(e) => eprintln!("Failed to serialize message: {}", e),
+                        Err(e) => tracing::error!(error = %e, "failed to serialize message"),
                     }
                 }
             }
@@ -97,12 +135,12 @@ impl Transport for RaftTransport {
     }
 
     async fn start(&self) -> RaftResult<()> {
-        let addr = "0.0.0.0:5000";
+        let addr = self.listen_addr.as_str();
         let listener = TcpListener::bind(addr)
             .await
             .map_err(|e| RaftError::Transport(format!("Bind failed: {}", e)))?;
 
-        println!("Transport listening on {}", addr);
+        tracing::info!(address = %addr, "transport listening");
 
         let msg_callback = Arc::clone(&self.msg_callback);
 
@@ -110,7 +148,7 @@ impl Transport for RaftTransport {
             loop {
                 match listener.accept().await {
                     Ok((mut stream, addr)) => {
-                        println!("New connection from: {}", addr);
+                        tracing::debug!(peer_addr = %addr, "new transport connection");
                         
                         let msg_callback = Arc::clone(&msg_callback);
                         
@@ -118,37 +156,45 @@ impl Transport for RaftTransport {
                             let mut buffer = Vec::new();
                             
                             loop {
-                                let mut len_bytes = [0u8; 4];
-                                match stream.read_exact(&mut len_bytes).await {
+                                let mut header = [0u8; 5];
+                                match stream.read_exact(&mut header).await {
                                     Ok(_) => {
-                                        let len = u32::from_be_bytes(len_bytes) as usize;
+                                        if header[0] != PROTOCOL_VERSION {
+                                            tracing::warn!(version = header[0], "unsupported protocol version, closing connection");
+                                            break;
+                                        }
+                                        let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+                                        if len > MAX_FRAME_SIZE {
+                                            tracing::warn!(bytes = len, "message frame too large, closing connection");
+                                            break;
+                                        }
                                         buffer.resize(len, 0);
                                         match stream.read_exact(&mut buffer).await {
                                             Ok(_) => {
                                                 match bincode::deserialize::<RaftMessage>(&buffer) {
                                                     Ok(msg) => {
                                                         if let Err(e) = (msg_callback)(msg) {
-                                                            eprintln!("Failed to process message: {}", e);
+                                                            tracing::error!(error = %e, "failed to process message");
                                                         }
                                                     }
-                                                    Err(e) => eprintln!("Failed to deserialize message: {}", e),
+                                                    Err(e) => tracing::warn!(error = %e, "failed to deserialize message"),
                                                 }
                                             }
                                             Err(e) => {
-                                                eprintln!("Failed to read message: {}", e);
+                                                tracing::warn!(error = %e, "failed to read message");
                                                 break;
                                             }
                                         }
                                     }
                                     Err(e) => {
-                                        eprintln!("Failed to read message length: {}", e);
+                                        tracing::warn!(error = %e, "failed to read message header");
                                         break;
                                     }
                                 }
                             }
                         });
                     }
-                    Err(e) => eprintln!("Failed to accept connection: {}", e),
+                    Err(e) => tracing::warn!(error = %e, "failed to accept connection"),
                 }
             }
         });
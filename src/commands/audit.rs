@@ -0,0 +1,202 @@
+//! # Audit Module
+//!
+//! An append-only record of every successful write command: when it ran,
+//! which client sent it, and the keys it touched. Lines are handed to a
+//! dedicated logger thread over a bounded channel, so a slow disk never
+//! stalls command execution; when the channel is full the line is dropped
+//! and counted instead. The file is rolled over to `<path>.1` once it
+//! reaches the configured size.
+use crate::config::config::Config;
+use crate::network::client::ClientInfo;
+use super::parser::Command;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Handle to the audit log; recording never blocks
+pub struct AuditLog {
+    sender: SyncSender<String>,
+    dropped: Arc<AtomicU64>,
+    max_value_len: usize,
+}
+
+impl AuditLog {
+    /// Opens (or creates) the log at `config.audit_log_path` and starts its logger thread
+    ///
+    /// The thread exits once the `AuditLog` is dropped and the queued lines are written.
+    pub fn open(config: &Config) -> io::Result<AuditLog> {
+        let file = RotatingFile::open(&config.audit_log_path, config.audit_log_max_size)?;
+        let (sender, receiver) = mpsc::sync_channel(config.audit_log_queue_size.max(1));
+        std::thread::Builder::new()
+            .name("audit-log".to_string())
+            .spawn(move || Self::run(receiver, file))?;
+        Ok(AuditLog {
+            sender,
+            dropped: Arc::new(AtomicU64::new(0)),
+            max_value_len: config.audit_log_max_value_len,
+        })
+    }
+
+    /// Queues a line for `command`, which must have succeeded
+    ///
+    /// Commands that don't write are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client that sent the command; `None` when it didn't come over the network
+    /// * `command` - The command that ran
+    pub fn record(&self, client: Option<&ClientInfo>, command: &Command) {
+        if !command.is_write() {
+            return;
+        }
+        match self.sender.try_send(self.format_line(client, command)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Returns how many lines were dropped because the logger fell behind
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Formats one line: `<unix time> id=<id> addr=<addr> db=0 cmd=<name> keys=[..] values=..`
+    fn format_line(&self, client: Option<&ClientInfo>, command: &Command) -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let (id, addr) = match client {
+            Some(client) => (client.id.to_string(), client.addr.as_str()),
+            None => ("-".to_string(), "-"),
+        };
+        let (keys, values) = Self::arguments(command);
+        let values = if self.max_value_len == 0 {
+            "[redacted]".to_string()
+        } else {
+            let values: Vec<String> = values.iter().map(|value| self.truncate(value)).collect();
+            format!("{:?}", values)
+        };
+        format!(
+            "{}.{:03} id={} addr={} db=0 cmd={} keys={:?} values={}",
+            now.as_secs(), now.subsec_millis(), id, addr, command.name(), keys, values
+        )
+    }
+
+    fn truncate(&self, value: &str) -> String {
+        match value.char_indices().nth(self.max_value_len) {
+            Some((end, _)) => format!("{}...", &value[..end]),
+            None => value.to_string(),
+        }
+    }
+
+    /// Splits a write command into the keys it touches and the values it stores
+    fn arguments(command: &Command) -> (Vec<&str>, Vec<String>) {
+        match command {
+            Command::Set(key, value)
+            | Command::LPush(key, value)
+            | Command::RPush(key, value) => (vec![key], vec![value.clone()]),
+            Command::Expire(key, seconds) => (vec![key], vec![seconds.to_string()]),
+            Command::ExpireOpts(key, seconds, _) => (vec![key], vec![seconds.to_string()]),
+            Command::SAdd(key, members) => (vec![key], members.clone()),
+            Command::SMove(source, destination, member) => (vec![source, destination], vec![member.clone()]),
+            Command::Copy(source, destination, ..) => (vec![source, destination], Vec::new()),
+            Command::BLMove(source, destination, ..) => (vec![source, destination], Vec::new()),
+            Command::SDiffStore(destination, keys)
+            | Command::SUnionStore(destination, keys)
+            | Command::SInterStore(destination, keys) => {
+                (std::iter::once(destination).chain(keys).map(String::as_str).collect(), Vec::new())
+            }
+            Command::HSet(key, pairs) | Command::HMSet(key, pairs) => (
+                vec![key],
+                pairs.iter().map(|(field, value)| format!("{} {}", field, value)).collect(),
+            ),
+            Command::HSetNx(key, field, value) => (vec![key], vec![format!("{} {}", field, value)]),
+            Command::HIncrBy(key, field, delta) => (vec![key], vec![format!("{} {}", field, delta)]),
+            Command::HIncrByFloat(key, field, delta) => (vec![key], vec![format!("{} {}", field, delta)]),
+            Command::ZAdd(key, _, pairs) => (
+                vec![key],
+                pairs.iter().map(|(score, member)| format!("{} {}", score, member)).collect(),
+            ),
+            Command::ZIncrBy(key, increment, member) => (vec![key], vec![format!("{} {}", increment, member)]),
+            Command::GeoAdd { key, items, .. } => (
+                vec![key],
+                items.iter().map(|(longitude, latitude, member)| format!("{} {} {}", longitude, latitude, member)).collect(),
+            ),
+            Command::GeoSearch { key, store, .. } => {
+                (std::iter::once(key).chain(store.as_ref().map(|(_, destination)| destination)).map(String::as_str).collect(), Vec::new())
+            }
+            Command::XDel(key, ids) => (vec![key], ids.clone()),
+            Command::XAdd(key, _, fields) => (
+                vec![key],
+                fields.iter().map(|(field, value)| format!("{} {}", field, value)).collect(),
+            ),
+            Command::XReadGroup { streams, .. } | Command::XRead { streams, .. } => {
+                (streams.iter().map(|(key, _)| key.as_str()).collect(), Vec::new())
+            }
+            Command::Sort { key, store, .. } => (std::iter::once(key).chain(store).map(String::as_str).collect(), Vec::new()),
+            Command::BLPop(keys, _)
+            | Command::BRPop(keys, _)
+            | Command::BZPopMin(keys, _)
+            | Command::BZPopMax(keys, _) => (keys.iter().map(String::as_str).collect(), Vec::new()),
+            _ => (command.key().into_iter().collect(), Vec::new()),
+        }
+    }
+
+    fn run(receiver: Receiver<String>, mut file: RotatingFile) {
+        // Write whatever has queued up, then flush once per batch
+        while let Ok(line) = receiver.recv() {
+            let result = std::iter::once(line)
+                .chain(receiver.try_iter())
+                .try_for_each(|line| file.write_line(&line))
+                .and_then(|()| file.flush());
+            if let Err(e) = result {
+                tracing::error!(error = %e, path = %file.path, "failed to write audit log");
+            }
+        }
+    }
+}
+
+/// An append-only file that moves itself to `<path>.1` once it grows past `max_size`
+struct RotatingFile {
+    path: String,
+    max_size: u64,
+    size: u64,
+    writer: BufWriter<File>,
+}
+
+impl RotatingFile {
+    fn open(path: &str, max_size: u64) -> io::Result<RotatingFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile { path: path.to_string(), max_size, size, writer: BufWriter::new(file) })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.max_size > 0 && self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.writer, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Flushes and fsyncs, so a batch is on disk before the next one is written
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()
+    }
+
+    /// Replaces `<path>.1` with the current file and starts an empty one
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        fs::rename(&self.path, format!("{}.1", self.path))?;
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.writer = BufWriter::new(file);
+        self.size = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+//! # Blocking Module
+//!
+//! Parks BLPOP/BRPOP/BLMOVE clients until one of their lists gets an element,
+//! BZPOPMIN/BZPOPMAX clients until one of their sorted sets gets a member,
+//! and XREAD BLOCK clients until one of their streams gets a new entry.
+//! Blocked clients wait on a condvar paired with the storage mutex, so the
+//! lock is released while they are parked. Each watched key keeps a FIFO
+//! queue of tickets and only the client at the front may pop from it,
+//! which makes wakeups fair: the longest-waiting client is served first.
+use crate::storage::memory::MemoryStorage;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Registry of clients blocked in BLPOP/BRPOP/BLMOVE/BZPOPMIN/BZPOPMAX/XREAD
+#[derive(Default)]
+pub struct BlockedPops {
+    // Always locked while holding the storage lock, never the other way round
+    queues: Mutex<HashMap<String, VecDeque<u64>>>,
+    next_ticket: AtomicU64,
+    blocked: AtomicUsize,
+    pushed: Condvar,
+    closed: AtomicBool,
+}
+
+impl BlockedPops {
+    /// Creates a registry with no blocked clients
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns how many clients are currently parked in a blocking pop
+    pub fn blocked_clients(&self) -> usize {
+        self.blocked.load(Ordering::SeqCst)
+    }
+
+    /// Wakes blocked clients after elements may have been pushed
+    pub fn notify_pushed(&self) {
+        self.pushed.notify_all();
+    }
+
+    /// Releases every blocked client with no result, for server shutdown
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.pushed.notify_all();
+    }
+
+    /// Converts a blocking command's timeout in seconds into how long to wait
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(None)` - For a timeout of 0, which waits forever
+    /// * `Ok(Some(Duration))` - For any other timeout
+    /// * `Err(String)` - If the timeout is too large to represent
+    pub fn timeout(seconds: f64) -> Result<Option<Duration>, String> {
+        if seconds == 0.0 {
+            return Ok(None);
+        }
+        Duration::try_from_secs_f64(seconds).map(Some).map_err(|_| "ERR timeout is out of range".to_string())
+    }
+
+    /// Pops from the first of `keys` holding a non-empty list, without blocking
+    ///
+    /// # Returns
+    ///
+    /// The key popped from and the element
+    pub fn try_pop(storage: &mut MemoryStorage, keys: &[String], from_left: bool) -> Option<(String, String)> {
+        keys.iter().find_map(|key| Self::pop_key(storage, key, from_left))
+    }
+
+    /// Pops from `key` if it holds a non-empty list, reaping it first if it has expired
+    pub fn pop_key(storage: &mut MemoryStorage, key: &str, from_left: bool) -> Option<(String, String)> {
+        storage.expire_if_needed(key);
+        if storage.llen(key) == 0 {
+            return None;
+        }
+        let value = if from_left { storage.lpop(key) } else { storage.rpop(key) };
+        value.map(|value| (key.to_string(), value))
+    }
+
+    /// Pops from the first of `keys` with an element, waiting up to `timeout`
+    ///
+    /// The client joins the queue of every key and sleeps until a push wakes
+    /// it. It then pops from the first key where it is next in line; if the
+    /// element was taken meanwhile (by a plain LPOP, say), it parks again.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The storage mutex, released while parked
+    /// * `keys` - Lists to watch, in priority order
+    /// * `timeout` - How long to wait; `None` waits until an element arrives
+    /// * `from_left` - Pop from the head (BLPOP) rather than the tail (BRPOP)
+    ///
+    /// # Returns
+    ///
+    /// The key popped from and the element, or `None` on timeout or shutdown
+    pub fn pop(
+        &self,
+        storage: &Mutex<MemoryStorage>,
+        keys: &[String],
+        timeout: Option<Duration>,
+        from_left: bool,
+    ) -> Option<(String, String)> {
+        self.wait_for(storage, keys, timeout, |storage, key| Self::pop_key(storage, key, from_left))
+            .map(|(_, popped)| popped)
+    }
+
+    /// Waits in line on `keys` until `take` succeeds on one of them
+    ///
+    /// `take` runs with the storage locked whenever this client is at the
+    /// front of a key's queue. Whatever it does to the storage, pop and push
+    /// alike, happens in that one critical section. Returning `None` means
+    /// there was nothing to take and the client parks again.
+    ///
+    /// # Returns
+    ///
+    /// The key `take` succeeded on and its result, or `None` on timeout or shutdown
+    pub fn wait_for<'k, T>(
+        &self,
+        storage: &Mutex<MemoryStorage>,
+        keys: &'k [String],
+        timeout: Option<Duration>,
+        mut take: impl FnMut(&mut MemoryStorage, &str) -> Option<T>,
+    ) -> Option<(&'k str, T)> {
+        // A deadline past what an Instant can hold is as good as none
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut storage = storage.lock().unwrap();
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut queues = self.queues.lock().unwrap();
+            for key in keys {
+                queues.entry(key.clone()).or_default().push_back(ticket);
+            }
+        }
+        self.blocked.fetch_add(1, Ordering::SeqCst);
+
+        let result = loop {
+            let next_in_line: Vec<&String> = {
+                let queues = self.queues.lock().unwrap();
+                keys.iter()
+                    .filter(|key| queues.get(*key).and_then(|queue| queue.front()) == Some(&ticket))
+                    .collect()
+            };
+            if let Some(taken) = next_in_line.iter().find_map(|key| take(&mut storage, key).map(|t| (key.as_str(), t))) {
+                break Some(taken);
+            }
+            if self.closed.load(Ordering::SeqCst) {
+                break None;
+            }
+            storage = match deadline {
+                None => self.pushed.wait(storage).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break None;
+                    }
+                    self.pushed.wait_timeout(storage, deadline - now).unwrap().0
+                },
+            };
+        };
+
+        let mut queues = self.queues.lock().unwrap();
+        for key in keys {
+            if let Some(queue) = queues.get_mut(key) {
+                queue.retain(|&waiting| waiting != ticket);
+                if queue.is_empty() {
+                    queues.remove(key);
+                }
+            }
+        }
+        drop(queues);
+        self.blocked.fetch_sub(1, Ordering::SeqCst);
+        drop(storage);
+        // Whoever is now at the front of these queues may have an element waiting
+        self.pushed.notify_all();
+        result
+    }
+}
@@ -4,18 +4,76 @@
 //! handling command processing and storage interactions with thread-safe
 //! mechanisms using Arc and Mutex.
 
+use std::collections::HashMap;
+use std::fmt::Write;
 use std::sync::Arc;
 use std::sync::Mutex;
-use crate::storage::memory::MemoryStorage;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::storage::aof;
+use crate::storage::geo::{GeoMatch, GeoSearchParams};
+use crate::storage::memory::{ExpireFlags, MemoryStorage, MEMORY_USAGE_SAMPLES};
+use crate::storage::scan::DEFAULT_SCAN_COUNT;
+use crate::storage::stream::{Stream, StreamEntry, StreamEntryId, STREAM_NODE_MAX_ENTRIES, XINFO_FULL_COUNT};
+use crate::storage::zset::ZAddOptions;
+use crate::cluster::replication::ReplicationAcks;
+use crate::cluster::slots::CLUSTER_SLOTS;
+use crate::config::config::Config;
+use crate::config::runtime::RuntimeConfig;
+use crate::network::client::ClientInfo;
+use crate::network::maintenance::MaintenanceStats;
+use crate::network::pubsub::Outbox;
+use crate::network::replica::{self, MasterLink, Replicas};
+use crate::network::resp::RespValue;
+use crate::network::stats::ServerStats;
 
-use super::parser::Command;
+use super::audit::AuditLog;
+use super::blocking::BlockedPops;
+use super::lolwut;
+use super::parser::{Command, DebugSubcommand, ListSide, SortOrder, XInfoSubcommand};
+
+/// Reply to OBJECT HELP, one line per subcommand
+const OBJECT_HELP: &[&str] = &[
+    "ENCODING <key> -- Return the encoding of the object stored at <key>",
+    "FREQ <key> -- Return the access frequency of the object",
+    "HELP -- Return subcommand help summary",
+    "IDLETIME <key> -- Return the idle time of the object",
+    "REFCOUNT <key> -- Return the reference count of the object",
+];
+
+/// Reply to a write refused because memory is over `max_memory` and nothing can be evicted
+const OOM_ERROR: &str = "OOM command not allowed when used memory > 'maxmemory'.";
+
+/// Call count and cumulative execution time of one command
+#[derive(Default)]
+pub struct CommandStat {
+    pub calls: AtomicU64,
+    pub usec_total: AtomicU64,
+}
 
 /// A thread-safe command executor that processes Redis-like commands
 /// 
 /// Manages the execution of commands against a shared memory storage,
 /// providing atomic operations and transaction support.
 pub struct CommandExecutor {
-    storage: Arc<Mutex<MemoryStorage>>
+    storage: Arc<Mutex<MemoryStorage>>,
+    commandstats: Arc<HashMap<&'static str, CommandStat>>,
+    replication: Arc<ReplicationAcks>,
+    config: Arc<Config>,
+    // The settings CONFIG SET changes, read here instead of from `config`
+    runtime: Arc<RuntimeConfig>,
+    blocked: Arc<BlockedPops>,
+    bgsave_in_progress: Arc<AtomicBool>,
+    aof_rewrite_in_progress: Arc<AtomicBool>,
+    audit: Option<AuditLog>,
+    // Clients cut off for not reading their replies or messages fast enough
+    output_buffer_disconnections: Arc<AtomicU64>,
+    maintenance: Arc<MaintenanceStats>,
+    stats: Arc<ServerStats>,
+    // Servers replicating this one, fed every write
+    replicas: Replicas,
+    // The master this server replicates, once REPLICAOF names one
+    master_link: MasterLink,
 }
 
 impl CommandExecutor {
@@ -25,7 +83,399 @@ impl CommandExecutor {
     ///
     /// * `storage` - Thread-safe reference to the memory storage
     pub fn new(storage: Arc<Mutex<MemoryStorage>>) -> Self {
-        CommandExecutor { storage }
+        Self::with_config(storage, Arc::new(Config::new()))
+    }
+
+    /// Creates a CommandExecutor that serves CONFIG commands from `config`
+    ///
+    /// Opens the audit log if `config` enables it; if the file can't be
+    /// opened the error is logged and auditing stays off.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - Thread-safe reference to the memory storage
+    /// * `config` - The server's running configuration
+    pub fn with_config(storage: Arc<Mutex<MemoryStorage>>, config: Arc<Config>) -> Self {
+        let commandstats = Command::NAMES
+            .iter()
+            .map(|&name| (name, CommandStat::default()))
+            .collect();
+        let audit = if config.audit_log_enabled {
+            AuditLog::open(&config)
+                .inspect_err(|e| tracing::error!(error = %e, path = %config.audit_log_path, "failed to open audit log"))
+                .ok()
+        } else {
+            None
+        };
+        let replicas = Replicas::new(config.repl_backlog_size);
+        CommandExecutor {
+            storage,
+            commandstats: Arc::new(commandstats),
+            replication: Arc::new(ReplicationAcks::new()),
+            runtime: Arc::new(RuntimeConfig::new(&config)),
+            config,
+            blocked: Arc::new(BlockedPops::new()),
+            bgsave_in_progress: Arc::new(AtomicBool::new(false)),
+            aof_rewrite_in_progress: Arc::new(AtomicBool::new(false)),
+            audit,
+            output_buffer_disconnections: Arc::new(AtomicU64::new(0)),
+            maintenance: Arc::new(MaintenanceStats::default()),
+            stats: Arc::new(ServerStats::default()),
+            replicas,
+            master_link: MasterLink::new(),
+        }
+    }
+
+    /// Returns the replica acknowledgement tracker WAIT blocks on
+    pub fn replication(&self) -> Arc<ReplicationAcks> {
+        Arc::clone(&self.replication)
+    }
+
+    /// Formats `INFO commandstats`: one `cmdstat_<name>` line per command called so far
+    ///
+    /// Reads only the atomic counters, never the storage lock.
+    pub fn commandstats(&self) -> String {
+        let mut names: Vec<&&str> = self.commandstats.keys().collect();
+        names.sort();
+
+        let mut output = String::from("# Commandstats\n");
+        for name in names {
+            let stat = &self.commandstats[*name];
+            let calls = stat.calls.load(Ordering::Relaxed);
+            if calls == 0 {
+                continue;
+            }
+            let usec = stat.usec_total.load(Ordering::Relaxed);
+            let _ = writeln!(
+                output,
+                "cmdstat_{}:calls={},usec={},usec_per_call={:.3}",
+                name, calls, usec, usec as f64 / calls as f64
+            );
+        }
+        output
+    }
+
+    /// Returns the counter connections bump when they disconnect a client
+    /// over its output buffer limit
+    pub fn output_buffer_disconnections(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.output_buffer_disconnections)
+    }
+
+    /// Returns where the server's maintenance thread records its task timings
+    pub fn maintenance_stats(&self) -> Arc<MaintenanceStats> {
+        Arc::clone(&self.maintenance)
+    }
+
+    /// Returns the connection, command and traffic counters the server and its clients update
+    pub fn server_stats(&self) -> Arc<ServerStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Returns the live values of the settings CONFIG SET changes
+    pub fn runtime_config(&self) -> Arc<RuntimeConfig> {
+        Arc::clone(&self.runtime)
+    }
+
+    /// Renders the INFO memory section, estimating the memory in use
+    fn memory_info(&self) -> String {
+        format!(
+            "# Memory\nused_memory:{}\nmaxmemory:{}\nmaxmemory_policy:{}\n",
+            self.storage.lock().unwrap().used_memory(),
+            self.runtime.max_memory(),
+            self.runtime.maxmemory_policy().as_str()
+        )
+    }
+
+    /// Renders the INFO clients section
+    fn clients_info(&self) -> String {
+        format!("# Clients\nconnected_clients:{}\n", self.stats.connected_clients())
+    }
+
+    /// Renders the INFO stats section
+    fn stats_info(&self) -> String {
+        format!(
+            "# Stats\nclient_output_buffer_limit_disconnections:{}\n{}{}",
+            self.output_buffer_disconnections.load(Ordering::Relaxed),
+            self.stats.info(),
+            self.replicas.sync_stats()
+        )
+    }
+
+    /// Renders the INFO persistence section
+    fn persistence_info(&self) -> String {
+        let (dirty, since_last_save) = {
+            let storage = self.storage.lock().unwrap();
+            (storage.dirty_count(), storage.since_last_save())
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        format!(
+            "# Persistence\nrdb_changes_since_last_save:{}\nrdb_bgsave_in_progress:{}\nrdb_last_save_time:{}\naof_rewrite_in_progress:{}\n",
+            dirty,
+            u8::from(self.bgsave_in_progress.load(Ordering::SeqCst)),
+            now.saturating_sub(since_last_save).as_secs(),
+            u8::from(self.aof_rewrite_in_progress.load(Ordering::SeqCst))
+        )
+    }
+
+    /// Renders the audit log's state as the INFO audit section
+    fn audit_info(&self) -> String {
+        format!(
+            "# Audit\naudit_log_enabled:{}\naudit_log_dropped:{}\n",
+            u8::from(self.audit.is_some()),
+            self.audit.as_ref().map_or(0, AuditLog::dropped)
+        )
+    }
+
+    /// Returns what REPLICAOF set: the master this server replicates, if any
+    pub fn master_link(&self) -> &MasterLink {
+        &self.master_link
+    }
+
+    /// Starts streaming to a replica that sent SYNC or PSYNC
+    ///
+    /// A replica that asked to continue from an offset the backlog still
+    /// holds is sent the writes after it. Otherwise the dataset is taken,
+    /// and the replica attached, under the storage lock; the full sync is
+    /// encoded after it is released, with the writes made meanwhile held
+    /// back to follow it.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The replica's connection, for `detach_replica`
+    /// * `addr` - The replica's address, for INFO replication
+    /// * `outbox` - Where the full sync and every later write are queued
+    /// * `psync` - The replication id and offset PSYNC asked to continue from
+    pub fn attach_replica(&self, client_id: u64, addr: &str, outbox: Outbox, psync: Option<(&str, u64)>) {
+        if let Some((replication_id, from)) = psync {
+            let current_id = self.replication.replication_id();
+            if self.replicas.resume(client_id, addr, outbox.clone(), replication_id, from, &current_id) {
+                tracing::info!(client_id, addr = %addr, offset = from, "replica attached, partial resync accepted");
+                return;
+            }
+        }
+        let (data, offset) = {
+            let storage = self.storage.lock().unwrap();
+            let offset = self.replicas.attach(client_id, addr, outbox);
+            (storage.frozen(), offset)
+        };
+        let full_sync = replica::full_sync(&data, &self.replication.replication_id(), offset);
+        self.replicas.start_streaming(client_id, full_sync);
+        tracing::info!(client_id, addr = %addr, "replica attached, full sync sent");
+    }
+
+    /// Records that a replica has applied the stream up to `offset`, for WAIT
+    ///
+    /// Acknowledgements from connections that aren't replicas are ignored.
+    pub fn ack_replica(&self, client_id: u64, offset: u64) {
+        if self.replicas.ack(client_id, offset) {
+            self.replication.ack(&client_id.to_string(), offset);
+        }
+    }
+
+    /// Stops streaming to a replica whose connection closed; other connections are ignored
+    pub fn detach_replica(&self, client_id: u64) {
+        self.replicas.detach(client_id);
+        self.replication.remove_replica(&client_id.to_string());
+    }
+
+    /// Sends an executed command to the replicas if it wrote anything
+    ///
+    /// Called with the storage still locked, so replicas get writes in the
+    /// order they were applied.
+    fn propagate(&self, command: &Command, response: &str) {
+        if let Some(args) = replica::replicated(command, response) {
+            self.propagate_args(&args);
+        }
+    }
+
+    /// Sends one write to the replicas and moves the offset WAIT waits for past it
+    fn propagate_args(&self, args: &[String]) {
+        if let Some(offset) = self.replicas.propagate(args) {
+            self.replication.record_write(offset);
+        }
+    }
+
+    /// Renders the role, the replicas and the stream's id and offset as the INFO replication section
+    fn replication_info(&self) -> String {
+        let mut output = String::from("# Replication\n");
+        match self.master_link.info() {
+            Some(master) => output.push_str(&master),
+            None => output.push_str("role:master\n"),
+        }
+        let _ = writeln!(output, "connected_slaves:{}", self.replicas.count());
+        output.push_str(&self.replicas.info());
+        let _ = writeln!(
+            output,
+            "master_replid:{}\nmaster_repl_offset:{}",
+            self.replication.replication_id(),
+            self.replication.replication_offset.load(Ordering::SeqCst)
+        );
+        output.push_str(&self.replicas.backlog_info());
+        output
+    }
+
+    /// Runs a DEBUG subcommand; refused outright in release builds
+    fn debug(&self, subcommand: DebugSubcommand) -> String {
+        if !cfg!(debug_assertions) {
+            return "ERR DEBUG command not supported in production mode".to_string();
+        }
+        match subcommand {
+            // Sleeps with the storage lock released, so only this client stalls
+            DebugSubcommand::Sleep(seconds) => {
+                std::thread::sleep(Duration::from_secs_f64(seconds));
+                "OK".to_string()
+            },
+            DebugSubcommand::Reload => {
+                let mut storage = self.storage.lock().unwrap();
+                let path = &self.config.snapshot_path;
+                match storage.save_snapshot(path).and_then(|()| storage.load_snapshot(path)) {
+                    Ok(()) => "OK".to_string(),
+                    Err(e) => format!("ERR Error trying to reload the DB: {}", e),
+                }
+            },
+            DebugSubcommand::Flushall => {
+                self.storage.lock().unwrap().flushall();
+                "OK".to_string()
+            },
+            DebugSubcommand::SetActiveExpire(enabled) => {
+                self.storage.lock().unwrap().set_active_expire(enabled);
+                "OK".to_string()
+            },
+            DebugSubcommand::QuicklistPackedThreshold(max_value) => {
+                let mut storage = self.storage.lock().unwrap();
+                let (max_size, _) = storage.list_encoding_limits();
+                storage.set_list_encoding_limits(max_size, max_value);
+                "OK".to_string()
+            },
+            DebugSubcommand::ChangeReplId => {
+                self.replication.change_replication_id();
+                "OK".to_string()
+            },
+            DebugSubcommand::Quicklist => {
+                let storage = self.storage.lock().unwrap();
+                let (max_size, max_value) = storage.list_encoding_limits();
+                let (listpack, quicklist) = storage.list_encoding_counts();
+                format!(
+                    "list-max-listpack-size:{}\nlist-max-listpack-value:{}\nlistpack_lists:{}\nquicklist_lists:{}",
+                    max_size, max_value, listpack, quicklist
+                )
+            },
+        }
+    }
+
+    /// Queues an audit line for `command` unless it failed
+    fn audit(&self, client: Option<&ClientInfo>, command: &Command, response: &str) {
+        if let Some(audit) = &self.audit {
+            if !response.starts_with("ERR") && !response.starts_with("WRONGTYPE") {
+                audit.record(client, command);
+            }
+        }
+    }
+
+    /// Zeros every command statistic and the server totals (CONFIG RESETSTAT)
+    pub fn reset_stats(&self) {
+        for stat in self.commandstats.values() {
+            stat.calls.store(0, Ordering::Relaxed);
+            stat.usec_total.store(0, Ordering::Relaxed);
+        }
+        self.stats.reset();
+    }
+
+    /// Writes a snapshot of the storage to `path`, blocking other commands meanwhile
+    pub fn save_snapshot(&self, path: &str) -> std::io::Result<()> {
+        self.storage.lock().unwrap().save_snapshot(path)
+    }
+
+    /// Replaces the whole dataset with a snapshot, as a replica does on a full sync
+    pub fn load_snapshot_from(&self, reader: impl std::io::BufRead) -> std::io::Result<()> {
+        self.storage.lock().unwrap().load_snapshot_from(reader)
+    }
+
+    /// Starts writing a snapshot to the configured path on a background thread
+    ///
+    /// Only taking a copy of the data happens under the storage lock. Once
+    /// the snapshot is on disk, the writes it holds come off the storage's
+    /// write counter.
+    ///
+    /// # Returns
+    ///
+    /// `false` if a background save is already running
+    pub(crate) fn start_background_save(&self) -> bool {
+        if self.bgsave_in_progress.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        let (data, dirty) = {
+            let storage = self.storage.lock().unwrap();
+            (storage.frozen(), storage.dirty_count())
+        };
+        let storage = Arc::clone(&self.storage);
+        let path = self.config.snapshot_path.clone();
+        let in_progress = Arc::clone(&self.bgsave_in_progress);
+        std::thread::spawn(move || {
+            match data.save(&path) {
+                Ok(()) => {
+                    storage.lock().unwrap().mark_saved_since(dirty);
+                    tracing::info!(path = %path, "background saving terminated with success");
+                }
+                Err(e) => tracing::error!(error = %e, "background save failed"),
+            }
+            in_progress.store(false, Ordering::SeqCst);
+        });
+        true
+    }
+
+    /// Waits for a running background save to finish, e.g. before the final save on shutdown
+    pub(crate) fn wait_for_background_save(&self) {
+        while self.bgsave_in_progress.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn bgsave(&self) -> String {
+        match self.start_background_save() {
+            true => "Background saving started".to_string(),
+            false => "ERR Background save already in progress".to_string(),
+        }
+    }
+
+    /// Rewrites the append-only file at the configured path on a background thread
+    ///
+    /// Only taking a copy of the data happens under the storage lock; the
+    /// copy shares its maps with the storage, so this is quick.
+    fn bgrewriteaof(&self) -> String {
+        if self.aof_rewrite_in_progress.swap(true, Ordering::SeqCst) {
+            return "ERR Background append only file rewriting already in progress".to_string();
+        }
+        let data = self.storage.lock().unwrap().frozen();
+        let path = self.config.appendfilename.clone();
+        let in_progress = Arc::clone(&self.aof_rewrite_in_progress);
+        std::thread::spawn(move || {
+            match aof::write_frozen(&data, &path) {
+                Ok(()) => tracing::info!(path = %path, "background AOF rewrite terminated with success"),
+                Err(e) => tracing::error!(error = %e, "background AOF rewrite failed"),
+            }
+            in_progress.store(false, Ordering::SeqCst);
+        });
+        "Background append only file rewriting started".to_string()
+    }
+
+    /// Returns how many clients are waiting in BLPOP/BRPOP
+    pub fn blocked_clients(&self) -> usize {
+        self.blocked.blocked_clients()
+    }
+
+    /// Wakes every client blocked in BLPOP/BRPOP with a nil reply, for shutdown
+    ///
+    /// Later blocking pops return immediately instead of parking.
+    pub fn release_blocked_clients(&self) {
+        self.blocked.close();
+    }
+
+    fn record_stat(&self, command: &Command, started: Instant) {
+        if let Some(stat) = self.commandstats.get(command.name()) {
+            stat.calls.fetch_add(1, Ordering::Relaxed);
+            stat.usec_total.fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+        }
     }
 
     /// Executes a single command and returns the result as a string
@@ -47,54 +497,1133 @@ impl CommandExecutor {
     /// * LPUSH/RPUSH - Returns the new length of the list
     /// * LPOP/RPOP - Returns the popped value or "(nil)" if list is empty
     /// * LLEN - Returns the length of the list
+    /// * LPOS - Returns the position of the matching element, or "(nil)";
+    ///   with COUNT, every position found one per line
+    /// * BLPOP/BRPOP - Returns the key and the popped value, or "(nil)" on timeout
+    /// * BLMOVE/BRPOPLPUSH - Returns the moved element, or "(nil)" on timeout
+    /// * SADD - Returns how many members were added
+    /// * SMEMBERS - Returns the members one per line, sorted
+    /// * SMOVE - Returns "1" if the member was moved, "0" if it wasn't in the source set
+    /// * SDIFFSTORE/SUNIONSTORE/SINTERSTORE - Returns the size of the stored set
+    /// * SINTERCARD - Returns the size of the intersection, capped at LIMIT
+    /// * SSCAN/HSCAN/ZSCAN - Returns the cursor to continue from ("0" once
+    ///   done), then the batch: members, fields each followed by its value,
+    ///   or members each followed by its score
+    /// * HSET - Returns how many fields were created; updated fields don't count
+    /// * HSETNX - Returns "1" if the field was set, "0" if it already existed
+    /// * HMSET - Returns "OK" once every field is set
+    /// * HINCRBY/HINCRBYFLOAT - Returns the field's new value
+    /// * ZADD - Returns how many members were added (or changed, with CH);
+    ///   with INCR, the member's new score or "(nil)" if the update was ruled out
+    /// * ZINCRBY - Returns the member's new score; a missing member starts at 0
+    /// * ZSCORE - Returns the member's score, or "(nil)" if it isn't in the set
+    /// * GEOADD - Returns how many members were added (or moved, with CH);
+    ///   coordinates out of range fail the whole command
+    /// * GEOSEARCH - Returns the matching members; with WITH* options, a RESP
+    ///   array with an array per match of the member followed by what they
+    ///   ask for: the distance, the geohash, then the longitude and latitude
+    ///   as an array of their own; with STORE or STOREDIST, how many members
+    ///   were stored
+    /// * ZCARD - Returns the number of members
+    /// * ZPOPMIN/ZPOPMAX - Returns the popped members each followed by its score
+    /// * BZPOPMIN/BZPOPMAX - Returns the key, member and score, or "(nil)" on timeout
+    /// * ZDIFF/ZUNION/ZINTER - Returns the resulting members, lowest score first,
+    ///   each followed by its score with WITHSCORES
+    /// * ZRANGEBYLEX/ZREVRANGEBYLEX - Returns the members in the range one per line
+    /// * ZLEXCOUNT - Returns how many members are in the range
+    /// * ZRANGEBYSCORE/ZREVRANGEBYSCORE - Returns the members in the range one
+    ///   per line, each followed by its score with WITHSCORES
+    /// * EXISTS - Returns "1" if the key exists, "0" otherwise
+    /// * TYPE - Returns "string", "list", "set", "zset" or "none"
+    /// * EXPIRE - Returns "1" if the timeout was set, "0" if the key doesn't
+    ///   exist or the NX/XX/GT/LT condition wasn't met
+    /// * TTL - Returns the remaining seconds, "-1" without a timeout, "-2" if missing
+    /// * PERSIST - Returns "1" if the timeout was removed, "0" if the key had none or doesn't exist
+    /// * COPY - Returns "1" if the value and its timeout were copied, "0" if
+    ///   the source is missing or the destination exists without REPLACE;
+    ///   the only database is 0
+    /// * ECHO - Returns the message unchanged
+    /// * PING - Returns "PONG", or the message if one was given
+    /// * OBJECT ENCODING - Returns the value's internal encoding, or "(nil)" if missing
+    /// * OBJECT FREQ - Returns the key's LFU access counter, or "(nil)" if missing
+    /// * OBJECT HELP - Returns one line per OBJECT subcommand
+    /// * MEMORY USAGE - Returns the estimated bytes the key takes, sampling
+    ///   `MEMORY_USAGE_SAMPLES` elements of a collection by default, or "(nil)" if missing
+    /// * INFO - Returns the clients, memory, persistence, stats and
+    ///   replication sections; INFO all adds commandstats, audit and maintenance
+    /// * INFO clients - Returns how many clients are connected
+    /// * INFO memory - Returns the estimated memory in use, the limit and the eviction policy
+    /// * INFO persistence - Returns the writes since the last snapshot, when
+    ///   that was, and whether a background save or AOF rewrite is running
+    /// * INFO stats - Returns how many clients were cut off by output buffer
+    ///   limits, the connection, command and traffic totals, the recent
+    ///   ops/sec, and how many full and partial resyncs replicas asked for
+    /// * INFO replication - Returns the role, the master's link status and
+    ///   applied offset on a replica, the attached replicas, the replication
+    ///   id and offset, and the backlog kept for partial resyncs
+    /// * INFO commandstats - Returns per-command call counts and timings
+    /// * INFO audit - Returns whether the audit log is on and how many lines it dropped
+    /// * INFO maintenance - Returns how often each background task ran and how long it took
+    /// * CONFIG GET - Returns name and value, one per line, of each matching parameter
+    /// * CONFIG SET - Returns "OK" once the new value is in effect, or an
+    ///   error for parameters that are unknown or fixed until a restart
+    /// * CONFIG RESETSTAT - Returns "OK" after zeroing the command statistics and server totals
+    /// * CONFIG REWRITE - Returns "OK" after writing the running config,
+    ///   with the values CONFIG SET changed, back to its file
+    /// * CLUSTER MEET/FORGET/NODES - Returns an error; the server does not run in cluster mode
+    /// * CLUSTER GETKEYSINSLOT - Returns up to count keys in the slot, one per line;
+    ///   an error unless `cluster_enabled` is set
+    /// * BGSAVE - Returns "Background saving started" and saves on another thread
+    /// * BGREWRITEAOF - Returns "Background append only file rewriting started"
+    ///   and writes a minimal AOF on another thread
+    /// * FLUSHALL - Returns "OK" after removing every key
+    /// * DEBUG - Returns "OK", or the list encoding report for QUICKLIST;
+    ///   an error in release builds
+    /// * WAIT - Returns how many replicas acknowledged the latest write;
+    ///   in a transaction, how many had at that point, without waiting
+    /// * SORT - Returns the sorted elements one per line, or their count with STORE
+    /// * LOLWUT - Returns computer art followed by the Redis version
     /// * MULTI - Returns "OK" when transaction starts
     /// * EXEC - Returns all transaction results followed by "OK"
     /// * DISCARD - Returns "OK" if transaction was rolled back successfully
     pub fn execute_command(&self, command: Command) -> String {
+        self.execute(command, None)
+    }
+
+    /// Same as `execute_command`, with writes audited as coming from `client`
+    pub fn execute_command_as(&self, command: Command, client: &ClientInfo) -> String {
+        self.execute(command, Some(client))
+    }
+
+    fn execute(&self, command: Command, client: Option<&ClientInfo>) -> String {
+        let started = Instant::now();
+        let response = match command {
+            Command::Info(ref section) => match section.as_deref() {
+                // Like Redis, the default leaves out commandstats
+                None | Some("default") => format!(
+                    "{}\n{}\n{}\n{}\n{}",
+                    self.clients_info(),
+                    self.memory_info(),
+                    self.persistence_info(),
+                    self.stats_info(),
+                    self.replication_info()
+                ),
+                Some("commandstats") => self.commandstats(),
+                Some("all") | Some("everything") => format!(
+                    "{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
+                    self.clients_info(),
+                    self.memory_info(),
+                    self.persistence_info(),
+                    self.stats_info(),
+                    self.replication_info(),
+                    self.commandstats(),
+                    self.audit_info(),
+                    self.maintenance.info()
+                ),
+                Some("clients") => self.clients_info(),
+                Some("memory") => self.memory_info(),
+                Some("persistence") => self.persistence_info(),
+                Some("stats") => self.stats_info(),
+                Some("replication") => self.replication_info(),
+                Some("audit") => self.audit_info(),
+                Some("maintenance") => self.maintenance.info(),
+                Some(_) => String::new(),
+            },
+            Command::ConfigResetStat => {
+                self.reset_stats();
+                "OK".to_string()
+            },
+            Command::ConfigRewrite => match &self.config.config_file {
+                None => "ERR The server is running without a config file".to_string(),
+                Some(path) => {
+                    let mut live = Config::clone(&self.config);
+                    self.runtime.apply_to(&mut live);
+                    match live.save_to_file_atomic(path) {
+                        Ok(()) => "OK".to_string(),
+                        Err(e) => format!("ERR Rewriting config file: {}", e),
+                    }
+                },
+            },
+            Command::ConfigGet(ref pattern) => {
+                let parameters = self.runtime.get(&self.config, pattern);
+                if parameters.is_empty() {
+                    "(empty list or set)".to_string()
+                } else {
+                    parameters.into_iter().flat_map(|(name, value)| [name, value]).collect::<Vec<_>>().join("\n")
+                }
+            },
+            Command::ConfigSet(ref name, ref value) => match self.runtime.set(&self.config, name, value) {
+                Ok(()) => {
+                    {
+                        // The storage applies these itself, on its next write
+                        let mut storage = self.storage.lock().unwrap();
+                        storage.set_encoding_limits(self.runtime.encoding_limits());
+                        storage.set_notify_flags(self.runtime.notify_keyspace_events());
+                    }
+                    tracing::info!(parameter = %name, value = %value, "configuration changed");
+                    "OK".to_string()
+                },
+                Err(e) => e,
+            },
+            Command::BgSave => self.bgsave(),
+            Command::BgRewriteAof => self.bgrewriteaof(),
+            Command::Debug(subcommand) => self.debug(subcommand),
+            // Blocks without holding the storage lock; a timeout of 0 waits forever
+            Command::Wait(num_replicas, timeout_ms) => {
+                let timeout = (timeout_ms > 0).then(|| std::time::Duration::from_millis(timeout_ms));
+                self.replication.wait(num_replicas as usize, timeout).to_string()
+            },
+            Command::BLPop(_, timeout)
+            | Command::BRPop(_, timeout)
+            | Command::BLMove(.., timeout)
+            | Command::BZPopMin(_, timeout)
+            | Command::BZPopMax(_, timeout) if BlockedPops::timeout(timeout).is_err() => {
+                "ERR timeout is out of range".to_string()
+            },
+            // Parks with the storage lock released until a push or the timeout
+            Command::BLPop(ref keys, timeout) | Command::BRPop(ref keys, timeout) => {
+                let timeout = BlockedPops::timeout(timeout).unwrap_or_default();
+                let from_left = matches!(command, Command::BLPop(..));
+                let pop = if from_left { "LPOP" } else { "RPOP" };
+                let popped = self.blocked.wait_for(&self.storage, keys, timeout, |storage, key| {
+                    let popped = BlockedPops::pop_key(storage, key, from_left)?;
+                    self.propagate_args(&[pop.to_string(), key.to_string()]);
+                    Some(popped)
+                });
+                match popped {
+                    Some((_, (key, value))) => format!("{}\n{}", key, value),
+                    None => "(nil)".to_string(),
+                }
+            },
+            // The push onto the destination happens in the same critical section as the pop
+            Command::BLMove(ref source, ref destination, from, to, timeout) => {
+                let timeout = BlockedPops::timeout(timeout).unwrap_or_default();
+                let moved = self.blocked.wait_for(&self.storage, std::slice::from_ref(source), timeout, |storage, source| {
+                    let moved = Self::lmove(storage, source, destination, from, to).transpose()?;
+                    // Replicas replay the move as the pop and push it amounted to
+                    if let Ok(value) = &moved {
+                        let pop = if from == ListSide::Left { "LPOP" } else { "RPOP" };
+                        let push = if to == ListSide::Left { "LPUSH" } else { "RPUSH" };
+                        self.propagate_args(&[pop.to_string(), source.to_string()]);
+                        self.propagate_args(&[push.to_string(), destination.clone(), value.clone()]);
+                    }
+                    Some(moved)
+                });
+                match moved {
+                    Some((_, Ok(value))) => value,
+                    Some((_, Err(e))) => e,
+                    None => "(nil)".to_string(),
+                }
+            },
+            // Parks like BLPOP, woken by ZADD instead of a push
+            Command::BZPopMin(ref keys, timeout) | Command::BZPopMax(ref keys, timeout) => {
+                let timeout = BlockedPops::timeout(timeout).unwrap_or_default();
+                let min = matches!(command, Command::BZPopMin(..));
+                let pop = if min { "ZPOPMIN" } else { "ZPOPMAX" };
+                let popped = self.blocked.wait_for(&self.storage, keys, timeout, |storage, key| {
+                    let popped = Self::zpop_one(storage, key, min)?;
+                    self.propagate_args(&[pop.to_string(), key.to_string()]);
+                    Some(popped)
+                });
+                match popped {
+                    Some((key, (member, score))) => format!("{}\n{}\n{}", key, member, score),
+                    None => "(nil)".to_string(),
+                }
+            },
+            // Replies at once if a stream already has new entries, else parks until an XADD
+            Command::XRead { ref streams, count, block: Some(block_ms) } => {
+                let timeout = (block_ms > 0).then(|| Duration::from_millis(block_ms));
+                // `$` is pinned before parking, so whatever is added meanwhile counts as new
+                let (streams, response) = {
+                    let mut storage = self.storage.lock().unwrap();
+                    let streams = Self::pin_stream_ids(&mut storage, streams);
+                    let response = Self::xread(&mut storage, &streams, count);
+                    (streams, response)
+                };
+                if response == "(nil)" {
+                    let keys: Vec<String> = streams.iter().map(|(key, _)| key.clone()).collect();
+                    let read = self.blocked.wait_for(&self.storage, &keys, timeout, |storage, key| {
+                        let (_, after) = streams.iter().find(|(watched, _)| watched == key)?;
+                        storage.expire_if_needed(key);
+                        match storage.xread(key, *after, count) {
+                            Ok(entries) if entries.is_empty() => None,
+                            read => Some(read),
+                        }
+                    });
+                    match read {
+                        Some((key, Ok(entries))) => RespValue::Array(vec![Self::format_stream(key, &entries)]).encode(),
+                        Some((_, Err(e))) => e,
+                        None => "(nil)".to_string(),
+                    }
+                } else {
+                    response
+                }
+            },
+            _ => {
+                let mut storage = self.storage.lock().unwrap();
+                match self.make_room(&mut storage, &command) {
+                    Err(e) => e,
+                    Ok(()) => {
+                        let response = Self::dispatch(&mut storage, &command);
+                        self.propagate(&command, &response);
+                        response
+                    },
+                }
+            },
+        };
+        if Self::may_push(&command) {
+            self.blocked.notify_pushed();
+        }
+        self.record_stat(&command, started);
+        self.audit(client, &command, &response);
+        response
+    }
+    
+    /// Executes a batch of commands as part of a transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `commands` - A slice of commands to execute in order
+    ///
+    /// # Returns
+    ///
+    /// A vector of strings containing the results of each command
+    ///
+    /// # Transaction Behavior
+    ///
+    /// * All commands in the transaction are executed atomically
+    /// * A command that fails replies with its error; the rest still run, as in Redis
+    /// * Results are collected and returned in the order of execution
+    pub fn execute_transaction(&self, commands: &[Command]) -> Vec<String> {
+        self.transaction(commands, None)
+    }
+
+    /// Same as `execute_transaction`, with writes audited as coming from `client`
+    pub fn execute_transaction_as(&self, commands: &[Command], client: &ClientInfo) -> Vec<String> {
+        self.transaction(commands, Some(client))
+    }
+
+    fn transaction(&self, commands: &[Command], client: Option<&ClientInfo>) -> Vec<String> {
+        let mut results = Vec::new();
         let mut storage = self.storage.lock().unwrap();
+        
+        for command in commands {
+            let started = Instant::now();
+            let result = match command {
+                // Never blocks inside a transaction, as in Redis: it counts
+                // the replicas that have acknowledged the writes so far
+                Command::Wait(..) => self.replication.acked_count(self.replication.offset()).to_string(),
+                _ => match self.make_room(&mut storage, command) {
+                    Err(e) => e,
+                    Ok(()) => Self::dispatch(&mut storage, command),
+                },
+            };
+            self.propagate(command, &result);
+            self.record_stat(command, started);
+            self.audit(client, command, &result);
+            results.push(result);
+        }
+        drop(storage);
+        if commands.iter().any(Self::may_push) {
+            self.blocked.notify_pushed();
+        }
+        
+        results
+    }
+
+    /// Evicts keys before a write that may need memory, once memory use is
+    /// over `max_memory`
+    ///
+    /// Evicted keys are deleted on replicas too.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - The command may run
+    /// * `Err(String)` - The OOM error, if memory is still over the limit
+    fn make_room(&self, storage: &mut MemoryStorage, command: &Command) -> Result<(), String> {
+        let max_memory = self.runtime.max_memory();
+        if max_memory == 0 || !Self::may_grow(command) {
+            return Ok(());
+        }
+        let mut evicted = Vec::new();
+        let fits = storage.evict(max_memory, self.runtime.maxmemory_policy(), &mut evicted);
+        for key in &evicted {
+            self.propagate_args(&["DEL".to_string(), key.clone()]);
+        }
+        self.stats.record_evictions(evicted.len());
+        if fits {
+            Ok(())
+        } else {
+            Err(OOM_ERROR.to_string())
+        }
+    }
+
+    /// Returns whether `command` is a write that may take more memory
+    ///
+    /// Deletions, pops and expirations only free memory, so they still run
+    /// when memory is full, as in Redis.
+    fn may_grow(command: &Command) -> bool {
+        command.is_write()
+            && !matches!(
+                command,
+                Command::Del(_)
+                    | Command::LPop(_)
+                    | Command::RPop(_)
+                    | Command::Expire(..)
+                    | Command::ExpireOpts(..)
+                    | Command::Persist(_)
+                    | Command::ZPopMin(..)
+                    | Command::ZPopMax(..)
+                    | Command::FlushAll
+                    | Command::Debug(_)
+            )
+    }
+
+    /// Returns whether `command` can add elements to a key a client is blocked on
+    fn may_push(command: &Command) -> bool {
+        matches!(
+            command,
+            Command::LPush(..)
+                | Command::RPush(..)
+                | Command::BLMove(..)
+                | Command::ZAdd(..)
+                | Command::ZIncrBy(..)
+                | Command::GeoAdd { .. }
+                | Command::XAdd(..)
+                | Command::GeoSearch { store: Some(_), .. }
+                | Command::Sort { store: Some(_), .. }
+                | Command::Exec
+        )
+    }
+
+    /// Moves one element between lists, reaping both keys first if they've expired
+    fn lmove(
+        storage: &mut MemoryStorage,
+        source: &str,
+        destination: &str,
+        from: ListSide,
+        to: ListSide,
+    ) -> Result<Option<String>, String> {
+        storage.expire_if_needed(source);
+        storage.expire_if_needed(destination);
+        storage.lmove(source, destination, from == ListSide::Left, to == ListSide::Left)
+    }
+
+    /// Pops the lowest (or highest) scored member, reaping the key first if it has expired
+    fn zpop_one(storage: &mut MemoryStorage, key: &str, min: bool) -> Option<(String, f64)> {
+        storage.expire_if_needed(key);
+        if min { storage.zpopmin(key, 1) } else { storage.zpopmax(key, 1) }.pop()
+    }
+
+    /// Formats sorted set members one per line, each followed by its score if asked
+    fn format_scored(members: Vec<(String, f64)>, withscores: bool) -> String {
+        if members.is_empty() {
+            return "(empty list or set)".to_string();
+        }
+        let mut lines = Vec::with_capacity(if withscores { members.len() * 2 } else { members.len() });
+        for (member, score) in members {
+            lines.push(member);
+            if withscores {
+                lines.push(score.to_string());
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// Formats a *SCAN reply: the next cursor, then the batch one item per line
+    fn format_scan(next_cursor: u64, items: impl IntoIterator<Item = String>) -> String {
+        let mut lines = vec![next_cursor.to_string()];
+        lines.extend(items);
+        lines.join("\n")
+    }
+
+    /// Formats one GEOSEARCH match for a reply with WITH* options
+    ///
+    /// Redis's fixed order: the member, the distance to 4 decimals, the
+    /// geohash as an integer, then the longitude and latitude to 17 decimals
+    /// as an array of their own, each only if asked for.
+    fn format_geo_match(found: &GeoMatch, params: &GeoSearchParams) -> RespValue {
+        let mut items = vec![RespValue::Bulk(found.member.clone())];
+        if params.with_dist {
+            items.push(RespValue::Bulk(format!("{:.4}", found.distance)));
+        }
+        if params.with_hash {
+            items.push(RespValue::Integer(found.hash as i64));
+        }
+        if params.with_coord {
+            items.push(RespValue::Array(vec![
+                RespValue::Bulk(format!("{:.17}", found.longitude)),
+                RespValue::Bulk(format!("{:.17}", found.latitude)),
+            ]));
+        }
+        RespValue::Array(items)
+    }
+
+    /// A stream entry as the stream commands nest it: its ID, then its fields and values
+    fn format_entry((id, fields): &StreamEntry) -> RespValue {
+        let fields = fields
+            .iter()
+            .flat_map(|(field, value)| [RespValue::Bulk(field.clone()), RespValue::Bulk(value.clone())])
+            .collect();
+        RespValue::Array(vec![RespValue::Bulk(id.to_string()), RespValue::Array(fields)])
+    }
+
+    fn format_entries(entries: &[StreamEntry]) -> RespValue {
+        RespValue::Array(entries.iter().map(Self::format_entry).collect())
+    }
+
+    /// One stream of an XREAD or XREADGROUP reply: its key, then its entries
+    fn format_stream(key: &str, entries: &[StreamEntry]) -> RespValue {
+        RespValue::Array(vec![RespValue::Bulk(key.to_string()), Self::format_entries(entries)])
+    }
+
+    /// Reads the entries after each stream's ID, for XREAD
+    ///
+    /// `$` must already be pinned to the stream's last ID. Streams with
+    /// nothing new are left out of the reply, and if none has anything
+    /// the reply is nil.
+    fn xread(storage: &mut MemoryStorage, streams: &[(String, StreamEntryId)], count: Option<usize>) -> String {
+        let mut replies = Vec::new();
+        for (key, after) in streams {
+            storage.expire_if_needed(key);
+            match storage.xread(key, *after, count) {
+                Err(e) => return e,
+                Ok(entries) if entries.is_empty() => {}
+                Ok(entries) => replies.push(Self::format_stream(key, &entries)),
+            }
+        }
+        if replies.is_empty() {
+            "(nil)".to_string()
+        } else {
+            RespValue::Array(replies).encode()
+        }
+    }
+
+    /// Resolves XREAD's `$` IDs to each stream's last ID as of now
+    fn pin_stream_ids(storage: &mut MemoryStorage, streams: &[(String, Option<StreamEntryId>)]) -> Vec<(String, StreamEntryId)> {
+        streams
+            .iter()
+            .map(|(key, id)| {
+                storage.expire_if_needed(key);
+                (key.clone(), id.unwrap_or_else(|| storage.xlast_id(key)))
+            })
+            .collect()
+    }
+
+    /// Builds XINFO's reply: name/value pairs, as Redis lays them out
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(RespValue)` - The reply
+    /// * `Err(String)` - NOGROUP if XINFO CONSUMERS names a group the stream doesn't have
+    fn format_xinfo(key: &str, stream: &Stream, subcommand: &XInfoSubcommand) -> Result<RespValue, String> {
+        let now = Instant::now();
+        let now_unix = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64;
+        let bulk = |value: &str| RespValue::Bulk(value.to_string());
+        let id = |id: StreamEntryId| RespValue::Bulk(id.to_string());
+        let count = |count: Option<u64>| count.map_or(RespValue::Nil, |count| RespValue::Integer(count as i64));
+        let millis_since = |then: Instant| now.duration_since(then).as_millis() as i64;
+        let radix_tree_keys = stream.len().div_ceil(STREAM_NODE_MAX_ENTRIES);
+        let header = [
+            (bulk("length"), RespValue::Integer(stream.len() as i64)),
+            (bulk("radix-tree-keys"), RespValue::Integer(radix_tree_keys as i64)),
+            (bulk("radix-tree-nodes"), RespValue::Integer(radix_tree_keys as i64 + 1)),
+            (bulk("last-generated-id"), id(stream.last_id)),
+            (bulk("max-deleted-entry-id"), id(stream.max_deleted_entry_id)),
+            (bulk("entries-added"), RespValue::Integer(stream.entries_added as i64)),
+            (bulk("recorded-first-entry-id"), id(stream.first_entry().map_or(StreamEntryId::MIN, |(first, _)| first))),
+        ];
+        let pairs: Vec<(RespValue, RespValue)> = match subcommand {
+            XInfoSubcommand::Stream => {
+                let entry = |entry: Option<StreamEntry>| entry.map_or(RespValue::Nil, |entry| Self::format_entry(&entry));
+                let mut pairs = header.to_vec();
+                pairs.push((bulk("groups"), RespValue::Integer(stream.groups.len() as i64)));
+                pairs.push((bulk("first-entry"), entry(stream.first_entry())));
+                pairs.push((bulk("last-entry"), entry(stream.last_entry())));
+                pairs
+            },
+            XInfoSubcommand::Groups => {
+                let groups = stream.groups.iter().map(|(name, group)| {
+                    RespValue::Array(vec![
+                        bulk("name"),
+                        bulk(name),
+                        bulk("consumers"),
+                        RespValue::Integer(group.consumers.len() as i64),
+                        bulk("pending"),
+                        RespValue::Integer(group.pel.len() as i64),
+                        bulk("last-delivered-id"),
+                        id(group.last_delivered_id),
+                        bulk("entries-read"),
+                        count(group.entries_read),
+                        bulk("lag"),
+                        count(stream.lag(group)),
+                    ])
+                });
+                return Ok(RespValue::Array(groups.collect()));
+            },
+            XInfoSubcommand::Consumers(group_name) => {
+                let Some(group) = stream.groups.get(group_name) else {
+                    return Err(format!("NOGROUP No such consumer group '{}' for key name '{}'", group_name, key));
+                };
+                let consumers = group.consumers.iter().map(|(name, consumer)| {
+                    RespValue::Array(vec![
+                        bulk("name"),
+                        bulk(name),
+                        bulk("pending"),
+                        RespValue::Integer(group.pending_for(name) as i64),
+                        bulk("idle"),
+                        RespValue::Integer(millis_since(consumer.seen_time)),
+                        bulk("inactive"),
+                        RespValue::Integer(consumer.active_time.map_or(-1, millis_since)),
+                    ])
+                });
+                return Ok(RespValue::Array(consumers.collect()));
+            },
+            XInfoSubcommand::Full(limit) => {
+                let limit = match limit.unwrap_or(XINFO_FULL_COUNT) {
+                    0 => usize::MAX,
+                    limit => limit,
+                };
+                let unix_at = |then: Instant| now_unix - millis_since(then);
+                let entries = stream.range(StreamEntryId::MIN, StreamEntryId::MAX, Some(limit));
+                let groups = stream.groups.iter().map(|(name, group)| {
+                    let pending = group.pel.iter().take(limit).map(|(pending_id, pending)| {
+                        RespValue::Array(vec![
+                            id(*pending_id),
+                            bulk(&pending.consumer),
+                            RespValue::Integer(unix_at(pending.delivered_at)),
+                            RespValue::Integer(pending.delivery_count as i64),
+                        ])
+                    });
+                    let consumers = group.consumers.iter().map(|(consumer_name, consumer)| {
+                        let own = group.pel.iter().filter(|(_, pending)| &pending.consumer == consumer_name);
+                        let own_pending = own.take(limit).map(|(pending_id, pending)| {
+                            RespValue::Array(vec![
+                                id(*pending_id),
+                                RespValue::Integer(unix_at(pending.delivered_at)),
+                                RespValue::Integer(pending.delivery_count as i64),
+                            ])
+                        });
+                        RespValue::Array(vec![
+                            bulk("name"),
+                            bulk(consumer_name),
+                            bulk("seen-time"),
+                            RespValue::Integer(unix_at(consumer.seen_time)),
+                            bulk("active-time"),
+                            RespValue::Integer(consumer.active_time.map_or(-1, unix_at)),
+                            bulk("pel-count"),
+                            RespValue::Integer(group.pending_for(consumer_name) as i64),
+                            bulk("pending"),
+                            RespValue::Array(own_pending.collect()),
+                        ])
+                    });
+                    RespValue::Array(vec![
+                        bulk("name"),
+                        bulk(name),
+                        bulk("last-delivered-id"),
+                        id(group.last_delivered_id),
+                        bulk("entries-read"),
+                        count(group.entries_read),
+                        bulk("lag"),
+                        count(stream.lag(group)),
+                        bulk("pel-count"),
+                        RespValue::Integer(group.pel.len() as i64),
+                        bulk("pending"),
+                        RespValue::Array(pending.collect()),
+                        bulk("consumers"),
+                        RespValue::Array(consumers.collect()),
+                    ])
+                });
+                let mut pairs = header.to_vec();
+                pairs.push((bulk("entries"), Self::format_entries(&entries)));
+                pairs.push((bulk("groups"), RespValue::Array(groups.collect())));
+                pairs
+            },
+        };
+        Ok(RespValue::Array(pairs.into_iter().flat_map(|(name, value)| [name, value]).collect()))
+    }
+
+    /// Returns whether running `command` bumps its key's LFU counter
+    ///
+    /// Commands that only inspect a key's metadata leave the counter alone,
+    /// as Redis's LOOKUP_NOTOUCH lookups do.
+    fn counts_as_access(command: &Command) -> bool {
+        !matches!(
+            command,
+            Command::ObjectEncoding(_)
+                | Command::ObjectFreq(_)
+                | Command::MemoryUsage(..)
+                | Command::Type(_)
+                | Command::Ttl(_)
+        )
+    }
+
+    /// Runs one command against the locked storage
+    ///
+    /// This is the single choke point every command passes through: the
+    /// command's key is reaped here if it has expired, so individual storage
+    /// operations never see an expired key, and the access is counted for
+    /// OBJECT FREQ.
+    fn dispatch(storage: &mut MemoryStorage, command: &Command) -> String {
+        if let Some(key) = command.key() {
+            storage.expire_if_needed(key);
+            if Self::counts_as_access(command) {
+                storage.lfu_touch(key);
+            }
+        }
+
         match command {
             Command::Set(key, value) => {
-                storage.set(key, value);
+                storage.set(key.to_string(), value.to_string());
                 "OK".to_string()
             },
             Command::Get(key) => {
-                match storage.get(&key) {
+                match storage.get(key) {
                     Some(value) => value.clone(),
                     None => "(nil)".to_string(),
                 }
             },
             Command::Del(key) => {
-                match storage.del(&key) {
+                match storage.del(key) {
                     true => "1".to_string(),
                     false => "0".to_string(),
                 }
             },
-            Command::Incr(key) => {
-                storage.incr(&key).to_string()
+            Command::Incr(key) => match storage.incr(key) {
+                Ok(value) => value.to_string(),
+                Err(e) => e,
             },
-            Command::Decr(key) => {
-                storage.decr(&key).to_string()
+            Command::Decr(key) => match storage.decr(key) {
+                Ok(value) => value.to_string(),
+                Err(e) => e,
             },
             Command::LPush(key, value) => {
-                storage.lpush(&key, value).to_string()
+                storage.lpush(key, value.to_string()).to_string()
             },
             Command::RPush(key, value) => {
-                storage.rpush(&key, value).to_string()
+                storage.rpush(key, value.to_string()).to_string()
             },
             Command::LPop(key) => {
-                match storage.lpop(&key) {
+                match storage.lpop(key) {
                     Some(value) => value,
                     None => "(nil)".to_string(),
                 }
             },
             Command::RPop(key) => {
-                match storage.rpop(&key) {
+                match storage.rpop(key) {
                     Some(value) => value,
                     None => "(nil)".to_string(),
                 }
             },
             Command::LLen(key) => {
-                storage.llen(&key).to_string()
+                storage.llen(key).to_string()
+            },
+            Command::LPos { key, element, rank, count, maxlen } => {
+                let positions = storage.lpos(key, element, *rank, count.unwrap_or(1), *maxlen);
+                match (count, positions.is_empty()) {
+                    (None, true) => "(nil)".to_string(),
+                    (Some(_), true) => "(empty list or set)".to_string(),
+                    _ => positions.iter().map(usize::to_string).collect::<Vec<_>>().join("\n"),
+                }
+            },
+            // Blocking would hold the lock forever here, so pop or reply nil at once
+            Command::BLPop(keys, _) | Command::BRPop(keys, _) => {
+                let from_left = matches!(command, Command::BLPop(..));
+                match BlockedPops::try_pop(storage, keys, from_left) {
+                    Some((key, value)) => format!("{}\n{}", key, value),
+                    None => "(nil)".to_string(),
+                }
+            },
+            Command::BLMove(source, destination, from, to, _) => {
+                match Self::lmove(storage, source, destination, *from, *to) {
+                    Ok(Some(value)) => value,
+                    Ok(None) => "(nil)".to_string(),
+                    Err(e) => e,
+                }
+            },
+            Command::SAdd(key, members) => match storage.sadd(key, members) {
+                Ok(added) => added.to_string(),
+                Err(e) => e,
+            },
+            Command::SMembers(key) => {
+                let members = storage.smembers(key);
+                if members.is_empty() {
+                    "(empty list or set)".to_string()
+                } else {
+                    members.join("\n")
+                }
+            },
+            Command::SMove(source, destination, member) => {
+                // Only the source is reaped above, as the command's key
+                storage.expire_if_needed(destination);
+                match storage.smove(source, destination, member) {
+                    Ok(true) => "1".to_string(),
+                    Ok(false) => "0".to_string(),
+                    Err(e) => e,
+                }
+            },
+            Command::SDiffStore(destination, keys)
+            | Command::SUnionStore(destination, keys)
+            | Command::SInterStore(destination, keys) => {
+                // Only the destination is reaped above, as the command's key
+                for key in keys {
+                    storage.expire_if_needed(key);
+                }
+                let stored = match command {
+                    Command::SDiffStore(..) => storage.sdiffstore(destination, keys),
+                    Command::SUnionStore(..) => storage.sunionstore(destination, keys),
+                    _ => storage.sinterstore(destination, keys),
+                };
+                match stored {
+                    Ok(count) => count.to_string(),
+                    Err(e) => e,
+                }
+            },
+            Command::SInterCard(_, keys, limit) => {
+                for key in keys {
+                    storage.expire_if_needed(key);
+                }
+                match storage.sintercard(keys, limit.unwrap_or(0)) {
+                    Ok(count) => count.to_string(),
+                    Err(e) => e,
+                }
+            },
+            Command::SScan(key, cursor, pattern, count) => {
+                let (next_cursor, members) = storage.sscan(key, *cursor, pattern.as_deref(), count.unwrap_or(DEFAULT_SCAN_COUNT));
+                Self::format_scan(next_cursor, members)
+            },
+            Command::HSet(key, pairs) => match storage.hset(key, pairs) {
+                Ok(added) => added.to_string(),
+                Err(e) => e,
+            },
+            Command::HSetNx(key, field, value) => match storage.hsetnx(key, field, value) {
+                Ok(true) => "1".to_string(),
+                Ok(false) => "0".to_string(),
+                Err(e) => e,
+            },
+            Command::HMSet(key, pairs) => match storage.hset(key, pairs) {
+                Ok(_) => "OK".to_string(),
+                Err(e) => e,
+            },
+            Command::HIncrBy(key, field, delta) => match storage.hincrby(key, field, *delta) {
+                Ok(value) => value.to_string(),
+                Err(e) => e,
+            },
+            Command::HIncrByFloat(key, field, delta) => match storage.hincrbyfloat(key, field, *delta) {
+                Ok(value) => value.to_string(),
+                Err(e) => e,
+            },
+            Command::HScan(key, cursor, pattern, count) => {
+                let (next_cursor, pairs) = storage.hscan(key, *cursor, pattern.as_deref(), count.unwrap_or(DEFAULT_SCAN_COUNT));
+                Self::format_scan(next_cursor, pairs.into_iter().flat_map(|(field, value)| [field, value]))
+            },
+            Command::ZAdd(key, options, pairs) if options.incr => {
+                let (increment, member) = &pairs[0];
+                match storage.zadd_incr(key, *increment, member, options) {
+                    Ok(Some(score)) => score.to_string(),
+                    Ok(None) => "(nil)".to_string(),
+                    Err(e) => e,
+                }
+            },
+            Command::ZIncrBy(key, increment, member) => {
+                match storage.zadd_incr(key, *increment, member, &ZAddOptions::default()) {
+                    Ok(score) => score.expect("ZINCRBY has no options to rule the update out").to_string(),
+                    Err(e) => e,
+                }
+            },
+            Command::ZAdd(key, options, pairs) => match storage.zadd(key, pairs, options) {
+                Ok(count) => count.to_string(),
+                Err(e) => e,
+            },
+            Command::GeoAdd { key, options, items } => match storage.geoadd(key, items, options) {
+                Ok(count) => count.to_string(),
+                Err(e) => e,
+            },
+            Command::GeoSearch { key, params, store: Some((mode, destination)) } => {
+                match storage.geosearchstore(key, destination, params, *mode) {
+                    Ok(count) => count.to_string(),
+                    Err(e) => e,
+                }
+            },
+            Command::GeoSearch { key, params, store: None } => match storage.geosearch(key, params) {
+                Err(e) => e,
+                Ok(matches) if matches.is_empty() => "(empty list or set)".to_string(),
+                Ok(matches) if params.with_details() => {
+                    RespValue::Array(matches.iter().map(|found| Self::format_geo_match(found, params)).collect()).encode()
+                },
+                Ok(matches) => matches.into_iter().map(|found| found.member).collect::<Vec<_>>().join("\n"),
+            },
+            Command::XAdd(key, id, fields) => match storage.xadd(key, *id, fields) {
+                Ok(id) => id.to_string(),
+                Err(e) => e,
+            },
+            Command::XLen(key) => storage.xlen(key).to_string(),
+            Command::XDel(key, ids) => storage.xdel(key, ids).to_string(),
+            Command::XRange(key, start, end, count) => {
+                let entries = storage.xrange(key, *start, *end, *count);
+                if entries.is_empty() {
+                    "(empty list or set)".to_string()
+                } else {
+                    Self::format_entries(&entries).encode()
+                }
+            },
+            Command::XGroupCreate(key, group, id, mkstream) => match storage.xgroup_create(key, group, *id, *mkstream) {
+                Ok(()) => "OK".to_string(),
+                Err(e) => e,
+            },
+            Command::XReadGroup { group, consumer, count, noack, streams } => {
+                let mut replies = Vec::new();
+                for (key, id) in streams {
+                    storage.expire_if_needed(key);
+                    match storage.xreadgroup(key, group, consumer, *id, *count, *noack) {
+                        Err(e) => return e,
+                        // Only rereading pending entries names a stream that had nothing
+                        Ok(entries) if entries.is_empty() && id.is_none() => {}
+                        Ok(entries) => replies.push(Self::format_stream(key, &entries)),
+                    }
+                }
+                if replies.is_empty() {
+                    "(nil)".to_string()
+                } else {
+                    RespValue::Array(replies).encode()
+                }
+            },
+            // BLOCK is ignored here, like BLPOP's timeout, so `$` never sees anything new
+            Command::XRead { count, streams, .. } => {
+                let streams = Self::pin_stream_ids(storage, streams);
+                Self::xread(storage, &streams, *count)
+            },
+            Command::XAutoClaim { key, group, consumer, min_idle_ms, start, count } => {
+                match storage.xautoclaim(key, group, consumer, *min_idle_ms, *start, *count) {
+                    Ok((cursor, claimed, deleted)) => RespValue::Array(vec![
+                        RespValue::Bulk(cursor),
+                        Self::format_entries(&claimed),
+                        RespValue::Array(deleted.into_iter().map(RespValue::Bulk).collect()),
+                    ])
+                    .encode(),
+                    Err(e) => e,
+                }
+            },
+            Command::XInfo(key, subcommand) => match storage.xinfo(key) {
+                Ok(stream) => match Self::format_xinfo(key, stream, subcommand) {
+                    Ok(info) => info.encode(),
+                    Err(e) => e,
+                },
+                Err(e) => e,
+            },
+            Command::ZScore(key, member) => match storage.zscore(key, member) {
+                Some(score) => score.to_string(),
+                None => "(nil)".to_string(),
+            },
+            Command::ZCard(key) => storage.zcard(key).to_string(),
+            Command::ZScan(key, cursor, pattern, count) => {
+                let (next_cursor, members) = storage.zscan(key, *cursor, pattern.as_deref(), count.unwrap_or(DEFAULT_SCAN_COUNT));
+                Self::format_scan(next_cursor, members.into_iter().flat_map(|(member, score)| [member, score.to_string()]))
+            },
+            Command::ZPopMin(key, count) => Self::format_scored(storage.zpopmin(key, count.unwrap_or(1)), true),
+            Command::ZPopMax(key, count) => Self::format_scored(storage.zpopmax(key, count.unwrap_or(1)), true),
+            Command::BZPopMin(keys, _) | Command::BZPopMax(keys, _) => {
+                let min = matches!(command, Command::BZPopMin(..));
+                match keys.iter().find_map(|key| Self::zpop_one(storage, key, min).map(|popped| (key, popped))) {
+                    Some((key, (member, score))) => format!("{}\n{}\n{}", key, member, score),
+                    None => "(nil)".to_string(),
+                }
+            },
+            Command::ZDiff(_, keys, withscores) => {
+                for key in keys {
+                    storage.expire_if_needed(key);
+                }
+                match storage.zdiff(keys) {
+                    Ok(members) => Self::format_scored(members, *withscores),
+                    Err(e) => e,
+                }
+            },
+            Command::ZUnion(_, keys, weights, agg, withscores) | Command::ZInter(_, keys, weights, agg, withscores) => {
+                for key in keys {
+                    storage.expire_if_needed(key);
+                }
+                let result = if matches!(command, Command::ZUnion(..)) {
+                    storage.zunion(keys, weights, *agg)
+                } else {
+                    storage.zinter(keys, weights, *agg)
+                };
+                match result {
+                    Ok(members) => Self::format_scored(members, *withscores),
+                    Err(e) => e,
+                }
+            },
+            Command::ZRangeByLex { key, min, max, limit, rev } => {
+                let (offset, count) = limit.unwrap_or((0, -1));
+                let members = storage.zrangebylex(key, min, max, offset, count, *rev);
+                if members.is_empty() {
+                    "(empty list or set)".to_string()
+                } else {
+                    members.join("\n")
+                }
+            },
+            Command::ZLexCount(key, min, max) => storage.zlexcount(key, min, max).to_string(),
+            Command::ZRangeByScore { key, min, max, withscores, limit } => {
+                let (offset, count) = limit.unwrap_or((0, -1));
+                Self::format_scored(storage.zrangebyscore(key, *min, *max, offset, count), *withscores)
+            },
+            Command::ZRevRangeByScore { key, max, min, withscores, limit } => {
+                let (offset, count) = limit.unwrap_or((0, -1));
+                Self::format_scored(storage.zrevrangebyscore(key, *max, *min, offset, count), *withscores)
+            },
+            Command::Exists(key) => {
+                match storage.exists(key) {
+                    true => "1".to_string(),
+                    false => "0".to_string(),
+                }
+            },
+            Command::Type(key) => {
+                storage.key_type(key).to_string()
+            },
+            Command::Expire(key, seconds) => {
+                match storage.expire_opts(key, *seconds, ExpireFlags::default()) {
+                    Ok(true) => "1".to_string(),
+                    Ok(false) => "0".to_string(),
+                    Err(e) => e,
+                }
+            },
+            Command::ExpireOpts(key, seconds, flags) => {
+                match storage.expire_opts(key, *seconds, *flags) {
+                    Ok(true) => "1".to_string(),
+                    Ok(false) => "0".to_string(),
+                    Err(e) => e,
+                }
+            },
+            Command::Ttl(key) => {
+                storage.ttl(key).to_string()
+            },
+            Command::Persist(key) => {
+                match storage.persist(key) {
+                    true => "1".to_string(),
+                    false => "0".to_string(),
+                }
+            },
+            Command::Copy(_, _, Some(db), _) if *db != 0 => "ERR DB index is out of range".to_string(),
+            Command::Copy(source, destination, _, replace) => {
+                match storage.copy(source, destination, *replace) {
+                    Ok(true) => "1".to_string(),
+                    Ok(false) => "0".to_string(),
+                    Err(e) => e,
+                }
+            },
+            Command::ObjectHelp => OBJECT_HELP.join("\n"),
+            Command::Echo(message) => message.clone(),
+            Command::Ping(message) => message.clone().unwrap_or_else(|| "PONG".to_string()),
+            Command::ObjectEncoding(key) => {
+                storage.object_encoding(key).unwrap_or("(nil)").to_string()
+            },
+            Command::MemoryUsage(key, samples) => {
+                match storage.memory_usage_for(key, samples.unwrap_or(MEMORY_USAGE_SAMPLES)) {
+                    Some(bytes) => bytes.to_string(),
+                    None => "(nil)".to_string(),
+                }
+            },
+            Command::ObjectFreq(key) => match storage.lfu_freq(key) {
+                Some(freq) => freq.to_string(),
+                None => "(nil)".to_string(),
+            },
+            Command::ClientId
+            | Command::ClientSetName(_)
+            | Command::ClientGetName
+            | Command::ClientList => {
+                "ERR CLIENT commands are handled by the connection".to_string()
+            },
+            Command::Sort { key, by, limit, get_patterns, order, alpha, store } => {
+                let sorted = storage.sort(
+                    key,
+                    by.as_deref(),
+                    *limit,
+                    get_patterns,
+                    *order == SortOrder::Desc,
+                    *alpha,
+                );
+                match (sorted, store) {
+                    (Err(e), _) => e,
+                    (Ok(values), Some(destination)) => {
+                        let count = values.len();
+                        let items = values.into_iter().map(Option::unwrap_or_default).collect();
+                        storage.set_list(destination, items);
+                        count.to_string()
+                    },
+                    (Ok(values), None) if values.is_empty() => "(empty list or set)".to_string(),
+                    (Ok(values), None) => values
+                        .into_iter()
+                        .map(|value| value.unwrap_or_else(|| "(nil)".to_string()))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                }
+            },
+            Command::Shutdown(_) => {
+                "ERR SHUTDOWN is handled by the connection".to_string()
+            },
+            Command::Quit => {
+                "ERR QUIT is handled by the connection".to_string()
+            },
+            Command::Reset => {
+                "ERR RESET is handled by the connection".to_string()
+            },
+            Command::FlushAll => {
+                storage.flushall();
+                "OK".to_string()
+            },
+            Command::Auth(..) | Command::AclWhoAmI | Command::AclList => {
+                "ERR AUTH and ACL are handled by the connection".to_string()
+            },
+            Command::Subscribe(_)
+            | Command::Unsubscribe(_)
+            | Command::PSubscribe(_)
+            | Command::PUnsubscribe(_)
+            | Command::Publish(..) => {
+                "ERR Pub/Sub commands are handled by the connection".to_string()
+            },
+            // The Raft cluster in `crate::cluster` is not attached to the server yet
+            Command::ClusterMeet(..) | Command::ClusterForget(_) | Command::ClusterNodes => {
+                "ERR This instance has cluster support disabled".to_string()
+            },
+            Command::ClusterGetKeysInSlot(..) if !storage.slot_index_enabled() => {
+                "ERR This instance has cluster support disabled".to_string()
+            },
+            Command::ClusterGetKeysInSlot(slot, _) if *slot >= CLUSTER_SLOTS => "ERR Invalid slot".to_string(),
+            Command::ClusterGetKeysInSlot(slot, count) => {
+                let keys = storage.cluster_getkeysinslot(*slot, *count);
+                if keys.is_empty() {
+                    "(empty list or set)".to_string()
+                } else {
+                    keys.join("\n")
+                }
+            },
+            Command::ClusterInfo
+            | Command::ClusterAddSlots(_)
+            | Command::ClusterAddSlotsRange(_)
+            | Command::ClusterDelSlots(_) => {
+                "ERR CLUSTER slot commands are handled by the connection".to_string()
+            },
+            // Served from the executor's statistics without the storage lock
+            Command::Info(_)
+            | Command::ConfigResetStat
+            | Command::ConfigRewrite
+            | Command::ConfigGet(_)
+            | Command::ConfigSet(..) => {
+                "ERR INFO and CONFIG cannot be used inside a transaction".to_string()
+            },
+            Command::Wait(..) => {
+                "ERR WAIT is answered by execute_command or execute_transaction".to_string()
+            },
+            Command::BgSave | Command::BgRewriteAof => {
+                "ERR BGSAVE and BGREWRITEAOF cannot be used inside a transaction".to_string()
+            },
+            Command::Debug(_) => {
+                "ERR DEBUG cannot be used inside a transaction".to_string()
+            },
+            Command::ReplicaOf(_) | Command::Sync | Command::PSync(..) | Command::ReplConfAck(_) => {
+                "ERR REPLICAOF, SYNC, PSYNC and REPLCONF are handled by the connection".to_string()
+            },
+            Command::Lolwut(version) => {
+                lolwut::lolwut(version.unwrap_or(lolwut::DEFAULT_VERSION), &[])
             },
             Command::Multi =>{
                 storage.start_transaction();
@@ -122,101 +1651,4 @@ impl CommandExecutor {
             Command::Unknown(cmd) => format!("ERR unknown command '{}'", cmd),
         }
     }
-    
-    /// Executes a batch of commands as part of a transaction
-    ///
-    /// # Arguments
-    ///
-    /// * `commands` - A slice of commands to execute in order
-    ///
-    /// # Returns
-    ///
-    /// A vector of strings containing the results of each command
-    ///
-    /// # Transaction Behavior
-    ///
-    /// * All commands in the transaction are executed atomically
-    /// * If any command fails, the entire transaction is rolled back
-    /// * Results are collected and returned in the order of execution
-    pub fn execute_transaction(&self, commands: &[Command]) -> Vec<String> {
-        let mut results = Vec::new();
-        let mut storage = self.storage.lock().unwrap();
-        
-        for command in commands {
-            let result = match command {
-                Command::Set(key, value) => {
-                    storage.set(key.to_string(), value.to_string());
-                    "OK".to_string()
-                },
-                Command::Get(key) => {
-                    match storage.get(&key) {
-                        Some(value) => value.clone(),
-                        None => "(nil)".to_string(),
-                    }
-                },
-                Command::Del(key) => {
-                    match storage.del(&key) {
-                        true => "1".to_string(),
-                        false => "0".to_string(),
-                    }
-                },
-                Command::Incr(key) => {
-                    storage.incr(&key).to_string()
-                },
-                Command::Decr(key) => {
-                    storage.decr(&key).to_string()
-                },
-                Command::LPush(key, value) => {
-                    storage.lpush(&key, value.to_string()).to_string()
-                },
-                Command::RPush(key, value) => {
-                    storage.rpush(&key, value.to_string()).to_string()
-                },
-                Command::LPop(key) => {
-                    match storage.lpop(&key) {
-                        Some(value) => value,
-                        None => "(nil)".to_string(),
-                    }
-                },
-                Command::RPop(key) => {
-                    match storage.rpop(&key) {
-                        Some(value) => value,
-                        None => "(nil)".to_string(),
-                    }
-                },
-                Command::LLen(key) => {
-                    storage.llen(&key).to_string()
-                },
-                Command::Multi =>{
-                    storage.start_transaction();
-                    "OK".to_string()
-                },
-                Command::Exec => {
-                    match storage.commit_transaction() {
-                        Ok(results) => {
-                            let mut response = String::new();
-                            for result in results {
-                                response.push_str(&format!("{}\n", result));
-                            }
-                            response.push_str("OK\n");
-                            response
-                        },
-                        Err(e) => format!("ERR: {}\n", e),
-                    }
-                },
-                Command::Discard => {
-                    match storage.rollback_transaction() {
-                        Ok(_) => "OK".to_string(),
-                        Err(e) => format!("ERR: {}", e),
-                    }
-                },
-                Command::Unknown(cmd) => format!("ERR unknown command '{}'", cmd),
-            
-            };
-            results.push(result);
-        }
-        
-        results
-    }
-    
-}
\ No newline at end of file
+}
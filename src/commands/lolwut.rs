@@ -0,0 +1,75 @@
+//! # LOLWUT Module
+//!
+//! Renders the computer art returned by the LOLWUT command. Each version of
+//! the command draws something different; version 5 draws a Dragon Curve.
+
+/// Version string appended to every LOLWUT reply
+const REDIS_VERSION_LINE: &str = "Redis Ver. 7.0.0";
+
+/// Version used when LOLWUT is called without VERSION
+pub const DEFAULT_VERSION: u64 = 6;
+
+/// Renders the LOLWUT output for `version`
+///
+/// # Arguments
+///
+/// * `version` - Which version's art to draw
+/// * `params` - Optional `[width, height, steps]` for version 5
+///
+/// # Returns
+///
+/// The art followed by the version line. Versions without art of their own
+/// return just the version line, like Redis does.
+pub fn lolwut(version: u64, params: &[u64]) -> String {
+    let art = match version {
+        5 => {
+            let width = params.first().copied().unwrap_or(66).clamp(1, 1000) as u32;
+            let height = params.get(1).copied().unwrap_or(33).clamp(1, 1000) as u32;
+            let steps = params.get(2).copied().unwrap_or(10).min(16);
+            dragon_curve(steps, width, height)
+        },
+        _ => String::new(),
+    };
+    format!("{}\n{}\n", art, REDIS_VERSION_LINE)
+}
+
+/// Draws the Dragon Curve after `steps` folds on a `width` x `height` grid
+///
+/// The curve has `2^steps` unit segments. Turn `k` (1-based) is a right turn
+/// when the bit above the lowest set bit of `k` is clear, and a left turn
+/// otherwise. The path is traced at double resolution so the midpoint of
+/// each segment is drawn too, then scaled to fit the grid.
+pub fn dragon_curve(steps: u64, width: u32, height: u32) -> String {
+    let (dx, dy) = ([2i64, 0, -2, 0], [0i64, 2, 0, -2]);
+    let mut direction = 0usize;
+    let (mut x, mut y) = (0i64, 0i64);
+    let mut points = vec![(x, y)];
+
+    for k in 1..=(1u64 << steps) {
+        points.push((x + dx[direction] / 2, y + dy[direction] / 2));
+        x += dx[direction];
+        y += dy[direction];
+        points.push((x, y));
+
+        let turn_left = (k & k.wrapping_neg()) << 1 & k != 0;
+        direction = if turn_left { (direction + 1) % 4 } else { (direction + 3) % 4 };
+    }
+
+    let (min_x, max_x) = points.iter().fold((i64::MAX, i64::MIN), |(lo, hi), p| (lo.min(p.0), hi.max(p.0)));
+    let (min_y, max_y) = points.iter().fold((i64::MAX, i64::MIN), |(lo, hi), p| (lo.min(p.1), hi.max(p.1)));
+    let (width, height) = (width as i64, height as i64);
+    let span_x = (max_x - min_x).max(1);
+    let span_y = (max_y - min_y).max(1);
+
+    let mut grid = vec![vec![' '; width as usize]; height as usize];
+    for (px, py) in points {
+        let column = (px - min_x) * (width - 1) / span_x;
+        let row = (py - min_y) * (height - 1) / span_y;
+        grid[row as usize][column as usize] = '#';
+    }
+
+    grid.into_iter()
+        .map(|row| row.into_iter().collect::<String>().trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
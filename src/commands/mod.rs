@@ -1,2 +1,5 @@
 pub mod parser;
-pub mod executor;
\ No newline at end of file
+pub mod executor;
+pub mod lolwut;
+pub mod blocking;
+pub mod audit;
\ No newline at end of file
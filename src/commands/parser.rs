@@ -4,6 +4,13 @@
 //! into structured command enums. Supports basic key-value operations, list operations,
 //! and transaction commands.
 
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use crate::storage::geo::{self, GeoAddOptions, GeoOrigin, GeoSearchParams, GeoShape, GeoStoreMode, GeoUnit};
+use crate::storage::memory::ExpireFlags;
+use crate::storage::stream::{StreamEntryId, XAddId, XAUTOCLAIM_COUNT};
+use crate::storage::zset::{AggType, LexBound, ScoreBound, ZAddOptions};
+
 /// Represents all supported Redis-like commands
 
 #[derive(Debug,PartialEq,Clone)]
@@ -18,12 +25,578 @@ pub enum Command {
     LPop(String),
     RPop(String),
     LLen(String),
+    LPos {
+        key: String,
+        element: String,
+        rank: i64,
+        count: Option<usize>,
+        maxlen: usize,
+    },
+    BLPop(Vec<String>, f64),
+    BRPop(Vec<String>, f64),
+    BLMove(String, String, ListSide, ListSide, f64),
+    SAdd(String, Vec<String>),
+    SMembers(String),
+    SMove(String, String, String),
+    SDiffStore(String, Vec<String>),
+    SUnionStore(String, Vec<String>),
+    SInterStore(String, Vec<String>),
+    SInterCard(usize, Vec<String>, Option<usize>),
+    SScan(String, u64, Option<String>, Option<usize>),
+    HSet(String, Vec<(String, String)>),
+    HSetNx(String, String, String),
+    HMSet(String, Vec<(String, String)>),
+    HIncrBy(String, String, i64),
+    HIncrByFloat(String, String, f64),
+    HScan(String, u64, Option<String>, Option<usize>),
+    ZAdd(String, ZAddOptions, Vec<(f64, String)>),
+    ZIncrBy(String, f64, String),
+    ZScore(String, String),
+    ZCard(String),
+    ZScan(String, u64, Option<String>, Option<usize>),
+    ZPopMin(String, Option<usize>),
+    ZPopMax(String, Option<usize>),
+    BZPopMin(Vec<String>, f64),
+    BZPopMax(Vec<String>, f64),
+    ZDiff(usize, Vec<String>, bool),
+    ZUnion(usize, Vec<String>, Vec<f64>, AggType, bool),
+    ZInter(usize, Vec<String>, Vec<f64>, AggType, bool),
+    ZRangeByScore {
+        key: String,
+        min: ScoreBound,
+        max: ScoreBound,
+        withscores: bool,
+        limit: Option<(i64, i64)>,
+    },
+    ZRevRangeByScore {
+        key: String,
+        max: ScoreBound,
+        min: ScoreBound,
+        withscores: bool,
+        limit: Option<(i64, i64)>,
+    },
+    ZRangeByLex {
+        key: String,
+        min: LexBound,
+        max: LexBound,
+        limit: Option<(i64, i64)>,
+        rev: bool,
+    },
+    ZLexCount(String, LexBound, LexBound),
+    GeoAdd {
+        key: String,
+        options: GeoAddOptions,
+        items: Vec<(f64, f64, String)>,
+    },
+    GeoSearch {
+        key: String,
+        params: GeoSearchParams,
+        store: Option<(GeoStoreMode, String)>,
+    },
+    XAdd(String, XAddId, Vec<(String, String)>),
+    XLen(String),
+    XDel(String, Vec<String>),
+    XRange(String, StreamEntryId, StreamEntryId, Option<usize>),
+    XGroupCreate(String, String, Option<StreamEntryId>, bool),
+    XReadGroup {
+        group: String,
+        consumer: String,
+        count: Option<usize>,
+        noack: bool,
+        streams: Vec<(String, Option<StreamEntryId>)>,
+    },
+    XInfo(String, XInfoSubcommand),
+    XAutoClaim {
+        key: String,
+        group: String,
+        consumer: String,
+        min_idle_ms: u64,
+        start: StreamEntryId,
+        count: usize,
+    },
+    XRead {
+        count: Option<usize>,
+        block: Option<u64>,
+        streams: Vec<(String, Option<StreamEntryId>)>,
+    },
+    Exists(String),
+    Type(String),
+    Expire(String, u64),
+    ExpireOpts(String, u64, ExpireFlags),
+    Ttl(String),
+    Persist(String),
+    Copy(String, String, Option<u64>, bool),
+    ObjectEncoding(String),
+    ObjectFreq(String),
+    ObjectHelp,
+    MemoryUsage(String, Option<usize>),
+    Echo(String),
+    Ping(Option<String>),
+    ClientId,
+    ClientSetName(String),
+    ClientGetName,
+    ClientList,
+    Info(Option<String>),
+    ConfigResetStat,
+    ConfigRewrite,
+    ConfigGet(String),
+    ConfigSet(String, String),
+    ClusterMeet(String, u16),
+    ClusterForget(String),
+    ClusterNodes,
+    ClusterInfo,
+    ClusterAddSlots(Vec<u16>),
+    ClusterAddSlotsRange(Vec<(u16, u16)>),
+    ClusterDelSlots(Vec<u16>),
+    ClusterGetKeysInSlot(u16, usize),
+    BgSave,
+    BgRewriteAof,
+    FlushAll,
+    Auth(Option<String>, String),
+    AclWhoAmI,
+    AclList,
+    Debug(DebugSubcommand),
+    Shutdown(ShutdownMode),
+    Quit,
+    Reset,
+    Wait(u64, u64),
+    ReplicaOf(Option<(String, u16)>),
+    Sync,
+    PSync(String, i64),
+    ReplConfAck(u64),
+    Sort {
+        key: String,
+        by: Option<String>,
+        limit: Option<(i64, i64)>,
+        get_patterns: Vec<String>,
+        order: SortOrder,
+        alpha: bool,
+        store: Option<String>,
+    },
+    Lolwut(Option<u64>),
+    Subscribe(Vec<String>),
+    Unsubscribe(Vec<String>),
+    PSubscribe(Vec<String>),
+    PUnsubscribe(Vec<String>),
+    Publish(String, String),
     Multi,
     Exec,
     Discard,
     Unknown(String),
 }
 
+impl Command {
+    /// Names of every command, as reported by `Command::name`
+    pub const NAMES: &'static [&'static str] = &[
+        "set", "get", "del", "incr", "decr", "lpush", "rpush", "lpop", "rpop", "llen", "lpos",
+        "blpop", "brpop", "blmove", "sadd", "smembers", "smove",
+        "sdiffstore", "sunionstore", "sinterstore", "sintercard", "sscan", "hset", "hsetnx", "hmset", "hincrby", "hincrbyfloat", "hscan",
+        "zadd", "zincrby", "zscore", "zcard", "zscan",
+        "zrangebyscore", "zrevrangebyscore", "zrangebylex", "zrevrangebylex", "zlexcount", "zpopmin", "zpopmax", "bzpopmin", "bzpopmax",
+        "zdiff", "zunion", "zinter", "geoadd", "geosearch", "xadd", "xlen", "xdel", "xrange", "xgroup", "xreadgroup", "xread", "xinfo", "xautoclaim",
+        "exists", "type", "expire", "ttl", "persist", "copy", "object", "memory", "echo", "ping", "client", "info", "config", "cluster", "bgsave", "bgrewriteaof",
+        "flushall", "auth", "acl", "debug", "shutdown", "quit", "reset",
+        "sort", "wait", "replicaof", "sync", "psync", "replconf", "lolwut",
+        "subscribe", "unsubscribe", "psubscribe", "punsubscribe", "publish", "multi", "exec", "discard", "unknown",
+    ];
+
+    /// Returns the lower-case command name, used for statistics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Set(..) => "set",
+            Command::Get(_) => "get",
+            Command::Del(_) => "del",
+            Command::Incr(_) => "incr",
+            Command::Decr(_) => "decr",
+            Command::LPush(..) => "lpush",
+            Command::RPush(..) => "rpush",
+            Command::LPop(_) => "lpop",
+            Command::RPop(_) => "rpop",
+            Command::LLen(_) => "llen",
+            Command::LPos { .. } => "lpos",
+            Command::BLPop(..) => "blpop",
+            Command::BRPop(..) => "brpop",
+            Command::BLMove(..) => "blmove",
+            Command::SAdd(..) => "sadd",
+            Command::SMembers(_) => "smembers",
+            Command::SMove(..) => "smove",
+            Command::SDiffStore(..) => "sdiffstore",
+            Command::SUnionStore(..) => "sunionstore",
+            Command::SInterStore(..) => "sinterstore",
+            Command::SInterCard(..) => "sintercard",
+            Command::SScan(..) => "sscan",
+            Command::HSet(..) => "hset",
+            Command::HSetNx(..) => "hsetnx",
+            Command::HMSet(..) => "hmset",
+            Command::HIncrBy(..) => "hincrby",
+            Command::HIncrByFloat(..) => "hincrbyfloat",
+            Command::HScan(..) => "hscan",
+            Command::ZAdd(..) => "zadd",
+            Command::ZIncrBy(..) => "zincrby",
+            Command::ZScore(..) => "zscore",
+            Command::ZCard(_) => "zcard",
+            Command::ZScan(..) => "zscan",
+            Command::ZPopMin(..) => "zpopmin",
+            Command::ZPopMax(..) => "zpopmax",
+            Command::BZPopMin(..) => "bzpopmin",
+            Command::BZPopMax(..) => "bzpopmax",
+            Command::ZDiff(..) => "zdiff",
+            Command::ZUnion(..) => "zunion",
+            Command::ZInter(..) => "zinter",
+            Command::ZRangeByScore { .. } => "zrangebyscore",
+            Command::ZRevRangeByScore { .. } => "zrevrangebyscore",
+            Command::ZRangeByLex { rev: false, .. } => "zrangebylex",
+            Command::ZRangeByLex { rev: true, .. } => "zrevrangebylex",
+            Command::ZLexCount(..) => "zlexcount",
+            Command::GeoAdd { .. } => "geoadd",
+            Command::GeoSearch { .. } => "geosearch",
+            Command::XAdd(..) => "xadd",
+            Command::XLen(_) => "xlen",
+            Command::XDel(..) => "xdel",
+            Command::XRange(..) => "xrange",
+            Command::XGroupCreate(..) => "xgroup",
+            Command::XReadGroup { .. } => "xreadgroup",
+            Command::XInfo(..) => "xinfo",
+            Command::XRead { .. } => "xread",
+            Command::XAutoClaim { .. } => "xautoclaim",
+            Command::Exists(_) => "exists",
+            Command::Type(_) => "type",
+            Command::Expire(..) | Command::ExpireOpts(..) => "expire",
+            Command::Ttl(_) => "ttl",
+            Command::Persist(_) => "persist",
+            Command::Copy(..) => "copy",
+            Command::ObjectEncoding(_) | Command::ObjectFreq(_) | Command::ObjectHelp => "object",
+            Command::MemoryUsage(..) => "memory",
+            Command::Echo(_) => "echo",
+            Command::Ping(_) => "ping",
+            Command::ClientId
+            | Command::ClientSetName(_)
+            | Command::ClientGetName
+            | Command::ClientList => "client",
+            Command::Info(_) => "info",
+            Command::ConfigResetStat | Command::ConfigRewrite | Command::ConfigGet(_) | Command::ConfigSet(..) => "config",
+            Command::ClusterMeet(..)
+            | Command::ClusterForget(_)
+            | Command::ClusterNodes
+            | Command::ClusterInfo
+            | Command::ClusterAddSlots(_)
+            | Command::ClusterAddSlotsRange(_)
+            | Command::ClusterDelSlots(_)
+            | Command::ClusterGetKeysInSlot(..) => "cluster",
+            Command::BgSave => "bgsave",
+            Command::BgRewriteAof => "bgrewriteaof",
+            Command::FlushAll => "flushall",
+            Command::Auth(..) => "auth",
+            Command::AclWhoAmI | Command::AclList => "acl",
+            Command::Debug(_) => "debug",
+            Command::Shutdown(_) => "shutdown",
+            Command::Quit => "quit",
+            Command::Reset => "reset",
+            Command::Sort { .. } => "sort",
+            Command::Wait(..) => "wait",
+            Command::ReplicaOf(_) => "replicaof",
+            Command::Sync => "sync",
+            Command::PSync(..) => "psync",
+            Command::ReplConfAck(_) => "replconf",
+            Command::Lolwut(_) => "lolwut",
+            Command::Subscribe(_) => "subscribe",
+            Command::Unsubscribe(_) => "unsubscribe",
+            Command::PSubscribe(_) => "psubscribe",
+            Command::PUnsubscribe(_) => "punsubscribe",
+            Command::Publish(..) => "publish",
+            Command::Multi => "multi",
+            Command::Exec => "exec",
+            Command::Discard => "discard",
+            Command::Unknown(_) => "unknown",
+        }
+    }
+
+    /// Returns the key this command reads or writes, if any
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            Command::Set(key, _)
+            | Command::Get(key)
+            | Command::Del(key)
+            | Command::Incr(key)
+            | Command::Decr(key)
+            | Command::LPush(key, _)
+            | Command::RPush(key, _)
+            | Command::LPop(key)
+            | Command::RPop(key)
+            | Command::LLen(key)
+            | Command::LPos { key, .. }
+            | Command::Exists(key)
+            | Command::Type(key)
+            | Command::Expire(key, _)
+            | Command::ExpireOpts(key, ..)
+            | Command::Ttl(key)
+            | Command::Persist(key)
+            | Command::Copy(key, ..)
+            | Command::ObjectEncoding(key)
+            | Command::ObjectFreq(key)
+            | Command::MemoryUsage(key, _)
+            | Command::SAdd(key, _)
+            | Command::SMembers(key)
+            | Command::SMove(key, ..)
+            | Command::SDiffStore(key, _)
+            | Command::SUnionStore(key, _)
+            | Command::SInterStore(key, _)
+            | Command::SScan(key, ..)
+            | Command::HSet(key, _)
+            | Command::HSetNx(key, ..)
+            | Command::HMSet(key, _)
+            | Command::HIncrBy(key, ..)
+            | Command::HIncrByFloat(key, ..)
+            | Command::HScan(key, ..)
+            | Command::ZAdd(key, ..)
+            | Command::ZIncrBy(key, ..)
+            | Command::ZScore(key, _)
+            | Command::ZCard(key)
+            | Command::ZScan(key, ..)
+            | Command::ZPopMin(key, _)
+            | Command::ZPopMax(key, _)
+            | Command::ZRangeByScore { key, .. }
+            | Command::ZRevRangeByScore { key, .. }
+            | Command::ZRangeByLex { key, .. }
+            | Command::ZLexCount(key, ..)
+            | Command::GeoAdd { key, .. }
+            | Command::GeoSearch { key, .. }
+            | Command::XAdd(key, ..)
+            | Command::XLen(key)
+            | Command::XDel(key, _)
+            | Command::XRange(key, ..)
+            | Command::XGroupCreate(key, ..)
+            | Command::XInfo(key, _)
+            | Command::XAutoClaim { key, .. }
+            | Command::BLMove(key, ..)
+            | Command::Sort { key, .. } => Some(key),
+            Command::BLPop(keys, _)
+            | Command::BRPop(keys, _)
+            | Command::SInterCard(_, keys, _)
+            | Command::BZPopMin(keys, _)
+            | Command::BZPopMax(keys, _)
+            | Command::ZDiff(_, keys, _)
+            | Command::ZUnion(_, keys, ..)
+            | Command::ZInter(_, keys, ..) => keys.first().map(String::as_str),
+            Command::XReadGroup { streams, .. } | Command::XRead { streams, .. } => {
+                streams.first().map(|(key, _)| key.as_str())
+            },
+            Command::ObjectHelp
+            | Command::Echo(_)
+            | Command::Ping(_)
+            | Command::ClientId
+            | Command::ClientSetName(_)
+            | Command::ClientGetName
+            | Command::ClientList
+            | Command::Info(_)
+            | Command::ConfigResetStat
+            | Command::ConfigRewrite
+            | Command::ConfigGet(_)
+            | Command::ConfigSet(..)
+            | Command::ClusterMeet(..)
+            | Command::ClusterForget(_)
+            | Command::ClusterNodes
+            | Command::ClusterInfo
+            | Command::ClusterAddSlots(_)
+            | Command::ClusterAddSlotsRange(_)
+            | Command::ClusterDelSlots(_)
+            | Command::ClusterGetKeysInSlot(..)
+            | Command::BgSave
+            | Command::BgRewriteAof
+            | Command::FlushAll
+            | Command::Auth(..)
+            | Command::AclWhoAmI
+            | Command::AclList
+            | Command::Debug(_)
+            | Command::Shutdown(_)
+            | Command::Quit
+            | Command::Reset
+            | Command::Wait(..)
+            | Command::ReplicaOf(_)
+            | Command::Sync
+            | Command::PSync(..)
+            | Command::ReplConfAck(_)
+            | Command::Lolwut(_)
+            | Command::Subscribe(_)
+            | Command::Unsubscribe(_)
+            | Command::PSubscribe(_)
+            | Command::PUnsubscribe(_)
+            | Command::Publish(..)
+            | Command::Multi
+            | Command::Exec
+            | Command::Discard
+            | Command::Unknown(_) => None,
+        }
+    }
+
+    /// Whether this command can modify the dataset
+    ///
+    /// Blocking pops count even when they time out, and SORT only with STORE.
+    pub fn is_write(&self) -> bool {
+        match self {
+            Command::Sort { store, .. } => store.is_some(),
+            Command::GeoSearch { store, .. } => store.is_some(),
+            _ => matches!(
+                self,
+                Command::Set(..)
+                    | Command::Del(_)
+                    | Command::Incr(_)
+                    | Command::Decr(_)
+                    | Command::LPush(..)
+                    | Command::RPush(..)
+                    | Command::LPop(_)
+                    | Command::RPop(_)
+                    | Command::BLPop(..)
+                    | Command::BRPop(..)
+                    | Command::BLMove(..)
+                    | Command::Expire(..)
+                    | Command::ExpireOpts(..)
+                    | Command::Persist(_)
+                    | Command::Copy(..)
+                    | Command::SAdd(..)
+                    | Command::SMove(..)
+                    | Command::SDiffStore(..)
+                    | Command::SUnionStore(..)
+                    | Command::SInterStore(..)
+                    | Command::HSet(..)
+                    | Command::HSetNx(..)
+                    | Command::HMSet(..)
+                    | Command::HIncrBy(..)
+                    | Command::HIncrByFloat(..)
+                    | Command::ZAdd(..)
+                    | Command::ZIncrBy(..)
+                    | Command::ZPopMin(..)
+                    | Command::ZPopMax(..)
+                    | Command::BZPopMin(..)
+                    | Command::BZPopMax(..)
+                    | Command::GeoAdd { .. }
+                    | Command::XAdd(..)
+                    | Command::XDel(..)
+                    | Command::XGroupCreate(..)
+                    | Command::XReadGroup { .. }
+                    | Command::XAutoClaim { .. }
+                    | Command::FlushAll
+                    | Command::Debug(DebugSubcommand::Flushall)
+            ),
+        }
+    }
+
+    /// ACL categories this command belongs to, without the leading `@`
+    ///
+    /// `connection` commands are always allowed, so an unauthenticated
+    /// client can still log in. Everything that is neither a write, an
+    /// administrative nor a Pub/Sub command counts as a read.
+    pub fn categories(&self) -> &'static [&'static str] {
+        match self {
+            Command::Auth(..)
+            | Command::AclWhoAmI
+            | Command::Quit
+            | Command::Reset
+            | Command::Ping(_)
+            | Command::Echo(_)
+            | Command::ClientId
+            | Command::ClientSetName(_)
+            | Command::ClientGetName
+            | Command::Multi
+            | Command::Exec
+            | Command::Discard => &["connection"],
+            Command::FlushAll => &["write", "admin", "dangerous"],
+            Command::ClientList
+            | Command::ConfigResetStat
+            | Command::ConfigRewrite
+            | Command::ConfigGet(_)
+            | Command::ConfigSet(..)
+            | Command::ClusterMeet(..)
+            | Command::ClusterForget(_)
+            | Command::ClusterNodes
+            | Command::ClusterAddSlots(_)
+            | Command::ClusterAddSlotsRange(_)
+            | Command::ClusterDelSlots(_)
+            | Command::BgSave
+            | Command::BgRewriteAof
+            | Command::Shutdown(_)
+            | Command::AclList
+            | Command::Debug(_)
+            | Command::ReplicaOf(_)
+            | Command::Sync
+            | Command::PSync(..)
+            | Command::ReplConfAck(_) => &["admin", "dangerous"],
+            Command::Subscribe(_)
+            | Command::Unsubscribe(_)
+            | Command::PSubscribe(_)
+            | Command::PUnsubscribe(_)
+            | Command::Publish(..) => &["pubsub"],
+            _ if self.is_write() => &["write"],
+            _ => &["read"],
+        }
+    }
+}
+
+/// DEBUG subcommands, available in debug builds only
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum DebugSubcommand {
+    /// Blocks the client for this many seconds
+    Sleep(f64),
+    /// Saves the snapshot and loads it back
+    Reload,
+    Flushall,
+    /// Allows or stops reaping expired keys in the background
+    SetActiveExpire(bool),
+    /// Longest list element, in bytes, a listpack-encoded list may hold
+    QuicklistPackedThreshold(usize),
+    /// Gives the replication stream a new id
+    ChangeReplId,
+    /// Reports list encoding limits and how many lists use each encoding
+    Quicklist,
+}
+
+/// What XINFO reports on
+#[derive(Debug,PartialEq,Clone)]
+pub enum XInfoSubcommand {
+    /// The stream itself, with its first and last entries
+    Stream,
+    /// The stream's consumer groups
+    Groups,
+    /// The consumers of the named group
+    Consumers(String),
+    /// The stream with up to this many entries and pending entries per
+    /// group; `None` for `XINFO_FULL_COUNT`, `Some(0)` for all of them
+    Full(Option<usize>),
+}
+
+/// Whether SHUTDOWN persists the dataset before exiting
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum ShutdownMode {
+    /// No argument: save, as snapshots are always configured
+    Default,
+    Save,
+    NoSave,
+}
+
+/// Direction of a SORT
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// End of a list, for the commands that move elements between lists
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum ListSide {
+    Left,
+    Right,
+}
+
+impl ListSide {
+    fn parse(side: &str) -> Option<Self> {
+        match side.to_uppercase().as_str() {
+            "LEFT" => Some(ListSide::Left),
+            "RIGHT" => Some(ListSide::Right),
+            _ => None,
+        }
+    }
+}
+
 /// Parser for Redis-like commands
 ///
 /// Converts string input into structured Command enums, handling command validation
@@ -53,13 +626,138 @@ impl CommandParser {
     /// * LPOP key
     /// * RPOP key
     /// * LLEN key
+    /// * LPOS key element [RANK rank] [COUNT count] [MAXLEN len]
+    /// * BLPOP key [key ...] timeout (seconds, fractions allowed; 0 waits forever)
+    /// * BRPOP key [key ...] timeout
+    /// * BLMOVE source destination LEFT|RIGHT LEFT|RIGHT timeout
+    /// * BRPOPLPUSH source destination timeout (BLMOVE ... RIGHT LEFT)
+    /// * SADD key member [member ...]
+    /// * SMEMBERS key
+    /// * SMOVE source destination member
+    /// * SDIFFSTORE destination key [key ...]
+    /// * SUNIONSTORE destination key [key ...]
+    /// * SINTERSTORE destination key [key ...]
+    /// * SINTERCARD numkeys key [key ...] [LIMIT limit]
+    /// * SSCAN key cursor [MATCH pattern] [COUNT count]
+    /// * HSET key field value [field value ...]
+    /// * HSETNX key field value
+    /// * HMSET key field value [field value ...]
+    /// * HINCRBY key field increment
+    /// * HINCRBYFLOAT key field increment
+    /// * HSCAN key cursor [MATCH pattern] [COUNT count]
+    /// * ZADD key [NX|XX] [GT|LT] [CH] [INCR] score member [score member ...]
+    /// * ZINCRBY key increment member
+    /// * ZSCORE key member
+    /// * ZCARD key
+    /// * ZSCAN key cursor [MATCH pattern] [COUNT count]
+    /// * ZPOPMIN key [count]
+    /// * ZPOPMAX key [count]
+    /// * BZPOPMIN key [key ...] timeout
+    /// * BZPOPMAX key [key ...] timeout
+    /// * ZDIFF numkeys key [key ...] [WITHSCORES]
+    /// * ZUNION numkeys key [key ...] [WEIGHTS weight ...] [AGGREGATE SUM|MIN|MAX] [WITHSCORES]
+    /// * ZINTER numkeys key [key ...] [WEIGHTS weight ...] [AGGREGATE SUM|MIN|MAX] [WITHSCORES]
+    /// * ZRANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count]
+    ///   (`(` before a bound makes it exclusive; `-inf` and `+inf` are allowed)
+    /// * ZREVRANGEBYSCORE key max min [WITHSCORES] [LIMIT offset count]
+    ///   (also accepted as ZRANGEBYSCOREREV)
+    /// * ZRANGEBYLEX key min max [LIMIT offset count]
+    ///   (`[member` inclusive, `(member` exclusive, `-` and `+` unbounded)
+    /// * ZREVRANGEBYLEX key max min [LIMIT offset count]
+    /// * ZLEXCOUNT key min max
+    /// * GEOADD key [NX|XX] [CH] longitude latitude member [longitude latitude member ...]
+    /// * GEOSEARCH key FROMMEMBER member|FROMLONLAT longitude latitude
+    ///   BYRADIUS radius unit|BYBOX width height unit [ASC|DESC] [COUNT count [ANY]]
+    ///   [WITHCOORD] [WITHDIST] [WITHHASH] [STORE destination|STOREDIST destination]
+    /// * GEORADIUS key longitude latitude radius unit [GEOSEARCH options]
+    ///   (the same as GEOSEARCH key FROMLONLAT longitude latitude BYRADIUS radius unit)
+    /// * GEORADIUSBYMEMBER key member radius unit [GEOSEARCH options]
+    ///   (the same as GEOSEARCH key FROMMEMBER member BYRADIUS radius unit)
+    /// * XADD key id|*|ms-* field value [field value ...]
+    /// * XLEN key
+    /// * XDEL key id [id ...]
+    /// * XRANGE key start end [COUNT count] (`-` and `+` are the first and last IDs)
+    /// * XGROUP CREATE key group id|$ [MKSTREAM]
+    /// * XREADGROUP GROUP group consumer [COUNT count] [NOACK] STREAMS key [key ...] id [id ...]
+    ///   (`>` reads new entries, any other ID rereads the consumer's pending ones)
+    /// * XINFO STREAM key [FULL [COUNT count]]
+    /// * XINFO GROUPS key
+    /// * XINFO CONSUMERS key group
+    /// * XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]
+    ///   (`$` is the stream's last ID when the command runs; BLOCK 0 waits forever)
+    /// * XAUTOCLAIM key group consumer min-idle-time start [COUNT count]
+    /// * EXISTS key
+    /// * TYPE key
+    /// * EXPIRE key seconds [NX|XX|GT|LT]
+    /// * TTL key
+    /// * PERSIST key
+    /// * COPY source destination [DB index] [REPLACE]
+    /// * OBJECT ENCODING key
+    /// * OBJECT FREQ key
+    /// * OBJECT HELP
+    /// * MEMORY USAGE key [SAMPLES count] (0 samples every element)
+    /// * ECHO message (the rest of the line; surrounding double quotes are dropped)
+    /// * PING [message]
+    /// * CLIENT ID
+    /// * CLIENT SETNAME name
+    /// * CLIENT GETNAME
+    /// * CLIENT LIST
+    /// * INFO [section]
+    /// * CONFIG RESETSTAT
+    /// * CONFIG REWRITE
+    /// * CONFIG GET pattern
+    /// * CONFIG SET parameter value (the rest of the line)
+    /// * CLUSTER MEET ip port
+    /// * CLUSTER FORGET node-id
+    /// * CLUSTER NODES
+    /// * CLUSTER INFO
+    /// * CLUSTER ADDSLOTS slot [slot ...]
+    /// * CLUSTER ADDSLOTSRANGE start end [start end ...]
+    /// * CLUSTER DELSLOTS slot [slot ...]
+    /// * CLUSTER GETKEYSINSLOT slot count
+    /// * BGSAVE
+    /// * BGREWRITEAOF
+    /// * FLUSHALL
+    /// * AUTH [username] password
+    /// * ACL WHOAMI
+    /// * ACL LIST
+    /// * DEBUG SLEEP seconds | RELOAD | FLUSHALL | SET-ACTIVE-EXPIRE 0|1
+    ///   | QUICKLIST-PACKED-THRESHOLD bytes | CHANGE-REPL-ID | QUICKLIST
+    /// * SHUTDOWN [NOSAVE|SAVE]
+    /// * QUIT
+    /// * RESET
+    /// * WAIT numreplicas timeout
+    /// * REPLICAOF host port | NO ONE (also accepted as SLAVEOF)
+    /// * SYNC
+    /// * PSYNC replid offset (`? -1` asks for a full sync)
+    /// * REPLCONF ACK offset
+    /// * SORT key [BY pattern] [LIMIT offset count] [GET pattern ...] [ASC|DESC] [ALPHA] [STORE destination]
+    /// * LOLWUT [VERSION version]
+    /// * SUBSCRIBE channel [channel ...]
+    /// * UNSUBSCRIBE [channel ...]
+    /// * PSUBSCRIBE pattern [pattern ...]
+    /// * PUNSUBSCRIBE [pattern ...]
+    /// * PUBLISH channel message (the message is the rest of the line, like ECHO)
     /// * MULTI
     /// * EXEC
     /// * DISCARD
     pub fn parse(input: &str) -> Command {
-        let parts: Vec<&str> = input.trim().split_whitespace().collect();
+        Self::parse_with_table(input, &CommandTable::default())
+    }
+
+    /// Parses a command string, resolving its name through a `CommandTable`
+    ///
+    /// Commands the table disables, or whose original name has been renamed
+    /// away, parse as `Command::Unknown` just like a command that doesn't exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The command string to parse
+    /// * `table` - The rename/disable mapping configured for the server
+    pub fn parse_with_table(input: &str, table: &CommandTable) -> Command {
+        let parts: Vec<&str> = input.split_whitespace().collect();
         match parts.as_slice() {
-            [command, rest @ ..] => match command.to_uppercase().as_str() {
+            [command, rest @ ..] => match table.resolve(command).unwrap_or_default().as_str() {
                 "SET" if rest.len() == 2 => Command::Set(rest[0].to_lowercase(), rest[1].to_string()),
                 "GET" if rest.len() == 1 => Command::Get(rest[0].to_lowercase()),
                 "DEL" if rest.len() == 1 => Command::Del(rest[0].to_lowercase()),
@@ -70,6 +768,346 @@ impl CommandParser {
                 "LPOP" if rest.len() == 1 => Command::LPop(rest[0].to_lowercase()),
                 "RPOP" if rest.len() == 1 => Command::RPop(rest[0].to_lowercase()),
                 "LLEN" if rest.len() == 1 => Command::LLen(rest[0].to_lowercase()),
+                "LPOS" if rest.len() >= 2 => {
+                    Self::parse_lpos(rest).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "BLPOP" => match Self::parse_blocking_pop(rest) {
+                    Some((keys, timeout)) => Command::BLPop(keys, timeout),
+                    None => Command::Unknown(input.to_string()),
+                },
+                "BRPOP" => match Self::parse_blocking_pop(rest) {
+                    Some((keys, timeout)) => Command::BRPop(keys, timeout),
+                    None => Command::Unknown(input.to_string()),
+                },
+                "BLMOVE" => match rest {
+                    [source, destination, from, to, timeout] => {
+                        match (ListSide::parse(from), ListSide::parse(to), Self::parse_timeout(timeout)) {
+                            (Some(from), Some(to), Some(timeout)) => {
+                                Command::BLMove(source.to_lowercase(), destination.to_lowercase(), from, to, timeout)
+                            },
+                            _ => Command::Unknown(input.to_string()),
+                        }
+                    },
+                    _ => Command::Unknown(input.to_string()),
+                },
+                "BRPOPLPUSH" => match rest {
+                    [source, destination, timeout] => match Self::parse_timeout(timeout) {
+                        Some(timeout) => Command::BLMove(
+                            source.to_lowercase(),
+                            destination.to_lowercase(),
+                            ListSide::Right,
+                            ListSide::Left,
+                            timeout,
+                        ),
+                        None => Command::Unknown(input.to_string()),
+                    },
+                    _ => Command::Unknown(input.to_string()),
+                },
+                // Members are case-sensitive, unlike keys
+                "SADD" if rest.len() >= 2 => {
+                    Command::SAdd(rest[0].to_lowercase(), rest[1..].iter().map(|m| m.to_string()).collect())
+                },
+                "SMEMBERS" if rest.len() == 1 => Command::SMembers(rest[0].to_lowercase()),
+                "SMOVE" if rest.len() == 3 => {
+                    Command::SMove(rest[0].to_lowercase(), rest[1].to_lowercase(), rest[2].to_string())
+                },
+                "SDIFFSTORE" if rest.len() >= 2 => Command::SDiffStore(rest[0].to_lowercase(), Self::keys(&rest[1..])),
+                "SUNIONSTORE" if rest.len() >= 2 => Command::SUnionStore(rest[0].to_lowercase(), Self::keys(&rest[1..])),
+                "SINTERSTORE" if rest.len() >= 2 => Command::SInterStore(rest[0].to_lowercase(), Self::keys(&rest[1..])),
+                "SINTERCARD" if !rest.is_empty() => {
+                    Self::parse_sintercard(rest).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "SSCAN" if rest.len() >= 2 => match Self::parse_scan(rest) {
+                    Some((key, cursor, pattern, count)) => Command::SScan(key, cursor, pattern, count),
+                    None => Command::Unknown(input.to_string()),
+                },
+                // Fields and values are case-sensitive, unlike keys
+                "HSET" if rest.len() >= 3 && rest.len() % 2 == 1 => {
+                    Command::HSet(rest[0].to_lowercase(), Self::field_value_pairs(&rest[1..]))
+                },
+                "HMSET" if rest.len() >= 3 && rest.len() % 2 == 1 => {
+                    Command::HMSet(rest[0].to_lowercase(), Self::field_value_pairs(&rest[1..]))
+                },
+                "HSETNX" if rest.len() == 3 => {
+                    Command::HSetNx(rest[0].to_lowercase(), rest[1].to_string(), rest[2].to_string())
+                },
+                "HINCRBY" if rest.len() == 3 => match rest[2].parse() {
+                    Ok(delta) => Command::HIncrBy(rest[0].to_lowercase(), rest[1].to_string(), delta),
+                    Err(_) => Command::Unknown(input.to_string()),
+                },
+                "HINCRBYFLOAT" if rest.len() == 3 => match rest[2].parse::<f64>() {
+                    Ok(delta) if delta.is_finite() => {
+                        Command::HIncrByFloat(rest[0].to_lowercase(), rest[1].to_string(), delta)
+                    },
+                    _ => Command::Unknown(input.to_string()),
+                },
+                "HSCAN" if rest.len() >= 2 => match Self::parse_scan(rest) {
+                    Some((key, cursor, pattern, count)) => Command::HScan(key, cursor, pattern, count),
+                    None => Command::Unknown(input.to_string()),
+                },
+                "ZADD" if !rest.is_empty() => Self::parse_zadd(rest).unwrap_or_else(|| Command::Unknown(input.to_string())),
+                "ZINCRBY" if rest.len() == 3 => match Self::parse_score(rest[1]) {
+                    Some(increment) => Command::ZIncrBy(rest[0].to_lowercase(), increment, rest[2].to_string()),
+                    None => Command::Unknown(input.to_string()),
+                },
+                "GEOADD" if !rest.is_empty() => Self::parse_geoadd(rest).unwrap_or_else(|| Command::Unknown(input.to_string())),
+                "GEOSEARCH" if !rest.is_empty() => {
+                    Self::parse_geosearch(rest).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "GEORADIUS" if rest.len() >= 5 => {
+                    Self::parse_georadius(rest, false).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "GEORADIUSBYMEMBER" if rest.len() >= 4 => {
+                    Self::parse_georadius(rest, true).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                // Fields, values, groups and consumers are case-sensitive, unlike keys
+                "XADD" if rest.len() >= 4 && rest.len() % 2 == 0 => match XAddId::parse(rest[1]) {
+                    Some(id) => Command::XAdd(rest[0].to_lowercase(), id, Self::field_value_pairs(&rest[2..])),
+                    None => Command::Unknown(input.to_string()),
+                },
+                "XLEN" if rest.len() == 1 => Command::XLen(rest[0].to_lowercase()),
+                "XDEL" if rest.len() >= 2 && rest[1..].iter().all(|id| StreamEntryId::parse(id, 0).is_some()) => {
+                    Command::XDel(rest[0].to_lowercase(), rest[1..].iter().map(|id| id.to_string()).collect())
+                },
+                "XRANGE" if rest.len() >= 3 => {
+                    Self::parse_xrange(rest).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "XGROUP" if !rest.is_empty() => match (rest[0].to_uppercase().as_str(), &rest[1..]) {
+                    ("CREATE", [key, group, id, options @ ..]) => {
+                        let mkstream = match options {
+                            [] => Some(false),
+                            [option] if option.eq_ignore_ascii_case("MKSTREAM") => Some(true),
+                            _ => None,
+                        };
+                        let id = match *id {
+                            "$" => Some(None),
+                            id => StreamEntryId::parse(id, 0).map(Some),
+                        };
+                        match (id, mkstream) {
+                            (Some(id), Some(mkstream)) => Command::XGroupCreate(key.to_lowercase(), group.to_string(), id, mkstream),
+                            _ => Command::Unknown(input.to_string()),
+                        }
+                    },
+                    _ => Command::Unknown(input.to_string()),
+                },
+                "XREADGROUP" if !rest.is_empty() => {
+                    Self::parse_xreadgroup(rest).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "XREAD" if !rest.is_empty() => {
+                    Self::parse_xread(rest).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "XAUTOCLAIM" if matches!(rest.len(), 5 | 7) => {
+                    Self::parse_xautoclaim(rest).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "XINFO" if rest.len() >= 2 => match (rest[0].to_uppercase().as_str(), &rest[1..]) {
+                    ("STREAM", [key]) => Command::XInfo(key.to_lowercase(), XInfoSubcommand::Stream),
+                    ("STREAM", [key, full]) if full.eq_ignore_ascii_case("FULL") => {
+                        Command::XInfo(key.to_lowercase(), XInfoSubcommand::Full(None))
+                    },
+                    ("STREAM", [key, full, keyword, count])
+                        if full.eq_ignore_ascii_case("FULL") && keyword.eq_ignore_ascii_case("COUNT") =>
+                    {
+                        match count.parse() {
+                            Ok(count) => Command::XInfo(key.to_lowercase(), XInfoSubcommand::Full(Some(count))),
+                            Err(_) => Command::Unknown(input.to_string()),
+                        }
+                    },
+                    ("GROUPS", [key]) => Command::XInfo(key.to_lowercase(), XInfoSubcommand::Groups),
+                    ("CONSUMERS", [key, group]) => {
+                        Command::XInfo(key.to_lowercase(), XInfoSubcommand::Consumers(group.to_string()))
+                    },
+                    _ => Command::Unknown(input.to_string()),
+                },
+                "ZSCORE" if rest.len() == 2 => Command::ZScore(rest[0].to_lowercase(), rest[1].to_string()),
+                "ZCARD" if rest.len() == 1 => Command::ZCard(rest[0].to_lowercase()),
+                "ZSCAN" if rest.len() >= 2 => match Self::parse_scan(rest) {
+                    Some((key, cursor, pattern, count)) => Command::ZScan(key, cursor, pattern, count),
+                    None => Command::Unknown(input.to_string()),
+                },
+                "ZPOPMIN" if matches!(rest.len(), 1 | 2) => match Self::parse_pop_count(rest) {
+                    Some(count) => Command::ZPopMin(rest[0].to_lowercase(), count),
+                    None => Command::Unknown(input.to_string()),
+                },
+                "ZPOPMAX" if matches!(rest.len(), 1 | 2) => match Self::parse_pop_count(rest) {
+                    Some(count) => Command::ZPopMax(rest[0].to_lowercase(), count),
+                    None => Command::Unknown(input.to_string()),
+                },
+                "BZPOPMIN" => match Self::parse_blocking_pop(rest) {
+                    Some((keys, timeout)) => Command::BZPopMin(keys, timeout),
+                    None => Command::Unknown(input.to_string()),
+                },
+                "BZPOPMAX" => match Self::parse_blocking_pop(rest) {
+                    Some((keys, timeout)) => Command::BZPopMax(keys, timeout),
+                    None => Command::Unknown(input.to_string()),
+                },
+                "ZRANGEBYLEX" if rest.len() >= 3 => {
+                    Self::parse_zrangebylex(rest, false).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "ZREVRANGEBYLEX" if rest.len() >= 3 => {
+                    Self::parse_zrangebylex(rest, true).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "ZLEXCOUNT" if rest.len() == 3 => match (Self::parse_lex_bound(rest[1]), Self::parse_lex_bound(rest[2])) {
+                    (Some(min), Some(max)) => Command::ZLexCount(rest[0].to_lowercase(), min, max),
+                    _ => Command::Unknown(input.to_string()),
+                },
+                name @ ("ZDIFF" | "ZUNION" | "ZINTER") if !rest.is_empty() => {
+                    Self::parse_zset_algebra(name, rest).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "ZRANGEBYSCORE" if rest.len() >= 3 => {
+                    Self::parse_zrangebyscore(rest, false).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "ZREVRANGEBYSCORE" | "ZRANGEBYSCOREREV" if rest.len() >= 3 => {
+                    Self::parse_zrangebyscore(rest, true).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "EXISTS" if rest.len() == 1 => Command::Exists(rest[0].to_lowercase()),
+                "TYPE" if rest.len() == 1 => Command::Type(rest[0].to_lowercase()),
+                "EXPIRE" if rest.len() == 2 => match rest[1].parse() {
+                    Ok(seconds) => Command::Expire(rest[0].to_lowercase(), seconds),
+                    Err(_) => Command::Unknown(input.to_string()),
+                },
+                "EXPIRE" if rest.len() == 3 => {
+                    Self::parse_expire_opts(rest).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "TTL" if rest.len() == 1 => Command::Ttl(rest[0].to_lowercase()),
+                "PERSIST" if rest.len() == 1 => Command::Persist(rest[0].to_lowercase()),
+                "COPY" if rest.len() >= 2 => {
+                    Self::parse_copy(rest).unwrap_or_else(|| Command::Unknown(input.to_string()))
+                },
+                "OBJECT" => match rest {
+                    [subcommand, key] if subcommand.eq_ignore_ascii_case("ENCODING") => {
+                        Command::ObjectEncoding(key.to_lowercase())
+                    },
+                    [subcommand, key] if subcommand.eq_ignore_ascii_case("FREQ") => Command::ObjectFreq(key.to_lowercase()),
+                    [subcommand] if subcommand.eq_ignore_ascii_case("HELP") => Command::ObjectHelp,
+                    _ => Command::Unknown(input.to_string()),
+                },
+                "MEMORY" => match rest {
+                    [subcommand, key] if subcommand.eq_ignore_ascii_case("USAGE") => {
+                        Command::MemoryUsage(key.to_lowercase(), None)
+                    },
+                    [subcommand, key, option, samples]
+                        if subcommand.eq_ignore_ascii_case("USAGE") && option.eq_ignore_ascii_case("SAMPLES") =>
+                    {
+                        match samples.parse() {
+                            Ok(samples) => Command::MemoryUsage(key.to_lowercase(), Some(samples)),
+                            Err(_) => Command::Unknown(input.to_string()),
+                        }
+                    },
+                    _ => Command::Unknown(input.to_string()),
+                },
+                "ECHO" if !rest.is_empty() => Command::Echo(Self::trailing_text(input, 1)),
+                "PING" if rest.is_empty() => Command::Ping(None),
+                "PING" => Command::Ping(Some(Self::trailing_text(input, 1))),
+                "CLIENT" if !rest.is_empty() => match (rest[0].to_uppercase().as_str(), &rest[1..]) {
+                    ("ID", []) => Command::ClientId,
+                    // Keep every word so a name with spaces is rejected rather than truncated
+                    ("SETNAME", [_, ..]) => Command::ClientSetName(rest[1..].join(" ")),
+                    ("GETNAME", []) => Command::ClientGetName,
+                    ("LIST", []) => Command::ClientList,
+                    _ => Command::Unknown(input.to_string()),
+                },
+                "INFO" if rest.len() <= 1 => Command::Info(rest.first().map(|section| section.to_lowercase())),
+                "CONFIG" if !rest.is_empty() => match (rest[0].to_uppercase().as_str(), &rest[1..]) {
+                    ("RESETSTAT", []) => Command::ConfigResetStat,
+                    ("REWRITE", []) => Command::ConfigRewrite,
+                    ("GET", [pattern]) => Command::ConfigGet(pattern.to_string()),
+                    // Values like save rules span several words
+                    ("SET", [name, value @ ..]) if !value.is_empty() => Command::ConfigSet(name.to_string(), value.join(" ")),
+                    _ => Command::Unknown(input.to_string()),
+                },
+                "CLUSTER" if !rest.is_empty() => match (rest[0].to_uppercase().as_str(), &rest[1..]) {
+                    ("MEET", [ip, port]) => match port.parse() {
+                        Ok(port) => Command::ClusterMeet(ip.to_string(), port),
+                        Err(_) => Command::Unknown(input.to_string()),
+                    },
+                    ("FORGET", [node_id]) => Command::ClusterForget(node_id.to_string()),
+                    ("NODES", []) => Command::ClusterNodes,
+                    ("INFO", []) => Command::ClusterInfo,
+                    ("ADDSLOTS", [_, ..]) => match Self::parse_slots(&rest[1..]) {
+                        Some(slots) => Command::ClusterAddSlots(slots),
+                        None => Command::Unknown(input.to_string()),
+                    },
+                    ("ADDSLOTSRANGE", [_, _, ..]) if rest.len() % 2 == 1 => match Self::parse_slots(&rest[1..]) {
+                        Some(bounds) => {
+                            Command::ClusterAddSlotsRange(bounds.chunks(2).map(|pair| (pair[0], pair[1])).collect())
+                        },
+                        None => Command::Unknown(input.to_string()),
+                    },
+                    ("DELSLOTS", [_, ..]) => match Self::parse_slots(&rest[1..]) {
+                        Some(slots) => Command::ClusterDelSlots(slots),
+                        None => Command::Unknown(input.to_string()),
+                    },
+                    ("GETKEYSINSLOT", [slot, count]) => match (slot.parse(), count.parse()) {
+                        (Ok(slot), Ok(count)) => Command::ClusterGetKeysInSlot(slot, count),
+                        _ => Command::Unknown(input.to_string()),
+                    },
+                    _ => Command::Unknown(input.to_string()),
+                },
+                "BGSAVE" if rest.is_empty() => Command::BgSave,
+                "BGREWRITEAOF" if rest.is_empty() => Command::BgRewriteAof,
+                "FLUSHALL" if rest.is_empty() => Command::FlushAll,
+                // Passwords are case-sensitive; the one-argument form logs in as "default"
+                "AUTH" => match rest {
+                    [password] => Command::Auth(None, password.to_string()),
+                    [username, password] => Command::Auth(Some(username.to_string()), password.to_string()),
+                    _ => Command::Unknown(input.to_string()),
+                },
+                "ACL" if rest.len() == 1 => match rest[0].to_uppercase().as_str() {
+                    "WHOAMI" => Command::AclWhoAmI,
+                    "LIST" => Command::AclList,
+                    _ => Command::Unknown(input.to_string()),
+                },
+                "DEBUG" if !rest.is_empty() => match Self::parse_debug(rest) {
+                    Some(subcommand) => Command::Debug(subcommand),
+                    None => Command::Unknown(input.to_string()),
+                },
+                "SHUTDOWN" if rest.len() <= 1 => match rest.first().map(|mode| mode.to_uppercase()).as_deref() {
+                    None => Command::Shutdown(ShutdownMode::Default),
+                    Some("SAVE") => Command::Shutdown(ShutdownMode::Save),
+                    Some("NOSAVE") => Command::Shutdown(ShutdownMode::NoSave),
+                    Some(_) => Command::Unknown(input.to_string()),
+                },
+                "QUIT" if rest.is_empty() => Command::Quit,
+                "RESET" if rest.is_empty() => Command::Reset,
+                "WAIT" if rest.len() == 2 => match (rest[0].parse(), rest[1].parse()) {
+                    (Ok(num_replicas), Ok(timeout_ms)) => Command::Wait(num_replicas, timeout_ms),
+                    _ => Command::Unknown(input.to_string()),
+                },
+                "REPLICAOF" | "SLAVEOF" => match rest {
+                    [no, one] if no.eq_ignore_ascii_case("NO") && one.eq_ignore_ascii_case("ONE") => Command::ReplicaOf(None),
+                    [host, port] => match port.parse() {
+                        Ok(port) => Command::ReplicaOf(Some((host.to_string(), port))),
+                        Err(_) => Command::Unknown(input.to_string()),
+                    },
+                    _ => Command::Unknown(input.to_string()),
+                },
+                "SYNC" if rest.is_empty() => Command::Sync,
+                "PSYNC" if rest.len() == 2 => match rest[1].parse() {
+                    Ok(offset) => Command::PSync(rest[0].to_string(), offset),
+                    Err(_) => Command::Unknown(input.to_string()),
+                },
+                "REPLCONF" => match rest {
+                    [ack, offset] if ack.eq_ignore_ascii_case("ACK") => match offset.parse() {
+                        Ok(offset) => Command::ReplConfAck(offset),
+                        Err(_) => Command::Unknown(input.to_string()),
+                    },
+                    _ => Command::Unknown(input.to_string()),
+                },
+                "SORT" if !rest.is_empty() => Self::parse_sort(rest).unwrap_or_else(|| Command::Unknown(input.to_string())),
+                "LOLWUT" => match rest {
+                    [] => Command::Lolwut(None),
+                    [keyword, version] if keyword.eq_ignore_ascii_case("VERSION") => match version.parse() {
+                        Ok(version) => Command::Lolwut(Some(version)),
+                        Err(_) => Command::Unknown(input.to_string()),
+                    },
+                    _ => Command::Unknown(input.to_string()),
+                },
+                // Channel names are case-sensitive, unlike keys
+                "SUBSCRIBE" if !rest.is_empty() => Command::Subscribe(rest.iter().map(|c| c.to_string()).collect()),
+                "UNSUBSCRIBE" => Command::Unsubscribe(rest.iter().map(|c| c.to_string()).collect()),
+                "PSUBSCRIBE" if !rest.is_empty() => Command::PSubscribe(rest.iter().map(|p| p.to_string()).collect()),
+                "PUNSUBSCRIBE" => Command::PUnsubscribe(rest.iter().map(|p| p.to_string()).collect()),
+                "PUBLISH" if rest.len() >= 2 => Command::Publish(rest[0].to_string(), Self::trailing_text(input, 2)),
                 "MULTI" if rest.is_empty() => Command::Multi,
                 "EXEC" if rest.is_empty() => Command::Exec,
                 "DISCARD" if rest.is_empty() => Command::Discard,
@@ -78,4 +1116,604 @@ impl CommandParser {
             _ => Command::Unknown("".to_string()),
         }
     }
-}
\ No newline at end of file
+
+    /// Returns everything after the first `words` words, keeping inner whitespace
+    ///
+    /// The inline protocol has no bulk strings, so a message wrapped in double
+    /// quotes is the way to send leading or trailing spaces.
+    fn trailing_text(input: &str, words: usize) -> String {
+        let mut message = input.trim();
+        for _ in 0..words {
+            message = message
+                .find(char::is_whitespace)
+                .map_or("", |end| message[end..].trim_start());
+        }
+        match message.strip_prefix('"').and_then(|m| m.strip_suffix('"')) {
+            Some(quoted) => quoted.to_string(),
+            None => message.to_string(),
+        }
+    }
+
+    /// Parses COPY's arguments: source, destination, then DB and REPLACE in any order
+    fn parse_copy(args: &[&str]) -> Option<Command> {
+        let (mut db, mut replace) = (None, false);
+        let mut options = args[2..].iter();
+        while let Some(option) = options.next() {
+            match option.to_uppercase().as_str() {
+                "DB" if db.is_none() => db = Some(options.next()?.parse().ok()?),
+                "REPLACE" if !replace => replace = true,
+                _ => return None,
+            }
+        }
+        Some(Command::Copy(args[0].to_lowercase(), args[1].to_lowercase(), db, replace))
+    }
+
+    /// Lower-cases a run of key arguments
+    fn keys(args: &[&str]) -> Vec<String> {
+        args.iter().map(|key| key.to_lowercase()).collect()
+    }
+
+    fn parse_debug(args: &[&str]) -> Option<DebugSubcommand> {
+        let subcommand = match (args[0].to_uppercase().as_str(), &args[1..]) {
+            // A sleep longer than a Duration can hold would panic the executor
+            ("SLEEP", [seconds]) => DebugSubcommand::Sleep(
+                Self::parse_timeout(seconds).filter(|&seconds| Duration::try_from_secs_f64(seconds).is_ok())?,
+            ),
+            ("RELOAD", []) => DebugSubcommand::Reload,
+            ("FLUSHALL", []) => DebugSubcommand::Flushall,
+            ("SET-ACTIVE-EXPIRE", ["0"]) => DebugSubcommand::SetActiveExpire(false),
+            ("SET-ACTIVE-EXPIRE", ["1"]) => DebugSubcommand::SetActiveExpire(true),
+            ("QUICKLIST-PACKED-THRESHOLD", [bytes]) => DebugSubcommand::QuicklistPackedThreshold(bytes.parse().ok()?),
+            ("CHANGE-REPL-ID", []) => DebugSubcommand::ChangeReplId,
+            ("QUICKLIST", []) => DebugSubcommand::Quicklist,
+            _ => return None,
+        };
+        Some(subcommand)
+    }
+
+    /// Parses slot numbers; range checks are left to the slot table so
+    /// they get Redis's error message
+    fn parse_slots(args: &[&str]) -> Option<Vec<u16>> {
+        args.iter().map(|slot| slot.parse().ok()).collect()
+    }
+
+    /// Parses `key [key ...] timeout`
+    fn parse_blocking_pop(args: &[&str]) -> Option<(Vec<String>, f64)> {
+        let (timeout, keys) = args.split_last()?;
+        let timeout = Self::parse_timeout(timeout)?;
+        if keys.is_empty() {
+            return None;
+        }
+        Some((Self::keys(keys), timeout))
+    }
+
+    /// Parses a blocking command's timeout, which must be a non-negative number of seconds
+    fn parse_timeout(timeout: &str) -> Option<f64> {
+        let timeout: f64 = timeout.parse().ok()?;
+        (timeout.is_finite() && timeout >= 0.0).then_some(timeout)
+    }
+
+    /// Pairs up `field value [field value ...]`; a trailing unpaired field is dropped
+    fn field_value_pairs(args: &[&str]) -> Vec<(String, String)> {
+        args.chunks_exact(2).map(|pair| (pair[0].to_string(), pair[1].to_string())).collect()
+    }
+
+    /// Parses `key cursor [MATCH pattern] [COUNT count]`, as the *SCAN commands take
+    ///
+    /// The pattern is case-sensitive; COUNT must be at least 1.
+    fn parse_scan(args: &[&str]) -> Option<(String, u64, Option<String>, Option<usize>)> {
+        let key = args[0].to_lowercase();
+        let cursor = args[1].parse().ok()?;
+        let mut pattern = None;
+        let mut count = None;
+
+        let mut rest = args[2..].iter();
+        while let Some(option) = rest.next() {
+            match option.to_uppercase().as_str() {
+                "MATCH" => pattern = Some(rest.next()?.to_string()),
+                "COUNT" => count = Some(rest.next()?.parse().ok().filter(|&count: &usize| count > 0)?),
+                _ => return None,
+            }
+        }
+        Some((key, cursor, pattern, count))
+    }
+
+    /// Parses `numkeys key [key ...] [LIMIT limit]`; `numkeys` must match the keys given
+    fn parse_sintercard(args: &[&str]) -> Option<Command> {
+        let numkeys: usize = args[0].parse().ok()?;
+        if numkeys == 0 || numkeys > args.len() - 1 {
+            return None;
+        }
+        let keys = Self::keys(&args[1..=numkeys]);
+        let limit = match &args[1 + numkeys..] {
+            [] => None,
+            [keyword, limit] if keyword.eq_ignore_ascii_case("LIMIT") => Some(limit.parse().ok()?),
+            _ => return None,
+        };
+        Some(Command::SInterCard(numkeys, keys, limit))
+    }
+
+    /// Parses the arguments of LPOS, returning `None` on a syntax error
+    ///
+    /// RANK may be negative to search from the tail but not 0; COUNT 0 and
+    /// MAXLEN 0 mean no limit. Each option may come in any order.
+    fn parse_lpos(args: &[&str]) -> Option<Command> {
+        let key = args[0].to_lowercase();
+        let element = args[1].to_string();
+        let (mut rank, mut count, mut maxlen) = (1, None, 0);
+        for option in args[2..].chunks(2) {
+            let [name, value] = option else {
+                return None;
+            };
+            match name.to_uppercase().as_str() {
+                "RANK" => rank = value.parse().ok().filter(|rank| *rank != 0)?,
+                "COUNT" => count = Some(value.parse().ok()?),
+                "MAXLEN" => maxlen = value.parse().ok()?,
+                _ => return None,
+            }
+        }
+        Some(Command::LPos { key, element, rank, count, maxlen })
+    }
+
+    /// Parses EXPIRE with its option, returning `None` on a syntax error
+    fn parse_expire_opts(args: &[&str]) -> Option<Command> {
+        let seconds = args[1].parse().ok()?;
+        let mut flags = ExpireFlags::default();
+        match args[2].to_uppercase().as_str() {
+            "NX" => flags.nx = true,
+            "XX" => flags.xx = true,
+            "GT" => flags.gt = true,
+            "LT" => flags.lt = true,
+            _ => return None,
+        }
+        Some(Command::ExpireOpts(args[0].to_lowercase(), seconds, flags))
+    }
+
+    /// Parses the arguments of ZADD, returning `None` on a syntax error
+    fn parse_zadd(args: &[&str]) -> Option<Command> {
+        let key = args[0].to_lowercase();
+        let mut options = ZAddOptions::default();
+        let mut rest = &args[1..];
+        while let Some((word, tail)) = rest.split_first() {
+            match word.to_uppercase().as_str() {
+                "NX" => options.nx = true,
+                "XX" => options.xx = true,
+                "GT" => options.gt = true,
+                "LT" => options.lt = true,
+                "CH" => options.ch = true,
+                "INCR" => options.incr = true,
+                _ => break,
+            }
+            rest = tail;
+        }
+
+        if rest.is_empty() || !rest.len().is_multiple_of(2) {
+            return None;
+        }
+        let mut pairs = Vec::with_capacity(rest.len() / 2);
+        for pair in rest.chunks_exact(2) {
+            pairs.push((Self::parse_score(pair[0])?, pair[1].to_string()));
+        }
+        let ZAddOptions { nx, xx, gt, lt, ch, incr } = options;
+        if !Self::validate_zadd_options(nx, xx, gt, lt, ch, incr, pairs.len()) {
+            return None;
+        }
+        Some(Command::ZAdd(key, options, pairs))
+    }
+
+    /// Parses the arguments of GEOADD, returning `None` on a syntax error
+    ///
+    /// Coordinates only need to be numbers here; the range check is left to
+    /// storage, which replies with the offending pair.
+    fn parse_geoadd(args: &[&str]) -> Option<Command> {
+        let key = args[0].to_lowercase();
+        let mut options = GeoAddOptions::default();
+        let mut rest = &args[1..];
+        while let Some((word, tail)) = rest.split_first() {
+            match word.to_uppercase().as_str() {
+                "NX" => options.nx = true,
+                "XX" => options.xx = true,
+                "CH" => options.ch = true,
+                _ => break,
+            }
+            rest = tail;
+        }
+
+        if rest.is_empty() || !rest.len().is_multiple_of(3) || (options.nx && options.xx) {
+            return None;
+        }
+        let mut items = Vec::with_capacity(rest.len() / 3);
+        for item in rest.chunks_exact(3) {
+            let longitude = item[0].parse::<f64>().ok().filter(|value| value.is_finite())?;
+            let latitude = item[1].parse::<f64>().ok().filter(|value| value.is_finite())?;
+            items.push((longitude, latitude, item[2].to_string()));
+        }
+        Some(Command::GeoAdd { key, options, items })
+    }
+
+    /// Parses the arguments of GEOSEARCH, returning `None` on a syntax error
+    ///
+    /// Exactly one FROM* and one BY* option are required. STORE and
+    /// STOREDIST reply with a count, so they can't be combined with WITH*.
+    fn parse_geosearch(args: &[&str]) -> Option<Command> {
+        let key = args[0].to_lowercase();
+        let distance = |word: &str| word.parse::<f64>().ok().filter(|value| value.is_finite() && *value >= 0.0);
+        let mut origin = None;
+        let mut area = None;
+        let mut descending = None;
+        let mut count = None;
+        let mut any = false;
+        let (mut with_coord, mut with_dist, mut with_hash) = (false, false, false);
+        let mut store = None;
+
+        let mut rest = args[1..].iter();
+        while let Some(option) = rest.next() {
+            match option.to_uppercase().as_str() {
+                "FROMMEMBER" if origin.is_none() => origin = Some(GeoOrigin::Member(rest.next()?.to_string())),
+                "FROMLONLAT" if origin.is_none() => {
+                    let longitude = rest.next()?.parse::<f64>().ok()?;
+                    let latitude = rest.next()?.parse::<f64>().ok()?;
+                    if !geo::valid_coordinates(longitude, latitude) {
+                        return None;
+                    }
+                    origin = Some(GeoOrigin::LonLat(longitude, latitude));
+                }
+                "BYRADIUS" if area.is_none() => {
+                    let radius = distance(rest.next()?)?;
+                    area = Some((GeoShape::Radius(radius), GeoUnit::parse(rest.next()?)?));
+                }
+                "BYBOX" if area.is_none() => {
+                    let width = distance(rest.next()?)?;
+                    let height = distance(rest.next()?)?;
+                    area = Some((GeoShape::Box(width, height), GeoUnit::parse(rest.next()?)?));
+                }
+                "ASC" => descending = Some(false),
+                "DESC" => descending = Some(true),
+                "COUNT" => count = Some(rest.next()?.parse::<usize>().ok().filter(|count| *count > 0)?),
+                "ANY" => any = true,
+                "WITHCOORD" => with_coord = true,
+                "WITHDIST" => with_dist = true,
+                "WITHHASH" => with_hash = true,
+                "STORE" => store = Some((GeoStoreMode::Geohash, rest.next()?.to_lowercase())),
+                "STOREDIST" => store = Some((GeoStoreMode::Distance, rest.next()?.to_lowercase())),
+                _ => return None,
+            }
+        }
+
+        let (shape, unit) = area?;
+        if (any && count.is_none()) || (store.is_some() && (with_coord || with_dist || with_hash)) {
+            return None;
+        }
+        let params = GeoSearchParams {
+            origin: origin?,
+            shape,
+            unit,
+            descending,
+            count,
+            any,
+            with_coord,
+            with_dist,
+            with_hash,
+        };
+        Some(Command::GeoSearch { key, params, store })
+    }
+
+    /// Parses GEORADIUS, or GEORADIUSBYMEMBER if `by_member`, as the GEOSEARCH
+    /// it is shorthand for, returning `None` on a syntax error
+    fn parse_georadius(args: &[&str], by_member: bool) -> Option<Command> {
+        let (from, origin_len) = if by_member { ("FROMMEMBER", 1) } else { ("FROMLONLAT", 2) };
+        let (origin, rest) = args[1..].split_at(origin_len);
+        let (radius, options) = rest.split_at(2);
+        let mut words = vec![args[0], from];
+        words.extend(origin);
+        words.push("BYRADIUS");
+        words.extend(radius);
+        words.extend(options);
+        Self::parse_geosearch(&words)
+    }
+
+    /// Returns whether ZADD's options can be used together
+    ///
+    /// NX never updates, so it can't be combined with XX, GT or LT; GT and LT
+    /// contradict each other; INCR takes exactly one score-member pair. CH
+    /// only changes what the reply counts, so it fits with everything.
+    fn validate_zadd_options(nx: bool, xx: bool, gt: bool, lt: bool, _ch: bool, incr: bool, pairs_count: usize) -> bool {
+        !(nx && (xx || gt || lt)) && !(gt && lt) && !(incr && pairs_count != 1)
+    }
+
+    /// Parses a sorted set score; `inf`, `+inf` and `-inf` are allowed, NaN is not
+    fn parse_score(score: &str) -> Option<f64> {
+        score.parse::<f64>().ok().filter(|score| !score.is_nan())
+    }
+
+    /// Parses `key first last [WITHSCORES] [LIMIT offset count]`, where the
+    /// bounds are `min max`, or `max min` when `rev` is set
+    fn parse_zrangebyscore(args: &[&str], rev: bool) -> Option<Command> {
+        let key = args[0].to_lowercase();
+        let first = Self::parse_score_bound(args[1])?;
+        let last = Self::parse_score_bound(args[2])?;
+        let mut withscores = false;
+        let mut limit = None;
+
+        let mut rest = args[3..].iter();
+        while let Some(option) = rest.next() {
+            match option.to_uppercase().as_str() {
+                "WITHSCORES" => withscores = true,
+                "LIMIT" => {
+                    let offset = rest.next()?.parse().ok()?;
+                    let count = rest.next()?.parse().ok()?;
+                    limit = Some((offset, count));
+                }
+                _ => return None,
+            }
+        }
+
+        Some(if rev {
+            Command::ZRevRangeByScore { key, max: first, min: last, withscores, limit }
+        } else {
+            Command::ZRangeByScore { key, min: first, max: last, withscores, limit }
+        })
+    }
+
+    /// Parses `key first last [LIMIT offset count]`, where the bounds are
+    /// `min max`, or `max min` when `rev` is set
+    fn parse_zrangebylex(args: &[&str], rev: bool) -> Option<Command> {
+        let key = args[0].to_lowercase();
+        let first = Self::parse_lex_bound(args[1])?;
+        let last = Self::parse_lex_bound(args[2])?;
+        let limit = match &args[3..] {
+            [] => None,
+            [keyword, offset, count] if keyword.eq_ignore_ascii_case("LIMIT") => {
+                Some((offset.parse().ok()?, count.parse().ok()?))
+            }
+            _ => return None,
+        };
+        let (min, max) = if rev { (last, first) } else { (first, last) };
+        Some(Command::ZRangeByLex { key, min, max, limit, rev })
+    }
+
+    /// Parses a member range bound: `-`, `+`, `[member` or `(member`
+    fn parse_lex_bound(bound: &str) -> Option<LexBound> {
+        match bound {
+            "-" => Some(LexBound::NegInf),
+            "+" => Some(LexBound::PosInf),
+            _ => match bound.split_at_checked(1)? {
+                ("[", member) => Some(LexBound::Inclusive(member.to_string())),
+                ("(", member) => Some(LexBound::Exclusive(member.to_string())),
+                _ => None,
+            },
+        }
+    }
+
+    /// Parses ZDIFF, ZUNION or ZINTER: `numkeys key [key ...]` and its options
+    ///
+    /// WEIGHTS (exactly one per key) and AGGREGATE only apply to ZUNION and
+    /// ZINTER; WITHSCORES to all three.
+    fn parse_zset_algebra(command: &str, args: &[&str]) -> Option<Command> {
+        let weighted = command != "ZDIFF";
+        let numkeys: usize = args[0].parse().ok()?;
+        if numkeys == 0 || numkeys > args.len() - 1 {
+            return None;
+        }
+        let keys = Self::keys(&args[1..=numkeys]);
+        let mut weights = Vec::new();
+        let mut agg = AggType::Sum;
+        let mut withscores = false;
+
+        let mut rest = args[1 + numkeys..].iter();
+        while let Some(option) = rest.next() {
+            match option.to_uppercase().as_str() {
+                "WITHSCORES" => withscores = true,
+                "WEIGHTS" if weighted => {
+                    weights = Vec::with_capacity(numkeys);
+                    for _ in 0..numkeys {
+                        weights.push(Self::parse_score(rest.next()?)?);
+                    }
+                }
+                "AGGREGATE" if weighted => {
+                    agg = match rest.next()?.to_uppercase().as_str() {
+                        "SUM" => AggType::Sum,
+                        "MIN" => AggType::Min,
+                        "MAX" => AggType::Max,
+                        _ => return None,
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(match command {
+            "ZDIFF" => Command::ZDiff(numkeys, keys, withscores),
+            "ZUNION" => Command::ZUnion(numkeys, keys, weights, agg, withscores),
+            _ => Command::ZInter(numkeys, keys, weights, agg, withscores),
+        })
+    }
+
+    /// Parses the optional count after `key`; `Some(None)` if there is none
+    fn parse_pop_count(args: &[&str]) -> Option<Option<usize>> {
+        match args.get(1) {
+            None => Some(None),
+            Some(count) => count.parse().ok().map(Some),
+        }
+    }
+
+    /// Parses a score range bound: `-inf`, `+inf`, `(score` or `score`
+    fn parse_score_bound(bound: &str) -> Option<ScoreBound> {
+        match bound.to_lowercase().as_str() {
+            "-inf" => Some(ScoreBound::NegInf),
+            "+inf" | "inf" => Some(ScoreBound::PosInf),
+            _ => match bound.strip_prefix('(') {
+                Some(score) => Self::parse_score(score).map(ScoreBound::Exclusive),
+                None => Self::parse_score(bound).map(ScoreBound::Inclusive),
+            },
+        }
+    }
+
+    /// Parses the arguments of SORT, returning `None` on a syntax error
+    /// Parses the arguments of XRANGE, returning `None` on a syntax error
+    fn parse_xrange(args: &[&str]) -> Option<Command> {
+        let start = StreamEntryId::parse_start(args[1])?;
+        let end = StreamEntryId::parse_end(args[2])?;
+        let count = match &args[3..] {
+            [] => None,
+            [keyword, count] if keyword.eq_ignore_ascii_case("COUNT") => Some(count.parse().ok()?),
+            _ => return None,
+        };
+        Some(Command::XRange(args[0].to_lowercase(), start, end, count))
+    }
+
+    /// Parses the arguments of XREADGROUP, returning `None` on a syntax error
+    ///
+    /// COUNT and NOACK may come in any order before STREAMS, which takes
+    /// the keys and then one ID per key.
+    fn parse_xreadgroup(args: &[&str]) -> Option<Command> {
+        let [keyword, group, consumer, rest @ ..] = args else {
+            return None;
+        };
+        if !keyword.eq_ignore_ascii_case("GROUP") {
+            return None;
+        }
+        let (mut count, mut noack) = (None, false);
+        let mut rest = rest.iter();
+        let streams = loop {
+            match rest.next()?.to_uppercase().as_str() {
+                "COUNT" => count = Some(rest.next()?.parse().ok()?),
+                "NOACK" => noack = true,
+                "STREAMS" => break rest.as_slice(),
+                _ => return None,
+            }
+        };
+        let streams = Self::parse_stream_ids(streams, ">")?;
+        Some(Command::XReadGroup { group: group.to_string(), consumer: consumer.to_string(), count, noack, streams })
+    }
+
+    /// Parses the arguments of XREAD, returning `None` on a syntax error
+    ///
+    /// COUNT and BLOCK may come in any order before STREAMS.
+    fn parse_xread(args: &[&str]) -> Option<Command> {
+        let (mut count, mut block) = (None, None);
+        let mut rest = args.iter();
+        let streams = loop {
+            match rest.next()?.to_uppercase().as_str() {
+                "COUNT" => count = Some(rest.next()?.parse().ok()?),
+                "BLOCK" => block = Some(rest.next()?.parse().ok()?),
+                "STREAMS" => break rest.as_slice(),
+                _ => return None,
+            }
+        };
+        let streams = Self::parse_stream_ids(streams, "$")?;
+        Some(Command::XRead { count, block, streams })
+    }
+
+    /// Parses the arguments of XAUTOCLAIM, returning `None` on a syntax error
+    ///
+    /// COUNT defaults to `XAUTOCLAIM_COUNT` and must be positive.
+    fn parse_xautoclaim(args: &[&str]) -> Option<Command> {
+        let count = match &args[5..] {
+            [] => XAUTOCLAIM_COUNT,
+            [keyword, count] if keyword.eq_ignore_ascii_case("COUNT") => count.parse().ok().filter(|&count| count > 0)?,
+            _ => return None,
+        };
+        Some(Command::XAutoClaim {
+            key: args[0].to_lowercase(),
+            group: args[1].to_string(),
+            consumer: args[2].to_string(),
+            min_idle_ms: args[3].parse().ok()?,
+            start: StreamEntryId::parse_start(args[4])?,
+            count,
+        })
+    }
+
+    /// Splits the arguments after STREAMS into keys and their IDs
+    ///
+    /// `latest` is the ID that stands for "entries newer than any seen",
+    /// which is returned as `None`.
+    fn parse_stream_ids(streams: &[&str], latest: &str) -> Option<Vec<(String, Option<StreamEntryId>)>> {
+        if streams.is_empty() || !streams.len().is_multiple_of(2) {
+            return None;
+        }
+        let (keys, ids) = streams.split_at(streams.len() / 2);
+        keys.iter()
+            .zip(ids)
+            .map(|(key, id)| match *id {
+                id if id == latest => Some((key.to_lowercase(), None)),
+                id => StreamEntryId::parse(id, 0).map(|id| (key.to_lowercase(), Some(id))),
+            })
+            .collect()
+    }
+
+    fn parse_sort(args: &[&str]) -> Option<Command> {
+        let key = args[0].to_lowercase();
+        let mut by = None;
+        let mut limit = None;
+        let mut get_patterns = Vec::new();
+        let mut order = SortOrder::Asc;
+        let mut alpha = false;
+        let mut store = None;
+
+        let mut rest = args[1..].iter();
+        while let Some(option) = rest.next() {
+            match option.to_uppercase().as_str() {
+                "BY" => by = Some(rest.next()?.to_string()),
+                "LIMIT" => {
+                    let offset = rest.next()?.parse().ok()?;
+                    let count = rest.next()?.parse().ok()?;
+                    limit = Some((offset, count));
+                }
+                "GET" => get_patterns.push(rest.next()?.to_string()),
+                "ASC" => order = SortOrder::Asc,
+                "DESC" => order = SortOrder::Desc,
+                "ALPHA" => alpha = true,
+                "STORE" => store = Some(rest.next()?.to_lowercase()),
+                _ => return None,
+            }
+        }
+
+        Some(Command::Sort { key, by, limit, get_patterns, order, alpha, store })
+    }
+}
+
+/// Client-facing command names, built once at startup from `Config::rename_command`
+///
+/// Mirrors the `rename-command` directive of redis.conf: mapping a command to
+/// an empty string disables it, mapping it to another name makes it respond
+/// only to that name.
+#[derive(Debug, Clone, Default)]
+pub struct CommandTable {
+    hidden: HashSet<String>,
+    aliases: HashMap<String, String>,
+}
+
+impl CommandTable {
+    /// Builds the table from `original name -> new name` pairs
+    ///
+    /// Names are case-insensitive; an empty new name disables the command.
+    pub fn new(rename_command: &HashMap<String, String>) -> Self {
+        let mut table = CommandTable::default();
+        for (original, renamed) in rename_command {
+            let original = original.to_uppercase();
+            let renamed = renamed.trim().to_uppercase();
+            if !renamed.is_empty() {
+                table.aliases.insert(renamed, original.clone());
+            }
+            table.hidden.insert(original);
+        }
+        table
+    }
+
+    /// Resolves the name a client sent to the built-in command name
+    ///
+    /// # Returns
+    ///
+    /// * `Some(String)` - The upper-cased built-in name to dispatch on
+    /// * `None` - If the command is disabled or only reachable under a new name
+    pub fn resolve(&self, name: &str) -> Option<String> {
+        let name = name.to_uppercase();
+        if let Some(original) = self.aliases.get(&name) {
+            return Some(original.clone());
+        }
+        if self.hidden.contains(&name) {
+            return None;
+        }
+        Some(name)
+    }
+}
@@ -0,0 +1,127 @@
+//! # Command Line Module
+//!
+//! Arguments of the server binary: an optional TOML config file and flags
+//! overriding a few of its settings. Flags win over the file, and the file
+//! wins over the defaults.
+use crate::config::config::Config;
+use std::io;
+
+/// What `--help` prints, and what a bad command line is answered with
+pub const USAGE: &str = "\
+Usage: rust-redis-imitate [config.toml] [options]
+
+Options:
+  --port N             Port to listen on
+  --host H             Address to listen on
+  --snapshot PATH      Snapshot file to load at startup and save to
+  --daemon-log FILE    Write logs to FILE instead of stderr
+  --loglevel L         error, warn, info, debug or trace
+  -h, --help           Show this help and exit
+  -v, --version        Show the version and exit";
+
+/// What the command line asks the binary to do
+#[derive(Debug, PartialEq)]
+pub enum CliAction {
+   /// Start the server with these settings
+   Run(CliArgs),
+   /// Print `USAGE` and exit
+   Help,
+   /// Print the version and exit
+   Version,
+}
+
+/// The settings given on the command line; `None` leaves a setting to the
+/// config file or the defaults
+#[derive(Debug, Default, PartialEq)]
+pub struct CliArgs {
+   /// The TOML config file, the only positional argument
+   pub config_path: Option<String>,
+   /// `--port`
+   pub port: Option<u16>,
+   /// `--host`
+   pub host: Option<String>,
+   /// `--snapshot`
+   pub snapshot_path: Option<String>,
+   /// `--daemon-log`
+   pub log_file: Option<String>,
+   /// `--loglevel`
+   pub log_level: Option<String>,
+}
+
+impl CliArgs {
+   /// Parses the arguments after the program name
+   ///
+   /// Flags take their value as the next argument or after `=`, as in
+   /// `--port=7000`.
+   ///
+   /// # Returns
+   ///
+   /// * `Ok(CliAction)` - What to do
+   /// * `Err(String)` - Why the command line is invalid: an unknown flag, a
+   ///   missing or bad value, or a second config file
+   pub fn parse(args: impl IntoIterator<Item = String>) -> Result<CliAction, String> {
+       let mut parsed = CliArgs::default();
+       let mut args = args.into_iter();
+       while let Some(arg) = args.next() {
+           let (flag, inline_value) = match arg.split_once('=') {
+               Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+               _ => (arg.clone(), None),
+           };
+           let mut value = || {
+               inline_value.clone().or_else(|| args.next()).ok_or_else(|| format!("{} needs a value", flag))
+           };
+           match flag.as_str() {
+               "-h" | "--help" => return Ok(CliAction::Help),
+               "-v" | "--version" => return Ok(CliAction::Version),
+               "--port" => {
+                   let port = value()?;
+                   parsed.port = Some(port.parse().map_err(|_| format!("invalid port '{}'", port))?);
+               }
+               "--host" => parsed.host = Some(value()?),
+               "--snapshot" => parsed.snapshot_path = Some(value()?),
+               "--daemon-log" => parsed.log_file = Some(value()?),
+               "--loglevel" => {
+                   let level = value()?.to_lowercase();
+                   if !matches!(level.as_str(), "error" | "warn" | "info" | "debug" | "trace") {
+                       return Err(format!("invalid log level '{}'", level));
+                   }
+                   parsed.log_level = Some(level);
+               }
+               _ if flag.starts_with('-') => return Err(format!("unknown option '{}'", flag)),
+               _ if parsed.config_path.is_some() => return Err(format!("unexpected argument '{}'", arg)),
+               _ => parsed.config_path = Some(arg),
+           }
+       }
+       Ok(CliAction::Run(parsed))
+   }
+
+   /// Overrides the settings given on the command line in `config`
+   pub fn apply_to(&self, config: &mut Config) {
+       if let Some(port) = self.port {
+           config.port = port;
+       }
+       if let Some(host) = &self.host {
+           config.host = host.clone();
+       }
+       if let Some(snapshot_path) = &self.snapshot_path {
+           config.snapshot_path = snapshot_path.clone();
+       }
+       if let Some(log_file) = &self.log_file {
+           config.log_file = log_file.clone();
+       }
+       if let Some(log_level) = &self.log_level {
+           config.log_level = log_level.clone();
+       }
+   }
+
+   /// Builds the server's configuration: the config file if one was given,
+   /// or the defaults, with the command line's settings on top
+   pub fn config(&self) -> io::Result<Config> {
+       let mut config = match &self.config_path {
+           Some(path) => Config::from_file(path)?,
+           None => Config::new(),
+       };
+       self.apply_to(&mut config);
+       Ok(config)
+   }
+}
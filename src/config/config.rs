@@ -3,18 +3,38 @@
 //! Provides configuration settings for the Redis-like server, 
 //! with serialization support through serde.
 
+use crate::storage::encoding::EncodingLimits;
+use crate::storage::notify::NotifyFlags;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 /// Server configuration settings
 ///
 /// Holds all configurable parameters for the Redis-like server instance.
-/// Supports serialization and deserialization through serde.
+/// Supports serialization and deserialization through serde; fields missing
+/// from a config file take their default values.
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Config {
    /// Server host address
    /// Default: "0.0.0.0" (binds to all network interfaces)
    pub host: String,
 
+   /// Addresses to listen on, IPv4 or IPv6, e.g. ["127.0.0.1", "::1"]
+   /// The server accepts clients on every one of them; when empty it
+   /// listens on `host` alone
+   /// Default: empty (listen on `host`)
+   pub bind: Vec<String>,
+
+   /// Whether clients connecting from other hosts are turned away while no
+   /// ACL users are configured; loopback clients are always served
+   /// Default: true
+   pub protected_mode: bool,
+
    /// Server port number
    /// Default: 6379 (standard Redis port)
    pub port: u16,
@@ -23,9 +43,227 @@ pub struct Config {
    /// Default: 1000
    pub max_connections: usize,
 
-   /// Maximum memory usage in bytes
-   /// Default: 1GB (1024*1024*1024 bytes)
+   /// Estimated memory use in bytes past which writes evict keys or fail
+   /// Default: 0 (no limit)
    pub max_memory: usize,
+
+   /// How keys are chosen for eviction once `max_memory` is reached:
+   /// "noeviction", "allkeys-lru", "allkeys-lfu", "allkeys-random",
+   /// "volatile-lru", "volatile-lfu", "volatile-random" or "volatile-ttl"
+   /// Default: "noeviction" (writes fail with an OOM error)
+   pub maxmemory_policy: String,
+
+   /// Maximum number of commands a single connection may issue per second,
+   /// on average; also accepted as `max_commands_per_sec_per_client`
+   /// Default: 0 (unlimited)
+   #[serde(alias = "max_commands_per_sec_per_client")]
+   pub max_commands_per_second: u64,
+
+   /// Commands a connection may send at once before the rate limit applies
+   /// Default: 0 (one second's worth, i.e. `max_commands_per_second`)
+   pub rate_limit_burst: u64,
+
+   /// What happens to commands over the rate limit: "delay" waits to read
+   /// them, "reject" answers "ERR rate limit exceeded"
+   /// Default: "delay"
+   pub rate_limit_mode: String,
+
+   /// Path of the snapshot file loaded at startup and saved on shutdown
+   /// Default: "redis_data.snapshot"
+   pub snapshot_path: String,
+
+   /// Commands exposed under a different name, keyed by original name
+   /// An empty new name disables the command entirely
+   /// Default: empty (every command keeps its own name)
+   pub rename_command: HashMap<String, String>,
+
+   /// How often the raft write-ahead log is fsynced in the background, in milliseconds
+   /// Default: 1000
+   pub raft_wal_flush_interval_ms: u64,
+
+   /// How long a read from an idle client may block before it is disconnected, in milliseconds
+   /// Default: 0 (no timeout)
+   pub tcp_read_timeout_ms: u64,
+
+   /// How long a write to a client that stopped reading may block before it is disconnected, in milliseconds
+   /// Default: 0 (no timeout)
+   pub tcp_write_timeout_ms: u64,
+
+   /// How long a client may take to finish sending a command once it has begun, in milliseconds
+   /// Checked on every maintenance tick; a client idle between commands is never affected
+   /// Default: 0 (no limit)
+   pub client_query_timeout_ms: u64,
+
+   /// How long writing a reply to a client may stay blocked before it is disconnected, in milliseconds
+   /// Covers the whole reply, unlike `tcp_write_timeout_ms`, which a trickle of progress resets
+   /// Default: 0 (no limit)
+   pub client_output_timeout_ms: u64,
+
+   /// Disables Nagle's algorithm on client sockets
+   /// Default: true
+   pub tcp_nodelay: bool,
+
+   /// Idle time before TCP keepalive probes are sent, in seconds
+   /// Default: 300 (0 disables keepalive)
+   pub tcp_keepalive_secs: u64,
+
+   /// Maximum log verbosity: "error", "warn", "info", "debug" or "trace"
+   /// Default: "info"
+   pub log_level: String,
+
+   /// Log output format: "text" for human-readable lines or "json"
+   /// Default: "text"
+   pub log_format: String,
+
+   /// File log lines are appended to instead of stderr
+   /// Default: "" (log to stderr)
+   pub log_file: String,
+
+   /// Serves clients as tasks on a tokio runtime instead of one pooled thread each
+   /// Default: false
+   pub async_server: bool,
+
+   /// Listeners opened on every bind address with SO_REUSEPORT, each with its
+   /// own accept loop, for heavy connection churn; platforms without
+   /// SO_REUSEPORT fall back to a single listener
+   /// Default: 1 (one listener per address)
+   pub acceptor_threads: usize,
+
+   /// Most elements a list may hold while it keeps the compact listpack encoding
+   /// Default: 128
+   pub list_max_listpack_size: usize,
+
+   /// Longest element, in bytes, a list may hold while it keeps the listpack encoding
+   /// Default: 64
+   pub list_max_ziplist_value: usize,
+
+   /// Most fields a hash may hold while it keeps the compact listpack encoding
+   /// Default: 128
+   pub hash_max_listpack_entries: usize,
+
+   /// Longest field or value, in bytes, a hash may hold while it keeps the listpack encoding
+   /// Default: 64
+   pub hash_max_listpack_value: usize,
+
+   /// Most members a set of integers may hold while it keeps the intset encoding
+   /// Default: 512
+   pub set_max_intset_entries: usize,
+
+   /// Most members any other set may hold while it keeps the listpack encoding
+   /// Default: 128
+   pub set_max_listpack_entries: usize,
+
+   /// Longest member, in bytes, a set may hold while it keeps the listpack encoding
+   /// Default: 64
+   pub set_max_listpack_value: usize,
+
+   /// Most members a sorted set may hold while it keeps the listpack encoding
+   /// Default: 128
+   pub zset_max_listpack_entries: usize,
+
+   /// Longest member, in bytes, a sorted set may hold while it keeps the listpack encoding
+   /// Default: 64
+   pub zset_max_listpack_value: usize,
+
+   /// Longest inline command, in bytes, a client may send; longer input is
+   /// rejected with a protocol error and the connection is closed
+   /// Default: 65536 (64KB, Redis's PROTO_INLINE_MAX_SIZE)
+   pub proto_max_inline_size: usize,
+
+   /// Rules for the background snapshot: a save happens once any rule has at
+   /// least `dirty_threshold` writes and `seconds` since the last save; also
+   /// accepted as `save`, with rules written Redis-style as `[seconds, changes]`
+   /// pairs, e.g. `save = [[900, 1], [300, 100]]`
+   /// Default: 1 write in 900s, 10 writes in 300s, 10000 writes in 60s
+   #[serde(alias = "save")]
+   pub save_conditions: Vec<SaveCondition>,
+
+   /// Disables every automatic snapshot, whatever `save_conditions` says
+   /// Default: false
+   pub no_save: bool,
+
+   /// Milliseconds between two runs of the background maintenance tasks
+   /// (expiring keys, purging the cache, checking `save_conditions`)
+   /// Default: 100 (Redis's `hz 10`)
+   pub maintenance_tick_ms: u64,
+
+   /// Bytes of recent writes a master keeps so a replica that lost its
+   /// link can catch up with PSYNC instead of a full sync; 0 disables it
+   /// Default: 1048576 (1MB)
+   pub repl_backlog_size: usize,
+
+   /// Path BGREWRITEAOF writes the rewritten append-only file to
+   /// Default: "appendonly.aof"
+   pub appendfilename: String,
+
+   /// Records every successful write command in the audit log
+   /// Default: false
+   pub audit_log_enabled: bool,
+
+   /// Path of the audit log
+   /// Default: "audit.log"
+   pub audit_log_path: String,
+
+   /// Longest value, in characters, written to the audit log before it is
+   /// truncated; 0 leaves values out entirely
+   /// Default: 0 (values redacted)
+   pub audit_log_max_value_len: usize,
+
+   /// Size in bytes at which the audit log is moved to `<path>.1` and restarted
+   /// Default: 67108864 (64MB; 0 never rotates)
+   pub audit_log_max_size: u64,
+
+   /// Lines that may wait for the audit logger before new ones are dropped
+   /// Default: 10000
+   pub audit_log_queue_size: usize,
+
+   /// Runs the node in cluster mode, which keeps the per-slot key index
+   /// CLUSTER GETKEYSINSLOT reads
+   /// Default: false
+   pub cluster_enabled: bool,
+
+   /// Output buffer limit for ordinary clients: replies to pipelined
+   /// commands that pile up before they can be written
+   /// Default: no limit
+   pub client_output_buffer_limit_normal: OutputBufferLimit,
+
+   /// Output buffer limit for subscribed clients: published messages and
+   /// replies queued for a subscriber that isn't reading them
+   /// Default: 32MB hard, 8MB soft for 60 seconds
+   pub client_output_buffer_limit_pubsub: OutputBufferLimit,
+
+   /// Keyspace events published over Pub/Sub, as Redis's flag letters:
+   /// `K` and/or `E` for the channels, then classes such as `g`, `$`, `l`,
+   /// or `A` for all of them; e.g. "KEA"
+   /// Default: "" (no notifications)
+   pub notify_keyspace_events: NotifyFlags,
+
+   /// Users clients can log in as with AUTH, keyed by username
+   /// While this is empty every connection may run every command; once it
+   /// isn't, connections start as "default" if that user exists without a
+   /// password, and must AUTH otherwise
+   /// Default: empty (no access control)
+   pub acl: HashMap<String, AclUser>,
+
+   /// File this configuration was loaded from, target of CONFIG REWRITE
+   /// Default: None (built from defaults, not from a file)
+   #[serde(skip)]
+   pub config_file: Option<String>,
+
+   /// Top-level keys of the config file that no field takes, e.g. typos
+   /// They are ignored; the binary logs a warning for each
+   /// Default: empty
+   #[serde(skip)]
+   pub unknown_keys: Vec<String>,
+}
+
+/// Names fields are also accepted under, which a config file may use
+const KEY_ALIASES: &[&str] = &["max_commands_per_sec_per_client", "save"];
+
+impl Default for Config {
+   fn default() -> Self {
+       Self::new()
+   }
 }
 
 impl Config {
@@ -34,9 +272,54 @@ impl Config {
    /// # Default Values
    ///
    /// * host: "0.0.0.0" - Binds to all network interfaces
+   /// * bind: empty - Listens on `host` only
+   /// * protected_mode: true - Only loopback clients are served until ACL users are set up
    /// * port: 6379 - Standard Redis port
    /// * max_connections: 1000 - Maximum concurrent connections
-   /// * max_memory: 1GB - Maximum memory usage
+   /// * max_memory: 0 - No memory limit
+   /// * maxmemory_policy: "noeviction" - Writes over the limit fail
+   /// * max_commands_per_second: 0 - No per-connection rate limit
+   /// * rate_limit_burst: 0 - Bursts of up to one second's worth of commands
+   /// * rate_limit_mode: "delay" - Commands over the limit wait
+   /// * snapshot_path: "redis_data.snapshot" - Snapshot file location
+   /// * rename_command: empty - No commands renamed or disabled
+   /// * cluster_enabled: false - No per-slot key index
+   /// * client_output_buffer_limit_normal: none - Ordinary clients are never cut off
+   /// * client_output_buffer_limit_pubsub: 32MB / 8MB for 60s - Slow subscribers are disconnected
+   /// * notify_keyspace_events: "" - No keyspace notifications
+   /// * acl: empty - No users, every client may run every command
+   /// * raft_wal_flush_interval_ms: 1000 - Raft WAL fsync interval
+   /// * tcp_read_timeout_ms: 0 - Idle clients are never timed out
+   /// * tcp_write_timeout_ms: 0 - Writes never time out
+   /// * client_query_timeout_ms: 0 - Half-sent commands may take forever
+   /// * client_output_timeout_ms: 0 - Stuck reply writes are never cut off
+   /// * tcp_nodelay: true - Responses are sent without Nagle delays
+   /// * tcp_keepalive_secs: 300 - Keepalive probes after five idle minutes
+   /// * log_level: "info" - Log verbosity
+   /// * log_format: "text" - Human-readable log lines
+   /// * log_file: "" - Logs go to stderr
+   /// * async_server: false - One worker thread per client
+   /// * acceptor_threads: 1 - One listener and accept loop per address
+   /// * list_max_listpack_size: 128 - Elements per listpack-encoded list
+   /// * list_max_ziplist_value: 64 - Bytes per listpack-encoded list element
+   /// * hash_max_listpack_entries: 128 - Fields per listpack-encoded hash
+   /// * hash_max_listpack_value: 64 - Bytes per listpack-encoded hash field or value
+   /// * set_max_intset_entries: 512 - Members per intset-encoded set
+   /// * set_max_listpack_entries: 128 - Members per listpack-encoded set
+   /// * set_max_listpack_value: 64 - Bytes per listpack-encoded set member
+   /// * zset_max_listpack_entries: 128 - Members per listpack-encoded sorted set
+   /// * zset_max_listpack_value: 64 - Bytes per listpack-encoded sorted set member
+   /// * proto_max_inline_size: 64KB - Longest accepted inline command
+   /// * save_conditions: 900s/1, 300s/10, 60s/10000 - Redis's default save rules
+   /// * no_save: false - Automatic snapshots follow `save_conditions`
+   /// * maintenance_tick_ms: 100 - Background tasks run ten times a second
+   /// * repl_backlog_size: 1MB - Writes kept for replicas to resume from
+   /// * appendfilename: "appendonly.aof" - Append-only file location
+   /// * audit_log_enabled: false - No audit log is written
+   /// * audit_log_path: "audit.log" - Audit log location
+   /// * audit_log_max_value_len: 0 - Values are redacted from the audit log
+   /// * audit_log_max_size: 64MB - Audit log size before rotation
+   /// * audit_log_queue_size: 10000 - Audit lines buffered for the logger thread
    ///
    /// # Returns
    ///
@@ -44,9 +327,473 @@ impl Config {
    pub fn new() -> Self {
        Config {
            host: "0.0.0.0".to_string(),
+           bind: Vec::new(),
+           protected_mode: true,
            port: 6379,
            max_connections: 1000,
-           max_memory: 1024 * 1024 * 1024,  // 1GB
+           max_memory: 0,
+           maxmemory_policy: "noeviction".to_string(),
+           max_commands_per_second: 0,
+           rate_limit_burst: 0,
+           rate_limit_mode: "delay".to_string(),
+           snapshot_path: "redis_data.snapshot".to_string(),
+           rename_command: HashMap::new(),
+           raft_wal_flush_interval_ms: 1000,
+           tcp_read_timeout_ms: 0,
+           tcp_write_timeout_ms: 0,
+           client_query_timeout_ms: 0,
+           client_output_timeout_ms: 0,
+           tcp_nodelay: true,
+           tcp_keepalive_secs: 300,
+           log_level: "info".to_string(),
+           log_format: "text".to_string(),
+           log_file: String::new(),
+           async_server: false,
+           acceptor_threads: 1,
+           list_max_listpack_size: 128,
+           list_max_ziplist_value: 64,
+           hash_max_listpack_entries: 128,
+           hash_max_listpack_value: 64,
+           set_max_intset_entries: 512,
+           set_max_listpack_entries: 128,
+           set_max_listpack_value: 64,
+           zset_max_listpack_entries: 128,
+           zset_max_listpack_value: 64,
+           proto_max_inline_size: 64 * 1024,
+           save_conditions: vec![
+               SaveCondition { dirty_threshold: 1, seconds: 900 },
+               SaveCondition { dirty_threshold: 10, seconds: 300 },
+               SaveCondition { dirty_threshold: 10000, seconds: 60 },
+           ],
+           no_save: false,
+           maintenance_tick_ms: 100,
+           repl_backlog_size: 1024 * 1024,
+           appendfilename: "appendonly.aof".to_string(),
+           audit_log_enabled: false,
+           audit_log_path: "audit.log".to_string(),
+           audit_log_max_value_len: 0,
+           audit_log_max_size: 64 * 1024 * 1024,
+           audit_log_queue_size: 10000,
+           cluster_enabled: false,
+           client_output_buffer_limit_normal: OutputBufferLimit::default(),
+           client_output_buffer_limit_pubsub: OutputBufferLimit {
+               hard_limit_bytes: 32 * 1024 * 1024,
+               soft_limit_bytes: 8 * 1024 * 1024,
+               soft_limit_seconds: 60,
+           },
+           notify_keyspace_events: NotifyFlags::default(),
+           acl: HashMap::new(),
+           config_file: None,
+           unknown_keys: Vec::new(),
        }
    }
-}
\ No newline at end of file
+
+   /// Loads a configuration from a TOML file
+   ///
+   /// The path is remembered in `config_file` so CONFIG REWRITE can write
+   /// the running configuration back to the same file. Fields the file
+   /// leaves out take their default values, and keys no field takes are
+   /// collected in `unknown_keys` rather than failing the load.
+   ///
+   /// # Arguments
+   ///
+   /// * `path` - Path of the TOML file to read
+   ///
+   /// # Returns
+   ///
+   /// * `Ok(Config)` - The loaded configuration
+   /// * `Err(io::Error)` - The file can't be read, isn't valid TOML or has a
+   ///   value of the wrong type; the message names the file and, for TOML
+   ///   errors, the line and column
+   pub fn from_file(path: &str) -> io::Result<Config> {
+       let contents = fs::read_to_string(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+       let invalid = |e: toml::de::Error| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e));
+       let mut config: Config = toml::from_str(&contents).map_err(invalid)?;
+       let table: toml::value::Table = toml::from_str(&contents).map_err(invalid)?;
+       let known = match toml::Value::try_from(Config::new()) {
+           Ok(toml::Value::Table(known)) => known,
+           _ => unreachable!("Config always serializes to a table"),
+       };
+       config.unknown_keys = table
+           .keys()
+           .filter(|key| !known.contains_key(*key) && !KEY_ALIASES.contains(&key.as_str()))
+           .cloned()
+           .collect();
+       config.config_file = Some(path.to_string());
+       Ok(config)
+   }
+
+   /// Builds a configuration from `REDIS_<FIELD>` environment variables
+   ///
+   /// Each field is read from the variable named after it in upper case,
+   /// e.g. `REDIS_PORT=7000` or `REDIS_NO_SAVE=true`; unset fields keep
+   /// their default values. Values are TOML, so structured fields are
+   /// written inline, e.g.
+   /// `REDIS_SAVE_CONDITIONS='[{ dirty_threshold = 1, seconds = 60 }]'`,
+   /// and anything that isn't valid TOML is taken as a plain string.
+   pub fn from_env() -> io::Result<Config> {
+       let mut table = match toml::Value::try_from(Config::new()) {
+           Ok(toml::Value::Table(table)) => table,
+           Ok(_) => unreachable!("Config always serializes to a table"),
+           Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+       };
+       for (key, value) in table.iter_mut() {
+           if let Ok(raw) = std::env::var(format!("REDIS_{}", key.to_uppercase())) {
+               *value = toml::from_str::<toml::value::Table>(&format!("value = {}", raw))
+                   .ok()
+                   .and_then(|mut parsed| parsed.remove("value"))
+                   .unwrap_or(toml::Value::String(raw));
+           }
+       }
+       toml::Value::Table(table)
+           .try_into()
+           .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+   }
+
+   /// Returns the addresses the server listens on: `bind`, or `host` if that's empty
+   pub fn bind_addresses(&self) -> Vec<&str> {
+       if self.bind.is_empty() {
+           vec![self.host.as_str()]
+       } else {
+           self.bind.iter().map(String::as_str).collect()
+       }
+   }
+
+   /// Returns the limits under which collections keep their compact encodings
+   pub fn encoding_limits(&self) -> EncodingLimits {
+       EncodingLimits {
+           list_max_listpack_size: self.list_max_listpack_size,
+           list_max_listpack_value: self.list_max_ziplist_value,
+           hash_max_listpack_entries: self.hash_max_listpack_entries,
+           hash_max_listpack_value: self.hash_max_listpack_value,
+           set_max_intset_entries: self.set_max_intset_entries,
+           set_max_listpack_entries: self.set_max_listpack_entries,
+           set_max_listpack_value: self.set_max_listpack_value,
+           zset_max_listpack_entries: self.zset_max_listpack_entries,
+           zset_max_listpack_value: self.zset_max_listpack_value,
+       }
+   }
+
+   /// Lists the configuration as CONFIG GET reports it, sorted by name
+   ///
+   /// Names are the field names with dashes for underscores, e.g.
+   /// `max-memory`. Save rules read as `<seconds> <changes>` pairs, lists
+   /// as space-separated words and tables as inline TOML. The ACL users are
+   /// left out, since they hold passwords.
+   pub fn parameters(&self) -> Vec<(String, String)> {
+       let table = match toml::Value::try_from(self) {
+           Ok(toml::Value::Table(table)) => table,
+           _ => return Vec::new(),
+       };
+       let mut parameters: Vec<(String, String)> = table
+           .into_iter()
+           .filter(|(name, _)| name != "acl")
+           .map(|(name, value)| {
+               let value = match (name.as_str(), value) {
+                   ("save_conditions", _) => format_save_conditions(&self.save_conditions),
+                   (_, toml::Value::String(value)) => value,
+                   (_, toml::Value::Array(values)) if values.iter().all(toml::Value::is_str) => {
+                       values.iter().filter_map(toml::Value::as_str).collect::<Vec<_>>().join(" ")
+                   }
+                   (_, value) => value.to_string(),
+               };
+               (name.replace('_', "-"), value)
+           })
+           .collect();
+       parameters.sort();
+       parameters
+   }
+
+   /// Whether the background snapshot is due
+   ///
+   /// # Arguments
+   ///
+   /// * `dirty` - Writes since the last save
+   /// * `since_last_save` - Time since the last save
+   pub fn save_due(&self, dirty: u64, since_last_save: Duration) -> bool {
+       !self.no_save && self.save_conditions.iter().any(|condition| condition.is_met(dirty, since_last_save))
+   }
+
+   /// Writes this configuration to `path` without ever leaving a half-written file
+   ///
+   /// The new contents go to `{path}.tmp`, which is then renamed over `path`.
+   /// If `path` already exists it is rewritten line by line: comments,
+   /// ordering and unknown keys are kept, known keys get their current
+   /// values, and keys missing from the file are added.
+   ///
+   /// # Arguments
+   ///
+   /// * `path` - Destination file
+   pub fn save_to_file_atomic(&self, path: &str) -> io::Result<()> {
+       let current = match toml::Value::try_from(self) {
+           Ok(toml::Value::Table(table)) => table,
+           Ok(_) => unreachable!("Config always serializes to a table"),
+           Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+       };
+       let original = match fs::read_to_string(path) {
+           Ok(contents) => contents,
+           Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+           Err(e) => return Err(e),
+       };
+
+       let temp_path = format!("{}.tmp", path);
+       fs::write(&temp_path, rewrite_toml(&original, &current))?;
+       fs::rename(&temp_path, Path::new(path))
+   }
+}
+
+/// One automatic snapshot rule, Redis's `save <seconds> <changes>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "SaveConditionRule")]
+pub struct SaveCondition {
+   /// Writes needed since the last save
+   pub dirty_threshold: u32,
+   /// Seconds needed since the last save
+   pub seconds: u32,
+}
+
+/// How a save rule may be written in a config file
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SaveConditionRule {
+   /// `[seconds, changes]`, as in Redis's `save` line
+   Pair(u32, u32),
+   Table { dirty_threshold: u32, seconds: u32 },
+}
+
+impl From<SaveConditionRule> for SaveCondition {
+   fn from(rule: SaveConditionRule) -> Self {
+       match rule {
+           SaveConditionRule::Pair(seconds, dirty_threshold) => SaveCondition { dirty_threshold, seconds },
+           SaveConditionRule::Table { dirty_threshold, seconds } => SaveCondition { dirty_threshold, seconds },
+       }
+   }
+}
+
+impl SaveCondition {
+   /// Whether both the write count and the elapsed time have been reached
+   pub fn is_met(&self, dirty: u64, since_last_save: Duration) -> bool {
+       dirty >= u64::from(self.dirty_threshold) && since_last_save >= Duration::from_secs(u64::from(self.seconds))
+   }
+}
+
+/// Formats save rules as Redis's `save` does: `<seconds> <changes>` pairs
+pub fn format_save_conditions(conditions: &[SaveCondition]) -> String {
+   conditions
+       .iter()
+       .map(|condition| format!("{} {}", condition.seconds, condition.dirty_threshold))
+       .collect::<Vec<_>>()
+       .join(" ")
+}
+
+/// Parses save rules written as `format_save_conditions` writes them
+///
+/// # Returns
+///
+/// The rules, none for an empty string, or `None` if a number is missing
+/// or invalid
+pub fn parse_save_conditions(value: &str) -> Option<Vec<SaveCondition>> {
+   let numbers: Vec<u32> = value.split_whitespace().map(str::parse).collect::<Result<_, _>>().ok()?;
+   if !numbers.len().is_multiple_of(2) {
+       return None;
+   }
+   Some(
+       numbers
+           .chunks(2)
+           .map(|pair| SaveCondition { seconds: pair[0], dirty_threshold: pair[1] })
+           .collect(),
+   )
+}
+
+/// How many bytes may wait to be written to one client, Redis's `client-output-buffer-limit`
+///
+/// A client is disconnected as soon as its backlog passes the hard limit,
+/// or once it has stayed above the soft limit for `soft_limit_seconds`.
+/// A limit of 0 is disabled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct OutputBufferLimit {
+   /// Backlog that disconnects the client at once
+   pub hard_limit_bytes: usize,
+   /// Backlog the client may only keep for `soft_limit_seconds`
+   pub soft_limit_bytes: usize,
+   /// How long the backlog may stay above `soft_limit_bytes`
+   pub soft_limit_seconds: u64,
+}
+
+impl OutputBufferLimit {
+   /// Whether `pending` bytes break the limit
+   ///
+   /// # Arguments
+   ///
+   /// * `pending` - Bytes waiting to be written
+   /// * `over_soft_since` - When the backlog went over the soft limit; kept
+   ///   up to date here, so pass the same one on every call for a client
+   pub fn is_exceeded(&self, pending: usize, over_soft_since: &mut Option<Instant>) -> bool {
+       if self.hard_limit_bytes > 0 && pending > self.hard_limit_bytes {
+           return true;
+       }
+       if self.soft_limit_bytes == 0 || pending <= self.soft_limit_bytes {
+           *over_soft_since = None;
+           return false;
+       }
+       let since = *over_soft_since.get_or_insert_with(Instant::now);
+       since.elapsed() >= Duration::from_secs(self.soft_limit_seconds)
+   }
+}
+
+/// One ACL user, a `[acl.<name>]` section of the config file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AclUser {
+   /// Password AUTH must present; empty accepts any password, like Redis's `nopass`
+   pub password: String,
+   /// Rules applied in order, the last matching one winning: `+@<category>`
+   /// and `-@<category>` (read, write, admin, pubsub, dangerous or all),
+   /// `+<command>` and `-<command>`, `allcommands` and `nocommands`
+   pub permissions: Vec<String>,
+}
+
+/// Rewrites a TOML document so its keys carry the values in `current`
+///
+/// Top-level keys live before the first `[section]`; each table-valued field
+/// (such as `rename_command`) is its own section. Lines that aren't known
+/// keys are copied unchanged, keys that no longer exist inside a known
+/// section are dropped, and missing keys are added where TOML requires them.
+fn rewrite_toml(original: &str, current: &toml::value::Table) -> String {
+   let mut output: Vec<String> = Vec::new();
+   let mut written: HashSet<String> = HashSet::new();
+   let mut written_in_section: HashSet<String> = HashSet::new();
+   let mut section: Option<String> = None;
+   let mut top_level_done = false;
+   // Inside an array of tables we write inline as a top-level key instead
+   let mut skipping = false;
+
+   // Appends the keys of `section` that the original file didn't mention
+   let finish_section = |output: &mut Vec<String>, section: &Option<String>, seen: &HashSet<String>| {
+       if let Some(toml::Value::Table(table)) = section.as_ref().and_then(|name| current.get(name)) {
+           for (key, value) in table {
+               if !seen.contains(key) {
+                   output.push(format!("{} = {}", toml_key(key), toml_inline(value)));
+               }
+           }
+       }
+   };
+   // Appends top-level scalars the original file didn't mention
+   let finish_top_level = |output: &mut Vec<String>, written: &HashSet<String>| {
+       for (key, value) in current {
+           if !value.is_table() && !written.contains(key) {
+               output.push(format!("{} = {}", toml_key(key), toml_inline(value)));
+           }
+       }
+   };
+
+   for line in original.lines() {
+       let trimmed = line.trim();
+       if trimmed.starts_with('[') {
+           if !top_level_done {
+               finish_top_level(&mut output, &written);
+               top_level_done = true;
+           }
+           finish_section(&mut output, &section, &written_in_section);
+           written_in_section.clear();
+
+           let name = trimmed.trim_matches(|c| c == '[' || c == ']').trim().trim_matches('"').to_string();
+           skipping = trimmed.starts_with("[[") && current.get(&name).is_some_and(|value| !value.is_table());
+           if skipping {
+               section = None;
+               continue;
+           }
+           // `[acl.reader]` belongs to `acl`, which mustn't be written again at the end
+           if let Some((parent, _)) = name.split_once('.') {
+               written.insert(parent.to_string());
+           }
+           written.insert(name.clone());
+           section = Some(name);
+           output.push(line.to_string());
+           continue;
+       }
+       if skipping {
+           continue;
+       }
+
+       let key = match trimmed.split_once('=') {
+           Some((key, _)) if !trimmed.starts_with('#') => key.trim().trim_matches('"').to_string(),
+           _ => {
+               output.push(line.to_string());
+               continue;
+           }
+       };
+
+       let replacement = match &section {
+           None => current.get(&key).filter(|value| !value.is_table()).map(Some),
+           Some(name) => match current.get(name) {
+               Some(toml::Value::Table(table)) => Some(table.get(&key)),
+               _ => None,
+           },
+       };
+       match replacement {
+           // Not a key we know about: keep it verbatim
+           None => output.push(line.to_string()),
+           // A known section whose key was removed at runtime
+           Some(None) => {}
+           Some(Some(value)) => {
+               output.push(format!("{} = {}", toml_key(&key), toml_inline(value)));
+               if section.is_some() {
+                   written_in_section.insert(key);
+               } else {
+                   written.insert(key);
+               }
+           }
+       }
+   }
+
+   if top_level_done {
+       finish_section(&mut output, &section, &written_in_section);
+   } else {
+       finish_top_level(&mut output, &written);
+   }
+   for (name, value) in current {
+       if let toml::Value::Table(table) = value {
+           if !written.contains(name) {
+               output.push(String::new());
+               output.push(format!("[{}]", toml_key(name)));
+               for (key, value) in table {
+                   output.push(format!("{} = {}", toml_key(key), toml_inline(value)));
+               }
+           }
+       }
+   }
+
+   let mut rewritten = output.join("\n");
+   rewritten.push('\n');
+   rewritten
+}
+
+/// Renders a value on one line, writing any tables inside it (such as the
+/// entries of `save_conditions`) as inline tables
+fn toml_inline(value: &toml::Value) -> String {
+   match value {
+       toml::Value::Array(items) => {
+           let items: Vec<String> = items.iter().map(toml_inline).collect();
+           format!("[{}]", items.join(", "))
+       }
+       toml::Value::Table(table) => {
+           let entries: Vec<String> = table
+               .iter()
+               .map(|(key, value)| format!("{} = {}", toml_key(key), toml_inline(value)))
+               .collect();
+           format!("{{ {} }}", entries.join(", "))
+       }
+       _ => value.to_string(),
+   }
+}
+
+/// Quotes a TOML key unless it is a valid bare key
+fn toml_key(key: &str) -> String {
+   if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+       key.to_string()
+   } else {
+       toml::Value::String(key.to_string()).to_string()
+   }
+}
@@ -0,0 +1,251 @@
+//! # Runtime Config Module
+//!
+//! The settings CONFIG SET may change while the server runs. They start out
+//! as the startup `Config`'s values and are read from here, not from the
+//! `Config`, wherever they are used; everything else stays fixed until a
+//! restart.
+use crate::config::config::{parse_save_conditions, Config, SaveCondition};
+use crate::storage::encoding::EncodingLimits;
+use crate::storage::eviction::MaxMemoryPolicy;
+use crate::storage::notify::NotifyFlags;
+use crate::storage::glob::GlobPattern;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Parameters CONFIG SET accepts, by their CONFIG GET names
+pub const MUTABLE_PARAMETERS: &[&str] = &[
+   "hash-max-listpack-entries",
+   "hash-max-listpack-value",
+   "list-max-listpack-size",
+   "list-max-ziplist-value",
+   "max-memory",
+   "maxmemory-policy",
+   "no-save",
+   "notify-keyspace-events",
+   "protected-mode",
+   "save-conditions",
+   "set-max-intset-entries",
+   "set-max-listpack-entries",
+   "set-max-listpack-value",
+   "tcp-read-timeout-ms",
+   "zset-max-listpack-entries",
+   "zset-max-listpack-value",
+];
+
+/// The live values of the settings CONFIG SET may change
+pub struct RuntimeConfig {
+   max_memory: AtomicUsize,
+   maxmemory_policy: RwLock<MaxMemoryPolicy>,
+   tcp_read_timeout_ms: AtomicU64,
+   save_conditions: RwLock<Vec<SaveCondition>>,
+   no_save: AtomicBool,
+   protected_mode: AtomicBool,
+   encoding_limits: RwLock<EncodingLimits>,
+   notify_keyspace_events: RwLock<NotifyFlags>,
+}
+
+impl RuntimeConfig {
+   /// Starts from the startup configuration's values
+   ///
+   /// An unknown `maxmemory_policy` is logged and taken as "noeviction".
+   pub fn new(config: &Config) -> Self {
+       let policy = MaxMemoryPolicy::parse(&config.maxmemory_policy).unwrap_or_else(|| {
+           tracing::warn!(policy = %config.maxmemory_policy, "unknown maxmemory policy, using noeviction");
+           MaxMemoryPolicy::NoEviction
+       });
+       RuntimeConfig {
+           max_memory: AtomicUsize::new(config.max_memory),
+           maxmemory_policy: RwLock::new(policy),
+           tcp_read_timeout_ms: AtomicU64::new(config.tcp_read_timeout_ms),
+           save_conditions: RwLock::new(config.save_conditions.clone()),
+           no_save: AtomicBool::new(config.no_save),
+           protected_mode: AtomicBool::new(config.protected_mode),
+           encoding_limits: RwLock::new(config.encoding_limits()),
+           notify_keyspace_events: RwLock::new(config.notify_keyspace_events),
+       }
+   }
+
+   /// Returns the memory limit in bytes, 0 for none
+   pub fn max_memory(&self) -> usize {
+       self.max_memory.load(Ordering::Relaxed)
+   }
+
+   /// Returns how keys are evicted once the memory limit is reached
+   pub fn maxmemory_policy(&self) -> MaxMemoryPolicy {
+       *self.maxmemory_policy.read().unwrap()
+   }
+
+   /// Returns the idle time after which newly accepted clients are closed, 0 for none
+   pub fn tcp_read_timeout_ms(&self) -> u64 {
+       self.tcp_read_timeout_ms.load(Ordering::Relaxed)
+   }
+
+   /// Whether clients from other hosts are turned away (when no ACL users exist)
+   pub fn protected_mode(&self) -> bool {
+       self.protected_mode.load(Ordering::Relaxed)
+   }
+
+   /// Returns the limits under which collections keep their compact encodings
+   pub fn encoding_limits(&self) -> EncodingLimits {
+       *self.encoding_limits.read().unwrap()
+   }
+
+   /// Returns which keyspace events are published
+   pub fn notify_keyspace_events(&self) -> NotifyFlags {
+       *self.notify_keyspace_events.read().unwrap()
+   }
+
+   /// Whether the background snapshot is due, by the current save rules
+   ///
+   /// # Arguments
+   ///
+   /// * `dirty` - Writes since the last save
+   /// * `since_last_save` - Time since the last save
+   pub fn save_due(&self, dirty: u64, since_last_save: Duration) -> bool {
+       !self.no_save.load(Ordering::Relaxed)
+           && self.save_conditions.read().unwrap().iter().any(|condition| condition.is_met(dirty, since_last_save))
+   }
+
+   /// Copies the live values into `config`, e.g. for CONFIG REWRITE
+   pub fn apply_to(&self, config: &mut Config) {
+       config.max_memory = self.max_memory();
+       config.maxmemory_policy = self.maxmemory_policy().as_str().to_string();
+       config.tcp_read_timeout_ms = self.tcp_read_timeout_ms();
+       config.save_conditions = self.save_conditions.read().unwrap().clone();
+       config.no_save = self.no_save.load(Ordering::Relaxed);
+       config.protected_mode = self.protected_mode();
+       let limits = self.encoding_limits();
+       config.list_max_listpack_size = limits.list_max_listpack_size;
+       config.list_max_ziplist_value = limits.list_max_listpack_value;
+       config.hash_max_listpack_entries = limits.hash_max_listpack_entries;
+       config.hash_max_listpack_value = limits.hash_max_listpack_value;
+       config.set_max_intset_entries = limits.set_max_intset_entries;
+       config.set_max_listpack_entries = limits.set_max_listpack_entries;
+       config.set_max_listpack_value = limits.set_max_listpack_value;
+       config.zset_max_listpack_entries = limits.zset_max_listpack_entries;
+       config.zset_max_listpack_value = limits.zset_max_listpack_value;
+       config.notify_keyspace_events = self.notify_keyspace_events();
+   }
+
+   /// Lists the parameters whose names match `pattern`, with their live values
+   ///
+   /// # Arguments
+   ///
+   /// * `config` - The startup configuration, for the fixed settings
+   /// * `pattern` - Glob over the parameter names, case-insensitive
+   pub fn get(&self, config: &Config, pattern: &str) -> Vec<(String, String)> {
+       let mut live = config.clone();
+       self.apply_to(&mut live);
+       let pattern = GlobPattern::new(&pattern.to_lowercase());
+       live.parameters().into_iter().filter(|(name, _)| pattern.matches(name)).collect()
+   }
+
+   /// Changes one setting (CONFIG SET)
+   ///
+   /// # Arguments
+   ///
+   /// * `config` - The startup configuration, to tell fixed settings from unknown ones
+   /// * `name` - A CONFIG GET name; underscores may stand for dashes
+   /// * `value` - The new value: a byte count with an optional k/kb/m/mb/g/gb
+   ///   suffix for `max-memory`, a policy name for `maxmemory-policy`,
+   ///   yes/no for `no-save` and `protected-mode`, `<seconds> <changes>` pairs for `save-conditions`,
+   ///   flag letters such as `KEA` for `notify-keyspace-events`, and a count for the others
+   ///
+   /// # Returns
+   ///
+   /// * `Ok(())` - The new value is in effect
+   /// * `Err(String)` - The parameter is unknown or fixed, or the value is invalid
+   pub fn set(&self, config: &Config, name: &str, value: &str) -> Result<(), String> {
+       let name = name.to_lowercase().replace('_', "-");
+       let invalid = |reason: &str| format!("ERR CONFIG SET failed (possibly related to argument '{}') - {}", name, reason);
+       match name.as_str() {
+           "max-memory" => {
+               let bytes = parse_memory(value).ok_or_else(|| invalid("argument must be a memory value"))?;
+               self.max_memory.store(bytes, Ordering::Relaxed);
+           }
+           "maxmemory-policy" => {
+               let policy = MaxMemoryPolicy::parse(value).ok_or_else(|| invalid("argument(s) must be one of the following"))?;
+               *self.maxmemory_policy.write().unwrap() = policy;
+           }
+           "tcp-read-timeout-ms" => {
+               let ms = value.parse().map_err(|_| invalid("argument couldn't be parsed into an integer"))?;
+               self.tcp_read_timeout_ms.store(ms, Ordering::Relaxed);
+           }
+           "save-conditions" => {
+               let conditions = parse_save_conditions(value).ok_or_else(|| invalid("Invalid save parameters"))?;
+               *self.save_conditions.write().unwrap() = conditions;
+           }
+           "notify-keyspace-events" => {
+               let flags = NotifyFlags::parse(value).map_err(|letter| {
+                   format!("ERR Invalid argument '{}' for CONFIG SET 'notify-keyspace-events'", letter)
+               })?;
+               *self.notify_keyspace_events.write().unwrap() = flags;
+           }
+           "no-save" => {
+               let no_save = parse_yes_no(value).ok_or_else(|| invalid("argument must be 'yes' or 'no'"))?;
+               self.no_save.store(no_save, Ordering::Relaxed);
+           }
+           "protected-mode" => {
+               let protected = parse_yes_no(value).ok_or_else(|| invalid("argument must be 'yes' or 'no'"))?;
+               self.protected_mode.store(protected, Ordering::Relaxed);
+           }
+           "list-max-listpack-size"
+           | "list-max-ziplist-value"
+           | "hash-max-listpack-entries"
+           | "hash-max-listpack-value"
+           | "set-max-intset-entries"
+           | "set-max-listpack-entries"
+           | "set-max-listpack-value"
+           | "zset-max-listpack-entries"
+           | "zset-max-listpack-value" => {
+               let limit = value.parse().map_err(|_| invalid("argument couldn't be parsed into an integer"))?;
+               let mut limits = self.encoding_limits.write().unwrap();
+               let field = match name.as_str() {
+                   "list-max-listpack-size" => &mut limits.list_max_listpack_size,
+                   "list-max-ziplist-value" => &mut limits.list_max_listpack_value,
+                   "hash-max-listpack-entries" => &mut limits.hash_max_listpack_entries,
+                   "hash-max-listpack-value" => &mut limits.hash_max_listpack_value,
+                   "set-max-intset-entries" => &mut limits.set_max_intset_entries,
+                   "set-max-listpack-entries" => &mut limits.set_max_listpack_entries,
+                   "set-max-listpack-value" => &mut limits.set_max_listpack_value,
+                   "zset-max-listpack-entries" => &mut limits.zset_max_listpack_entries,
+                   _ => &mut limits.zset_max_listpack_value,
+               };
+               *field = limit;
+           }
+           _ if config.parameters().iter().any(|(known, _)| *known == name) => {
+               return Err(invalid("can't set immutable config"));
+           }
+           _ => return Err(format!("ERR Unknown option or number of arguments for CONFIG SET - '{}'", name)),
+       }
+       Ok(())
+   }
+}
+
+/// Parses a CONFIG SET boolean: yes/no, or true/false
+fn parse_yes_no(value: &str) -> Option<bool> {
+   match value.to_lowercase().as_str() {
+       "yes" | "true" => Some(true),
+       "no" | "false" => Some(false),
+       _ => None,
+   }
+}
+
+/// Parses a memory size: bytes, or a number with a k/m/g (powers of 1000)
+/// or kb/mb/gb (powers of 1024) suffix, case-insensitive
+pub fn parse_memory(value: &str) -> Option<usize> {
+   let value = value.to_lowercase();
+   let digits = value.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+   let unit = match &value[digits.len()..] {
+       "" | "b" => 1,
+       "k" => 1000,
+       "kb" => 1024,
+       "m" => 1000 * 1000,
+       "mb" => 1024 * 1024,
+       "g" => 1000 * 1000 * 1000,
+       "gb" => 1024 * 1024 * 1024,
+       _ => return None,
+   };
+   digits.parse::<usize>().ok()?.checked_mul(unit)
+}
@@ -0,0 +1,133 @@
+//! # Validation Module
+//!
+//! Checks a configuration before the server starts, so a nonsense setting
+//! stops startup with a message naming it instead of failing later, or
+//! cryptically, once the server is running.
+use crate::config::config::Config;
+use crate::storage::eviction::MaxMemoryPolicy;
+use crate::storage::memory::{CACHE_CAPACITY, CACHE_MEMORY};
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+
+/// Whether a `ConfigProblem` stops the server from starting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+   /// The server refuses to start
+   Error,
+   /// The server starts, but probably not as intended
+   Warning,
+}
+
+/// One thing `Config::validate` found wrong with a setting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+   pub severity: Severity,
+   /// The offending field, as named in the config file
+   pub field: &'static str,
+   /// What is wrong, starting with `field = value`
+   pub message: String,
+}
+
+impl ConfigProblem {
+   fn error(field: &'static str, value: impl fmt::Debug, reason: impl fmt::Display) -> Self {
+       ConfigProblem { severity: Severity::Error, field, message: format!("{} = {:?}: {}", field, value, reason) }
+   }
+
+   fn warning(field: &'static str, value: impl fmt::Debug, reason: impl fmt::Display) -> Self {
+       ConfigProblem { severity: Severity::Warning, field, message: format!("{} = {:?}: {}", field, value, reason) }
+   }
+
+   /// Whether the server must not start with this problem
+   pub fn is_error(&self) -> bool {
+       self.severity == Severity::Error
+   }
+}
+
+impl fmt::Display for ConfigProblem {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+       f.write_str(&self.message)
+   }
+}
+
+impl Config {
+   /// Checks the settings for invalid ranges, conflicting options and
+   /// persistence files that can't be written
+   ///
+   /// The snapshot's directory, and the audit log's if it's enabled, are
+   /// checked by creating and removing a probe file next to the target.
+   ///
+   /// # Returns
+   ///
+   /// Every problem found, errors and warnings, in field order; empty if
+   /// the configuration is fine
+   pub fn validate(&self) -> Vec<ConfigProblem> {
+       let mut problems = Vec::new();
+       if self.port == 0 {
+           problems.push(ConfigProblem::warning("port", self.port, "the OS will pick a random port"));
+       }
+       if self.max_connections == 0 {
+           problems.push(ConfigProblem::error("max_connections", self.max_connections, "every client would be turned away"));
+       }
+       if self.max_memory > 0 && self.max_memory < CACHE_MEMORY {
+           let reason = format!("less than the {} bytes the {}-entry read cache alone may take", CACHE_MEMORY, CACHE_CAPACITY);
+           problems.push(ConfigProblem::error("max_memory", self.max_memory, reason));
+       }
+       if MaxMemoryPolicy::parse(&self.maxmemory_policy).is_none() {
+           problems.push(ConfigProblem::error("maxmemory_policy", &self.maxmemory_policy, "not an eviction policy"));
+       }
+       if self.rate_limit_burst > 0 && self.max_commands_per_second == 0 {
+           problems.push(ConfigProblem::warning(
+               "rate_limit_burst",
+               self.rate_limit_burst,
+               "has no effect while max_commands_per_second is 0",
+           ));
+       }
+       if !matches!(self.rate_limit_mode.as_str(), "delay" | "reject") {
+           problems.push(ConfigProblem::warning("rate_limit_mode", &self.rate_limit_mode, "neither \"delay\" nor \"reject\", taken as \"delay\""));
+       }
+       if let Err(e) = probe(&self.snapshot_path) {
+           problems.push(ConfigProblem::error("snapshot_path", &self.snapshot_path, format!("can't be written: {}", e)));
+       }
+       if !matches!(self.log_level.to_lowercase().as_str(), "off" | "error" | "warn" | "info" | "debug" | "trace") {
+           problems.push(ConfigProblem::warning("log_level", &self.log_level, "not a log level, taken as \"info\""));
+       }
+       if !matches!(self.log_format.as_str(), "text" | "json") {
+           problems.push(ConfigProblem::warning("log_format", &self.log_format, "neither \"text\" nor \"json\", taken as \"text\""));
+       }
+       if self.acceptor_threads == 0 {
+           problems.push(ConfigProblem::warning("acceptor_threads", self.acceptor_threads, "taken as 1"));
+       }
+       if self.proto_max_inline_size == 0 {
+           problems.push(ConfigProblem::error("proto_max_inline_size", self.proto_max_inline_size, "every command would be too long"));
+       }
+       if self.maintenance_tick_ms == 0 {
+           problems.push(ConfigProblem::warning("maintenance_tick_ms", self.maintenance_tick_ms, "taken as 1"));
+       }
+       if self.audit_log_enabled {
+           if let Err(e) = probe(&self.audit_log_path) {
+               problems.push(ConfigProblem::error("audit_log_path", &self.audit_log_path, format!("can't be written: {}", e)));
+           }
+       }
+       for (field, limit) in [
+           ("client_output_buffer_limit_normal", &self.client_output_buffer_limit_normal),
+           ("client_output_buffer_limit_pubsub", &self.client_output_buffer_limit_pubsub),
+       ] {
+           if limit.hard_limit_bytes > 0 && limit.soft_limit_bytes > limit.hard_limit_bytes {
+               problems.push(ConfigProblem::error(field, limit, "soft_limit_bytes is above hard_limit_bytes"));
+           }
+       }
+       problems
+   }
+}
+
+/// Checks that a file can be created next to `path`, by creating and
+/// removing `{path}.probe`
+fn probe(path: &str) -> io::Result<()> {
+   if path.is_empty() {
+       return Err(io::Error::new(io::ErrorKind::InvalidInput, "the path is empty"));
+   }
+   let probe = format!("{}.probe", path);
+   File::create(&probe)?;
+   fs::remove_file(&probe)
+}
@@ -3,4 +3,5 @@ pub mod commands;
 pub mod cache;
 pub mod config;
 pub mod network;
-pub mod cluster;
\ No newline at end of file
+pub mod cluster;
+pub mod logging;
@@ -0,0 +1,222 @@
+//! # Logging Module
+//!
+//! Initialises the global `tracing` subscriber used by the server.
+//! Events are written to stderr or a log file, either as human-readable
+//! lines or as one JSON object per line for log aggregation.
+//!
+//! Levels follow one convention throughout the server: `error` and `warn`
+//! for failures, `info` for lifecycle events (startup, connections,
+//! replication links, persistence), `debug` for per-command traces and
+//! `trace` for anything carrying key values or replies.
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Environment variable that overrides the configured log level
+pub const LOG_LEVEL_ENV: &str = "RUST_LOG";
+
+/// Installs the global tracing subscriber
+///
+/// # Arguments
+///
+/// * `level` - Maximum verbosity: "error", "warn", "info", "debug", "trace" or "off".
+///   `RUST_LOG` takes precedence when it holds one of these.
+/// * `format` - "json" for one JSON object per event, anything else for plain text
+/// * `file` - Path log lines are appended to, or "" for stderr
+///
+/// Calling this more than once is harmless; only the first call takes effect.
+///
+/// # Errors
+///
+/// Returns an error if the log file can't be opened
+pub fn init_logging(level: &str, format: &str, file: &str) -> io::Result<()> {
+    let level = log_level(level, std::env::var(LOG_LEVEL_ENV).ok().as_deref());
+    // A subscriber is already installed (e.g. by a test); keep using it
+    let _ = tracing::subscriber::set_global_default(subscriber(level, format, file)?);
+    Ok(())
+}
+
+/// Picks the level to log at
+///
+/// # Arguments
+///
+/// * `configured` - The `log_level` setting
+/// * `env` - The value of `RUST_LOG`, if set
+///
+/// # Returns
+///
+/// The environment's level if it names one, else the configured level,
+/// falling back to "info" when neither is recognised
+pub fn log_level(configured: &str, env: Option<&str>) -> LevelFilter {
+    env.and_then(|env| LevelFilter::from_str(env.trim()).ok())
+        .or_else(|| LevelFilter::from_str(configured).ok())
+        .unwrap_or(LevelFilter::INFO)
+}
+
+/// Builds a subscriber logging up to `level` in `format` to `file`
+///
+/// # Arguments
+///
+/// * `level` - Maximum verbosity
+/// * `format` - "json" for one JSON object per event, anything else for plain text
+/// * `file` - Path log lines are appended to, or "" for stderr
+///
+/// # Errors
+///
+/// Returns an error if the log file can't be opened
+pub fn subscriber(level: LevelFilter, format: &str, file: &str) -> io::Result<Box<dyn Subscriber + Send + Sync>> {
+    let writer = if file.is_empty() {
+        BoxMakeWriter::new(io::stderr)
+    } else {
+        BoxMakeWriter::new(Mutex::new(OpenOptions::new().create(true).append(true).open(file)?))
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(writer)
+        .with_ansi(file.is_empty());
+
+    Ok(if format.eq_ignore_ascii_case("json") {
+        Box::new(builder.event_format(JsonFormat).finish())
+    } else {
+        Box::new(builder.finish())
+    })
+}
+
+/// Formats each event as a single-line JSON object
+///
+/// The object holds `timestamp` (seconds since the Unix epoch), `level`,
+/// `target`, `message` and every structured field recorded on the event.
+struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+
+        let mut object = Map::new();
+        object.insert("timestamp".to_string(), Value::from(timestamp));
+        object.insert("level".to_string(), Value::from(metadata.level().as_str()));
+        object.insert("target".to_string(), Value::from(metadata.target()));
+        event.record(&mut JsonVisitor(&mut object));
+
+        writeln!(writer, "{}", Value::Object(object))
+    }
+}
+
+/// Copies event fields into a JSON map, keeping numbers and booleans typed
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_string(), Value::from(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_format_keeps_structured_fields() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .event_format(JsonFormat)
+            .with_writer(move || writer.clone())
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(client_addr = "127.0.0.1:5000", latency_us = 42u64, "command executed");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["message"], "command executed");
+        assert_eq!(line["client_addr"], "127.0.0.1:5000");
+        assert_eq!(line["latency_us"], 42);
+    }
+
+    #[test]
+    fn test_environment_overrides_configured_level() {
+        assert_eq!(log_level("debug", None), LevelFilter::DEBUG);
+        assert_eq!(log_level("debug", Some("warn")), LevelFilter::WARN);
+        // Directives this parser doesn't understand leave the setting alone
+        assert_eq!(log_level("debug", Some("redis_imitate=trace")), LevelFilter::DEBUG);
+        assert_eq!(log_level("loud", None), LevelFilter::INFO);
+    }
+
+    #[test]
+    fn test_log_file_receives_records_up_to_the_level() {
+        let path = std::env::temp_dir().join(format!("redis_imitate_log_{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let subscriber = subscriber(LevelFilter::INFO, "json", path.to_str().unwrap()).unwrap();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!(command = "get", "command executed");
+            tracing::info!(client_id = 1u64, "client disconnected");
+        });
+
+        let output = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["message"], "client disconnected");
+        assert_eq!(lines[0]["client_id"], 1);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,42 +1,77 @@
-use crate::config::config::Config;
-use crate::network::server::Server;
-use crate::storage::memory::MemoryStorage;
+use redis_imitate::config::cli::{CliAction, CliArgs, USAGE};
+use redis_imitate::config::validate::ConfigProblem;
+use redis_imitate::logging::init_logging;
+use redis_imitate::network::server::Server;
+use redis_imitate::storage::memory::MemoryStorage;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
-mod network;
-mod commands;
-mod storage;
-mod cache;
-mod config;
-
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = Config::new();
+    let args = match CliArgs::parse(std::env::args().skip(1)) {
+        Ok(CliAction::Run(args)) => args,
+        Ok(CliAction::Help) => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Ok(CliAction::Version) => {
+            println!("rust-redis-imitate {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let config = args.config()?;
+    init_logging(&config.log_level, &config.log_format, &config.log_file)?;
+    for key in &config.unknown_keys {
+        tracing::warn!(key = %key, file = ?config.config_file, "ignoring unknown config key");
+    }
+    let problems = config.validate();
+    for problem in &problems {
+        if problem.is_error() {
+            tracing::error!(field = problem.field, "{}", problem);
+        } else {
+            tracing::warn!(field = problem.field, "{}", problem);
+        }
+    }
+    if problems.iter().any(ConfigProblem::is_error) {
+        tracing::error!("refusing to start with an invalid configuration");
+        std::process::exit(1);
+    }
+    let snapshot_path = config.snapshot_path.clone();
     let storage = Arc::new(Mutex::new(MemoryStorage::new()));
 
     {
         let mut storage = storage.lock().unwrap();
-        if let Err(e) = storage.load_snapshot("redis_data.snapshot") {
-            eprintln!("Failed to load snapshot: {}. Starting with empty storage.", e);
+        if let Err(e) = storage.load_snapshot(&snapshot_path) {
+            tracing::warn!(error = %e, "failed to load snapshot, starting with empty storage");
         } else {
-            println!("Loaded data from snapshot.");
+            tracing::info!(path = %snapshot_path, "loaded data from snapshot");
         }
     }
 
-    let storage_clone = Arc::clone(&storage);
-    std::thread::spawn(move || {
-        loop {
-            std::thread::sleep(std::time::Duration::from_secs(300));
-            let storage = storage_clone.lock().unwrap();
-            if let Err(e) = storage.save_snapshot("redis_data.snapshot") {
-                eprintln!("Failed to save snapshot: {}", e);
-            } else {
-                println!("Saved snapshot successfully.");
-            }
-        }
-    });
-
-    let server = Server::new(config);
+    // The server snapshots this same storage in the background and on exit
+    let server = Server::with_storage(config, storage);
+    register_sigterm_handler(server.shutdown_flag());
     server.run()?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Sets `shutdown` once the process receives SIGTERM
+///
+/// This is what `signal_hook::flag::register` does; the handler only
+/// stores into the atomic, which is async-signal-safe.
+#[cfg(unix)]
+fn register_sigterm_handler(shutdown: Arc<AtomicBool>) {
+    let registered = unsafe {
+        signal_hook_registry::register(libc::SIGTERM, move || shutdown.store(true, Ordering::Relaxed))
+    };
+    if let Err(e) = registered {
+        tracing::error!(error = %e, "failed to register SIGTERM handler");
+    }
+}
+
+#[cfg(not(unix))]
+fn register_sigterm_handler(_shutdown: Arc<AtomicBool>) {}
@@ -0,0 +1,161 @@
+//! # ACL Module
+//!
+//! Users and their command permissions, built once at startup from the
+//! `acl` section of the config. Each user's rules are applied in order
+//! against a command's name and categories, and the last one that matches
+//! decides; a command no rule matches is refused. Commands in the
+//! `connection` category (AUTH, PING, QUIT, ...) are always allowed.
+use crate::commands::parser::Command;
+use crate::config::config::AclUser;
+use std::collections::{BTreeMap, HashMap};
+
+/// The user a connection is logged in as until it sends AUTH
+pub const DEFAULT_USER: &str = "default";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Rule {
+    AllowCategory(String),
+    DenyCategory(String),
+    AllowCommand(String),
+    DenyCommand(String),
+}
+
+impl Rule {
+    fn parse(rule: &str) -> Option<Rule> {
+        let rule = rule.trim().to_lowercase();
+        match rule.as_str() {
+            "allcommands" => return Some(Rule::AllowCategory("all".to_string())),
+            "nocommands" => return Some(Rule::DenyCategory("all".to_string())),
+            _ => {}
+        }
+        let (allow, name) = match rule.split_at_checked(1)? {
+            ("+", name) => (true, name),
+            ("-", name) => (false, name),
+            _ => return None,
+        };
+        match name.strip_prefix('@') {
+            Some("") => None,
+            Some(category) if allow => Some(Rule::AllowCategory(category.to_string())),
+            Some(category) => Some(Rule::DenyCategory(category.to_string())),
+            None if !Command::NAMES.contains(&name) => None,
+            None if allow => Some(Rule::AllowCommand(name.to_string())),
+            None => Some(Rule::DenyCommand(name.to_string())),
+        }
+    }
+
+    /// `Some(allowed)` if this rule covers the command, `None` otherwise
+    fn decide(&self, name: &str, categories: &[&str]) -> Option<bool> {
+        let in_category = |category: &str| category == "all" || categories.contains(&category);
+        match self {
+            Rule::AllowCategory(category) => in_category(category).then_some(true),
+            Rule::DenyCategory(category) => in_category(category).then_some(false),
+            Rule::AllowCommand(command) => (command == name).then_some(true),
+            Rule::DenyCommand(command) => (command == name).then_some(false),
+        }
+    }
+}
+
+struct User {
+    password: String,
+    rules: Vec<Rule>,
+    // As written in the config, for ACL LIST
+    permissions: Vec<String>,
+}
+
+/// Every configured user, shared by all connections
+#[derive(Default)]
+pub struct Acl {
+    users: HashMap<String, User>,
+}
+
+impl Acl {
+    /// Builds the user table from `Config::acl`
+    ///
+    /// Rules that don't parse (an unknown command, a missing `+`/`-`) are
+    /// logged and skipped, so they never grant anything.
+    pub fn new(users: &HashMap<String, AclUser>) -> Acl {
+        let users = users
+            .iter()
+            .map(|(name, user)| {
+                let rules = user
+                    .permissions
+                    .iter()
+                    .filter_map(|permission| {
+                        let rule = Rule::parse(permission);
+                        if rule.is_none() {
+                            tracing::warn!(user = %name, rule = %permission, "ignoring invalid ACL rule");
+                        }
+                        rule
+                    })
+                    .collect();
+                let user = User { password: user.password.clone(), rules, permissions: user.permissions.clone() };
+                (name.clone(), user)
+            })
+            .collect();
+        Acl { users }
+    }
+
+    /// Whether any users are configured; without them everything is allowed
+    pub fn is_enabled(&self) -> bool {
+        !self.users.is_empty()
+    }
+
+    /// Returns the user a new connection starts as, or `None` if it must AUTH first
+    pub fn initial_user(&self) -> Option<&'static str> {
+        match self.users.get(DEFAULT_USER) {
+            _ if !self.is_enabled() => Some(DEFAULT_USER),
+            Some(user) if user.password.is_empty() => Some(DEFAULT_USER),
+            _ => None,
+        }
+    }
+
+    /// Checks a username and password pair; a user without a password accepts any
+    pub fn authenticate(&self, username: &str, password: &str) -> bool {
+        self.users
+            .get(username)
+            .is_some_and(|user| user.password.is_empty() || user.password == password)
+    }
+
+    /// Whether `username` may run `command`
+    pub fn permits(&self, username: &str, command: &Command) -> bool {
+        if !self.is_enabled() {
+            return true;
+        }
+        let categories = command.categories();
+        if categories.contains(&"connection") {
+            return true;
+        }
+        let Some(user) = self.users.get(username) else {
+            return false;
+        };
+        user.rules
+            .iter()
+            .rev()
+            .find_map(|rule| rule.decide(command.name(), categories))
+            .unwrap_or(false)
+    }
+
+    /// Returns one `user <name> on <nopass|>***> <rules>` line per user, sorted by name
+    ///
+    /// Passwords are masked. Without configured users, reports the
+    /// implicit default user that may run everything.
+    pub fn list(&self) -> String {
+        if !self.is_enabled() {
+            return format!("user {} on nopass +@all", DEFAULT_USER);
+        }
+        let users: BTreeMap<&String, &User> = self.users.iter().collect();
+        users
+            .into_iter()
+            .map(|(name, user)| {
+                let password = if user.password.is_empty() { "nopass" } else { ">***" };
+                let mut line = format!("user {} on {}", name, password);
+                for permission in &user.permissions {
+                    line.push(' ');
+                    line.push_str(permission);
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
@@ -0,0 +1,292 @@
+//! # Async Server Module
+//!
+//! Tokio front end for the server, selected with `config.async_server`.
+//! Every client is a task on a tokio runtime instead of a pooled thread, so
+//! idle connections cost memory but no threads. Commands still go through
+//! the shared `CommandExecutor`; each one runs under `block_in_place` so
+//! waiting on the storage mutex never stalls the reactor.
+use crate::network::client::ClientInfo;
+use crate::network::connection::{scan_inline, InlineRead, Reply, Session};
+use crate::network::pubsub::OutboxReceiver;
+use crate::network::server::{configure_stream, Server, ACCEPT_POLL_INTERVAL, DENIED_REPLY, MAX_CLIENTS_REPLY};
+use crate::network::stats::ServerStats;
+
+use std::future::Future;
+use std::io;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch, Notify};
+use tokio::task::JoinSet;
+
+/// Runs the accept loop on a tokio runtime until the shutdown flag is set
+///
+/// On shutdown every client task is told to stop and awaited before this
+/// returns, mirroring the threaded server.
+pub(crate) fn run(server: &Server, listeners: Vec<std::net::TcpListener>) -> io::Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("redis-async")
+        .build()?;
+    runtime.block_on(accept_loop(server, listeners))
+}
+
+/// Accepts clients on `listener` and hands them to the accept loop
+async fn accept_from(listener: TcpListener, accepted: mpsc::Sender<TcpStream>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                if accepted.send(stream).await.is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "connection failed");
+                tokio::time::sleep(ACCEPT_POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+async fn accept_loop(server: &Server, listeners: Vec<std::net::TcpListener>) -> io::Result<()> {
+    // Each listener accepts on its own task; admitting the clients stays
+    // here so it never races
+    let (sender, mut incoming) = mpsc::channel(1);
+    let mut acceptors = JoinSet::new();
+    for listener in listeners {
+        tracing::info!(address = %listener.local_addr()?, "server is running (async)");
+        acceptors.spawn(accept_from(TcpListener::from_std(listener)?, sender.clone()));
+    }
+    drop(sender);
+    let (stop_clients, stopped) = watch::channel(false);
+    let mut tasks = JoinSet::new();
+
+    while !server.shutdown.load(Ordering::Relaxed) {
+        let mut stream = tokio::select! {
+            Some(stream) = incoming.recv() => stream,
+            _ = tokio::time::sleep(ACCEPT_POLL_INTERVAL) => continue,
+        };
+        // Reap finished clients so the set only holds live ones
+        while tasks.try_join_next().is_some() {}
+
+        if server.denied(stream.peer_addr().ok()) {
+            let _ = stream.write_all(DENIED_REPLY).await;
+            continue;
+        }
+        let Some(slot) = server.admit() else {
+            let _ = stream.write_all(MAX_CLIENTS_REPLY).await;
+            continue;
+        };
+
+        let stream = stream.into_std()?;
+        let read_timeout_ms = server.executor.runtime_config().tcp_read_timeout_ms();
+        if let Err(e) = configure_stream(&stream, &server.config, read_timeout_ms) {
+            tracing::warn!(error = %e, "failed to apply socket options");
+        }
+        let addr = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let stream = TcpStream::from_std(stream)?;
+        let client = server.clients.register_addr(addr);
+        let session = server.new_session(Arc::clone(&client));
+        let timeouts = ClientTimeouts::from_millis(read_timeout_ms, server.config.tcp_write_timeout_ms);
+        let mut stopped = stopped.clone();
+
+        tasks.spawn(async move {
+            let _slot = slot;
+            let result = tokio::select! {
+                result = serve(stream, session, timeouts) => result,
+                _ = stopped.changed() => Ok(()),
+                // Dropping the half-read command with the connection
+                _ = client.wait_evicted() => Ok(()),
+            };
+            if let Err(e) = result {
+                tracing::error!(error = %e, "error handling client");
+            }
+        });
+    }
+
+    tracing::info!("server shutting down gracefully");
+    // Closes the listeners
+    acceptors.shutdown().await;
+    // A task parked in BLPOP sits inside block_in_place and can't see the stop signal
+    server.executor.release_blocked_clients();
+    let _ = stop_clients.send(true);
+    while tasks.join_next().await.is_some() {}
+    Ok(())
+}
+
+/// Read and write deadlines for one client; `None` waits forever
+#[derive(Clone, Copy)]
+struct ClientTimeouts {
+    read: Option<Duration>,
+    write: Option<Duration>,
+}
+
+impl ClientTimeouts {
+    fn from_millis(read_ms: u64, write_ms: u64) -> Self {
+        let timeout = |ms: u64| (ms > 0).then(|| Duration::from_millis(ms));
+        ClientTimeouts { read: timeout(read_ms), write: timeout(write_ms) }
+    }
+}
+
+/// Awaits `operation`, failing with `TimedOut` once `limit` has passed
+async fn with_timeout<T>(limit: Option<Duration>, operation: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, operation)
+            .await
+            .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into())),
+        None => operation.await,
+    }
+}
+
+/// Serves one client until it disconnects, times out, or is closed by
+/// SHUTDOWN, QUIT or a too big inline request
+///
+/// Responses to pipelined commands are batched until the read buffer runs
+/// dry, like `Connection::process`. Once the client subscribes, its
+/// responses and published messages both arrive through the session's
+/// outbox, which this task drains alongside reading commands, until the
+/// outbox reports the client went over its output buffer limit.
+async fn serve(stream: TcpStream, mut session: Session, timeouts: ClientTimeouts) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut write_buf = Vec::new();
+    let mut line = Vec::new();
+    let mut messages: Option<OutboxReceiver> = None;
+    let overflowed = Arc::new(Notify::new());
+    let stats = session.server_stats();
+    let client = session.client();
+
+    loop {
+        // read_until keeps partial input in `line` if a message wins the race
+        let read = tokio::select! {
+            read = with_timeout(timeouts.read, read_inline(&mut reader, &mut line, session.max_inline_size(), &stats, &client)) => read,
+            _ = overflowed.notified() => return Ok(()),
+            Some(message) = next_message(&mut messages) => {
+                // A client that stopped reading blocks this write until it's cut off
+                client.begin_write();
+                let written = tokio::select! {
+                    written = with_timeout(timeouts.write, writer.write_all(&message)) => written,
+                    _ = overflowed.notified() => return Ok(()),
+                };
+                client.end_write();
+                match written {
+                    Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                        session.log_timeout();
+                        return Ok(());
+                    }
+                    result => result?,
+                }
+                stats.record_output(message.len());
+                continue;
+            }
+        };
+        let read = match read {
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                session.log_timeout();
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        let response = match read {
+            InlineRead::Eof => {
+                flush(&mut session, &mut writer, &mut write_buf, timeouts.write).await?;
+                session.log_disconnect();
+                return Ok(());
+            }
+            InlineRead::TooBig => Some(Reply::Lines(session.reject_too_big_inline())),
+            _ => tokio::task::block_in_place(|| session.execute_inline(&line)),
+        };
+        line.clear();
+        if let Some(response) = response {
+            response.append_to(&mut write_buf);
+        }
+        if session.output_buffer_exceeded(write_buf.len()) {
+            return Ok(());
+        }
+        if session.is_closing() || reader.buffer().is_empty() {
+            match flush(&mut session, &mut writer, &mut write_buf, timeouts.write).await {
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                    session.log_timeout();
+                    return Ok(());
+                }
+                result => result?,
+            }
+            if messages.is_none() {
+                messages = session.take_outbox_receiver();
+                if let Some(receiver) = &messages {
+                    let overflowed = Arc::clone(&overflowed);
+                    receiver.on_overflow(move || overflowed.notify_one());
+                }
+            }
+        }
+        if session.is_closing() {
+            // A subscribed client's last reply went through the outbox
+            if let Some(receiver) = messages.as_mut() {
+                while let Ok(message) = receiver.try_recv() {
+                    with_timeout(timeouts.write, writer.write_all(&message)).await?;
+                    stats.record_output(message.len());
+                }
+            }
+            return Ok(());
+        }
+    }
+}
+
+/// Reads one line into `line`, giving up once it outgrows the inline limit
+///
+/// Input is moved into `line` as it arrives, so a read cancelled by an
+/// incoming message loses nothing.
+async fn read_inline(
+    reader: &mut BufReader<OwnedReadHalf>,
+    line: &mut Vec<u8>,
+    max_size: usize,
+    stats: &ServerStats,
+    client: &ClientInfo,
+) -> io::Result<InlineRead> {
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            client.record_complete_input();
+            return Ok(if line.is_empty() { InlineRead::Eof } else { InlineRead::Line });
+        }
+        let (used, read) = scan_inline(available, line, max_size);
+        reader.consume(used);
+        stats.record_input(used);
+        if read != InlineRead::Incomplete {
+            client.record_complete_input();
+            return Ok(read);
+        }
+        client.record_partial_input();
+    }
+}
+
+/// Waits for the next published message, or forever if the client never subscribed
+async fn next_message(messages: &mut Option<OutboxReceiver>) -> Option<Vec<u8>> {
+    match messages {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn flush(
+    session: &mut Session,
+    writer: &mut tokio::net::tcp::OwnedWriteHalf,
+    buf: &mut Vec<u8>,
+    limit: Option<Duration>,
+) -> io::Result<()> {
+    if session.deliver(buf) || buf.is_empty() {
+        return Ok(());
+    }
+    let client = session.client();
+    client.begin_write();
+    let written = with_timeout(limit, writer.write_all(buf)).await;
+    client.end_write();
+    written?;
+    session.server_stats().record_output(buf.len());
+    buf.clear();
+    Ok(())
+}
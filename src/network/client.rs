@@ -0,0 +1,276 @@
+//! # Client Module
+//!
+//! Tracks per-connection metadata (id, peer address, optional name) for
+//! the CLIENT family of commands and for connection log lines, and how long
+//! each client has been stuck mid-command or mid-reply, so the maintenance
+//! thread can disconnect stalled clients.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Metadata describing one client connection
+pub struct ClientInfo {
+    /// Unique, monotonically increasing connection id
+    pub id: u64,
+    /// Peer address as reported by the socket
+    pub addr: String,
+    name: Mutex<Option<String>>,
+    rate: Mutex<CommandRate>,
+    stall: Mutex<Stall>,
+    // Set once the registry disconnected the client for stalling; clients
+    // without a socket are told through `eviction`
+    evicted: AtomicBool,
+    eviction: Notify,
+    // Second handle to the client's socket, used to close it on server shutdown
+    socket: Option<TcpStream>,
+}
+
+/// Commands counted in one-second windows, for CLIENT LIST's `cmd_rate`
+struct CommandRate {
+    window_start: Instant,
+    in_window: u64,
+    last_window: u64,
+}
+
+/// Operations a client has left unfinished, and since when
+#[derive(Default)]
+struct Stall {
+    // The first part of a command that hasn't been completed arrived
+    reading_since: Option<Instant>,
+    // A reply write started and hasn't returned
+    writing_since: Option<Instant>,
+}
+
+impl ClientInfo {
+    /// Counts one command towards the rate CLIENT LIST reports
+    pub fn record_command(&self) {
+        let mut rate = self.rate.lock().unwrap();
+        let elapsed = rate.window_start.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            rate.last_window = if elapsed < Duration::from_secs(2) { rate.in_window } else { 0 };
+            rate.in_window = 0;
+            rate.window_start = Instant::now();
+        }
+        rate.in_window += 1;
+    }
+
+    /// Returns how many commands the client ran in the last full second
+    pub fn command_rate(&self) -> u64 {
+        let rate = self.rate.lock().unwrap();
+        let elapsed = rate.window_start.elapsed();
+        if elapsed >= Duration::from_secs(2) {
+            0
+        } else if elapsed >= Duration::from_secs(1) {
+            rate.in_window
+        } else {
+            rate.last_window
+        }
+    }
+
+    /// Notes that part of a command arrived without its end; only the first part starts the clock
+    pub fn record_partial_input(&self) {
+        self.stall.lock().unwrap().reading_since.get_or_insert_with(Instant::now);
+    }
+
+    /// Notes that the command being read is complete
+    pub fn record_complete_input(&self) {
+        self.stall.lock().unwrap().reading_since = None;
+    }
+
+    /// Notes that a write to the client's socket is starting
+    pub fn begin_write(&self) {
+        self.stall.lock().unwrap().writing_since = Some(Instant::now());
+    }
+
+    /// Notes that the write to the client's socket returned
+    pub fn end_write(&self) {
+        self.stall.lock().unwrap().writing_since = None;
+    }
+
+    /// Whether the registry disconnected this client for stalling
+    ///
+    /// Its half-sent command should then be dropped rather than run.
+    pub fn is_evicted(&self) -> bool {
+        self.evicted.load(Ordering::Relaxed)
+    }
+
+    /// Waits until the registry disconnects this client for stalling
+    ///
+    /// For front ends whose sockets the registry can't close, which should
+    /// drop the connection once this returns.
+    pub async fn wait_evicted(&self) {
+        let notified = self.eviction.notified();
+        if !self.is_evicted() {
+            notified.await;
+        }
+    }
+
+    /// Returns what the client has been stuck on for too long, if anything
+    ///
+    /// # Arguments
+    ///
+    /// * `query_timeout` - How long a command may take to arrive once begun
+    /// * `output_timeout` - How long a reply write may block
+    fn stalled(&self, query_timeout: Option<Duration>, output_timeout: Option<Duration>) -> Option<&'static str> {
+        let stall = self.stall.lock().unwrap();
+        let over = |since: Option<Instant>, limit: Option<Duration>| {
+            matches!((since, limit), (Some(since), Some(limit)) if since.elapsed() > limit)
+        };
+        if over(stall.reading_since, query_timeout) {
+            Some("query")
+        } else if over(stall.writing_since, output_timeout) {
+            Some("output")
+        } else {
+            None
+        }
+    }
+
+    /// Returns the name set with CLIENT SETNAME, if any
+    pub fn name(&self) -> Option<String> {
+        self.name.lock().unwrap().clone()
+    }
+
+    /// Sets or clears the connection name
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - The name was updated; an empty name clears it
+    /// * `Err(String)` - The name contains spaces, newlines or other special characters
+    pub fn set_name(&self, name: &str) -> Result<(), String> {
+        if !name.chars().all(|c| c.is_ascii_graphic()) {
+            return Err("ERR Client names cannot contain spaces, newlines or special characters.".to_string());
+        }
+        *self.name.lock().unwrap() = if name.is_empty() { None } else { Some(name.to_string()) };
+        Ok(())
+    }
+}
+
+impl fmt::Display for ClientInfo {
+    /// Formats the client the way CLIENT LIST reports it
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "id={} addr={} cmd_rate={} name={}",
+            self.id, self.addr, self.command_rate(), self.name().unwrap_or_default()
+        )
+    }
+}
+
+/// Registry of all connected clients, shared by the server and its connections
+#[derive(Default)]
+pub struct ClientRegistry {
+    next_id: AtomicU64,
+    clients: Mutex<BTreeMap<u64, Arc<ClientInfo>>>,
+}
+
+impl ClientRegistry {
+    /// Creates an empty registry; the first client gets id 1
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Assigns the next id to a newly accepted client and records it
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The client's socket
+    pub fn register(&self, stream: &TcpStream) -> Arc<ClientInfo> {
+        let addr = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        self.insert(addr, stream.try_clone().ok())
+    }
+
+    /// Records a client whose socket the registry can't close
+    ///
+    /// Used by the tokio front end, which stops its own client tasks on
+    /// shutdown and when `wait_evicted` returns; `disconnect_all` skips
+    /// these clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The client's peer address
+    pub fn register_addr(&self, addr: String) -> Arc<ClientInfo> {
+        self.insert(addr, None)
+    }
+
+    fn insert(&self, addr: String, socket: Option<TcpStream>) -> Arc<ClientInfo> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let rate = Mutex::new(CommandRate { window_start: Instant::now(), in_window: 0, last_window: 0 });
+        let client = Arc::new(ClientInfo {
+            id,
+            addr,
+            name: Mutex::new(None),
+            rate,
+            stall: Mutex::new(Stall::default()),
+            evicted: AtomicBool::new(false),
+            eviction: Notify::new(),
+            socket,
+        });
+        self.clients.lock().unwrap().insert(id, Arc::clone(&client));
+        client
+    }
+
+    /// Removes a disconnected client
+    pub fn unregister(&self, id: u64) {
+        self.clients.lock().unwrap().remove(&id);
+    }
+
+    /// Closes every registered client's socket
+    ///
+    /// Blocked reads return end-of-file, so each connection's worker exits.
+    pub fn disconnect_all(&self) {
+        for client in self.clients.lock().unwrap().values() {
+            if let Some(socket) = &client.socket {
+                let _ = socket.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    /// Closes the sockets of clients stuck mid-command or mid-reply for too long
+    ///
+    /// Unlike the idle timeout, a client waiting quietly between commands is
+    /// never affected. Clients without a socket the registry can close are
+    /// woken from `ClientInfo::wait_evicted` instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `query_timeout` - How long a command may take to arrive once begun; `None` for no limit
+    /// * `output_timeout` - How long a reply write may block; `None` for no limit
+    ///
+    /// # Returns
+    ///
+    /// How many clients were disconnected
+    pub fn disconnect_stalled(&self, query_timeout: Option<Duration>, output_timeout: Option<Duration>) -> usize {
+        let mut disconnected = 0;
+        for client in self.clients.lock().unwrap().values() {
+            if client.is_evicted() {
+                continue;
+            }
+            let Some(stalled_on) = client.stalled(query_timeout, output_timeout) else {
+                continue;
+            };
+            tracing::warn!(client_id = client.id, client_addr = %client.addr, stalled_on, "closing stalled client");
+            client.evicted.store(true, Ordering::Relaxed);
+            match &client.socket {
+                Some(socket) => {
+                    let _ = socket.shutdown(Shutdown::Both);
+                }
+                None => client.eviction.notify_waiters(),
+            }
+            disconnected += 1;
+        }
+        disconnected
+    }
+
+    /// Formats every connected client, one per line, ordered by id
+    pub fn list(&self) -> String {
+        self.clients.lock().unwrap()
+            .values()
+            .map(|client| client.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
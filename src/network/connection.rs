@@ -2,12 +2,120 @@
 //! 
 //! Handles individual client connections, providing command processing,
 //! transaction management, and network communication for the Redis-like server.
-use std::collections::VecDeque;
-use crate::commands::parser::{Command, CommandParser};
+use std::collections::{BTreeSet, VecDeque};
+use crate::commands::parser::{Command, CommandParser, CommandTable, ShutdownMode};
 use crate::commands::executor::CommandExecutor;
+use crate::cluster::slots::SlotTable;
+use crate::network::acl::{Acl, DEFAULT_USER};
+use crate::network::client::{ClientInfo, ClientRegistry};
+use crate::config::config::OutputBufferLimit;
+use crate::network::pubsub::{encode_frame, Outbox, OutboxReceiver, PubSub};
+use crate::network::server::ShutdownHandle;
+use crate::network::stats::ServerStats;
 use std::net::TcpStream;
 use std::io::{self, BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Token bucket bounding how many commands a connection may run per second
+///
+/// The bucket holds up to `burst` tokens and refills continuously at
+/// `max_commands_per_second`, so a pipelined burst that stays under the
+/// average rate never waits. Once it is empty the connection either sleeps
+/// until the next token arrives or, with `reject` set, has the command refused.
+struct RateLimiter {
+    max_commands_per_second: u64,
+    burst: u64,
+    reject: bool,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    fn new(max_commands_per_second: u64) -> Self {
+        RateLimiter {
+            max_commands_per_second,
+            burst: max_commands_per_second,
+            reject: false,
+            tokens: max_commands_per_second as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Sets the bucket capacity; 0 makes it one second's worth of commands
+    fn set_burst(&mut self, burst: u64) {
+        self.burst = if burst == 0 { self.max_commands_per_second } else { burst };
+        self.tokens = self.burst as f64;
+    }
+
+    /// Takes a token for the next command
+    ///
+    /// Blocks until one is available, unless the limiter rejects instead;
+    /// then returns `false` when the bucket is empty.
+    fn acquire(&mut self) -> bool {
+        if self.max_commands_per_second == 0 {
+            return true;
+        }
+
+        self.refill();
+        if self.tokens < 1.0 {
+            if self.reject {
+                return false;
+            }
+            let wait = (1.0 - self.tokens) / self.max_commands_per_second as f64;
+            std::thread::sleep(Duration::from_secs_f64(wait));
+            self.refill();
+        }
+        self.tokens = (self.tokens - 1.0).max(0.0);
+        true
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let earned = now.duration_since(self.last_refill).as_secs_f64() * self.max_commands_per_second as f64;
+        self.tokens = (self.tokens + earned).min(self.burst as f64);
+        self.last_refill = now;
+    }
+}
+
+/// Per-client command state, independent of how the socket is driven
+///
+/// Holds the transaction stack, rate limiter and client metadata, and turns
+/// one line of input into one response. Both the threaded `Connection` and
+/// the tokio front end in `async_server` drive their clients through it.
+/// Dropping the session removes the client from the registry and from
+/// every Pub/Sub channel.
+pub(crate) struct Session {
+    executor: Arc<CommandExecutor>,
+    max_inline_size: usize,
+    transaction_stack: VecDeque<Vec<Command>>,
+    // Replication offset right after this client's last write, what WAIT waits for
+    last_write_offset: u64,
+    rate_limiter: RateLimiter,
+    command_table: Arc<CommandTable>,
+    acl: Arc<Acl>,
+    // Who the client is logged in as; `None` until it authenticates
+    user: Option<String>,
+    client: Arc<ClientInfo>,
+    clients: Arc<ClientRegistry>,
+    shutdown: Option<ShutdownHandle>,
+    closing: bool,
+    pubsub: Arc<PubSub>,
+    slots: Arc<SlotTable>,
+    channels: BTreeSet<String>,
+    patterns: BTreeSet<String>,
+    // Subscriptions whose confirmation hasn't reached the outbox yet
+    pending_channels: Vec<String>,
+    pending_patterns: Vec<String>,
+    outbox: Option<Outbox>,
+    outbox_receiver: Option<OutboxReceiver>,
+    normal_output_limit: OutputBufferLimit,
+    pubsub_output_limit: OutputBufferLimit,
+    over_soft_limit_since: Option<Instant>,
+    output_buffer_disconnections: Arc<AtomicU64>,
+    stats: Arc<ServerStats>,
+}
 
 /// Manages a single client connection and its transaction state
 ///
@@ -18,8 +126,8 @@ use std::sync::Arc;
 /// - Connection state maintenance
 pub struct Connection {
     stream: BufReader<TcpStream>,
-    executor: Arc<CommandExecutor>,
-    transaction_stack: VecDeque<Vec<Command>>,
+    write_buf: Vec<u8>,
+    session: Session,
 }
 
 impl Connection {
@@ -35,44 +143,544 @@ impl Connection {
     ///
     /// A new Connection instance ready to process client commands
     pub fn new(stream: TcpStream, executor: Arc<CommandExecutor>) -> Self {
+        let clients = Arc::new(ClientRegistry::new());
+        let client = clients.register(&stream);
+        Self::with_client(stream, executor, client, clients)
+    }
+
+    /// Creates a Connection for a client already registered with the server
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - TCP stream for the client connection
+    /// * `executor` - Shared command executor for processing commands
+    /// * `client` - This connection's metadata (id, address, name)
+    /// * `clients` - The registry `client` belongs to, used by CLIENT LIST
+    pub fn with_client(
+        stream: TcpStream,
+        executor: Arc<CommandExecutor>,
+        client: Arc<ClientInfo>,
+        clients: Arc<ClientRegistry>,
+    ) -> Self {
         Connection {
             stream: BufReader::new(stream),
-            executor,
-            transaction_stack: VecDeque::new(),
+            write_buf: Vec::new(),
+            session: Session::new(executor, client, clients),
         }
     }
 
+    /// Limits this connection to `max_commands_per_second` commands per second
+    ///
+    /// A value of 0 disables the limit. Commands over the limit wait for
+    /// their turn, and up to one second's worth may arrive at once.
+    pub fn set_rate_limit(&mut self, max_commands_per_second: u64) {
+        self.session.set_rate_limit(max_commands_per_second);
+    }
+
+    /// Lets up to `burst` commands through at once before the rate limit applies
+    ///
+    /// 0 means one second's worth. Call after `set_rate_limit`.
+    pub fn set_rate_limit_burst(&mut self, burst: u64) {
+        self.session.set_rate_limit_burst(burst);
+    }
+
+    /// Refuses commands over the rate limit with "ERR rate limit exceeded"
+    /// instead of making them wait
+    pub fn set_rate_limit_reject(&mut self, reject: bool) {
+        self.session.set_rate_limit_reject(reject);
+    }
+
+    /// Caps inline commands at `max_inline_size` bytes
+    ///
+    /// A longer line is answered with a protocol error and closes the
+    /// connection, so a client can't make the server buffer unbounded input.
+    pub fn set_max_inline_size(&mut self, max_inline_size: usize) {
+        self.session.set_max_inline_size(max_inline_size);
+    }
+
+    /// Allows this connection to stop the server with SHUTDOWN
+    pub fn set_shutdown_handle(&mut self, shutdown: ShutdownHandle) {
+        self.session.set_shutdown_handle(shutdown);
+    }
+
+    /// Resolves command names through `command_table` (renamed/disabled commands)
+    pub fn set_command_table(&mut self, command_table: Arc<CommandTable>) {
+        self.session.set_command_table(command_table);
+    }
+
+    /// Disconnects the client once replies (`normal`) or, after it
+    /// subscribes, messages (`pubsub`) pile up past the limit unread
+    pub fn set_output_buffer_limits(&mut self, normal: OutputBufferLimit, pubsub: OutputBufferLimit) {
+        self.session.set_output_buffer_limits(normal, pubsub);
+    }
+
+    /// Shares the server's Pub/Sub channels with this connection
+    pub fn set_pubsub(&mut self, pubsub: Arc<PubSub>) {
+        self.session.set_pubsub(pubsub);
+    }
+
+    /// Shares the server's hash slot assignments with this connection
+    pub fn set_slot_table(&mut self, slots: Arc<SlotTable>) {
+        self.session.set_slot_table(slots);
+    }
+
+    /// Checks this connection's commands against `acl`
+    ///
+    /// Once `acl` has users, the connection must AUTH before anything else
+    /// unless a passwordless "default" user is configured.
+    pub fn set_acl(&mut self, acl: Arc<Acl>) {
+        self.session.set_acl(acl);
+    }
+
    /// Processes client commands in a loop until the connection is closed
    ///
    /// # Returns
    ///
-   /// * `Ok(())` if the connection was closed normally
+   /// * `Ok(())` if the connection was closed normally, or dropped because a
+   ///   read or write hit the socket's timeout
    /// * `Err(e)` if an I/O error occurred
    ///
    /// # Command Processing Flow
    ///
    /// 1. Reads command from client
-   /// 2. Parses the command
-   /// 3. Handles the command (including transaction management)
-   /// 4. Writes response back to client
+   /// 2. Waits for the rate limiter if the connection is over its budget
+   /// 3. Parses the command
+   /// 4. Handles the command (including transaction management)
+   /// 5. Appends the response to the write buffer
+   /// 6. Flushes the buffer once no further pipelined input is waiting
     pub fn process(&mut self) -> io::Result<()> {
+        match self.serve() {
+            // Socket timeouts surface as WouldBlock on Unix and TimedOut on Windows
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                self.session.log_timeout();
+                let _ = self.stream.get_ref().shutdown(std::net::Shutdown::Both);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    fn serve(&mut self) -> io::Result<()> {
+        let mut line = Vec::new();
         loop {
-            let mut command = String::new();
-            let bytes_read = self.stream.read_line(&mut command)?;
-            if bytes_read == 0 {
-                println!("Client disconnected");
+            line.clear();
+            let response = match self.read_inline(&mut line)? {
+                InlineRead::Eof => {
+                    self.flush_responses()?;
+                    self.session.log_disconnect();
+                    return Ok(());
+                }
+                InlineRead::TooBig => Some(Reply::Lines(self.session.reject_too_big_inline())),
+                _ => self.session.execute_inline(&line),
+            };
+            if let Some(response) = response {
+                response.append_to(&mut self.write_buf);
+            }
+            if self.session.output_buffer_exceeded(self.write_buf.len()) {
+                let _ = self.stream.get_ref().shutdown(std::net::Shutdown::Both);
+                return Ok(());
+            }
+            if self.session.is_closing() {
+                self.flush_responses()?;
                 return Ok(());
             }
-            println!("Received command: {}", command.trim());
-            let parsed_command = CommandParser::parse(&command);
-            let response = self.handle_command(parsed_command);
-            
-            println!("Sending response: {}", response);
-            for line in response.lines(){
-                self.stream.get_mut().write_all(line.as_bytes())?;
-                self.stream.get_mut().write_all(b"\r\n")?;
+            if !self.has_pending_input()? {
+                self.flush_responses()?;
+            }
+        }
+    }
+
+    /// Reads one line into `line`, giving up once it outgrows the inline limit
+    ///
+    /// A client disconnected for stalling reads as end-of-file, so the rest
+    /// of its command is never run.
+    fn read_inline(&mut self, line: &mut Vec<u8>) -> io::Result<InlineRead> {
+        let client = &self.session.client;
+        loop {
+            let available = self.stream.fill_buf()?;
+            if available.is_empty() || client.is_evicted() {
+                client.record_complete_input();
+                return Ok(if line.is_empty() || client.is_evicted() { InlineRead::Eof } else { InlineRead::Line });
+            }
+            let (used, read) = scan_inline(available, line, self.session.max_inline_size());
+            self.stream.consume(used);
+            self.session.stats.record_input(used);
+            if read != InlineRead::Incomplete {
+                client.record_complete_input();
+                return Ok(read);
+            }
+            client.record_partial_input();
+        }
+    }
+
+    /// Writes all buffered responses to the client in a single write
+    ///
+    /// Once the client has subscribed, a writer thread owns the socket's
+    /// output so published messages can arrive while this thread blocks on
+    /// reads; responses are then queued to that thread instead.
+    fn flush_responses(&mut self) -> io::Result<()> {
+        if self.session.deliver(&mut self.write_buf) {
+            if let Some(receiver) = self.session.take_outbox_receiver() {
+                self.spawn_writer(receiver)?;
             }
-            self.stream.get_mut().flush()?;
+            return Ok(());
+        }
+        if self.write_buf.is_empty() {
+            return Ok(());
+        }
+        let stream = self.stream.get_mut();
+        self.session.client.begin_write();
+        let written = stream.write_all(&self.write_buf).and_then(|_| stream.flush());
+        self.session.client.end_write();
+        written?;
+        self.session.stats.record_output(self.write_buf.len());
+        self.write_buf.clear();
+        Ok(())
+    }
+
+    fn spawn_writer(&self, mut receiver: OutboxReceiver) -> io::Result<()> {
+        let mut stream = self.stream.get_ref().try_clone()?;
+        // The writer may be stuck on a client that stopped reading; closing
+        // the socket unblocks it and the reading thread alike
+        let closer = self.stream.get_ref().try_clone()?;
+        let stats = self.session.server_stats();
+        let client = self.session.client();
+        receiver.on_overflow(move || {
+            let _ = closer.shutdown(std::net::Shutdown::Both);
+        });
+        std::thread::spawn(move || {
+            while let Some(bytes) = receiver.blocking_recv() {
+                client.begin_write();
+                let written = stream.write_all(&bytes);
+                client.end_write();
+                if written.is_err() {
+                    // Wakes the reading thread so the connection closes
+                    let _ = stream.shutdown(std::net::Shutdown::Both);
+                    break;
+                }
+                stats.record_output(bytes.len());
+            }
+        });
+        Ok(())
+    }
+
+    /// Returns whether more client input can be read without blocking
+    ///
+    /// Pipelining clients send several commands at once; their responses are
+    /// batched until this returns `false`.
+    fn has_pending_input(&mut self) -> io::Result<bool> {
+        if !self.stream.buffer().is_empty() {
+            return Ok(true);
+        }
+        self.stream.get_ref().set_nonblocking(true)?;
+        let pending = match self.stream.fill_buf() {
+            Ok(buf) => Ok(!buf.is_empty()),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        };
+        self.stream.get_ref().set_nonblocking(false)?;
+        pending
+    }
+}
+
+/// Inline commands longer than this are refused unless configured otherwise
+pub(crate) const DEFAULT_MAX_INLINE_SIZE: usize = 64 * 1024;
+
+/// The outcome of reading client input up to the end of a line
+#[derive(Debug, PartialEq)]
+pub(crate) enum InlineRead {
+    /// A whole line, newline included, is ready
+    Line,
+    /// The input ran out before the newline; read more
+    Incomplete,
+    /// The line is already longer than the inline limit
+    TooBig,
+    /// The client closed the connection without sending anything more
+    Eof,
+}
+
+/// Moves input from `available` into `line`, up to and including a newline
+///
+/// Stops taking input once `line` exceeds `max_size` bytes, so a client
+/// that never sends a newline can't make `line` grow without bound.
+///
+/// # Returns
+///
+/// How many bytes of `available` were used, and what was found
+pub(crate) fn scan_inline(available: &[u8], line: &mut Vec<u8>, max_size: usize) -> (usize, InlineRead) {
+    let room = max_size.saturating_sub(line.len()).saturating_add(1);
+    match available.iter().take(room).position(|&b| b == b'\n') {
+        Some(end) => {
+            line.extend_from_slice(&available[..=end]);
+            (end + 1, InlineRead::Line)
+        }
+        None => {
+            let used = available.len().min(room);
+            line.extend_from_slice(&available[..used]);
+            let read = if line.len() > max_size { InlineRead::TooBig } else { InlineRead::Incomplete };
+            (used, read)
+        }
+    }
+}
+
+/// One reply to a line of client input
+#[derive(Debug, PartialEq)]
+pub(crate) enum Reply {
+    /// "\n"-separated lines, framed by `append_response`
+    Lines(String),
+    /// Already RESP-encoded, like Pub/Sub confirmations; sent as is
+    Resp(String),
+}
+
+impl Reply {
+    pub(crate) fn append_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            Reply::Lines(response) => append_response(buf, response),
+            Reply::Resp(response) => buf.extend_from_slice(response.as_bytes()),
+        }
+    }
+}
+
+/// Appends a "\n"-separated response to `buf`, one "\r\n"-terminated line each
+///
+/// A reply spanning several lines (EXEC, SMEMBERS, SORT, INFO, ...) is
+/// preceded by a `*<count>` header line so the client knows how many lines
+/// belong to it. A one-line reply that itself starts with `*` gets a `*1`
+/// header too, so the first line of a reply starting with `*` is always a
+/// header and never a value.
+pub(crate) fn append_response(buf: &mut Vec<u8>, response: &str) {
+    let count = response.lines().count();
+    if count > 1 || response.starts_with('*') {
+        buf.extend_from_slice(format!("*{}\r\n", count).as_bytes());
+    }
+    for line in response.lines() {
+        buf.extend_from_slice(line.as_bytes());
+        buf.extend_from_slice(b"\r\n");
+    }
+}
+
+impl Session {
+    /// Creates the state for a freshly registered client
+    pub(crate) fn new(executor: Arc<CommandExecutor>, client: Arc<ClientInfo>, clients: Arc<ClientRegistry>) -> Self {
+        Session {
+            max_inline_size: DEFAULT_MAX_INLINE_SIZE,
+            transaction_stack: VecDeque::new(),
+            last_write_offset: 0,
+            rate_limiter: RateLimiter::new(0),
+            command_table: Arc::new(CommandTable::default()),
+            acl: Arc::new(Acl::default()),
+            user: Some(DEFAULT_USER.to_string()),
+            client,
+            clients,
+            shutdown: None,
+            closing: false,
+            pubsub: Arc::new(PubSub::new()),
+            slots: Arc::new(SlotTable::new()),
+            channels: BTreeSet::new(),
+            patterns: BTreeSet::new(),
+            pending_channels: Vec::new(),
+            pending_patterns: Vec::new(),
+            outbox: None,
+            outbox_receiver: None,
+            normal_output_limit: OutputBufferLimit::default(),
+            pubsub_output_limit: OutputBufferLimit::default(),
+            over_soft_limit_since: None,
+            output_buffer_disconnections: executor.output_buffer_disconnections(),
+            stats: executor.server_stats(),
+            executor,
+        }
+    }
+
+    pub(crate) fn set_rate_limit(&mut self, max_commands_per_second: u64) {
+        self.rate_limiter = RateLimiter::new(max_commands_per_second);
+    }
+
+    pub(crate) fn set_rate_limit_burst(&mut self, burst: u64) {
+        self.rate_limiter.set_burst(burst);
+    }
+
+    pub(crate) fn set_rate_limit_reject(&mut self, reject: bool) {
+        self.rate_limiter.reject = reject;
+    }
+
+    pub(crate) fn set_shutdown_handle(&mut self, shutdown: ShutdownHandle) {
+        self.shutdown = Some(shutdown);
+    }
+
+    pub(crate) fn set_max_inline_size(&mut self, max_inline_size: usize) {
+        self.max_inline_size = max_inline_size;
+    }
+
+    pub(crate) fn max_inline_size(&self) -> usize {
+        self.max_inline_size
+    }
+
+    pub(crate) fn set_command_table(&mut self, command_table: Arc<CommandTable>) {
+        self.command_table = command_table;
+    }
+
+    pub(crate) fn set_pubsub(&mut self, pubsub: Arc<PubSub>) {
+        self.pubsub = pubsub;
+    }
+
+    pub(crate) fn set_slot_table(&mut self, slots: Arc<SlotTable>) {
+        self.slots = slots;
+    }
+
+    /// Also logs the client out unless `acl` lets new connections in as "default"
+    pub(crate) fn set_acl(&mut self, acl: Arc<Acl>) {
+        self.user = acl.initial_user().map(str::to_string);
+        self.acl = acl;
+    }
+
+    /// Queues `buf` for the socket through the Pub/Sub outbox, if the client has one
+    ///
+    /// Returns `false`, leaving `buf` alone, until the client first
+    /// subscribes; the caller then writes `buf` itself. Channels subscribed
+    /// by the command that produced `buf` only start receiving messages
+    /// here, so their confirmation is always sent before their first message.
+    pub(crate) fn deliver(&mut self, buf: &mut Vec<u8>) -> bool {
+        let Some(outbox) = &self.outbox else {
+            return false;
+        };
+        let channels: Vec<String> = self.pending_channels.drain(..).collect();
+        let patterns: Vec<String> = self.pending_patterns.drain(..).collect();
+        self.pubsub.subscribe_after_reply(&channels, &patterns, self.client.id, outbox, std::mem::take(buf));
+        true
+    }
+
+    /// Returns the server-wide counters the front end adds its socket traffic to
+    pub(crate) fn server_stats(&self) -> Arc<ServerStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Returns this connection's metadata, which the front end updates as it reads and writes
+    pub(crate) fn client(&self) -> Arc<ClientInfo> {
+        Arc::clone(&self.client)
+    }
+
+    /// Hands the receiving end of the outbox to the front end, once
+    pub(crate) fn take_outbox_receiver(&mut self) -> Option<OutboxReceiver> {
+        self.outbox_receiver.take()
+    }
+
+    pub(crate) fn set_output_buffer_limits(&mut self, normal: OutputBufferLimit, pubsub: OutputBufferLimit) {
+        self.normal_output_limit = normal;
+        self.pubsub_output_limit = pubsub;
+    }
+
+    /// Whether `pending` unwritten reply bytes break the normal client limit
+    ///
+    /// If they do, the disconnection is counted and logged and the session
+    /// is marked closing; the front end should drop the connection without
+    /// writing. Once subscribed, the outbox enforces the Pub/Sub limit instead.
+    pub(crate) fn output_buffer_exceeded(&mut self, pending: usize) -> bool {
+        if self.outbox.is_some() || !self.normal_output_limit.is_exceeded(pending, &mut self.over_soft_limit_since) {
+            return false;
+        }
+        self.output_buffer_disconnections.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            client_id = self.client.id,
+            client_addr = %self.client.addr,
+            pending_bytes = pending,
+            "closing client that reached its output buffer limit"
+        );
+        self.closing = true;
+        true
+    }
+
+    /// Returns whether the client asked for its connection to be closed
+    pub(crate) fn is_closing(&self) -> bool {
+        self.closing
+    }
+
+    pub(crate) fn log_timeout(&self) {
+        tracing::info!(client_id = self.client.id, client_addr = %self.client.addr, "client timed out, closing connection");
+    }
+
+    pub(crate) fn log_disconnect(&self) {
+        tracing::info!(client_id = self.client.id, client_addr = %self.client.addr, "client disconnected");
+    }
+
+    /// Runs one raw line of client input, as read off the socket
+    ///
+    /// The line may end in "\r\n" or a bare "\n". Blank lines are skipped
+    /// without a reply, as Redis does, and a line holding a NUL byte is
+    /// refused rather than parsed.
+    ///
+    /// # Returns
+    ///
+    /// The reply to send, or `None` if nothing should be sent
+    pub(crate) fn execute_inline(&mut self, line: &[u8]) -> Option<Reply> {
+        let command = String::from_utf8_lossy(line);
+        let command = command.trim();
+        if command.is_empty() {
+            return None;
+        }
+        if command.contains('\0') {
+            return Some(Reply::Lines("ERR Protocol error: unexpected NUL in inline request".to_string()));
+        }
+        Some(self.execute_line(command))
+    }
+
+    /// Answers a line that outgrew the inline limit and marks the session closing
+    pub(crate) fn reject_too_big_inline(&mut self) -> String {
+        tracing::warn!(
+            client_id = self.client.id,
+            client_addr = %self.client.addr,
+            max_inline_size = self.max_inline_size,
+            "closing client that sent a too big inline request"
+        );
+        self.closing = true;
+        "ERR Protocol error: too big inline request".to_string()
+    }
+
+    /// Runs one line of client input and returns the reply
+    ///
+    /// Waits for the rate limiter first, so this may block, or refuses the
+    /// command if the limiter is set to reject.
+    pub(crate) fn execute_line(&mut self, command: &str) -> Reply {
+        tracing::trace!(client_id = self.client.id, command = %command, "received command");
+        if !self.rate_limiter.acquire() {
+            tracing::debug!(client_id = self.client.id, "rate limit exceeded");
+            return Reply::Lines("ERR rate limit exceeded".to_string());
+        }
+        self.client.record_command();
+        self.stats.record_command();
+        let started = Instant::now();
+        let parsed_command = CommandParser::parse_with_table(command, &self.command_table);
+        let name = parsed_command.name();
+        let writes = parsed_command.is_write() || matches!(parsed_command, Command::Exec);
+        // A subscribed client's PING gets a frame too
+        let subscription = matches!(
+            parsed_command,
+            Command::Subscribe(_) | Command::Unsubscribe(_) | Command::PSubscribe(_) | Command::PUnsubscribe(_)
+        ) || (matches!(parsed_command, Command::Ping(_)) && self.subscribed());
+        // A GEOSEARCH with WITH* options nests an array per match, and the
+        // stream reads an array per entry, already encoded
+        let nested = matches!(&parsed_command, Command::GeoSearch { params, store: None, .. } if params.with_details())
+            || matches!(
+                parsed_command,
+                Command::XRange(..) | Command::XRead { .. } | Command::XReadGroup { .. } | Command::XAutoClaim { .. } | Command::XInfo(..)
+            );
+        let response = self.handle_command(parsed_command);
+        if writes {
+            self.last_write_offset = self.executor.replication().offset();
+        }
+        tracing::debug!(
+            client_id = self.client.id,
+            client_name = %self.client.name().unwrap_or_default(),
+            client_addr = %self.client.addr,
+            command = name,
+            latency_us = started.elapsed().as_micros() as u64,
+            "command executed"
+        );
+        tracing::trace!(client_id = self.client.id, response = %response, "sending response");
+        // Confirmations are RESP arrays; errors (NOAUTH, NOPERM) are still plain lines
+        if (subscription || nested) && response.starts_with('*') {
+            Reply::Resp(response)
+        } else {
+            Reply::Lines(response)
         }
     }
 
@@ -88,39 +696,147 @@ impl Connection {
    ///
    /// # Transaction Handling
    ///
-   /// * MULTI - Starts a new transaction
-   /// * EXEC - Executes the current transaction
+   /// * MULTI - Starts a new transaction, nested inside any open one
+   /// * EXEC - Executes the current transaction; a nested one is added to
+   ///   the enclosing transaction instead and replies "QUEUED"
    /// * DISCARD - Discards the current transaction
+   /// * CLIENT - Answered from this connection's metadata, never queued
+   /// * SHUTDOWN - Stops the server; nothing is sent back on success
+   /// * WAIT - Waits for replicas to acknowledge this client's last write;
+   ///   inside MULTI it is queued, and EXEC answers it without waiting
+   /// * QUIT - Discards any open transaction, replies "OK" and closes the
+   ///   connection; never queued, even inside MULTI
+   /// * RESET - Discards any open transaction, drops every subscription,
+   ///   clears the client name and logs back in as a new connection would;
+   ///   never queued
+   /// * (P)SUBSCRIBE/(P)UNSUBSCRIBE/PUBLISH - Served from the shared Pub/Sub
+   ///   registry; while subscribed, only (P)SUBSCRIBE, (P)UNSUBSCRIBE, PING,
+   ///   RESET and QUIT are accepted, and PING is answered with a frame
+   /// * AUTH - Logs in as another user; until it succeeds, a connection that
+   ///   must authenticate gets NOAUTH for everything but AUTH, RESET and QUIT
+   /// * ACL WHOAMI/ACL LIST - Answered from the server's users
+   /// * REPLICAOF - Starts or stops replicating a master; while replicating,
+   ///   writes get READONLY
+   /// * SYNC - Turns this connection into a replica's link: the dataset,
+   ///   then every write, is streamed to it from then on
+   /// * PSYNC - Like SYNC, but only the writes after the given offset are
+   ///   streamed if the backlog still holds them
+   /// * REPLCONF ACK - Records the offset a replica has applied, for WAIT;
+   ///   nothing is replied
    /// * Other commands - Queued if in transaction, executed immediately otherwise
+   ///
+   /// # Permissions
+   ///
+   /// A command the logged-in user's rules don't allow gets NOPERM, and is
+   /// never queued.
     fn handle_command(&mut self, command: Command) -> String {
+        let Some(user) = &self.user else {
+            if !matches!(command, Command::Auth(..) | Command::Reset | Command::Quit) {
+                return "NOAUTH Authentication required.".to_string();
+            }
+            return self.handle_permitted(command);
+        };
+        if !matches!(command, Command::Unknown(_)) && !self.acl.permits(user, &command) {
+            return format!("NOPERM User {} has no permissions to run the '{}' command", user, command.name());
+        }
+        self.handle_permitted(command)
+    }
+
+    fn handle_permitted(&mut self, command: Command) -> String {
+        let allowed_while_subscribed = matches!(
+            command,
+            Command::Subscribe(_)
+                | Command::Unsubscribe(_)
+                | Command::PSubscribe(_)
+                | Command::PUnsubscribe(_)
+                | Command::Ping(_)
+                | Command::Reset
+                | Command::Quit
+        );
+        if self.subscribed() && !allowed_while_subscribed {
+            return "ERR Command not allowed inside a subscribed context".to_string();
+        }
+        if command.is_write() && self.executor.master_link().is_replica() {
+            return "READONLY You can't write against a read only replica.".to_string();
+        }
         match command {
+            Command::Subscribe(channels) => self.subscribe(channels),
+            Command::Unsubscribe(channels) => self.unsubscribe(channels),
+            Command::PSubscribe(patterns) => self.psubscribe(patterns),
+            Command::PUnsubscribe(patterns) => self.punsubscribe(patterns),
+            Command::Publish(channel, message) => self.pubsub.publish(&channel, &message).to_string(),
+            // Shaped like a confirmation: `pong`, the message (empty if none) and 0
+            Command::Ping(message) if self.subscribed() => {
+                encode_frame(&[Some("pong"), Some(message.as_deref().unwrap_or(""))], Some(0))
+            }
+            // Queued commands run under one storage lock at EXEC; no storage
+            // transaction is opened, so nothing is left open if EXEC never comes
             Command::Multi => {
                 self.transaction_stack.push_back(Vec::new());
-                self.executor.execute_command(command)
+                "OK".to_string()
             }
             Command::Exec => {
                 if self.transaction_stack.is_empty() {
                     "ERR EXEC without MULTI".to_string()
                 } else {
                     let commands = self.transaction_stack.pop_back().unwrap();
-                    let results = self.executor.execute_transaction(&commands);
-                    if !self.transaction_stack.is_empty() {
-                        // If still in the outer transaction, add the results as multiple commands
-                        for result in results.iter() {
-                            self.transaction_stack.back_mut().unwrap().push(
-                                Command::Set(format!("RESULT:{}", uuid::Uuid::new_v4().to_string()), result.clone())
-                            );
+                    match self.transaction_stack.back_mut() {
+                        // A nested EXEC hands its commands to the enclosing
+                        // transaction; they run when the outermost EXEC does
+                        Some(outer) => {
+                            outer.extend(commands);
+                            "QUEUED".to_string()
                         }
+                        None => self.executor.execute_transaction_as(&commands, &self.client).join("\n"),
                     }
-                    results.join("\n")
                 }
             }
+            Command::ClientId => self.client.id.to_string(),
+            Command::ClientSetName(name) => match self.client.set_name(&name) {
+                Ok(()) => "OK".to_string(),
+                Err(e) => e,
+            },
+            Command::ClientGetName => self.client.name().unwrap_or_else(|| "(nil)".to_string()),
+            Command::ClientList => self.clients.list(),
+            Command::Auth(username, password) => self.auth(username, &password),
+            Command::AclWhoAmI => self.user.clone().unwrap_or_default(),
+            Command::AclList => self.acl.list(),
+            Command::ClusterInfo => self.slots.info(),
+            Command::ClusterAddSlots(slots) => match self.slots.add_slots(&slots) {
+                Ok(()) => "OK".to_string(),
+                Err(e) => e,
+            },
+            Command::ClusterAddSlotsRange(ranges) => match self.slots.add_slot_ranges(&ranges) {
+                Ok(()) => "OK".to_string(),
+                Err(e) => e,
+            },
+            Command::ClusterDelSlots(slots) => match self.slots.del_slots(&slots) {
+                Ok(()) => "OK".to_string(),
+                Err(e) => e,
+            },
+            Command::Shutdown(mode) => self.shutdown(mode),
+            Command::ReplicaOf(master) => self.replica_of(master),
+            Command::Sync => self.sync(None),
+            Command::PSync(replication_id, offset) => {
+                // `? -1`, or any negative offset, asks for a full sync
+                let resume = u64::try_from(offset).ok().filter(|_| replication_id != "?");
+                self.sync(resume.map(|offset| (replication_id.as_str(), offset)))
+            }
+            Command::ReplConfAck(offset) => {
+                self.executor.ack_replica(self.client.id, offset);
+                String::new()
+            }
+            Command::Quit => self.quit(),
+            Command::Reset => self.reset(),
+            Command::Wait(num_replicas, timeout_ms) if self.transaction_stack.is_empty() => {
+                self.wait(num_replicas, timeout_ms)
+            }
             Command::Discard => {
                 if self.transaction_stack.is_empty() {
                     "ERR DISCARD without MULTI".to_string()
                 } else {
                     self.transaction_stack.pop_back();
-                    self.executor.execute_command(command)
+                    "OK".to_string()
                 }
             }
             _ => {
@@ -128,9 +844,215 @@ impl Connection {
                     self.transaction_stack.back_mut().unwrap().push(command);
                     "QUEUED".to_string()
                 } else {
-                    self.executor.execute_command(command)
+                    self.executor.execute_command_as(command, &self.client)
                 }
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Whether the client is in subscriber mode
+    fn subscribed(&self) -> bool {
+        self.subscription_count() > 0
+    }
+
+    /// Channels plus patterns, the count every (un)subscribe reply reports
+    fn subscription_count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    fn ensure_outbox(&mut self) {
+        if self.outbox.is_none() {
+            let (outbox, receiver) = Outbox::with_limit(
+                self.client.id,
+                self.pubsub_output_limit,
+                Arc::clone(&self.output_buffer_disconnections),
+            );
+            self.outbox = Some(outbox);
+            self.outbox_receiver = Some(receiver);
+        }
+    }
+
+    /// Subscribes to each channel, replying `subscribe <channel> <count>` per channel
+    ///
+    /// Each confirmation is its own RESP array. The subscriptions take
+    /// effect once the reply is handed to `deliver`.
+    fn subscribe(&mut self, channels: Vec<String>) -> String {
+        self.ensure_outbox();
+        let mut reply = String::new();
+        for channel in channels {
+            if self.channels.insert(channel.clone()) {
+                self.pending_channels.push(channel.clone());
+            }
+            reply.push_str(&encode_frame(&[Some("subscribe"), Some(&channel)], Some(self.subscription_count())));
+        }
+        reply
+    }
+
+    /// Subscribes to each pattern, replying `psubscribe <pattern> <count>` per pattern
+    fn psubscribe(&mut self, patterns: Vec<String>) -> String {
+        self.ensure_outbox();
+        let mut reply = String::new();
+        for pattern in patterns {
+            if self.patterns.insert(pattern.clone()) {
+                self.pending_patterns.push(pattern.clone());
+            }
+            reply.push_str(&encode_frame(&[Some("psubscribe"), Some(&pattern)], Some(self.subscription_count())));
+        }
+        reply
+    }
+
+    /// Unsubscribes from each pattern, or from all of them when none are given
+    fn punsubscribe(&mut self, patterns: Vec<String>) -> String {
+        let patterns = if patterns.is_empty() {
+            self.patterns.iter().cloned().collect()
+        } else {
+            patterns
+        };
+        if patterns.is_empty() {
+            return encode_frame(&[Some("punsubscribe"), None], Some(self.subscription_count()));
+        }
+        let mut reply = String::new();
+        for pattern in patterns {
+            self.patterns.remove(&pattern);
+            self.pending_patterns.retain(|pending| *pending != pattern);
+            self.pubsub.punsubscribe(&pattern, self.client.id);
+            reply.push_str(&encode_frame(&[Some("punsubscribe"), Some(&pattern)], Some(self.subscription_count())));
+        }
+        reply
+    }
+
+    /// Unsubscribes from each channel, or from all of them when none are given
+    fn unsubscribe(&mut self, channels: Vec<String>) -> String {
+        let channels = if channels.is_empty() {
+            self.channels.iter().cloned().collect()
+        } else {
+            channels
+        };
+        if channels.is_empty() {
+            return encode_frame(&[Some("unsubscribe"), None], Some(self.subscription_count()));
+        }
+        let mut reply = String::new();
+        for channel in channels {
+            self.channels.remove(&channel);
+            self.pending_channels.retain(|pending| *pending != channel);
+            self.pubsub.unsubscribe(&channel, self.client.id);
+            reply.push_str(&encode_frame(&[Some("unsubscribe"), Some(&channel)], Some(self.subscription_count())));
+        }
+        reply
+    }
+
+    /// Logs in as `username`, or as "default" when AUTH is given only a password
+    fn auth(&mut self, username: Option<String>, password: &str) -> String {
+        if !self.acl.is_enabled() {
+            return "ERR AUTH <password> called without any password configured for the default user. \
+                    Are you sure your configuration is correct?".to_string();
+        }
+        let username = username.unwrap_or_else(|| DEFAULT_USER.to_string());
+        if !self.acl.authenticate(&username, password) {
+            tracing::warn!(client_id = self.client.id, user = %username, "failed AUTH attempt");
+            return "WRONGPASS invalid username-password pair or user is disabled.".to_string();
+        }
+        self.user = Some(username);
+        "OK".to_string()
+    }
+
+    /// Ends the session: open transactions are discarded and the front end
+    /// closes the socket once the reply is flushed
+    fn quit(&mut self) -> String {
+        self.transaction_stack.clear();
+        tracing::info!(client_id = self.client.id, client_addr = %self.client.addr, "client sent QUIT");
+        self.closing = true;
+        "OK".to_string()
+    }
+
+    /// Puts the session back the way a new connection starts: open
+    /// transactions are discarded, every subscription is dropped, the
+    /// client name is cleared and the client is logged in as `set_acl` would
+    fn reset(&mut self) -> String {
+        self.transaction_stack.clear();
+        self.pubsub.unsubscribe_all(self.client.id);
+        self.channels.clear();
+        self.patterns.clear();
+        self.pending_channels.clear();
+        self.pending_patterns.clear();
+        let _ = self.client.set_name("");
+        self.user = self.acl.initial_user().map(str::to_string);
+        "RESET".to_string()
+    }
+
+    /// Blocks until `num_replicas` replicas have acknowledged this client's
+    /// last write, or `timeout_ms` passes (0 waits forever)
+    fn wait(&self, num_replicas: u64, timeout_ms: u64) -> String {
+        let timeout = (timeout_ms > 0).then(|| Duration::from_millis(timeout_ms));
+        self.executor.replication()
+            .wait_for_offset(self.last_write_offset, num_replicas as usize, timeout)
+            .to_string()
+    }
+
+    /// Replicates `master`, or stops replicating with `None` (REPLICAOF NO ONE)
+    fn replica_of(&self, master: Option<(String, u16)>) -> String {
+        let link = self.executor.master_link();
+        let Some((host, port)) = master else {
+            link.stop();
+            return "OK".to_string();
+        };
+        match link.start(host, port, Arc::clone(&self.executor)) {
+            Ok(true) => "OK".to_string(),
+            Ok(false) => "OK Already connected to specified master".to_string(),
+            Err(e) => format!("ERR Can't start replicating: {}", e),
+        }
+    }
+
+    /// Attaches this connection as a replica, streaming the full sync and then every write to it
+    ///
+    /// With `psync`, the replication id and offset PSYNC named, only the
+    /// writes after that offset are streamed if the backlog still holds
+    /// them. Everything goes through the outbox, like Pub/Sub messages, so
+    /// the reply itself is empty. A replica's backlog is never capped.
+    fn sync(&mut self, psync: Option<(&str, u64)>) -> String {
+        if self.outbox.is_some() {
+            return "ERR SYNC is not allowed on a subscribed or replica connection".to_string();
+        }
+        if !self.transaction_stack.is_empty() {
+            return "ERR SYNC is not allowed inside a transaction".to_string();
+        }
+        let (outbox, receiver) = Outbox::with_limit(
+            self.client.id,
+            OutputBufferLimit::default(),
+            Arc::clone(&self.output_buffer_disconnections),
+        );
+        self.outbox = Some(outbox.clone());
+        self.outbox_receiver = Some(receiver);
+        self.executor.attach_replica(self.client.id, &self.client.addr, outbox, psync);
+        String::new()
+    }
+
+    /// Saves (unless NOSAVE), then asks the server to stop and closes this connection
+    ///
+    /// Returns the reply to send, which is empty on success: like Redis, a
+    /// successful SHUTDOWN just closes the connection.
+    fn shutdown(&mut self, mode: ShutdownMode) -> String {
+        let Some(shutdown) = self.shutdown.clone() else {
+            return "ERR SHUTDOWN is not available on this connection".to_string();
+        };
+        let save = mode != ShutdownMode::NoSave;
+        if save {
+            if let Err(e) = self.executor.save_snapshot(&shutdown.snapshot_path) {
+                tracing::error!(error = %e, "SHUTDOWN failed to save snapshot");
+                return "ERR Errors trying to SHUTDOWN. Check logs.".to_string();
+            }
+        }
+        tracing::info!(client_id = self.client.id, save, "SHUTDOWN requested by client");
+        shutdown.request(save);
+        self.closing = true;
+        String::new()
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.clients.unregister(self.client.id);
+        self.pubsub.unsubscribe_all(self.client.id);
+        self.executor.detach_replica(self.client.id);
+    }
+}
@@ -0,0 +1,154 @@
+//! # Maintenance Module
+//!
+//! The server's single background thread for periodic work: reaping
+//! expired keys, purging the cache and saving a snapshot once a save
+//! condition is met. Every registered task runs once per tick, in the order
+//! it was registered, and takes whatever lock it needs itself, so nothing
+//! is held between tasks. How long each task takes is kept for INFO.
+use std::fmt::Write;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Work the maintenance thread runs on every tick
+pub type MaintenanceTask = Arc<dyn Fn() + Send + Sync>;
+
+#[derive(Default)]
+struct TaskStat {
+    runs: u64,
+    usec_total: u64,
+}
+
+/// Run counts and timings of the maintenance tasks, read by INFO maintenance
+#[derive(Default)]
+pub struct MaintenanceStats {
+    tick_ms: AtomicU64,
+    ticks: AtomicU64,
+    // In registration order
+    tasks: Mutex<Vec<(&'static str, TaskStat)>>,
+}
+
+impl MaintenanceStats {
+    fn record(&self, name: &'static str, elapsed: Duration) {
+        let mut tasks = self.tasks.lock().unwrap();
+        let index = match tasks.iter().position(|(task, _)| *task == name) {
+            Some(index) => index,
+            None => {
+                tasks.push((name, TaskStat::default()));
+                tasks.len() - 1
+            }
+        };
+        let stat = &mut tasks[index].1;
+        stat.runs += 1;
+        stat.usec_total += elapsed.as_micros() as u64;
+    }
+
+    /// Returns how many ticks have completed
+    pub fn ticks(&self) -> u64 {
+        self.ticks.load(Ordering::Relaxed)
+    }
+
+    /// Returns how many times the task `name` has run
+    pub fn runs(&self, name: &str) -> u64 {
+        let tasks = self.tasks.lock().unwrap();
+        tasks.iter().find(|(task, _)| *task == name).map_or(0, |(_, stat)| stat.runs)
+    }
+
+    /// Formats `INFO maintenance`: the tick, then one `task_<name>` line per task that has run
+    pub fn info(&self) -> String {
+        let mut output = format!(
+            "# Maintenance\nmaintenance_tick_ms:{}\nmaintenance_ticks:{}\n",
+            self.tick_ms.load(Ordering::Relaxed),
+            self.ticks()
+        );
+        for (name, stat) in self.tasks.lock().unwrap().iter() {
+            let _ = writeln!(
+                output,
+                "task_{}:runs={},usec={},usec_per_run={:.3}",
+                name, stat.runs, stat.usec_total, stat.usec_total as f64 / stat.runs as f64
+            );
+        }
+        output
+    }
+}
+
+/// The tasks to run and how often, owned by the server until it starts serving
+pub struct Maintenance {
+    tick: Duration,
+    tasks: Vec<(&'static str, MaintenanceTask)>,
+    stats: Arc<MaintenanceStats>,
+}
+
+impl Maintenance {
+    /// Creates a schedule with no tasks
+    ///
+    /// # Arguments
+    ///
+    /// * `tick` - Time between the starts of two rounds of tasks
+    /// * `stats` - Where run counts and timings are recorded
+    pub fn new(tick: Duration, stats: Arc<MaintenanceStats>) -> Self {
+        stats.tick_ms.store(tick.as_millis() as u64, Ordering::Relaxed);
+        Maintenance { tick, tasks: Vec::new(), stats }
+    }
+
+    /// Adds a task that runs on every tick after the ones already registered
+    ///
+    /// A task should do a bounded amount of work: the next tick waits for
+    /// it, and so does stopping the thread.
+    pub fn register(&mut self, name: &'static str, task: impl Fn() + Send + Sync + 'static) {
+        self.tasks.push((name, Arc::new(task)));
+    }
+
+    /// Starts running the tasks on a new thread
+    pub fn spawn(&self) -> io::Result<MaintenanceThread> {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let tick = self.tick;
+        let tasks = self.tasks.clone();
+        let stats = Arc::clone(&self.stats);
+        let thread = thread::Builder::new()
+            .name("redis-maintenance".to_string())
+            .spawn(move || {
+                let mut next_tick = Instant::now() + tick;
+                // Woken early, and for good, when the sender is dropped
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(next_tick.saturating_duration_since(Instant::now())) {
+                    for (name, task) in &tasks {
+                        let started = Instant::now();
+                        task();
+                        stats.record(name, started.elapsed());
+                    }
+                    stats.ticks.fetch_add(1, Ordering::Relaxed);
+                    next_tick = (next_tick + tick).max(Instant::now());
+                }
+            })?;
+        Ok(MaintenanceThread { stop: Some(stop), thread: Some(thread) })
+    }
+}
+
+/// The running maintenance thread; stopped when dropped
+pub struct MaintenanceThread {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MaintenanceThread {
+    /// Stops the thread once its current task, if any, returns, and waits for it
+    pub fn stop(mut self) {
+        self.shut_down();
+    }
+
+    fn shut_down(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for MaintenanceThread {
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+}
@@ -1,2 +1,10 @@
 pub mod server;
+pub mod async_server;
 pub mod connection;
+pub mod client;
+pub mod pubsub;
+pub mod resp;
+pub mod acl;
+pub mod maintenance;
+pub mod replica;
+pub mod stats;
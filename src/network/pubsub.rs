@@ -0,0 +1,382 @@
+//! # Pub/Sub Module
+//!
+//! Channel registry shared by every connection. SUBSCRIBE adds the
+//! connection's outbox to a channel, PSUBSCRIBE to a glob pattern, and
+//! PUBLISH pushes a `message` frame into every outbox on the channel plus a
+//! `pmessage` frame for every matching pattern. Outboxes are unbounded
+//! channels drained by whichever front end owns the socket; each one keeps
+//! count of the bytes its client hasn't taken yet and cuts the client off
+//! once they break its output buffer limit, so a subscriber that stops
+//! reading can't make the server buffer without bound.
+//!
+//! Frames, and the replies confirming (un)subscriptions, are RESP arrays
+//! exactly as Redis sends them, so Pub/Sub clients can parse them unchanged.
+use crate::config::config::OutputBufferLimit;
+use crate::storage::glob::GlobPattern;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// Bytes queued for a subscribed client's socket
+///
+/// Once the bytes waiting in it break the output buffer limit, the outbox
+/// refuses everything from then on, which drops the client from every
+/// channel, and runs the hook its receiver set to close the connection.
+#[derive(Clone)]
+pub struct Outbox {
+    sender: UnboundedSender<Vec<u8>>,
+    buffer: Arc<OutputBuffer>,
+}
+
+/// The receiving end of an `Outbox`, drained by the client's writer
+pub struct OutboxReceiver {
+    receiver: UnboundedReceiver<Vec<u8>>,
+    buffer: Arc<OutputBuffer>,
+}
+
+type OverflowHook = Box<dyn FnOnce() + Send>;
+
+/// State shared by both ends of an outbox
+struct OutputBuffer {
+    client_id: u64,
+    limit: OutputBufferLimit,
+    // Sent but not yet taken by the receiver
+    pending: AtomicUsize,
+    over_soft_since: Mutex<Option<Instant>>,
+    overflowed: AtomicBool,
+    on_overflow: Mutex<Option<OverflowHook>>,
+    disconnections: Arc<AtomicU64>,
+}
+
+impl Outbox {
+    /// Creates an outbox that never cuts its client off
+    pub fn unbounded() -> (Outbox, OutboxReceiver) {
+        Self::with_limit(0, OutputBufferLimit::default(), Arc::new(AtomicU64::new(0)))
+    }
+
+    /// Creates an outbox for `client_id` that enforces `limit`
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The client the outbox belongs to, for the log line
+    /// * `limit` - When the backlog gets the client disconnected
+    /// * `disconnections` - Incremented when that happens
+    pub fn with_limit(client_id: u64, limit: OutputBufferLimit, disconnections: Arc<AtomicU64>) -> (Outbox, OutboxReceiver) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let buffer = Arc::new(OutputBuffer {
+            client_id,
+            limit,
+            pending: AtomicUsize::new(0),
+            over_soft_since: Mutex::new(None),
+            overflowed: AtomicBool::new(false),
+            on_overflow: Mutex::new(None),
+            disconnections,
+        });
+        (Outbox { sender, buffer: Arc::clone(&buffer) }, OutboxReceiver { receiver, buffer })
+    }
+
+    /// Queues `bytes` for the client
+    ///
+    /// # Returns
+    ///
+    /// `false` if the client has gone away or is over its output buffer limit
+    pub fn send(&self, bytes: Vec<u8>) -> bool {
+        let buffer = &self.buffer;
+        if buffer.overflowed.load(Ordering::Acquire) {
+            return false;
+        }
+        let pending = buffer.pending.fetch_add(bytes.len(), Ordering::AcqRel) + bytes.len();
+        if buffer.limit.is_exceeded(pending, &mut buffer.over_soft_since.lock().unwrap()) {
+            buffer.overflow(pending);
+            return false;
+        }
+        self.sender.send(bytes).is_ok()
+    }
+
+    /// Returns how many bytes wait for the client's writer
+    pub fn pending(&self) -> usize {
+        self.buffer.pending.load(Ordering::Acquire)
+    }
+}
+
+impl OutputBuffer {
+    fn overflow(&self, pending: usize) {
+        if self.overflowed.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.disconnections.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            client_id = self.client_id,
+            pending_bytes = pending,
+            "closing client that reached its output buffer limit"
+        );
+        if let Some(hook) = self.on_overflow.lock().unwrap().take() {
+            hook();
+        }
+    }
+
+    fn taken(&self, bytes: &[u8]) {
+        self.pending.fetch_sub(bytes.len(), Ordering::AcqRel);
+    }
+}
+
+impl OutboxReceiver {
+    /// Waits for the next queued bytes; `None` once the client was cut off
+    pub async fn recv(&mut self) -> Option<Vec<u8>> {
+        let bytes = self.receiver.recv().await?;
+        self.take(bytes)
+    }
+
+    /// Same as `recv`, for a writer on its own thread
+    pub fn blocking_recv(&mut self) -> Option<Vec<u8>> {
+        let bytes = self.receiver.blocking_recv()?;
+        self.take(bytes)
+    }
+
+    /// Returns queued bytes without waiting
+    pub fn try_recv(&mut self) -> Result<Vec<u8>, TryRecvError> {
+        let bytes = self.receiver.try_recv()?;
+        self.take(bytes).ok_or(TryRecvError::Disconnected)
+    }
+
+    /// Runs `hook` once the client goes over its limit, or right away if it already has
+    ///
+    /// The front end closes the connection here; its writer may be stuck
+    /// writing to a client that stopped reading.
+    pub fn on_overflow(&self, hook: impl FnOnce() + Send + 'static) {
+        if self.buffer.overflowed.load(Ordering::Acquire) {
+            hook();
+            return;
+        }
+        *self.buffer.on_overflow.lock().unwrap() = Some(Box::new(hook));
+        // Overflowed while the hook was being installed
+        if self.buffer.overflowed.load(Ordering::Acquire) {
+            if let Some(hook) = self.buffer.on_overflow.lock().unwrap().take() {
+                hook();
+            }
+        }
+    }
+
+    fn take(&self, bytes: Vec<u8>) -> Option<Vec<u8>> {
+        self.buffer.taken(&bytes);
+        (!self.buffer.overflowed.load(Ordering::Acquire)).then_some(bytes)
+    }
+}
+
+struct Subscriber {
+    client_id: u64,
+    outbox: Outbox,
+}
+
+struct PatternSubscription {
+    pattern: String,
+    compiled: GlobPattern,
+    subscribers: Vec<Subscriber>,
+}
+
+#[derive(Default)]
+struct Registry {
+    channels: HashMap<String, Vec<Subscriber>>,
+    // Pattern subscriptions grouped by the literal prefix every match starts
+    // with, so PUBLISH only tries the patterns whose prefix fits the channel
+    patterns: BTreeMap<Vec<u8>, Vec<PatternSubscription>>,
+}
+
+impl Registry {
+    fn add(&mut self, channel: &str, client_id: u64, outbox: Outbox) -> bool {
+        let subscribers = self.channels.entry(channel.to_string()).or_default();
+        add_subscriber(subscribers, client_id, outbox)
+    }
+
+    fn add_pattern(&mut self, pattern: &str, client_id: u64, outbox: Outbox) -> bool {
+        let compiled = GlobPattern::new(pattern);
+        let group = self.patterns.entry(compiled.literal_prefix().to_vec()).or_default();
+        let index = match group.iter().position(|p| p.pattern == pattern) {
+            Some(index) => index,
+            None => {
+                group.push(PatternSubscription { pattern: pattern.to_string(), compiled, subscribers: Vec::new() });
+                group.len() - 1
+            },
+        };
+        add_subscriber(&mut group[index].subscribers, client_id, outbox)
+    }
+
+    fn remove_pattern(&mut self, pattern: &str, client_id: u64) {
+        let prefix = GlobPattern::new(pattern).literal_prefix().to_vec();
+        if let Some(group) = self.patterns.get_mut(&prefix) {
+            for subscription in group.iter_mut().filter(|p| p.pattern == pattern) {
+                subscription.subscribers.retain(|s| s.client_id != client_id);
+            }
+            group.retain(|p| !p.subscribers.is_empty());
+            if group.is_empty() {
+                self.patterns.remove(&prefix);
+            }
+        }
+    }
+}
+
+/// Encodes a Pub/Sub frame: a RESP array of bulk strings, ending in `count`
+/// as an integer if one is given
+///
+/// A `None` item is sent as a null bulk string, as UNSUBSCRIBE does when
+/// the client had no subscriptions.
+pub(crate) fn encode_frame(items: &[Option<&str>], count: Option<usize>) -> String {
+    let mut frame = format!("*{}\r\n", items.len() + usize::from(count.is_some()));
+    for item in items {
+        match item {
+            Some(item) => frame.push_str(&format!("${}\r\n{}\r\n", item.len(), item)),
+            None => frame.push_str("$-1\r\n"),
+        }
+    }
+    if let Some(count) = count {
+        frame.push_str(&format!(":{}\r\n", count));
+    }
+    frame
+}
+
+fn add_subscriber(subscribers: &mut Vec<Subscriber>, client_id: u64, outbox: Outbox) -> bool {
+    if subscribers.iter().any(|s| s.client_id == client_id) {
+        return false;
+    }
+    subscribers.push(Subscriber { client_id, outbox });
+    true
+}
+
+/// Maps channel names and patterns to the clients subscribed to them
+#[derive(Default)]
+pub struct PubSub {
+    registry: Mutex<Registry>,
+}
+
+impl PubSub {
+    /// Creates a registry with no channels
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribes a client to a channel
+    ///
+    /// # Arguments
+    ///
+    /// * `channel` - Channel name (case-sensitive)
+    /// * `client_id` - The subscribing client
+    /// * `outbox` - Where the client's messages are queued
+    ///
+    /// # Returns
+    ///
+    /// `false` if the client was already subscribed to the channel
+    pub fn subscribe(&self, channel: &str, client_id: u64, outbox: Outbox) -> bool {
+        self.registry.lock().unwrap().add(channel, client_id, outbox)
+    }
+
+    /// Subscribes a client to every channel matching a glob pattern
+    ///
+    /// # Returns
+    ///
+    /// `false` if the client was already subscribed to the pattern
+    pub fn psubscribe(&self, pattern: &str, client_id: u64, outbox: Outbox) -> bool {
+        self.registry.lock().unwrap().add_pattern(pattern, client_id, outbox)
+    }
+
+    /// Queues `reply` in the outbox, then subscribes the client to `channels` and `patterns`
+    ///
+    /// Both happen under the registry lock, so no message published to these
+    /// channels can be queued ahead of the reply confirming the subscription,
+    /// and any PUBLISH issued after the client has read the reply reaches it.
+    pub fn subscribe_after_reply(
+        &self,
+        channels: &[String],
+        patterns: &[String],
+        client_id: u64,
+        outbox: &Outbox,
+        reply: Vec<u8>,
+    ) {
+        let mut registry = self.registry.lock().unwrap();
+        if !reply.is_empty() {
+            outbox.send(reply);
+        }
+        for channel in channels {
+            registry.add(channel, client_id, outbox.clone());
+        }
+        for pattern in patterns {
+            registry.add_pattern(pattern, client_id, outbox.clone());
+        }
+    }
+
+    /// Removes a client from a channel, dropping the channel once it's empty
+    pub fn unsubscribe(&self, channel: &str, client_id: u64) {
+        let mut registry = self.registry.lock().unwrap();
+        if let Some(subscribers) = registry.channels.get_mut(channel) {
+            subscribers.retain(|s| s.client_id != client_id);
+            if subscribers.is_empty() {
+                registry.channels.remove(channel);
+            }
+        }
+    }
+
+    /// Removes a client from a pattern subscription
+    pub fn punsubscribe(&self, pattern: &str, client_id: u64) {
+        self.registry.lock().unwrap().remove_pattern(pattern, client_id);
+    }
+
+    /// Removes a client from every channel and pattern, used when it disconnects
+    pub fn unsubscribe_all(&self, client_id: u64) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.channels.retain(|_, subscribers| {
+            subscribers.retain(|s| s.client_id != client_id);
+            !subscribers.is_empty()
+        });
+        registry.patterns.retain(|_, group| {
+            for subscription in group.iter_mut() {
+                subscription.subscribers.retain(|s| s.client_id != client_id);
+            }
+            group.retain(|p| !p.subscribers.is_empty());
+            !group.is_empty()
+        });
+    }
+
+    /// Sends `message` to every subscriber of `channel` and of each matching pattern
+    ///
+    /// A client subscribed both to the channel and to matching patterns gets
+    /// one frame per subscription. Subscribers whose outbox is closed have
+    /// gone away without unsubscribing; they are pruned here and not counted.
+    ///
+    /// # Returns
+    ///
+    /// The number of frames delivered
+    pub fn publish(&self, channel: &str, message: &str) -> usize {
+        let mut registry = self.registry.lock().unwrap();
+        let mut receivers = 0;
+
+        if let Some(subscribers) = registry.channels.get_mut(channel) {
+            let frame = encode_frame(&[Some("message"), Some(channel), Some(message)], None).into_bytes();
+            subscribers.retain(|s| s.outbox.send(frame.clone()));
+            receivers += subscribers.len();
+            if subscribers.is_empty() {
+                registry.channels.remove(channel);
+            }
+        }
+
+        // Only groups keyed by a prefix of the channel name can match it
+        let bytes = channel.as_bytes();
+        for end in 0..=bytes.len() {
+            let Some(group) = registry.patterns.get_mut(&bytes[..end]) else {
+                continue;
+            };
+            for subscription in group.iter_mut().filter(|p| p.compiled.matches(channel)) {
+                let frame = encode_frame(&[Some("pmessage"), Some(&subscription.pattern), Some(channel), Some(message)], None)
+                    .into_bytes();
+                subscription.subscribers.retain(|s| s.outbox.send(frame.clone()));
+                receivers += subscription.subscribers.len();
+            }
+            group.retain(|p| !p.subscribers.is_empty());
+            if group.is_empty() {
+                registry.patterns.remove(&bytes[..end]);
+            }
+        }
+        receivers
+    }
+}
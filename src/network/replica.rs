@@ -0,0 +1,741 @@
+//! # Replica Module
+//!
+//! Master/replica replication over the inline protocol. A replica opens an
+//! ordinary client connection to its master and sends PSYNC. The master
+//! answers `FULLRESYNC <replid> <offset>` and a length-prefixed snapshot of
+//! its dataset taken at that offset, then every write it executes from
+//! then on, one inline command per line. The replica loads the snapshot in
+//! place of its own data, applies each line through its own executor, and
+//! refuses writes from its own clients while it has a master.
+//!
+//! The replication offset counts the bytes of those write lines. A replica
+//! acknowledges the offset it has applied with `REPLCONF ACK`, which WAIT
+//! counts, and after losing its link asks to continue from it with
+//! `PSYNC <replid> <offset>`. The master answers `CONTINUE <replid>` and
+//! the writes missed if its backlog still holds them, or with a full sync.
+//!
+//! Writes are sent in the form that replays them exactly: a blocking pop
+//! becomes the plain pop it ended up doing, and HINCRBYFLOAT becomes an
+//! HSET of the new value. EXPIRE is sent as is, so a replica's deadlines
+//! trail the master's by the time the line takes to arrive.
+use crate::commands::executor::CommandExecutor;
+use crate::commands::parser::{Command, CommandParser, DebugSubcommand, SortOrder};
+use crate::network::pubsub::Outbox;
+use crate::storage::geo::GeoStoreMode;
+use crate::storage::memory::FrozenStorage;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long a replica waits before reconnecting to a master it lost
+pub const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often an idle replica acknowledges its offset to the master
+pub const ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Most bytes of a full sync's snapshot a replica reads at once
+const SNAPSHOT_CHUNK: usize = 64 * 1024;
+
+/// How long a replica waits for its master to accept the connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Replica {
+    client_id: u64,
+    addr: String,
+    outbox: Outbox,
+    // Writes executed while the full sync was being encoded; `None` once it was sent
+    pending: Option<Vec<u8>>,
+    // The offset the replica last acknowledged with REPLCONF ACK
+    acked: u64,
+}
+
+/// The write stream: every replica it feeds and the recent bytes of it
+struct Stream {
+    replicas: Vec<Replica>,
+    // Created when the first replica attaches, unless the backlog size is 0
+    backlog: Option<VecDeque<u8>>,
+    // Bytes of writes sent since the server started
+    offset: u64,
+}
+
+/// The replicas attached to a master, fed every write it executes
+///
+/// Each write advances the replication offset by the bytes of its line,
+/// and the last `backlog_size` bytes are kept so a replica that lost its
+/// link can resume with PSYNC from the offset it had applied.
+pub struct Replicas {
+    stream: Mutex<Stream>,
+    backlog_size: usize,
+    sync_full: AtomicU64,
+    sync_partial_ok: AtomicU64,
+    sync_partial_err: AtomicU64,
+}
+
+impl Replicas {
+    /// Creates a registry with no replicas, keeping up to `backlog_size` bytes for PSYNC
+    pub fn new(backlog_size: usize) -> Self {
+        Replicas {
+            stream: Mutex::new(Stream { replicas: Vec::new(), backlog: None, offset: 0 }),
+            backlog_size,
+            sync_full: AtomicU64::new(0),
+            sync_partial_ok: AtomicU64::new(0),
+            sync_partial_err: AtomicU64::new(0),
+        }
+    }
+
+    /// Adds a replica whose full sync is still being encoded
+    ///
+    /// Writes propagated from now on are held back until `start_streaming`
+    /// has queued the full sync, so the replica gets them after it. Call
+    /// this with the storage locked, where the dataset for the full sync
+    /// is taken, so no write falls between the two.
+    ///
+    /// # Returns
+    ///
+    /// The replication offset the full sync is taken at
+    pub fn attach(&self, client_id: u64, addr: &str, outbox: Outbox) -> u64 {
+        let mut stream = self.stream.lock().unwrap();
+        if stream.backlog.is_none() && self.backlog_size > 0 {
+            stream.backlog = Some(VecDeque::new());
+        }
+        stream.replicas.push(Replica {
+            client_id,
+            addr: addr.to_string(),
+            outbox,
+            pending: Some(Vec::new()),
+            acked: 0,
+        });
+        self.sync_full.fetch_add(1, Ordering::Relaxed);
+        stream.offset
+    }
+
+    /// Adds a replica that asked with PSYNC to continue from offset `from`
+    ///
+    /// Succeeds if `replication_id` names the current stream and the bytes
+    /// after `from` are all still in the backlog; the replica is then sent
+    /// `CONTINUE <id>` and those bytes, and streams from there like any other.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the replica needs a full sync instead; nothing changes then
+    pub fn resume(&self, client_id: u64, addr: &str, outbox: Outbox, replication_id: &str, from: u64, current_id: &str) -> bool {
+        let mut stream = self.stream.lock().unwrap();
+        let offset = stream.offset;
+        let missed = match &stream.backlog {
+            Some(backlog) if replication_id == current_id && from <= offset && offset - from <= backlog.len() as u64 => {
+                backlog.range(backlog.len() - (offset - from) as usize..).copied().collect::<Vec<u8>>()
+            }
+            _ => {
+                self.sync_partial_err.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+        };
+        let mut payload = format!("CONTINUE {}\r\n", current_id).into_bytes();
+        payload.extend_from_slice(&missed);
+        if outbox.send(payload) {
+            stream.replicas.push(Replica { client_id, addr: addr.to_string(), outbox, pending: None, acked: from });
+        }
+        self.sync_partial_ok.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Queues `full_sync` for the replica, then the writes held back meanwhile
+    pub fn start_streaming(&self, client_id: u64, full_sync: Vec<u8>) {
+        let mut stream = self.stream.lock().unwrap();
+        let Some(replica) = stream.replicas.iter_mut().find(|replica| replica.client_id == client_id) else {
+            return;
+        };
+        let pending = replica.pending.take().unwrap_or_default();
+        let sent = replica.outbox.send(full_sync) && (pending.is_empty() || replica.outbox.send(pending));
+        if !sent {
+            stream.replicas.retain(|replica| replica.client_id != client_id);
+        }
+    }
+
+    /// Records a replica's REPLCONF ACK
+    ///
+    /// # Returns
+    ///
+    /// `false` if `client_id` isn't an attached replica
+    pub fn ack(&self, client_id: u64, offset: u64) -> bool {
+        let mut stream = self.stream.lock().unwrap();
+        match stream.replicas.iter_mut().find(|replica| replica.client_id == client_id) {
+            Some(replica) => {
+                replica.acked = replica.acked.max(offset);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forgets a replica, once its connection has closed
+    pub fn detach(&self, client_id: u64) {
+        self.stream.lock().unwrap().replicas.retain(|replica| replica.client_id != client_id);
+    }
+
+    /// Returns how many replicas are attached
+    pub fn count(&self) -> usize {
+        self.stream.lock().unwrap().replicas.len()
+    }
+
+    /// Sends one executed write to every replica and appends it to the backlog
+    ///
+    /// Replicas whose connection has gone away are dropped.
+    ///
+    /// # Returns
+    ///
+    /// The replication offset after the write, or `None` while no replica
+    /// has ever attached and the write went nowhere
+    pub fn propagate(&self, args: &[String]) -> Option<u64> {
+        let mut stream = self.stream.lock().unwrap();
+        if stream.replicas.is_empty() && stream.backlog.is_none() {
+            return None;
+        }
+        let mut line = args.join(" ").into_bytes();
+        line.extend_from_slice(b"\r\n");
+        stream.offset += line.len() as u64;
+        if let Some(backlog) = &mut stream.backlog {
+            backlog.extend(&line);
+            let excess = backlog.len().saturating_sub(self.backlog_size);
+            backlog.drain(..excess);
+        }
+        stream.replicas.retain_mut(|replica| match &mut replica.pending {
+            Some(pending) => {
+                pending.extend_from_slice(&line);
+                true
+            }
+            None => replica.outbox.send(line.clone()),
+        });
+        Some(stream.offset)
+    }
+
+    /// Formats one `slave<n>:id=<client id>,addr=<addr>,state=<state>,offset=<acked>` line per replica
+    ///
+    /// The state is `wait_bgsave` until the full sync has been queued, then `online`.
+    pub fn info(&self) -> String {
+        let mut output = String::new();
+        for (index, replica) in self.stream.lock().unwrap().replicas.iter().enumerate() {
+            let state = if replica.pending.is_some() { "wait_bgsave" } else { "online" };
+            let _ = writeln!(
+                output,
+                "slave{}:id={},addr={},state={},offset={}",
+                index, replica.client_id, replica.addr, state, replica.acked
+            );
+        }
+        output
+    }
+
+    /// Formats the `repl_backlog_*` INFO replication lines
+    pub fn backlog_info(&self) -> String {
+        let stream = self.stream.lock().unwrap();
+        let histlen = stream.backlog.as_ref().map_or(0, VecDeque::len);
+        format!(
+            "repl_backlog_active:{}\nrepl_backlog_size:{}\nrepl_backlog_first_byte_offset:{}\nrepl_backlog_histlen:{}\n",
+            u8::from(stream.backlog.is_some()),
+            self.backlog_size,
+            stream.offset - histlen as u64,
+            histlen
+        )
+    }
+
+    /// Formats the `sync_full`, `sync_partial_ok` and `sync_partial_err` INFO stats lines
+    pub fn sync_stats(&self) -> String {
+        format!(
+            "sync_full:{}\nsync_partial_ok:{}\nsync_partial_err:{}\n",
+            self.sync_full.load(Ordering::Relaxed),
+            self.sync_partial_ok.load(Ordering::Relaxed),
+            self.sync_partial_err.load(Ordering::Relaxed)
+        )
+    }
+}
+
+/// Encodes the reply to SYNC: the FULLRESYNC header, then `data` as a length-prefixed snapshot
+///
+/// The header is `FULLRESYNC <replid> <offset>`, the offset `data` was
+/// taken at; `$<len>` and the snapshot's bytes follow, which don't count
+/// towards the offset.
+pub fn full_sync(data: &FrozenStorage, replication_id: &str, offset: u64) -> Vec<u8> {
+    let mut snapshot = Vec::new();
+    // Writing to memory can't fail
+    let _ = data.write_snapshot(&mut snapshot);
+    let mut payload = format!("FULLRESYNC {} {}\r\n${}\r\n", replication_id, offset, snapshot.len()).into_bytes();
+    payload.append(&mut snapshot);
+    payload
+}
+
+/// Returns the arguments replicas replay `command` with, given the reply it got
+///
+/// `None` for commands that don't write, for failed writes, and for pops
+/// that found nothing. Blocking pops are left to the executor, which sends
+/// the pop they ended up doing while it still holds the storage lock.
+pub fn replicated(command: &Command, response: &str) -> Option<Vec<String>> {
+    if ["ERR", "WRONGTYPE", "NOGROUP", "BUSYGROUP"].iter().any(|error| response.starts_with(error)) {
+        return None;
+    }
+    let args = |words: &[&str]| Some(words.iter().map(|word| word.to_string()).collect::<Vec<_>>());
+    match command {
+        Command::Set(key, value) => args(&["SET", key, value]),
+        Command::Del(key) => args(&["DEL", key]),
+        Command::Incr(key) => args(&["INCR", key]),
+        Command::Decr(key) => args(&["DECR", key]),
+        Command::LPush(key, value) => args(&["LPUSH", key, value]),
+        Command::RPush(key, value) => args(&["RPUSH", key, value]),
+        Command::LPop(_) | Command::RPop(_) if response == "(nil)" => None,
+        Command::LPop(key) => args(&["LPOP", key]),
+        Command::RPop(key) => args(&["RPOP", key]),
+        Command::Expire(key, seconds) => args(&["EXPIRE", key, &seconds.to_string()]),
+        // Only a deadline that was actually set needs to reach the replicas
+        Command::ExpireOpts(..) if response == "0" => None,
+        Command::ExpireOpts(key, seconds, _) => args(&["EXPIRE", key, &seconds.to_string()]),
+        Command::Persist(_) if response == "0" => None,
+        Command::Persist(key) => args(&["PERSIST", key]),
+        Command::Copy(..) if response == "0" => None,
+        Command::Copy(source, destination, _, replace) => {
+            let mut words = vec!["COPY".to_string(), source.clone(), destination.clone()];
+            if *replace {
+                words.push("REPLACE".to_string());
+            }
+            Some(words)
+        }
+        Command::SAdd(key, members) => Some(with_key("SADD", key, members)),
+        Command::SMove(source, destination, member) => args(&["SMOVE", source, destination, member]),
+        Command::SDiffStore(destination, keys) => Some(with_key("SDIFFSTORE", destination, keys)),
+        Command::SUnionStore(destination, keys) => Some(with_key("SUNIONSTORE", destination, keys)),
+        Command::SInterStore(destination, keys) => Some(with_key("SINTERSTORE", destination, keys)),
+        Command::HSet(key, pairs) | Command::HMSet(key, pairs) => {
+            let flat: Vec<String> = pairs.iter().flat_map(|(field, value)| [field.clone(), value.clone()]).collect();
+            Some(with_key("HSET", key, &flat))
+        }
+        Command::HSetNx(key, field, value) => args(&["HSETNX", key, field, value]),
+        Command::HIncrBy(key, field, increment) => args(&["HINCRBY", key, field, &increment.to_string()]),
+        // Replaying the addition could round differently; the result can't
+        Command::HIncrByFloat(key, field, _) => args(&["HSET", key, field, response]),
+        Command::ZAdd(key, options, pairs) => {
+            let mut words = vec!["ZADD".to_string(), key.clone()];
+            for (set, flag) in [
+                (options.nx, "NX"),
+                (options.xx, "XX"),
+                (options.gt, "GT"),
+                (options.lt, "LT"),
+                (options.ch, "CH"),
+                (options.incr, "INCR"),
+            ] {
+                if set {
+                    words.push(flag.to_string());
+                }
+            }
+            for (score, member) in pairs {
+                words.push(score.to_string());
+                words.push(member.clone());
+            }
+            Some(words)
+        }
+        Command::ZIncrBy(key, increment, member) => args(&["ZINCRBY", key, &increment.to_string(), member]),
+        Command::GeoAdd { key, options, items } => {
+            let mut words = vec!["GEOADD".to_string(), key.clone()];
+            for (set, flag) in [(options.nx, "NX"), (options.xx, "XX"), (options.ch, "CH")] {
+                if set {
+                    words.push(flag.to_string());
+                }
+            }
+            for (longitude, latitude, member) in items {
+                words.extend([longitude.to_string(), latitude.to_string(), member.clone()]);
+            }
+            Some(words)
+        }
+        Command::GeoSearch { key, params, store: Some((mode, destination)) } => {
+            let mut words = vec!["GEOSEARCH".to_string(), key.clone()];
+            words.extend(params.to_args());
+            let option = match mode {
+                GeoStoreMode::Geohash => "STORE",
+                GeoStoreMode::Distance => "STOREDIST",
+            };
+            words.extend([option.to_string(), destination.clone()]);
+            Some(words)
+        }
+        // Replicas get the ID the master generated, so both sides agree on it
+        Command::XDel(..) if response == "0" => None,
+        Command::XDel(key, ids) => Some(with_key("XDEL", key, ids)),
+        Command::XAdd(key, _, fields) => {
+            let flat: Vec<String> = fields.iter().flat_map(|(field, value)| [field.clone(), value.clone()]).collect();
+            Some(with_key("XADD", key, &[&[response.to_string()], flat.as_slice()].concat()))
+        }
+        Command::XGroupCreate(key, group, id, mkstream) => {
+            let id = id.map_or_else(|| "$".to_string(), |id| id.to_string());
+            let mut words = vec!["XGROUP".to_string(), "CREATE".to_string(), key.clone(), group.clone(), id];
+            if *mkstream {
+                words.push("MKSTREAM".to_string());
+            }
+            Some(words)
+        }
+        // Rereading pending entries changes nothing a replica keeps
+        Command::XReadGroup { .. } if response == "(nil)" => None,
+        Command::XReadGroup { streams, .. } if streams.iter().all(|(_, id)| id.is_some()) => None,
+        Command::XReadGroup { group, consumer, count, noack, streams } => {
+            let mut words = vec!["XREADGROUP".to_string(), "GROUP".to_string(), group.clone(), consumer.clone()];
+            if let Some(count) = count {
+                words.extend(["COUNT".to_string(), count.to_string()]);
+            }
+            if *noack {
+                words.push("NOACK".to_string());
+            }
+            words.push("STREAMS".to_string());
+            words.extend(streams.iter().map(|(key, _)| key.clone()));
+            words.extend(streams.iter().map(|(_, id)| id.map_or_else(|| ">".to_string(), |id| id.to_string())));
+            Some(words)
+        }
+        Command::XAutoClaim { key, group, consumer, min_idle_ms, start, count } => args(&[
+            "XAUTOCLAIM",
+            key,
+            group,
+            consumer,
+            &min_idle_ms.to_string(),
+            &start.to_string(),
+            "COUNT",
+            &count.to_string(),
+        ]),
+        Command::ZPopMin(key, count) | Command::ZPopMax(key, count) => {
+            let name = if matches!(command, Command::ZPopMin(..)) { "ZPOPMIN" } else { "ZPOPMAX" };
+            let mut words = vec![name.to_string(), key.clone()];
+            words.extend(count.map(|count| count.to_string()));
+            Some(words)
+        }
+        Command::FlushAll | Command::Debug(DebugSubcommand::Flushall) => args(&["FLUSHALL"]),
+        Command::Sort { key, by, limit, get_patterns, order, alpha, store: Some(destination) } => {
+            let mut words = vec!["SORT".to_string(), key.clone()];
+            if let Some(pattern) = by {
+                words.extend(["BY".to_string(), pattern.clone()]);
+            }
+            if let Some((offset, count)) = limit {
+                words.extend(["LIMIT".to_string(), offset.to_string(), count.to_string()]);
+            }
+            for pattern in get_patterns {
+                words.extend(["GET".to_string(), pattern.clone()]);
+            }
+            if *order == SortOrder::Desc {
+                words.push("DESC".to_string());
+            }
+            if *alpha {
+                words.push("ALPHA".to_string());
+            }
+            words.extend(["STORE".to_string(), destination.clone()]);
+            Some(words)
+        }
+        _ => None,
+    }
+}
+
+fn with_key(name: &str, key: &str, rest: &[String]) -> Vec<String> {
+    let mut words = vec![name.to_string(), key.to_string()];
+    words.extend(rest.iter().cloned());
+    words
+}
+
+/// What the replication thread knows of its master, shared with `Link`
+struct Master {
+    host: String,
+    port: u16,
+    // Whether the link is up and the dataset synced
+    up: AtomicBool,
+    // The offset of the master's stream applied so far
+    offset: AtomicU64,
+    sync_in_progress: AtomicBool,
+    sync_total_bytes: AtomicU64,
+    sync_read_bytes: AtomicU64,
+    // How long the last completed full sync took, from FULLRESYNC to loaded
+    last_sync_ms: AtomicU64,
+}
+
+impl Master {
+    fn new(host: String, port: u16) -> Self {
+        Master {
+            host,
+            port,
+            up: AtomicBool::new(false),
+            offset: AtomicU64::new(0),
+            sync_in_progress: AtomicBool::new(false),
+            sync_total_bytes: AtomicU64::new(0),
+            sync_read_bytes: AtomicU64::new(0),
+            last_sync_ms: AtomicU64::new(0),
+        }
+    }
+}
+
+/// The running connection to a master
+struct Link {
+    master: Arc<Master>,
+    // Dropped to stop the thread between connection attempts
+    stop: Option<Sender<()>>,
+    // Shut down to stop the thread while it waits for the master
+    stream: Arc<Mutex<Option<TcpStream>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Link {
+    fn stop(mut self) {
+        self.stop.take();
+        if let Some(stream) = self.stream.lock().unwrap().take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// What REPLICAOF set: the master this server replicates, if any
+#[derive(Default)]
+pub struct MasterLink {
+    link: Mutex<Option<Link>>,
+}
+
+impl MasterLink {
+    /// Creates the link of a server that is a master itself
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the server replicates a master, and so refuses client writes
+    pub fn is_replica(&self) -> bool {
+        self.link.lock().unwrap().is_some()
+    }
+
+    /// Formats the INFO replication lines of a replica, `None` on a master
+    ///
+    /// Reports the master, whether the link is up, the offset applied so
+    /// far, how far a full sync in progress got, and how many milliseconds
+    /// the last one took.
+    pub fn info(&self) -> Option<String> {
+        let link = self.link.lock().unwrap();
+        let master = &link.as_ref()?.master;
+        let status = if master.up.load(Ordering::SeqCst) { "up" } else { "down" };
+        Some(format!(
+            "role:slave\nmaster_host:{}\nmaster_port:{}\nmaster_link_status:{}\nslave_repl_offset:{}\n\
+             master_sync_in_progress:{}\nmaster_sync_total_bytes:{}\nmaster_sync_read_bytes:{}\nmaster_sync_last_duration_ms:{}\n",
+            master.host,
+            master.port,
+            status,
+            master.offset.load(Ordering::SeqCst),
+            u8::from(master.sync_in_progress.load(Ordering::SeqCst)),
+            master.sync_total_bytes.load(Ordering::SeqCst),
+            master.sync_read_bytes.load(Ordering::SeqCst),
+            master.last_sync_ms.load(Ordering::SeqCst)
+        ))
+    }
+
+    /// Starts replicating `host:port`, dropping the link to any previous master
+    ///
+    /// The connection runs on its own thread and is retried every
+    /// `RECONNECT_INTERVAL` while the master can't be reached. The first
+    /// time it connects, the local dataset is replaced by the master's;
+    /// after that it asks to continue from the offset it had applied, and
+    /// is only sent the whole dataset again if the master can't.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the server already replicates `host:port`; nothing changes then
+    pub fn start(&self, host: String, port: u16, executor: Arc<CommandExecutor>) -> io::Result<bool> {
+        let mut link = self.link.lock().unwrap();
+        if link.as_ref().is_some_and(|link| link.master.host == host && link.master.port == port) {
+            return Ok(false);
+        }
+        if let Some(previous) = link.take() {
+            previous.stop();
+        }
+        let master = Arc::new(Master::new(host, port));
+        let stream = Arc::new(Mutex::new(None));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = {
+            let (master, stream) = (Arc::clone(&master), Arc::clone(&stream));
+            thread::Builder::new()
+                .name("redis-replica".to_string())
+                .spawn(move || replicate(&master, &executor, &stream, &stopped))?
+        };
+        tracing::info!(master_host = %master.host, master_port = port, "replicating master");
+        *link = Some(Link { master, stop: Some(stop), stream, thread: Some(thread) });
+        Ok(true)
+    }
+
+    /// Drops the link to the master, keeping the data replicated so far
+    ///
+    /// Does nothing on a server that isn't a replica.
+    pub fn stop(&self) {
+        let link = self.link.lock().unwrap().take();
+        if let Some(link) = link {
+            tracing::info!(master_host = %link.master.host, master_port = link.master.port, "stopped replicating master");
+            link.stop();
+        }
+    }
+}
+
+/// Syncs with the master until stopped, reconnecting whenever the link drops
+fn replicate(master: &Master, executor: &CommandExecutor, stream: &Mutex<Option<TcpStream>>, stopped: &mpsc::Receiver<()>) {
+    let (host, port) = (&master.host, master.port);
+    // The id of the stream the local dataset follows, once a full sync completed
+    let mut replication_id = None;
+    loop {
+        match sync_with(master, executor, &mut replication_id, stream, stopped) {
+            Ok(()) => tracing::info!(master_host = %host, master_port = port, "master closed the replication link"),
+            Err(e) => tracing::warn!(master_host = %host, master_port = port, error = %e, "replication link failed"),
+        }
+        master.up.store(false, Ordering::SeqCst);
+        master.sync_in_progress.store(false, Ordering::SeqCst);
+        if let Err(RecvTimeoutError::Disconnected) | Ok(()) = stopped.recv_timeout(RECONNECT_INTERVAL) {
+            return;
+        }
+    }
+}
+
+/// Connects, sends PSYNC and applies what the master streams back until the connection ends
+///
+/// Asks to continue the stream `replication_id` names from the offset
+/// applied so far, or for a full sync while there is none. Acknowledges
+/// the offset with `REPLCONF ACK` whenever it has applied all it read, and
+/// every `ACK_INTERVAL` while the master is idle.
+fn sync_with(
+    master: &Master,
+    executor: &CommandExecutor,
+    replication_id: &mut Option<String>,
+    stream: &Mutex<Option<TcpStream>>,
+    stopped: &mpsc::Receiver<()>,
+) -> io::Result<()> {
+    let (host, port) = (master.host.as_str(), master.port);
+    let address = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "master address did not resolve"))?;
+    let mut connection = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+    *stream.lock().unwrap() = Some(connection.try_clone()?);
+    // Stopped while connecting, before the stream could be shut down
+    if let Err(TryRecvError::Disconnected) = stopped.try_recv() {
+        return Ok(());
+    }
+    let request = match replication_id {
+        Some(id) => format!("PSYNC {} {}\r\n", id, master.offset.load(Ordering::SeqCst)),
+        None => "PSYNC ? -1\r\n".to_string(),
+    };
+    connection.write_all(request.as_bytes())?;
+
+    let mut reader = BufReader::new(connection.try_clone()?);
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(());
+    }
+    let header = String::from_utf8_lossy(&line).trim().to_string();
+    match header.split(' ').collect::<Vec<_>>()[..] {
+        ["FULLRESYNC", id, offset] => {
+            let Ok(offset) = offset.parse::<u64>() else {
+                return Err(io::Error::other(format!("malformed FULLRESYNC: {}", header)));
+            };
+            let started = Instant::now();
+            let Some(snapshot) = receive_snapshot(master, &mut reader)? else {
+                return Ok(());
+            };
+            // Loading replaces every key, with the storage locked throughout
+            executor.load_snapshot_from(&snapshot[..])?;
+            *replication_id = Some(id.to_string());
+            master.offset.store(offset, Ordering::SeqCst);
+            master.sync_in_progress.store(false, Ordering::SeqCst);
+            master.last_sync_ms.store(started.elapsed().as_millis() as u64, Ordering::SeqCst);
+            master.up.store(true, Ordering::SeqCst);
+            tracing::info!(
+                master_host = %host,
+                master_port = port,
+                bytes = snapshot.len(),
+                duration_ms = started.elapsed().as_millis() as u64,
+                "full sync with master done"
+            );
+        }
+        ["CONTINUE", _] => {
+            master.up.store(true, Ordering::SeqCst);
+            tracing::info!(
+                master_host = %host,
+                master_port = port,
+                offset = master.offset.load(Ordering::SeqCst),
+                "partial resync with master accepted"
+            );
+        }
+        _ => return Err(io::Error::other(format!("master refused PSYNC: {}", header))),
+    }
+
+    connection.set_read_timeout(Some(ACK_INTERVAL))?;
+    let ack = |connection: &mut TcpStream| {
+        connection.write_all(format!("REPLCONF ACK {}\r\n", master.offset.load(Ordering::SeqCst)).as_bytes())
+    };
+    ack(&mut connection)?;
+    loop {
+        line.clear();
+        // A timeout leaves what it read of a line in `line`; reading on appends the rest
+        loop {
+            match reader.read_until(b'\n', &mut line) {
+                Ok(_) => break,
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => ack(&mut connection)?,
+                Err(e) => return Err(e),
+            }
+        }
+        // A line cut short by the connection closing was never applied
+        if !line.ends_with(b"\n") {
+            return Ok(());
+        }
+        apply(executor, &line);
+        master.offset.fetch_add(line.len() as u64, Ordering::SeqCst);
+        if reader.buffer().is_empty() {
+            ack(&mut connection)?;
+        }
+    }
+}
+
+/// Reads the `$<len>` line and the snapshot after FULLRESYNC, recording progress for INFO
+///
+/// # Returns
+///
+/// `None` if the master closed the connection before all of it arrived
+fn receive_snapshot(master: &Master, reader: &mut BufReader<TcpStream>) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    let length = String::from_utf8_lossy(&line);
+    let Some(total) = length.trim().strip_prefix('$').and_then(|total| total.parse::<u64>().ok()) else {
+        return Err(io::Error::other(format!("expected the snapshot length, got: {}", length.trim())));
+    };
+    master.sync_total_bytes.store(total, Ordering::SeqCst);
+    master.sync_read_bytes.store(0, Ordering::SeqCst);
+    master.sync_in_progress.store(true, Ordering::SeqCst);
+    tracing::info!(master_host = %master.host, master_port = master.port, bytes = total, "full sync with master started");
+
+    let mut snapshot = Vec::new();
+    let mut chunk = vec![0; SNAPSHOT_CHUNK];
+    while (snapshot.len() as u64) < total {
+        let wanted = (total - snapshot.len() as u64).min(SNAPSHOT_CHUNK as u64) as usize;
+        let read = reader.read(&mut chunk[..wanted])?;
+        if read == 0 {
+            return Ok(None);
+        }
+        snapshot.extend_from_slice(&chunk[..read]);
+        master.sync_read_bytes.store(snapshot.len() as u64, Ordering::SeqCst);
+    }
+    Ok(Some(snapshot))
+}
+
+/// Runs one command line of the master's stream
+fn apply(executor: &CommandExecutor, line: &[u8]) {
+    let line = String::from_utf8_lossy(line);
+    let command = line.trim();
+    if command.is_empty() {
+        return;
+    }
+    let parsed = CommandParser::parse(command);
+    let name = parsed.name();
+    let response = executor.execute_command(parsed);
+    if response.starts_with("ERR") {
+        tracing::warn!(command = name, response = %response, "replicated command failed");
+        tracing::trace!(command = %command, "failed replicated command line");
+    }
+}
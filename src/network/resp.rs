@@ -0,0 +1,40 @@
+//! # RESP Module
+//!
+//! Replies that the "\n"-separated line format can't express, like
+//! GEOSEARCH's array per match or the stream commands' entries, are built
+//! as RESP values instead and sent to the client encoded exactly as Redis
+//! would send them.
+
+/// A RESP reply value
+#[derive(Debug, Clone, PartialEq)]
+pub enum RespValue {
+    /// A bulk string
+    Bulk(String),
+    Integer(i64),
+    Array(Vec<RespValue>),
+    /// The null bulk string, for a value that isn't there
+    Nil,
+}
+
+impl RespValue {
+    /// Returns the value in RESP wire format
+    pub fn encode(&self) -> String {
+        let mut encoded = String::new();
+        self.encode_into(&mut encoded);
+        encoded
+    }
+
+    fn encode_into(&self, encoded: &mut String) {
+        match self {
+            RespValue::Bulk(value) => encoded.push_str(&format!("${}\r\n{}\r\n", value.len(), value)),
+            RespValue::Integer(value) => encoded.push_str(&format!(":{}\r\n", value)),
+            RespValue::Nil => encoded.push_str("$-1\r\n"),
+            RespValue::Array(items) => {
+                encoded.push_str(&format!("*{}\r\n", items.len()));
+                for item in items {
+                    item.encode_into(encoded);
+                }
+            }
+        }
+    }
+}
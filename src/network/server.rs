@@ -3,19 +3,185 @@
 //! Implements the main Redis-like server functionality, handling network listening,
 //! connection management, and thread pool coordination for concurrent client handling.
 use crate::config::config::Config;
-use crate::network::connection::Connection;
+use crate::config::validate::ConfigProblem;
+use crate::network::client::{ClientInfo, ClientRegistry};
+use crate::network::acl::Acl;
+use crate::network::async_server;
+use crate::network::connection::{Connection, Session};
+use crate::network::maintenance::Maintenance;
+use crate::network::pubsub::PubSub;
+use crate::network::stats::ServerStats;
+use crate::cluster::slots::SlotTable;
 use crate::commands::executor::CommandExecutor;
+use crate::commands::parser::CommandTable;
 use crate::storage::memory::MemoryStorage;
 
-use std::net::{TcpListener, TcpStream};
-use std::io;
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::io::{self, Write};
 use threadpool::ThreadPool;
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long the accept loop sleeps between polls of the shutdown flag
+pub(crate) const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long `ServerHandle::shutdown` waits for the server to stop
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Reply sent to a client that connects while the server is full
+pub(crate) const MAX_CLIENTS_REPLY: &[u8] = b"ERR max number of clients reached\r\n";
+
+/// Reply sent before closing a client that protected mode turns away
+pub(crate) const DENIED_REPLY: &[u8] = b"DENIED Redis is running in protected mode because protected mode is enabled \
+and no ACL users are configured. In this mode connections are only accepted from the loopback interface. \
+If you want to connect from external computers you may adopt one of the following solutions: \
+1) Disable protected mode by sending the command 'CONFIG SET protected-mode no' from the loopback interface, \
+by connecting from the same host the server is running on; however MAKE SURE the server is not publicly \
+accessible from the internet if you do so. Use CONFIG REWRITE to make this change permanent. \
+2) Alternatively disable protected mode by setting `protected_mode = false` in the configuration file \
+and restarting the server. \
+3) Set up ACL users with passwords in the `[acl]` section of the configuration file. \
+NOTE: You only need to do one of the above things in order for the server to accept connections from the outside.\r\n";
+
+/// Tells whether a client's address is on this host, for protected mode
+///
+/// `Server` uses `LoopbackCheck`; tests swap in their own to make local
+/// clients look remote.
+pub trait PeerCheck: Send + Sync {
+    /// Whether `peer` connected over the loopback interface
+    fn is_loopback(&self, peer: &SocketAddr) -> bool;
+}
+
+/// Treats 127.0.0.0/8, ::1 and IPv4-mapped loopback addresses as local
+pub struct LoopbackCheck;
+
+impl PeerCheck for LoopbackCheck {
+    fn is_loopback(&self, peer: &SocketAddr) -> bool {
+        match peer.ip() {
+            IpAddr::V4(ip) => ip.is_loopback(),
+            IpAddr::V6(ip) => ip.is_loopback() || ip.to_ipv4_mapped().is_some_and(|ip| ip.is_loopback()),
+        }
+    }
+}
+
+/// Lets a client connection stop the server (the SHUTDOWN command)
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    requested: Arc<AtomicBool>,
+    save_on_exit: Arc<AtomicBool>,
+    /// Where SHUTDOWN SAVE writes its snapshot
+    pub snapshot_path: String,
+}
+
+impl ShutdownHandle {
+    /// Asks the accept loop to stop
+    ///
+    /// # Arguments
+    ///
+    /// * `save` - Whether the server saves its final snapshot on the way out
+    pub fn request(&self, save: bool) {
+        self.save_on_exit.store(save, Ordering::Relaxed);
+        self.requested.store(true, Ordering::Relaxed);
+    }
+}
 
 pub struct Server {
     pub config: Arc<Config>,
-    thread_pool: ThreadPool,
     storage: Arc<Mutex<MemoryStorage>>,
+    pub(crate) executor: Arc<CommandExecutor>,
+    command_table: Arc<CommandTable>,
+    acl: Arc<Acl>,
+    pub(crate) clients: Arc<ClientRegistry>,
+    pubsub: Arc<PubSub>,
+    slot_assignments: Arc<SlotTable>,
+    pub(crate) shutdown: Arc<AtomicBool>,
+    save_on_exit: Arc<AtomicBool>,
+    max_clients: Arc<AtomicUsize>,
+    stats: Arc<ServerStats>,
+    peer_check: Arc<dyn PeerCheck>,
+    maintenance: Maintenance,
+    // What `Config::validate` refused the configuration for; `start` and
+    // `run` fail with these instead of serving
+    config_errors: Vec<String>,
+}
+
+/// A server running on its own thread, returned by `Server::start`
+///
+/// Dropping the handle asks the server to stop without waiting for it.
+pub struct ServerHandle {
+    shutdown: Arc<AtomicBool>,
+    local_addrs: Vec<SocketAddr>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl ServerHandle {
+    /// Returns the address the server listens on, e.g. to find the port given for port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addrs[0]
+    }
+
+    /// Returns every address the server listens on, in `bind` order
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Stops the server and waits up to `SHUTDOWN_TIMEOUT` for it to finish
+    ///
+    /// The server stops accepting, disconnects its clients, waits for their
+    /// in-flight commands and saves its final snapshot.
+    pub fn shutdown(self) -> io::Result<()> {
+        self.shutdown_timeout(SHUTDOWN_TIMEOUT)
+    }
+
+    /// Same as `shutdown`, with a custom wait
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - The server stopped and saved its snapshot
+    /// * `Err(io::Error)` - The server failed, or is still stopping after
+    ///   `timeout` (`TimedOut`); it keeps stopping in the background
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> io::Result<()> {
+        self.shutdown.store(true, Ordering::Relaxed);
+        self.wait(Some(timeout))
+    }
+
+    /// Waits for the server to stop on its own, e.g. after a SHUTDOWN command
+    pub fn join(mut self) -> io::Result<()> {
+        self.wait(None)
+    }
+
+    fn wait(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+        if let Some(timeout) = timeout {
+            let deadline = Instant::now() + timeout;
+            while !thread.is_finished() {
+                if Instant::now() >= deadline {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "server did not stop in time"));
+                }
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+            }
+        }
+        thread.join().unwrap_or_else(|_| Err(io::Error::other("server thread panicked")))
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Decrements the live connection count when a client's worker finishes
+pub(crate) struct ConnectionSlot(Arc<ServerStats>);
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.disconnected();
+    }
 }
 
 /// Core server structure managing all server components
@@ -27,46 +193,519 @@ pub struct Server {
 /// - Server configuration
 impl Server {
 
-   /// Creates a new server instance with the given configuration
+   /// Creates a new server instance with the given configuration and empty storage
     pub fn new(config: Config) -> Self {
+        Self::with_storage(config, Arc::new(Mutex::new(MemoryStorage::new())))
+    }
+
+   /// Creates a server that serves, and snapshots, an existing storage
+   ///
+   /// Used to serve the data loaded from a snapshot at startup.
+    pub fn with_storage(config: Config, storage: Arc<Mutex<MemoryStorage>>) -> Self {
+        let config_errors = config
+            .validate()
+            .into_iter()
+            .filter(ConfigProblem::is_error)
+            .map(|problem| problem.message)
+            .collect();
         let config = Arc::new(config);
-        let thread_pool = ThreadPool::new(config.max_connections);
-        let storage = Arc::new(Mutex::new(MemoryStorage::new()));
-        Server { config, thread_pool, storage }
+        let pubsub = Arc::new(PubSub::new());
+        {
+            let mut storage = storage.lock().unwrap();
+            storage.set_encoding_limits(config.encoding_limits());
+            storage.set_notify_flags(config.notify_keyspace_events);
+            let notifications = Arc::clone(&pubsub);
+            storage.set_notifier(move |channel, message| {
+                notifications.publish(channel, message);
+            });
+            if config.cluster_enabled {
+                storage.enable_slot_index();
+            }
+        }
+        // One executor for all clients so command statistics are server-wide
+        let executor = Arc::new(CommandExecutor::with_config(Arc::clone(&storage), Arc::clone(&config)));
+        let command_table = Arc::new(CommandTable::new(&config.rename_command));
+        let acl = Arc::new(Acl::new(&config.acl));
+        let clients = Arc::new(ClientRegistry::new());
+        let slot_assignments = Arc::new(SlotTable::new());
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let save_on_exit = Arc::new(AtomicBool::new(true));
+        let max_clients = Arc::new(AtomicUsize::new(config.max_connections));
+        let stats = executor.server_stats();
+        let maintenance = Self::maintenance(&config, &storage, &executor, &clients);
+        Server {
+            config,
+            storage,
+            executor,
+            command_table,
+            acl,
+            clients,
+            pubsub,
+            slot_assignments,
+            shutdown,
+            save_on_exit,
+            max_clients,
+            stats,
+            peer_check: Arc::new(LoopbackCheck),
+            maintenance,
+            config_errors,
+        }
+    }
+
+   /// Builds the maintenance schedule every server starts with
+   ///
+   /// Each task locks the storage only for its own step: reaping a sample
+   /// of expired keys (unless DEBUG SET-ACTIVE-EXPIRE 0 turned that off),
+   /// dropping stale cache entries, starting a background save once a save
+   /// condition is met, and sampling the command rate for INFO stats. With
+   /// `client_query_timeout_ms` or `client_output_timeout_ms` set, stalled
+   /// clients are disconnected too.
+    fn maintenance(
+        config: &Config,
+        storage: &Arc<Mutex<MemoryStorage>>,
+        executor: &Arc<CommandExecutor>,
+        clients: &Arc<ClientRegistry>,
+    ) -> Maintenance {
+        let tick = Duration::from_millis(config.maintenance_tick_ms.max(1));
+        let mut maintenance = Maintenance::new(tick, executor.maintenance_stats());
+
+        let expiring = Arc::clone(storage);
+        maintenance.register("active_expire", move || {
+            let mut storage = expiring.lock().unwrap();
+            if storage.active_expire_enabled() {
+                storage.purge_expired_sample();
+            }
+        });
+        let caching = Arc::clone(storage);
+        maintenance.register("cache_purge", move || {
+            caching.lock().unwrap().purge_expired_cache();
+        });
+        let saving = Arc::clone(storage);
+        let saver = Arc::clone(executor);
+        let runtime = executor.runtime_config();
+        maintenance.register("snapshot", move || {
+            let due = {
+                let storage = saving.lock().unwrap();
+                runtime.save_due(storage.dirty_count(), storage.since_last_save())
+            };
+            if due && saver.start_background_save() {
+                tracing::info!("save condition met, background saving started");
+            }
+        });
+        let stats = executor.server_stats();
+        maintenance.register("ops_sample", move || stats.sample_ops(Instant::now()));
+        let limit = |ms: u64| (ms > 0).then(|| Duration::from_millis(ms));
+        let (query_timeout, output_timeout) = (limit(config.client_query_timeout_ms), limit(config.client_output_timeout_ms));
+        if query_timeout.is_some() || output_timeout.is_some() {
+            let clients = Arc::clone(clients);
+            let stats = executor.server_stats();
+            maintenance.register("stalled_clients", move || {
+                stats.record_stalled_clients(clients.disconnect_stalled(query_timeout, output_timeout));
+            });
+        }
+        maintenance
+    }
+
+   /// Adds a task to the maintenance thread the server runs while serving
+   ///
+   /// It runs on every `maintenance_tick_ms` tick, after the built-in tasks,
+   /// and its timings show up in INFO maintenance.
+   ///
+   /// # Arguments
+   ///
+   /// * `name` - The task's name in INFO maintenance
+   /// * `task` - The work to run; it should return quickly
+    pub fn register_maintenance_task(&mut self, name: &'static str, task: impl Fn() + Send + Sync + 'static) {
+        self.maintenance.register(name, task);
+    }
+
+   /// Replaces how protected mode tells local clients from remote ones
+    pub fn set_peer_check(&mut self, check: impl PeerCheck + 'static) {
+        self.peer_check = Arc::new(check);
+    }
+
+   /// Returns the flag that stops the accept loop once set to `true`
+   ///
+   /// Intended for signal handlers and other threads that need to
+   /// request a graceful shutdown.
+    pub fn shutdown_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.shutdown)
+    }
+
+   /// Returns the connection limit, which is read on every accept
+   ///
+   /// Storing a new value takes effect for the next client that connects;
+   /// clients already connected are never dropped.
+    pub fn max_clients(&self) -> Arc<AtomicUsize> {
+        Arc::clone(&self.max_clients)
+    }
+
+   /// Returns the number of clients currently connected
+    pub fn connected_clients(&self) -> usize {
+        self.stats.connected_clients()
+    }
+
+   /// Returns a handle connections use to implement SHUTDOWN
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            requested: Arc::clone(&self.shutdown),
+            save_on_exit: Arc::clone(&self.save_on_exit),
+            snapshot_path: self.config.snapshot_path.clone(),
+        }
+    }
+
+   /// Binds the listener and serves clients on a new thread
+   ///
+   /// The server is accepting connections by the time this returns; stop
+   /// it with `ServerHandle::shutdown`, which runs the same shutdown as the
+   /// shutdown flag and waits for the maintenance, replication and client
+   /// threads to finish. This is the entry point for embedding the server,
+   /// e.g. in a test harness: configure port 0, build the server with
+   /// `Server::new` or `Server::with_storage`, and connect to
+   /// `ServerHandle::local_addr`, which reports the port the OS picked.
+   ///
+   /// # Returns
+   ///
+   /// * `Ok(ServerHandle)` - The running server
+   /// * `Err(io::Error)` - If the configuration is invalid or the address can't be bound
+    pub fn start(self) -> io::Result<ServerHandle> {
+        let listeners = self.bind()?;
+        let mut local_addrs: Vec<SocketAddr> = listeners.iter().map(TcpListener::local_addr).collect::<io::Result<_>>()?;
+        // An address's acceptor_threads listeners all report it
+        local_addrs.dedup();
+        let shutdown = Arc::clone(&self.shutdown);
+        let thread = thread::Builder::new()
+            .name("redis-server".to_string())
+            .spawn(move || self.serve(listeners))?;
+        Ok(ServerHandle { shutdown, local_addrs, thread: Some(thread) })
     }
 
    /// Starts the server and begins accepting client connections
+   ///
+   /// Blocks until the server shuts down. Clients are served by a pool of worker threads, one per connection,
+   /// unless `config.async_server` selects the tokio front end.
+   ///
    /// # Server Lifecycle
-   /// 1. Binds to configured host:port
-   /// 2. Accepts incoming connections
-   /// 3. Spawns worker thread for each client
+   /// 1. Binds every configured address on the configured port, failing if any can't be
+   ///    bound or if `Config::validate` found errors in the configuration
+   /// 2. Accepts incoming connections until the shutdown flag is set
+   /// 3. Spawns worker thread for each client, or turns it away with an
+   ///    error once `max_clients` connections are live
    /// 4. Manages shared storage across all connections
+   /// 5. Runs the maintenance tasks in the background: expiring keys, purging
+   ///    the cache, saving a snapshot whenever a save condition is met and,
+   ///    if configured, disconnecting stalled clients
+   /// 6. On shutdown, disconnects all clients, waits for their workers to finish
+   ///    and saves a final snapshot (unless SHUTDOWN NOSAVE asked otherwise)
     pub fn run(&self) -> io::Result<()> {
-        let address = format!("{}:{}", self.config.host, self.config.port);
-        let listener = TcpListener::bind(&address)?;
-        println!("Server is running on {}", address);
-        
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    let storage = Arc::clone(&self.storage);
-                    self.thread_pool.execute(move || {
-                        let executor = Arc::new(CommandExecutor::new(storage));
-                        if let Err(e) = handle_client(stream,  executor) {
-                            eprintln!("Error handling client: {}", e);
-                        }
-                    });
-                }
-                Err(e) => eprintln!("Connection failed: {}", e),
+        let listeners = self.bind()?;
+        self.serve(listeners)
+    }
+
+   /// Binds the listeners for every configured address
+   ///
+   /// With port 0 the first address gets a port from the OS and the others
+   /// are bound on that same port, so every listener shares one port. Each
+   /// address gets `acceptor_threads` listeners.
+   ///
+   /// # Returns
+   ///
+   /// * `Ok(Vec<TcpListener>)` - Non-blocking listeners, in `bind` order
+   /// * `Err(io::Error)` - Listing the configuration's errors, if
+   ///   `Config::validate` found any, or naming the first address that
+   ///   can't be bound
+    fn bind(&self) -> io::Result<Vec<TcpListener>> {
+        if !self.config_errors.is_empty() {
+            let message = format!("invalid configuration: {}", self.config_errors.join("; "));
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        let mut port = self.config.port;
+        let mut listeners = Vec::new();
+        for host in self.config.bind_addresses() {
+            let address = format_address(host, port);
+            let bound = bind_address(&address, self.config.acceptor_threads)
+                .map_err(|e| io::Error::new(e.kind(), format!("failed to bind {}: {}", address, e)))?;
+            for listener in bound {
+                listener.set_nonblocking(true)?;
+                port = listener.local_addr()?.port();
+                listeners.push(listener);
             }
         }
+        Ok(listeners)
+    }
 
+    fn serve(&self, listeners: Vec<TcpListener>) -> io::Result<()> {
+        let maintenance = self.maintenance.spawn()?;
+        let served = if self.config.async_server {
+            async_server::run(self, listeners)
+        } else {
+            self.run_threaded(listeners)
+        };
+        maintenance.stop();
+        self.executor.master_link().stop();
+        served?;
+        self.finish();
         Ok(())
     }
+
+    fn run_threaded(&self, listeners: Vec<TcpListener>) -> io::Result<()> {
+        let mut thread_pool = ThreadPool::new(self.max_clients.load(Ordering::Relaxed).max(1));
+        // Each listener accepts on its own thread; admitting the clients
+        // stays on this one so it never races
+        let (sender, incoming) = mpsc::channel();
+        let mut acceptors = Vec::new();
+        let mut wakers = Vec::new();
+        for listener in listeners {
+            tracing::info!(address = %listener.local_addr()?, "server is running");
+            wakers.push(listener.try_clone()?);
+            let shutdown = Arc::clone(&self.shutdown);
+            let sender = sender.clone();
+            acceptors.push(
+                thread::Builder::new()
+                    .name("redis-acceptor".to_string())
+                    .spawn(move || accept_from(listener, sender, shutdown))?,
+            );
+        }
+        drop(sender);
+
+        while !self.shutdown.load(Ordering::Relaxed) {
+            match incoming.recv_timeout(ACCEPT_POLL_INTERVAL) {
+                Ok(stream) => self.spawn_client(&mut thread_pool, stream)?,
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        tracing::info!("server shutting down gracefully");
+        // Shutting a listener down wakes an acceptor blocked in accept
+        for listener in &wakers {
+            let _ = socket2::SockRef::from(listener).shutdown(std::net::Shutdown::Read);
+        }
+        for acceptor in acceptors {
+            let _ = acceptor.join();
+        }
+        drop(wakers);
+        self.executor.release_blocked_clients();
+        self.clients.disconnect_all();
+        thread_pool.join();
+        Ok(())
+    }
+
+   /// Serves a newly accepted client on the thread pool, or turns it away
+   /// with an error once `max_clients` connections are live
+    fn spawn_client(&self, thread_pool: &mut ThreadPool, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        if self.denied(stream.peer_addr().ok()) {
+            let _ = stream.write_all(DENIED_REPLY);
+            return Ok(());
+        }
+        let Some(slot) = self.admit() else {
+            let _ = stream.write_all(MAX_CLIENTS_REPLY);
+            return Ok(());
+        };
+        if let Err(e) = configure_stream(&stream, &self.config, self.executor.runtime_config().tcp_read_timeout_ms()) {
+            tracing::warn!(error = %e, "failed to apply socket options");
+        }
+        let client = self.clients.register(&stream);
+        let clients = Arc::clone(&self.clients);
+        let executor = Arc::clone(&self.executor);
+        let config = Arc::clone(&self.config);
+        let command_table = Arc::clone(&self.command_table);
+        let shutdown = self.shutdown_handle();
+        let pubsub = Arc::clone(&self.pubsub);
+        let acl = Arc::clone(&self.acl);
+        let slot_assignments = Arc::clone(&self.slot_assignments);
+        // Grow the pool with the limit so admitted clients never queue
+        let max_clients = self.max_clients.load(Ordering::Relaxed);
+        if thread_pool.max_count() < max_clients {
+            thread_pool.set_num_threads(max_clients);
+        }
+        thread_pool.execute(move || {
+            let _slot = slot;
+            let mut connection = Connection::with_client(stream, executor, client, clients);
+            connection.set_shutdown_handle(shutdown);
+            connection.set_pubsub(pubsub);
+            connection.set_acl(acl);
+            connection.set_slot_table(slot_assignments);
+            if let Err(e) = handle_client(connection, &config, command_table) {
+                tracing::error!(error = %e, "error handling client");
+            }
+        });
+        Ok(())
+    }
+
+   /// Saves the final snapshot, unless SHUTDOWN NOSAVE asked otherwise
+    fn finish(&self) {
+        // A background save still writing would race the final one
+        self.executor.wait_for_background_save();
+        if self.save_on_exit.load(Ordering::Relaxed) {
+            if let Err(e) = self.storage.lock().unwrap().save_snapshot(&self.config.snapshot_path) {
+                tracing::error!(error = %e, "failed to save snapshot");
+            }
+        }
+        tracing::info!("goodbye");
+    }
+
+   /// Whether protected mode turns away a client connecting from `peer`
+   ///
+   /// Only while protected mode is on and no ACL users are configured;
+   /// a client whose address can't be read is treated as remote.
+    pub(crate) fn denied(&self, peer: Option<SocketAddr>) -> bool {
+        if !self.executor.runtime_config().protected_mode() || self.acl.is_enabled() {
+            return false;
+        }
+        if peer.is_some_and(|peer| self.peer_check.is_loopback(&peer)) {
+            return false;
+        }
+        tracing::warn!(peer = ?peer, "rejecting client from another host, protected mode is on");
+        true
+    }
+
+   /// Reserves a connection slot for a newly accepted client
+   ///
+   /// Returns `None` when `max_clients` connections are already live. Only
+   /// the accept loop calls this, so checking then adding can't race past
+   /// the limit.
+    pub(crate) fn admit(&self) -> Option<ConnectionSlot> {
+        if self.stats.connected_clients() >= self.max_clients.load(Ordering::Relaxed) {
+            tracing::warn!("rejecting client, max number of clients reached");
+            self.stats.rejected();
+            return None;
+        }
+        self.stats.connected();
+        Some(ConnectionSlot(Arc::clone(&self.stats)))
+    }
+
+   /// Builds the command state for a client served by the tokio front end
+    pub(crate) fn new_session(&self, client: Arc<ClientInfo>) -> Session {
+        let mut session = Session::new(Arc::clone(&self.executor), client, Arc::clone(&self.clients));
+        session.set_rate_limit(self.config.max_commands_per_second);
+        session.set_rate_limit_burst(self.config.rate_limit_burst);
+        session.set_rate_limit_reject(self.config.rate_limit_mode == "reject");
+        session.set_max_inline_size(self.config.proto_max_inline_size);
+        session.set_command_table(Arc::clone(&self.command_table));
+        session.set_shutdown_handle(self.shutdown_handle());
+        session.set_pubsub(Arc::clone(&self.pubsub));
+        session.set_acl(Arc::clone(&self.acl));
+        session.set_slot_table(Arc::clone(&self.slot_assignments));
+        session.set_output_buffer_limits(
+            self.config.client_output_buffer_limit_normal,
+            self.config.client_output_buffer_limit_pubsub,
+        );
+        session
+    }
+}
+
+/// Accepts clients on `listener` and hands them to the accept loop, until
+/// the server shuts down
+///
+/// On Linux a blocking accept gives up after the socket's read timeout, so
+/// the thread waits for clients in the kernel and still sees the shutdown
+/// flag every `ACCEPT_POLL_INTERVAL`; elsewhere it polls.
+fn accept_from(listener: TcpListener, accepted: mpsc::Sender<TcpStream>, shutdown: Arc<AtomicBool>) {
+    let blocking = cfg!(target_os = "linux")
+        && socket2::SockRef::from(&listener).set_read_timeout(Some(ACCEPT_POLL_INTERVAL)).is_ok()
+        && listener.set_nonblocking(false).is_ok();
+    while !shutdown.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if accepted.send(stream).is_err() {
+                    return;
+                }
+            }
+            Err(ref e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                if !blocking {
+                    thread::sleep(ACCEPT_POLL_INTERVAL);
+                }
+            }
+            Err(_) if shutdown.load(Ordering::Relaxed) => return,
+            Err(e) => tracing::warn!(error = %e, "connection failed"),
+        }
+    }
+}
+
+/// Binds `acceptors` listeners to `address`, sharing it through SO_REUSEPORT
+///
+/// The kernel spreads new connections across them. Where SO_REUSEPORT
+/// isn't available a single listener is bound instead.
+fn bind_address(address: &str, acceptors: usize) -> io::Result<Vec<TcpListener>> {
+    if acceptors <= 1 {
+        return Ok(vec![TcpListener::bind(address)?]);
+    }
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    {
+        use socket2::{Domain, Protocol, Socket, Type};
+        use std::net::ToSocketAddrs;
+
+        let mut addr = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to bind"))?;
+        let mut listeners = Vec::with_capacity(acceptors);
+        for _ in 0..acceptors {
+            let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+            socket.set_reuse_address(true)?;
+            socket.set_reuse_port(true)?;
+            socket.bind(&addr.into())?;
+            socket.listen(1024)?;
+            let listener = TcpListener::from(socket);
+            // With port 0, the rest join the port the first one got
+            addr = listener.local_addr()?;
+            listeners.push(listener);
+        }
+        Ok(listeners)
+    }
+    #[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+    {
+        tracing::warn!(acceptors, "SO_REUSEPORT is not supported here, using a single listener");
+        Ok(vec![TcpListener::bind(address)?])
+    }
+}
+
+/// Formats `host` and `port` as an address to bind or connect to
+///
+/// IPv6 literals are bracketed ("[::1]:6379"); brackets already around
+/// `host` are accepted too.
+pub fn format_address(host: &str, port: u16) -> String {
+    let host = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Applies the configured timeouts and TCP options to an accepted client socket
+///
+/// The read timeout is passed in since CONFIG SET may have changed it.
+pub(crate) fn configure_stream(stream: &TcpStream, config: &Config, read_timeout_ms: u64) -> io::Result<()> {
+    let timeout = |ms: u64| (ms > 0).then(|| Duration::from_millis(ms));
+    stream.set_read_timeout(timeout(read_timeout_ms))?;
+    stream.set_write_timeout(timeout(config.tcp_write_timeout_ms))?;
+    stream.set_nodelay(config.tcp_nodelay)?;
+
+    let socket = socket2::SockRef::from(stream);
+    if config.tcp_keepalive_secs > 0 {
+        let keepalive = socket2::TcpKeepalive::new()
+            .with_time(Duration::from_secs(config.tcp_keepalive_secs));
+        socket.set_tcp_keepalive(&keepalive)?;
+    } else {
+        socket.set_keepalive(false)?;
+    }
+    Ok(())
 }
 
 /// Handles an individual client connection
-fn handle_client(stream: TcpStream, executor: Arc<CommandExecutor>) -> io::Result<()> {
-    let mut connection = Connection::new(stream,  executor);
+fn handle_client(
+    mut connection: Connection,
+    config: &Config,
+    command_table: Arc<CommandTable>,
+) -> io::Result<()> {
+    connection.set_rate_limit(config.max_commands_per_second);
+    connection.set_rate_limit_burst(config.rate_limit_burst);
+    connection.set_rate_limit_reject(config.rate_limit_mode == "reject");
+    connection.set_max_inline_size(config.proto_max_inline_size);
+    connection.set_command_table(command_table);
+    connection.set_output_buffer_limits(config.client_output_buffer_limit_normal, config.client_output_buffer_limit_pubsub);
     connection.process()
 }
\ No newline at end of file
@@ -0,0 +1,151 @@
+//! # Stats Module
+//!
+//! Server-wide counters for INFO: connections accepted and turned away,
+//! clients connected, commands processed, bytes moved over the network,
+//! keys evicted and stalled clients disconnected. Everything is a relaxed atomic bumped by whichever thread
+//! or task serves the client, apart from the ops/sec samples, which the
+//! maintenance thread takes once per tick.
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// How many ops/sec samples `instantaneous_ops_per_sec` averages
+pub const OPS_SAMPLES: usize = 16;
+
+/// Recent command rates, one per maintenance tick
+#[derive(Default)]
+struct OpsSamples {
+    // When the last sample was taken and the command count then
+    last: Option<(Instant, u64)>,
+    rates: [u64; OPS_SAMPLES],
+    taken: usize,
+}
+
+/// Live server counters, shared by the accept loop, every client and INFO
+#[derive(Default)]
+pub struct ServerStats {
+    total_connections_received: AtomicU64,
+    connected_clients: AtomicUsize,
+    rejected_connections: AtomicU64,
+    total_commands_processed: AtomicU64,
+    total_net_input_bytes: AtomicU64,
+    total_net_output_bytes: AtomicU64,
+    evicted_keys: AtomicU64,
+    stalled_clients: AtomicU64,
+    ops: Mutex<OpsSamples>,
+}
+
+impl ServerStats {
+    /// Counts a client the accept loop admitted
+    pub fn connected(&self) {
+        self.total_connections_received.fetch_add(1, Ordering::Relaxed);
+        self.connected_clients.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Counts a client that went away
+    pub fn disconnected(&self) {
+        self.connected_clients.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Counts a client turned away because `max_clients` were connected
+    pub fn rejected(&self) {
+        self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts one command a client sent
+    pub fn record_command(&self) {
+        self.total_commands_processed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts `bytes` read from a client socket
+    pub fn record_input(&self, bytes: usize) {
+        self.total_net_input_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Counts `bytes` written to a client socket
+    pub fn record_output(&self, bytes: usize) {
+        self.total_net_output_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Counts keys evicted to stay under `max_memory`
+    pub fn record_evictions(&self, keys: usize) {
+        self.evicted_keys.fetch_add(keys as u64, Ordering::Relaxed);
+    }
+
+    /// Counts clients disconnected for stalling mid-command or mid-reply
+    pub fn record_stalled_clients(&self, clients: usize) {
+        self.stalled_clients.fetch_add(clients as u64, Ordering::Relaxed);
+    }
+
+    /// Returns the number of clients currently connected
+    pub fn connected_clients(&self) -> usize {
+        self.connected_clients.load(Ordering::SeqCst)
+    }
+
+    /// Returns how many commands clients have sent
+    pub fn total_commands_processed(&self) -> u64 {
+        self.total_commands_processed.load(Ordering::Relaxed)
+    }
+
+    /// Records the command rate since the previous call
+    ///
+    /// The maintenance thread calls this on every tick; the first call only
+    /// starts the clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - When the sample is taken
+    pub fn sample_ops(&self, now: Instant) {
+        let commands = self.total_commands_processed();
+        let mut ops = self.ops.lock().unwrap();
+        if let Some((then, before)) = ops.last {
+            let elapsed_ms = now.duration_since(then).as_millis() as u64;
+            if elapsed_ms == 0 {
+                return;
+            }
+            let index = ops.taken % OPS_SAMPLES;
+            ops.rates[index] = commands.saturating_sub(before) * 1000 / elapsed_ms;
+            ops.taken += 1;
+        }
+        ops.last = Some((now, commands));
+    }
+
+    /// Returns the average of the last `OPS_SAMPLES` command rates
+    pub fn instantaneous_ops_per_sec(&self) -> u64 {
+        let ops = self.ops.lock().unwrap();
+        let taken = ops.taken.min(OPS_SAMPLES);
+        if taken == 0 {
+            return 0;
+        }
+        ops.rates[..taken].iter().sum::<u64>() / taken as u64
+    }
+
+    /// Zeroes the totals for CONFIG RESETSTAT; connected clients are still connected
+    pub fn reset(&self) {
+        self.total_connections_received.store(0, Ordering::Relaxed);
+        self.rejected_connections.store(0, Ordering::Relaxed);
+        self.total_commands_processed.store(0, Ordering::Relaxed);
+        self.total_net_input_bytes.store(0, Ordering::Relaxed);
+        self.total_net_output_bytes.store(0, Ordering::Relaxed);
+        self.evicted_keys.store(0, Ordering::Relaxed);
+        self.stalled_clients.store(0, Ordering::Relaxed);
+        *self.ops.lock().unwrap() = OpsSamples::default();
+    }
+
+    /// Formats the counters as INFO stats lines
+    pub fn info(&self) -> String {
+        format!(
+            "total_connections_received:{}\ntotal_commands_processed:{}\ninstantaneous_ops_per_sec:{}\n\
+             total_net_input_bytes:{}\ntotal_net_output_bytes:{}\nrejected_connections:{}\nevicted_keys:{}\n\
+             stalled_clients_disconnected:{}\n",
+            self.total_connections_received.load(Ordering::Relaxed),
+            self.total_commands_processed(),
+            self.instantaneous_ops_per_sec(),
+            self.total_net_input_bytes.load(Ordering::Relaxed),
+            self.total_net_output_bytes.load(Ordering::Relaxed),
+            self.rejected_connections.load(Ordering::Relaxed),
+            self.evicted_keys.load(Ordering::Relaxed),
+            self.stalled_clients.load(Ordering::Relaxed)
+        )
+    }
+}
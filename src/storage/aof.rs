@@ -0,0 +1,100 @@
+//! # AOF Module
+//!
+//! Writes the dataset as the shortest append-only file that rebuilds it:
+//! one command per key (long collections are split into batches), followed
+//! by an `EXPIREAT` for every key with a time to live. A stream becomes an
+//! `XADD` per entry and an `XGROUP CREATE` per group at its last delivered
+//! ID; its groups' pending entries, and a stream left with no entries and
+//! no groups, are not carried over, and neither is which IDs were deleted. Commands are encoded as RESP arrays,
+//! the format Redis uses for its AOF, so values containing spaces or
+//! newlines survive.
+use crate::storage::memory::{FrozenStorage, MemoryStorage};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+/// Most elements a rewritten RPUSH/SADD/ZADD/HSET carries, as Redis's `AOF_REWRITE_ITEMS_PER_CMD`
+pub const AOF_REWRITE_ITEMS_PER_CMD: usize = 64;
+
+/// Writes a minimal AOF for the storage's current committed data to `new_aof_path`
+///
+/// The file is built next to its destination, fsynced and then renamed
+/// into place, so `new_aof_path` is never left half written.
+///
+/// # Arguments
+///
+/// * `storage` - The storage to rewrite
+/// * `new_aof_path` - Where the new AOF goes, replacing any file there
+pub fn aof_rewrite(storage: &MemoryStorage, new_aof_path: &str) -> io::Result<()> {
+    write_frozen(&storage.frozen(), new_aof_path)
+}
+
+/// Same as `aof_rewrite`, for data already taken out of the storage
+///
+/// BGREWRITEAOF uses this to do the writing after the storage lock is released.
+pub fn write_frozen(data: &FrozenStorage, new_aof_path: &str) -> io::Result<()> {
+    let temp_path = format!("{}.rewrite.tmp", new_aof_path);
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    write_commands(data, &mut writer)?;
+    let file = writer.into_inner().map_err(io::IntoInnerError::into_error)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, new_aof_path)
+}
+
+fn write_commands(data: &FrozenStorage, out: &mut impl Write) -> io::Result<()> {
+    for (key, value) in data.strings.iter() {
+        write_command(out, &["SET", key, value])?;
+    }
+    for (key, list) in data.lists.iter() {
+        let items: Vec<&str> = list.iter().map(String::as_str).collect();
+        write_batched(out, "RPUSH", key, &items)?;
+    }
+    for (key, set) in data.sets.iter() {
+        let members: Vec<&str> = set.iter().map(String::as_str).collect();
+        write_batched(out, "SADD", key, &members)?;
+    }
+    for (key, zset) in data.zsets.iter() {
+        let pairs: Vec<(String, &str)> = zset.iter().map(|(member, score)| (score.to_string(), member)).collect();
+        let flat: Vec<&str> = pairs.iter().flat_map(|(score, member)| [score.as_str(), member]).collect();
+        // Each score travels with its member, so batches hold pairs
+        for chunk in flat.chunks(AOF_REWRITE_ITEMS_PER_CMD * 2) {
+            write_command(out, &[&["ZADD", key.as_str()], chunk].concat())?;
+        }
+    }
+    for (key, hash) in data.hashes.iter() {
+        let flat: Vec<&str> = hash.iter().flat_map(|(field, value)| [field.as_str(), value]).collect();
+        // Fields travel with their values, like ZADD's score/member pairs
+        for chunk in flat.chunks(AOF_REWRITE_ITEMS_PER_CMD * 2) {
+            write_command(out, &[&["HSET", key.as_str()], chunk].concat())?;
+        }
+    }
+    for (key, stream) in data.streams.iter() {
+        for (id, fields) in &stream.entries {
+            let id = id.to_string();
+            let flat: Vec<&str> = fields.iter().flat_map(|(field, value)| [field.as_str(), value]).collect();
+            write_command(out, &[&["XADD", key.as_str(), &id], flat.as_slice()].concat())?;
+        }
+        for (name, group) in &stream.groups {
+            write_command(out, &["XGROUP", "CREATE", key, name, &group.last_delivered_id.to_string(), "MKSTREAM"])?;
+        }
+    }
+    for (key, at) in &data.expire_at {
+        write_command(out, &["EXPIREAT", key, &at.to_string()])?;
+    }
+    Ok(())
+}
+
+fn write_batched(out: &mut impl Write, command: &str, key: &str, items: &[&str]) -> io::Result<()> {
+    for chunk in items.chunks(AOF_REWRITE_ITEMS_PER_CMD) {
+        write_command(out, &[&[command, key], chunk].concat())?;
+    }
+    Ok(())
+}
+
+/// Writes one command as a RESP array of bulk strings
+fn write_command(out: &mut impl Write, args: &[&str]) -> io::Result<()> {
+    write!(out, "*{}\r\n", args.len())?;
+    for arg in args {
+        write!(out, "${}\r\n{}\r\n", arg.len(), arg)?;
+    }
+    Ok(())
+}
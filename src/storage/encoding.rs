@@ -0,0 +1,99 @@
+//! # Encoding Module
+//!
+//! The encodings OBJECT ENCODING reports for collections. Every collection is
+//! stored the same way here whatever its size, so the encoding is a name
+//! worked out from the contents and the limits below. As in Redis, a write
+//! that takes a collection past its compact encoding's limits upgrades it for
+//! good: it keeps the full encoding even after it shrinks again.
+
+/// Encodings from most to least compact; an upgrade only ever moves right
+const ORDER: &[&str] = &["intset", "listpack", "quicklist", "hashtable", "skiplist"];
+
+/// The limits under which each type keeps its compact encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingLimits {
+    /// Most elements a listpack list may hold
+    pub list_max_listpack_size: usize,
+    /// Longest element, in bytes, a listpack list may hold
+    pub list_max_listpack_value: usize,
+    /// Most fields a listpack hash may hold
+    pub hash_max_listpack_entries: usize,
+    /// Longest field or value, in bytes, a listpack hash may hold
+    pub hash_max_listpack_value: usize,
+    /// Most members an intset, a set of integers only, may hold
+    pub set_max_intset_entries: usize,
+    /// Most members a listpack set may hold
+    pub set_max_listpack_entries: usize,
+    /// Longest member, in bytes, a listpack set may hold
+    pub set_max_listpack_value: usize,
+    /// Most members a listpack sorted set may hold
+    pub zset_max_listpack_entries: usize,
+    /// Longest member, in bytes, a listpack sorted set may hold
+    pub zset_max_listpack_value: usize,
+}
+
+impl Default for EncodingLimits {
+    /// Redis's defaults
+    fn default() -> Self {
+        EncodingLimits {
+            list_max_listpack_size: 128,
+            list_max_listpack_value: 64,
+            hash_max_listpack_entries: 128,
+            hash_max_listpack_value: 64,
+            set_max_intset_entries: 512,
+            set_max_listpack_entries: 128,
+            set_max_listpack_value: 64,
+            zset_max_listpack_entries: 128,
+            zset_max_listpack_value: 64,
+        }
+    }
+}
+
+impl EncodingLimits {
+    /// Returns `"listpack"` or `"quicklist"` for a list of `len` elements
+    pub fn list<'a>(&self, len: usize, mut elements: impl Iterator<Item = &'a String>) -> &'static str {
+        if len <= self.list_max_listpack_size && elements.all(|value| value.len() <= self.list_max_listpack_value) {
+            "listpack"
+        } else {
+            "quicklist"
+        }
+    }
+
+    /// Returns `"listpack"` or `"hashtable"` for a hash of `len` fields
+    pub fn hash<'a>(&self, len: usize, mut pairs: impl Iterator<Item = (&'a String, &'a String)>) -> &'static str {
+        let fits = |text: &String| text.len() <= self.hash_max_listpack_value;
+        if len <= self.hash_max_listpack_entries && pairs.all(|(field, value)| fits(field) && fits(value)) {
+            "listpack"
+        } else {
+            "hashtable"
+        }
+    }
+
+    /// Returns `"intset"`, `"listpack"` or `"hashtable"` for a set of `len` members
+    pub fn set<'a>(&self, len: usize, members: impl Iterator<Item = &'a String> + Clone) -> &'static str {
+        if len <= self.set_max_intset_entries && members.clone().all(|member| member.parse::<i64>().is_ok()) {
+            "intset"
+        } else if len <= self.set_max_listpack_entries
+            && members.clone().all(|member| member.len() <= self.set_max_listpack_value)
+        {
+            "listpack"
+        } else {
+            "hashtable"
+        }
+    }
+
+    /// Returns `"listpack"` or `"skiplist"` for a sorted set of `len` members
+    pub fn zset<'a>(&self, len: usize, mut members: impl Iterator<Item = &'a str>) -> &'static str {
+        if len <= self.zset_max_listpack_entries && members.all(|member| member.len() <= self.zset_max_listpack_value) {
+            "listpack"
+        } else {
+            "skiplist"
+        }
+    }
+}
+
+/// Returns the less compact of two encodings
+pub fn widest(a: &'static str, b: &'static str) -> &'static str {
+    let rank = |encoding| ORDER.iter().position(|known| *known == encoding);
+    if rank(b) > rank(a) { b } else { a }
+}
@@ -0,0 +1,69 @@
+//! # Eviction Module
+//!
+//! The maxmemory policies: which keys may be evicted once the estimated
+//! memory use goes over `max_memory`, and how a victim is chosen. Like
+//! Redis, the LRU, LFU and TTL policies compare a small random sample of
+//! keys rather than the whole keyspace.
+
+/// How many candidate keys each eviction compares
+pub const EVICTION_SAMPLES: usize = 5;
+
+/// What to do when a write would take memory use over `max_memory`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MaxMemoryPolicy {
+    /// Refuse the write with an OOM error
+    #[default]
+    NoEviction,
+    /// Evict the least recently used key
+    AllKeysLru,
+    /// Evict the least frequently used key
+    AllKeysLfu,
+    /// Evict any key
+    AllKeysRandom,
+    /// Evict the least recently used key with an expiration
+    VolatileLru,
+    /// Evict the least frequently used key with an expiration
+    VolatileLfu,
+    /// Evict any key with an expiration
+    VolatileRandom,
+    /// Evict the key with an expiration closest to expiring
+    VolatileTtl,
+}
+
+impl MaxMemoryPolicy {
+    /// Every policy and its name in `maxmemory_policy`
+    pub const ALL: [(&'static str, MaxMemoryPolicy); 8] = [
+        ("noeviction", MaxMemoryPolicy::NoEviction),
+        ("allkeys-lru", MaxMemoryPolicy::AllKeysLru),
+        ("allkeys-lfu", MaxMemoryPolicy::AllKeysLfu),
+        ("allkeys-random", MaxMemoryPolicy::AllKeysRandom),
+        ("volatile-lru", MaxMemoryPolicy::VolatileLru),
+        ("volatile-lfu", MaxMemoryPolicy::VolatileLfu),
+        ("volatile-random", MaxMemoryPolicy::VolatileRandom),
+        ("volatile-ttl", MaxMemoryPolicy::VolatileTtl),
+    ];
+
+    /// Parses a policy name such as "allkeys-lru" (case-insensitive)
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(policy, _)| policy.eq_ignore_ascii_case(name))
+            .map(|(_, policy)| *policy)
+    }
+
+    /// Returns the policy's name
+    pub fn as_str(self) -> &'static str {
+        Self::ALL.iter().find(|(_, policy)| *policy == self).map_or("noeviction", |(name, _)| name)
+    }
+
+    /// Whether only keys with an expiration may be evicted
+    pub fn volatile_only(self) -> bool {
+        matches!(
+            self,
+            MaxMemoryPolicy::VolatileLru
+                | MaxMemoryPolicy::VolatileLfu
+                | MaxMemoryPolicy::VolatileRandom
+                | MaxMemoryPolicy::VolatileTtl
+        )
+    }
+}
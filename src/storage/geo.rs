@@ -0,0 +1,374 @@
+//! # Geo Module
+//!
+//! The geospatial commands. Like Redis, a location is kept as a sorted set
+//! member whose score is the 52-bit interleaved geohash of its coordinates,
+//! so GEOADD is ZADD with the coordinates encoded first. A geohash cell at
+//! any precision is a contiguous range of scores, so GEOSEARCH only scans
+//! the cells around its centre and decodes their members' scores back to
+//! the centre of each member's own cell.
+use crate::storage::zset::{ScoreBound, SortedSet, ZAddOptions};
+
+/// The longitudes GEOADD accepts
+pub const LONGITUDE_RANGE: (f64, f64) = (-180.0, 180.0);
+
+/// The latitudes GEOADD accepts; beyond them Web Mercator, which geohash
+/// scores are compatible with, isn't defined
+pub const LATITUDE_RANGE: (f64, f64) = (-85.05112878, 85.05112878);
+
+/// Bits of precision per coordinate; twice this fits an f64 exactly
+const STEP: u32 = 26;
+
+/// The Earth's radius in meters, the one Redis measures distances with
+const EARTH_RADIUS: f64 = 6372797.560856;
+
+/// Conditions GEOADD puts on each member
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GeoAddOptions {
+    /// Only add new members, never move existing ones
+    pub nx: bool,
+    /// Only move existing members, never add new ones
+    pub xx: bool,
+    /// Count moved members as well as added ones in the reply
+    pub ch: bool,
+}
+
+impl GeoAddOptions {
+    /// Returns the ZADD options that store members the same way
+    pub fn zadd_options(&self) -> ZAddOptions {
+        ZAddOptions { nx: self.nx, xx: self.xx, ch: self.ch, ..ZAddOptions::default() }
+    }
+}
+
+/// A unit GEOSEARCH takes distances in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoUnit {
+    Meters,
+    Kilometers,
+    Miles,
+    Feet,
+}
+
+impl GeoUnit {
+    /// Parses `m`, `km`, `mi` or `ft`, in any case
+    pub fn parse(unit: &str) -> Option<GeoUnit> {
+        match unit.to_lowercase().as_str() {
+            "m" => Some(GeoUnit::Meters),
+            "km" => Some(GeoUnit::Kilometers),
+            "mi" => Some(GeoUnit::Miles),
+            "ft" => Some(GeoUnit::Feet),
+            _ => None,
+        }
+    }
+
+    /// Returns the unit as GEOSEARCH takes it
+    pub fn name(self) -> &'static str {
+        match self {
+            GeoUnit::Meters => "m",
+            GeoUnit::Kilometers => "km",
+            GeoUnit::Miles => "mi",
+            GeoUnit::Feet => "ft",
+        }
+    }
+
+    /// Returns how many meters one unit is
+    pub fn meters(self) -> f64 {
+        match self {
+            GeoUnit::Meters => 1.0,
+            GeoUnit::Kilometers => 1000.0,
+            GeoUnit::Miles => 1609.34,
+            GeoUnit::Feet => 0.3048,
+        }
+    }
+}
+
+/// Where a GEOSEARCH is centred
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoOrigin {
+    /// `FROMMEMBER`: at a member of the searched key
+    Member(String),
+    /// `FROMLONLAT`: at a longitude and latitude
+    LonLat(f64, f64),
+}
+
+/// The area a GEOSEARCH covers, in its unit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoShape {
+    /// `BYRADIUS`: a circle of this radius
+    Radius(f64),
+    /// `BYBOX`: a box this wide and this high
+    Box(f64, f64),
+}
+
+/// What GEOSEARCH looks for, and which details it replies with
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoSearchParams {
+    pub origin: GeoOrigin,
+    pub shape: GeoShape,
+    pub unit: GeoUnit,
+    /// `Some(true)` for DESC, `Some(false)` for ASC; `None` leaves matches
+    /// in geohash order unless COUNT is given without ANY
+    pub descending: Option<bool>,
+    /// `COUNT`: most matches to return
+    pub count: Option<usize>,
+    /// `ANY`: stop at the first `count` matches rather than the closest
+    pub any: bool,
+    pub with_coord: bool,
+    pub with_dist: bool,
+    pub with_hash: bool,
+}
+
+impl GeoSearchParams {
+    /// Returns whether any WITH* option is given, in which case each match
+    /// is replied with as an array of the member and the details asked for
+    pub fn with_details(&self) -> bool {
+        self.with_coord || self.with_dist || self.with_hash
+    }
+
+    /// Returns the GEOSEARCH arguments after the key that ask for this search,
+    /// without the WITH* options
+    pub fn to_args(&self) -> Vec<String> {
+        let mut words = match &self.origin {
+            GeoOrigin::Member(member) => vec!["FROMMEMBER".to_string(), member.clone()],
+            GeoOrigin::LonLat(longitude, latitude) => {
+                vec!["FROMLONLAT".to_string(), longitude.to_string(), latitude.to_string()]
+            }
+        };
+        match self.shape {
+            GeoShape::Radius(radius) => words.extend(["BYRADIUS".to_string(), radius.to_string()]),
+            GeoShape::Box(width, height) => words.extend(["BYBOX".to_string(), width.to_string(), height.to_string()]),
+        }
+        words.push(self.unit.name().to_string());
+        match self.descending {
+            Some(true) => words.push("DESC".to_string()),
+            Some(false) => words.push("ASC".to_string()),
+            None => {}
+        }
+        if let Some(count) = self.count {
+            words.extend(["COUNT".to_string(), count.to_string()]);
+            if self.any {
+                words.push("ANY".to_string());
+            }
+        }
+        words
+    }
+}
+
+/// How GEOSEARCH STORE and STOREDIST score the members they store
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoStoreMode {
+    /// `STORE`: by geohash, so the result is a geo index itself
+    Geohash,
+    /// `STOREDIST`: by distance from the centre, in the search's unit
+    Distance,
+}
+
+/// One member GEOSEARCH found
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoMatch {
+    pub member: String,
+    /// Distance from the centre, in the search's unit
+    pub distance: f64,
+    /// The member's score, its geohash
+    pub hash: f64,
+    pub longitude: f64,
+    pub latitude: f64,
+}
+
+/// Returns the members of a geo index inside the search's area
+///
+/// # Returns
+///
+/// * `Ok(Vec<GeoMatch>)` - The matches, ordered and limited as asked
+/// * `Err(String)` - If the search is centred on a member that isn't there
+pub fn search(zset: &SortedSet, params: &GeoSearchParams) -> Result<Vec<GeoMatch>, String> {
+    let (longitude, latitude) = match &params.origin {
+        GeoOrigin::Member(member) => {
+            decode(zset.score(member).ok_or_else(|| "ERR could not decode requested zset member".to_string())?)
+        }
+        GeoOrigin::LonLat(longitude, latitude) => (*longitude, *latitude),
+    };
+    let unit = params.unit.meters();
+    let mut matches = Vec::new();
+    // The centre's cell comes first, so ANY usually stops inside it
+    'cells: for (min, max) in cell_ranges(longitude, latitude, params.shape, unit) {
+        for (member, hash) in zset.range_by_score(ScoreBound::Inclusive(min), ScoreBound::Exclusive(max)) {
+            let (member_longitude, member_latitude) = decode(hash);
+            let inside = match params.shape {
+                GeoShape::Radius(radius) => distance(longitude, latitude, member_longitude, member_latitude) <= radius * unit,
+                // The width is measured along the member's own parallel, as Redis does
+                GeoShape::Box(width, height) => {
+                    distance(member_longitude, latitude, member_longitude, member_latitude) <= height * unit / 2.0
+                        && distance(longitude, member_latitude, member_longitude, member_latitude) <= width * unit / 2.0
+                }
+            };
+            if !inside {
+                continue;
+            }
+            matches.push(GeoMatch {
+                member: member.to_string(),
+                distance: distance(longitude, latitude, member_longitude, member_latitude) / unit,
+                hash,
+                longitude: member_longitude,
+                latitude: member_latitude,
+            });
+            if params.any && params.count == Some(matches.len()) {
+                break 'cells;
+            }
+        }
+    }
+    sort_geo_results(&mut matches, params.descending, params.count.map(|count| (count, params.any)));
+    Ok(matches)
+}
+
+/// Orders GEOSEARCH's matches by distance and keeps the first `count`
+///
+/// # Arguments
+///
+/// * `results` - The matches, in the order they were found
+/// * `descending` - `Some(true)` for DESC, `Some(false)` for ASC, `None`
+///   to leave them as found
+/// * `count` - COUNT, and whether ANY was given; with ANY the first `count`
+///   found are kept, without it the closest, so the matches are sorted
+///   nearest first unless DESC is asked
+///
+/// The sort is stable either way: matches at the same distance stay in
+/// the order they were found in.
+pub fn sort_geo_results(results: &mut Vec<GeoMatch>, descending: Option<bool>, count: Option<(usize, bool)>) {
+    if let Some((count, true)) = count {
+        results.truncate(count);
+    }
+    let descending = descending.or(matches!(count, Some((_, false))).then_some(false));
+    match descending {
+        Some(false) => results.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+        Some(true) => results.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+        None => {}
+    }
+    if let Some((count, false)) = count {
+        results.truncate(count);
+    }
+}
+
+/// Returns the score ranges of the geohash cells that can hold members
+/// inside a search's area, the centre's own cell first
+///
+/// These are the cell holding the centre and its eight neighbours, at the
+/// finest step where the nine cells still cover the area's bounding box.
+/// Neighbours wrap around the antimeridian; rows past the latitude range
+/// are dropped, and so are cells that repeat at the coarsest steps.
+fn cell_ranges(longitude: f64, latitude: f64, shape: GeoShape, unit: f64) -> Vec<(f64, f64)> {
+    let (half_width, half_height) = match shape {
+        GeoShape::Radius(radius) => (radius * unit, radius * unit),
+        GeoShape::Box(width, height) => (width * unit / 2.0, height * unit / 2.0),
+    };
+    let latitude_delta = (half_height / EARTH_RADIUS).to_degrees();
+    // The widest longitude span on the sphere, or None if it takes them all
+    let longitude_delta = match shape {
+        GeoShape::Radius(_) => {
+            let ratio = (half_width / EARTH_RADIUS).min(std::f64::consts::FRAC_PI_2).sin() / latitude.to_radians().cos();
+            (half_width / EARTH_RADIUS < std::f64::consts::FRAC_PI_2 && ratio < 1.0).then(|| ratio.asin().to_degrees())
+        }
+        // Box members are measured along their own parallel, widest nearest the pole
+        GeoShape::Box(..) => {
+            let widest = (latitude.abs() + latitude_delta).min(90.0);
+            let ratio = (half_width / EARTH_RADIUS / 2.0).min(std::f64::consts::FRAC_PI_2).sin() / widest.to_radians().cos();
+            (ratio < 1.0).then(|| 2.0 * ratio.asin().to_degrees())
+        }
+    };
+    let south = (latitude - latitude_delta).max(LATITUDE_RANGE.0);
+    let north = (latitude + latitude_delta).min(LATITUDE_RANGE.1);
+
+    let covers = |step: u32| {
+        let cells = 1u64 << step;
+        let block = |value: f64, (min, max): (f64, f64)| {
+            let size = (max - min) / cells as f64;
+            let centre = (cell(value, (min, max)) >> (STEP - step)) as f64;
+            (min + (centre - 1.0) * size, min + (centre + 2.0) * size)
+        };
+        let (bottom, top) = block(latitude, LATITUDE_RANGE);
+        let (left, right) = block(longitude, LONGITUDE_RANGE);
+        let longitude_covered = cells <= 3
+            || longitude_delta.is_some_and(|delta| left <= longitude - delta && longitude + delta <= right);
+        bottom <= south && north <= top && longitude_covered
+    };
+    let step = (1..=STEP).rev().find(|&step| covers(step)).unwrap_or(1);
+
+    let cells = 1i64 << step;
+    let centre_latitude = (cell(latitude, LATITUDE_RANGE) >> (STEP - step)) as i64;
+    let centre_longitude = (cell(longitude, LONGITUDE_RANGE) >> (STEP - step)) as i64;
+    let shift = 2 * (STEP - step);
+    let mut ranges = Vec::with_capacity(9);
+    for row in [0, -1, 1].map(|offset| centre_latitude + offset) {
+        if !(0..cells).contains(&row) {
+            continue;
+        }
+        for column in [0, -1, 1].map(|offset| (centre_longitude + offset).rem_euclid(cells)) {
+            let hash = spread(row as u64) | spread(column as u64) << 1;
+            let range = ((hash << shift) as f64, ((hash + 1) << shift) as f64);
+            if !ranges.contains(&range) {
+                ranges.push(range);
+            }
+        }
+    }
+    ranges
+}
+
+/// Returns whether GEOADD accepts the coordinates
+pub fn valid_coordinates(longitude: f64, latitude: f64) -> bool {
+    (LONGITUDE_RANGE.0..=LONGITUDE_RANGE.1).contains(&longitude)
+        && (LATITUDE_RANGE.0..=LATITUDE_RANGE.1).contains(&latitude)
+}
+
+/// Returns the geohash score of valid coordinates
+///
+/// Each coordinate is scaled to 26 bits across its range, then the bits are
+/// interleaved with latitude in the even positions, as Redis does, so the
+/// scores agree with a real server's.
+pub fn encode(longitude: f64, latitude: f64) -> f64 {
+    let latitude = spread(cell(latitude, LATITUDE_RANGE));
+    let longitude = spread(cell(longitude, LONGITUDE_RANGE));
+    (latitude | longitude << 1) as f64
+}
+
+/// Returns which of the 2^26 cells across `range` a coordinate falls in
+fn cell(value: f64, (min, max): (f64, f64)) -> u64 {
+    let cells = (1u64 << STEP) as f64;
+    (((value - min) / (max - min) * cells) as u64).min((1 << STEP) - 1)
+}
+
+/// Returns the coordinates at the centre of a geohash score's cell
+pub fn decode(score: f64) -> (f64, f64) {
+    let bits = score as u64;
+    let centre = |cell: u64, (min, max): (f64, f64)| {
+        let size = (max - min) / (1u64 << STEP) as f64;
+        (min + (cell as f64 + 0.5) * size).clamp(min, max)
+    };
+    (centre(squash(bits >> 1), LONGITUDE_RANGE), centre(squash(bits), LATITUDE_RANGE))
+}
+
+/// Returns the great-circle distance in meters between two points
+pub fn distance(longitude1: f64, latitude1: f64, longitude2: f64, latitude2: f64) -> f64 {
+    let (latitude1, latitude2) = (latitude1.to_radians(), latitude2.to_radians());
+    let u = ((latitude2 - latitude1) / 2.0).sin();
+    let v = ((longitude2 - longitude1).to_radians() / 2.0).sin();
+    2.0 * EARTH_RADIUS * (u * u + latitude1.cos() * latitude2.cos() * v * v).sqrt().asin()
+}
+
+/// Moves the low 32 bits of `bits` to the even bit positions
+fn spread(bits: u64) -> u64 {
+    let mut bits = bits & 0xFFFF_FFFF;
+    bits = (bits | bits << 16) & 0x0000_FFFF_0000_FFFF;
+    bits = (bits | bits << 8) & 0x00FF_00FF_00FF_00FF;
+    bits = (bits | bits << 4) & 0x0F0F_0F0F_0F0F_0F0F;
+    bits = (bits | bits << 2) & 0x3333_3333_3333_3333;
+    (bits | bits << 1) & 0x5555_5555_5555_5555
+}
+
+/// Moves the even bit positions of `bits` to the low 32 bits, undoing `spread`
+fn squash(bits: u64) -> u64 {
+    let mut bits = bits & 0x5555_5555_5555_5555;
+    bits = (bits | bits >> 1) & 0x3333_3333_3333_3333;
+    bits = (bits | bits >> 2) & 0x0F0F_0F0F_0F0F_0F0F;
+    bits = (bits | bits >> 4) & 0x00FF_00FF_00FF_00FF;
+    bits = (bits | bits >> 8) & 0x0000_FFFF_0000_FFFF;
+    (bits | bits >> 16) & 0x0000_0000_FFFF_FFFF
+}
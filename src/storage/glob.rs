@@ -0,0 +1,155 @@
+//! # Glob Module
+//!
+//! Redis-style glob patterns, as used by KEYS-like key matching and by
+//! PSUBSCRIBE. Supported syntax:
+//! - `*` matches any run of bytes, `?` matches exactly one byte
+//! - `[abc]`, `[a-z]` and `[^a-z]` match one byte from (or not from) a set
+//! - `\x` matches `x` literally
+//!
+//! Patterns are compiled once so matching doesn't re-parse them.
+
+/// One compiled element of a pattern
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Byte(u8),
+    AnyByte,
+    AnyRun,
+    Class { negated: bool, ranges: Vec<(u8, u8)> },
+}
+
+impl Token {
+    fn matches(&self, byte: u8) -> bool {
+        match self {
+            Token::Byte(expected) => *expected == byte,
+            Token::AnyByte => true,
+            Token::AnyRun => false,
+            Token::Class { negated, ranges } => {
+                ranges.iter().any(|&(low, high)| low <= byte && byte <= high) != *negated
+            },
+        }
+    }
+}
+
+/// A compiled glob pattern
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobPattern {
+    tokens: Vec<Token>,
+    prefix: Vec<u8>,
+}
+
+impl GlobPattern {
+    /// Compiles `pattern`
+    ///
+    /// An unterminated `[` and a trailing `\` match themselves literally.
+    pub fn new(pattern: &str) -> Self {
+        let bytes = pattern.as_bytes();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'*' => {
+                    // Consecutive stars behave like one
+                    if tokens.last() != Some(&Token::AnyRun) {
+                        tokens.push(Token::AnyRun);
+                    }
+                },
+                b'?' => tokens.push(Token::AnyByte),
+                b'\\' if i + 1 < bytes.len() => {
+                    i += 1;
+                    tokens.push(Token::Byte(bytes[i]));
+                },
+                b'[' => match Self::parse_class(&bytes[i + 1..]) {
+                    Some((token, consumed)) => {
+                        tokens.push(token);
+                        i += consumed;
+                    },
+                    None => tokens.push(Token::Byte(b'[')),
+                },
+                byte => tokens.push(Token::Byte(byte)),
+            }
+            i += 1;
+        }
+
+        let prefix = tokens
+            .iter()
+            .map_while(|token| match token {
+                Token::Byte(byte) => Some(*byte),
+                _ => None,
+            })
+            .collect();
+        GlobPattern { tokens, prefix }
+    }
+
+    /// Parses the body of a `[...]` class, returning it and the bytes consumed
+    /// including the closing `]`
+    fn parse_class(bytes: &[u8]) -> Option<(Token, usize)> {
+        let mut i = 0;
+        let negated = bytes.first() == Some(&b'^');
+        if negated {
+            i += 1;
+        }
+        let mut ranges = Vec::new();
+        while i < bytes.len() {
+            let low = match bytes[i] {
+                b']' => return Some((Token::Class { negated, ranges }, i + 1)),
+                b'\\' if i + 1 < bytes.len() => {
+                    i += 1;
+                    bytes[i]
+                },
+                byte => byte,
+            };
+            if i + 2 < bytes.len() && bytes[i + 1] == b'-' && bytes[i + 2] != b']' {
+                let high = bytes[i + 2];
+                ranges.push((low.min(high), low.max(high)));
+                i += 3;
+            } else {
+                ranges.push((low, low));
+                i += 1;
+            }
+        }
+        None
+    }
+
+    /// Returns the literal bytes every match must start with
+    pub fn literal_prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// Returns whether `text` matches the whole pattern
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.as_bytes();
+        let (mut p, mut t) = (0, 0);
+        // Where the last `*` was and how much text it has swallowed so far
+        let mut backtrack: Option<(usize, usize)> = None;
+
+        while t < text.len() {
+            match self.tokens.get(p) {
+                Some(Token::AnyRun) => {
+                    backtrack = Some((p, t));
+                    p += 1;
+                    continue;
+                },
+                Some(token) if token.matches(text[t]) => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                },
+                _ => {},
+            }
+            match backtrack {
+                Some((star, swallowed)) => {
+                    p = star + 1;
+                    t = swallowed + 1;
+                    backtrack = Some((star, t));
+                },
+                None => return false,
+            }
+        }
+        self.tokens[p..].iter().all(|token| *token == Token::AnyRun)
+    }
+}
+
+/// Returns whether `text` matches the glob `pattern`
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    GlobPattern::new(pattern).matches(text)
+}
@@ -0,0 +1,40 @@
+//! # LFU Module
+//!
+//! The access frequency counter behind OBJECT FREQ, computed the way Redis
+//! does for its LFU maxmemory policies (`lfu-log-factor 10`,
+//! `lfu-decay-time 1`). The counter is 8 bits wide and grows
+//! logarithmically: each access increments it with probability
+//! `1 / ((counter - LFU_INIT_VAL) * LFU_LOG_FACTOR + 1)`, so about a
+//! million hits are needed to saturate it at 255. It then loses one point
+//! for every `LFU_DECAY_MINUTES` the key goes unaccessed.
+
+/// Counter of a freshly created key, so new keys aren't evicted at once
+pub const LFU_INIT_VAL: u8 = 5;
+
+/// How slowly the counter grows; Redis's `lfu-log-factor` default
+pub const LFU_LOG_FACTOR: f64 = 10.0;
+
+/// Minutes without access that cost the counter one point; Redis's `lfu-decay-time` default
+pub const LFU_DECAY_MINUTES: u64 = 1;
+
+/// Counts one access, as Redis's `LFULogIncr`
+///
+/// # Arguments
+///
+/// * `counter` - The current counter
+/// * `random` - A random number in `[0, 1)`; the increment happens if it is
+///   below the increment probability
+pub fn log_incr(counter: u8, random: f64) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+    let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+    let probability = 1.0 / (base * LFU_LOG_FACTOR + 1.0);
+    if random < probability { counter + 1 } else { counter }
+}
+
+/// Applies the decay for `idle_minutes` without access, as Redis's `LFUDecrAndReturn`
+pub fn decay(counter: u8, idle_minutes: u64) -> u8 {
+    let periods = idle_minutes / LFU_DECAY_MINUTES;
+    u8::try_from(periods).map_or(0, |periods| counter.saturating_sub(periods))
+}
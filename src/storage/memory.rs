@@ -1,23 +1,81 @@
 //! # Memory Storage Module
 //! 
 //! Provides in-memory storage implementation with support for:
-//! - String and List data types
+//! - String, List, Set, Sorted Set, Hash and Stream data types
+//! - Key expiration (TTLs), reaped on access and by sampling in the background
 //! - Transaction management with MULTI/EXEC/DISCARD
-//! - Snapshots for persistence
+//! - Snapshots for persistence and full syncs, with a count of writes since the last one
 //! - LRU caching
+//! - LFU access counters for OBJECT FREQ
+//! - Memory estimates and key eviction for `max_memory`
 //! - Thread-safe concurrent access
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use rand::seq::IteratorRandom;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::fs::File;
 use std::io::{self, BufWriter, BufReader, Write, BufRead};
 use crate::cache::avlcache::AVLCache;
-use std::time::Duration;
+use crate::cluster::slots::hash_slot;
+use crate::storage::encoding::{self, EncodingLimits};
+use crate::storage::eviction::{MaxMemoryPolicy, EVICTION_SAMPLES};
+use crate::storage::lfu;
+use crate::storage::notify::NotifyFlags;
+use crate::storage::scan;
+use crate::storage::geo::{self, GeoAddOptions, GeoMatch, GeoSearchParams, GeoStoreMode};
+use crate::storage::stream::{Consumer, ConsumerGroup, PendingEntry, Stream, StreamEntry, StreamEntryId, XAddId};
+use crate::storage::zset::{AggType, LexBound, ScoreBound, SortedSet, ZAddOptions};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-/// Represents a single transaction layer with changes to strings and lists
+/// Most expired keys one `purge_expired_sample` call removes
+pub const ACTIVE_EXPIRE_SAMPLE: usize = 20;
+
+/// Bytes `used_memory` adds for every key, beyond its name and contents
+const KEY_OVERHEAD: usize = 64;
+
+/// Entries the read cache in front of the strings holds
+pub const CACHE_CAPACITY: usize = 1000;
+
+/// The least memory a full read cache's keys take, by `used_memory`'s count
+pub const CACHE_MEMORY: usize = CACHE_CAPACITY * KEY_OVERHEAD;
+
+/// Bytes `used_memory` adds for every element of a collection, beyond its contents
+const ELEMENT_OVERHEAD: usize = 16;
+
+/// Bytes `memory_usage_for` adds for a collection's own structure
+const COLLECTION_OVERHEAD: usize = 128;
+
+/// Bytes `memory_usage_for` adds for each sorted set member's skiplist node
+const SKIPLIST_NODE_OVERHEAD: usize = 24;
+
+/// Elements MEMORY USAGE samples from a collection unless told otherwise
+pub const MEMORY_USAGE_SAMPLES: usize = 5;
+
+/// The condition EXPIRE's NX, XX, GT or LT option puts on a new deadline
+///
+/// A key without an expiration counts as never expiring, so GT never
+/// applies to it and LT always does. At most one flag is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExpireFlags {
+    /// Only set a deadline on a key that has none
+    pub nx: bool,
+    /// Only replace an existing deadline
+    pub xx: bool,
+    /// Only move the deadline later
+    pub gt: bool,
+    /// Only move the deadline earlier
+    pub lt: bool,
+}
+
+/// Represents a single transaction layer with changes to every data type
 #[derive(Clone)]
 struct TransactionLayer {
     strings: HashMap<String, Option<String>>,
     lists: HashMap<String, Option<VecDeque<String>>>,
+    sets: HashMap<String, Option<HashSet<String>>>,
+    zsets: HashMap<String, Option<SortedSet>>,
+    hashes: HashMap<String, Option<HashMap<String, String>>>,
+    streams: HashMap<String, Option<Stream>>,
 }
 
 /// Main storage engine implementing Redis-like functionality
@@ -27,8 +85,144 @@ struct TransactionLayer {
 pub struct MemoryStorage {
     strings: Arc<HashMap<String, String>>,
     lists: Arc<HashMap<String, VecDeque<String>>>,
+    sets: Arc<HashMap<String, HashSet<String>>>,
+    zsets: Arc<HashMap<String, SortedSet>>,
+    hashes: Arc<HashMap<String, HashMap<String, String>>>,
+    streams: Arc<HashMap<String, Stream>>,
     transaction_stack: Vec<TransactionLayer>,
     cache: AVLCache<String,String>,
+    expires: HashMap<String, Instant>,
+    // Access counters live outside the transaction layers: they describe
+    // how keys are used, not what they hold
+    lfu_freq: HashMap<String, u8>,
+    lfu_last_access_sec: HashMap<String, u64>,
+    // Writes since the last snapshot; shared so a saver can poll it without the lock
+    dirty_count: Arc<AtomicU64>,
+    last_save_time: Instant,
+    encoding_limits: EncodingLimits,
+    // Collections a write took past their compact encoding, with the encoding
+    // they were upgraded to; they keep it until they are deleted
+    upgraded_encodings: HashMap<String, &'static str>,
+    // Keys by hash slot for CLUSTER GETKEYSINSLOT, kept only in cluster mode.
+    // Keys are added as they're written and dropped lazily once they're gone
+    keys_by_slot: Option<HashMap<u16, Vec<String>>>,
+    // Whether expired keys may be reaped in the background, not only on access
+    active_expire: bool,
+    notify_flags: NotifyFlags,
+    // Publishes a keyspace notification: channel, then message
+    notifier: Option<Notifier>,
+}
+
+type Notifier = Arc<dyn Fn(&str, &str) + Send + Sync>;
+
+/// A point-in-time copy of the committed data, taken by `MemoryStorage::frozen`
+///
+/// The maps are shared copy-on-write with the storage, so taking one is
+/// cheap and later writes don't show through.
+pub struct FrozenStorage {
+    pub(crate) strings: Arc<HashMap<String, String>>,
+    pub(crate) lists: Arc<HashMap<String, VecDeque<String>>>,
+    pub(crate) sets: Arc<HashMap<String, HashSet<String>>>,
+    pub(crate) zsets: Arc<HashMap<String, SortedSet>>,
+    pub(crate) hashes: Arc<HashMap<String, HashMap<String, String>>>,
+    pub(crate) streams: Arc<HashMap<String, Stream>>,
+    /// Expiration deadlines as Unix times in seconds, rounded up; keys
+    /// already past their deadline are absent from the maps too
+    pub(crate) expire_at: HashMap<String, u64>,
+}
+
+impl FrozenStorage {
+    /// Writes the data as snapshot records, one line per key
+    ///
+    /// Each line is `STRING key value`, `LIST key len items..`, `SET key
+    /// len members..`, `ZSET key len score member..` or `HASH key len field
+    /// value..`, and every deadline follows as `EXPIREAT key unix_seconds`.
+    /// A stream takes a `STREAM key last_id max_deleted_id entries_added`
+    /// line, then `XENTRY key id field value..` per entry, `XGROUP key group
+    /// last_delivered_id entries_read` per group (`-` if unknown), `XCONSUMER
+    /// key group consumer` per consumer and `XPENDING key group id consumer
+    /// delivery_count` per pending entry. Delivery and activity times are not
+    /// saved. `MemoryStorage::load_snapshot_from` reads them back.
+    pub fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        for (key, value) in self.strings.iter() {
+            writeln!(writer, "STRING {} {}", key, value)?;
+        }
+
+        for (key, list) in self.lists.iter() {
+            write!(writer, "LIST {} {}", key, list.len())?;
+            for item in list {
+                write!(writer, " {}", item)?;
+            }
+            writeln!(writer)?;
+        }
+
+        for (key, set) in self.sets.iter() {
+            write!(writer, "SET {} {}", key, set.len())?;
+            for member in set {
+                write!(writer, " {}", member)?;
+            }
+            writeln!(writer)?;
+        }
+
+        for (key, zset) in self.zsets.iter() {
+            write!(writer, "ZSET {} {}", key, zset.len())?;
+            for (member, score) in zset.iter() {
+                write!(writer, " {} {}", score, member)?;
+            }
+            writeln!(writer)?;
+        }
+
+        for (key, hash) in self.hashes.iter() {
+            write!(writer, "HASH {} {}", key, hash.len())?;
+            for (field, value) in hash {
+                write!(writer, " {} {}", field, value)?;
+            }
+            writeln!(writer)?;
+        }
+
+        for (key, stream) in self.streams.iter() {
+            writeln!(writer, "STREAM {} {} {} {}", key, stream.last_id, stream.max_deleted_entry_id, stream.entries_added)?;
+            for (id, fields) in &stream.entries {
+                write!(writer, "XENTRY {} {}", key, id)?;
+                for (field, value) in fields {
+                    write!(writer, " {} {}", field, value)?;
+                }
+                writeln!(writer)?;
+            }
+            for (name, group) in &stream.groups {
+                let entries_read = group.entries_read.map_or_else(|| "-".to_string(), |read| read.to_string());
+                writeln!(writer, "XGROUP {} {} {} {}", key, name, group.last_delivered_id, entries_read)?;
+                for consumer in group.consumers.keys() {
+                    writeln!(writer, "XCONSUMER {} {} {}", key, name, consumer)?;
+                }
+                for (id, pending) in &group.pel {
+                    writeln!(writer, "XPENDING {} {} {} {} {}", key, name, id, pending.consumer, pending.delivery_count)?;
+                }
+            }
+        }
+
+        for (key, at) in &self.expire_at {
+            writeln!(writer, "EXPIREAT {} {}", key, at)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the snapshot to `path`, through a temporary file renamed into
+    /// place so a failed save never clobbers the previous snapshot
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let temp_path = format!("{}.tmp", path);
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        self.write_snapshot(&mut writer)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(&temp_path, path)
+    }
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MemoryStorage {
@@ -37,33 +231,277 @@ impl MemoryStorage {
         MemoryStorage {
             strings: Arc::new(HashMap::new()),
             lists: Arc::new(HashMap::new()),
+            sets: Arc::new(HashMap::new()),
+            zsets: Arc::new(HashMap::new()),
+            hashes: Arc::new(HashMap::new()),
+            streams: Arc::new(HashMap::new()),
             transaction_stack: Vec::new(),
-            cache: AVLCache::new(1000, Duration::from_secs(300)),
+            cache: AVLCache::new(CACHE_CAPACITY, Duration::from_secs(300)),
+            expires: HashMap::new(),
+            lfu_freq: HashMap::new(),
+            lfu_last_access_sec: HashMap::new(),
+            dirty_count: Arc::new(AtomicU64::new(0)),
+            last_save_time: Instant::now(),
+            encoding_limits: EncodingLimits::default(),
+            upgraded_encodings: HashMap::new(),
+            keys_by_slot: None,
+            active_expire: true,
+            notify_flags: NotifyFlags::default(),
+            notifier: None,
+        }
+    }
+
+    /// Sets the limits under which a list reports the listpack encoding
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - Most elements a listpack list may hold
+    /// * `max_value` - Longest element, in bytes, a listpack list may hold
+    pub fn set_list_encoding_limits(&mut self, max_size: usize, max_value: usize) {
+        self.encoding_limits.list_max_listpack_size = max_size;
+        self.encoding_limits.list_max_listpack_value = max_value;
+    }
+
+    /// Returns the `(max_size, max_value)` set by `set_list_encoding_limits`
+    pub fn list_encoding_limits(&self) -> (usize, usize) {
+        (self.encoding_limits.list_max_listpack_size, self.encoding_limits.list_max_listpack_value)
+    }
+
+    /// Sets the limits under which every type keeps its compact encoding
+    ///
+    /// Collections already upgraded stay upgraded; the others are judged by
+    /// the new limits from now on, and upgraded by the next write past them.
+    pub fn set_encoding_limits(&mut self, limits: EncodingLimits) {
+        self.encoding_limits = limits;
+    }
+
+    /// Returns the limits set by `set_encoding_limits`
+    pub fn encoding_limits(&self) -> EncodingLimits {
+        self.encoding_limits
+    }
+
+    /// Sets which keyspace events are published (`notify-keyspace-events`)
+    pub fn set_notify_flags(&mut self, flags: NotifyFlags) {
+        self.notify_flags = flags;
+    }
+
+    /// Returns the flags set by `set_notify_flags`
+    pub fn notify_flags(&self) -> NotifyFlags {
+        self.notify_flags
+    }
+
+    /// Sets where keyspace notifications go, e.g. the server's Pub/Sub
+    ///
+    /// `publish` is called with the storage locked, once per channel, with
+    /// the channel and the message.
+    pub fn set_notifier(&mut self, publish: impl Fn(&str, &str) + Send + Sync + 'static) {
+        self.notifier = Some(Arc::new(publish));
+    }
+
+    /// Publishes what a *STORE command did to its destination: `event`, or
+    /// `del` if the result was empty and the destination was deleted
+    fn notify_stored(&self, class: NotifyFlags, event: &str, destination: &str, stored: usize) {
+        let destination = destination.to_lowercase();
+        match stored {
+            0 => self.notify(NotifyFlags::GENERIC, "del", &destination),
+            _ => self.notify(class, event, &destination),
+        }
+    }
+
+    /// Publishes `event` on the key if the flags ask for events of `class`
+    ///
+    /// The flags are checked first, so with notifications off no channel
+    /// name is ever built.
+    fn notify(&self, class: NotifyFlags, event: &str, key: &str) {
+        if !self.notify_flags.publishes(class) {
+            return;
+        }
+        let Some(publish) = &self.notifier else {
+            return;
+        };
+        if self.notify_flags.contains(NotifyFlags::KEYSPACE) {
+            publish(&format!("__keyspace@0__:{}", key), event);
+        }
+        if self.notify_flags.contains(NotifyFlags::KEYEVENT) {
+            publish(&format!("__keyevent@0__:{}", event), key);
+        }
+    }
+
+    /// Counts the lists by the encoding OBJECT ENCODING reports: `(listpack, quicklist)`
+    pub fn list_encoding_counts(&self) -> (usize, usize) {
+        let mut keys: HashSet<&String> = self.lists.keys().collect();
+        for layer in &self.transaction_stack {
+            keys.extend(layer.lists.keys());
+        }
+        let listpack = keys
+            .iter()
+            .filter(|key| self.object_encoding_list(key) == Some("listpack"))
+            .count();
+        let quicklist = keys
+            .iter()
+            .filter(|key| self.object_encoding_list(key) == Some("quicklist"))
+            .count();
+        (listpack, quicklist)
+    }
+
+    /// Allows or stops reaping expired keys in the background (DEBUG SET-ACTIVE-EXPIRE)
+    ///
+    /// Keys are still expired when accessed either way.
+    pub fn set_active_expire(&mut self, enabled: bool) {
+        self.active_expire = enabled;
+    }
+
+    /// Whether expired keys may be reaped in the background
+    pub fn active_expire_enabled(&self) -> bool {
+        self.active_expire
+    }
+
+    /// Starts keeping the per-slot key index CLUSTER GETKEYSINSLOT reads
+    ///
+    /// Keys already stored are indexed right away.
+    pub fn enable_slot_index(&mut self) {
+        self.keys_by_slot = Some(HashMap::new());
+        self.reindex_slots();
+    }
+
+    /// Whether `enable_slot_index` was called
+    pub fn slot_index_enabled(&self) -> bool {
+        self.keys_by_slot.is_some()
+    }
+
+    fn reindex_slots(&mut self) {
+        let Some(index) = self.keys_by_slot.as_mut() else {
+            return;
+        };
+        index.clear();
+        let keys = self
+            .strings
+            .keys()
+            .chain(self.lists.keys())
+            .chain(self.sets.keys())
+            .chain(self.zsets.keys())
+            .chain(self.hashes.keys())
+            .chain(self.streams.keys());
+        for key in keys {
+            index.entry(hash_slot(key)).or_default().push(key.clone());
+        }
+    }
+
+    /// Adds a key that is being written to the slot index, if there is one
+    fn index_key(&mut self, key: &str) {
+        if let Some(index) = self.keys_by_slot.as_mut() {
+            let bucket = index.entry(hash_slot(key)).or_default();
+            if !bucket.iter().any(|indexed| indexed == key) {
+                bucket.push(key.to_string());
+            }
+        }
+    }
+
+    /// Returns up to `count` existing keys whose hash slot is `slot`
+    ///
+    /// Keys deleted or expired since they were indexed are dropped from the
+    /// index here. Always empty unless the slot index is enabled.
+    pub fn cluster_getkeysinslot(&mut self, slot: u16, count: usize) -> Vec<String> {
+        let Some(bucket) = self.keys_by_slot.as_mut().and_then(|index| index.remove(&slot)) else {
+            return Vec::new();
+        };
+        let mut live = Vec::with_capacity(bucket.len());
+        for key in bucket {
+            if !self.expire_if_needed(&key) && self.exists(&key) {
+                live.push(key);
+            }
         }
+        let keys = live.iter().take(count).cloned().collect();
+        if let Some(index) = self.keys_by_slot.as_mut().filter(|_| !live.is_empty()) {
+            index.insert(slot, live);
+        }
+        keys
+    }
+
+    /// Returns how many writes happened since the last snapshot
+    pub fn dirty_count(&self) -> u64 {
+        self.dirty_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the write counter itself, readable without the storage lock
+    pub fn dirty_counter(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.dirty_count)
+    }
+
+    /// Returns the time since the last snapshot, or since startup
+    pub fn since_last_save(&self) -> Duration {
+        self.last_save_time.elapsed()
+    }
+
+    /// Records a successful snapshot: resets the write counter and the save time
+    pub fn mark_saved(&mut self) {
+        self.dirty_count.store(0, Ordering::Relaxed);
+        self.last_save_time = Instant::now();
+    }
+
+    /// Records a snapshot of a `frozen` copy that was written without the lock
+    ///
+    /// Only the `dirty_at_freeze` writes the copy holds are taken off the
+    /// counter; writes made while it was being saved still count.
+    pub fn mark_saved_since(&mut self, dirty_at_freeze: u64) {
+        let _ = self.dirty_count.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |dirty| {
+            Some(dirty.saturating_sub(dirty_at_freeze))
+        });
+        self.last_save_time = Instant::now();
+    }
+
+   /// Helper method to count one write towards the next snapshot
+    fn mark_dirty(&self) {
+        self.dirty_count.fetch_add(1, Ordering::Relaxed);
     }
 
    /// Saves the current storage state to a file
    ///
+   /// Writes the committed data of `frozen`, in the format `write_snapshot` describes.
+   ///
    /// # Arguments
    ///
    /// * `path` - Path to save the snapshot file
     pub fn save_snapshot(&self, path: &str) -> io::Result<()> {
-        let file = File::create(path)?;
-        let mut writer = BufWriter::new(file);
+        self.frozen().save(path)
+    }
 
-        for (key, value) in self.strings.iter() {
-            writeln!(writer, "STRING {} {}", key, value)?;
+   /// Takes a point-in-time copy of the committed data, for writing out without the lock
+   ///
+   /// Open transactions are not included. Keys that have expired but
+   /// haven't been reaped yet are left out.
+    pub fn frozen(&self) -> FrozenStorage {
+        let now = Instant::now();
+        let now_unix = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut expired = HashSet::new();
+        let mut expire_at = HashMap::new();
+        for (key, deadline) in &self.expires {
+            match deadline.checked_duration_since(now) {
+                Some(remaining) if !remaining.is_zero() => {
+                    let at = now_unix + remaining;
+                    expire_at.insert(key.clone(), at.as_secs() + u64::from(at.subsec_nanos() > 0));
+                }
+                _ => {
+                    expired.insert(key.clone());
+                }
+            }
         }
-
-        for (key, list) in self.lists.iter() {
-            write!(writer, "LIST {} {}", key, list.len())?;
-            for item in list {
-                write!(writer, " {}", item)?;
+        fn live<V: Clone>(map: &Arc<HashMap<String, V>>, expired: &HashSet<String>) -> Arc<HashMap<String, V>> {
+            if expired.iter().any(|key| map.contains_key(key)) {
+                Arc::new(map.iter().filter(|(key, _)| !expired.contains(*key)).map(|(k, v)| (k.clone(), v.clone())).collect())
+            } else {
+                Arc::clone(map)
             }
-            writeln!(writer)?;
         }
-
-        Ok(())
+        FrozenStorage {
+            strings: live(&self.strings, &expired),
+            lists: live(&self.lists, &expired),
+            sets: live(&self.sets, &expired),
+            zsets: live(&self.zsets, &expired),
+            hashes: live(&self.hashes, &expired),
+            streams: live(&self.streams, &expired),
+            expire_at,
+        }
     }
 
    /// Loads storage state from a snapshot file
@@ -73,9 +511,28 @@ impl MemoryStorage {
    /// * `path` - Path to the snapshot file to load
     pub fn load_snapshot(&mut self, path: &str) -> io::Result<()> {
         let file = File::open(path)?;
-        let reader = BufReader::new(file);
+        self.load_snapshot_from(BufReader::new(file))
+    }
+
+   /// Replaces the storage state with a snapshot read from `reader`
+   ///
+   /// Every key and deadline is replaced, so nothing from before the load
+   /// survives in the data. Deadlines already past leave the key to be
+   /// reaped on its next access. Stream consumers and pending entries
+   /// count as seen and delivered at the time of the load.
+   ///
+   /// # Arguments
+   ///
+   /// * `reader` - Snapshot records, as `FrozenStorage::write_snapshot` writes them
+    pub fn load_snapshot_from(&mut self, reader: impl BufRead) -> io::Result<()> {
         let mut new_strings = HashMap::new();
         let mut new_lists = HashMap::new();
+        let mut new_sets = HashMap::new();
+        let mut new_zsets = HashMap::new();
+        let mut new_hashes = HashMap::new();
+        let mut new_streams: HashMap<String, Stream> = HashMap::new();
+        let mut expire_at = Vec::new();
+        let now = Instant::now();
 
         for line in reader.lines() {
             let line = line?;
@@ -85,18 +542,79 @@ impl MemoryStorage {
             }
 
             match parts[0] {
-                "STRING" => {
-                    if parts.len() >= 3 {
-                        new_strings.insert(parts[1].to_string(), parts[2].to_string());
+                "STRING" if parts.len() >= 3 => {
+                    new_strings.insert(parts[1].to_string(), parts[2].to_string());
+                }
+                "LIST" if parts.len() >= 3 => {
+                    let mut list = VecDeque::new();
+                    for item in &parts[3..] {
+                        list.push_back(item.to_string());
                     }
+                    new_lists.insert(parts[1].to_string(), list);
+                }
+                "SET" if parts.len() >= 3 => {
+                    let set = parts[3..].iter().map(|member| member.to_string()).collect();
+                    new_sets.insert(parts[1].to_string(), set);
                 }
-                "LIST" => {
-                    if parts.len() >= 3 {
-                        let mut list = VecDeque::new();
-                        for item in &parts[3..] {
-                            list.push_back(item.to_string());
+                "ZSET" if parts.len() >= 3 => {
+                    let mut zset = SortedSet::new();
+                    for pair in parts[3..].chunks_exact(2) {
+                        if let Ok(score) = pair[0].parse() {
+                            zset.insert(pair[1], score);
                         }
-                        new_lists.insert(parts[1].to_string(), list);
+                    }
+                    new_zsets.insert(parts[1].to_string(), zset);
+                }
+                "HASH" if parts.len() >= 3 => {
+                    let hash = parts[3..]
+                        .chunks_exact(2)
+                        .map(|pair| (pair[0].to_string(), pair[1].to_string()))
+                        .collect();
+                    new_hashes.insert(parts[1].to_string(), hash);
+                }
+                "STREAM" if parts.len() == 5 => {
+                    if let (Some(last_id), Some(max_deleted_entry_id), Ok(entries_added)) =
+                        (StreamEntryId::parse(parts[2], 0), StreamEntryId::parse(parts[3], 0), parts[4].parse())
+                    {
+                        let stream = Stream { last_id, max_deleted_entry_id, entries_added, ..Stream::new() };
+                        new_streams.insert(parts[1].to_string(), stream);
+                    }
+                }
+                "XENTRY" if parts.len() >= 3 => {
+                    if let (Some(stream), Some(id)) = (new_streams.get_mut(parts[1]), StreamEntryId::parse(parts[2], 0)) {
+                        let fields = parts[3..]
+                            .chunks_exact(2)
+                            .map(|pair| (pair[0].to_string(), pair[1].to_string()))
+                            .collect();
+                        stream.entries.insert(id, fields);
+                    }
+                }
+                "XGROUP" if parts.len() == 5 => {
+                    if let (Some(stream), Some(last_delivered_id)) = (new_streams.get_mut(parts[1]), StreamEntryId::parse(parts[3], 0)) {
+                        let group = ConsumerGroup {
+                            last_delivered_id,
+                            entries_read: parts[4].parse().ok(),
+                            pel: BTreeMap::new(),
+                            consumers: BTreeMap::new(),
+                        };
+                        stream.groups.insert(parts[2].to_string(), group);
+                    }
+                }
+                "XCONSUMER" if parts.len() == 4 => {
+                    if let Some(group) = new_streams.get_mut(parts[1]).and_then(|stream| stream.groups.get_mut(parts[2])) {
+                        group.consumers.insert(parts[3].to_string(), Consumer { seen_time: now, active_time: None });
+                    }
+                }
+                "XPENDING" if parts.len() == 6 => {
+                    let group = new_streams.get_mut(parts[1]).and_then(|stream| stream.groups.get_mut(parts[2]));
+                    if let (Some(group), Some(id), Ok(delivery_count)) = (group, StreamEntryId::parse(parts[3], 0), parts[5].parse()) {
+                        let pending = PendingEntry { consumer: parts[4].to_string(), delivered_at: now, delivery_count };
+                        group.pel.insert(id, pending);
+                    }
+                }
+                "EXPIREAT" if parts.len() == 3 => {
+                    if let Ok(at) = parts[2].parse::<u64>() {
+                        expire_at.push((parts[1].to_string(), at));
                     }
                 }
                 _ => {}
@@ -105,6 +623,29 @@ impl MemoryStorage {
 
         self.strings = Arc::new(new_strings);
         self.lists = Arc::new(new_lists);
+        self.sets = Arc::new(new_sets);
+        self.zsets = Arc::new(new_zsets);
+        self.hashes = Arc::new(new_hashes);
+        self.streams = Arc::new(new_streams);
+        let now_unix = SystemTime::now();
+        self.expires = expire_at
+            .into_iter()
+            .filter(|(key, _)| {
+                self.strings.contains_key(key)
+                    || self.lists.contains_key(key)
+                    || self.sets.contains_key(key)
+                    || self.zsets.contains_key(key)
+                    || self.hashes.contains_key(key)
+                    || self.streams.contains_key(key)
+            })
+            .map(|(key, at)| {
+                let remaining = (UNIX_EPOCH + Duration::from_secs(at)).duration_since(now_unix).unwrap_or_default();
+                (key, now + remaining)
+            })
+            .collect();
+        self.cache.clear();
+        self.reindex_slots();
+        self.mark_saved();
         Ok(())
     }
 
@@ -115,6 +656,10 @@ impl MemoryStorage {
         self.transaction_stack.push(TransactionLayer {
             strings: HashMap::new(),
             lists: HashMap::new(),
+            sets: HashMap::new(),
+            zsets: HashMap::new(),
+            hashes: HashMap::new(),
+            streams: HashMap::new(),
         });
     }
 
@@ -163,6 +708,66 @@ impl MemoryStorage {
                 }
             }
             self.lists = Arc::new(new_lists);
+
+            let mut new_sets = (*self.sets).clone();
+            for (key, value_opt) in committed_layer.sets {
+                match value_opt {
+                    Some(value) => {
+                        results.push(value.len().to_string());
+                        new_sets.insert(key, value);
+                    }
+                    None => {
+                        new_sets.remove(&key);
+                        results.push("OK".to_string());
+                    }
+                }
+            }
+            self.sets = Arc::new(new_sets);
+
+            let mut new_zsets = (*self.zsets).clone();
+            for (key, value_opt) in committed_layer.zsets {
+                match value_opt {
+                    Some(value) => {
+                        results.push(value.len().to_string());
+                        new_zsets.insert(key, value);
+                    }
+                    None => {
+                        new_zsets.remove(&key);
+                        results.push("OK".to_string());
+                    }
+                }
+            }
+            self.zsets = Arc::new(new_zsets);
+
+            let mut new_hashes = (*self.hashes).clone();
+            for (key, value_opt) in committed_layer.hashes {
+                match value_opt {
+                    Some(value) => {
+                        results.push(value.len().to_string());
+                        new_hashes.insert(key, value);
+                    }
+                    None => {
+                        new_hashes.remove(&key);
+                        results.push("OK".to_string());
+                    }
+                }
+            }
+            self.hashes = Arc::new(new_hashes);
+
+            let mut new_streams = (*self.streams).clone();
+            for (key, value_opt) in committed_layer.streams {
+                match value_opt {
+                    Some(value) => {
+                        results.push(value.len().to_string());
+                        new_streams.insert(key, value);
+                    }
+                    None => {
+                        new_streams.remove(&key);
+                        results.push("OK".to_string());
+                    }
+                }
+            }
+            self.streams = Arc::new(new_streams);
         } else {
             // This is a nested transaction, merge changes into the parent transaction
             let parent_layer = self.transaction_stack.last_mut().unwrap();
@@ -174,6 +779,22 @@ impl MemoryStorage {
                 parent_layer.lists.insert(key, value_opt);
                 results.push("QUEUED".to_string());
             }
+            for (key, value_opt) in committed_layer.sets {
+                parent_layer.sets.insert(key, value_opt);
+                results.push("QUEUED".to_string());
+            }
+            for (key, value_opt) in committed_layer.zsets {
+                parent_layer.zsets.insert(key, value_opt);
+                results.push("QUEUED".to_string());
+            }
+            for (key, value_opt) in committed_layer.hashes {
+                parent_layer.hashes.insert(key, value_opt);
+                results.push("QUEUED".to_string());
+            }
+            for (key, value_opt) in committed_layer.streams {
+                parent_layer.streams.insert(key, value_opt);
+                results.push("QUEUED".to_string());
+            }
         }
         
         self.cache.clear();
@@ -206,12 +827,20 @@ impl MemoryStorage {
     /// * `value` - The value to store
     pub fn set(&mut self, key: String, value: String) {
         let key = key.to_lowercase();
+        self.put_string(key.clone(), value);
+        self.notify(NotifyFlags::STRING, "set", &key);
+    }
+
+    fn put_string(&mut self, key: String, value: String) {
+        self.index_key(&key);
         if let Some(layer) = self.transaction_stack.last_mut() {
             layer.strings.insert(key.clone(), Some(value.clone()));
         } else {
             Arc::make_mut(&mut self.strings).insert(key.clone(), value.clone());
         }
+        self.expires.remove(&key);
         self.cache.put(key, value);
+        self.mark_dirty();
     }
 
     /// Retrieves a value by its key
@@ -259,22 +888,166 @@ impl MemoryStorage {
     ///
     /// `true` if the key existed and was marked for deletion or removed
     pub fn del(&mut self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        let removed = self.remove_key(&key);
+        if removed {
+            self.notify(NotifyFlags::GENERIC, "del", &key);
+        }
+        removed
+    }
+
+    /// Deletes the key as `del` does, without a keyspace notification
+    fn remove_key(&mut self, key: &str) -> bool {
         let key = key.to_lowercase();
         let result = if let Some(layer) = self.transaction_stack.last_mut() {
             layer.strings.insert(key.to_string(), None);
             layer.lists.insert(key.to_string(), None);
+            layer.sets.insert(key.to_string(), None);
+            layer.zsets.insert(key.to_string(), None);
+            layer.hashes.insert(key.to_string(), None);
+            layer.streams.insert(key.to_string(), None);
             true
         } else {
             Arc::make_mut(&mut self.strings).remove(&key).is_some() ||
-            Arc::make_mut(&mut self.lists).remove(&key).is_some()
+            Arc::make_mut(&mut self.lists).remove(&key).is_some() ||
+            Arc::make_mut(&mut self.sets).remove(&key).is_some() ||
+            Arc::make_mut(&mut self.zsets).remove(&key).is_some() ||
+            Arc::make_mut(&mut self.hashes).remove(&key).is_some() ||
+            Arc::make_mut(&mut self.streams).remove(&key).is_some()
         };
         if result {
             self.cache.remove(&key);
+            self.expires.remove(&key);
+            self.upgraded_encodings.remove(&key);
+            self.lfu_forget(&key);
+            self.mark_dirty();
         }
 
         result
     }
 
+    /// Copies the value at `source` to `destination`, along with its expiration
+    ///
+    /// The copy gets the time to live `source` has left, or none if it has
+    /// none. Either key is reaped first if its expiration has passed, so an
+    /// expired `source` is not copied and an expired `destination` doesn't
+    /// stand in the way.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The key to copy (case-insensitive)
+    /// * `destination` - Where to copy it (case-insensitive)
+    /// * `replace` - Overwrite `destination`, value and expiration, if it exists
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(true)` - If the value was copied
+    /// * `Ok(false)` - If `source` doesn't exist, or `destination` does and `replace` is off
+    /// * `Err(String)` - If `source` and `destination` are the same key
+    pub fn copy(&mut self, source: &str, destination: &str, replace: bool) -> Result<bool, String> {
+        let (source, destination) = (source.to_lowercase(), destination.to_lowercase());
+        if source == destination {
+            return Err("ERR source and destination objects are the same".to_string());
+        }
+        self.expire_if_needed(&source);
+        self.expire_if_needed(&destination);
+        if !self.exists(&source) || (!replace && self.exists(&destination)) {
+            return Ok(false);
+        }
+        let deadline = self.expires.get(&source).copied();
+        match self.key_type(&source) {
+            "string" => {
+                let value = self.get(&source).unwrap_or_default();
+                self.remove_key(&destination);
+                self.put_string(destination.clone(), value);
+            }
+            "list" => {
+                let items = self.list_values(&source);
+                self.set_list(&destination, items);
+            }
+            "set" => {
+                let members = self.set_ref(&source).cloned().unwrap_or_default();
+                self.store_set(&destination, members);
+            }
+            "zset" => {
+                let zset = self.zset_ref(&source).cloned().unwrap_or_default();
+                self.remove_key(&destination);
+                self.index_key(&destination);
+                match self.transaction_stack.last_mut() {
+                    Some(layer) => {
+                        layer.zsets.insert(destination.clone(), Some(zset));
+                    }
+                    None => {
+                        Arc::make_mut(&mut self.zsets).insert(destination.clone(), zset);
+                    }
+                }
+            }
+            "stream" => {
+                let stream = self.stream_ref(&source).cloned().unwrap_or_default();
+                self.remove_key(&destination);
+                self.index_key(&destination);
+                match self.transaction_stack.last_mut() {
+                    Some(layer) => {
+                        layer.streams.insert(destination.clone(), Some(stream));
+                    }
+                    None => {
+                        Arc::make_mut(&mut self.streams).insert(destination.clone(), stream);
+                    }
+                }
+            }
+            _ => {
+                let hash = self.hash_ref(&source).cloned().unwrap_or_default();
+                self.remove_key(&destination);
+                self.index_key(&destination);
+                match self.transaction_stack.last_mut() {
+                    Some(layer) => {
+                        layer.hashes.insert(destination.clone(), Some(hash));
+                    }
+                    None => {
+                        Arc::make_mut(&mut self.hashes).insert(destination.clone(), hash);
+                    }
+                }
+            }
+        }
+        // Writing the copy cleared whatever expiration `destination` had
+        if let Some(deadline) = deadline {
+            self.expires.insert(destination.clone(), deadline);
+        }
+        self.notify(NotifyFlags::GENERIC, "copy_to", &destination);
+        self.mark_dirty();
+        Ok(true)
+    }
+
+    /// Removes every key, along with timeouts, cached values and access counters
+    ///
+    /// Changes pending in open transactions are dropped too, so committing
+    /// them can't bring keys back.
+    pub fn flushall(&mut self) {
+        self.strings = Arc::new(HashMap::new());
+        self.lists = Arc::new(HashMap::new());
+        self.sets = Arc::new(HashMap::new());
+        self.zsets = Arc::new(HashMap::new());
+        self.hashes = Arc::new(HashMap::new());
+        self.streams = Arc::new(HashMap::new());
+        for layer in &mut self.transaction_stack {
+            layer.strings.clear();
+            layer.lists.clear();
+            layer.sets.clear();
+            layer.zsets.clear();
+            layer.hashes.clear();
+            layer.streams.clear();
+        }
+        self.cache.clear();
+        self.expires.clear();
+        self.lfu_freq.clear();
+        self.lfu_last_access_sec.clear();
+        self.upgraded_encodings.clear();
+        if let Some(index) = self.keys_by_slot.as_mut() {
+            index.clear();
+        }
+        self.mark_dirty();
+    }
+
     /// Increments the numeric value stored at the given key
     ///
     /// If the key doesn't exist, it's initialized with "0" before incrementing.
@@ -286,14 +1059,20 @@ impl MemoryStorage {
     ///
     /// # Returns
     ///
-    /// The new value after incrementing
-    pub fn incr(&mut self, key: &str) -> i64 {
+    /// * `Ok(i64)` - The new value after incrementing
+    /// * `Err(String)` - If the result would overflow; the value is left as it was
+    pub fn incr(&mut self, key: &str) -> Result<i64, String> {
         let key = key.to_lowercase();
         let value = self.get_or_insert_string(&key, "0".to_string());
-        let mut num: i64 = value.parse().unwrap_or(0);
-        num += 1;
+        let num = value
+            .parse::<i64>()
+            .unwrap_or(0)
+            .checked_add(1)
+            .ok_or_else(|| "ERR increment or decrement would overflow".to_string())?;
         *value = num.to_string();
-        num
+        self.notify(NotifyFlags::STRING, "incrby", &key);
+        self.mark_dirty();
+        Ok(num)
     }
 
     /// Decrements the numeric value stored at the given key
@@ -307,14 +1086,20 @@ impl MemoryStorage {
     ///
     /// # Returns
     ///
-    /// The new value after decrementing
-    pub fn decr(&mut self, key: &str) -> i64 {
+    /// * `Ok(i64)` - The new value after decrementing
+    /// * `Err(String)` - If the result would overflow; the value is left as it was
+    pub fn decr(&mut self, key: &str) -> Result<i64, String> {
         let key = key.to_lowercase();
         let value = self.get_or_insert_string(&key, "0".to_string());
-        let mut num: i64 = value.parse().unwrap_or(0);
-        num -= 1;
+        let num = value
+            .parse::<i64>()
+            .unwrap_or(0)
+            .checked_sub(1)
+            .ok_or_else(|| "ERR increment or decrement would overflow".to_string())?;
         *value = num.to_string();
-        num
+        self.notify(NotifyFlags::STRING, "decrby", &key);
+        self.mark_dirty();
+        Ok(num)
     }
     
     /// Pushes a value to the front of a list
@@ -333,7 +1118,11 @@ impl MemoryStorage {
         let key = key.to_lowercase();
         let list = self.get_or_insert_list(&key);
         list.push_front(value);
-        list.len()
+        let len = list.len();
+        self.maybe_upgrade_list(&key);
+        self.notify(NotifyFlags::LIST, "lpush", &key);
+        self.mark_dirty();
+        len
     }
     
     /// Pushes a value to the end of a list
@@ -352,7 +1141,11 @@ impl MemoryStorage {
         let key = key.to_lowercase();
         let list = self.get_or_insert_list(&key);
         list.push_back(value);
-        list.len()
+        let len = list.len();
+        self.maybe_upgrade_list(&key);
+        self.notify(NotifyFlags::LIST, "rpush", &key);
+        self.mark_dirty();
+        len
     }
 
     /// Removes and returns the first element from a list
@@ -367,7 +1160,12 @@ impl MemoryStorage {
     /// * `None` - If the list is empty or doesn't exist
     pub fn lpop(&mut self, key: &str) -> Option<String> {
         let key = key.to_lowercase();
-        self.get_or_insert_list(&key).pop_front()
+        let value = self.get_or_insert_list(&key).pop_front();
+        if value.is_some() {
+            self.notify(NotifyFlags::LIST, "lpop", &key);
+            self.mark_dirty();
+        }
+        value
     }
 
     /// Removes and returns the last element from a list
@@ -382,22 +1180,62 @@ impl MemoryStorage {
     /// * `None` - If the list is empty or doesn't exist
     pub fn rpop(&mut self, key: &str) -> Option<String> {
         let key = key.to_lowercase();
-        self.get_or_insert_list(&key).pop_back()
+        let value = self.get_or_insert_list(&key).pop_back();
+        if value.is_some() {
+            self.notify(NotifyFlags::LIST, "rpop", &key);
+            self.mark_dirty();
+        }
+        value
     }
 
-    /// Returns the length of a list
+    /// Pops an element from one end of a list and pushes it onto an end of another
     ///
-    /// If in a transaction, returns the length from the most recent transaction layer
-    /// that has the list. Otherwise, returns the length from main storage.
+    /// Both happen under the caller's storage lock, so the element is never
+    /// missing from both lists. The source may also be the destination, which
+    /// rotates the list.
     ///
     /// # Arguments
     ///
-    /// * `key` - The list's key (case-insensitive)
+    /// * `source` - The list to pop from (case-insensitive)
+    /// * `destination` - The list to push onto (case-insensitive)
+    /// * `from_left` - Pop the source's head rather than its tail
+    /// * `to_left` - Push onto the destination's head rather than its tail
     ///
     /// # Returns
     ///
-    /// The length of the list, or 0 if it doesn't exist
-    pub fn llen(&self, key: &str) -> usize {
+    /// * `Ok(Some(String))` - The element moved
+    /// * `Ok(None)` - If the source is empty or doesn't exist
+    /// * `Err(String)` - If either key holds something other than a list
+    pub fn lmove(&mut self, source: &str, destination: &str, from_left: bool, to_left: bool) -> Result<Option<String>, String> {
+        self.check_type(source, "list")?;
+        self.check_type(destination, "list")?;
+        if self.llen(source) == 0 {
+            return Ok(None);
+        }
+        let value = if from_left { self.lpop(source) } else { self.rpop(source) };
+        if let Some(value) = &value {
+            if to_left {
+                self.lpush(destination, value.clone());
+            } else {
+                self.rpush(destination, value.clone());
+            }
+        }
+        Ok(value)
+    }
+
+    /// Returns the length of a list
+    ///
+    /// If in a transaction, returns the length from the most recent transaction layer
+    /// that has the list. Otherwise, returns the length from main storage.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The list's key (case-insensitive)
+    ///
+    /// # Returns
+    ///
+    /// The length of the list, or 0 if it doesn't exist
+    pub fn llen(&self, key: &str) -> usize {
         let key = key.to_lowercase();
         for layer in self.transaction_stack.iter().rev() {
             if let Some(Some(list)) = layer.lists.get(&key) {
@@ -407,11 +1245,48 @@ impl MemoryStorage {
         self.lists.get(&key).map_or(0, |list| list.len())
     }
 
+    /// Returns the positions of `element` in a list, as LPOS
+    ///
+    /// The scan stops once `maxlen` elements have been compared, even if
+    /// fewer than `count` matches were found, so bounded searches of long
+    /// lists only touch the end they start from.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The list's key (case-insensitive)
+    /// * `element` - The value to look for (case-sensitive)
+    /// * `rank` - Which match to start from: 1 is the first from the head,
+    ///   -1 the first from the tail; 0 is taken as 1
+    /// * `count` - How many matches to return; 0 returns all of them
+    /// * `maxlen` - How many elements to compare; 0 compares the whole list
+    ///
+    /// # Returns
+    ///
+    /// Zero-based positions counted from the head, in the order they were
+    /// found; empty if the list doesn't exist
+    pub fn lpos(&self, key: &str, element: &str, rank: i64, count: usize, maxlen: usize) -> Vec<usize> {
+        let Some(list) = self.list_ref(&key.to_lowercase()) else {
+            return Vec::new();
+        };
+        let maxlen = if maxlen == 0 { list.len() } else { maxlen };
+        let count = if count == 0 { usize::MAX } else { count };
+        let skip = rank.unsigned_abs().max(1) as usize - 1;
+        let matches = |(_, item): &(usize, &String)| *item == element;
+        if rank < 0 {
+            list.iter().enumerate().rev().take(maxlen).filter(matches).skip(skip).take(count).map(|(i, _)| i).collect()
+        } else {
+            list.iter().enumerate().take(maxlen).filter(matches).skip(skip).take(count).map(|(i, _)| i).collect()
+        }
+    }
+
     /// Helper method to get or insert a string value
     ///
     /// Returns a mutable reference to the string value, creating it if necessary
     fn get_or_insert_string(&mut self, key: &str, default: String) -> &mut String {
         let key = key.to_lowercase();
+        self.index_key(&key);
+        // The caller is about to change the value, so GET must not serve the old one
+        self.cache.remove(&key);
         if let Some(layer) = self.transaction_stack.last_mut() {
             layer.strings.entry(key.to_string())
                 .or_insert_with(|| self.strings.get(&key).cloned())
@@ -428,6 +1303,9 @@ impl MemoryStorage {
    /// Returns a mutable reference to the list, creating it if necessary
     fn get_or_insert_list(&mut self, key: &str) -> &mut VecDeque<String> {
         let key = key.to_lowercase();
+        self.index_key(&key);
+        let exists = self.list_ref(&key).is_some_and(|existing| !existing.is_empty());
+        self.reset_encoding(&key, exists);
         if let Some(layer) = self.transaction_stack.last_mut() {
             layer.lists.entry(key.to_string())
                 .or_insert_with(|| self.lists.get(&key).cloned())
@@ -435,7 +1313,1766 @@ impl MemoryStorage {
         } else {
             Arc::make_mut(&mut self.lists)
                 .entry(key.to_string())
-                .or_insert_with(VecDeque::new)
+                .or_default()
+        }
+    }
+
+    /// Returns the list at the key, looking through transaction layers
+    fn list_ref(&self, key: &str) -> Option<&VecDeque<String>> {
+        for layer in self.transaction_stack.iter().rev() {
+            if let Some(list) = layer.lists.get(key) {
+                return list.as_ref();
+            }
+        }
+        self.lists.get(key)
+    }
+
+    /// Returns the members of the set at the key, looking through transaction layers
+    fn set_ref(&self, key: &str) -> Option<&HashSet<String>> {
+        for layer in self.transaction_stack.iter().rev() {
+            if let Some(set) = layer.sets.get(key) {
+                return set.as_ref();
+            }
+        }
+        self.sets.get(key)
+    }
+
+   /// Helper method to get or insert a set
+   ///
+   /// Returns a mutable reference to the set, creating it if necessary
+    fn get_or_insert_set(&mut self, key: &str) -> &mut HashSet<String> {
+        let key = key.to_lowercase();
+        self.index_key(&key);
+        let exists = self.set_ref(&key).is_some_and(|existing| !existing.is_empty());
+        self.reset_encoding(&key, exists);
+        if let Some(layer) = self.transaction_stack.last_mut() {
+            layer.sets.entry(key.to_string())
+                .or_insert_with(|| self.sets.get(&key).cloned())
+                .get_or_insert_with(HashSet::new)
+        } else {
+            Arc::make_mut(&mut self.sets)
+                .entry(key.to_string())
+                .or_default()
+        }
+    }
+
+    /// Removes the set at the key, as Redis never keeps empty sets
+    fn remove_set(&mut self, key: &str) {
+        if let Some(layer) = self.transaction_stack.last_mut() {
+            layer.sets.insert(key.to_string(), None);
+        } else {
+            Arc::make_mut(&mut self.sets).remove(key);
+        }
+    }
+
+    /// Fails with WRONGTYPE if the key exists and holds something other than `expected`
+    fn check_type(&mut self, key: &str, expected: &str) -> Result<(), String> {
+        match self.key_type(key) {
+            "none" => Ok(()),
+            found if found == expected => Ok(()),
+            _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+        }
+    }
+
+    /// Adds members to a set
+    ///
+    /// Creates the set if it doesn't exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The set's key (case-insensitive)
+    /// * `members` - The members to add (case-sensitive)
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - How many members were not already in the set
+    /// * `Err(String)` - If the key holds a string or a list
+    pub fn sadd(&mut self, key: &str, members: &[String]) -> Result<usize, String> {
+        let key = key.to_lowercase();
+        self.check_type(&key, "set")?;
+        let set = self.get_or_insert_set(&key);
+        let added = members.iter().filter(|member| set.insert(member.to_string())).count();
+        self.maybe_upgrade_set(&key);
+        if added > 0 {
+            self.notify(NotifyFlags::SET, "sadd", &key);
+            self.mark_dirty();
+        }
+        Ok(added)
+    }
+
+    /// Returns every member of a set, sorted
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The set's key (case-insensitive)
+    ///
+    /// # Returns
+    ///
+    /// The set's members, or an empty vector if it doesn't exist
+    pub fn smembers(&self, key: &str) -> Vec<String> {
+        let key = key.to_lowercase();
+        let mut members: Vec<String> = self.set_ref(&key).map_or_else(Vec::new, |set| set.iter().cloned().collect());
+        members.sort();
+        members
+    }
+
+    /// Returns the number of members in a set, or 0 if it doesn't exist
+    pub fn scard(&self, key: &str) -> usize {
+        self.set_ref(&key.to_lowercase()).map_or(0, |set| set.len())
+    }
+
+    /// Returns the next batch of a set's members, as SSCAN; see `hscan`
+    ///
+    /// Sets have a single representation here, so there's no intset index
+    /// cursor to fall back on: the hash-ordered cursor of `scan::scan`
+    /// stays valid when a set of integers grows into what Redis would
+    /// convert to a hashtable mid-scan. Members added after the scan began
+    /// may be returned, or not, depending on where their hash falls.
+    pub fn sscan(&self, key: &str, cursor: u64, pattern: Option<&str>, count: usize) -> (u64, Vec<String>) {
+        let Some(set) = self.set_ref(&key.to_lowercase()) else {
+            return (0, Vec::new());
+        };
+        let (next_cursor, members) = scan::scan(set.iter().map(|member| (member.as_str(), member)), cursor, pattern, count);
+        (next_cursor, members.into_iter().cloned().collect())
+    }
+
+    /// Moves a member from one set to another
+    ///
+    /// Both steps happen under the caller's storage lock, so no other command
+    /// can see the member in both sets or in neither. The source is deleted
+    /// once it is empty and the destination is created if needed. Inside a
+    /// transaction both changes land in the current layer.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The set to take the member from (case-insensitive)
+    /// * `destination` - The set to add the member to (case-insensitive)
+    /// * `member` - The member to move (case-sensitive)
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(true)` - If the member was in `source` and has been moved
+    /// * `Ok(false)` - If `source` doesn't contain the member
+    /// * `Err(String)` - If either key holds a string or a list
+    pub fn smove(&mut self, source: &str, destination: &str, member: &str) -> Result<bool, String> {
+        let source = source.to_lowercase();
+        let destination = destination.to_lowercase();
+        self.check_type(&source, "set")?;
+        self.check_type(&destination, "set")?;
+        if !self.set_ref(&source).is_some_and(|set| set.contains(member)) {
+            return Ok(false);
+        }
+        if source == destination {
+            return Ok(true);
+        }
+
+        let set = self.get_or_insert_set(&source);
+        set.remove(member);
+        if set.is_empty() {
+            self.remove_set(&source);
+        }
+        self.get_or_insert_set(&destination).insert(member.to_string());
+        self.maybe_upgrade_set(&destination);
+        self.notify(NotifyFlags::SET, "srem", &source);
+        self.notify(NotifyFlags::SET, "sadd", &destination);
+        self.mark_dirty();
+        Ok(true)
+    }
+
+    /// Reads the sets at `keys`, treating missing keys as empty sets
+    fn source_sets(&mut self, keys: &[String]) -> Result<Vec<HashSet<String>>, String> {
+        let mut sets = Vec::with_capacity(keys.len());
+        for key in keys {
+            let key = key.to_lowercase();
+            self.check_type(&key, "set")?;
+            sets.push(self.set_ref(&key).cloned().unwrap_or_default());
+        }
+        Ok(sets)
+    }
+
+    /// Replaces whatever is stored at the key with the set `members`
+    ///
+    /// An empty `members` deletes the key, as Redis never keeps empty sets.
+    ///
+    /// # Returns
+    ///
+    /// The number of members stored
+    fn store_set(&mut self, key: &str, members: HashSet<String>) -> usize {
+        let key = key.to_lowercase();
+        self.remove_key(&key);
+        let count = members.len();
+        if count == 0 {
+            return 0;
+        }
+        self.index_key(&key);
+        if let Some(layer) = self.transaction_stack.last_mut() {
+            layer.sets.insert(key.clone(), Some(members));
+        } else {
+            Arc::make_mut(&mut self.sets).insert(key.clone(), members);
+        }
+        self.maybe_upgrade_set(&key);
+        self.mark_dirty();
+        count
+    }
+
+    /// Stores the members of the first set that are in none of the others
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Where to store the result, overwriting it (case-insensitive)
+    /// * `keys` - The source sets; missing keys count as empty sets
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - The size of the stored set
+    /// * `Err(String)` - If a source holds something other than a set
+    pub fn sdiffstore(&mut self, destination: &str, keys: &[String]) -> Result<usize, String> {
+        let mut sets = self.source_sets(keys)?.into_iter();
+        let mut result = sets.next().unwrap_or_default();
+        for set in sets {
+            result.retain(|member| !set.contains(member));
+        }
+        let stored = self.store_set(destination, result);
+        self.notify_stored(NotifyFlags::SET, "sdiffstore", destination, stored);
+        Ok(stored)
+    }
+
+    /// Stores the members found in any of the sets
+    ///
+    /// Arguments and return value are as for `sdiffstore`.
+    pub fn sunionstore(&mut self, destination: &str, keys: &[String]) -> Result<usize, String> {
+        let result = self.source_sets(keys)?.into_iter().flatten().collect();
+        let stored = self.store_set(destination, result);
+        self.notify_stored(NotifyFlags::SET, "sunionstore", destination, stored);
+        Ok(stored)
+    }
+
+    /// Stores the members found in every one of the sets
+    ///
+    /// Arguments and return value are as for `sdiffstore`.
+    pub fn sinterstore(&mut self, destination: &str, keys: &[String]) -> Result<usize, String> {
+        let mut sets = self.source_sets(keys)?.into_iter();
+        let mut result = sets.next().unwrap_or_default();
+        for set in sets {
+            result.retain(|member| set.contains(member));
+        }
+        let stored = self.store_set(destination, result);
+        self.notify_stored(NotifyFlags::SET, "sinterstore", destination, stored);
+        Ok(stored)
+    }
+
+    /// Counts the members found in every one of the sets, without building the intersection
+    ///
+    /// Walks the smallest set and probes the others, stopping early once
+    /// `limit` matches are found. Any missing or empty set makes the answer 0.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The sets to intersect (case-insensitive)
+    /// * `limit` - Stop counting at this many; 0 counts every match
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - The size of the intersection, capped at `limit`
+    /// * `Err(String)` - If a key holds something other than a set
+    pub fn sintercard(&mut self, keys: &[String], limit: usize) -> Result<usize, String> {
+        let keys: Vec<String> = keys.iter().map(|key| key.to_lowercase()).collect();
+        for key in &keys {
+            self.check_type(key, "set")?;
+        }
+        let mut sets = Vec::with_capacity(keys.len());
+        for key in &keys {
+            match self.set_ref(key) {
+                Some(set) if !set.is_empty() => sets.push(set),
+                _ => return Ok(0),
+            }
+        }
+        sets.sort_by_key(|set| set.len());
+        let Some((smallest, others)) = sets.split_first() else {
+            return Ok(0);
+        };
+
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let count = smallest
+            .iter()
+            .filter(|member| others.iter().all(|set| set.contains(*member)))
+            .take(limit)
+            .count();
+        Ok(count)
+    }
+
+    /// Returns the hash at the key, looking through transaction layers
+    fn hash_ref(&self, key: &str) -> Option<&HashMap<String, String>> {
+        for layer in self.transaction_stack.iter().rev() {
+            if let Some(hash) = layer.hashes.get(key) {
+                return hash.as_ref();
+            }
+        }
+        self.hashes.get(key)
+    }
+
+   /// Helper method to get or insert a hash
+   ///
+   /// Returns a mutable reference to the hash, creating it if necessary
+    fn get_or_insert_hash(&mut self, key: &str) -> &mut HashMap<String, String> {
+        let key = key.to_lowercase();
+        self.index_key(&key);
+        let exists = self.hash_ref(&key).is_some_and(|existing| !existing.is_empty());
+        self.reset_encoding(&key, exists);
+        if let Some(layer) = self.transaction_stack.last_mut() {
+            layer.hashes.entry(key.to_string())
+                .or_insert_with(|| self.hashes.get(&key).cloned())
+                .get_or_insert_with(HashMap::new)
+        } else {
+            Arc::make_mut(&mut self.hashes)
+                .entry(key.to_string())
+                .or_default()
+        }
+    }
+
+    /// Sets fields of a hash, creating the hash if it doesn't exist
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The hash's key (case-insensitive)
+    /// * `pairs` - `(field, value)` pairs, applied in order; fields are case-sensitive
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - How many fields were not already in the hash
+    /// * `Err(String)` - If the key holds something other than a hash
+    pub fn hset(&mut self, key: &str, pairs: &[(String, String)]) -> Result<usize, String> {
+        let key = key.to_lowercase();
+        self.check_type(&key, "hash")?;
+        let hash = self.get_or_insert_hash(&key);
+        let added = pairs
+            .iter()
+            .filter(|(field, value)| hash.insert(field.clone(), value.clone()).is_none())
+            .count();
+        self.maybe_upgrade_hash(&key);
+        self.notify(NotifyFlags::HASH, "hset", &key);
+        self.mark_dirty();
+        Ok(added)
+    }
+
+    /// Sets a field of a hash only if the field doesn't exist yet
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(true)` - If the field was set
+    /// * `Ok(false)` - If the field already existed and was left alone
+    /// * `Err(String)` - If the key holds something other than a hash
+    pub fn hsetnx(&mut self, key: &str, field: &str, value: &str) -> Result<bool, String> {
+        let key = key.to_lowercase();
+        self.check_type(&key, "hash")?;
+        if self.hash_ref(&key).is_some_and(|hash| hash.contains_key(field)) {
+            return Ok(false);
+        }
+        self.get_or_insert_hash(&key).insert(field.to_string(), value.to_string());
+        self.maybe_upgrade_hash(&key);
+        self.notify(NotifyFlags::HASH, "hset", &key);
+        self.mark_dirty();
+        Ok(true)
+    }
+
+    /// Adds `delta` to the integer stored in a hash field
+    ///
+    /// A missing hash or field starts from 0.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(i64)` - The field's new value
+    /// * `Err(String)` - If the key holds something other than a hash, the
+    ///   field doesn't hold an integer, or the result would overflow
+    pub fn hincrby(&mut self, key: &str, field: &str, delta: i64) -> Result<i64, String> {
+        let key = key.to_lowercase();
+        self.check_type(&key, "hash")?;
+        let current = match self.hash_ref(&key).and_then(|hash| hash.get(field)) {
+            Some(value) => value.parse::<i64>().map_err(|_| "ERR hash value is not an integer".to_string())?,
+            None => 0,
+        };
+        let updated = current
+            .checked_add(delta)
+            .ok_or_else(|| "ERR increment or decrement would overflow".to_string())?;
+        self.get_or_insert_hash(&key).insert(field.to_string(), updated.to_string());
+        self.maybe_upgrade_hash(&key);
+        self.notify(NotifyFlags::HASH, "hincrby", &key);
+        self.mark_dirty();
+        Ok(updated)
+    }
+
+    /// Adds `delta` to the number stored in a hash field
+    ///
+    /// A missing hash or field starts from 0. The result is stored in its
+    /// shortest form, so `10.50` plus `0` is stored as `10.5`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(f64)` - The field's new value
+    /// * `Err(String)` - If the key holds something other than a hash, the
+    ///   field doesn't hold a number, or the result would be NaN or infinite
+    pub fn hincrbyfloat(&mut self, key: &str, field: &str, delta: f64) -> Result<f64, String> {
+        let key = key.to_lowercase();
+        self.check_type(&key, "hash")?;
+        let current = match self.hash_ref(&key).and_then(|hash| hash.get(field)) {
+            Some(value) => value
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .ok_or_else(|| "ERR hash value is not a float".to_string())?,
+            None => 0.0,
+        };
+        let updated = current + delta;
+        if !updated.is_finite() {
+            return Err("ERR increment would produce NaN or Infinity".to_string());
+        }
+        self.get_or_insert_hash(&key).insert(field.to_string(), updated.to_string());
+        self.maybe_upgrade_hash(&key);
+        self.notify(NotifyFlags::HASH, "hincrbyfloat", &key);
+        self.mark_dirty();
+        Ok(updated)
+    }
+
+    /// Returns the value of a hash field, or `None` if the hash or the field doesn't exist
+    pub fn hget(&self, key: &str, field: &str) -> Option<String> {
+        self.hash_ref(&key.to_lowercase()).and_then(|hash| hash.get(field)).cloned()
+    }
+
+    /// Returns the number of fields in a hash, or 0 if it doesn't exist
+    pub fn hlen(&self, key: &str) -> usize {
+        self.hash_ref(&key.to_lowercase()).map_or(0, HashMap::len)
+    }
+
+    /// Returns the next batch of a hash's fields and their values, as HSCAN
+    ///
+    /// See `scan::scan` for how the cursor works. A missing key, or one
+    /// holding another type, is empty.
+    ///
+    /// # Returns
+    ///
+    /// The cursor to continue from (0 once done) and the field/value pairs
+    pub fn hscan(&self, key: &str, cursor: u64, pattern: Option<&str>, count: usize) -> (u64, Vec<(String, String)>) {
+        let Some(hash) = self.hash_ref(&key.to_lowercase()) else {
+            return (0, Vec::new());
+        };
+        let (next_cursor, pairs) = scan::scan(hash.iter().map(|(field, value)| (field.as_str(), (field, value))), cursor, pattern, count);
+        (next_cursor, pairs.into_iter().map(|(field, value)| (field.clone(), value.clone())).collect())
+    }
+
+    /// Returns the stream at the key, looking through transaction layers
+    fn stream_ref(&self, key: &str) -> Option<&Stream> {
+        for layer in self.transaction_stack.iter().rev() {
+            if let Some(stream) = layer.streams.get(key) {
+                return stream.as_ref();
+            }
+        }
+        self.streams.get(key)
+    }
+
+   /// Helper method to get or insert a stream
+   ///
+   /// Returns a mutable reference to the stream, creating it if necessary
+    fn get_or_insert_stream(&mut self, key: &str) -> &mut Stream {
+        let key = key.to_lowercase();
+        self.index_key(&key);
+        if let Some(layer) = self.transaction_stack.last_mut() {
+            layer.streams.entry(key.to_string())
+                .or_insert_with(|| self.streams.get(&key).cloned())
+                .get_or_insert_with(Stream::new)
+        } else {
+            Arc::make_mut(&mut self.streams)
+                .entry(key.to_string())
+                .or_default()
+        }
+    }
+
+    /// Appends an entry to a stream, creating the stream if it doesn't exist
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The stream's key (case-insensitive)
+    /// * `id` - The ID to give the entry, or how to generate it
+    /// * `fields` - The entry's field/value pairs, in order; fields are case-sensitive
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(StreamEntryId)` - The new entry's ID
+    /// * `Err(String)` - If the key holds something other than a stream, or
+    ///   the ID isn't greater than the stream's last one
+    pub fn xadd(&mut self, key: &str, id: XAddId, fields: &[(String, String)]) -> Result<StreamEntryId, String> {
+        let key = key.to_lowercase();
+        self.check_type(&key, "stream")?;
+        let now_ms = Self::unix_millis();
+        if self.stream_ref(&key).is_none() {
+            // Try the ID on a scratch stream first, so a rejected one creates nothing
+            Stream::new().add(id, Vec::new(), now_ms)?;
+        }
+        let added = self.get_or_insert_stream(&key).add(id, fields.to_vec(), now_ms)?;
+        self.notify(NotifyFlags::STREAM, "xadd", &key);
+        self.mark_dirty();
+        Ok(added)
+    }
+
+    /// Returns how many entries the stream at the key holds, 0 if it doesn't exist
+    pub fn xlen(&self, key: &str) -> usize {
+        self.stream_ref(&key.to_lowercase()).map_or(0, Stream::len)
+    }
+
+    /// Returns up to `count` entries of a stream from `start` to `end`, both included
+    ///
+    /// # Returns
+    ///
+    /// The entries oldest first, or an empty vector if the key doesn't hold a stream
+    pub fn xrange(&self, key: &str, start: StreamEntryId, end: StreamEntryId, count: Option<usize>) -> Vec<StreamEntry> {
+        self.stream_ref(&key.to_lowercase()).map_or_else(Vec::new, |stream| stream.range(start, end, count))
+    }
+
+    /// Returns up to `count` entries of a stream with IDs greater than `after`
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<StreamEntry>)` - The entries oldest first; empty if there are none yet
+    /// * `Err(String)` - If the key holds another type
+    pub fn xread(&mut self, key: &str, after: StreamEntryId, count: Option<usize>) -> Result<Vec<StreamEntry>, String> {
+        self.check_type(key, "stream")?;
+        Ok(after.next().map_or_else(Vec::new, |start| self.xrange(key, start, StreamEntryId::MAX, count)))
+    }
+
+    /// Deletes entries from a stream by ID
+    ///
+    /// Entries still pending in a consumer group stay in its PEL until they
+    /// are claimed. The stream remains, even once it has no entries left.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The stream's key (case-insensitive)
+    /// * `ids` - The entry IDs; ones not in the stream are ignored
+    ///
+    /// # Returns
+    ///
+    /// How many entries were deleted; 0 if the key doesn't hold a stream
+    pub fn xdel(&mut self, key: &str, ids: &[String]) -> u64 {
+        let key = key.to_lowercase();
+        if self.stream_ref(&key).is_none() {
+            return 0;
+        }
+        let ids: Vec<StreamEntryId> = ids.iter().filter_map(|id| StreamEntryId::parse(id, 0)).collect();
+        let deleted = self.get_or_insert_stream(&key).delete(&ids);
+        if deleted > 0 {
+            self.notify(NotifyFlags::STREAM, "xdel", &key);
+            self.mark_dirty();
         }
+        deleted
     }
-}
\ No newline at end of file
+
+    /// Returns the last ID a stream generated, which XREAD's `$` stands for
+    ///
+    /// A key that doesn't hold a stream counts as `0-0`, so any entry
+    /// added to it later is new.
+    pub fn xlast_id(&self, key: &str) -> StreamEntryId {
+        self.stream_ref(&key.to_lowercase()).map_or(StreamEntryId::MIN, |stream| stream.last_id)
+    }
+
+    /// Creates a consumer group on a stream
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The stream's key (case-insensitive)
+    /// * `group` - The group's name (case-sensitive)
+    /// * `id` - The last ID the group counts as delivered; `None` (`$`) for the stream's last ID
+    /// * `mkstream` - Create an empty stream if the key doesn't exist
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the group was created
+    /// * `Err(String)` - If the stream doesn't exist (without `mkstream`),
+    ///   the key holds something else or the group already exists
+    pub fn xgroup_create(&mut self, key: &str, group: &str, id: Option<StreamEntryId>, mkstream: bool) -> Result<(), String> {
+        let key = key.to_lowercase();
+        self.check_type(&key, "stream")?;
+        match self.stream_ref(&key) {
+            None if !mkstream => {
+                return Err("ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want \
+                    to use the MKSTREAM option to create an empty stream automatically."
+                    .to_string())
+            }
+            Some(stream) if stream.groups.contains_key(group) => {
+                return Err("BUSYGROUP Consumer Group name already exists".to_string())
+            }
+            _ => {}
+        }
+        self.get_or_insert_stream(&key).create_group(group, id)?;
+        self.notify(NotifyFlags::STREAM, "xgroup-create", &key);
+        self.mark_dirty();
+        Ok(())
+    }
+
+    /// Reads a stream as a consumer of one of its groups, as XREADGROUP does
+    ///
+    /// See `Stream::read_group` for which entries are delivered and how the
+    /// PEL changes.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The stream's key (case-insensitive)
+    /// * `group` - The group to read as (case-sensitive)
+    /// * `consumer` - The consumer to deliver to, created if new (case-sensitive)
+    /// * `id` - `None` (`>`) for new entries, or an ID to reread the consumer's pending entries after
+    /// * `count` - Most entries to return; `None` for no limit
+    /// * `noack` - Deliver new entries without adding them to the PEL
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<StreamEntry>)` - The entries delivered, oldest first
+    /// * `Err(String)` - If the key holds something else, or has no such stream or group
+    pub fn xreadgroup(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        id: Option<StreamEntryId>,
+        count: Option<usize>,
+        noack: bool,
+    ) -> Result<Vec<StreamEntry>, String> {
+        let key = key.to_lowercase();
+        self.check_type(&key, "stream")?;
+        if !self.stream_ref(&key).is_some_and(|stream| stream.groups.contains_key(group)) {
+            return Err(format!(
+                "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                key, group
+            ));
+        }
+        let entries = self
+            .get_or_insert_stream(&key)
+            .read_group(group, consumer, id, count, noack, Instant::now())
+            .unwrap_or_default();
+        if id.is_none() && !entries.is_empty() {
+            self.mark_dirty();
+        }
+        Ok(entries)
+    }
+
+    /// Claims a group's idle pending entries for a consumer, as XAUTOCLAIM does
+    ///
+    /// See `Stream::auto_claim` for how the PEL is scanned.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The stream's key (case-insensitive)
+    /// * `group` - The group whose PEL is scanned (case-sensitive)
+    /// * `consumer` - The consumer to hand entries over to, created if new (case-sensitive)
+    /// * `min_idle_ms` - Only entries delivered at least this long ago are claimed
+    /// * `start` - The ID the scan starts at
+    /// * `count` - Most entries to claim
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((String, Vec<StreamEntry>, Vec<String>))` - The cursor to pass as the next `start`
+    ///   (`0-0` when the scan is complete), the entries claimed, and the IDs of pending
+    ///   entries that were deleted from the stream and so dropped from the PEL
+    /// * `Err(String)` - If the key holds something else, or has no such stream or group
+    pub fn xautoclaim(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle_ms: u64,
+        start: StreamEntryId,
+        count: usize,
+    ) -> Result<(String, Vec<StreamEntry>, Vec<String>), String> {
+        let key = key.to_lowercase();
+        self.check_type(&key, "stream")?;
+        if !self.stream_ref(&key).is_some_and(|stream| stream.groups.contains_key(group)) {
+            return Err(format!("NOGROUP No such key '{}' or consumer group '{}'", key, group));
+        }
+        let (cursor, claimed, deleted) = self
+            .get_or_insert_stream(&key)
+            .auto_claim(group, consumer, Duration::from_millis(min_idle_ms), start, count, Instant::now())
+            .unwrap_or_default();
+        if !claimed.is_empty() {
+            self.notify(NotifyFlags::STREAM, "xautoclaim", &key);
+        }
+        self.mark_dirty();
+        Ok((cursor.to_string(), claimed, deleted.iter().map(StreamEntryId::to_string).collect()))
+    }
+
+    /// Returns the stream at the key, for XINFO to report on
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(&Stream)` - The stream
+    /// * `Err(String)` - If the key doesn't exist or holds something else
+    pub fn xinfo(&mut self, key: &str) -> Result<&Stream, String> {
+        let key = key.to_lowercase();
+        self.check_type(&key, "stream")?;
+        self.stream_ref(&key).ok_or_else(|| "ERR no such key".to_string())
+    }
+
+    /// Returns the sorted set at the key, looking through transaction layers
+    fn zset_ref(&self, key: &str) -> Option<&SortedSet> {
+        for layer in self.transaction_stack.iter().rev() {
+            if let Some(zset) = layer.zsets.get(key) {
+                return zset.as_ref();
+            }
+        }
+        self.zsets.get(key)
+    }
+
+   /// Helper method to get or insert a sorted set
+   ///
+   /// Returns a mutable reference to the sorted set, creating it if necessary
+    fn get_or_insert_zset(&mut self, key: &str) -> &mut SortedSet {
+        let key = key.to_lowercase();
+        self.index_key(&key);
+        let exists = self.zset_ref(&key).is_some_and(|existing| !existing.is_empty());
+        self.reset_encoding(&key, exists);
+        if let Some(layer) = self.transaction_stack.last_mut() {
+            layer.zsets.entry(key.to_string())
+                .or_insert_with(|| self.zsets.get(&key).cloned())
+                .get_or_insert_with(SortedSet::new)
+        } else {
+            Arc::make_mut(&mut self.zsets)
+                .entry(key.to_string())
+                .or_default()
+        }
+    }
+
+    /// Removes the sorted set at the key if it has no members left
+    fn remove_zset_if_empty(&mut self, key: &str) {
+        if !self.zset_ref(key).is_some_and(SortedSet::is_empty) {
+            return;
+        }
+        if let Some(layer) = self.transaction_stack.last_mut() {
+            layer.zsets.insert(key.to_string(), None);
+        } else {
+            Arc::make_mut(&mut self.zsets).remove(key);
+        }
+    }
+
+    /// Adds members to a sorted set or updates their scores
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The sorted set's key (case-insensitive)
+    /// * `pairs` - `(score, member)` pairs, applied in order
+    /// * `options` - The NX/XX/GT/LT/CH conditions
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - How many members were added, or added and changed with CH
+    /// * `Err(String)` - If the key holds another type
+    pub fn zadd(&mut self, key: &str, pairs: &[(f64, String)], options: &ZAddOptions) -> Result<usize, String> {
+        let key = key.to_lowercase();
+        self.check_type(&key, "zset")?;
+        let count = self.get_or_insert_zset(&key).add(pairs, options);
+        self.remove_zset_if_empty(&key);
+        self.maybe_upgrade_zset(&key);
+        if count > 0 {
+            self.notify(NotifyFlags::ZSET, "zadd", &key);
+        }
+        self.mark_dirty();
+        Ok(count)
+    }
+
+    /// Adds locations to a geo index, a sorted set scored by geohash
+    ///
+    /// Every pair is checked before anything is stored, so one bad pair
+    /// leaves the key untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The sorted set's key (case-insensitive)
+    /// * `items` - `(longitude, latitude, member)` triples, applied in order
+    /// * `options` - The NX/XX/CH conditions
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - How many members were added, or added and moved with CH
+    /// * `Err(String)` - If a pair is out of range or the key holds another type
+    pub fn geoadd(&mut self, key: &str, items: &[(f64, f64, String)], options: &GeoAddOptions) -> Result<usize, String> {
+        if let Some((longitude, latitude, _)) =
+            items.iter().find(|(longitude, latitude, _)| !geo::valid_coordinates(*longitude, *latitude))
+        {
+            return Err(format!("ERR invalid longitude,latitude pair {:.6},{:.6}", longitude, latitude));
+        }
+        let pairs: Vec<(f64, String)> = items
+            .iter()
+            .map(|(longitude, latitude, member)| (geo::encode(*longitude, *latitude), member.clone()))
+            .collect();
+        self.zadd(key, &pairs, &options.zadd_options())
+    }
+
+    /// Finds the members of a geo index inside an area
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<GeoMatch>)` - The matches, none if the key doesn't exist
+    /// * `Err(String)` - If the key holds another type, or the search is
+    ///   centred on a member the index doesn't have
+    pub fn geosearch(&mut self, key: &str, params: &GeoSearchParams) -> Result<Vec<GeoMatch>, String> {
+        let key = key.to_lowercase();
+        self.check_type(&key, "zset")?;
+        match self.zset_ref(&key) {
+            Some(zset) => geo::search(zset, params),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Stores what a GEOSEARCH finds as a sorted set, overwriting the destination
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The geo index to search (case-insensitive)
+    /// * `destination` - Where to store the matches (case-insensitive); it's
+    ///   deleted if there are none
+    /// * `params` - The search
+    /// * `mode` - Whether to score the matches by geohash or by distance
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - How many members were stored
+    /// * `Err(String)` - As for `geosearch`; the destination is left alone
+    pub fn geosearchstore(
+        &mut self,
+        source: &str,
+        destination: &str,
+        params: &GeoSearchParams,
+        mode: GeoStoreMode,
+    ) -> Result<usize, String> {
+        let mut result = SortedSet::new();
+        for found in self.geosearch(source, params)? {
+            let score = match mode {
+                GeoStoreMode::Geohash => found.hash,
+                GeoStoreMode::Distance => found.distance,
+            };
+            result.insert(&found.member, score);
+        }
+        let stored = self.store_zset(destination, result);
+        self.notify_stored(NotifyFlags::ZSET, "geosearchstore", destination, stored);
+        Ok(stored)
+    }
+
+    /// Replaces whatever is at the key with a sorted set, deleting the key
+    /// if the set is empty; returns the set's size
+    fn store_zset(&mut self, key: &str, zset: SortedSet) -> usize {
+        let key = key.to_lowercase();
+        self.remove_key(&key);
+        let count = zset.len();
+        if count == 0 {
+            return 0;
+        }
+        self.index_key(&key);
+        if let Some(layer) = self.transaction_stack.last_mut() {
+            layer.zsets.insert(key.clone(), Some(zset));
+        } else {
+            Arc::make_mut(&mut self.zsets).insert(key.clone(), zset);
+        }
+        self.maybe_upgrade_zset(&key);
+        self.mark_dirty();
+        count
+    }
+
+    /// Adds `increment` to a member's score, as ZADD INCR does
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(f64))` - The member's new score
+    /// * `Ok(None)` - If `options` ruled the update out
+    /// * `Err(String)` - If the key holds another type or the score would be NaN
+    pub fn zadd_incr(&mut self, key: &str, increment: f64, member: &str, options: &ZAddOptions) -> Result<Option<f64>, String> {
+        let key = key.to_lowercase();
+        self.check_type(&key, "zset")?;
+        let score = self.get_or_insert_zset(&key).increment(member, increment, options);
+        self.remove_zset_if_empty(&key);
+        self.maybe_upgrade_zset(&key);
+        if matches!(score, Ok(Some(_))) {
+            self.notify(NotifyFlags::ZSET, "zincr", &key);
+            self.mark_dirty();
+        }
+        score
+    }
+
+    /// Returns the score of a member of the sorted set at the key
+    pub fn zscore(&self, key: &str, member: &str) -> Option<f64> {
+        self.zset_ref(&key.to_lowercase())?.score(member)
+    }
+
+    /// Returns the number of members in a sorted set, or 0 if it doesn't exist
+    pub fn zcard(&self, key: &str) -> usize {
+        self.zset_ref(&key.to_lowercase()).map_or(0, SortedSet::len)
+    }
+
+    /// Returns the next batch of a sorted set's members and their scores, as ZSCAN; see `hscan`
+    pub fn zscan(&self, key: &str, cursor: u64, pattern: Option<&str>, count: usize) -> (u64, Vec<(String, f64)>) {
+        let Some(zset) = self.zset_ref(&key.to_lowercase()) else {
+            return (0, Vec::new());
+        };
+        let (next_cursor, members) = scan::scan(zset.iter().map(|(member, score)| (member, (member, score))), cursor, pattern, count);
+        (next_cursor, members.into_iter().map(|(member, score)| (member.to_string(), score)).collect())
+    }
+
+   /// Helper method to look up several sorted sets at once
+   ///
+   /// Fails if any key holds another type; a missing key gives `None`
+    fn source_zsets(&mut self, keys: &[String]) -> Result<Vec<Option<&SortedSet>>, String> {
+        let keys: Vec<String> = keys.iter().map(|key| key.to_lowercase()).collect();
+        for key in &keys {
+            self.check_type(key, "zset")?;
+        }
+        Ok(keys.iter().map(|key| self.zset_ref(key)).collect())
+    }
+
+    /// Returns the members of the first sorted set that are in none of the others
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<(String, f64)>)` - The members and their scores, lowest first
+    /// * `Err(String)` - If a key holds another type
+    pub fn zdiff(&mut self, keys: &[String]) -> Result<Vec<(String, f64)>, String> {
+        Ok(Self::scored(&SortedSet::diff(&self.source_zsets(keys)?)))
+    }
+
+    /// Returns the members of any of the sorted sets, scores weighted and then combined by `agg`
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The sorted sets (case-insensitive)
+    /// * `weights` - One multiplier per key; empty weighs every key 1
+    /// * `agg` - How to combine the scores of a member found in several sets
+    pub fn zunion(&mut self, keys: &[String], weights: &[f64], agg: AggType) -> Result<Vec<(String, f64)>, String> {
+        Ok(Self::scored(&SortedSet::union(&self.source_zsets(keys)?, weights, agg)))
+    }
+
+    /// Returns the members found in all of the sorted sets, scores combined as in `zunion`
+    pub fn zinter(&mut self, keys: &[String], weights: &[f64], agg: AggType) -> Result<Vec<(String, f64)>, String> {
+        Ok(Self::scored(&SortedSet::inter(&self.source_zsets(keys)?, weights, agg)))
+    }
+
+    fn scored(zset: &SortedSet) -> Vec<(String, f64)> {
+        zset.iter().map(|(member, score)| (member.to_string(), score)).collect()
+    }
+
+    /// Removes and returns up to `count` members with the lowest scores
+    ///
+    /// # Returns
+    ///
+    /// `(member, score)` pairs, lowest first; empty if the key doesn't hold a sorted set
+    pub fn zpopmin(&mut self, key: &str, count: usize) -> Vec<(String, f64)> {
+        self.zpop(key, count, SortedSet::pop_min, "zpopmin")
+    }
+
+    /// Removes and returns up to `count` members with the highest scores, highest first
+    pub fn zpopmax(&mut self, key: &str, count: usize) -> Vec<(String, f64)> {
+        self.zpop(key, count, SortedSet::pop_max, "zpopmax")
+    }
+
+    fn zpop(
+        &mut self,
+        key: &str,
+        count: usize,
+        pop: fn(&mut SortedSet) -> Option<(String, f64)>,
+        event: &str,
+    ) -> Vec<(String, f64)> {
+        let key = key.to_lowercase();
+        if self.zcard(&key) == 0 || count == 0 {
+            return Vec::new();
+        }
+        let zset = self.get_or_insert_zset(&key);
+        let popped = std::iter::from_fn(|| pop(zset)).take(count).collect();
+        self.remove_zset_if_empty(&key);
+        self.notify(NotifyFlags::ZSET, event, &key);
+        self.mark_dirty();
+        popped
+    }
+
+    /// Returns the members of a sorted set scored between `min` and `max`, lowest first
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The sorted set's key (case-insensitive)
+    /// * `min`, `max` - The score range
+    /// * `offset` - How many matching members to skip; negative returns nothing
+    /// * `count` - The most members to return; negative returns all the rest
+    ///
+    /// # Returns
+    ///
+    /// `(member, score)` pairs, empty if the key doesn't exist
+    pub fn zrangebyscore(&self, key: &str, min: ScoreBound, max: ScoreBound, offset: i64, count: i64) -> Vec<(String, f64)> {
+        match self.zset_ref(&key.to_lowercase()) {
+            Some(zset) => Self::limit(zset.range_by_score(min, max), offset, count),
+            None => Vec::new(),
+        }
+    }
+
+    /// Same as `zrangebyscore`, but from the highest score down
+    pub fn zrevrangebyscore(&self, key: &str, max: ScoreBound, min: ScoreBound, offset: i64, count: i64) -> Vec<(String, f64)> {
+        match self.zset_ref(&key.to_lowercase()) {
+            Some(zset) => Self::limit(zset.range_by_score(min, max).rev(), offset, count),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the members of a sorted set between `min` and `max` in lexicographic order
+    ///
+    /// Meant for sorted sets whose members all share one score, as in Redis.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The sorted set's key (case-insensitive)
+    /// * `min`, `max` - The member range
+    /// * `offset`, `count` - A LIMIT clause, as in `zrangebyscore`
+    /// * `rev` - Walk from `max` down to `min` instead
+    pub fn zrangebylex(&self, key: &str, min: &LexBound, max: &LexBound, offset: i64, count: i64, rev: bool) -> Vec<String> {
+        let Some(zset) = self.zset_ref(&key.to_lowercase()) else {
+            return Vec::new();
+        };
+        let members = if rev {
+            Self::limit(zset.rev_range_by_lex(max, min), offset, count)
+        } else {
+            Self::limit(zset.range_by_lex(min, max), offset, count)
+        };
+        members.into_iter().map(|(member, _)| member).collect()
+    }
+
+    /// Returns how many members of a sorted set lie between `min` and `max`
+    pub fn zlexcount(&self, key: &str, min: &LexBound, max: &LexBound) -> usize {
+        self.zset_ref(&key.to_lowercase())
+            .map_or(0, |zset| zset.range_by_lex(min, max).count())
+    }
+
+    /// Applies a LIMIT `offset count` clause to sorted set members
+    fn limit<'a>(members: impl Iterator<Item = (&'a str, f64)>, offset: i64, count: i64) -> Vec<(String, f64)> {
+        let Ok(offset) = usize::try_from(offset) else {
+            return Vec::new();
+        };
+        let count = usize::try_from(count).unwrap_or(usize::MAX);
+        members
+            .skip(offset)
+            .take(count)
+            .map(|(member, score)| (member.to_string(), score))
+            .collect()
+    }
+
+    /// Returns whether the key holds a string, a non-empty list, a set or a sorted set
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to check (case-insensitive)
+    pub fn exists(&mut self, key: &str) -> bool {
+        self.key_type(key) != "none"
+    }
+
+    /// Returns the name of the type stored at the key
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to inspect (case-insensitive)
+    ///
+    /// # Returns
+    ///
+    /// `"string"`, `"list"`, `"set"`, `"zset"`, `"hash"`, `"stream"`, or
+    /// `"none"` if the key doesn't exist
+    pub fn key_type(&mut self, key: &str) -> &'static str {
+        if self.get(key).is_some() {
+            "string"
+        } else if self.llen(key) > 0 {
+            "list"
+        } else if self.scard(key) > 0 {
+            "set"
+        } else if self.zcard(key) > 0 {
+            "zset"
+        } else if self.hlen(key) > 0 {
+            "hash"
+        } else if self.stream_ref(&key.to_lowercase()).is_some() {
+            // Unlike the other types, a stream with no entries still exists
+            "stream"
+        } else {
+            "none"
+        }
+    }
+
+    /// Returns the internal encoding Redis would use for the value at the key
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to inspect (case-insensitive)
+    ///
+    /// # Returns
+    ///
+    /// `"int"`, `"embstr"` or `"raw"` for strings, the collection's encoding
+    /// for the other types, or `None` if the key doesn't exist
+    pub fn object_encoding(&mut self, key: &str) -> Option<&'static str> {
+        match self.get(key) {
+            Some(value) if value.parse::<i64>().is_ok() => Some("int"),
+            Some(value) if value.len() <= 44 => Some("embstr"),
+            Some(_) => Some("raw"),
+            None => self
+                .object_encoding_list(key)
+                .or_else(|| self.object_encoding_hash(key))
+                .or_else(|| self.object_encoding_set(key))
+                .or_else(|| self.object_encoding_zset(key))
+                .or_else(|| self.stream_ref(&key.to_lowercase()).map(|_| "stream")),
+        }
+    }
+
+    /// Returns the encoding of the list at the key
+    ///
+    /// Lists are stored as a single `VecDeque`, so the encoding is worked out
+    /// from the contents: a list that fits both listpack limits is
+    /// `"listpack"`, anything larger is `"quicklist"`, as is a list a write
+    /// already upgraded (see `maybe_upgrade_list`).
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The list's key (case-insensitive)
+    ///
+    /// # Returns
+    ///
+    /// The encoding name, or `None` if the key doesn't hold a list
+    pub fn object_encoding_list(&self, key: &str) -> Option<&'static str> {
+        let key = key.to_lowercase();
+        let list = self.list_ref(&key).filter(|list| !list.is_empty())?;
+        Some(self.with_upgrade(&key, self.encoding_limits.list(list.len(), list.iter())))
+    }
+
+    /// Returns the encoding of the hash at the key: `"listpack"` or
+    /// `"hashtable"`, worked out like `object_encoding_list`'s
+    pub fn object_encoding_hash(&self, key: &str) -> Option<&'static str> {
+        let key = key.to_lowercase();
+        let hash = self.hash_ref(&key).filter(|hash| !hash.is_empty())?;
+        Some(self.with_upgrade(&key, self.encoding_limits.hash(hash.len(), hash.iter())))
+    }
+
+    /// Returns the encoding of the set at the key: `"intset"`, `"listpack"`
+    /// or `"hashtable"`, worked out like `object_encoding_list`'s
+    pub fn object_encoding_set(&self, key: &str) -> Option<&'static str> {
+        let key = key.to_lowercase();
+        let set = self.set_ref(&key).filter(|set| !set.is_empty())?;
+        Some(self.with_upgrade(&key, self.encoding_limits.set(set.len(), set.iter())))
+    }
+
+    /// Returns the encoding of the sorted set at the key: `"listpack"` or
+    /// `"skiplist"`, worked out like `object_encoding_list`'s
+    pub fn object_encoding_zset(&self, key: &str) -> Option<&'static str> {
+        let key = key.to_lowercase();
+        let zset = self.zset_ref(&key).filter(|zset| !zset.is_empty())?;
+        Some(self.with_upgrade(&key, self.encoding_limits.zset(zset.len(), zset.iter().map(|(member, _)| member))))
+    }
+
+    /// Returns the wider of `encoding` and the one the key was upgraded to
+    fn with_upgrade(&self, key: &str, encoding: &'static str) -> &'static str {
+        match self.upgraded_encodings.get(key) {
+            Some(upgraded) => encoding::widest(encoding, upgraded),
+            None => encoding,
+        }
+    }
+
+    /// Upgrades the list at the key for good if it no longer fits the
+    /// listpack limits; the list writes call this after every change
+    ///
+    /// The limits are read at the time of the call, so new limits apply from
+    /// the next write on.
+    pub fn maybe_upgrade_list(&mut self, key: &str) {
+        let encoding = self.object_encoding_list(key);
+        self.record_upgrade(key, encoding, "listpack");
+    }
+
+    /// Upgrades the hash at the key for good if it no longer fits the
+    /// listpack limits; see `maybe_upgrade_list`
+    pub fn maybe_upgrade_hash(&mut self, key: &str) {
+        let encoding = self.object_encoding_hash(key);
+        self.record_upgrade(key, encoding, "listpack");
+    }
+
+    /// Upgrades the set at the key for good if it no longer fits the intset
+    /// limits, and again past the listpack ones; see `maybe_upgrade_list`
+    pub fn maybe_upgrade_set(&mut self, key: &str) {
+        let encoding = self.object_encoding_set(key);
+        self.record_upgrade(key, encoding, "intset");
+    }
+
+    /// Upgrades the sorted set at the key for good if it no longer fits the
+    /// listpack limits; see `maybe_upgrade_list`
+    pub fn maybe_upgrade_zset(&mut self, key: &str) {
+        let encoding = self.object_encoding_zset(key);
+        self.record_upgrade(key, encoding, "listpack");
+    }
+
+    fn record_upgrade(&mut self, key: &str, encoding: Option<&'static str>, compact: &'static str) {
+        match encoding {
+            Some(encoding) if encoding != compact => {
+                self.upgraded_encodings.insert(key.to_lowercase(), encoding);
+            }
+            _ => {}
+        }
+    }
+
+    /// Forgets an upgrade before a collection is created at the key, as a
+    /// new collection starts out compact
+    fn reset_encoding(&mut self, key: &str, exists: bool) {
+        if !exists {
+            self.upgraded_encodings.remove(key);
+        }
+    }
+
+    /// Sets a key to expire after the given number of seconds
+    ///
+    /// Expirations apply immediately, even inside a transaction. Overwriting
+    /// the key with SET or deleting it clears the expiration.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to expire (case-insensitive)
+    /// * `seconds` - Time to live; 0 expires the key on its next access
+    ///
+    /// # Returns
+    ///
+    /// `true` if the key exists and the expiration was set; `false` if it
+    /// doesn't exist or the time is out of range
+    pub fn expire(&mut self, key: &str, seconds: u64) -> bool {
+        self.expire_opts(key, seconds, ExpireFlags::default()).unwrap_or(false)
+    }
+
+    /// Sets a key to expire after the given number of seconds, if `flags` allow
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to expire (case-insensitive)
+    /// * `seconds` - Time to live; 0 expires the key on its next access
+    /// * `flags` - NX, XX, GT or LT, compared against the current deadline
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(true)` - If the key exists and the expiration was set
+    /// * `Ok(false)` - If it doesn't exist or the condition wasn't met
+    /// * `Err(String)` - If the deadline is too far away to represent; like
+    ///   Redis, it must fit in an i64 of Unix milliseconds
+    pub fn expire_opts(&mut self, key: &str, seconds: u64, flags: ExpireFlags) -> Result<bool, String> {
+        let unix_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64;
+        let deadline = seconds
+            .checked_mul(1000)
+            .and_then(|ms| i64::try_from(ms).ok())
+            .and_then(|ms| ms.checked_add(unix_ms))
+            .and_then(|_| Instant::now().checked_add(Duration::from_secs(seconds)))
+            .ok_or_else(|| "ERR invalid expire time in 'expire' command".to_string())?;
+        let key = key.to_lowercase();
+        if !self.exists(&key) {
+            return Ok(false);
+        }
+        let current = self.expires.get(&key).copied();
+        let allowed = match flags {
+            ExpireFlags { nx: true, .. } => current.is_none(),
+            ExpireFlags { xx: true, .. } => current.is_some(),
+            ExpireFlags { gt: true, .. } => current.is_some_and(|current| deadline > current),
+            ExpireFlags { lt: true, .. } => current.is_none_or(|current| deadline < current),
+            _ => true,
+        };
+        if !allowed {
+            return Ok(false);
+        }
+        self.expires.insert(key.clone(), deadline);
+        self.notify(NotifyFlags::GENERIC, "expire", &key);
+        self.mark_dirty();
+        Ok(true)
+    }
+
+    /// Removes the key's expiration, so it lives until deleted
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to persist (case-insensitive)
+    ///
+    /// # Returns
+    ///
+    /// `true` if the key had an expiration; `false` if it had none or doesn't exist
+    pub fn persist(&mut self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        if !self.exists(&key) {
+            return false;
+        }
+        let removed = self.expires.remove(&key).is_some();
+        if removed {
+            self.notify(NotifyFlags::GENERIC, "persist", &key);
+            self.mark_dirty();
+        }
+        removed
+    }
+
+    /// Returns the remaining time to live of a key in seconds
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to inspect (case-insensitive)
+    ///
+    /// # Returns
+    ///
+    /// * `-2` - If the key doesn't exist
+    /// * `-1` - If the key exists but has no expiration
+    /// * The remaining seconds otherwise, rounded up
+    pub fn ttl(&mut self, key: &str) -> i64 {
+        let key = key.to_lowercase();
+        if !self.exists(&key) {
+            return -2;
+        }
+        match self.expires.get(&key) {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                remaining.as_millis().div_ceil(1000) as i64
+            }
+            None => -1,
+        }
+    }
+
+    /// Deletes the key if its expiration has passed
+    ///
+    /// Called once per command before it touches the key, so individual
+    /// operations never need to check expirations themselves.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to check (case-insensitive)
+    ///
+    /// # Returns
+    ///
+    /// `true` if the key had expired and was removed
+    pub fn expire_if_needed(&mut self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        match self.expires.get(&key) {
+            Some(deadline) if *deadline <= Instant::now() => {}
+            _ => return false,
+        }
+        self.expires.remove(&key);
+        Arc::make_mut(&mut self.strings).remove(&key);
+        Arc::make_mut(&mut self.lists).remove(&key);
+        Arc::make_mut(&mut self.sets).remove(&key);
+        Arc::make_mut(&mut self.zsets).remove(&key);
+        Arc::make_mut(&mut self.hashes).remove(&key);
+        Arc::make_mut(&mut self.streams).remove(&key);
+        for layer in self.transaction_stack.iter_mut() {
+            layer.strings.remove(&key);
+            layer.lists.remove(&key);
+            layer.sets.remove(&key);
+            layer.zsets.remove(&key);
+            layer.hashes.remove(&key);
+            layer.streams.remove(&key);
+        }
+        self.cache.remove(&key);
+        self.lfu_forget(&key);
+        self.notify(NotifyFlags::EXPIRED, "expired", &key);
+        true
+    }
+
+    /// Removes up to `ACTIVE_EXPIRE_SAMPLE` expired keys, picked at random
+    ///
+    /// Called on every maintenance tick so keys nobody reads again still go
+    /// away, as Redis's active expire cycle does; bounding the batch keeps
+    /// each call's hold on the storage lock short.
+    ///
+    /// # Returns
+    ///
+    /// How many keys were removed
+    pub fn purge_expired_sample(&mut self) -> usize {
+        let now = Instant::now();
+        let expired: Vec<String> = self
+            .expires
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(key, _)| key)
+            .choose_multiple(&mut rand::thread_rng(), ACTIVE_EXPIRE_SAMPLE)
+            .into_iter()
+            .cloned()
+            .collect();
+        expired.iter().filter(|key| self.expire_if_needed(key)).count()
+    }
+
+    /// Drops cached values that have outlived the cache's time to live
+    pub fn purge_expired_cache(&mut self) -> usize {
+        self.cache.purge_expired()
+    }
+
+    /// Records an access to the key in its LFU counter
+    ///
+    /// The counter first decays for the minutes since the last access, then
+    /// is incremented with a probability that drops as it grows; see the
+    /// `lfu` module. Missing keys are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key being accessed (case-insensitive)
+    pub fn lfu_touch(&mut self, key: &str) {
+        let key = key.to_lowercase();
+        if self.key_type(&key) == "none" {
+            return;
+        }
+        let now = Self::unix_secs();
+        let counter = lfu::log_incr(self.lfu_counter(&key, now), rand::random());
+        self.lfu_freq.insert(key.clone(), counter);
+        self.lfu_last_access_sec.insert(key, now);
+    }
+
+    /// Returns the key's LFU access counter, as OBJECT FREQ reports it
+    ///
+    /// The counter is maintained for every key, but like Redis's it is
+    /// only meaningful when evicting by an LFU maxmemory policy.
+    ///
+    /// # Returns
+    ///
+    /// The counter, 0 to 255, or `None` if the key doesn't exist
+    pub fn lfu_freq(&mut self, key: &str) -> Option<u8> {
+        let key = key.to_lowercase();
+        if self.key_type(&key) == "none" {
+            return None;
+        }
+        Some(self.lfu_counter(&key, Self::unix_secs()))
+    }
+
+    /// Returns the decayed counter; a key never touched has the initial value
+    fn lfu_counter(&self, key: &str, now: u64) -> u8 {
+        match (self.lfu_freq.get(key), self.lfu_last_access_sec.get(key)) {
+            (Some(&counter), Some(&last)) => lfu::decay(counter, now.saturating_sub(last) / 60),
+            _ => lfu::LFU_INIT_VAL,
+        }
+    }
+
+    /// Estimates the memory the committed data takes, in bytes
+    ///
+    /// Counts key names and contents plus a fixed overhead per key and per
+    /// element. It walks the whole keyspace, so it is only worth calling
+    /// while a `max_memory` limit is set.
+    pub fn used_memory(&self) -> usize {
+        let strings = self.strings.iter().map(|(key, value)| (key, value.len()));
+        let lists = self.lists.iter().map(|(key, list)| (key, Self::list_size(list)));
+        let sets = self.sets.iter().map(|(key, set)| (key, Self::set_size(set)));
+        let zsets = self.zsets.iter().map(|(key, zset)| (key, Self::zset_size(zset)));
+        let hashes = self.hashes.iter().map(|(key, hash)| (key, Self::hash_size(hash)));
+        let streams = self.streams.iter().map(|(key, stream)| (key, Self::stream_size(stream)));
+        strings
+            .chain(lists)
+            .chain(sets)
+            .chain(zsets)
+            .chain(hashes)
+            .chain(streams)
+            .map(|(key, size)| KEY_OVERHEAD + key.len() + size)
+            .sum()
+    }
+
+    /// Estimates the memory one key takes, as `used_memory` counts it
+    fn key_memory(&self, key: &str) -> usize {
+        let size = if let Some(value) = self.strings.get(key) {
+            value.len()
+        } else if let Some(list) = self.lists.get(key) {
+            Self::list_size(list)
+        } else if let Some(set) = self.sets.get(key) {
+            Self::set_size(set)
+        } else if let Some(zset) = self.zsets.get(key) {
+            Self::zset_size(zset)
+        } else if let Some(hash) = self.hashes.get(key) {
+            Self::hash_size(hash)
+        } else if let Some(stream) = self.streams.get(key) {
+            Self::stream_size(stream)
+        } else {
+            return 0;
+        };
+        KEY_OVERHEAD + key.len() + size
+    }
+
+    /// Estimates the memory a key takes, for MEMORY USAGE
+    ///
+    /// Strings are measured exactly: value, name and a fixed overhead.
+    /// Collections are sized from the average of their first `samples`
+    /// elements (all of them for 0), scaled up for how each type is stored:
+    /// a per-element overhead for lists, the load factor for hashes (1.5)
+    /// and sets (1.3), the score and skiplist node for sorted sets.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to measure (case-insensitive)
+    /// * `samples` - How many elements of a collection to look at
+    ///
+    /// # Returns
+    ///
+    /// The estimate in bytes, or `None` if the key doesn't exist
+    pub fn memory_usage_for(&mut self, key: &str, samples: usize) -> Option<usize> {
+        let key = key.to_lowercase();
+        self.expire_if_needed(&key);
+        if let Some(value) = self.strings.get(&key) {
+            return Some(value.len() + key.len() + KEY_OVERHEAD);
+        }
+        // The average element size (as measured by `size`) times the element count
+        fn total<T>(count: usize, elements: impl Iterator<Item = T>, samples: usize, size: impl Fn(T) -> usize) -> f64 {
+            let samples = if samples == 0 { count } else { samples.min(count) };
+            let sampled: usize = elements.take(samples).map(size).sum();
+            sampled as f64 / samples.max(1) as f64 * count as f64
+        }
+        let contents = if let Some(list) = self.lists.get(&key) {
+            total(list.len(), list.iter(), samples, |value| value.len() + ELEMENT_OVERHEAD)
+        } else if let Some(hash) = self.hashes.get(&key) {
+            total(hash.len(), hash.iter(), samples, |(field, value)| field.len() + value.len()) * 1.5
+        } else if let Some(set) = self.sets.get(&key) {
+            total(set.len(), set.iter(), samples, String::len) * 1.3
+        } else if let Some(zset) = self.zsets.get(&key) {
+            total(zset.len(), zset.iter(), samples, |(member, _)| member.len() + 8 + SKIPLIST_NODE_OVERHEAD)
+        } else if let Some(stream) = self.streams.get(&key) {
+            total(stream.len(), stream.entries.values(), samples, |fields| Self::entry_size(fields))
+        } else {
+            return None;
+        };
+        Some(contents.ceil() as usize + key.len() + COLLECTION_OVERHEAD)
+    }
+
+    fn list_size(list: &VecDeque<String>) -> usize {
+        list.iter().map(|value| ELEMENT_OVERHEAD + value.len()).sum()
+    }
+
+    fn set_size(set: &HashSet<String>) -> usize {
+        set.iter().map(|member| ELEMENT_OVERHEAD + member.len()).sum()
+    }
+
+    // Members plus their 8-byte scores
+    fn zset_size(zset: &SortedSet) -> usize {
+        zset.iter().map(|(member, _)| ELEMENT_OVERHEAD + member.len() + 8).sum()
+    }
+
+    fn hash_size(hash: &HashMap<String, String>) -> usize {
+        hash.iter().map(|(field, value)| ELEMENT_OVERHEAD + field.len() + value.len()).sum()
+    }
+
+    // Entries with their 16-byte IDs, plus the pending entries of every group
+    fn stream_size(stream: &Stream) -> usize {
+        let entries: usize = stream.entries.values().map(|fields| Self::entry_size(fields)).sum();
+        let pending: usize = stream
+            .groups
+            .values()
+            .flat_map(|group| group.pel.values())
+            .map(|pending| ELEMENT_OVERHEAD + 16 + pending.consumer.len())
+            .sum();
+        entries + pending
+    }
+
+    fn entry_size(fields: &[(String, String)]) -> usize {
+        ELEMENT_OVERHEAD + 16 + fields.iter().map(|(field, value)| field.len() + value.len()).sum::<usize>()
+    }
+
+    /// Evicts keys until `used_memory` is at most `max_memory`
+    ///
+    /// Each victim is the best of `EVICTION_SAMPLES` random candidates by
+    /// `policy`: the oldest access for LRU (to the second), the lowest
+    /// counter for LFU, the nearest deadline for TTL.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_memory` - The limit in bytes
+    /// * `policy` - Which keys may go and how they're picked
+    /// * `evicted` - Receives the evicted keys, in order
+    ///
+    /// # Returns
+    ///
+    /// `false` if memory use is still over the limit because `policy` is
+    /// noeviction or ran out of keys it may evict
+    pub fn evict(&mut self, max_memory: usize, policy: MaxMemoryPolicy, evicted: &mut Vec<String>) -> bool {
+        let mut used = self.used_memory();
+        while used > max_memory {
+            let Some(victim) = self.eviction_victim(policy) else {
+                return false;
+            };
+            used = used.saturating_sub(self.key_memory(&victim));
+            self.remove_key(&victim);
+            self.notify(NotifyFlags::EVICTED, "evicted", &victim);
+            evicted.push(victim);
+        }
+        true
+    }
+
+    fn eviction_victim(&self, policy: MaxMemoryPolicy) -> Option<String> {
+        if policy == MaxMemoryPolicy::NoEviction {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        let candidates: Vec<&String> = if policy.volatile_only() {
+            self.expires.keys().choose_multiple(&mut rng, EVICTION_SAMPLES)
+        } else {
+            self.strings
+                .keys()
+                .chain(self.lists.keys())
+                .chain(self.sets.keys())
+                .chain(self.zsets.keys())
+                .chain(self.hashes.keys())
+                .chain(self.streams.keys())
+                .choose_multiple(&mut rng, EVICTION_SAMPLES)
+        };
+        let now = Self::unix_secs();
+        let victim = match policy {
+            MaxMemoryPolicy::AllKeysLru | MaxMemoryPolicy::VolatileLru => candidates
+                .into_iter()
+                .min_by_key(|key| self.lfu_last_access_sec.get(*key).copied().unwrap_or(0)),
+            MaxMemoryPolicy::AllKeysLfu | MaxMemoryPolicy::VolatileLfu => {
+                candidates.into_iter().min_by_key(|key| self.lfu_counter(key, now))
+            }
+            MaxMemoryPolicy::VolatileTtl => candidates.into_iter().min_by_key(|key| self.expires.get(*key).copied()),
+            _ => candidates.into_iter().next(),
+        };
+        victim.cloned()
+    }
+
+    fn lfu_forget(&mut self, key: &str) {
+        self.lfu_freq.remove(key);
+        self.lfu_last_access_sec.remove(key);
+    }
+
+    fn unix_secs() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+    }
+
+    fn unix_millis() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64)
+    }
+
+    /// Returns every element of a list, front to back
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The list's key (case-insensitive)
+    ///
+    /// # Returns
+    ///
+    /// The list's elements, or an empty vector if it doesn't exist
+    pub fn list_values(&self, key: &str) -> Vec<String> {
+        let key = key.to_lowercase();
+        for layer in self.transaction_stack.iter().rev() {
+            match layer.lists.get(&key) {
+                Some(Some(list)) => return list.iter().cloned().collect(),
+                Some(None) => return Vec::new(),
+                None => {}
+            }
+        }
+        self.lists.get(&key).map_or_else(Vec::new, |list| list.iter().cloned().collect())
+    }
+
+    /// Replaces whatever is stored at the key with a list of `items`
+    ///
+    /// An empty `items` deletes the key, as Redis never keeps empty lists.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The destination key (case-insensitive)
+    /// * `items` - The new list contents, front to back
+    pub fn set_list(&mut self, key: &str, items: Vec<String>) {
+        let key = key.to_lowercase();
+        self.remove_key(&key);
+        if items.is_empty() {
+            return;
+        }
+        self.index_key(&key);
+        let list: VecDeque<String> = items.into_iter().collect();
+        if let Some(layer) = self.transaction_stack.last_mut() {
+            layer.lists.insert(key.clone(), Some(list));
+        } else {
+            Arc::make_mut(&mut self.lists).insert(key.clone(), list);
+        }
+        self.maybe_upgrade_list(&key);
+        self.mark_dirty();
+    }
+
+    /// Sorts the elements of a list or the members of a set, as the SORT command does
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The list to sort (case-insensitive)
+    /// * `by` - Pattern whose `*` is replaced by each element to find its weight;
+    ///   a pattern without `*` skips sorting
+    /// * `limit` - `(offset, count)` window over the sorted elements; a negative count means all
+    /// * `get_patterns` - Patterns looked up per element instead of returning it; `#` is the element
+    /// * `descending` - Sort from largest to smallest
+    /// * `alpha` - Compare as strings rather than as numbers
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Option<String>>)` - The sorted values; `None` where a GET lookup found nothing
+    /// * `Err(String)` - If the key holds a string or a weight is not a number
+    pub fn sort(
+        &mut self,
+        key: &str,
+        by: Option<&str>,
+        limit: Option<(i64, i64)>,
+        get_patterns: &[String],
+        descending: bool,
+        alpha: bool,
+    ) -> Result<Vec<Option<String>>, String> {
+        if self.get(key).is_some() {
+            return Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string());
+        }
+        let elements = match self.scard(key) {
+            0 => self.list_values(key),
+            _ => self.smembers(key),
+        };
+
+        let no_sort = by.is_some_and(|pattern| !pattern.contains('*'));
+        let mut elements = if no_sort {
+            elements
+        } else {
+            let mut weighted = Vec::with_capacity(elements.len());
+            for element in elements {
+                let weight = match by {
+                    Some(pattern) => self.sort_lookup(pattern, &element),
+                    None => Some(element.clone()),
+                };
+                weighted.push((element, weight));
+            }
+
+            let mut sorted: Vec<String> = if alpha {
+                weighted.sort_by(|a, b| a.1.cmp(&b.1));
+                weighted.into_iter().map(|(element, _)| element).collect()
+            } else {
+                let mut scored = Vec::with_capacity(weighted.len());
+                for (element, weight) in weighted {
+                    let score = match weight {
+                        Some(weight) => weight.parse::<f64>()
+                            .map_err(|_| "ERR One or more scores can't be converted into double".to_string())?,
+                        None => 0.0,
+                    };
+                    scored.push((element, score));
+                }
+                scored.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+                scored.into_iter().map(|(element, _)| element).collect()
+            };
+            if descending {
+                sorted.reverse();
+            }
+            sorted
+        };
+
+        if let Some((offset, count)) = limit {
+            let offset = offset.max(0) as usize;
+            let count = if count < 0 { usize::MAX } else { count as usize };
+            elements = elements.into_iter().skip(offset).take(count).collect();
+        }
+
+        if get_patterns.is_empty() {
+            return Ok(elements.into_iter().map(Some).collect());
+        }
+        let mut values = Vec::with_capacity(elements.len() * get_patterns.len());
+        for element in &elements {
+            for pattern in get_patterns {
+                values.push(self.sort_lookup(pattern, element));
+            }
+        }
+        Ok(values)
+    }
+
+    /// Resolves a BY/GET pattern for one element
+    ///
+    /// `#` stands for the element itself; otherwise the first `*` is replaced
+    /// by the element and the resulting string key is read. Hash field
+    /// patterns (`key->field`) have no hash to read from and resolve to `None`.
+    fn sort_lookup(&mut self, pattern: &str, element: &str) -> Option<String> {
+        if pattern == "#" {
+            return Some(element.to_string());
+        }
+        if pattern.contains("->") {
+            return None;
+        }
+        let key = pattern.replacen('*', element, 1);
+        self.expire_if_needed(&key);
+        self.get(&key)
+    }
+}
@@ -1 +1,10 @@
-pub mod memory;
\ No newline at end of file
+pub mod memory;
+pub mod glob;
+pub mod zset;
+pub mod lfu;
+pub mod aof;
+pub mod scan;
+pub mod eviction;pub mod geo;
+pub mod encoding;
+pub mod notify;
+pub mod stream;
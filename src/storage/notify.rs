@@ -0,0 +1,131 @@
+//! # Keyspace Notification Module
+//!
+//! Which keyspace events are published, as set by `notify-keyspace-events`.
+//! The flags use Redis's letters: `K` and `E` pick the `__keyspace@0__:<key>`
+//! and `__keyevent@0__:<event>` channels, and the others pick the classes of
+//! events published on them. Without `K` or `E` nothing is published, and
+//! `MemoryStorage` can skip building channel names altogether.
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// A set of `notify-keyspace-events` flags
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NotifyFlags(u16);
+
+/// Each flag's letter, in the order CONFIG GET writes them after `A`
+const LETTERS: &[(char, NotifyFlags)] = &[
+    ('g', NotifyFlags::GENERIC),
+    ('$', NotifyFlags::STRING),
+    ('l', NotifyFlags::LIST),
+    ('s', NotifyFlags::SET),
+    ('h', NotifyFlags::HASH),
+    ('z', NotifyFlags::ZSET),
+    ('x', NotifyFlags::EXPIRED),
+    ('e', NotifyFlags::EVICTED),
+    ('t', NotifyFlags::STREAM),
+    ('d', NotifyFlags::MODULE),
+    ('K', NotifyFlags::KEYSPACE),
+    ('E', NotifyFlags::KEYEVENT),
+    ('m', NotifyFlags::KEY_MISS),
+];
+
+impl NotifyFlags {
+    /// `K`: publish on `__keyspace@0__:<key>`, with the event as the message
+    pub const KEYSPACE: NotifyFlags = NotifyFlags(1);
+    /// `E`: publish on `__keyevent@0__:<event>`, with the key as the message
+    pub const KEYEVENT: NotifyFlags = NotifyFlags(1 << 1);
+    /// `g`: DEL, EXPIRE, PERSIST, COPY and other type-independent commands
+    pub const GENERIC: NotifyFlags = NotifyFlags(1 << 2);
+    /// `$`: string commands
+    pub const STRING: NotifyFlags = NotifyFlags(1 << 3);
+    /// `l`: list commands
+    pub const LIST: NotifyFlags = NotifyFlags(1 << 4);
+    /// `s`: set commands
+    pub const SET: NotifyFlags = NotifyFlags(1 << 5);
+    /// `h`: hash commands
+    pub const HASH: NotifyFlags = NotifyFlags(1 << 6);
+    /// `z`: sorted set commands
+    pub const ZSET: NotifyFlags = NotifyFlags(1 << 7);
+    /// `x`: keys removed because they expired
+    pub const EXPIRED: NotifyFlags = NotifyFlags(1 << 8);
+    /// `e`: keys evicted for `max_memory`
+    pub const EVICTED: NotifyFlags = NotifyFlags(1 << 9);
+    /// `t`: stream commands
+    pub const STREAM: NotifyFlags = NotifyFlags(1 << 10);
+    /// `d`: module key types; accepted for compatibility, there are no modules
+    pub const MODULE: NotifyFlags = NotifyFlags(1 << 11);
+    /// `m`: reads of missing keys; accepted for compatibility, never published
+    pub const KEY_MISS: NotifyFlags = NotifyFlags(1 << 12);
+    /// `A`: every class but key misses, `g$lshzxetd`
+    pub const ALL: NotifyFlags = NotifyFlags(0b0000_1111_1111_1100);
+
+    /// Parses a `notify-keyspace-events` value such as `"KEA"`; `""` disables
+    /// notifications
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(NotifyFlags)` - The flags
+    /// * `Err(char)` - The first character that isn't a flag
+    pub fn parse(value: &str) -> Result<NotifyFlags, char> {
+        value.chars().try_fold(NotifyFlags::default(), |flags, letter| {
+            let flag = match letter {
+                'A' => NotifyFlags::ALL,
+                _ => LETTERS
+                    .iter()
+                    .find(|(known, _)| *known == letter)
+                    .map(|(_, flag)| *flag)
+                    .ok_or(letter)?,
+            };
+            Ok(flags | flag)
+        })
+    }
+
+    /// Whether every flag in `other` is set
+    pub fn contains(self, other: NotifyFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether events of `class` are published on at least one channel
+    pub fn publishes(self, class: NotifyFlags) -> bool {
+        self.0 & (Self::KEYSPACE.0 | Self::KEYEVENT.0) != 0 && self.contains(class)
+    }
+}
+
+impl std::ops::BitOr for NotifyFlags {
+    type Output = NotifyFlags;
+
+    fn bitor(self, other: NotifyFlags) -> NotifyFlags {
+        NotifyFlags(self.0 | other.0)
+    }
+}
+
+impl fmt::Display for NotifyFlags {
+    /// Writes the flags as CONFIG GET reports them, with `A` standing for
+    /// all of its classes
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let all = self.contains(NotifyFlags::ALL);
+        if all {
+            f.write_str("A")?;
+        }
+        for (letter, flag) in LETTERS {
+            if self.contains(*flag) && !(all && NotifyFlags::ALL.contains(*flag)) {
+                write!(f, "{}", letter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for NotifyFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for NotifyFlags {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        NotifyFlags::parse(&value)
+            .map_err(|letter| serde::de::Error::custom(format!("invalid notify-keyspace-events flag '{}'", letter)))
+    }
+}
@@ -0,0 +1,61 @@
+//! # Scan Module
+//!
+//! Cursors for HSCAN, SSCAN and ZSCAN. A collection's elements are visited
+//! in the order of a fixed 64-bit hash of their names, and a cursor is the
+//! hash to resume from, so it stays valid however the collection changes
+//! between calls. Every element present for the whole iteration is returned
+//! exactly once; elements added or removed meanwhile may or may not be.
+use super::glob::GlobPattern;
+
+/// Elements a scan returns per call when no COUNT is given
+pub const DEFAULT_SCAN_COUNT: usize = 10;
+
+/// FNV-1a of `name`, which orders elements for scanning
+fn scan_hash(name: &str) -> u64 {
+    name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Takes the next batch of `elements` from `cursor`
+///
+/// Returns up to `count` elements, or a few more when several share the
+/// last one's hash, since the cursor can't point between them. MATCH is
+/// applied to the batch afterwards, as Redis does, so a call may return
+/// nothing although the iteration isn't over.
+///
+/// # Arguments
+///
+/// * `elements` - Each element with the name its hash and MATCH apply to
+/// * `cursor` - 0 to start, then the cursor the previous call returned
+/// * `pattern` - Glob the names of returned elements must match
+/// * `count` - How many elements to visit; 0 is taken as 1
+///
+/// # Returns
+///
+/// The cursor for the next call, 0 once every element has been visited, and the batch
+pub fn scan<'a, T>(
+    elements: impl Iterator<Item = (&'a str, T)>,
+    cursor: u64,
+    pattern: Option<&str>,
+    count: usize,
+) -> (u64, Vec<T>) {
+    let mut remaining: Vec<(u64, &str, T)> = elements
+        .map(|(name, element)| (scan_hash(name), name, element))
+        .filter(|(hash, ..)| *hash >= cursor)
+        .collect();
+    remaining.sort_unstable_by_key(|(hash, name, _)| (*hash, *name));
+
+    let mut end = count.max(1).min(remaining.len());
+    while end < remaining.len() && remaining[end].0 == remaining[end - 1].0 {
+        end += 1;
+    }
+    let next_cursor = remaining.get(end).map_or(0, |(hash, ..)| *hash);
+    remaining.truncate(end);
+
+    let pattern = pattern.map(GlobPattern::new);
+    let batch = remaining
+        .into_iter()
+        .filter(|(_, name, _)| pattern.as_ref().is_none_or(|pattern| pattern.matches(name)))
+        .map(|(_, _, element)| element)
+        .collect();
+    (next_cursor, batch)
+}
@@ -0,0 +1,445 @@
+//! # Stream Module
+//!
+//! The value type behind XADD and the consumer group commands. An entry is
+//! a list of field/value pairs under an ID made of a Unix time in
+//! milliseconds and a sequence number, and entries are kept in a B-tree by
+//! ID, so a range of IDs is found in O(log n). Each consumer group
+//! remembers the last ID it delivered and keeps a pending entries list
+//! (PEL) of the entries it delivered that haven't been acknowledged yet,
+//! ordered by ID as well so it can be scanned from any point.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Entries Redis packs into one radix tree node, its `stream-node-max-entries` default
+pub const STREAM_NODE_MAX_ENTRIES: usize = 100;
+
+/// Entries and pending entries `XINFO STREAM key FULL` lists unless told otherwise
+pub const XINFO_FULL_COUNT: usize = 10;
+
+/// Entries XAUTOCLAIM claims at most unless told otherwise
+pub const XAUTOCLAIM_COUNT: usize = 100;
+
+/// XAUTOCLAIM looks at no more than this many pending entries per entry it
+/// may claim, so one call over a large PEL stays short
+pub const XAUTOCLAIM_ATTEMPTS_PER_COUNT: usize = 10;
+
+/// The ID of a stream entry: milliseconds, then a sequence number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamEntryId {
+    /// Unix time in milliseconds
+    pub ms: u64,
+    /// Tells apart entries added in the same millisecond
+    pub seq: u64,
+}
+
+impl StreamEntryId {
+    /// `0-0`, which no entry may have
+    pub const MIN: StreamEntryId = StreamEntryId { ms: 0, seq: 0 };
+    /// The largest ID there is
+    pub const MAX: StreamEntryId = StreamEntryId { ms: u64::MAX, seq: u64::MAX };
+
+    /// Parses `ms-seq`, or a bare `ms` with `missing_seq` as its sequence number
+    pub fn parse(id: &str, missing_seq: u64) -> Option<Self> {
+        match id.split_once('-') {
+            Some((ms, seq)) => Some(StreamEntryId { ms: ms.parse().ok()?, seq: seq.parse().ok()? }),
+            None => Some(StreamEntryId { ms: id.parse().ok()?, seq: missing_seq }),
+        }
+    }
+
+    /// Parses the start of a range: `-` is the smallest ID and a bare `ms`
+    /// starts at its first sequence number
+    pub fn parse_start(id: &str) -> Option<Self> {
+        if id == "-" {
+            Some(StreamEntryId::MIN)
+        } else {
+            StreamEntryId::parse(id, 0)
+        }
+    }
+
+    /// Parses the end of a range: `+` is the largest ID and a bare `ms`
+    /// ends at its last sequence number
+    pub fn parse_end(id: &str) -> Option<Self> {
+        if id == "+" {
+            Some(StreamEntryId::MAX)
+        } else {
+            StreamEntryId::parse(id, u64::MAX)
+        }
+    }
+
+    /// Returns the ID right after this one, or `None` for `MAX`
+    pub fn next(self) -> Option<Self> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamEntryId { ms: self.ms, seq }),
+            None => self.ms.checked_add(1).map(|ms| StreamEntryId { ms, seq: 0 }),
+        }
+    }
+}
+
+impl fmt::Display for StreamEntryId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// The ID XADD is asked to give a new entry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XAddId {
+    /// `*`: the current time, numbered after any entry from the same millisecond
+    Auto,
+    /// `ms-*`: the given time, with the next free sequence number
+    AutoSeq(u64),
+    /// An ID given in full; a bare `ms` means `ms-0`
+    Explicit(StreamEntryId),
+}
+
+impl XAddId {
+    /// Parses XADD's ID argument
+    pub fn parse(id: &str) -> Option<Self> {
+        if id == "*" {
+            return Some(XAddId::Auto);
+        }
+        if let Some(ms) = id.strip_suffix("-*") {
+            return ms.parse().ok().map(XAddId::AutoSeq);
+        }
+        StreamEntryId::parse(id, 0).map(XAddId::Explicit)
+    }
+}
+
+/// A stream entry as the read commands return it: its ID and its field/value pairs
+pub type StreamEntry = (StreamEntryId, Vec<(String, String)>);
+
+/// An entry a group delivered and is waiting to see acknowledged
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEntry {
+    /// The consumer it was last delivered to
+    pub consumer: String,
+    /// When it was last delivered
+    pub delivered_at: Instant,
+    /// How many times it has been delivered
+    pub delivery_count: u64,
+}
+
+/// A consumer of a group, created the first time it reads
+#[derive(Debug, Clone, PartialEq)]
+pub struct Consumer {
+    /// When it last tried to read
+    pub seen_time: Instant,
+    /// When it last got an entry, or `None` if it never did
+    pub active_time: Option<Instant>,
+}
+
+/// A consumer group of a stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsumerGroup {
+    /// New entries are the ones after this ID
+    pub last_delivered_id: StreamEntryId,
+    /// How many of the stream's entries the group has read, when that can be known
+    pub entries_read: Option<u64>,
+    /// Entries delivered and not yet acknowledged, by ID
+    pub pel: BTreeMap<StreamEntryId, PendingEntry>,
+    pub consumers: BTreeMap<String, Consumer>,
+}
+
+impl ConsumerGroup {
+    /// Returns how many of the group's pending entries were delivered to `consumer`
+    pub fn pending_for(&self, consumer: &str) -> usize {
+        self.pel.values().filter(|pending| pending.consumer == consumer).count()
+    }
+}
+
+/// A stream: entries by ID plus the consumer groups reading them
+///
+/// A stream exists even when it has no entries, once XADD or XGROUP
+/// CREATE ... MKSTREAM created it.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Stream {
+    pub entries: BTreeMap<StreamEntryId, Vec<(String, String)>>,
+    /// The ID of the newest entry ever added, even if it was deleted since
+    pub last_id: StreamEntryId,
+    /// The largest ID ever deleted, or `0-0` if nothing was
+    pub max_deleted_entry_id: StreamEntryId,
+    /// How many entries were ever added
+    pub entries_added: u64,
+    pub groups: BTreeMap<String, ConsumerGroup>,
+}
+
+impl Stream {
+    /// Creates an empty stream
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns how many entries the stream holds
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds an entry and returns its ID
+    ///
+    /// IDs only ever grow: a `*` while the clock is behind the last ID
+    /// reuses the last ID's millisecond with the next sequence number.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID XADD was given
+    /// * `fields` - The entry's field/value pairs
+    /// * `now_ms` - The Unix time in milliseconds `*` stands for
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(StreamEntryId)` - The new entry's ID
+    /// * `Err(String)` - If the ID is `0-0` or not greater than the last one
+    pub fn add(&mut self, id: XAddId, fields: Vec<(String, String)>, now_ms: u64) -> Result<StreamEntryId, String> {
+        let too_small = || "ERR The ID specified in XADD is equal or smaller than the target stream top item".to_string();
+        let id = match id {
+            XAddId::Auto if now_ms > self.last_id.ms => StreamEntryId { ms: now_ms, seq: 0 },
+            XAddId::Auto => self.last_id.next().ok_or_else(|| {
+                "ERR The stream has exhausted the last possible ID, unable to add more items".to_string()
+            })?,
+            XAddId::AutoSeq(ms) if ms == self.last_id.ms => {
+                StreamEntryId { ms, seq: self.last_id.seq.checked_add(1).ok_or_else(too_small)? }
+            }
+            XAddId::AutoSeq(ms) => StreamEntryId { ms, seq: 0 },
+            XAddId::Explicit(id) => id,
+        };
+        if id == StreamEntryId::MIN {
+            return Err("ERR The ID specified in XADD must be greater than 0-0".to_string());
+        }
+        if id <= self.last_id {
+            return Err(too_small());
+        }
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+        Ok(id)
+    }
+
+    /// Returns up to `count` entries from `start` to `end`, both included, oldest first
+    pub fn range(&self, start: StreamEntryId, end: StreamEntryId, count: Option<usize>) -> Vec<StreamEntry> {
+        if start > end {
+            return Vec::new();
+        }
+        self.entries
+            .range(start..=end)
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, fields)| (*id, fields.clone()))
+            .collect()
+    }
+
+    /// Returns the oldest entry, if there is one
+    pub fn first_entry(&self) -> Option<StreamEntry> {
+        self.entries.first_key_value().map(|(id, fields)| (*id, fields.clone()))
+    }
+
+    /// Returns the newest entry, if there is one
+    pub fn last_entry(&self) -> Option<StreamEntry> {
+        self.entries.last_key_value().map(|(id, fields)| (*id, fields.clone()))
+    }
+
+    /// Removes entries by ID, leaving any PEL that references them alone
+    ///
+    /// The last ID and the count of entries added stay as they were, so
+    /// XADD still can't reuse a deleted ID.
+    ///
+    /// # Returns
+    ///
+    /// How many of the IDs were entries of the stream
+    pub fn delete(&mut self, ids: &[StreamEntryId]) -> u64 {
+        let mut deleted = 0;
+        for id in ids {
+            if self.entries.remove(id).is_some() {
+                self.max_deleted_entry_id = self.max_deleted_entry_id.max(*id);
+                deleted += 1;
+            }
+        }
+        deleted
+    }
+
+    /// Creates a consumer group whose new entries are the ones after `id`
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The group's name (case-sensitive)
+    /// * `id` - The last ID the group counts as delivered; `None` (`$`) for the stream's last ID
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the group was created
+    /// * `Err(String)` - BUSYGROUP if the stream already has a group by that name
+    pub fn create_group(&mut self, name: &str, id: Option<StreamEntryId>) -> Result<(), String> {
+        if self.groups.contains_key(name) {
+            return Err("BUSYGROUP Consumer Group name already exists".to_string());
+        }
+        let last_delivered_id = id.unwrap_or(self.last_id);
+        let group = ConsumerGroup {
+            last_delivered_id,
+            entries_read: self.entries_read_at(last_delivered_id),
+            pel: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        };
+        self.groups.insert(name.to_string(), group);
+        Ok(())
+    }
+
+    /// Works out how many entries a group that delivered everything up to `id` has read
+    ///
+    /// That is known at or past the last ID, while no entry was ever
+    /// deleted, and when every deleted entry comes before the oldest
+    /// one left and so before `id`. Otherwise there is no telling, and
+    /// Redis reports it as nil.
+    fn entries_read_at(&self, id: StreamEntryId) -> Option<u64> {
+        if id >= self.last_id {
+            Some(self.entries_added)
+        } else if self.max_deleted_entry_id == StreamEntryId::MIN {
+            Some(self.entries.range(..=id).count() as u64)
+        } else {
+            let first = self.entries.keys().next()?;
+            (self.max_deleted_entry_id < *first)
+                .then(|| self.entries_added - self.len() as u64 + self.entries.range(..=id).count() as u64)
+        }
+    }
+
+    /// Returns how many entries `group` has yet to read, when that can be known
+    ///
+    /// An entry deleted after the group's last delivered ID makes the count
+    /// unknowable, as it does in Redis.
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 || group.last_delivered_id >= self.last_id {
+            return Some(0);
+        }
+        if self.max_deleted_entry_id > group.last_delivered_id {
+            return None;
+        }
+        group.entries_read.map(|read| self.entries_added.saturating_sub(read))
+    }
+
+    /// Delivers entries to a consumer of a group, as XREADGROUP does
+    ///
+    /// With `id` of `None` (`>`), up to `count` entries after the group's
+    /// last delivered ID are handed out, move the group past them and join
+    /// the PEL under `consumer` unless `noack` is set. With an ID, the
+    /// consumer's own pending entries after it are returned again, leaving
+    /// the PEL alone; an entry deleted since comes back with no fields. The
+    /// consumer is created if it doesn't exist.
+    ///
+    /// # Returns
+    ///
+    /// The entries delivered, or `None` if the stream has no such group
+    pub fn read_group(
+        &mut self,
+        group_name: &str,
+        consumer: &str,
+        id: Option<StreamEntryId>,
+        count: Option<usize>,
+        noack: bool,
+        now: Instant,
+    ) -> Option<Vec<StreamEntry>> {
+        let group = self.groups.get(group_name)?;
+        let entries: Vec<StreamEntry> = match id {
+            None => match group.last_delivered_id.next() {
+                Some(start) => self.range(start, StreamEntryId::MAX, count),
+                None => Vec::new(),
+            },
+            Some(after) => group
+                .pel
+                .range(after..)
+                .filter(|(pending_id, pending)| **pending_id > after && pending.consumer == consumer)
+                .take(count.unwrap_or(usize::MAX))
+                .map(|(pending_id, _)| (*pending_id, self.entries.get(pending_id).cloned().unwrap_or_default()))
+                .collect(),
+        };
+        let entries_read = match (id, entries.last()) {
+            (None, Some((last, _))) => self.entries_read_at(*last).or_else(|| {
+                // Nothing deleted among what was just read: the count moves on by as many
+                let read = group.entries_read?;
+                (self.max_deleted_entry_id <= group.last_delivered_id).then_some(read + entries.len() as u64)
+            }),
+            _ => group.entries_read,
+        };
+
+        let group = self.groups.get_mut(group_name)?;
+        let state = group
+            .consumers
+            .entry(consumer.to_string())
+            .or_insert(Consumer { seen_time: now, active_time: None });
+        state.seen_time = now;
+        if id.is_none() {
+            if let Some((last, _)) = entries.last() {
+                state.active_time = Some(now);
+                group.last_delivered_id = *last;
+                group.entries_read = entries_read;
+            }
+            if !noack {
+                for (entry_id, _) in &entries {
+                    let pending = PendingEntry { consumer: consumer.to_string(), delivered_at: now, delivery_count: 1 };
+                    group.pel.insert(*entry_id, pending);
+                }
+            }
+        }
+        Some(entries)
+    }
+
+    /// Hands pending entries idle for at least `min_idle` over to `consumer`
+    ///
+    /// The group's PEL is scanned in ID order from `start`. Each entry
+    /// claimed is redelivered to `consumer`; an entry that is no longer in
+    /// the stream is dropped from the PEL instead. The scan stops after
+    /// `count` claims or `count * XAUTOCLAIM_ATTEMPTS_PER_COUNT` entries.
+    ///
+    /// # Returns
+    ///
+    /// `None` if there is no such group, else the ID to resume the scan
+    /// from (`0-0` once it reached the end of the PEL), the entries claimed
+    /// and the IDs dropped as deleted
+    pub fn auto_claim(
+        &mut self,
+        group_name: &str,
+        consumer: &str,
+        min_idle: Duration,
+        start: StreamEntryId,
+        count: usize,
+        now: Instant,
+    ) -> Option<(StreamEntryId, Vec<StreamEntry>, Vec<StreamEntryId>)> {
+        let group = self.groups.get_mut(group_name)?;
+        let mut attempts = count.saturating_mul(XAUTOCLAIM_ATTEMPTS_PER_COUNT);
+        let (mut claimed, mut deleted) = (Vec::new(), Vec::new());
+        let mut scanned = group.pel.range_mut(start..);
+        let mut cursor = StreamEntryId::MIN;
+        while claimed.len() < count && attempts > 0 {
+            let Some((id, pending)) = scanned.next() else {
+                break;
+            };
+            attempts -= 1;
+            match self.entries.get(id) {
+                None => deleted.push(*id),
+                Some(_) if now.saturating_duration_since(pending.delivered_at) < min_idle => {}
+                Some(fields) => {
+                    pending.consumer = consumer.to_string();
+                    pending.delivered_at = now;
+                    pending.delivery_count += 1;
+                    claimed.push((*id, fields.clone()));
+                },
+            }
+        }
+        if let Some((id, _)) = scanned.next() {
+            cursor = *id;
+        }
+        for id in &deleted {
+            group.pel.remove(id);
+        }
+        let state = group
+            .consumers
+            .entry(consumer.to_string())
+            .or_insert(Consumer { seen_time: now, active_time: None });
+        state.seen_time = now;
+        if !claimed.is_empty() {
+            state.active_time = Some(now);
+        }
+        Some((cursor, claimed, deleted))
+    }
+}
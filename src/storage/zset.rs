@@ -0,0 +1,388 @@
+//! # Sorted Set Module
+//!
+//! The value type behind ZADD and friends: members with a floating-point
+//! score, kept ordered by score and then by member. A hash map answers
+//! score lookups and a B-tree keeps the order, so both stay O(log n).
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
+
+/// A score ordered with `f64::total_cmp`, so it can key a B-tree
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// A member as the B-tree orders it
+type Entry = (Score, String);
+
+/// Conditions ZADD puts on each update
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ZAddOptions {
+    /// Only add new members, never update existing ones
+    pub nx: bool,
+    /// Only update existing members, never add new ones
+    pub xx: bool,
+    /// Only update a member when its new score is greater
+    pub gt: bool,
+    /// Only update a member when its new score is less
+    pub lt: bool,
+    /// Count changed members as well as added ones in the reply
+    pub ch: bool,
+    /// Add the score to the member's current one instead of replacing it
+    pub incr: bool,
+}
+
+impl ZAddOptions {
+    /// Returns whether a member currently scored `old` may be given `new`
+    fn allows(&self, old: Option<f64>, new: f64) -> bool {
+        match old {
+            None => !self.xx,
+            Some(_) if self.nx => false,
+            Some(old) => !(self.gt && new <= old || self.lt && new >= old),
+        }
+    }
+}
+
+/// One end of a ZRANGEBYSCORE range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreBound {
+    /// Scores strictly beyond the value, written `(value`
+    Exclusive(f64),
+    /// Scores up to and including the value
+    Inclusive(f64),
+    /// `-inf`
+    NegInf,
+    /// `+inf`
+    PosInf,
+}
+
+impl ScoreBound {
+    /// Returns the bound as inclusive or not, with the infinities made inclusive
+    fn value(self) -> (f64, bool) {
+        match self {
+            // Adding 0 turns -0 into 0, the way scores are stored
+            ScoreBound::Exclusive(value) => (value + 0.0, false),
+            ScoreBound::Inclusive(value) => (value + 0.0, true),
+            ScoreBound::NegInf => (f64::NEG_INFINITY, true),
+            ScoreBound::PosInf => (f64::INFINITY, true),
+        }
+    }
+}
+
+/// One end of a ZRANGEBYLEX range
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    /// Members from (or up to) this one, written `[member`
+    Inclusive(String),
+    /// Members strictly beyond this one, written `(member`
+    Exclusive(String),
+    /// `+`, beyond every member
+    PosInf,
+    /// `-`, before every member
+    NegInf,
+}
+
+impl LexBound {
+    /// Returns whether `member` is at or past this bound used as a minimum
+    fn admits_from_below(&self, member: &str) -> bool {
+        match self {
+            LexBound::NegInf => true,
+            LexBound::PosInf => false,
+            LexBound::Inclusive(min) => member >= min.as_str(),
+            LexBound::Exclusive(min) => member > min.as_str(),
+        }
+    }
+
+    /// Returns whether `member` is at or before this bound used as a maximum
+    fn admits_from_above(&self, member: &str) -> bool {
+        match self {
+            LexBound::PosInf => true,
+            LexBound::NegInf => false,
+            LexBound::Inclusive(max) => member <= max.as_str(),
+            LexBound::Exclusive(max) => member < max.as_str(),
+        }
+    }
+}
+
+/// How ZUNION and ZINTER combine the scores of a member found in several sets
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AggType {
+    #[default]
+    Sum,
+    Min,
+    Max,
+}
+
+impl AggType {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            // inf + -inf is NaN; Redis scores that as 0
+            AggType::Sum => Self::or_zero(a + b),
+            AggType::Min => a.min(b),
+            AggType::Max => a.max(b),
+        }
+    }
+
+    /// Scales a score by its set's weight, where 0 * inf counts as 0
+    fn weigh(score: f64, weight: f64) -> f64 {
+        Self::or_zero(score * weight)
+    }
+
+    fn or_zero(score: f64) -> f64 {
+        if score.is_nan() { 0.0 } else { score }
+    }
+}
+
+/// Members ordered by score, ties broken by comparing the members
+#[derive(Debug, Clone, Default)]
+pub struct SortedSet {
+    scores: HashMap<String, f64>,
+    ordered: BTreeSet<Entry>,
+}
+
+impl SortedSet {
+    /// Creates an empty sorted set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of members
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    /// Returns whether the set has no members
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Returns the score of `member`, if it is in the set
+    pub fn score(&self, member: &str) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Sets the score of `member`, adding it if needed
+    ///
+    /// # Returns
+    ///
+    /// The member's previous score, or `None` if it was just added
+    pub fn insert(&mut self, member: &str, score: f64) -> Option<f64> {
+        // Keep -0 and 0 as one score, as they compare equal
+        let score = score + 0.0;
+        let old = self.scores.insert(member.to_string(), score);
+        if let Some(old) = old {
+            self.ordered.remove(&(Score(old), member.to_string()));
+        }
+        self.ordered.insert((Score(score), member.to_string()));
+        old
+    }
+
+    /// Removes `member`, returning its score if it was in the set
+    pub fn remove(&mut self, member: &str) -> Option<f64> {
+        let score = self.scores.remove(member)?;
+        self.ordered.remove(&(Score(score), member.to_string()));
+        Some(score)
+    }
+
+    /// Removes and returns the member with the lowest score
+    pub fn pop_min(&mut self) -> Option<(String, f64)> {
+        let (score, member) = self.ordered.pop_first()?;
+        self.scores.remove(&member);
+        Some((member, score.0))
+    }
+
+    /// Removes and returns the member with the highest score
+    pub fn pop_max(&mut self) -> Option<(String, f64)> {
+        let (score, member) = self.ordered.pop_last()?;
+        self.scores.remove(&member);
+        Some((member, score.0))
+    }
+
+    /// Iterates over the members from the lowest score to the highest
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&str, f64)> {
+        self.ordered.iter().map(|(score, member)| (member.as_str(), score.0))
+    }
+
+    /// Iterates over the members scored between `min` and `max`, lowest first
+    ///
+    /// Only the matching part of the B-tree is visited, so a narrow range
+    /// costs O(log n) plus the members it returns.
+    pub fn range_by_score(&self, min: ScoreBound, max: ScoreBound) -> impl DoubleEndedIterator<Item = (&str, f64)> {
+        let nothing = (Self::first_at(f64::INFINITY), Bound::Excluded((Score(f64::INFINITY), String::new())));
+        self.ordered
+            .range(Self::score_range(min, max).unwrap_or(nothing))
+            .map(|(score, member)| (member.as_str(), score.0))
+    }
+
+    /// Turns score bounds into B-tree bounds, or `None` if no score fits
+    ///
+    /// The empty string sorts before every member, so `(score, "")` marks
+    /// where a score starts; exclusive bounds move to the next float.
+    fn score_range(min: ScoreBound, max: ScoreBound) -> Option<(Bound<Entry>, Bound<Entry>)> {
+        let start = match min.value() {
+            (value, true) => value,
+            (value, false) if value < f64::INFINITY => value.next_up(),
+            _ => return None,
+        };
+        let end = match max.value() {
+            (f64::INFINITY, true) => return Some((Self::first_at(start), Bound::Unbounded)),
+            (value, true) => value.next_up(),
+            (value, false) => value,
+        };
+        (Score(start) < Score(end)).then(|| (Self::first_at(start), Bound::Excluded((Score(end), String::new()))))
+    }
+
+    fn first_at(score: f64) -> Bound<Entry> {
+        Bound::Included((Score(score), String::new()))
+    }
+
+    /// Iterates over the members between `min` and `max` in lexicographic order
+    ///
+    /// Like Redis, this assumes every member has the same score, so that
+    /// score order is member order: it skips to the first member in range
+    /// and stops at the first one past it. With mixed scores the result is
+    /// whatever contiguous run that walk finds.
+    pub fn range_by_lex<'s: 'b, 'b>(&'s self, min: &'b LexBound, max: &'b LexBound) -> impl Iterator<Item = (&'s str, f64)> + 'b {
+        self.iter()
+            .skip_while(move |(member, _)| !min.admits_from_below(member))
+            .take_while(move |(member, _)| max.admits_from_above(member))
+    }
+
+    /// Same as `range_by_lex`, walking down from `max` to `min`
+    pub fn rev_range_by_lex<'s: 'b, 'b>(&'s self, max: &'b LexBound, min: &'b LexBound) -> impl Iterator<Item = (&'s str, f64)> + 'b {
+        self.iter()
+            .rev()
+            .skip_while(move |(member, _)| !max.admits_from_above(member))
+            .take_while(move |(member, _)| min.admits_from_below(member))
+    }
+
+    /// Returns the members of the first set that are in none of the others
+    ///
+    /// A missing set (`None`) counts as empty. Scores come from the first set.
+    pub fn diff(sets: &[Option<&SortedSet>]) -> SortedSet {
+        let mut result = SortedSet::new();
+        let Some((Some(first), others)) = sets.split_first() else {
+            return result;
+        };
+        for (member, score) in first.iter() {
+            if !others.iter().flatten().any(|other| other.score(member).is_some()) {
+                result.insert(member, score);
+            }
+        }
+        result
+    }
+
+    /// Returns every member of any of the sets, scores combined by `agg`
+    ///
+    /// Each set's scores are multiplied by its weight first; sets without
+    /// a weight, which is all of them when `weights` is empty, weigh 1.
+    pub fn union(sets: &[Option<&SortedSet>], weights: &[f64], agg: AggType) -> SortedSet {
+        let mut scores: HashMap<&str, f64> = HashMap::new();
+        for (i, set) in sets.iter().enumerate() {
+            let weight = weights.get(i).copied().unwrap_or(1.0);
+            for (member, score) in set.iter().flat_map(|set| set.iter()) {
+                let score = AggType::weigh(score, weight);
+                scores
+                    .entry(member)
+                    .and_modify(|total| *total = agg.apply(*total, score))
+                    .or_insert(score);
+            }
+        }
+        Self::from_scores(scores)
+    }
+
+    /// Returns the members found in all of the sets, scores combined by `agg`
+    ///
+    /// Weights work as in `union`. Any missing set makes the result empty.
+    pub fn inter(sets: &[Option<&SortedSet>], weights: &[f64], agg: AggType) -> SortedSet {
+        let Some(sets) = sets.iter().copied().collect::<Option<Vec<&SortedSet>>>() else {
+            return SortedSet::new();
+        };
+        let Some((first, others)) = sets.split_first() else {
+            return SortedSet::new();
+        };
+        let weight = |i: usize| weights.get(i).copied().unwrap_or(1.0);
+        let mut scores = HashMap::new();
+        'members: for (member, score) in first.iter() {
+            let mut total = AggType::weigh(score, weight(0));
+            for (i, other) in others.iter().enumerate() {
+                let Some(score) = other.score(member) else {
+                    continue 'members;
+                };
+                total = agg.apply(total, AggType::weigh(score, weight(i + 1)));
+            }
+            scores.insert(member, total);
+        }
+        Self::from_scores(scores)
+    }
+
+    fn from_scores(scores: HashMap<&str, f64>) -> SortedSet {
+        let mut result = SortedSet::new();
+        for (member, score) in scores {
+            result.insert(member, score);
+        }
+        result
+    }
+
+    /// Applies ZADD's `score member` pairs under `options`
+    ///
+    /// # Returns
+    ///
+    /// How many members were added, or added and changed with CH
+    pub fn add(&mut self, pairs: &[(f64, String)], options: &ZAddOptions) -> usize {
+        let mut added = 0;
+        let mut changed = 0;
+        for (score, member) in pairs {
+            let old = self.score(member);
+            if !options.allows(old, *score) {
+                continue;
+            }
+            match old {
+                None => added += 1,
+                Some(old) if old != *score => changed += 1,
+                Some(_) => continue,
+            }
+            self.insert(member, *score);
+        }
+        if options.ch { added + changed } else { added }
+    }
+
+    /// Adds `increment` to the score of `member` under `options`, as ZADD INCR does
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(f64))` - The member's new score
+    /// * `Ok(None)` - If the options ruled the update out
+    /// * `Err(String)` - If the new score would not be a number
+    pub fn increment(&mut self, member: &str, increment: f64, options: &ZAddOptions) -> Result<Option<f64>, String> {
+        let old = self.score(member);
+        let score = old.unwrap_or(0.0) + increment;
+        if score.is_nan() {
+            return Err("ERR resulting score is not a number (NaN)".to_string());
+        }
+        if !options.allows(old, score) {
+            return Ok(None);
+        }
+        self.insert(member, score);
+        Ok(Some(score))
+    }
+}
@@ -0,0 +1,148 @@
+use redis_imitate::commands::executor::CommandExecutor;
+use redis_imitate::commands::parser::Command;
+use redis_imitate::config::config::Config;
+use redis_imitate::storage::aof::{aof_rewrite, AOF_REWRITE_ITEMS_PER_CMD};
+use redis_imitate::storage::memory::MemoryStorage;
+use redis_imitate::storage::stream::{StreamEntryId, XAddId};
+use redis_imitate::storage::zset::ZAddOptions;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aof_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("redis_imitate_aof_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("appendonly.aof")
+    }
+
+    // Decodes a file of RESP arrays back into commands
+    fn read_commands(path: &PathBuf) -> Vec<Vec<String>> {
+        let contents = fs::read_to_string(path).unwrap();
+        let mut lines = contents.split("\r\n");
+        let mut commands = Vec::new();
+        while let Some(header) = lines.next().filter(|line| !line.is_empty()) {
+            let count: usize = header.strip_prefix('*').unwrap().parse().unwrap();
+            let args = (0..count)
+                .map(|_| {
+                    let len: usize = lines.next().unwrap().strip_prefix('$').unwrap().parse().unwrap();
+                    let arg = lines.next().unwrap().to_string();
+                    assert_eq!(arg.len(), len);
+                    arg
+                })
+                .collect();
+            commands.push(args);
+        }
+        commands
+    }
+
+    #[test]
+    fn test_rewrite_emits_one_command_per_key() {
+        let mut storage = MemoryStorage::new();
+        storage.set("greeting".to_string(), "hello world".to_string());
+        storage.set("counter".to_string(), "1".to_string());
+        for i in 0..3 {
+            storage.incr("counter").unwrap();
+            storage.rpush("list", format!("item{}", i));
+        }
+        storage.sadd("set", &["a".to_string(), "b".to_string()]).unwrap();
+        storage.zadd("zset", &[(1.5, "one".to_string()), (2.0, "two".to_string())], &ZAddOptions::default()).unwrap();
+        storage.expire("greeting", 100);
+        storage.set("gone".to_string(), "x".to_string());
+        storage.expire("gone", 0);
+
+        let path = aof_path("rewrite");
+        aof_rewrite(&storage, path.to_str().unwrap()).unwrap();
+        let mut commands = read_commands(&path);
+        commands.sort();
+
+        assert_eq!(commands.len(), 6);
+        assert_eq!(commands[0][..2], ["EXPIREAT".to_string(), "greeting".to_string()]);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let at: u64 = commands[0][2].parse().unwrap();
+        assert!(at > now + 90 && at <= now + 101);
+        assert_eq!(commands[1], ["RPUSH", "list", "item0", "item1", "item2"]);
+        let mut sadd = commands[2].clone();
+        sadd[2..].sort();
+        assert_eq!(sadd, ["SADD", "set", "a", "b"]);
+        assert_eq!(commands[3], ["SET", "counter", "4"]);
+        assert_eq!(commands[4], ["SET", "greeting", "hello world"]);
+        assert_eq!(commands[5], ["ZADD", "zset", "1.5", "one", "2", "two"]);
+        assert!(!PathBuf::from(format!("{}.rewrite.tmp", path.display())).exists());
+    }
+
+    #[test]
+    fn test_rewrite_splits_long_collections() {
+        let mut storage = MemoryStorage::new();
+        let total = AOF_REWRITE_ITEMS_PER_CMD * 2 + 1;
+        for i in 0..total {
+            storage.rpush("list", i.to_string());
+            storage.zadd("zset", &[(i as f64, format!("m{}", i))], &ZAddOptions::default()).unwrap();
+        }
+
+        let path = aof_path("batches");
+        aof_rewrite(&storage, path.to_str().unwrap()).unwrap();
+        let commands = read_commands(&path);
+
+        let rpush: Vec<&Vec<String>> = commands.iter().filter(|command| command[0] == "RPUSH").collect();
+        assert_eq!(rpush.len(), 3);
+        assert_eq!(rpush[0].len(), 2 + AOF_REWRITE_ITEMS_PER_CMD);
+        let items: Vec<String> = rpush.iter().flat_map(|command| command[2..].to_vec()).collect();
+        assert_eq!(items, (0..total).map(|i| i.to_string()).collect::<Vec<_>>());
+
+        let zadd: Vec<&Vec<String>> = commands.iter().filter(|command| command[0] == "ZADD").collect();
+        assert_eq!(zadd.len(), 3);
+        assert_eq!(zadd[0].len(), 2 + AOF_REWRITE_ITEMS_PER_CMD * 2);
+        assert_eq!(zadd[2][2..], ["128".to_string(), "m128".to_string()]);
+    }
+
+    #[test]
+    fn test_bgrewriteaof_command() {
+        let path = aof_path("command");
+        let _ = fs::remove_file(&path);
+        let config = Config { appendfilename: path.to_str().unwrap().to_string(), ..Config::new() };
+        let executor = CommandExecutor::with_config(Arc::new(Mutex::new(MemoryStorage::new())), Arc::new(config));
+        executor.execute_command(Command::Set("key".to_string(), "value".to_string()));
+
+        assert_eq!(
+            executor.execute_command(Command::BgRewriteAof),
+            "Background append only file rewriting started".to_string()
+        );
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !path.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(read_commands(&path), vec![vec!["SET".to_string(), "key".to_string(), "value".to_string()]]);
+    }
+
+    #[test]
+    fn test_rewrite_includes_hashes() {
+        let mut storage = MemoryStorage::new();
+        storage.hset("user", &[("name".to_string(), "ada".to_string())]).unwrap();
+
+        let path = aof_path("hashes");
+        aof_rewrite(&storage, path.to_str().unwrap()).unwrap();
+        assert_eq!(read_commands(&path), vec![vec!["HSET", "user", "name", "ada"]]);
+    }
+
+    #[test]
+    fn test_rewrite_includes_streams() {
+        let mut storage = MemoryStorage::new();
+        storage.xadd("events", XAddId::Explicit(StreamEntryId { ms: 1, seq: 0 }), &[("kind".to_string(), "click".to_string())]).unwrap();
+        storage.xgroup_create("events", "readers", Some(StreamEntryId::MIN), false).unwrap();
+
+        let path = aof_path("streams");
+        aof_rewrite(&storage, path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            read_commands(&path),
+            vec![
+                vec!["XADD", "events", "1-0", "kind", "click"],
+                vec!["XGROUP", "CREATE", "events", "readers", "0-0", "MKSTREAM"],
+            ]
+        );
+    }
+}
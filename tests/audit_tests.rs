@@ -0,0 +1,118 @@
+use redis_imitate::commands::executor::CommandExecutor;
+use redis_imitate::commands::parser::Command;
+use redis_imitate::config::config::Config;
+use redis_imitate::network::client::ClientRegistry;
+use redis_imitate::storage::memory::MemoryStorage;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audit_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("redis_imitate_audit_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("audit.log")
+    }
+
+    fn audited_executor(path: &Path, configure: impl FnOnce(&mut Config)) -> CommandExecutor {
+        let mut config = Config::new();
+        config.audit_log_enabled = true;
+        config.audit_log_path = path.to_str().unwrap().to_string();
+        configure(&mut config);
+        CommandExecutor::with_config(Arc::new(Mutex::new(MemoryStorage::new())), Arc::new(config))
+    }
+
+    // Waits for the logger thread to write `count` lines
+    fn read_lines(path: &Path, count: usize) -> Vec<String> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let lines: Vec<String> = fs::read_to_string(path)
+                .unwrap_or_default()
+                .lines()
+                .map(str::to_string)
+                .collect();
+            if lines.len() >= count || Instant::now() >= deadline {
+                return lines;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_writes_are_audited_in_order_and_reads_are_not() {
+        let path = audit_path("order");
+        let executor = audited_executor(&path, |_| {});
+        let clients = ClientRegistry::new();
+        let client = clients.register_addr("127.0.0.1:5555".to_string());
+
+        executor.execute_command_as(Command::Set("a".to_string(), "secret".to_string()), &client);
+        executor.execute_command_as(Command::Get("a".to_string()), &client);
+        executor.execute_command_as(Command::Set("b".to_string(), "2".to_string()), &client);
+        executor.execute_command_as(Command::Get("b".to_string()), &client);
+        executor.execute_command_as(Command::Del("a".to_string()), &client);
+        // Fails with WRONGTYPE, so it changed nothing and isn't audited
+        executor.execute_command_as(Command::SAdd("b".to_string(), vec!["m".to_string()]), &client);
+        executor.execute_transaction_as(&[Command::Get("b".to_string()), Command::Del("b".to_string())], &client);
+
+        let lines = read_lines(&path, 4);
+        assert_eq!(lines.len(), 4);
+        let commands: Vec<&str> = lines
+            .iter()
+            .map(|line| line.split(" cmd=").nth(1).unwrap().split(' ').next().unwrap())
+            .collect();
+        assert_eq!(commands, vec!["set", "set", "del", "del"]);
+        assert!(lines[0].contains(&format!("id={} addr=127.0.0.1:5555 db=0 cmd=set keys=[\"a\"] values=[redacted]", client.id)));
+        assert!(lines[2].contains("cmd=del keys=[\"a\"]"));
+        assert!(lines[3].contains("cmd=del keys=[\"b\"]"));
+        assert!(lines.iter().all(|line| !line.contains("secret")));
+        assert!(lines[0].split(' ').next().unwrap().parse::<f64>().is_ok());
+
+        let info = executor.execute_command(Command::Info(Some("audit".to_string())));
+        assert_eq!(info, "# Audit\naudit_log_enabled:1\naudit_log_dropped:0\n");
+    }
+
+    #[test]
+    fn test_values_are_truncated_when_allowed() {
+        let path = audit_path("truncate");
+        let executor = audited_executor(&path, |config| config.audit_log_max_value_len = 4);
+
+        executor.execute_command(Command::Set("k".to_string(), "abcdefgh".to_string()));
+        executor.execute_command(Command::LPush("l".to_string(), "xy".to_string()));
+
+        let lines = read_lines(&path, 2);
+        assert!(lines[0].ends_with("id=- addr=- db=0 cmd=set keys=[\"k\"] values=[\"abcd...\"]"));
+        assert!(lines[1].ends_with("cmd=lpush keys=[\"l\"] values=[\"xy\"]"));
+    }
+
+    #[test]
+    fn test_log_rotates_by_size() {
+        let path = audit_path("rotate");
+        let executor = audited_executor(&path, |config| config.audit_log_max_size = 300);
+
+        for i in 0..10 {
+            executor.execute_command(Command::Set(format!("key{}", i), "v".to_string()));
+        }
+        let rotated = PathBuf::from(format!("{}.1", path.display()));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !fs::read_to_string(&path).unwrap_or_default().contains("key9") && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(rotated.exists());
+        assert!(fs::metadata(&path).unwrap().len() <= 300);
+        assert!(fs::metadata(&rotated).unwrap().len() <= 300);
+        assert!(fs::read_to_string(&path).unwrap().contains("key9"));
+    }
+
+    #[test]
+    fn test_disabled_audit_log() {
+        let executor = CommandExecutor::new(Arc::new(Mutex::new(MemoryStorage::new())));
+        let info = executor.execute_command(Command::Info(Some("audit".to_string())));
+        assert_eq!(info, "# Audit\naudit_log_enabled:0\naudit_log_dropped:0\n");
+    }
+}
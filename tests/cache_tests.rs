@@ -104,4 +104,19 @@ mod tests {
         std::thread::sleep(Duration::from_millis(100));
         assert_eq!(cache.get(&"key1".to_string()), Some(2));
     }
+
+    #[test]
+    fn test_purge_expired() {
+        let mut cache = AVLCache::new(10, Duration::from_millis(100));
+        for i in 0..5 {
+            cache.put(i, i);
+        }
+        std::thread::sleep(Duration::from_millis(150));
+        cache.put(5, 5);
+
+        assert_eq!(cache.purge_expired(), 5);
+        assert_eq!(cache.purge_expired(), 0);
+        assert_eq!(cache.get(&0), None);
+        assert_eq!(cache.get(&5), Some(5));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,421 @@
+use redis_imitate::commands::executor::CommandExecutor;
+use redis_imitate::commands::parser::Command;
+use redis_imitate::config::cli::{CliAction, CliArgs};
+use redis_imitate::config::config::{AclUser, Config, OutputBufferLimit, SaveCondition};
+use redis_imitate::config::validate::Severity;
+use redis_imitate::network::server::Server;
+use redis_imitate::storage::memory::MemoryStorage;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_config(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("redis_imitate_config_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("redis.toml");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_from_file_fills_missing_fields_with_defaults() {
+        let path = temp_config("defaults", "port = 7000\n");
+        let config = Config::from_file(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(config.port, 7000);
+        assert_eq!(config.host, "0.0.0.0");
+        assert_eq!(config.config_file.as_deref(), path.to_str());
+    }
+
+    #[test]
+    fn test_from_file_tolerates_empty_files_and_unknown_keys() {
+        let empty = temp_config("empty", "");
+        let config = Config::from_file(empty.to_str().unwrap()).unwrap();
+        assert_eq!(config.port, Config::new().port);
+        assert!(config.unknown_keys.is_empty());
+
+        // A typo is reported, not fatal; aliases aren't typos
+        let typo = temp_config("typo", "prot = 7000\nport = 7001\nsave = [[60, 1]]\n");
+        let config = Config::from_file(typo.to_str().unwrap()).unwrap();
+        assert_eq!(config.port, 7001);
+        assert_eq!(config.unknown_keys, vec!["prot".to_string()]);
+    }
+
+    #[test]
+    fn test_from_file_errors_name_the_file_and_position() {
+        let broken = temp_config("broken", "port = 7000\nhost \"127.0.0.1\"\n");
+        let path = broken.to_str().unwrap();
+        let error = Config::from_file(path).unwrap_err().to_string();
+        assert!(error.starts_with(path), "{}", error);
+        assert!(error.contains("line 2"), "{}", error);
+
+        let wrong_type = temp_config("wrong_type", "port = \"high\"\n");
+        let error = Config::from_file(wrong_type.to_str().unwrap()).unwrap_err().to_string();
+        assert!(error.contains("port"), "{}", error);
+
+        let missing = std::env::temp_dir().join("redis_imitate_config_missing.toml");
+        let error = Config::from_file(missing.to_str().unwrap()).unwrap_err().to_string();
+        assert!(error.starts_with(missing.to_str().unwrap()), "{}", error);
+    }
+
+    #[test]
+    fn test_rewrite_keeps_comments_and_updates_values() {
+        let original = "\
+# Listening port
+port = 7000
+unknown_key = \"kept\"
+
+[rename_command]
+# Disable FLUSHALL
+FLUSHALL = \"\"
+";
+        let path = temp_config("rewrite", original);
+        let path_str = path.to_str().unwrap();
+        let mut config = Config::from_file(path_str).unwrap();
+        config.port = 7001;
+        config.rename_command.insert("DEL".to_string(), "REMOVE".to_string());
+        config.save_to_file_atomic(path_str).unwrap();
+
+        let rewritten = fs::read_to_string(&path).unwrap();
+        assert!(rewritten.contains("# Listening port\nport = 7001\n"));
+        assert!(rewritten.contains("unknown_key = \"kept\""));
+        assert!(rewritten.contains("# Disable FLUSHALL\nFLUSHALL = \"\"\n"));
+        assert!(rewritten.contains("DEL = \"REMOVE\""));
+        assert!(!path.with_extension("toml.tmp").exists());
+
+        let reloaded = Config::from_file(path_str).unwrap();
+        assert_eq!(reloaded.port, 7001);
+        assert_eq!(reloaded.max_connections, config.max_connections);
+        assert_eq!(reloaded.rename_command, config.rename_command);
+    }
+
+    #[test]
+    fn test_config_rewrite_command() {
+        let storage = Arc::new(Mutex::new(MemoryStorage::new()));
+        let executor = CommandExecutor::new(Arc::clone(&storage));
+        assert_eq!(
+            executor.execute_command(Command::ConfigRewrite),
+            "ERR The server is running without a config file".to_string()
+        );
+
+        let path = temp_config("command", "port = 7002\n");
+        let config = Config::from_file(path.to_str().unwrap()).unwrap();
+        let executor = CommandExecutor::with_config(storage, Arc::new(config));
+        assert_eq!(executor.execute_command(Command::ConfigRewrite), "OK".to_string());
+        let rewritten = fs::read_to_string(&path).unwrap();
+        assert!(rewritten.starts_with("port = 7002\n"));
+        assert!(rewritten.contains("max_connections = "));
+
+        // Values changed at runtime are what gets written
+        let set = |name: &str, value: &str| executor.execute_command(Command::ConfigSet(name.to_string(), value.to_string()));
+        assert_eq!(set("max-memory", "10mb"), "OK".to_string());
+        assert_eq!(executor.execute_command(Command::ConfigRewrite), "OK".to_string());
+        assert_eq!(Config::from_file(path.to_str().unwrap()).unwrap().max_memory, 10 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_config_get_and_set() {
+        let executor = CommandExecutor::new(Arc::new(Mutex::new(MemoryStorage::new())));
+        let get = |pattern: &str| executor.execute_command(Command::ConfigGet(pattern.to_string()));
+        let set = |name: &str, value: &str| executor.execute_command(Command::ConfigSet(name.to_string(), value.to_string()));
+
+        assert_eq!(get("port"), "port\n6379".to_string());
+        assert_eq!(get("MAXMEMORY-*"), "maxmemory-policy\nnoeviction".to_string());
+        assert_eq!(get("save-conditions"), "save-conditions\n900 1 300 10 60 10000".to_string());
+        assert_eq!(get("nothing*"), "(empty list or set)".to_string());
+        // ACL users hold passwords
+        assert_eq!(get("acl"), "(empty list or set)".to_string());
+        assert!(get("*").contains("tcp-read-timeout-ms\n0"));
+
+        assert_eq!(set("max_memory", "2k"), "OK".to_string());
+        assert_eq!(set("maxmemory-policy", "ALLKEYS-LRU"), "OK".to_string());
+        assert_eq!(set("save-conditions", "60 5"), "OK".to_string());
+        assert_eq!(set("no-save", "yes"), "OK".to_string());
+        assert_eq!(get("max-memory"), "max-memory\n2000".to_string());
+        assert_eq!(get("maxmemory-policy"), "maxmemory-policy\nallkeys-lru".to_string());
+        assert_eq!(get("save-conditions"), "save-conditions\n60 5".to_string());
+        let runtime = executor.runtime_config();
+        assert!(!runtime.save_due(10, Duration::from_secs(60)));
+        assert_eq!(set("no-save", "no"), "OK".to_string());
+        assert!(runtime.save_due(10, Duration::from_secs(60)));
+
+        assert_eq!(
+            set("max-memory", "lots"),
+            "ERR CONFIG SET failed (possibly related to argument 'max-memory') - argument must be a memory value".to_string()
+        );
+        assert_eq!(
+            set("save-conditions", "60"),
+            "ERR CONFIG SET failed (possibly related to argument 'save-conditions') - Invalid save parameters".to_string()
+        );
+        assert_eq!(
+            set("port", "7000"),
+            "ERR CONFIG SET failed (possibly related to argument 'port') - can't set immutable config".to_string()
+        );
+        assert_eq!(
+            set("no-such-thing", "1"),
+            "ERR Unknown option or number of arguments for CONFIG SET - 'no-such-thing'".to_string()
+        );
+        assert_eq!(get("port"), "port\n6379".to_string());
+    }
+
+    #[test]
+    fn test_save_conditions() {
+        let config = Config::new();
+        assert_eq!(config.save_conditions, vec![
+            SaveCondition { dirty_threshold: 1, seconds: 900 },
+            SaveCondition { dirty_threshold: 10, seconds: 300 },
+            SaveCondition { dirty_threshold: 10000, seconds: 60 },
+        ]);
+        assert!(!config.save_due(0, Duration::from_secs(3600)));
+        assert!(!config.save_due(5, Duration::from_secs(299)));
+        assert!(config.save_due(10, Duration::from_secs(300)));
+        assert!(config.save_due(10000, Duration::from_secs(60)));
+
+        let path = temp_config("save", "\
+no_save = true
+
+[[save_conditions]]
+dirty_threshold = 2
+seconds = 5
+");
+        let path_str = path.to_str().unwrap();
+        let mut config = Config::from_file(path_str).unwrap();
+        assert_eq!(config.save_conditions, vec![SaveCondition { dirty_threshold: 2, seconds: 5 }]);
+        assert!(!config.save_due(100, Duration::from_secs(100)));
+        config.no_save = false;
+        assert!(config.save_due(2, Duration::from_secs(5)));
+
+        config.save_conditions.push(SaveCondition { dirty_threshold: 1, seconds: 60 });
+        config.save_to_file_atomic(path_str).unwrap();
+        let reloaded = Config::from_file(path_str).unwrap();
+        assert_eq!(reloaded.save_conditions, config.save_conditions);
+        assert!(!reloaded.no_save);
+
+        // Redis-style `save` lines are `[seconds, changes]` pairs
+        let path = temp_config("save_pairs", "save = [[900, 1], [60, 10000]]\n");
+        let config = Config::from_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(config.save_conditions, vec![
+            SaveCondition { dirty_threshold: 1, seconds: 900 },
+            SaveCondition { dirty_threshold: 10000, seconds: 60 },
+        ]);
+    }
+
+    #[test]
+    fn test_acl_users_survive_rewrite() {
+        let path = temp_config("acl", "\
+port = 7004
+
+[acl.reader]
+password = \"readpass\"
+permissions = [\"+@read\"]
+");
+        let path_str = path.to_str().unwrap();
+        let mut config = Config::from_file(path_str).unwrap();
+        let reader = AclUser { password: "readpass".to_string(), permissions: vec!["+@read".to_string()] };
+        assert_eq!(config.acl.get("reader"), Some(&reader));
+        assert!(Config::new().acl.is_empty());
+
+        config.port = 7005;
+        config.save_to_file_atomic(path_str).unwrap();
+        let reloaded = Config::from_file(path_str).unwrap();
+        assert_eq!(reloaded.port, 7005);
+        assert_eq!(reloaded.acl, config.acl);
+    }
+
+    #[test]
+    fn test_output_buffer_limits() {
+        let config = Config::new();
+        assert_eq!(config.client_output_buffer_limit_normal, OutputBufferLimit::default());
+        assert_eq!(config.client_output_buffer_limit_pubsub.hard_limit_bytes, 32 * 1024 * 1024);
+
+        let mut since = None;
+        assert!(!OutputBufferLimit::default().is_exceeded(usize::MAX, &mut since));
+
+        let limit = OutputBufferLimit { hard_limit_bytes: 100, soft_limit_bytes: 10, soft_limit_seconds: 0 };
+        assert!(limit.is_exceeded(101, &mut since));
+        assert!(!limit.is_exceeded(10, &mut since));
+        assert!(limit.is_exceeded(11, &mut since));
+
+        // Over the soft limit only counts once it lasts long enough
+        let limit = OutputBufferLimit { soft_limit_seconds: 60, ..limit };
+        let mut since = None;
+        assert!(!limit.is_exceeded(50, &mut since));
+        assert!(since.is_some());
+        assert!(!limit.is_exceeded(5, &mut since));
+        assert!(since.is_none());
+        let mut since = Some(std::time::Instant::now() - Duration::from_secs(61));
+        assert!(limit.is_exceeded(50, &mut since));
+    }
+
+    #[test]
+    fn test_from_env() {
+        std::env::set_var("REDIS_PORT", "7003");
+        std::env::set_var("REDIS_NO_SAVE", "true");
+        std::env::set_var("REDIS_LOG_LEVEL", "debug");
+        std::env::set_var("REDIS_SAVE_CONDITIONS", "[{ dirty_threshold = 3, seconds = 30 }]");
+        let config = Config::from_env().unwrap();
+        assert_eq!(config.port, 7003);
+        assert!(config.no_save);
+        assert_eq!(config.log_level, "debug");
+        assert_eq!(config.save_conditions, vec![SaveCondition { dirty_threshold: 3, seconds: 30 }]);
+        assert_eq!(config.host, "0.0.0.0");
+
+        std::env::set_var("REDIS_PORT", "not a port");
+        assert!(Config::from_env().is_err());
+        for name in ["REDIS_PORT", "REDIS_NO_SAVE", "REDIS_LOG_LEVEL", "REDIS_SAVE_CONDITIONS"] {
+            std::env::remove_var(name);
+        }
+    }
+
+    #[test]
+    fn test_bgsave_writes_snapshot_and_resets_dirty_count() {
+        let dir = std::env::temp_dir().join(format!("redis_imitate_bgsave_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let snapshot = dir.join("dump.snapshot");
+        let _ = fs::remove_file(&snapshot);
+        let config = Config { snapshot_path: snapshot.to_str().unwrap().to_string(), ..Config::new() };
+        let storage = Arc::new(Mutex::new(MemoryStorage::new()));
+        let executor = CommandExecutor::with_config(Arc::clone(&storage), Arc::new(config));
+
+        executor.execute_command(Command::Set("key".to_string(), "value".to_string()));
+        assert_eq!(storage.lock().unwrap().dirty_count(), 1);
+        assert_eq!(executor.execute_command(Command::BgSave), "Background saving started".to_string());
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while storage.lock().unwrap().dirty_count() != 0 && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(storage.lock().unwrap().dirty_count(), 0);
+        let mut loaded = MemoryStorage::new();
+        loaded.load_snapshot(snapshot.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.get("key"), Some("value".to_string()));
+    }
+
+    #[test]
+    fn test_rate_limit_settings() {
+        let config = Config::new();
+        assert_eq!((config.max_commands_per_second, config.rate_limit_burst), (0, 0));
+        assert_eq!(config.rate_limit_mode, "delay");
+
+        let path = temp_config("rate", "max_commands_per_sec_per_client = 50\nrate_limit_mode = \"reject\"\n");
+        let config = Config::from_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(config.max_commands_per_second, 50);
+        assert_eq!(config.rate_limit_mode, "reject");
+    }
+
+    fn cli(args: &[&str]) -> Result<CliAction, String> {
+        CliArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_command_line_overrides_config_file_and_defaults() {
+        let path = temp_config("cli", "port = 7000\nhost = \"127.0.0.1\"\nsnapshot_path = \"file.snapshot\"\n");
+        let path = path.to_str().unwrap();
+
+        // No flags: the file, then the defaults
+        let Ok(CliAction::Run(args)) = cli(&[path]) else { panic!("config file not accepted") };
+        let config = args.config().unwrap();
+        assert_eq!((config.port, config.host.as_str(), config.snapshot_path.as_str()), (7000, "127.0.0.1", "file.snapshot"));
+        assert_eq!(config.log_level, "info");
+
+        // Flags win over both, before or after the file, with or without `=`
+        let Ok(CliAction::Run(args)) = cli(&["--port", "7100", path, "--snapshot=cli.snapshot", "--loglevel", "DEBUG"]) else {
+            panic!("flags not accepted")
+        };
+        let config = args.config().unwrap();
+        assert_eq!((config.port, config.host.as_str(), config.snapshot_path.as_str()), (7100, "127.0.0.1", "cli.snapshot"));
+        assert_eq!(config.log_level, "debug");
+        assert_eq!(config.config_file.as_deref(), Some(path));
+
+        // Without a file, flags go straight over the defaults
+        let Ok(CliAction::Run(args)) = cli(&["--host", "::1", "--daemon-log", "server.log"]) else { panic!("flags not accepted") };
+        let config = args.config().unwrap();
+        assert_eq!((config.port, config.host.as_str(), config.log_file.as_str()), (6379, "::1", "server.log"));
+        assert_eq!(config.snapshot_path, Config::new().snapshot_path);
+    }
+
+    #[test]
+    fn test_command_line_rejects_what_it_does_not_know() {
+        assert_eq!(cli(&["--help"]), Ok(CliAction::Help));
+        assert_eq!(cli(&["-v", "--bogus"]), Ok(CliAction::Version));
+        assert_eq!(cli(&[]), Ok(CliAction::Run(CliArgs::default())));
+        assert_eq!(cli(&["--bogus"]), Err("unknown option '--bogus'".to_string()));
+        assert_eq!(cli(&["--port"]), Err("--port needs a value".to_string()));
+        assert_eq!(cli(&["--port", "70000"]), Err("invalid port '70000'".to_string()));
+        assert_eq!(cli(&["--loglevel", "loud"]), Err("invalid log level 'loud'".to_string()));
+        assert_eq!(cli(&["a.toml", "b.toml"]), Err("unexpected argument 'b.toml'".to_string()));
+    }
+
+    // The problems `validate` finds, as (severity, message)
+    fn problems(config: &Config) -> Vec<(Severity, String)> {
+        config.validate().into_iter().map(|problem| (problem.severity, problem.message)).collect()
+    }
+
+    #[test]
+    fn test_validate_accepts_the_defaults() {
+        assert_eq!(problems(&Config::new()), Vec::new());
+    }
+
+    #[test]
+    fn test_validate_names_the_offending_field_and_value() {
+        let errors = |config: Config| -> Vec<String> {
+            problems(&config).into_iter().filter(|(severity, _)| *severity == Severity::Error).map(|(_, message)| message).collect()
+        };
+        assert_eq!(
+            errors(Config { max_connections: 0, ..Config::new() }),
+            vec!["max_connections = 0: every client would be turned away".to_string()]
+        );
+        assert_eq!(
+            errors(Config { max_memory: 1000, ..Config::new() }),
+            vec!["max_memory = 1000: less than the 64000 bytes the 1000-entry read cache alone may take".to_string()]
+        );
+        assert_eq!(
+            errors(Config { maxmemory_policy: "sometimes".to_string(), ..Config::new() }),
+            vec!["maxmemory_policy = \"sometimes\": not an eviction policy".to_string()]
+        );
+        assert_eq!(
+            errors(Config { proto_max_inline_size: 0, ..Config::new() }),
+            vec!["proto_max_inline_size = 0: every command would be too long".to_string()]
+        );
+        let limit = OutputBufferLimit { hard_limit_bytes: 10, soft_limit_bytes: 20, soft_limit_seconds: 1 };
+        let found = errors(Config { client_output_buffer_limit_normal: limit, ..Config::new() });
+        assert_eq!(found.len(), 1);
+        assert!(found[0].starts_with("client_output_buffer_limit_normal = OutputBufferLimit { hard_limit_bytes: 10"));
+        assert!(found[0].ends_with(": soft_limit_bytes is above hard_limit_bytes"));
+
+        // Persistence paths are probed
+        let missing = "/nonexistent_redis_imitate_dir/data.snapshot".to_string();
+        let found = errors(Config { snapshot_path: missing.clone(), ..Config::new() });
+        assert_eq!(found.len(), 1);
+        assert!(found[0].starts_with("snapshot_path = \"/nonexistent_redis_imitate_dir/data.snapshot\": can't be written: "));
+        assert_eq!(errors(Config { audit_log_path: missing.clone(), ..Config::new() }), Vec::<String>::new());
+        let found = errors(Config { audit_log_enabled: true, audit_log_path: missing, ..Config::new() });
+        assert!(found[0].starts_with("audit_log_path = "));
+    }
+
+    #[test]
+    fn test_validate_warns_about_settings_with_a_fallback() {
+        let config = Config { port: 0, rate_limit_mode: "slow".to_string(), acceptor_threads: 0, ..Config::new() };
+        assert_eq!(
+            problems(&config),
+            vec![
+                (Severity::Warning, "port = 0: the OS will pick a random port".to_string()),
+                (Severity::Warning, "rate_limit_mode = \"slow\": neither \"delay\" nor \"reject\", taken as \"delay\"".to_string()),
+                (Severity::Warning, "acceptor_threads = 0: taken as 1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_server_refuses_to_start_with_an_invalid_config() {
+        let config = Config { host: "127.0.0.1".to_string(), port: 0, max_connections: 0, ..Config::new() };
+        let error = Server::new(config).start().err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(error.to_string(), "invalid configuration: max_connections = 0: every client would be turned away");
+    }
+}
@@ -1,39 +1,86 @@
 use redis_imitate::network::connection::Connection;
+use redis_imitate::network::server::{Server, ServerHandle};
 use redis_imitate::commands::executor::CommandExecutor;
+use redis_imitate::commands::parser::CommandParser;
+use redis_imitate::config::config::Config;
 use redis_imitate::storage::memory::MemoryStorage;
 use std::io::{BufRead, BufReader, Write};
-use std::net::{TcpListener, TcpStream};
+use std::net::{Shutdown, TcpListener, TcpStream};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    // Helper function to create a mock connection
-    fn setup_connection() -> (Connection, TcpStream) {
-        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
-        let addr = listener.local_addr().unwrap();
-        
-        let client = TcpStream::connect(addr).unwrap();
-        let (server, _) = listener.accept().unwrap();
-        
-        // Create MemoryStorage and wrap it in Arc<Mutex>
-        let storage = Arc::new(Mutex::new(MemoryStorage::new()));
-        let executor = Arc::new(CommandExecutor::new(storage));
-        let connection = Connection::new(server, executor);
-        
-        (connection, client)
+    // A server on a port the OS picks, shut down and its snapshot removed
+    // when the test is done with it
+    struct TestServer {
+        handle: Option<ServerHandle>,
+        snapshot_path: String,
+    }
+
+    impl TestServer {
+        fn start(name: &str, configure: impl FnOnce(&mut Config)) -> TestServer {
+            Self::with_storage(name, Arc::new(Mutex::new(MemoryStorage::new())), configure)
+        }
+
+        fn with_storage(name: &str, storage: Arc<Mutex<MemoryStorage>>, configure: impl FnOnce(&mut Config)) -> TestServer {
+            let mut config = Config::new();
+            config.host = "127.0.0.1".to_string();
+            config.port = 0;
+            config.snapshot_path = std::env::temp_dir()
+                .join(format!("connection_{}_{}.snapshot", name, std::process::id()))
+                .to_string_lossy()
+                .into_owned();
+            configure(&mut config);
+            let snapshot_path = config.snapshot_path.clone();
+            let handle = Server::with_storage(config, storage).start().unwrap();
+            TestServer { handle: Some(handle), snapshot_path }
+        }
+
+        fn connect(&self) -> TcpStream {
+            TcpStream::connect(self.handle.as_ref().unwrap().local_addr()).unwrap()
+        }
+    }
+
+    impl Drop for TestServer {
+        fn drop(&mut self) {
+            if let Some(handle) = self.handle.take() {
+                let _ = handle.shutdown();
+            }
+            let _ = std::fs::remove_file(&self.snapshot_path);
+        }
+    }
+
+    // Starts a default server and connects one client to it
+    fn setup_connection(name: &str) -> (TestServer, TcpStream) {
+        let server = TestServer::start(name, |_| {});
+        let client = server.connect();
+        (server, client)
+    }
+
+    // Reads one reply: a single line, or a `*<count>` header and that many lines
+    fn read_reply(reader: &mut BufReader<TcpStream>) -> Vec<String> {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let first = line.trim_end_matches("\r\n").to_string();
+        let Some(count) = first.strip_prefix('*').and_then(|count| count.parse().ok()) else {
+            return vec![first];
+        };
+        (0..count)
+            .map(|_| {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                line.trim_end_matches("\r\n").to_string()
+            })
+            .collect()
     }
 
     #[test]
     fn test_basic_command() {
-        let (mut connection, mut client) = setup_connection();
-        
-        // Spawn a thread to handle the connection
-        let handle = thread::spawn(move || {
-            connection.process().unwrap();
-        });
+        let (_server, mut client) = setup_connection("basic_command");
 
         // Send a SET command
         writeln!(client, "SET key value").unwrap();
@@ -47,16 +94,13 @@ mod tests {
         
         // Close connection
         drop(reader);
-        handle.join().unwrap();
     }
 
     #[test]
     fn test_nested_transactions() {
-        let (mut connection, client) = setup_connection();
-        
-        let handle = thread::spawn(move || {
-            connection.process().unwrap();
-        });
+        let storage = Arc::new(Mutex::new(MemoryStorage::new()));
+        let server = TestServer::with_storage("nested_transactions", Arc::clone(&storage), |_| {});
+        let client = server.connect();
 
         let mut reader = BufReader::new(client);
         let mut response = String::new();
@@ -78,30 +122,29 @@ mod tests {
         reader.read_line(&mut response).unwrap();
         assert_eq!(response.trim(), "QUEUED");
 
-        // Execute inner transaction
+        // Execute inner transaction: its command joins the outer one
         writeln!(reader.get_ref(), "EXEC").unwrap();
         response.clear();
         reader.read_line(&mut response).unwrap();
-        assert!(response.trim().contains("OK"));
+        assert_eq!(response.trim(), "QUEUED");
+        assert_eq!(storage.lock().unwrap().get("inner"), None);
 
         // Execute outer transaction
         writeln!(reader.get_ref(), "EXEC").unwrap();
         response.clear();
         reader.read_line(&mut response).unwrap();
         assert!(response.trim().contains("OK"));
+        assert_eq!(storage.lock().unwrap().get("inner"), Some("value".to_string()));
+        // The SET was the only write: nothing else was stored for the inner EXEC
+        assert_eq!(storage.lock().unwrap().dirty_count(), 1);
 
         // Close connection
         drop(reader);
-        handle.join().unwrap();
     }
 
     #[test]
     fn test_transaction_discard() {
-        let (mut connection, client) = setup_connection();
-        
-        let handle = thread::spawn(move || {
-            connection.process().unwrap();
-        });
+        let (_server, client) = setup_connection("transaction_discard");
 
         let mut reader = BufReader::new(client);
         let mut response = String::new();
@@ -131,16 +174,11 @@ mod tests {
 
         // Close connection
         drop(reader);
-        handle.join().unwrap();
     }
 
     #[test]
     fn test_invalid_transaction_commands() {
-        let (mut connection, client) = setup_connection();
-        
-        let handle = thread::spawn(move || {
-            connection.process().unwrap();
-        });
+        let (_server, client) = setup_connection("invalid_transaction_commands");
 
         let mut reader = BufReader::new(client);
         let mut response = String::new();
@@ -158,6 +196,423 @@ mod tests {
 
         // Close connection
         drop(reader);
+    }
+
+    #[test]
+    fn test_rate_limit_delays_excess_commands() {
+        let server = TestServer::start("rate_limit_delay", |config| config.max_commands_per_second = 5);
+        let client = server.connect();
+
+        let mut reader = BufReader::new(client);
+        let mut response = String::new();
+        let start = Instant::now();
+
+        // The first five commands use up the burst, the next five wait a
+        // fifth of a second each for a token
+        for i in 0..10 {
+            writeln!(reader.get_ref(), "SET key{} value", i).unwrap();
+            response.clear();
+            reader.read_line(&mut response).unwrap();
+            assert_eq!(response.trim(), "OK");
+        }
+        assert!(start.elapsed() >= Duration::from_millis(900));
+
+        // Close connection
+        drop(reader);
+    }
+
+    #[test]
+    fn test_pipelined_commands() {
+        let (_server, client) = setup_connection("pipelined_commands");
+
+        let mut reader = BufReader::new(client);
+        let mut response = String::new();
+
+        // Send the whole pipeline in one write; every response must still arrive in order
+        reader.get_ref().write_all(b"SET key value\nGET key\nINCR counter\nINCR counter\n").unwrap();
+        for expected in ["OK", "value", "1", "2"] {
+            response.clear();
+            reader.read_line(&mut response).unwrap();
+            assert_eq!(response.trim(), expected);
+        }
+
+        // Close connection
+        drop(reader);
+    }
+
+    #[test]
+    fn test_client_name_commands() {
+        let (_server, client) = setup_connection("client_name_commands");
+
+        let mut reader = BufReader::new(client);
+        let mut response = String::new();
+        let mut send = |command: &str| {
+            writeln!(reader.get_ref(), "{}", command).unwrap();
+            response.clear();
+            reader.read_line(&mut response).unwrap();
+            response.trim().to_string()
+        };
+
+        assert_eq!(send("CLIENT ID"), "1");
+        assert_eq!(send("CLIENT GETNAME"), "(nil)");
+        assert_eq!(send("CLIENT SETNAME worker-1"), "OK");
+        assert_eq!(send("CLIENT GETNAME"), "worker-1");
+        assert!(send("CLIENT SETNAME bad name").starts_with("ERR"));
+        assert_eq!(send("CLIENT GETNAME"), "worker-1");
+        let list = send("CLIENT LIST");
+        assert!(list.starts_with("id=1 addr="));
+        assert!(list.ends_with("name=worker-1"));
+
+        // Close connection
+        drop(reader);
+    }
+
+    #[test]
+    fn test_multi_line_replies_do_not_leak_into_the_next_reply() {
+        let (_server, client) = setup_connection("multi_line_replies");
+
+        let mut reader = BufReader::new(client);
+        let mut send = |command: &str| {
+            writeln!(reader.get_ref(), "{}", command).unwrap();
+            read_reply(&mut reader)
+        };
+
+        assert_eq!(send("MULTI"), vec!["OK"]);
+        assert_eq!(send("SET key value"), vec!["QUEUED"]);
+        assert_eq!(send("INCR counter"), vec!["QUEUED"]);
+        assert_eq!(send("RPUSH list item"), vec!["QUEUED"]);
+        assert_eq!(send("EXEC"), vec!["OK", "1", "1"]);
+        assert_eq!(send("GET key"), vec!["value"]);
+
+        assert_eq!(send("SADD set a b"), vec!["2"]);
+        assert_eq!(send("SMEMBERS set"), vec!["a", "b"]);
+        assert_eq!(send("SMEMBERS missing"), vec!["(empty list or set)"]);
+
+        // A value that looks like a header is framed so it can't be mistaken for one
+        assert_eq!(send("SET star *2"), vec!["OK"]);
+        assert_eq!(send("GET star"), vec!["*2"]);
+        assert_eq!(send("GET key"), vec!["value"]);
+
+        // Close connection
+        drop(reader);
+    }
+
+    #[test]
+    fn test_geosearch_with_options_is_sent_as_nested_resp() {
+        let (_server, client) = setup_connection("geosearch_resp");
+
+        let mut reader = BufReader::new(client);
+        writeln!(reader.get_ref(), "GEOADD sicily 13.361389 38.115556 Palermo").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["1"]);
+
+        writeln!(reader.get_ref(), "GEOSEARCH sicily FROMMEMBER Palermo BYRADIUS 10 m WITHDIST").unwrap();
+        let mut reply = String::new();
+        for _ in 0..6 {
+            reader.read_line(&mut reply).unwrap();
+        }
+        assert_eq!(reply, "*1\r\n*2\r\n$7\r\nPalermo\r\n$6\r\n0.0000\r\n");
+
+        // Nothing else followed the reply
+        writeln!(reader.get_ref(), "PING").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["PONG"]);
+    }
+
+    #[test]
+    fn test_xrange_is_sent_as_nested_resp() {
+        let (_server, client) = setup_connection("xrange_resp");
+
+        let mut reader = BufReader::new(client);
+        writeln!(reader.get_ref(), "XADD events 1-0 kind click").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["1-0"]);
+
+        writeln!(reader.get_ref(), "XRANGE events - +").unwrap();
+        let mut reply = String::new();
+        for _ in 0..9 {
+            reader.read_line(&mut reply).unwrap();
+        }
+        assert_eq!(reply, "*1\r\n*2\r\n$3\r\n1-0\r\n*2\r\n$4\r\nkind\r\n$5\r\nclick\r\n");
+
+        writeln!(reader.get_ref(), "PING").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["PONG"]);
+    }
+
+    #[test]
+    fn test_quit_closes_the_connection_after_ok() {
+        let (_server, client) = setup_connection("quit");
+
+        let mut reader = BufReader::new(client);
+        writeln!(reader.get_ref(), "QUIT").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["OK"]);
+
+        // The server hangs up on its own: the next read hits end of stream
+        let mut line = String::new();
+        assert_eq!(reader.read_line(&mut line).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_quit_inside_multi_discards_the_transaction() {
+        let storage = Arc::new(Mutex::new(MemoryStorage::new()));
+        let server = TestServer::with_storage("quit_inside_multi", Arc::clone(&storage), |_| {});
+        let client = server.connect();
+
+        let mut reader = BufReader::new(client);
+        writeln!(reader.get_ref(), "MULTI").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["OK"]);
+        writeln!(reader.get_ref(), "SET key value").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["QUEUED"]);
+
+        // QUIT is not queued: it answers and hangs up straight away
+        writeln!(reader.get_ref(), "QUIT").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["OK"]);
+        let mut line = String::new();
+        assert_eq!(reader.read_line(&mut line).unwrap(), 0);
+
+        let mut storage = storage.lock().unwrap();
+        assert_eq!(storage.get("key"), None);
+        // MULTI left no storage-level transaction open
+        assert!(storage.rollback_transaction().is_err());
+    }
+
+    // Waits for a background save or rewrite to write `path`
+    fn wait_for_file(path: &str) -> String {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !std::path::Path::new(path).exists() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_writes_after_exec_are_saved_and_rewritten() {
+        let aof_path = std::env::temp_dir()
+            .join(format!("connection_exec_persisted_{}.aof", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let _ = std::fs::remove_file(&aof_path);
+        let server = TestServer::start("exec_persisted", |config| config.appendfilename = aof_path.clone());
+        let _ = std::fs::remove_file(&server.snapshot_path);
+        let client = server.connect();
+
+        let mut reader = BufReader::new(client);
+        let mut send = |command: &str| {
+            writeln!(reader.get_ref(), "{}", command).unwrap();
+            read_reply(&mut reader)
+        };
+        assert_eq!(send("MULTI"), vec!["OK"]);
+        assert_eq!(send("SET a 1"), vec!["QUEUED"]);
+        assert_eq!(send("EXEC"), vec!["OK"]);
+        assert_eq!(send("SET b 2"), vec!["OK"]);
+        assert_eq!(send("BGSAVE"), vec!["Background saving started"]);
+        assert_eq!(send("BGREWRITEAOF"), vec!["Background append only file rewriting started"]);
+
+        wait_for_file(&server.snapshot_path);
+        let mut reloaded = MemoryStorage::new();
+        reloaded.load_snapshot(&server.snapshot_path).unwrap();
+        assert_eq!(reloaded.get("a"), Some("1".to_string()));
+        assert_eq!(reloaded.get("b"), Some("2".to_string()));
+
+        let aof = wait_for_file(&aof_path);
+        let _ = std::fs::remove_file(&aof_path);
+        // Each SET is a `*3` header and a `$<len>` line before each argument
+        let lines: Vec<&str> = aof.split("\r\n").filter(|line| !line.is_empty()).collect();
+        let mut commands: Vec<String> = lines
+            .chunks(7)
+            .map(|command| command.iter().skip(2).step_by(2).copied().collect::<Vec<_>>().join(" "))
+            .collect();
+        commands.sort();
+        assert_eq!(commands, vec!["SET a 1", "SET b 2"]);
+        let replayed = CommandExecutor::new(Arc::new(Mutex::new(MemoryStorage::new())));
+        for command in &commands {
+            replayed.execute_command(CommandParser::parse(command));
+        }
+        assert_eq!(replayed.execute_command(CommandParser::parse("GET a")), "1");
+        assert_eq!(replayed.execute_command(CommandParser::parse("GET b")), "2");
+    }
+
+    // Peak resident memory of this test process, where /proc reports it
+    fn peak_rss_kb() -> Option<u64> {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+        line.split_whitespace().nth(1)?.parse().ok()
+    }
+
+    #[test]
+    fn test_too_big_inline_request_is_refused_with_bounded_memory() {
+        let (_server, client) = setup_connection("too_big_inline");
+        let before = peak_rss_kb();
+
+        // 100MB without a newline; the server hangs up long before the end
+        let mut writer = client.try_clone().unwrap();
+        let sender = thread::spawn(move || {
+            let chunk = vec![b'a'; 1024 * 1024];
+            for _ in 0..100 {
+                if writer.write_all(&chunk).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut reader = BufReader::new(client);
+        assert_eq!(read_reply(&mut reader), vec!["ERR Protocol error: too big inline request"]);
+        sender.join().unwrap();
+
+        if let (Some(before), Some(after)) = (before, peak_rss_kb()) {
+            assert!(after - before < 32 * 1024, "peak memory grew by {}KB", after - before);
+        }
+    }
+
+    #[test]
+    fn test_inline_request_limit_is_configurable() {
+        let server = TestServer::start("inline_limit", |config| config.proto_max_inline_size = 16);
+        let client = server.connect();
+
+        let mut reader = BufReader::new(client);
+        writeln!(reader.get_ref(), "SET key 12345678").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["OK"]);
+        writeln!(reader.get_ref(), "SET key 123456789").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["ERR Protocol error: too big inline request"]);
+        let mut line = String::new();
+        assert_eq!(reader.read_line(&mut line).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_blank_lines_bare_newlines_and_nul_bytes() {
+        let (_server, client) = setup_connection("blank_lines");
+
+        let mut reader = BufReader::new(client);
+        // Blank lines get no reply at all, whatever their line ending
+        reader.get_ref().write_all(b"\r\n\n   \nSET key value\nGET key\r\n").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["OK"]);
+        assert_eq!(read_reply(&mut reader), vec!["value"]);
+
+        reader.get_ref().write_all(b"GET k\0ey\n").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["ERR Protocol error: unexpected NUL in inline request"]);
+        writeln!(reader.get_ref(), "GET key").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["value"]);
+    }
+
+    #[test]
+    fn test_wait_tracks_each_clients_last_write() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let executor = Arc::new(CommandExecutor::new(Arc::new(Mutex::new(MemoryStorage::new()))));
+        let replication = executor.replication();
+        replication.ack("replica1", 3);
+
+        let mut clients = Vec::new();
+        for _ in 0..2 {
+            let client = TcpStream::connect(addr).unwrap();
+            let (server, _) = listener.accept().unwrap();
+            let mut connection = Connection::new(server, Arc::clone(&executor));
+            thread::spawn(move || connection.process());
+            clients.push(client);
+        }
+        let mut writer = BufReader::new(clients.pop().unwrap());
+        let mut reader = BufReader::new(clients.pop().unwrap());
+
+        // The write stream is past what the replica has, but only the writer cares
+        replication.record_write(5);
+        writeln!(writer.get_mut(), "SET key value").unwrap();
+        assert_eq!(read_reply(&mut writer), vec!["OK"]);
+        writeln!(reader.get_mut(), "GET key").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["value"]);
+        writeln!(reader.get_mut(), "WAIT 1 0").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["1"]);
+
+        writeln!(writer.get_mut(), "WAIT 1 100").unwrap();
+        assert_eq!(read_reply(&mut writer), vec!["0"]);
+
+        let acker = Arc::clone(&replication);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            acker.ack("replica1", 5);
+        });
+        let start = Instant::now();
+        writeln!(writer.get_mut(), "WAIT 1 5000").unwrap();
+        assert_eq!(read_reply(&mut writer), vec!["1"]);
+        assert!(start.elapsed() < Duration::from_secs(5));
         handle.join().unwrap();
     }
+
+    #[test]
+    fn test_rate_limit_throttles_one_client_but_not_another() {
+        let server = TestServer::start("rate_limit_per_client", |config| config.max_commands_per_second = 20);
+        let mut busy = BufReader::new(server.connect());
+        let mut quiet = BufReader::new(server.connect());
+
+        // A pipelined burst of 60: 20 go through at once, the rest at 20 per second
+        let flooder = thread::spawn(move || {
+            let start = Instant::now();
+            let burst: String = (0..60).map(|i| format!("SET key{} value\n", i)).collect();
+            busy.get_mut().write_all(burst.as_bytes()).unwrap();
+            for _ in 0..60 {
+                assert_eq!(read_reply(&mut busy), vec!["OK"]);
+            }
+            let elapsed = start.elapsed();
+            writeln!(busy.get_mut(), "CLIENT LIST").unwrap();
+            (elapsed, read_reply(&mut busy))
+        });
+
+        thread::sleep(Duration::from_millis(200));
+        let start = Instant::now();
+        for _ in 0..10 {
+            writeln!(quiet.get_mut(), "GET key0").unwrap();
+            assert_eq!(read_reply(&mut quiet), vec!["value"]);
+        }
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        let (elapsed, list) = flooder.join().unwrap();
+        assert!(elapsed >= Duration::from_millis(1900));
+        let rate: u64 = list[0].split("cmd_rate=").nth(1).unwrap().split(' ').next().unwrap().parse().unwrap();
+        assert!((10..=30).contains(&rate), "cmd_rate={}", rate);
+    }
+
+    #[test]
+    fn test_rate_limit_can_reject_instead_of_delaying() {
+        let server = TestServer::start("rate_limit_reject", |config| {
+            config.max_commands_per_second = 2;
+            config.rate_limit_burst = 3;
+            config.rate_limit_mode = "reject".to_string();
+        });
+        let client = server.connect();
+
+        let mut reader = BufReader::new(client);
+        let replies: Vec<String> = (0..4)
+            .map(|i| {
+                writeln!(reader.get_ref(), "SET key{} value", i).unwrap();
+                read_reply(&mut reader).remove(0)
+            })
+            .collect();
+        assert_eq!(replies, vec!["OK", "OK", "OK", "ERR rate limit exceeded"]);
+
+        thread::sleep(Duration::from_millis(600));
+        writeln!(reader.get_ref(), "GET key3").unwrap();
+        assert_eq!(read_reply(&mut reader), vec!["(nil)"]);
+    }
+
+    #[test]
+    fn test_commands_and_traffic_are_counted() {
+        let (server, client) = setup_connection("traffic");
+
+        let mut reader = BufReader::new(client);
+        let mut response = String::new();
+        let input = b"SET key value\nGET key\nBOGUS\n";
+        reader.get_ref().write_all(input).unwrap();
+        for _ in 0..3 {
+            reader.read_line(&mut response).unwrap();
+        }
+        // Replies are counted once written, so wait for the server to hang up
+        reader.get_ref().shutdown(Shutdown::Write).unwrap();
+        assert_eq!(reader.read_line(&mut String::new()).unwrap(), 0);
+        drop(reader);
+
+        // The INFO request itself counts as a command and as input
+        let mut observer = BufReader::new(server.connect());
+        let request = b"INFO stats\n";
+        observer.get_ref().write_all(request).unwrap();
+        let info = read_reply(&mut observer).join("\n") + "\n";
+        assert!(info.contains("total_commands_processed:4\n"), "{}", info);
+        assert!(info.contains(&format!("total_net_input_bytes:{}\n", input.len() + request.len())), "{}", info);
+        assert!(info.contains(&format!("total_net_output_bytes:{}\n", response.len())), "{}", info);
+    }
 }
\ No newline at end of file
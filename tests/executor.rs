@@ -1,8 +1,12 @@
 use redis_imitate::storage::memory::MemoryStorage;
 use redis_imitate::commands::executor::CommandExecutor;
-use redis_imitate::commands::parser::Command;
+use redis_imitate::commands::parser::{Command, CommandParser, SortOrder};
+use redis_imitate::config::config::Config;
+use redis_imitate::network::resp::RespValue;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
 #[cfg(test)]
 mod tests {
@@ -42,6 +46,20 @@ mod tests {
         assert_eq!(executor.execute_command(Command::Decr("counter".to_string())), "0".to_string());
     }
 
+    #[test]
+    fn test_incr_overflow_is_an_error() {
+        let executor = setup();
+
+        executor.execute_command(Command::Set("n".to_string(), i64::MAX.to_string()));
+        assert_eq!(
+            executor.execute_command(Command::Incr("n".to_string())),
+            "ERR increment or decrement would overflow".to_string()
+        );
+        // The storage lock wasn't poisoned by a panic
+        assert_eq!(executor.execute_command(Command::Get("n".to_string())), i64::MAX.to_string());
+        assert_eq!(executor.execute_command(Command::Decr("n".to_string())), (i64::MAX - 1).to_string());
+    }
+
     #[test]
     fn test_list_operations() {
         let executor = setup();
@@ -70,4 +88,1097 @@ mod tests {
         assert_eq!(executor.execute_command(Command::Discard), "OK".to_string());
         assert_eq!(executor.execute_command(Command::Get("key1".to_string())), "(nil)".to_string());
     }
+
+    #[test]
+    fn test_expired_key_is_gone_for_every_command() {
+        let executor = setup();
+
+        executor.execute_command(Command::Set("temp".to_string(), "5".to_string()));
+        executor.execute_command(Command::RPush("templist".to_string(), "item".to_string()));
+        assert_eq!(executor.execute_command(Command::Expire("temp".to_string(), 1)), "1".to_string());
+        assert_eq!(executor.execute_command(Command::Expire("templist".to_string(), 1)), "1".to_string());
+        assert_eq!(executor.execute_command(Command::Ttl("temp".to_string())), "1".to_string());
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+
+        assert_eq!(executor.execute_command(Command::Get("temp".to_string())), "(nil)".to_string());
+        assert_eq!(executor.execute_command(Command::LLen("templist".to_string())), "0".to_string());
+
+        for key in ["temp", "templist"] {
+            executor.execute_command(Command::Set(key.to_string(), "5".to_string()));
+            executor.execute_command(Command::Expire(key.to_string(), 0));
+        }
+        assert_eq!(executor.execute_command(Command::Exists("temp".to_string())), "0".to_string());
+        assert_eq!(executor.execute_command(Command::Type("templist".to_string())), "none".to_string());
+
+        executor.execute_command(Command::Set("counter".to_string(), "41".to_string()));
+        executor.execute_command(Command::Expire("counter".to_string(), 0));
+        assert_eq!(executor.execute_command(Command::Incr("counter".to_string())), "1".to_string());
+        assert_eq!(executor.execute_command(Command::Ttl("counter".to_string())), "-1".to_string());
+    }
+
+    #[test]
+    fn test_expire_and_ttl() {
+        let executor = setup();
+
+        assert_eq!(executor.execute_command(Command::Expire("missing".to_string(), 10)), "0".to_string());
+        assert_eq!(executor.execute_command(Command::Ttl("missing".to_string())), "-2".to_string());
+
+        executor.execute_command(Command::Set("key".to_string(), "value".to_string()));
+        assert_eq!(executor.execute_command(Command::Ttl("key".to_string())), "-1".to_string());
+        assert_eq!(executor.execute_command(Command::Type("key".to_string())), "string".to_string());
+        executor.execute_command(Command::Expire("key".to_string(), 100));
+        assert_eq!(executor.execute_command(Command::Ttl("key".to_string())), "100".to_string());
+
+        executor.execute_command(Command::Set("key".to_string(), "other".to_string()));
+        assert_eq!(executor.execute_command(Command::Ttl("key".to_string())), "-1".to_string());
+    }
+
+    #[test]
+    fn test_expire_out_of_range_is_rejected() {
+        let executor = setup();
+        executor.execute_command(Command::Set("key".to_string(), "value".to_string()));
+
+        for input in ["EXPIRE key 9300000000000000000", "EXPIRE key 18446744073709551615 NX"] {
+            let reply = executor.execute_command(CommandParser::parse(input));
+            assert_eq!(reply, "ERR invalid expire time in 'expire' command", "{}", input);
+        }
+        assert_eq!(executor.execute_command(Command::Ttl("key".to_string())), "-1".to_string());
+
+        // The storage lock wasn't poisoned
+        assert_eq!(executor.execute_command(Command::Set("other".to_string(), "1".to_string())), "OK".to_string());
+        assert_eq!(executor.execute_command(Command::Get("key".to_string())), "value".to_string());
+    }
+
+    #[test]
+    fn test_info_commandstats() {
+        let executor = setup();
+
+        executor.execute_command(Command::Set("key".to_string(), "value".to_string()));
+        executor.execute_command(Command::Get("key".to_string()));
+        executor.execute_command(Command::Get("key".to_string()));
+
+        let info = executor.execute_command(Command::Info(Some("commandstats".to_string())));
+        let lines: Vec<&str> = info.lines().collect();
+        assert_eq!(lines[0], "# Commandstats");
+        assert!(lines[1].starts_with("cmdstat_get:calls=2,usec="));
+        assert!(lines[1].contains(",usec_per_call="));
+        assert!(lines[2].starts_with("cmdstat_set:calls=1,usec="));
+        assert_eq!(lines.len(), 3);
+
+        assert_eq!(executor.execute_command(Command::ConfigResetStat), "OK".to_string());
+        // The INFO call made above is gone too; only the RESETSTAT itself is counted
+        let info = executor.execute_command(Command::Info(Some("commandstats".to_string())));
+        let lines: Vec<&str> = info.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("cmdstat_config:calls=1,"));
+    }
+
+    #[test]
+    fn test_info_sections() {
+        let executor = setup();
+        executor.execute_command(Command::Set("key".to_string(), "value".to_string()));
+
+        let info = executor.execute_command(Command::Info(None));
+        for section in ["# Clients", "# Memory", "# Persistence", "# Stats", "# Replication"] {
+            assert!(info.lines().any(|line| line == section), "{} missing", section);
+        }
+        assert!(!info.contains("# Commandstats"));
+        assert!(!info.contains("cmdstat_set"));
+
+        let all = executor.execute_command(Command::Info(Some("all".to_string())));
+        assert!(all.contains("# Replication"));
+        assert!(all.lines().any(|line| line.starts_with("cmdstat_set:calls=1,")));
+    }
+
+    #[test]
+    fn test_cluster_commands_without_cluster_mode() {
+        let executor = setup();
+        assert_eq!(
+            executor.execute_command(Command::ClusterMeet("127.0.0.1".to_string(), 7001)),
+            "ERR This instance has cluster support disabled".to_string()
+        );
+    }
+
+    fn sort(key: &str) -> Command {
+        Command::Sort {
+            key: key.to_string(),
+            by: None,
+            limit: None,
+            get_patterns: Vec::new(),
+            order: SortOrder::Asc,
+            alpha: false,
+            store: None,
+        }
+    }
+
+    #[test]
+    fn test_sort() {
+        let executor = setup();
+        for item in ["3", "10", "1", "2"] {
+            executor.execute_command(Command::RPush("nums".to_string(), item.to_string()));
+        }
+
+        assert_eq!(executor.execute_command(sort("nums")), "1\n2\n3\n10".to_string());
+        // Alphabetically "10" sorts before "2"
+        assert_eq!(
+            executor.execute_command(Command::Sort {
+                key: "nums".to_string(),
+                by: None,
+                limit: Some((1, 2)),
+                get_patterns: Vec::new(),
+                order: SortOrder::Desc,
+                alpha: true,
+                store: None,
+            }),
+            "2\n10".to_string()
+        );
+
+        executor.execute_command(Command::RPush("words".to_string(), "b".to_string()));
+        assert!(executor.execute_command(sort("words")).starts_with("ERR"));
+        executor.execute_command(Command::Set("str".to_string(), "x".to_string()));
+        assert!(executor.execute_command(sort("str")).starts_with("WRONGTYPE"));
+        assert_eq!(executor.execute_command(sort("missing")), "(empty list or set)".to_string());
+    }
+
+    #[test]
+    fn test_sort_by_get_and_store() {
+        let executor = setup();
+        for (id, weight) in [("a", "3"), ("b", "1"), ("c", "2")] {
+            executor.execute_command(Command::RPush("ids".to_string(), id.to_string()));
+            executor.execute_command(Command::Set(format!("weight_{}", id), weight.to_string()));
+            executor.execute_command(Command::Set(format!("name_{}", id), format!("name-{}", id)));
+        }
+
+        let by_weight = |store: Option<&str>| Command::Sort {
+            key: "ids".to_string(),
+            by: Some("weight_*".to_string()),
+            limit: None,
+            get_patterns: vec!["#".to_string(), "name_*".to_string(), "missing_*".to_string()],
+            order: SortOrder::Asc,
+            alpha: false,
+            store: store.map(str::to_string),
+        };
+        assert_eq!(
+            executor.execute_command(by_weight(None)),
+            "b\nname-b\n(nil)\nc\nname-c\n(nil)\na\nname-a\n(nil)".to_string()
+        );
+
+        assert_eq!(executor.execute_command(by_weight(Some("sorted"))), "9".to_string());
+        assert_eq!(executor.execute_command(Command::LLen("sorted".to_string())), "9".to_string());
+        assert_eq!(executor.execute_command(Command::LPop("sorted".to_string())), "b".to_string());
+    }
+
+    #[test]
+    fn test_wait_counts_acknowledged_replicas() {
+        let executor = setup();
+        assert_eq!(executor.execute_command(Command::Wait(0, 0)), "0".to_string());
+
+        let replication = executor.replication();
+        replication.record_write(3);
+        replication.ack("replica1", 3);
+        replication.ack("replica2", 2);
+        assert_eq!(executor.execute_command(Command::Wait(1, 0)), "1".to_string());
+        // Only one replica is caught up, so this waits out the timeout
+        assert_eq!(executor.execute_command(Command::Wait(2, 50)), "1".to_string());
+    }
+
+    #[test]
+    fn test_wait_in_a_transaction_does_not_block() {
+        let executor = setup();
+        let replication = executor.replication();
+        replication.record_write(3);
+        replication.ack("replica1", 3);
+        replication.ack("replica2", 2);
+
+        // A timeout of 0 would wait forever outside a transaction
+        let started = Instant::now();
+        let results = executor.execute_transaction(&[
+            Command::Wait(2, 0),
+            Command::Set("key".to_string(), "value".to_string()),
+            Command::Wait(1, 0),
+        ]);
+        assert_eq!(results, vec!["1".to_string(), "OK".to_string(), "1".to_string()]);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_lolwut() {
+        let executor = setup();
+        assert!(executor.execute_command(Command::Lolwut(None)).contains("Redis Ver."));
+
+        let art = executor.execute_command(Command::Lolwut(Some(5)));
+        assert!(art.ends_with("\nRedis Ver. 7.0.0\n"));
+        assert!(art.contains('#'));
+        assert!(art.lines().all(|line| line.len() <= 66));
+    }
+
+    #[test]
+    fn test_object_encoding() {
+        let executor = setup();
+        let encoding = |key: &str| executor.execute_command(Command::ObjectEncoding(key.to_string()));
+        assert_eq!(encoding("missing"), "(nil)".to_string());
+
+        executor.execute_command(Command::Set("counter".to_string(), "42".to_string()));
+        executor.execute_command(Command::Set("short".to_string(), "hello".to_string()));
+        executor.execute_command(Command::Set("long".to_string(), "x".repeat(45)));
+        assert_eq!(encoding("counter"), "int".to_string());
+        assert_eq!(encoding("short"), "embstr".to_string());
+        assert_eq!(encoding("long"), "raw".to_string());
+
+        for i in 0..127 {
+            executor.execute_command(Command::RPush("list".to_string(), i.to_string()));
+        }
+        assert_eq!(encoding("list"), "listpack".to_string());
+        // list_max_listpack_size is inclusive: 128 elements still fit
+        executor.execute_command(Command::RPush("list".to_string(), "127".to_string()));
+        assert_eq!(encoding("list"), "listpack".to_string());
+        executor.execute_command(Command::RPush("list".to_string(), "128".to_string()));
+        assert_eq!(encoding("list"), "quicklist".to_string());
+
+        executor.execute_command(Command::RPush("wide".to_string(), "x".repeat(65)));
+        assert_eq!(encoding("wide"), "quicklist".to_string());
+
+        let help = executor.execute_command(Command::ObjectHelp);
+        let lines: Vec<&str> = help.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "ENCODING <key> -- Return the encoding of the object stored at <key>");
+        assert!(lines[2].starts_with("HELP -- "));
+    }
+
+    #[test]
+    fn test_echo() {
+        let executor = setup();
+        let echo = CommandParser::parse("ECHO \"hello world\"");
+        assert_eq!(executor.execute_command(echo), "hello world".to_string());
+    }
+
+    // Spins until `count` clients are parked in BLPOP/BRPOP
+    fn wait_for_blocked(executor: &CommandExecutor, count: usize) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while executor.blocked_clients() != count {
+            assert!(Instant::now() < deadline, "clients never blocked");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_blpop_returns_immediately_when_a_list_has_elements() {
+        let executor = setup();
+        executor.execute_command(Command::RPush("second".to_string(), "a".to_string()));
+        executor.execute_command(Command::RPush("second".to_string(), "b".to_string()));
+        let keys = vec!["first".to_string(), "second".to_string()];
+        assert_eq!(executor.execute_command(Command::BLPop(keys.clone(), 0.0)), "second\na".to_string());
+        assert_eq!(executor.execute_command(Command::BRPop(keys, 0.0)), "second\nb".to_string());
+    }
+
+    #[test]
+    fn test_blpop_times_out_with_nil() {
+        let executor = setup();
+        let started = Instant::now();
+        let response = executor.execute_command(Command::BLPop(vec!["empty".to_string()], 0.1));
+        assert_eq!(response, "(nil)".to_string());
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(executor.blocked_clients(), 0);
+    }
+
+    #[test]
+    fn test_blocking_timeout_out_of_range() {
+        let executor = Arc::new(setup());
+        for input in ["BLPOP k 1e300", "BRPOP k 1e300", "BLMOVE k d LEFT RIGHT 1e300", "BZPOPMIN k 1e300", "BZPOPMAX k 1e300"] {
+            let reply = executor.execute_command(CommandParser::parse(input));
+            assert_eq!(reply, "ERR timeout is out of range", "{}", input);
+        }
+
+        // Too far away for an Instant, so it waits until a push
+        let waiter = {
+            let executor = Arc::clone(&executor);
+            thread::spawn(move || executor.execute_command(CommandParser::parse("BLPOP k 1e19")))
+        };
+        wait_for_blocked(&executor, 1);
+        executor.execute_command(Command::RPush("k".to_string(), "v".to_string()));
+        assert_eq!(waiter.join().unwrap(), "k\nv".to_string());
+        assert_eq!(executor.execute_command(Command::Get("k".to_string())), "(nil)".to_string());
+    }
+
+    #[test]
+    fn test_blocked_consumers_are_served_in_order() {
+        let executor = Arc::new(setup());
+        let consumer = |executor: &Arc<CommandExecutor>| {
+            let executor = Arc::clone(executor);
+            thread::spawn(move || executor.execute_command(Command::BLPop(vec!["jobs".to_string()], 0.0)))
+        };
+
+        let first = consumer(&executor);
+        wait_for_blocked(&executor, 1);
+        let second = consumer(&executor);
+        wait_for_blocked(&executor, 2);
+
+        executor.execute_command(Command::RPush("jobs".to_string(), "job1".to_string()));
+        assert_eq!(first.join().unwrap(), "jobs\njob1".to_string());
+        wait_for_blocked(&executor, 1);
+        executor.execute_command(Command::RPush("jobs".to_string(), "job2".to_string()));
+        assert_eq!(second.join().unwrap(), "jobs\njob2".to_string());
+        assert_eq!(executor.execute_command(Command::LLen("jobs".to_string())), "0".to_string());
+    }
+
+    #[test]
+    fn test_blocked_consumer_reparks_when_its_element_is_taken() {
+        let executor = Arc::new(setup());
+        let consumer = {
+            let executor = Arc::clone(&executor);
+            thread::spawn(move || executor.execute_command(Command::BRPop(vec!["jobs".to_string()], 0.0)))
+        };
+        wait_for_blocked(&executor, 1);
+
+        // The push wakes the consumer, but LPOP takes the element first
+        let results = executor.execute_transaction(&[
+            Command::LPush("jobs".to_string(), "stolen".to_string()),
+            Command::LPop("jobs".to_string()),
+        ]);
+        assert_eq!(results[1], "stolen".to_string());
+        thread::sleep(Duration::from_millis(50));
+        wait_for_blocked(&executor, 1);
+
+        executor.execute_command(Command::LPush("jobs".to_string(), "kept".to_string()));
+        assert_eq!(consumer.join().unwrap(), "jobs\nkept".to_string());
+    }
+
+    #[test]
+    fn test_blpop_inside_transaction_does_not_block() {
+        let executor = setup();
+        let results = executor.execute_transaction(&[Command::BLPop(vec!["empty".to_string()], 0.0)]);
+        assert_eq!(results, vec!["(nil)".to_string()]);
+    }
+
+    #[test]
+    fn test_release_blocked_clients() {
+        let executor = Arc::new(setup());
+        let consumer = {
+            let executor = Arc::clone(&executor);
+            thread::spawn(move || executor.execute_command(Command::BLPop(vec!["jobs".to_string()], 0.0)))
+        };
+        wait_for_blocked(&executor, 1);
+        executor.release_blocked_clients();
+        assert_eq!(consumer.join().unwrap(), "(nil)".to_string());
+    }
+
+    #[test]
+    fn test_smove() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        assert_eq!(run("SADD src a b"), "2".to_string());
+        assert_eq!(run("SMOVE src dst a"), "1".to_string());
+        assert_eq!(run("SMOVE src dst a"), "0".to_string());
+        assert_eq!(run("SMEMBERS dst"), "a".to_string());
+        assert_eq!(run("TYPE dst"), "set".to_string());
+        assert_eq!(run("SMOVE src dst b"), "1".to_string());
+        assert_eq!(run("EXISTS src"), "0".to_string());
+        assert_eq!(run("SMEMBERS src"), "(empty list or set)".to_string());
+        assert_eq!(run("SMEMBERS dst"), "a\nb".to_string());
+
+        run("SET plain value");
+        assert!(run("SMOVE dst plain a").starts_with("WRONGTYPE"));
+        assert_eq!(run("SORT dst ALPHA DESC"), "b\na".to_string());
+    }
+
+    #[test]
+    fn test_blmove_chains_queues_through_a_blocked_mover() {
+        let executor = Arc::new(setup());
+        let spawn = |executor: &Arc<CommandExecutor>, line: &'static str| {
+            let executor = Arc::clone(executor);
+            thread::spawn(move || executor.execute_command(CommandParser::parse(line)))
+        };
+
+        // The consumer waits on the second queue, the mover feeds it from the first
+        let consumer = spawn(&executor, "BLPOP done 0");
+        wait_for_blocked(&executor, 1);
+        let mover = spawn(&executor, "BLMOVE todo done RIGHT LEFT 0");
+        wait_for_blocked(&executor, 2);
+
+        executor.execute_command(Command::LPush("todo".to_string(), "job".to_string()));
+        assert_eq!(mover.join().unwrap(), "job".to_string());
+        assert_eq!(consumer.join().unwrap(), "done\njob".to_string());
+        assert_eq!(executor.execute_command(Command::LLen("todo".to_string())), "0".to_string());
+        assert_eq!(executor.execute_command(Command::LLen("done".to_string())), "0".to_string());
+    }
+
+    #[test]
+    fn test_blmove_without_blocking() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        run("RPUSH src a");
+        run("RPUSH src b");
+        assert_eq!(run("BRPOPLPUSH src dst 0"), "b".to_string());
+        assert_eq!(run("BLMOVE src dst LEFT RIGHT 0"), "a".to_string());
+        assert_eq!(run("SORT dst ALPHA"), "a\nb".to_string());
+        assert_eq!(run("BLMOVE src dst LEFT LEFT 0.05"), "(nil)".to_string());
+
+        run("SET plain value");
+        assert!(run("BLMOVE dst plain LEFT LEFT 0").starts_with("WRONGTYPE"));
+        assert!(run("BLMOVE plain dst LEFT LEFT 0").starts_with("WRONGTYPE"));
+
+        let results = executor.execute_transaction(&[CommandParser::parse("BRPOPLPUSH src dst 0")]);
+        assert_eq!(results, vec!["(nil)".to_string()]);
+    }
+
+    #[test]
+    fn test_set_store_commands() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        run("SADD a x y z");
+        run("SADD b y");
+        assert_eq!(run("SDIFFSTORE dst a b"), "2".to_string());
+        assert_eq!(run("SMEMBERS dst"), "x\nz".to_string());
+        assert_eq!(run("SUNIONSTORE dst a b"), "3".to_string());
+        assert_eq!(run("SINTERSTORE dst a b"), "1".to_string());
+        assert_eq!(run("SMEMBERS dst"), "y".to_string());
+        assert_eq!(run("SINTERSTORE dst a missing"), "0".to_string());
+        assert_eq!(run("EXISTS dst"), "0".to_string());
+    }
+
+    #[test]
+    fn test_sintercard() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        run("SADD a 1 2 3 4");
+        run("SADD b 2 3 4 5");
+        assert_eq!(run("SINTERCARD 2 a b"), "3".to_string());
+        assert_eq!(run("SINTERCARD 2 a b LIMIT 2"), "2".to_string());
+        assert_eq!(run("SINTERCARD 2 a b LIMIT 0"), "3".to_string());
+        assert_eq!(run("SINTERCARD 3 a b missing"), "0".to_string());
+    }
+
+    #[test]
+    fn test_zadd() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        assert_eq!(run("ZADD board 10 alice 20 bob"), "2".to_string());
+        assert_eq!(run("ZADD board CH 15 alice 30 carol"), "2".to_string());
+        assert_eq!(run("ZSCORE board alice"), "15".to_string());
+        assert_eq!(run("ZCARD board"), "3".to_string());
+        assert_eq!(run("TYPE board"), "zset".to_string());
+
+        assert_eq!(run("ZADD board INCR 2.5 alice"), "17.5".to_string());
+        assert_eq!(run("ZADD board INCR NX 1 alice"), "(nil)".to_string());
+        assert_eq!(run("ZSCORE board alice"), "17.5".to_string());
+        assert_eq!(run("ZADD board INCR XX 1 nobody"), "(nil)".to_string());
+        assert_eq!(run("ZSCORE board nobody"), "(nil)".to_string());
+        assert_eq!(run("ZADD board INCR NX 4 dave"), "4".to_string());
+        assert_eq!(run("ZADD board INCR XX 1 dave"), "5".to_string());
+
+        // XX against a missing key adds nothing and leaves no empty key behind
+        assert_eq!(run("ZADD empty XX 1 alice"), "0".to_string());
+        assert_eq!(run("EXISTS empty"), "0".to_string());
+
+        run("SET plain value");
+        assert!(run("ZADD plain 1 alice").starts_with("WRONGTYPE"));
+    }
+
+    #[test]
+    fn test_zincrby() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        assert_eq!(run("ZINCRBY board 2.5 alice"), "2.5".to_string());
+        assert_eq!(run("ZINCRBY board -1 alice"), "1.5".to_string());
+        assert_eq!(run("ZADD board INCR 1 alice"), "2.5".to_string());
+        assert_eq!(run("ZCARD board"), "1".to_string());
+
+        run("ZADD board +inf bob");
+        assert!(run("ZINCRBY board -inf bob").starts_with("ERR"));
+        assert_eq!(run("ZSCORE board bob"), "inf".to_string());
+
+        run("SET plain value");
+        assert!(run("ZINCRBY plain 1 alice").starts_with("WRONGTYPE"));
+    }
+
+    #[test]
+    fn test_zrangebyscore() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        run("ZADD board 1 alice 2 bob 2 carol 3.5 dave");
+        assert_eq!(run("ZRANGEBYSCORE board 2 +inf"), "bob\ncarol\ndave".to_string());
+        assert_eq!(run("ZRANGEBYSCORE board (1 3.5 WITHSCORES LIMIT 1 5"), "carol\n2\ndave\n3.5".to_string());
+        assert_eq!(run("ZREVRANGEBYSCORE board +inf -inf LIMIT 0 2"), "dave\ncarol".to_string());
+        assert_eq!(run("ZRANGEBYSCORE board (3.5 +inf"), "(empty list or set)".to_string());
+        assert_eq!(run("ZRANGEBYSCORE missing -inf +inf"), "(empty list or set)".to_string());
+    }
+
+    #[test]
+    fn test_zpopmin_and_zpopmax() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        run("ZADD board 1 alice 2 bob 3 carol");
+        assert_eq!(run("ZPOPMIN board"), "alice\n1".to_string());
+        assert_eq!(run("ZPOPMAX board 5"), "carol\n3\nbob\n2".to_string());
+        assert_eq!(run("ZPOPMIN board"), "(empty list or set)".to_string());
+        assert_eq!(run("EXISTS board"), "0".to_string());
+    }
+
+    #[test]
+    fn test_bzpopmin_waits_for_zadd() {
+        let executor = Arc::new(setup());
+        executor.execute_command(CommandParser::parse("ZADD ready 5 x 7 y"));
+        assert_eq!(executor.execute_command(CommandParser::parse("BZPOPMAX empty ready 0")), "ready\ny\n7".to_string());
+        assert_eq!(executor.execute_command(CommandParser::parse("BZPOPMIN empty 0.1")), "(nil)".to_string());
+
+        let waiter = {
+            let executor = Arc::clone(&executor);
+            thread::spawn(move || executor.execute_command(CommandParser::parse("BZPOPMIN empty 0")))
+        };
+        wait_for_blocked(&executor, 1);
+        executor.execute_command(CommandParser::parse("ZADD empty 2 b 1 a"));
+        assert_eq!(waiter.join().unwrap(), "empty\na\n1".to_string());
+        assert_eq!(executor.execute_command(CommandParser::parse("ZCARD empty")), "1".to_string());
+
+        // Inside a transaction it never blocks
+        let results = executor.execute_transaction(&[CommandParser::parse("BZPOPMIN nothing 0")]);
+        assert_eq!(results, vec!["(nil)".to_string()]);
+    }
+
+    #[test]
+    fn test_zdiff_zunion_zinter() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        run("ZADD a 1 x 2 y 3 z");
+        run("ZADD b 10 y 20 z 30 w");
+        assert_eq!(run("ZDIFF 2 a b WITHSCORES"), "x\n1".to_string());
+        assert_eq!(run("ZUNION 2 a b"), "x\ny\nz\nw".to_string());
+        assert_eq!(run("ZUNION 2 a b WEIGHTS 1 0 AGGREGATE MAX WITHSCORES"), "w\n0\nx\n1\ny\n2\nz\n3".to_string());
+        assert_eq!(run("ZINTER 2 a b WITHSCORES"), "y\n12\nz\n23".to_string());
+        assert_eq!(run("ZINTER 2 a missing"), "(empty list or set)".to_string());
+
+        // Nothing is stored
+        assert_eq!(run("ZCARD a"), "3".to_string());
+        run("SET plain value");
+        assert!(run("ZINTER 2 a plain").starts_with("WRONGTYPE"));
+    }
+
+    #[test]
+    fn test_zrangebylex_matches_redis() {
+        // The examples from the Redis documentation of these commands
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        run("ZADD myzset 0 a 0 b 0 c 0 d 0 e 0 f 0 g");
+        assert_eq!(run("ZRANGEBYLEX myzset - [c"), "a\nb\nc".to_string());
+        assert_eq!(run("ZRANGEBYLEX myzset - (c"), "a\nb".to_string());
+        assert_eq!(run("ZRANGEBYLEX myzset [aaa (g"), "b\nc\nd\ne\nf".to_string());
+        assert_eq!(run("ZREVRANGEBYLEX myzset [c -"), "c\nb\na".to_string());
+        assert_eq!(run("ZREVRANGEBYLEX myzset (c -"), "b\na".to_string());
+        assert_eq!(run("ZREVRANGEBYLEX myzset (g [aaa"), "f\ne\nd\nc\nb".to_string());
+        assert_eq!(run("ZLEXCOUNT myzset - +"), "7".to_string());
+        assert_eq!(run("ZLEXCOUNT myzset [b [f"), "5".to_string());
+        assert_eq!(run("ZRANGEBYLEX myzset [z +"), "(empty list or set)".to_string());
+    }
+
+    #[test]
+    fn test_object_freq_counts_accesses() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        assert_eq!(run("OBJECT FREQ missing"), "(nil)".to_string());
+
+        run("SET hot value");
+        run("SET cold value");
+        assert_eq!(run("OBJECT FREQ hot"), "5".to_string());
+        for _ in 0..1000 {
+            run("GET hot");
+        }
+        // Logarithmic: a thousand hits is nowhere near saturating the counter
+        let freq: u32 = run("OBJECT FREQ hot").parse().unwrap();
+        assert!((10..40).contains(&freq), "freq {}", freq);
+
+        // Metadata lookups don't count as accesses
+        for _ in 0..100 {
+            run("TYPE cold");
+            run("OBJECT FREQ cold");
+        }
+        assert_eq!(run("OBJECT FREQ cold"), "5".to_string());
+
+        run("DEL hot");
+        assert_eq!(run("OBJECT FREQ hot"), "(nil)".to_string());
+    }
+    #[test]
+    fn test_cluster_getkeysinslot() {
+        let executor = setup();
+        let run = |input: &str| executor.execute_command(CommandParser::parse(input));
+        assert_eq!(run("CLUSTER GETKEYSINSLOT 0 10"), "ERR This instance has cluster support disabled");
+
+        let mut storage = MemoryStorage::new();
+        storage.enable_slot_index();
+        let executor = CommandExecutor::new(Arc::new(Mutex::new(storage)));
+        let run = |input: &str| executor.execute_command(CommandParser::parse(input));
+        run("SET foo 1");
+        run("SET {foo}.bar 2");
+        let keys = run("CLUSTER GETKEYSINSLOT 12182 10");
+        let mut keys: Vec<&str> = keys.lines().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["foo", "{foo}.bar"]);
+        assert_eq!(run("CLUSTER GETKEYSINSLOT 0 10"), "(empty list or set)");
+        assert_eq!(run("CLUSTER GETKEYSINSLOT 16384 10"), "ERR Invalid slot");
+    }
+    #[test]
+    fn test_debug_subcommands() {
+        let path = std::env::temp_dir().join(format!("redis_imitate_debug_reload_{}.rdb", std::process::id()));
+        let config = Config { snapshot_path: path.to_str().unwrap().to_string(), ..Config::new() };
+        let storage = Arc::new(Mutex::new(MemoryStorage::new()));
+        let executor = CommandExecutor::with_config(Arc::clone(&storage), Arc::new(config));
+        let run = |input: &str| executor.execute_command(CommandParser::parse(input));
+
+        run("SET kept value");
+        run("RPUSH short a");
+        run("RPUSH long bbbbbbbbbb");
+        assert_eq!(run("DEBUG RELOAD"), "OK");
+        assert_eq!(run("GET kept"), "value");
+        assert!(path.exists());
+
+        assert_eq!(run("DEBUG QUICKLIST-PACKED-THRESHOLD 5"), "OK");
+        assert_eq!(run("OBJECT ENCODING long"), "quicklist");
+        let report = run("DEBUG QUICKLIST");
+        assert!(report.contains("list-max-listpack-value:5"));
+        assert!(report.contains("listpack_lists:1\nquicklist_lists:1"));
+
+        let replid = |info: String| info.lines().find_map(|line| line.strip_prefix("master_replid:").map(str::to_string));
+        let before = replid(run("INFO replication")).unwrap();
+        assert_eq!(before.len(), 40);
+        assert_eq!(run("DEBUG CHANGE-REPL-ID"), "OK");
+        assert_ne!(replid(run("INFO replication")).unwrap(), before);
+
+        assert_eq!(run("DEBUG SET-ACTIVE-EXPIRE 0"), "OK");
+        assert!(!storage.lock().unwrap().active_expire_enabled());
+
+        let started = Instant::now();
+        assert_eq!(run("DEBUG SLEEP 0.1"), "OK");
+        assert!(started.elapsed() >= Duration::from_millis(100));
+
+        assert_eq!(run("DEBUG FLUSHALL"), "OK");
+        assert_eq!(run("GET kept"), "(nil)");
+        assert_eq!(
+            executor.execute_transaction(&[CommandParser::parse("DEBUG FLUSHALL")]),
+            vec!["ERR DEBUG cannot be used inside a transaction".to_string()]
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+    #[test]
+    fn test_hset_hsetnx_and_hmset() {
+        let executor = setup();
+        let run = |input: &str| executor.execute_command(CommandParser::parse(input));
+        assert_eq!(run("HSET user name ada lang en"), "2");
+        assert_eq!(run("HSET user name grace"), "0");
+        assert_eq!(run("HSETNX user name ada"), "0");
+        assert_eq!(run("HSETNX user born 1906"), "1");
+        assert_eq!(run("HMSET user name hopper city nyc"), "OK");
+        assert_eq!(run("TYPE user"), "hash");
+        assert_eq!(run("HSET user name"), "ERR unknown command 'HSET user name'");
+        run("SET plain value");
+        assert!(run("HSET plain f v").starts_with("WRONGTYPE"));
+    }
+    #[test]
+    fn test_hincrby_and_hincrbyfloat() {
+        let executor = setup();
+        let run = |input: &str| executor.execute_command(CommandParser::parse(input));
+        assert_eq!(run("HINCRBY user:1 visits 1"), "1");
+        assert_eq!(run("HINCRBY user:1 visits 10"), "11");
+        assert_eq!(run("HINCRBYFLOAT user:1 score 1.5"), "1.5");
+        assert_eq!(run("HINCRBYFLOAT user:1 score 2.5"), "4");
+        assert_eq!(run("HINCRBY user:1 score 1"), "5");
+        assert_eq!(run("HINCRBYFLOAT user:1 score 0.25"), "5.25");
+        assert_eq!(run("HINCRBY user:1 score 1"), "ERR hash value is not an integer");
+        assert_eq!(run("HINCRBY user:1 visits many"), "ERR unknown command 'HINCRBY user:1 visits many'");
+    }
+    #[test]
+    fn test_scan_commands() {
+        let executor = setup();
+        let run = |input: &str| executor.execute_command(CommandParser::parse(input));
+        run("HSET user name ada lang en");
+        let reply = run("HSCAN user 0");
+        let mut lines: Vec<&str> = reply.lines().collect();
+        assert_eq!(lines.remove(0), "0");
+        let mut pairs: Vec<String> = lines.chunks(2).map(|pair| pair.join("=")).collect();
+        pairs.sort();
+        assert_eq!(pairs, ["lang=en", "name=ada"]);
+        assert_eq!(run("HSCAN user 0 MATCH n* COUNT 100"), "0\nname\nada");
+        assert_eq!(run("HSCAN missing 0"), "0");
+
+        run("SADD tags red");
+        assert_eq!(run("SSCAN tags 0"), "0\nred");
+        run("ZADD board 1.5 ada");
+        assert_eq!(run("ZSCAN board 0 COUNT 1"), "0\nada\n1.5");
+        assert_eq!(run("ZSCAN board 0 COUNT 0"), "ERR unknown command 'ZSCAN board 0 COUNT 0'");
+    }
+    #[test]
+    fn test_persist() {
+        let executor = setup();
+
+        assert_eq!(executor.execute_command(Command::Persist("missing".to_string())), "0".to_string());
+        executor.execute_command(Command::Set("session".to_string(), "abc".to_string()));
+        assert_eq!(executor.execute_command(Command::Persist("session".to_string())), "0".to_string());
+
+        executor.execute_command(Command::Expire("session".to_string(), 100));
+        assert_eq!(executor.execute_command(Command::Ttl("session".to_string())), "100".to_string());
+        assert_eq!(executor.execute_command(Command::Persist("session".to_string())), "1".to_string());
+        assert_eq!(executor.execute_command(Command::Ttl("session".to_string())), "-1".to_string());
+        assert_eq!(executor.execute_command(Command::Persist("session".to_string())), "0".to_string());
+        assert_eq!(executor.execute_command(Command::Get("session".to_string())), "abc".to_string());
+    }
+    #[test]
+    fn test_copy() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+
+        run("ZADD board 1 ada 2 bob");
+        run("EXPIRE board 100");
+        assert_eq!(run("COPY board backup"), "1");
+        assert_eq!(run("ZSCORE backup bob"), "2");
+        assert_eq!(run("TTL backup"), "100");
+        assert_eq!(run("COPY board backup"), "0");
+        assert_eq!(run("COPY missing backup REPLACE"), "0");
+
+        run("HSET user name ada");
+        assert_eq!(run("COPY user backup DB 0 REPLACE"), "1");
+        assert_eq!(run("TYPE backup"), "hash");
+        assert_eq!(run("TTL backup"), "-1");
+        assert_eq!(run("COPY user other DB 1"), "ERR DB index is out of range");
+        assert_eq!(run("COPY user user"), "ERR source and destination objects are the same");
+    }
+    #[test]
+    fn test_max_memory_set_at_runtime_refuses_or_evicts() {
+        let storage = Arc::new(Mutex::new(MemoryStorage::new()));
+        let executor = CommandExecutor::new(Arc::clone(&storage));
+        let set = |key: &str| executor.execute_command(Command::Set(key.to_string(), "x".repeat(100)));
+        let config_set = |name: &str, value: &str| executor.execute_command(Command::ConfigSet(name.to_string(), value.to_string()));
+        for i in 0..100 {
+            assert_eq!(set(&format!("key{}", i)), "OK".to_string());
+        }
+        let used = storage.lock().unwrap().used_memory();
+        let limit = used / 2;
+
+        // noeviction refuses writes that need memory, but not deletions
+        assert_eq!(config_set("max-memory", &limit.to_string()), "OK".to_string());
+        assert_eq!(set("more"), "OOM command not allowed when used memory > 'maxmemory'.".to_string());
+        assert_eq!(executor.execute_command(Command::Del("key0".to_string())), "1".to_string());
+
+        assert_eq!(config_set("maxmemory-policy", "allkeys-random"), "OK".to_string());
+        assert_eq!(set("more"), "OK".to_string());
+        let remaining = (1..100)
+            .filter(|i| executor.execute_command(Command::Get(format!("key{}", i))) != "(nil)")
+            .count();
+        assert!(remaining < 55, "{} keys left", remaining);
+        assert!(storage.lock().unwrap().used_memory() <= limit + 300);
+        let stats = executor.execute_command(Command::Info(Some("stats".to_string())));
+        assert!(stats.contains(&format!("evicted_keys:{}\n", 99 - remaining)), "{}", stats);
+
+        // Lifting the limit stops eviction
+        assert_eq!(config_set("max-memory", "0"), "OK".to_string());
+        for i in 0..100 {
+            set(&format!("new{}", i));
+        }
+        assert_eq!(executor.execute_command(Command::Get("new0".to_string())), "x".repeat(100));
+        assert!(storage.lock().unwrap().used_memory() > used);
+    }
+    #[test]
+    fn test_expire_options() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        assert_eq!(run("EXPIRE missing 10 NX"), "0".to_string());
+
+        run("SET key value");
+        // Without a deadline, the key counts as never expiring
+        assert_eq!(run("EXPIRE key 100 XX"), "0".to_string());
+        assert_eq!(run("EXPIRE key 100 GT"), "0".to_string());
+        assert_eq!(run("TTL key"), "-1".to_string());
+        assert_eq!(run("EXPIRE key 100 NX"), "1".to_string());
+        assert_eq!(run("EXPIRE key 50 NX"), "0".to_string());
+
+        assert_eq!(run("EXPIRE key 50 GT"), "0".to_string());
+        assert_eq!(run("EXPIRE key 200 GT"), "1".to_string());
+        assert_eq!(run("TTL key"), "200".to_string());
+        assert_eq!(run("EXPIRE key 300 LT"), "0".to_string());
+        assert_eq!(run("EXPIRE key 30 LT"), "1".to_string());
+        assert_eq!(run("EXPIRE key 60 XX"), "1".to_string());
+        assert_eq!(run("TTL key"), "60".to_string());
+
+        run("SET other value");
+        assert_eq!(run("EXPIRE other 10 LT"), "1".to_string());
+        assert_eq!(run("TTL other"), "10".to_string());
+    }
+    #[test]
+    fn test_lpos_replies() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        for item in ["x", "y", "x"] {
+            run(&format!("RPUSH list {}", item));
+        }
+        assert_eq!(run("LPOS list x"), "0".to_string());
+        assert_eq!(run("LPOS list x RANK -1"), "2".to_string());
+        assert_eq!(run("LPOS list z"), "(nil)".to_string());
+        assert_eq!(run("LPOS list x COUNT 0"), "0\n2".to_string());
+        assert_eq!(run("LPOS list x COUNT 0 MAXLEN 2"), "0".to_string());
+        assert_eq!(run("LPOS list z COUNT 2"), "(empty list or set)".to_string());
+    }
+    #[test]
+    fn test_geoadd_replies() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        assert_eq!(run("GEOADD sicily 13.361389 38.115556 Palermo 15.087269 37.502669 Catania"), "2".to_string());
+        assert_eq!(run("ZSCORE sicily Palermo"), "3479099956230698".to_string());
+        assert_eq!(run("GEOADD sicily CH 13.361389 38.115556 Palermo 13.583333 37.316667 Catania"), "1".to_string());
+        assert_eq!(run("GEOADD sicily 13.361389 91 Palermo"), "ERR invalid longitude,latitude pair 13.361389,91.000000".to_string());
+        run("SET string value");
+        assert!(run("GEOADD string 0 0 member").starts_with("WRONGTYPE"));
+    }
+    #[test]
+    fn test_geosearch_replies() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        run("GEOADD sicily 13.361389 38.115556 Palermo 15.087269 37.502669 Catania");
+        run("GEOADD sicily 12.758489 38.788135 edge1 17.241510 38.788135 edge2");
+
+        // The distances a real server reports
+        assert_eq!(
+            run("GEOSEARCH sicily FROMLONLAT 15 37 BYRADIUS 200 km ASC WITHDIST"),
+            "*2\r\n*2\r\n$7\r\nCatania\r\n$7\r\n56.4413\r\n*2\r\n$7\r\nPalermo\r\n$8\r\n190.4424\r\n".to_string()
+        );
+        assert_eq!(
+            run("GEOSEARCH sicily FROMLONLAT 15 37 BYBOX 400 400 km DESC COUNT 3"),
+            "edge1\nedge2\nPalermo".to_string()
+        );
+        assert_eq!(
+            run("GEOSEARCH sicily FROMMEMBER Palermo BYRADIUS 10 m WITHHASH"),
+            "*1\r\n*2\r\n$7\r\nPalermo\r\n:3479099956230698\r\n".to_string()
+        );
+        assert_eq!(run("GEOSEARCH sicily FROMLONLAT 0 0 BYRADIUS 1 km"), "(empty list or set)".to_string());
+        assert_eq!(run("GEOSEARCH missing FROMMEMBER Palermo BYRADIUS 1 km"), "(empty list or set)".to_string());
+        assert_eq!(run("GEOSEARCH sicily FROMMEMBER Rome BYRADIUS 1 km"), "ERR could not decode requested zset member".to_string());
+
+        assert_eq!(run("GEOSEARCH sicily FROMLONLAT 15 37 BYBOX 400 400 km ASC COUNT 3 STOREDIST near"), "3".to_string());
+        assert!(run("ZSCORE near Catania").starts_with("56.44125787"));
+        assert_eq!(run("GEOSEARCH sicily FROMLONLAT 15 37 BYRADIUS 200 km STORE near"), "2".to_string());
+        assert_eq!(run("ZCARD near"), "2".to_string());
+        assert_eq!(run("ZSCORE near Palermo"), "3479099956230698".to_string());
+        // Nothing found deletes the destination, whatever it held
+        assert_eq!(run("GEOSEARCH sicily FROMLONLAT 0 0 BYRADIUS 1 km STORE near"), "0".to_string());
+        assert_eq!(run("EXISTS near"), "0".to_string());
+
+        run("SET string value");
+        assert!(run("GEOSEARCH string FROMLONLAT 0 0 BYRADIUS 1 km").starts_with("WRONGTYPE"));
+        assert!(run("GEOSEARCH string FROMLONLAT 0 0 BYRADIUS 1 km STORE near").starts_with("WRONGTYPE"));
+    }
+    #[test]
+    fn test_geosearch_with_options_nest_each_match() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        run("GEOADD sicily 13.361389 38.115556 Palermo");
+
+        // Whatever the options' order, the details come as Redis orders them
+        for (options, details) in [
+            ("WITHDIST", vec!["$6\r\n0.0000\r\n"]),
+            ("WITHHASH", vec![":3479099956230698\r\n"]),
+            ("WITHCOORD", vec!["*2\r\n$20\r\n13.36138933897018433\r\n$20\r\n38.11555639549629859\r\n"]),
+            ("WITHHASH WITHDIST", vec!["$6\r\n0.0000\r\n", ":3479099956230698\r\n"]),
+            ("WITHCOORD WITHDIST", vec!["$6\r\n0.0000\r\n", "*2\r\n$20\r\n13.36138933897018433\r\n$20\r\n38.11555639549629859\r\n"]),
+            ("WITHCOORD WITHHASH", vec![":3479099956230698\r\n", "*2\r\n$20\r\n13.36138933897018433\r\n$20\r\n38.11555639549629859\r\n"]),
+            (
+                "WITHCOORD WITHHASH WITHDIST",
+                vec!["$6\r\n0.0000\r\n", ":3479099956230698\r\n", "*2\r\n$20\r\n13.36138933897018433\r\n$20\r\n38.11555639549629859\r\n"],
+            ),
+        ] {
+            let expected = format!("*1\r\n*{}\r\n$7\r\nPalermo\r\n{}", details.len() + 1, details.concat());
+            assert_eq!(run(&format!("GEOSEARCH sicily FROMMEMBER Palermo BYRADIUS 10 m {}", options)), expected, "{}", options);
+        }
+        assert_eq!(run("GEOSEARCH sicily FROMMEMBER Palermo BYRADIUS 10 m"), "Palermo".to_string());
+    }
+
+    #[test]
+    fn test_memory_usage_replies() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        run("SET key value");
+        assert_eq!(run("MEMORY USAGE key"), (5 + 3 + 64).to_string());
+        assert_eq!(run("MEMORY USAGE missing"), "(nil)".to_string());
+        run("RPUSH list a");
+        run("RPUSH list bbbbbbbbbb");
+        assert_eq!(run("MEMORY USAGE list SAMPLES 1"), (2 * 17 + 4 + 128).to_string());
+        assert_eq!(run("MEMORY USAGE list SAMPLES 0"), (17 + 26 + 4 + 128).to_string());
+    }
+    #[test]
+    fn test_config_set_encoding_limits_apply_from_the_next_write() {
+        let executor = setup();
+        let run = |input: &str| executor.execute_command(CommandParser::parse(input));
+        run("HSET hash a 1");
+        run("HSET hash b 2");
+        assert_eq!(run("CONFIG SET hash-max-listpack-entries 2"), "OK");
+        assert_eq!(run("CONFIG GET hash-max-listpack-entries"), "hash-max-listpack-entries\n2");
+        assert_eq!(run("OBJECT ENCODING hash"), "listpack");
+        run("HSET hash c 3");
+        assert_eq!(run("OBJECT ENCODING hash"), "hashtable");
+        // Raising the limit again doesn't undo the upgrade
+        assert_eq!(run("CONFIG SET hash-max-listpack-entries 128"), "OK");
+        run("HSET hash d 4");
+        assert_eq!(run("OBJECT ENCODING hash"), "hashtable");
+
+        assert_eq!(run("CONFIG SET set-max-intset-entries 1"), "OK");
+        run("SADD set 1");
+        assert_eq!(run("OBJECT ENCODING set"), "intset");
+        run("SADD set 2");
+        assert_eq!(run("OBJECT ENCODING set"), "listpack");
+        assert_eq!(
+            run("CONFIG SET zset-max-listpack-value many"),
+            "ERR CONFIG SET failed (possibly related to argument 'zset-max-listpack-value') - argument couldn't be parsed into an integer"
+        );
+    }
+
+    #[test]
+    fn test_xinfo_stream_groups_and_consumers() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        let bulk = |value: &str| RespValue::Bulk(value.to_string());
+        let entry = |id: &str, value: &str| RespValue::Array(vec![bulk(id), RespValue::Array(vec![bulk("kind"), bulk(value)])]);
+
+        assert_eq!(run("XINFO STREAM events"), "ERR no such key");
+        assert_eq!(run("XADD events 1-1 kind click"), "1-1");
+        assert_eq!(run("XADD events 2-* kind view"), "2-0");
+        assert_eq!(run("XLEN events"), "2");
+        assert_eq!(run("XRANGE events - + COUNT 1"), RespValue::Array(vec![entry("1-1", "click")]).encode());
+        assert_eq!(run("XGROUP CREATE events readers 0"), "OK");
+        assert_eq!(run("XREADGROUP GROUP readers alice COUNT 1 STREAMS events >"),
+            RespValue::Array(vec![RespValue::Array(vec![bulk("events"), RespValue::Array(vec![entry("1-1", "click")])])]).encode());
+
+        let stream = RespValue::Array(vec![
+            bulk("length"), RespValue::Integer(2),
+            bulk("radix-tree-keys"), RespValue::Integer(1),
+            bulk("radix-tree-nodes"), RespValue::Integer(2),
+            bulk("last-generated-id"), bulk("2-0"),
+            bulk("max-deleted-entry-id"), bulk("0-0"),
+            bulk("entries-added"), RespValue::Integer(2),
+            bulk("recorded-first-entry-id"), bulk("1-1"),
+            bulk("groups"), RespValue::Integer(1),
+            bulk("first-entry"), entry("1-1", "click"),
+            bulk("last-entry"), entry("2-0", "view"),
+        ]);
+        assert_eq!(run("XINFO STREAM events"), stream.encode());
+
+        let groups = RespValue::Array(vec![RespValue::Array(vec![
+            bulk("name"), bulk("readers"),
+            bulk("consumers"), RespValue::Integer(1),
+            bulk("pending"), RespValue::Integer(1),
+            bulk("last-delivered-id"), bulk("1-1"),
+            bulk("entries-read"), RespValue::Integer(1),
+            bulk("lag"), RespValue::Integer(1),
+        ])]);
+        assert_eq!(run("XINFO GROUPS events"), groups.encode());
+
+        // Idle times depend on the clock, so only the fixed fields are checked
+        let consumers = run("XINFO CONSUMERS events readers");
+        assert!(consumers.starts_with("*1\r\n*8\r\n$4\r\nname\r\n$5\r\nalice\r\n$7\r\npending\r\n:1\r\n$4\r\nidle\r\n"));
+        assert!(consumers.contains("$8\r\ninactive\r\n"));
+        assert_eq!(run("XINFO CONSUMERS events nobody"), "NOGROUP No such consumer group 'nobody' for key name 'events'");
+
+        let full = run("XINFO STREAM events FULL COUNT 1");
+        assert!(full.contains(&RespValue::Array(vec![entry("1-1", "click")]).encode()));
+        assert!(!full.contains("view"));
+        assert!(full.contains("$9\r\npel-count\r\n:1\r\n"));
+
+        assert_eq!(run("XGROUP CREATE empty readers $ MKSTREAM"), "OK");
+        assert!(run("XINFO STREAM empty").ends_with("$11\r\nfirst-entry\r\n$-1\r\n$10\r\nlast-entry\r\n$-1\r\n"));
+        run("SET plain value");
+        assert!(run("XINFO STREAM plain").starts_with("WRONGTYPE"));
+    }
+
+    #[test]
+    fn test_xread_returns_new_entries_without_blocking() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        let stream = |key: &str, entries: &[(&str, &str)]| {
+            let entries = entries.iter().map(|(id, value)| {
+                RespValue::Array(vec![
+                    RespValue::Bulk(id.to_string()),
+                    RespValue::Array(vec![RespValue::Bulk("kind".to_string()), RespValue::Bulk(value.to_string())]),
+                ])
+            });
+            RespValue::Array(vec![RespValue::Bulk(key.to_string()), RespValue::Array(entries.collect())])
+        };
+
+        run("XADD a 1-0 kind click");
+        run("XADD a 2-0 kind view");
+        run("XADD b 1-0 kind scroll");
+        assert_eq!(
+            run("XREAD COUNT 1 STREAMS a b 1-0 0"),
+            RespValue::Array(vec![stream("a", &[("2-0", "view")]), stream("b", &[("1-0", "scroll")])]).encode()
+        );
+        // Streams with nothing after their ID are left out
+        assert_eq!(run("XREAD STREAMS a b 0 1"), RespValue::Array(vec![stream("a", &[("1-0", "click"), ("2-0", "view")])]).encode());
+        assert_eq!(run("XREAD STREAMS a b $ $"), "(nil)");
+        assert_eq!(run("XREAD STREAMS missing 0"), "(nil)");
+
+        let started = Instant::now();
+        assert_eq!(run("XREAD BLOCK 100 STREAMS a $"), "(nil)");
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(executor.blocked_clients(), 0);
+
+        run("SET plain value");
+        assert!(run("XREAD BLOCK 0 STREAMS plain 0").starts_with("WRONGTYPE"));
+    }
+
+    #[test]
+    fn test_xread_block_wakes_every_reader_on_xadd() {
+        let executor = Arc::new(setup());
+        executor.execute_command(CommandParser::parse("XADD events 1-0 kind click"));
+        let reader = |executor: &Arc<CommandExecutor>, line: &'static str| {
+            let executor = Arc::clone(executor);
+            thread::spawn(move || executor.execute_command(CommandParser::parse(line)))
+        };
+
+        let first = reader(&executor, "XREAD BLOCK 0 STREAMS other events $ $");
+        wait_for_blocked(&executor, 1);
+        let second = reader(&executor, "XREAD BLOCK 5000 STREAMS events 1-0");
+        wait_for_blocked(&executor, 2);
+
+        assert_eq!(executor.execute_command(CommandParser::parse("XADD events 2-0 kind view")), "2-0");
+        let expected = RespValue::Array(vec![RespValue::Array(vec![
+            RespValue::Bulk("events".to_string()),
+            RespValue::Array(vec![RespValue::Array(vec![
+                RespValue::Bulk("2-0".to_string()),
+                RespValue::Array(vec![RespValue::Bulk("kind".to_string()), RespValue::Bulk("view".to_string())]),
+            ])]),
+        ])]);
+        // Reading takes nothing away, so both readers get the entry
+        assert_eq!(first.join().unwrap(), expected.encode());
+        assert_eq!(second.join().unwrap(), expected.encode());
+        assert_eq!(executor.blocked_clients(), 0);
+    }
+
+    #[test]
+    fn test_xautoclaim_reply() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        run("XADD events 1-0 kind click");
+        run("XGROUP CREATE events readers 0");
+        run("XREADGROUP GROUP readers alice STREAMS events >");
+
+        let claimed = RespValue::Array(vec![
+            RespValue::Bulk("0-0".to_string()),
+            RespValue::Array(vec![RespValue::Array(vec![
+                RespValue::Bulk("1-0".to_string()),
+                RespValue::Array(vec![RespValue::Bulk("kind".to_string()), RespValue::Bulk("click".to_string())]),
+            ])]),
+            RespValue::Array(Vec::new()),
+        ]);
+        assert_eq!(run("XAUTOCLAIM events readers bob 0 -"), claimed.encode());
+        assert!(run("XINFO CONSUMERS events readers").contains("$3\r\nbob\r\n$7\r\npending\r\n:1\r\n"));
+        assert_eq!(run("XAUTOCLAIM events nobody bob 0 0"), "NOGROUP No such key 'events' or consumer group 'nobody'");
+    }
+
+    #[test]
+    fn test_xdel_is_reported_by_xinfo() {
+        let executor = setup();
+        let run = |line: &str| executor.execute_command(CommandParser::parse(line));
+        run("XADD events 1-0 kind click");
+        run("XADD events 2-0 kind view");
+
+        assert_eq!(run("XDEL events 2-0 5-0"), "1");
+        assert_eq!(run("XLEN events"), "1");
+        assert!(!run("XRANGE events - +").contains("view"));
+        let info = run("XINFO STREAM events");
+        assert!(info.contains("$17\r\nlast-generated-id\r\n$3\r\n2-0\r\n$20\r\nmax-deleted-entry-id\r\n$3\r\n2-0\r\n"));
+        assert!(info.contains("$13\r\nentries-added\r\n:2\r\n"));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,40 @@
+use redis_imitate::storage::glob::{glob_match, GlobPattern};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcards() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("news.*", "news.sports"));
+        assert!(glob_match("news.*", "news."));
+        assert!(!glob_match("news.*", "news"));
+        assert!(glob_match("h?llo", "hello"));
+        assert!(!glob_match("h?llo", "hllo"));
+        assert!(glob_match("*a*b*c", "xxaxxbxxc"));
+        assert!(!glob_match("*a*b*c", "xxaxxcxxb"));
+        assert!(glob_match("a**b", "ab"));
+    }
+
+    #[test]
+    fn test_classes_and_escapes() {
+        assert!(glob_match("h[ae]llo", "hallo"));
+        assert!(!glob_match("h[ae]llo", "hillo"));
+        assert!(glob_match("h[^e]llo", "hallo"));
+        assert!(!glob_match("h[^e]llo", "hello"));
+        assert!(glob_match("h[a-c]llo", "hbllo"));
+        assert!(glob_match("h[c-a]llo", "hbllo"));
+        assert!(glob_match("x\\*y", "x*y"));
+        assert!(!glob_match("x\\*y", "xzy"));
+        // An unterminated class is just a literal bracket
+        assert!(glob_match("a[b", "a[b"));
+    }
+
+    #[test]
+    fn test_literal_prefix() {
+        assert_eq!(GlobPattern::new("news.*").literal_prefix(), b"news.");
+        assert_eq!(GlobPattern::new("a\\*b?").literal_prefix(), b"a*b");
+        assert_eq!(GlobPattern::new("*news").literal_prefix(), b"");
+    }
+}
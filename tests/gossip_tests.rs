@@ -0,0 +1,103 @@
+use redis_imitate::cluster::gossip::Topology;
+use redis_imitate::cluster::message::{NodeInfo, RaftMessage};
+use redis_imitate::cluster::state::NodeRole;
+use redis_imitate::cluster::transport::MockTransport;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, epoch: u64) -> NodeInfo {
+        NodeInfo { id: id.to_string(), addr: format!("{}:7000", id), role: NodeRole::Follower, epoch }
+    }
+
+    /// Nodes `n1`..`n{count}`, each with a transport that only records what it sends
+    fn cluster(count: usize) -> Vec<(Topology, MockTransport)> {
+        (1..=count)
+            .map(|i| {
+                let id = format!("n{}", i);
+                (Topology::new(id.clone(), format!("{}:7000", id)), MockTransport::new(id))
+            })
+            .collect()
+    }
+
+    /// Delivers every message sent, and every one that causes, until the cluster is quiet
+    ///
+    /// A message is addressed by node id, or by address to a node only met so far.
+    async fn deliver(nodes: &[(Topology, MockTransport)]) -> usize {
+        let mut delivered = 0;
+        loop {
+            let mut pending = Vec::new();
+            for (_, transport) in nodes {
+                pending.extend(transport.messages.lock().await.drain(..));
+            }
+            if pending.is_empty() {
+                return delivered;
+            }
+            for (to, message) in pending {
+                let (topology, transport) = nodes
+                    .iter()
+                    .find(|(topology, _)| topology.node_id() == to || format!("{}:7000", topology.node_id()) == to)
+                    .unwrap();
+                topology.handle_gossip(transport, &message).await.unwrap();
+                delivered += 1;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_one_meet_joins_a_node_to_the_whole_cluster() {
+        let nodes = cluster(5);
+        // n2..n5 already form a cluster, met one after another
+        for i in 1..4 {
+            let next = format!("n{}:7000", i + 2);
+            nodes[i].0.meet(&nodes[i].1, &next).await.unwrap();
+            deliver(&nodes).await;
+        }
+        assert_eq!(nodes[0].0.nodes().len(), 1);
+
+        nodes[0].0.meet(&nodes[0].1, "n2:7000").await.unwrap();
+        assert!(deliver(&nodes).await > 0);
+
+        let ids: Vec<String> = (1..=5).map(|i| format!("n{}", i)).collect();
+        for (topology, transport) in &nodes {
+            let known: Vec<String> = topology.nodes().into_iter().map(|node| node.id).collect();
+            assert_eq!(known, ids, "{}", topology.node_id());
+            // One connection per peer, known by its id, none left under an address
+            let mut connected: Vec<String> = transport.connections.lock().await.keys().cloned().collect();
+            connected.sort();
+            let peers: Vec<String> = ids.iter().filter(|id| **id != topology.node_id()).cloned().collect();
+            assert_eq!(connected, peers, "{}", topology.node_id());
+        }
+
+        // Meeting a known node again sends nothing
+        nodes[0].0.meet(&nodes[0].1, "n5:7000").await.unwrap();
+        assert_eq!(deliver(&nodes).await, 0);
+    }
+
+    #[test]
+    fn test_merge_keeps_the_newest_information() {
+        let topology = Topology::new("n1".to_string(), "n1:7000".to_string());
+        let gossip = |nodes: Vec<NodeInfo>| RaftMessage::ClusterGossip { sender_id: "n2".to_string(), nodes };
+
+        let (learned, sender_behind) = topology.merge(&gossip(vec![node("n2", 3)]));
+        assert_eq!(learned, vec![node("n2", 3)]);
+        assert!(sender_behind);
+
+        // An older epoch doesn't overwrite, a newer one does
+        let leader = NodeInfo { role: NodeRole::Leader, ..node("n2", 2) };
+        assert_eq!(topology.merge(&gossip(vec![node("n1", 0), leader])), (Vec::new(), false));
+        assert_eq!(topology.nodes()[1], node("n2", 3));
+        let leader = NodeInfo { role: NodeRole::Leader, ..node("n2", 4) };
+        topology.merge(&gossip(vec![node("n1", 0), leader.clone()]));
+        assert_eq!(topology.nodes()[1], leader);
+
+        // Gossip never rewrites this node's own entry
+        topology.update_self(NodeRole::Candidate, 5);
+        topology.merge(&gossip(vec![NodeInfo { addr: "elsewhere:1".to_string(), ..node("n1", 9) }]));
+        let this = &topology.nodes()[0];
+        assert_eq!((this.addr.as_str(), &this.role, this.epoch), ("n1:7000", &NodeRole::Candidate, 5));
+
+        assert_eq!(topology.merge(&RaftMessage::Heartbeat { term: 1, leader_id: "n2".to_string() }), (Vec::new(), false));
+    }
+}
@@ -0,0 +1,31 @@
+use redis_imitate::storage::lfu::{decay, log_incr, LFU_INIT_VAL};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_incr_probability() {
+        // Up to the initial value every access counts
+        assert_eq!(log_incr(0, 0.999), 1);
+        assert_eq!(log_incr(LFU_INIT_VAL, 0.999), LFU_INIT_VAL + 1);
+
+        // Past it the chance is 1 / ((counter - 5) * 10 + 1): 1/11 at 6
+        assert_eq!(log_incr(6, 0.09), 7);
+        assert_eq!(log_incr(6, 0.091), 6);
+        // 1/101 at 15
+        assert_eq!(log_incr(15, 0.0098), 16);
+        assert_eq!(log_incr(15, 0.01), 15);
+
+        assert_eq!(log_incr(255, 0.0), 255);
+    }
+
+    #[test]
+    fn test_decay_loses_a_point_per_idle_minute() {
+        assert_eq!(decay(20, 0), 20);
+        assert_eq!(decay(20, 1), 19);
+        assert_eq!(decay(20, 15), 5);
+        assert_eq!(decay(20, 20), 0);
+        assert_eq!(decay(20, 10_000), 0);
+    }
+}
@@ -0,0 +1,75 @@
+use redis_imitate::network::maintenance::{Maintenance, MaintenanceStats};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counting(maintenance: &mut Maintenance, name: &'static str) -> Arc<AtomicUsize> {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&runs);
+        maintenance.register(name, move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+        });
+        runs
+    }
+
+    #[test]
+    fn test_tasks_run_once_per_tick_in_order() {
+        let stats = Arc::new(MaintenanceStats::default());
+        let mut maintenance = Maintenance::new(Duration::from_millis(20), Arc::clone(&stats));
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for name in ["first", "second"] {
+            let order = Arc::clone(&order);
+            maintenance.register(name, move || order.lock().unwrap().push(name));
+        }
+        let thread = maintenance.spawn().unwrap();
+        thread::sleep(Duration::from_millis(500));
+        thread.stop();
+
+        let ticks = stats.ticks();
+        assert!((10..=26).contains(&ticks), "{} ticks", ticks);
+        assert_eq!(stats.runs("first"), ticks);
+        assert_eq!(stats.runs("second"), ticks);
+        let order = order.lock().unwrap();
+        assert!(order.chunks(2).all(|pair| pair == ["first", "second"]));
+    }
+
+    #[test]
+    fn test_stop_does_not_wait_for_the_tick() {
+        let stats = Arc::new(MaintenanceStats::default());
+        let mut maintenance = Maintenance::new(Duration::from_secs(60), stats);
+        let runs = counting(&mut maintenance, "counting");
+        let thread = maintenance.spawn().unwrap();
+
+        let started = Instant::now();
+        thread.stop();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_info_reports_every_task() {
+        let stats = Arc::new(MaintenanceStats::default());
+        let mut maintenance = Maintenance::new(Duration::from_millis(10), Arc::clone(&stats));
+        let runs = counting(&mut maintenance, "counting");
+        let thread = maintenance.spawn().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while runs.load(Ordering::SeqCst) < 3 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(thread);
+
+        let info = stats.info();
+        let mut lines = info.lines();
+        assert_eq!(lines.next(), Some("# Maintenance"));
+        assert_eq!(lines.next(), Some("maintenance_tick_ms:10"));
+        assert!(lines.next().unwrap().starts_with("maintenance_ticks:"));
+        let task = lines.next().unwrap();
+        assert!(task.starts_with(&format!("task_counting:runs={},usec=", runs.load(Ordering::SeqCst))), "{}", task);
+        assert_eq!(lines.next(), None);
+    }
+}
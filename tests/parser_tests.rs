@@ -1,4 +1,9 @@
-use redis_imitate::commands::parser::{Command,CommandParser};
+use redis_imitate::commands::parser::{Command,CommandParser,CommandTable,DebugSubcommand,ListSide,ShutdownMode,SortOrder,XInfoSubcommand};
+use redis_imitate::storage::geo::{GeoAddOptions, GeoOrigin, GeoSearchParams, GeoShape, GeoStoreMode, GeoUnit};
+use redis_imitate::storage::memory::ExpireFlags;
+use redis_imitate::storage::stream::{StreamEntryId, XAddId};
+use redis_imitate::storage::zset::{AggType, LexBound, ScoreBound, ZAddOptions};
+use std::collections::HashMap;
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -238,5 +243,838 @@ mod tests {
             CommandParser::parse("SE"),
             Command::Unknown("SE".to_string())
         );
-    }    
-}
\ No newline at end of file
+    }
+
+    fn table(pairs: &[(&str, &str)]) -> CommandTable {
+        let renames: HashMap<String, String> = pairs
+            .iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect();
+        CommandTable::new(&renames)
+    }
+
+    #[test]
+    fn test_disabled_command_is_unknown() {
+        let table = table(&[("del", "")]);
+        assert_eq!(
+            CommandParser::parse_with_table("DEL mykey", &table),
+            Command::Unknown("DEL mykey".to_string())
+        );
+        assert_eq!(
+            CommandParser::parse_with_table("GET mykey", &table),
+            Command::Get("mykey".to_string())
+        );
+    }
+
+    #[test]
+    fn test_renamed_command() {
+        let table = table(&[("SET", "store")]);
+        assert_eq!(
+            CommandParser::parse_with_table("STORE mykey myvalue", &table),
+            Command::Set("mykey".to_string(), "myvalue".to_string())
+        );
+        assert_eq!(
+            CommandParser::parse_with_table("store mykey myvalue", &table),
+            Command::Set("mykey".to_string(), "myvalue".to_string())
+        );
+        assert_eq!(
+            CommandParser::parse_with_table("SET mykey myvalue", &table),
+            Command::Unknown("SET mykey myvalue".to_string())
+        );
+    }
+
+    #[test]
+    fn test_lpos_options() {
+        assert_eq!(
+            CommandParser::parse("LPOS MyList a"),
+            Command::LPos { key: "mylist".to_string(), element: "a".to_string(), rank: 1, count: None, maxlen: 0 }
+        );
+        assert_eq!(
+            CommandParser::parse("lpos list A maxlen 10 rank -2 count 0"),
+            Command::LPos { key: "list".to_string(), element: "A".to_string(), rank: -2, count: Some(0), maxlen: 10 }
+        );
+        for invalid in ["LPOS list", "LPOS list a RANK 0", "LPOS list a COUNT -1", "LPOS list a MAXLEN", "LPOS list a LIMIT 1"] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_expiration_commands() {
+        assert_eq!(CommandParser::parse("EXPIRE MyKey 10"), Command::Expire("mykey".to_string(), 10));
+        assert_eq!(CommandParser::parse("TTL mykey"), Command::Ttl("mykey".to_string()));
+        assert_eq!(CommandParser::parse("PERSIST MyKey"), Command::Persist("mykey".to_string()));
+        assert!(CommandParser::parse("PERSIST mykey").is_write());
+        assert_eq!(
+            CommandParser::parse("COPY Src Dst REPLACE db 3"),
+            Command::Copy("src".to_string(), "dst".to_string(), Some(3), true)
+        );
+        assert_eq!(CommandParser::parse("copy a b"), Command::Copy("a".to_string(), "b".to_string(), None, false));
+        for invalid in ["COPY a", "COPY a b DB", "COPY a b DB x", "COPY a b REPLACE REPLACE", "COPY a b NOW"] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+        assert_eq!(CommandParser::parse("EXISTS mykey"), Command::Exists("mykey".to_string()));
+        assert_eq!(CommandParser::parse("TYPE mykey"), Command::Type("mykey".to_string()));
+        assert_eq!(
+            CommandParser::parse("EXPIRE mykey soon"),
+            Command::Unknown("EXPIRE mykey soon".to_string())
+        );
+        assert_eq!(
+            CommandParser::parse("EXPIRE MyKey 10 gt"),
+            Command::ExpireOpts("mykey".to_string(), 10, ExpireFlags { gt: true, ..ExpireFlags::default() })
+        );
+        assert!(CommandParser::parse("EXPIRE mykey 10 NX").is_write());
+        for invalid in ["EXPIRE mykey 10 NX XX", "EXPIRE mykey 10 GT LT", "EXPIRE mykey 10 CH", "EXPIRE mykey soon NX"] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_client_commands() {
+        assert_eq!(CommandParser::parse("CLIENT ID"), Command::ClientId);
+        assert_eq!(CommandParser::parse("client setname MyConn"), Command::ClientSetName("MyConn".to_string()));
+        assert_eq!(CommandParser::parse("CLIENT SETNAME my conn"), Command::ClientSetName("my conn".to_string()));
+        assert_eq!(CommandParser::parse("CLIENT GETNAME"), Command::ClientGetName);
+        assert_eq!(CommandParser::parse("CLIENT LIST"), Command::ClientList);
+        assert_eq!(CommandParser::parse("CLIENT KILL 1"), Command::Unknown("CLIENT KILL 1".to_string()));
+    }
+
+    #[test]
+    fn test_info_and_config_commands() {
+        assert_eq!(CommandParser::parse("INFO"), Command::Info(None));
+        assert_eq!(CommandParser::parse("INFO CommandStats"), Command::Info(Some("commandstats".to_string())));
+        assert_eq!(CommandParser::parse("config resetstat"), Command::ConfigResetStat);
+        assert_eq!(CommandParser::parse("CONFIG REWRITE"), Command::ConfigRewrite);
+        assert_eq!(CommandParser::parse("CONFIG GET max-*"), Command::ConfigGet("max-*".to_string()));
+        assert_eq!(
+            CommandParser::parse("config set save-conditions 900 1 300 10"),
+            Command::ConfigSet("save-conditions".to_string(), "900 1 300 10".to_string())
+        );
+        assert_eq!(CommandParser::parse("CONFIG SET max-memory"), Command::Unknown("CONFIG SET max-memory".to_string()));
+        assert_eq!(CommandParser::parse("CONFIG GET"), Command::Unknown("CONFIG GET".to_string()));
+    }
+
+    #[test]
+    fn test_cluster_commands() {
+        assert_eq!(
+            CommandParser::parse("CLUSTER MEET 127.0.0.1 7001"),
+            Command::ClusterMeet("127.0.0.1".to_string(), 7001)
+        );
+        assert_eq!(CommandParser::parse("cluster forget Node2"), Command::ClusterForget("Node2".to_string()));
+        assert_eq!(CommandParser::parse("CLUSTER NODES"), Command::ClusterNodes);
+        assert_eq!(
+            CommandParser::parse("CLUSTER MEET 127.0.0.1 port"),
+            Command::Unknown("CLUSTER MEET 127.0.0.1 port".to_string())
+        );
+        assert_eq!(CommandParser::parse("CLUSTER INFO"), Command::ClusterInfo);
+        assert_eq!(CommandParser::parse("cluster addslots 1 2 3"), Command::ClusterAddSlots(vec![1, 2, 3]));
+        assert_eq!(CommandParser::parse("CLUSTER DELSLOTS 16383"), Command::ClusterDelSlots(vec![16383]));
+        assert_eq!(
+            CommandParser::parse("CLUSTER ADDSLOTSRANGE 0 99 200 299"),
+            Command::ClusterAddSlotsRange(vec![(0, 99), (200, 299)])
+        );
+        assert_eq!(CommandParser::parse("CLUSTER GETKEYSINSLOT 7 100"), Command::ClusterGetKeysInSlot(7, 100));
+        assert_eq!(
+            CommandParser::parse("CLUSTER GETKEYSINSLOT 7 -1"),
+            Command::Unknown("CLUSTER GETKEYSINSLOT 7 -1".to_string())
+        );
+        for input in ["CLUSTER ADDSLOTS", "CLUSTER ADDSLOTS one", "CLUSTER ADDSLOTS 70000", "CLUSTER ADDSLOTSRANGE 0 99 200"] {
+            assert_eq!(CommandParser::parse(input), Command::Unknown(input.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_shutdown_command() {
+        assert_eq!(CommandParser::parse("SHUTDOWN"), Command::Shutdown(ShutdownMode::Default));
+        assert_eq!(CommandParser::parse("shutdown save"), Command::Shutdown(ShutdownMode::Save));
+        assert_eq!(CommandParser::parse("SHUTDOWN NOSAVE"), Command::Shutdown(ShutdownMode::NoSave));
+        assert_eq!(CommandParser::parse("SHUTDOWN NOW"), Command::Unknown("SHUTDOWN NOW".to_string()));
+    }
+
+    #[test]
+    fn test_debug_command() {
+        assert_eq!(CommandParser::parse("DEBUG SLEEP 0.5"), Command::Debug(DebugSubcommand::Sleep(0.5)));
+        assert_eq!(CommandParser::parse("debug reload"), Command::Debug(DebugSubcommand::Reload));
+        assert_eq!(CommandParser::parse("DEBUG FLUSHALL"), Command::Debug(DebugSubcommand::Flushall));
+        assert_eq!(
+            CommandParser::parse("DEBUG SET-ACTIVE-EXPIRE 0"),
+            Command::Debug(DebugSubcommand::SetActiveExpire(false))
+        );
+        assert_eq!(
+            CommandParser::parse("DEBUG QUICKLIST-PACKED-THRESHOLD 100"),
+            Command::Debug(DebugSubcommand::QuicklistPackedThreshold(100))
+        );
+        assert_eq!(CommandParser::parse("DEBUG CHANGE-REPL-ID"), Command::Debug(DebugSubcommand::ChangeReplId));
+        assert_eq!(CommandParser::parse("DEBUG QUICKLIST"), Command::Debug(DebugSubcommand::Quicklist));
+        assert!(CommandParser::parse("DEBUG FLUSHALL").is_write());
+        assert!(!CommandParser::parse("DEBUG RELOAD").is_write());
+        for invalid in ["DEBUG", "DEBUG SLEEP -1", "DEBUG SLEEP 1e300", "DEBUG SET-ACTIVE-EXPIRE 2", "DEBUG SEGFAULT"] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_quit_command() {
+        assert_eq!(CommandParser::parse("QUIT"), Command::Quit);
+        assert_eq!(CommandParser::parse("quit"), Command::Quit);
+        assert_eq!(CommandParser::parse("RESET"), Command::Reset);
+        assert!(matches!(CommandParser::parse("RESET now"), Command::Unknown(_)));
+        assert_eq!(CommandParser::parse("QUIT now"), Command::Unknown("QUIT now".to_string()));
+    }
+
+    #[test]
+    fn test_sort_command() {
+        assert_eq!(
+            CommandParser::parse("SORT MyList BY weight_* LIMIT 0 5 GET # GET obj_*->name DESC ALPHA STORE Dest"),
+            Command::Sort {
+                key: "mylist".to_string(),
+                by: Some("weight_*".to_string()),
+                limit: Some((0, 5)),
+                get_patterns: vec!["#".to_string(), "obj_*->name".to_string()],
+                order: SortOrder::Desc,
+                alpha: true,
+                store: Some("dest".to_string()),
+            }
+        );
+        assert_eq!(
+            CommandParser::parse("sort mylist"),
+            Command::Sort {
+                key: "mylist".to_string(),
+                by: None,
+                limit: None,
+                get_patterns: Vec::new(),
+                order: SortOrder::Asc,
+                alpha: false,
+                store: None,
+            }
+        );
+        assert_eq!(CommandParser::parse("SORT mylist LIMIT 0"), Command::Unknown("SORT mylist LIMIT 0".to_string()));
+        assert_eq!(CommandParser::parse("SORT mylist SIDEWAYS"), Command::Unknown("SORT mylist SIDEWAYS".to_string()));
+    }
+
+    #[test]
+    fn test_wait_command() {
+        assert_eq!(CommandParser::parse("WAIT 2 100"), Command::Wait(2, 100));
+        assert_eq!(CommandParser::parse("WAIT 2 -1"), Command::Unknown("WAIT 2 -1".to_string()));
+    }
+
+    #[test]
+    fn test_lolwut_command() {
+        assert_eq!(CommandParser::parse("LOLWUT"), Command::Lolwut(None));
+        assert_eq!(CommandParser::parse("lolwut version 5"), Command::Lolwut(Some(5)));
+        assert_eq!(CommandParser::parse("LOLWUT 5"), Command::Unknown("LOLWUT 5".to_string()));
+    }
+
+    #[test]
+    fn test_object_encoding_command() {
+        assert_eq!(CommandParser::parse("OBJECT encoding MyList"), Command::ObjectEncoding("mylist".to_string()));
+        assert_eq!(CommandParser::parse("OBJECT IDLETIME mylist"), Command::Unknown("OBJECT IDLETIME mylist".to_string()));
+        assert_eq!(CommandParser::parse("object help"), Command::ObjectHelp);
+        assert_eq!(CommandParser::parse("OBJECT HELP extra"), Command::Unknown("OBJECT HELP extra".to_string()));
+        assert_eq!(CommandParser::parse("OBJECT"), Command::Unknown("OBJECT".to_string()));
+    }
+
+    #[test]
+    fn test_echo_command() {
+        assert_eq!(CommandParser::parse("ECHO hello"), Command::Echo("hello".to_string()));
+        assert_eq!(CommandParser::parse("echo \"hello world\""), Command::Echo("hello world".to_string()));
+        assert_eq!(CommandParser::parse("ECHO  Hello   World "), Command::Echo("Hello   World".to_string()));
+        assert_eq!(CommandParser::parse("ECHO"), Command::Unknown("ECHO".to_string()));
+    }
+
+    #[test]
+    fn test_pubsub_commands() {
+        assert_eq!(
+            CommandParser::parse("SUBSCRIBE News sports"),
+            Command::Subscribe(vec!["News".to_string(), "sports".to_string()])
+        );
+        assert_eq!(CommandParser::parse("UNSUBSCRIBE"), Command::Unsubscribe(vec![]));
+        assert_eq!(CommandParser::parse("psubscribe news.*"), Command::PSubscribe(vec!["news.*".to_string()]));
+        assert_eq!(CommandParser::parse("PUNSUBSCRIBE"), Command::PUnsubscribe(vec![]));
+        assert_eq!(
+            CommandParser::parse("PUBLISH news hello  world"),
+            Command::Publish("news".to_string(), "hello  world".to_string())
+        );
+        assert_eq!(CommandParser::parse("PUBLISH news"), Command::Unknown("PUBLISH news".to_string()));
+        assert_eq!(CommandParser::parse("SUBSCRIBE"), Command::Unknown("SUBSCRIBE".to_string()));
+        assert_eq!(CommandParser::parse("PING"), Command::Ping(None));
+        assert_eq!(CommandParser::parse("PING hi there"), Command::Ping(Some("hi there".to_string())));
+    }
+
+    #[test]
+    fn test_blocking_pop_commands() {
+        assert_eq!(
+            CommandParser::parse("BLPOP Queue1 queue2 0"),
+            Command::BLPop(vec!["queue1".to_string(), "queue2".to_string()], 0.0)
+        );
+        assert_eq!(CommandParser::parse("brpop queue 1.5"), Command::BRPop(vec!["queue".to_string()], 1.5));
+        assert_eq!(CommandParser::parse("BLPOP 0"), Command::Unknown("BLPOP 0".to_string()));
+        assert_eq!(CommandParser::parse("BLPOP queue -1"), Command::Unknown("BLPOP queue -1".to_string()));
+        assert_eq!(CommandParser::parse("BRPOP queue soon"), Command::Unknown("BRPOP queue soon".to_string()));
+    }
+
+    #[test]
+    fn test_set_commands() {
+        assert_eq!(
+            CommandParser::parse("SADD Colors Red blue"),
+            Command::SAdd("colors".to_string(), vec!["Red".to_string(), "blue".to_string()])
+        );
+        assert_eq!(CommandParser::parse("smembers Colors"), Command::SMembers("colors".to_string()));
+        assert_eq!(
+            CommandParser::parse("SMOVE Src Dst Member"),
+            Command::SMove("src".to_string(), "dst".to_string(), "Member".to_string())
+        );
+        assert_eq!(CommandParser::parse("SMOVE src dst"), Command::Unknown("SMOVE src dst".to_string()));
+        assert_eq!(CommandParser::parse("SADD colors"), Command::Unknown("SADD colors".to_string()));
+    }
+
+    #[test]
+    fn test_hash_commands() {
+        let pairs = vec![("Name".to_string(), "Ada".to_string()), ("lang".to_string(), "en".to_string())];
+        assert_eq!(CommandParser::parse("HSET User Name Ada lang en"), Command::HSet("user".to_string(), pairs.clone()));
+        assert_eq!(CommandParser::parse("hmset User Name Ada lang en"), Command::HMSet("user".to_string(), pairs));
+        assert_eq!(
+            CommandParser::parse("HSETNX User Name Ada"),
+            Command::HSetNx("user".to_string(), "Name".to_string(), "Ada".to_string())
+        );
+        assert!(CommandParser::parse("HSETNX user name ada").is_write());
+        assert_eq!(
+            CommandParser::parse("HINCRBY User Visits -3"),
+            Command::HIncrBy("user".to_string(), "Visits".to_string(), -3)
+        );
+        assert_eq!(
+            CommandParser::parse("hincrbyfloat user price 0.25"),
+            Command::HIncrByFloat("user".to_string(), "price".to_string(), 0.25)
+        );
+        assert!(CommandParser::parse("HINCRBYFLOAT user price 1").is_write());
+        for invalid in [
+            "HSET user",
+            "HSET user name",
+            "HSET user a 1 b",
+            "HMSET user name",
+            "HSETNX user name",
+            "HINCRBY user visits 1.5",
+            "HINCRBYFLOAT user price inf",
+            "HINCRBYFLOAT user price",
+        ] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_blocking_move_commands() {
+        assert_eq!(
+            CommandParser::parse("BLMOVE Src Dst left RIGHT 2"),
+            Command::BLMove("src".to_string(), "dst".to_string(), ListSide::Left, ListSide::Right, 2.0)
+        );
+        assert_eq!(
+            CommandParser::parse("BRPOPLPUSH src dst 0"),
+            Command::BLMove("src".to_string(), "dst".to_string(), ListSide::Right, ListSide::Left, 0.0)
+        );
+        assert_eq!(
+            CommandParser::parse("BLMOVE src dst UP LEFT 0"),
+            Command::Unknown("BLMOVE src dst UP LEFT 0".to_string())
+        );
+        assert_eq!(CommandParser::parse("BRPOPLPUSH src dst"), Command::Unknown("BRPOPLPUSH src dst".to_string()));
+    }
+
+    #[test]
+    fn test_set_store_commands() {
+        let keys = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(CommandParser::parse("SDIFFSTORE Dst A b"), Command::SDiffStore("dst".to_string(), keys.clone()));
+        assert_eq!(CommandParser::parse("sunionstore dst a b"), Command::SUnionStore("dst".to_string(), keys.clone()));
+        assert_eq!(CommandParser::parse("SINTERSTORE dst a b"), Command::SInterStore("dst".to_string(), keys));
+        assert_eq!(CommandParser::parse("SINTERSTORE dst"), Command::Unknown("SINTERSTORE dst".to_string()));
+    }
+
+    #[test]
+    fn test_sintercard_command() {
+        let keys = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(CommandParser::parse("SINTERCARD 2 A b"), Command::SInterCard(2, keys.clone(), None));
+        assert_eq!(CommandParser::parse("sintercard 2 a b limit 5"), Command::SInterCard(2, keys, Some(5)));
+        // numkeys must match the number of keys given
+        assert_eq!(CommandParser::parse("SINTERCARD 3 a b"), Command::Unknown("SINTERCARD 3 a b".to_string()));
+        assert_eq!(CommandParser::parse("SINTERCARD 1 a b"), Command::Unknown("SINTERCARD 1 a b".to_string()));
+        assert_eq!(CommandParser::parse("SINTERCARD 0"), Command::Unknown("SINTERCARD 0".to_string()));
+        assert_eq!(
+            CommandParser::parse("SINTERCARD 1 a LIMIT -1"),
+            Command::Unknown("SINTERCARD 1 a LIMIT -1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_zadd_command() {
+        assert_eq!(
+            CommandParser::parse("ZADD Board 1.5 alice -inf Bob"),
+            Command::ZAdd(
+                "board".to_string(),
+                ZAddOptions::default(),
+                vec![(1.5, "alice".to_string()), (f64::NEG_INFINITY, "Bob".to_string())]
+            )
+        );
+        let options = ZAddOptions { xx: true, gt: true, ch: true, ..ZAddOptions::default() };
+        assert_eq!(
+            CommandParser::parse("zadd board xx gt ch 3 alice"),
+            Command::ZAdd("board".to_string(), options, vec![(3.0, "alice".to_string())])
+        );
+        assert_eq!(CommandParser::parse("ZSCORE Board alice"), Command::ZScore("board".to_string(), "alice".to_string()));
+        assert_eq!(CommandParser::parse("ZCARD board"), Command::ZCard("board".to_string()));
+
+        for valid in ["XX GT", "XX LT", "CH", "INCR NX", "INCR XX", "GT", "LT CH"] {
+            let line = format!("ZADD board {} 1 alice", valid);
+            assert!(matches!(CommandParser::parse(&line), Command::ZAdd(..)), "{}", line);
+        }
+        for invalid in [
+            "ZADD board NX XX 1 alice",
+            "ZADD board NX GT 1 alice",
+            "ZADD board NX LT 1 alice",
+            "ZADD board GT LT 1 alice",
+            "ZADD board INCR 1 alice 2 bob",
+            "ZADD board 1",
+            "ZADD board 1 alice 2",
+            "ZADD board NX",
+            "ZADD board nan alice",
+            "ZADD board high alice",
+        ] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+
+        assert_eq!(
+            CommandParser::parse("ZINCRBY Board -2.5 alice"),
+            Command::ZIncrBy("board".to_string(), -2.5, "alice".to_string())
+        );
+        for invalid in ["ZINCRBY board 1", "ZINCRBY board nan alice", "ZINCRBY board 1 alice bob"] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_zrangebyscore_commands() {
+        assert_eq!(
+            CommandParser::parse("ZRANGEBYSCORE Board (1 +inf WITHSCORES LIMIT 2 -1"),
+            Command::ZRangeByScore {
+                key: "board".to_string(),
+                min: ScoreBound::Exclusive(1.0),
+                max: ScoreBound::PosInf,
+                withscores: true,
+                limit: Some((2, -1)),
+            }
+        );
+        assert_eq!(
+            CommandParser::parse("zrangebyscore board -inf 5"),
+            Command::ZRangeByScore {
+                key: "board".to_string(),
+                min: ScoreBound::NegInf,
+                max: ScoreBound::Inclusive(5.0),
+                withscores: false,
+                limit: None,
+            }
+        );
+        let reversed = Command::ZRevRangeByScore {
+            key: "board".to_string(),
+            max: ScoreBound::Inclusive(5.0),
+            min: ScoreBound::Exclusive(-2.5),
+            withscores: false,
+            limit: Some((0, 3)),
+        };
+        assert_eq!(CommandParser::parse("ZREVRANGEBYSCORE board 5 (-2.5 LIMIT 0 3"), reversed);
+        assert_eq!(CommandParser::parse("ZRANGEBYSCOREREV board 5 (-2.5 limit 0 3"), reversed);
+
+        for invalid in [
+            "ZRANGEBYSCORE board 1",
+            "ZRANGEBYSCORE board low 5",
+            "ZRANGEBYSCORE board (nan 5",
+            "ZRANGEBYSCORE board 1 5 LIMIT 0",
+            "ZRANGEBYSCORE board 1 5 LIMIT zero 1",
+            "ZRANGEBYSCORE board 1 5 REV",
+        ] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_zpop_commands() {
+        assert_eq!(CommandParser::parse("ZPOPMIN Board"), Command::ZPopMin("board".to_string(), None));
+        assert_eq!(CommandParser::parse("zpopmax board 3"), Command::ZPopMax("board".to_string(), Some(3)));
+        assert_eq!(
+            CommandParser::parse("BZPOPMIN a B 1.5"),
+            Command::BZPopMin(vec!["a".to_string(), "b".to_string()], 1.5)
+        );
+        assert_eq!(CommandParser::parse("BZPOPMAX a 0"), Command::BZPopMax(vec!["a".to_string()], 0.0));
+        for invalid in ["ZPOPMIN", "ZPOPMIN board -1", "ZPOPMAX board 1 2", "BZPOPMIN a", "BZPOPMAX a -1"] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_zset_algebra_commands() {
+        let keys = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(CommandParser::parse("ZDIFF 2 A b"), Command::ZDiff(2, keys.clone(), false));
+        assert_eq!(CommandParser::parse("zdiff 2 a b withscores"), Command::ZDiff(2, keys.clone(), true));
+        assert_eq!(
+            CommandParser::parse("ZUNION 2 a b WEIGHTS 2 0.5 AGGREGATE max WITHSCORES"),
+            Command::ZUnion(2, keys.clone(), vec![2.0, 0.5], AggType::Max, true)
+        );
+        assert_eq!(
+            CommandParser::parse("ZINTER 2 a b"),
+            Command::ZInter(2, keys, Vec::new(), AggType::Sum, false)
+        );
+        // Everything after numkeys keys is an option
+        assert_eq!(
+            CommandParser::parse("ZINTER 1 a AGGREGATE MIN"),
+            Command::ZInter(1, vec!["a".to_string()], Vec::new(), AggType::Min, false)
+        );
+
+        for invalid in [
+            "ZDIFF 0 a",
+            "ZDIFF 3 a b",
+            "ZDIFF 2 a b WEIGHTS 1 2",
+            "ZUNION 2 a b WEIGHTS 1",
+            "ZUNION 2 a b WEIGHTS 1 nan",
+            "ZUNION 2 a b AGGREGATE AVG",
+            "ZINTER 2 a b c",
+            "ZINTER x a",
+        ] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_lex_range_commands() {
+        assert_eq!(
+            CommandParser::parse("ZRANGEBYLEX Letters [a (C LIMIT 1 2"),
+            Command::ZRangeByLex {
+                key: "letters".to_string(),
+                min: LexBound::Inclusive("a".to_string()),
+                max: LexBound::Exclusive("C".to_string()),
+                limit: Some((1, 2)),
+                rev: false,
+            }
+        );
+        // ZREVRANGEBYLEX takes max first; the command stores the bounds by meaning
+        assert_eq!(
+            CommandParser::parse("ZREVRANGEBYLEX letters + ["),
+            Command::ZRangeByLex {
+                key: "letters".to_string(),
+                min: LexBound::Inclusive(String::new()),
+                max: LexBound::PosInf,
+                limit: None,
+                rev: true,
+            }
+        );
+        assert_eq!(
+            CommandParser::parse("ZLEXCOUNT letters - +"),
+            Command::ZLexCount("letters".to_string(), LexBound::NegInf, LexBound::PosInf)
+        );
+        for invalid in [
+            "ZRANGEBYLEX letters a c",
+            "ZRANGEBYLEX letters [a",
+            "ZRANGEBYLEX letters [a [c LIMIT 1",
+            "ZRANGEBYLEX letters [a [c WITHSCORES",
+            "ZLEXCOUNT letters - + extra",
+            "ZLEXCOUNT letters -inf +",
+        ] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_memory_usage_command() {
+        assert_eq!(CommandParser::parse("MEMORY USAGE Key"), Command::MemoryUsage("key".to_string(), None));
+        assert_eq!(CommandParser::parse("memory usage key samples 0"), Command::MemoryUsage("key".to_string(), Some(0)));
+        for invalid in ["MEMORY USAGE", "MEMORY STATS", "MEMORY USAGE key SAMPLES", "MEMORY USAGE key SAMPLES -1", "MEMORY USAGE key COUNT 5"] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_object_freq_command() {
+        assert_eq!(CommandParser::parse("OBJECT FREQ Key"), Command::ObjectFreq("key".to_string()));
+        assert_eq!(CommandParser::parse("object freq key"), Command::ObjectFreq("key".to_string()));
+        assert_eq!(CommandParser::parse("OBJECT FREQ"), Command::Unknown("OBJECT FREQ".to_string()));
+    }
+
+    #[test]
+    fn test_is_write() {
+        assert!(CommandParser::parse("SET k v").is_write());
+        assert!(CommandParser::parse("ZPOPMIN z").is_write());
+        assert!(CommandParser::parse("SORT l STORE dst").is_write());
+        assert!(!CommandParser::parse("SORT l").is_write());
+        assert!(!CommandParser::parse("GET k").is_write());
+        assert!(!CommandParser::parse("WAIT 1 0").is_write());
+        assert!(!CommandParser::parse("BGSAVE").is_write());
+    }
+
+    #[test]
+    fn test_bgsave_command() {
+        assert_eq!(CommandParser::parse("bgsave"), Command::BgSave);
+        assert_eq!(CommandParser::parse("BGSAVE SCHEDULE"), Command::Unknown("BGSAVE SCHEDULE".to_string()));
+        assert_eq!(CommandParser::parse("BGREWRITEAOF"), Command::BgRewriteAof);
+    }
+
+    #[test]
+    fn test_auth_and_acl_commands() {
+        assert_eq!(CommandParser::parse("AUTH Secret"), Command::Auth(None, "Secret".to_string()));
+        assert_eq!(
+            CommandParser::parse("auth reader Secret"),
+            Command::Auth(Some("reader".to_string()), "Secret".to_string())
+        );
+        assert_eq!(CommandParser::parse("AUTH"), Command::Unknown("AUTH".to_string()));
+        assert_eq!(CommandParser::parse("acl whoami"), Command::AclWhoAmI);
+        assert_eq!(CommandParser::parse("ACL LIST"), Command::AclList);
+        assert_eq!(CommandParser::parse("FLUSHALL"), Command::FlushAll);
+    }
+
+    #[test]
+    fn test_replicaof_and_sync() {
+        assert_eq!(
+            CommandParser::parse("REPLICAOF Master.local 6380"),
+            Command::ReplicaOf(Some(("Master.local".to_string(), 6380)))
+        );
+        assert_eq!(CommandParser::parse("replicaof no one"), Command::ReplicaOf(None));
+        assert_eq!(CommandParser::parse("SLAVEOF NO ONE"), Command::ReplicaOf(None));
+        assert_eq!(CommandParser::parse("SYNC"), Command::Sync);
+        for invalid in ["REPLICAOF", "REPLICAOF host", "REPLICAOF host 70000", "SYNC now"] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+        assert_eq!(CommandParser::parse("REPLICAOF NO ONE").categories(), &["admin", "dangerous"]);
+        assert!(!CommandParser::parse("SYNC").is_write());
+        assert_eq!(CommandParser::parse("PSYNC ? -1"), Command::PSync("?".to_string(), -1));
+        assert_eq!(CommandParser::parse("psync 8f1cA 120"), Command::PSync("8f1cA".to_string(), 120));
+        assert_eq!(CommandParser::parse("REPLCONF ack 42"), Command::ReplConfAck(42));
+        for invalid in ["PSYNC ?", "PSYNC id one", "REPLCONF ACK", "REPLCONF ACK -1", "REPLCONF GETACK *"] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_scan_commands() {
+        assert_eq!(CommandParser::parse("HSCAN Hash 0"), Command::HScan("hash".to_string(), 0, None, None));
+        assert_eq!(
+            CommandParser::parse("sscan set 42 match Tag:* count 5"),
+            Command::SScan("set".to_string(), 42, Some("Tag:*".to_string()), Some(5))
+        );
+        assert_eq!(
+            CommandParser::parse("ZSCAN z 7 COUNT 1 MATCH m"),
+            Command::ZScan("z".to_string(), 7, Some("m".to_string()), Some(1))
+        );
+        for invalid in ["HSCAN h", "HSCAN h -1", "HSCAN h 0 COUNT", "HSCAN h 0 COUNT 0", "SSCAN s 0 LIMIT 1"] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+        assert_eq!(CommandParser::parse("ZSCAN z 0").categories(), &["read"]);
+    }
+
+    #[test]
+    fn test_categories() {
+        assert_eq!(CommandParser::parse("GET k").categories(), &["read"]);
+        assert_eq!(CommandParser::parse("SMEMBERS k").categories(), &["read"]);
+        assert_eq!(CommandParser::parse("SET k v").categories(), &["write"]);
+        assert_eq!(CommandParser::parse("FLUSHALL").categories(), &["write", "admin", "dangerous"]);
+        assert_eq!(CommandParser::parse("CONFIG REWRITE").categories(), &["admin", "dangerous"]);
+        assert_eq!(CommandParser::parse("PUBLISH c m").categories(), &["pubsub"]);
+        assert_eq!(CommandParser::parse("AUTH p").categories(), &["connection"]);
+    }
+
+    #[test]
+    fn test_geoadd_command() {
+        assert_eq!(
+            CommandParser::parse("GEOADD Sicily XX CH 13.361389 38.115556 Palermo 15.087269 37.502669 Catania"),
+            Command::GeoAdd {
+                key: "sicily".to_string(),
+                options: GeoAddOptions { nx: false, xx: true, ch: true },
+                items: vec![
+                    (13.361389, 38.115556, "Palermo".to_string()),
+                    (15.087269, 37.502669, "Catania".to_string()),
+                ],
+            }
+        );
+        // Out-of-range coordinates still parse; storage rejects them with the pair
+        assert!(matches!(CommandParser::parse("GEOADD sicily 200 100 nowhere"), Command::GeoAdd { .. }));
+        for invalid in [
+            "GEOADD sicily",
+            "GEOADD sicily 13.361389 38.115556",
+            "GEOADD sicily NX XX 13.361389 38.115556 Palermo",
+            "GEOADD sicily GT 13.361389 38.115556 Palermo",
+            "GEOADD sicily east 38.115556 Palermo",
+            "GEOADD sicily inf 38.115556 Palermo",
+        ] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_geosearch_command() {
+        let params = GeoSearchParams {
+            origin: GeoOrigin::Member("Palermo".to_string()),
+            shape: GeoShape::Box(400.0, 200.0),
+            unit: GeoUnit::Kilometers,
+            descending: Some(true),
+            count: Some(3),
+            any: true,
+            with_coord: false,
+            with_dist: false,
+            with_hash: false,
+        };
+        assert_eq!(
+            CommandParser::parse("GEOSEARCH Sicily FROMMEMBER Palermo BYBOX 400 200 KM DESC COUNT 3 ANY STOREDIST Near"),
+            Command::GeoSearch {
+                key: "sicily".to_string(),
+                params: params.clone(),
+                store: Some((GeoStoreMode::Distance, "near".to_string())),
+            }
+        );
+        assert_eq!(params.to_args().join(" "), "FROMMEMBER Palermo BYBOX 400 200 km DESC COUNT 3 ANY");
+        assert!(matches!(
+            CommandParser::parse("geosearch sicily fromlonlat 15 37 byradius 200 mi withcoord withdist withhash"),
+            Command::GeoSearch { store: None, .. }
+        ));
+        for invalid in [
+            "GEOSEARCH sicily",
+            "GEOSEARCH sicily FROMLONLAT 15 37",
+            "GEOSEARCH sicily BYRADIUS 1 km",
+            "GEOSEARCH sicily FROMLONLAT 15 37 FROMMEMBER Palermo BYRADIUS 1 km",
+            "GEOSEARCH sicily FROMLONLAT 15 37 BYRADIUS 1 km BYBOX 1 1 km",
+            "GEOSEARCH sicily FROMLONLAT 15 91 BYRADIUS 1 km",
+            "GEOSEARCH sicily FROMLONLAT 15 37 BYRADIUS -1 km",
+            "GEOSEARCH sicily FROMLONLAT 15 37 BYRADIUS 1 yards",
+            "GEOSEARCH sicily FROMLONLAT 15 37 BYRADIUS 1 km ANY",
+            "GEOSEARCH sicily FROMLONLAT 15 37 BYRADIUS 1 km COUNT 0",
+            "GEOSEARCH sicily FROMLONLAT 15 37 BYRADIUS 1 km WITHDIST STORE near",
+            "GEOSEARCH sicily FROMLONLAT 15 37 BYRADIUS 1 km STORE",
+        ] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_georadius_commands_are_geosearch() {
+        assert_eq!(
+            CommandParser::parse("GEORADIUS Sicily 15 37 200 km ASC COUNT 2 STORE Near"),
+            CommandParser::parse("GEOSEARCH Sicily FROMLONLAT 15 37 BYRADIUS 200 km ASC COUNT 2 STORE Near")
+        );
+        assert!(matches!(CommandParser::parse("GEORADIUS Sicily 15 37 200 km STORE Near"), Command::GeoSearch { store: Some(_), .. }));
+        assert_eq!(
+            CommandParser::parse("georadiusbymember Sicily Palermo 100 mi withcoord"),
+            CommandParser::parse("GEOSEARCH Sicily FROMMEMBER Palermo BYRADIUS 100 mi WITHCOORD")
+        );
+        for invalid in [
+            "GEORADIUS sicily 15 37 200",
+            "GEORADIUS sicily 15 37 200 yards",
+            "GEORADIUS sicily 15 37 200 km FROMMEMBER Palermo",
+            "GEORADIUS sicily 15 37 200 km BYBOX 1 1 km",
+            "GEORADIUSBYMEMBER sicily Palermo 200",
+            "GEORADIUSBYMEMBER sicily Palermo 200 km WITHDIST STORE near",
+        ] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_stream_commands() {
+        let id = |ms, seq| StreamEntryId { ms, seq };
+        assert_eq!(
+            CommandParser::parse("XADD Events * kind click"),
+            Command::XAdd("events".to_string(), XAddId::Auto, vec![("kind".to_string(), "click".to_string())])
+        );
+        assert_eq!(
+            CommandParser::parse("XADD events 5 a b"),
+            Command::XAdd("events".to_string(), XAddId::Explicit(id(5, 0)), vec![("a".to_string(), "b".to_string())])
+        );
+        assert_eq!(
+            CommandParser::parse("XRANGE events - + COUNT 2"),
+            Command::XRange("events".to_string(), StreamEntryId::MIN, StreamEntryId::MAX, Some(2))
+        );
+        assert_eq!(
+            CommandParser::parse("XRANGE events 1 2"),
+            Command::XRange("events".to_string(), id(1, 0), id(2, u64::MAX), None)
+        );
+        assert_eq!(
+            CommandParser::parse("XGROUP CREATE events readers $ MKSTREAM"),
+            Command::XGroupCreate("events".to_string(), "readers".to_string(), None, true)
+        );
+        assert_eq!(
+            CommandParser::parse("XREADGROUP GROUP readers alice NOACK COUNT 3 STREAMS a b > 0"),
+            Command::XReadGroup {
+                group: "readers".to_string(),
+                consumer: "alice".to_string(),
+                count: Some(3),
+                noack: true,
+                streams: vec![("a".to_string(), None), ("b".to_string(), Some(id(0, 0)))],
+            }
+        );
+        assert_eq!(CommandParser::parse("XINFO STREAM events FULL"), Command::XInfo("events".to_string(), XInfoSubcommand::Full(None)));
+        assert_eq!(
+            CommandParser::parse("XINFO STREAM events FULL COUNT 0"),
+            Command::XInfo("events".to_string(), XInfoSubcommand::Full(Some(0)))
+        );
+        assert_eq!(
+            CommandParser::parse("XINFO CONSUMERS events readers"),
+            Command::XInfo("events".to_string(), XInfoSubcommand::Consumers("readers".to_string()))
+        );
+        for invalid in [
+            "XADD events * kind",
+            "XADD events 1-x kind click",
+            "XRANGE events + - COUNT",
+            "XGROUP CREATE events readers 0 NOMKSTREAM",
+            "XREADGROUP GROUP readers alice STREAMS a b >",
+            "XREADGROUP readers alice STREAMS a >",
+            "XINFO HELP events",
+            "XINFO STREAM events FULL COUNT x",
+        ] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_xread_command() {
+        assert_eq!(
+            CommandParser::parse("XREAD BLOCK 0 COUNT 2 STREAMS A b $ 5"),
+            Command::XRead {
+                count: Some(2),
+                block: Some(0),
+                streams: vec![("a".to_string(), None), ("b".to_string(), Some(StreamEntryId { ms: 5, seq: 0 }))],
+            }
+        );
+        assert_eq!(
+            CommandParser::parse("xread streams events 1-2"),
+            Command::XRead { count: None, block: None, streams: vec![("events".to_string(), Some(StreamEntryId { ms: 1, seq: 2 }))] }
+        );
+        for invalid in ["XREAD STREAMS a", "XREAD BLOCK -1 STREAMS a $", "XREAD STREAMS a >", "XREAD COUNT 1 a $"] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_xautoclaim_command() {
+        assert_eq!(
+            CommandParser::parse("XAUTOCLAIM Events readers Bob 3600000 0-0 COUNT 25"),
+            Command::XAutoClaim {
+                key: "events".to_string(),
+                group: "readers".to_string(),
+                consumer: "Bob".to_string(),
+                min_idle_ms: 3_600_000,
+                start: StreamEntryId { ms: 0, seq: 0 },
+                count: 25,
+            }
+        );
+        assert!(matches!(CommandParser::parse("xautoclaim events readers bob 0 -"), Command::XAutoClaim { count: 100, .. }));
+        for invalid in [
+            "XAUTOCLAIM events readers bob 0",
+            "XAUTOCLAIM events readers bob -1 0",
+            "XAUTOCLAIM events readers bob 0 0 COUNT 0",
+            "XAUTOCLAIM events readers bob 0 0 LIMIT 5",
+        ] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_xdel_command() {
+        assert_eq!(
+            CommandParser::parse("XDEL Events 1-0 2"),
+            Command::XDel("events".to_string(), vec!["1-0".to_string(), "2".to_string()])
+        );
+        for invalid in ["XDEL events", "XDEL events 1-0 nope"] {
+            assert_eq!(CommandParser::parse(invalid), Command::Unknown(invalid.to_string()));
+        }
+    }
+}
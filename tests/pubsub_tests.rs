@@ -0,0 +1,112 @@
+use redis_imitate::config::config::OutputBufferLimit;
+use redis_imitate::network::pubsub::{Outbox, PubSub};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_counts_receivers() {
+        let pubsub = PubSub::new();
+        let (first, mut first_messages) = Outbox::unbounded();
+        let (second, mut second_messages) = Outbox::unbounded();
+        assert!(pubsub.subscribe("news", 1, first.clone()));
+        assert!(!pubsub.subscribe("news", 1, first));
+        assert!(pubsub.subscribe("news", 2, second));
+
+        assert_eq!(pubsub.publish("news", "hello"), 2);
+        assert_eq!(first_messages.try_recv().unwrap(), b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n".to_vec());
+        assert_eq!(second_messages.try_recv().unwrap(), b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n".to_vec());
+        assert!(first_messages.try_recv().is_err());
+
+        pubsub.unsubscribe("news", 1);
+        assert_eq!(pubsub.publish("news", "again"), 1);
+        pubsub.unsubscribe_all(2);
+        assert_eq!(pubsub.publish("news", "nobody"), 0);
+    }
+
+    #[test]
+    fn test_dead_subscribers_are_pruned() {
+        let pubsub = PubSub::new();
+        let (alive, _alive_messages) = Outbox::unbounded();
+        let (dead, dead_messages) = Outbox::unbounded();
+        pubsub.subscribe("news", 1, alive);
+        pubsub.subscribe("news", 2, dead);
+        drop(dead_messages);
+
+        assert_eq!(pubsub.publish("news", "first"), 1);
+        // The dead subscriber is gone, so resubscribing its id works again
+        let (revived, _revived_messages) = Outbox::unbounded();
+        assert!(pubsub.subscribe("news", 2, revived));
+        assert_eq!(pubsub.publish("news", "second"), 2);
+    }
+
+    #[test]
+    fn test_pattern_subscribers_get_pmessage_frames() {
+        let pubsub = PubSub::new();
+        let (outbox, mut messages) = Outbox::unbounded();
+        assert!(pubsub.psubscribe("news.*", 1, outbox.clone()));
+        assert!(!pubsub.psubscribe("news.*", 1, outbox.clone()));
+        assert!(pubsub.psubscribe("*", 1, outbox.clone()));
+        assert!(pubsub.subscribe("news.tech", 1, outbox));
+
+        // One frame for the channel and one for each matching pattern
+        assert_eq!(pubsub.publish("news.tech", "rust"), 3);
+        let mut frames = Vec::new();
+        while let Ok(frame) = messages.try_recv() {
+            frames.push(String::from_utf8(frame).unwrap());
+        }
+        assert_eq!(frames[0], "*3\r\n$7\r\nmessage\r\n$9\r\nnews.tech\r\n$4\r\nrust\r\n");
+        assert!(frames.contains(&"*4\r\n$8\r\npmessage\r\n$6\r\nnews.*\r\n$9\r\nnews.tech\r\n$4\r\nrust\r\n".to_string()));
+        assert!(frames.contains(&"*4\r\n$8\r\npmessage\r\n$1\r\n*\r\n$9\r\nnews.tech\r\n$4\r\nrust\r\n".to_string()));
+
+        assert_eq!(pubsub.publish("weather", "sunny"), 1);
+        pubsub.punsubscribe("*", 1);
+        assert_eq!(pubsub.publish("weather", "rain"), 0);
+        pubsub.unsubscribe_all(1);
+        assert_eq!(pubsub.publish("news.tech", "again"), 0);
+    }
+
+    #[test]
+    fn test_subscriber_over_its_output_limit_is_cut_off() {
+        let pubsub = PubSub::new();
+        let disconnections = Arc::new(AtomicU64::new(0));
+        let limit = OutputBufferLimit { hard_limit_bytes: 100, ..OutputBufferLimit::default() };
+        let (outbox, mut messages) = Outbox::with_limit(7, limit, Arc::clone(&disconnections));
+        let closed = Arc::new(AtomicBool::new(false));
+        let hook_flag = Arc::clone(&closed);
+        messages.on_overflow(move || hook_flag.store(true, Ordering::SeqCst));
+        pubsub.subscribe("news", 7, outbox.clone());
+
+        // Each frame is 50 bytes, so the third unread one breaks the limit
+        for _ in 0..2 {
+            assert_eq!(pubsub.publish("news", "0123456789abcdef"), 1);
+        }
+        assert_eq!(outbox.pending(), 100);
+        assert!(!closed.load(Ordering::SeqCst));
+        assert_eq!(pubsub.publish("news", "0123456789abcdef"), 0);
+        assert!(closed.load(Ordering::SeqCst));
+        assert_eq!(disconnections.load(Ordering::SeqCst), 1);
+
+        // Nothing more reaches the client, not even what was already queued
+        assert!(messages.try_recv().is_err());
+        assert_eq!(pubsub.publish("news", "again"), 0);
+        assert_eq!(disconnections.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_reading_keeps_a_subscriber_under_its_limit() {
+        let pubsub = PubSub::new();
+        let limit = OutputBufferLimit { hard_limit_bytes: 100, ..OutputBufferLimit::default() };
+        let (outbox, mut messages) = Outbox::with_limit(1, limit, Arc::new(AtomicU64::new(0)));
+        pubsub.subscribe("news", 1, outbox.clone());
+
+        for _ in 0..10 {
+            assert_eq!(pubsub.publish("news", "0123456789abcdef"), 1);
+            assert!(messages.try_recv().is_ok());
+        }
+        assert_eq!(outbox.pending(), 0);
+    }
+}
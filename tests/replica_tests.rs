@@ -0,0 +1,94 @@
+use redis_imitate::commands::parser::CommandParser;
+use redis_imitate::network::pubsub::Outbox;
+use redis_imitate::network::replica::{full_sync, replicated, Replicas};
+use redis_imitate::storage::memory::MemoryStorage;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay(command: &str, response: &str) -> Option<String> {
+        replicated(&CommandParser::parse(command), response).map(|args| args.join(" "))
+    }
+
+    #[test]
+    fn test_writes_are_replayed_as_sent() {
+        assert_eq!(replay("SET key value", "OK").as_deref(), Some("SET key value"));
+        assert_eq!(replay("HMSET h a 1 b 2", "OK").as_deref(), Some("HSET h a 1 b 2"));
+        assert_eq!(replay("ZADD z XX CH 1.5 m", "1").as_deref(), Some("ZADD z XX CH 1.5 m"));
+        assert_eq!(
+            replay("SORT list BY w_* LIMIT 0 2 GET # DESC ALPHA STORE out", "2").as_deref(),
+            Some("SORT list BY w_* LIMIT 0 2 GET # DESC ALPHA STORE out")
+        );
+        assert_eq!(replay("XDEL s 1-0 2", "1").as_deref(), Some("XDEL s 1-0 2"));
+        assert_eq!(replay("FLUSHALL", "OK").as_deref(), Some("FLUSHALL"));
+    }
+
+    #[test]
+    fn test_results_replace_what_could_replay_differently() {
+        assert_eq!(replay("HINCRBYFLOAT h f 0.1", "0.30000000000000004").as_deref(), Some("HSET h f 0.30000000000000004"));
+        assert_eq!(replay("LPOP list", "(nil)"), None);
+        assert_eq!(replay("PERSIST key", "0"), None);
+        assert_eq!(replay("XDEL s 1-0", "0"), None);
+        assert_eq!(replay("INCR key", "ERR value is not an integer"), None);
+        assert_eq!(replay("SADD key m", "WRONGTYPE Operation against a key holding the wrong kind of value"), None);
+        assert_eq!(replay("GET key", "value"), None);
+        // Left to the executor, which sends the pop that happened
+        assert_eq!(replay("BLPOP list 0", "list\na"), None);
+    }
+
+    #[test]
+    fn test_writes_during_full_sync_follow_it() {
+        let replicas = Replicas::new(1024);
+        let (outbox, mut receiver) = Outbox::unbounded();
+        assert_eq!(replicas.attach(7, "127.0.0.1:1", outbox), 0);
+        assert!(replicas.info().contains("slave0:id=7,addr=127.0.0.1:1,state=wait_bgsave"));
+
+        assert_eq!(replicas.propagate(&["INCR".to_string(), "counter".to_string()]), Some(14));
+        assert!(receiver.try_recv().is_err());
+        let mut storage = MemoryStorage::new();
+        storage.set("counter".to_string(), "1".to_string());
+        replicas.start_streaming(7, full_sync(&storage.frozen(), "id", 0));
+        replicas.propagate(&["DEL".to_string(), "counter".to_string()]);
+
+        assert_eq!(receiver.try_recv().unwrap(), b"FULLRESYNC id 0\r\n$17\r\nSTRING counter 1\n");
+        assert_eq!(receiver.try_recv().unwrap(), b"INCR counter\r\n");
+        assert_eq!(receiver.try_recv().unwrap(), b"DEL counter\r\n");
+        assert!(replicas.info().contains("state=online"));
+
+        replicas.detach(7);
+        assert_eq!(replicas.count(), 0);
+    }
+
+    #[test]
+    fn test_psync_continues_from_the_backlog() {
+        let replicas = Replicas::new(32);
+        let args = |line: &str| line.split(' ').map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(replicas.propagate(&args("SET a 1")), None);
+
+        let (outbox, _receiver) = Outbox::unbounded();
+        replicas.attach(1, "127.0.0.1:1", outbox);
+        assert_eq!(replicas.propagate(&args("SET a 1")), Some(9));
+        assert_eq!(replicas.propagate(&args("SET b 2")), Some(18));
+        replicas.detach(1);
+        // Still kept with no replica attached
+        assert_eq!(replicas.propagate(&args("SET c 3")), Some(27));
+
+        let (outbox, mut receiver) = Outbox::unbounded();
+        assert!(replicas.resume(2, "127.0.0.1:2", outbox, "id", 9, "id"));
+        assert_eq!(receiver.try_recv().unwrap(), b"CONTINUE id\r\nSET b 2\r\nSET c 3\r\n");
+        assert_eq!(replicas.propagate(&args("SET d 4")), Some(36));
+        assert_eq!(receiver.try_recv().unwrap(), b"SET d 4\r\n");
+        assert!(replicas.ack(2, 36));
+        assert!(replicas.info().contains("slave0:id=2,addr=127.0.0.1:2,state=online,offset=36"));
+
+        // Offsets the backlog has dropped, offsets not reached yet and other streams need a full sync
+        for (id, from) in [("id", 0), ("id", 37), ("other", 36)] {
+            let (outbox, _receiver) = Outbox::unbounded();
+            assert!(!replicas.resume(3, "127.0.0.1:3", outbox, id, from, "id"));
+        }
+        assert!(replicas.backlog_info().contains("repl_backlog_first_byte_offset:4\nrepl_backlog_histlen:32"));
+        assert_eq!(replicas.sync_stats(), "sync_full:1\nsync_partial_ok:1\nsync_partial_err:3\n");
+        assert!(!replicas.ack(3, 36));
+    }
+}
@@ -0,0 +1,1345 @@
+use redis_imitate::config::config::{AclUser, Config, OutputBufferLimit, SaveCondition};
+use redis_imitate::network::server::{format_address, LoopbackCheck, PeerCheck, Server};
+use redis_imitate::storage::memory::MemoryStorage;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every test runs against both the threaded and the tokio front end
+    macro_rules! for_both_servers {
+        ($($test:ident),* $(,)?) => {
+            mod threaded {
+                $(#[test] fn $test() { super::$test(false) })*
+            }
+            mod async_tokio {
+                $(#[test] fn $test() { super::$test(true) })*
+            }
+        };
+    }
+
+    for_both_servers!(
+        test_graceful_shutdown_saves_snapshot,
+        test_shutdown_command_saves_and_stops_server,
+        test_shutdown_nosave_skips_snapshot,
+        test_client_that_stops_reading_is_disconnected,
+        test_connections_over_the_limit_are_rejected,
+        test_publish_reaches_subscriber,
+        test_subscribed_context_rejects_other_commands,
+        test_pubsub_frames_are_byte_exact_resp,
+        test_subscriber_that_never_reads_is_disconnected,
+        test_slot_assignments_are_shared_by_connections,
+        test_restart_serves_the_saved_snapshot,
+        test_handle_reports_its_address_and_frees_the_port,
+        test_blpop_waits_for_push_and_shutdown_releases_it,
+        test_too_big_inline_request_closes_only_that_client,
+        test_acl_read_only_user,
+        test_maintenance_tasks_run_until_shutdown,
+        test_replica_follows_master,
+        test_replica_resumes_with_partial_resync,
+        test_full_sync_sends_snapshot_while_master_takes_writes,
+        test_listens_on_every_bind_address,
+        test_protected_mode_turns_away_remote_clients,
+        test_snapshot_follows_save_rules,
+        test_acceptor_threads_share_one_address,
+        test_stalled_clients_are_disconnected,
+        test_keyspace_notifications_follow_config_set,
+    );
+
+    // Helper function to build a config bound to a free local port
+    fn test_config(name: &str, async_server: bool) -> Config {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut config = Config::new();
+        config.host = "127.0.0.1".to_string();
+        config.port = port;
+        config.max_connections = 4;
+        config.async_server = async_server;
+        config.snapshot_path = std::env::temp_dir()
+            .join(format!("{}_{}.snapshot", name, port))
+            .to_string_lossy()
+            .into_owned();
+        config
+    }
+
+    fn connect(config: &Config) -> TcpStream {
+        for _ in 0..50 {
+            if let Ok(stream) = TcpStream::connect((config.host.as_str(), config.port)) {
+                return stream;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("server did not start on port {}", config.port);
+    }
+
+    // Reads exactly as many bytes as `expected` holds and compares them
+    fn expect_bytes(reader: &mut impl Read, expected: &str) {
+        let mut actual = vec![0; expected.len()];
+        reader.read_exact(&mut actual).unwrap();
+        assert_eq!(String::from_utf8_lossy(&actual), expected);
+    }
+
+    fn test_graceful_shutdown_saves_snapshot(async_server: bool) {
+        let config = test_config("graceful_shutdown", async_server);
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+
+        let client = connect(&config);
+        let mut reader = BufReader::new(client);
+        let mut response = String::new();
+        writeln!(reader.get_ref(), "SET key value").unwrap();
+        reader.read_line(&mut response).unwrap();
+        assert_eq!(response.trim(), "OK");
+        drop(reader);
+
+        server.shutdown().unwrap();
+
+        let snapshot = std::fs::read_to_string(&snapshot_path).unwrap();
+        assert!(snapshot.contains("STRING key value"));
+        std::fs::remove_file(&snapshot_path).unwrap();
+    }
+
+    fn test_shutdown_command_saves_and_stops_server(async_server: bool) {
+        let config = test_config("shutdown_save", async_server);
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+
+        // A second, idle client must not keep the server alive
+        let _idle = connect(&config);
+        let client = connect(&config);
+        let mut reader = BufReader::new(client);
+        let mut response = String::new();
+        writeln!(reader.get_ref(), "SET key value").unwrap();
+        reader.read_line(&mut response).unwrap();
+        assert_eq!(response.trim(), "OK");
+
+        // No reply on success, the connection is simply closed
+        writeln!(reader.get_ref(), "SHUTDOWN SAVE").unwrap();
+        response.clear();
+        assert_eq!(reader.read_line(&mut response).unwrap(), 0);
+        server.join().unwrap();
+
+        let snapshot = std::fs::read_to_string(&snapshot_path).unwrap();
+        assert!(snapshot.contains("STRING key value"));
+        std::fs::remove_file(&snapshot_path).unwrap();
+    }
+
+    fn test_shutdown_nosave_skips_snapshot(async_server: bool) {
+        let config = test_config("shutdown_nosave", async_server);
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+
+        let client = connect(&config);
+        let mut reader = BufReader::new(client);
+        let mut response = String::new();
+        writeln!(reader.get_ref(), "SET key value").unwrap();
+        reader.read_line(&mut response).unwrap();
+        writeln!(reader.get_ref(), "SHUTDOWN NOSAVE").unwrap();
+        response.clear();
+        assert_eq!(reader.read_line(&mut response).unwrap(), 0);
+        server.join().unwrap();
+
+        assert!(!std::path::Path::new(&snapshot_path).exists());
+    }
+
+    fn test_client_that_stops_reading_is_disconnected(async_server: bool) {
+        let mut config = test_config("write_timeout", async_server);
+        config.max_connections = 1;
+        config.tcp_write_timeout_ms = 200;
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+
+        // Ask for far more data than the socket buffers hold, then never read it
+        let stalled = connect(&config);
+        let mut writer = stalled.try_clone().unwrap();
+        let big_value = "x".repeat(1024 * 1024);
+        let flood = thread::spawn(move || {
+            writeln!(writer, "SET big {}", big_value)?;
+            for _ in 0..200 {
+                writeln!(writer, "GET big")?;
+            }
+            Ok::<(), std::io::Error>(())
+        });
+
+        // The only connection slot must be freed up for the next client;
+        // until the write timeout fires, newcomers are turned away
+        let mut response = String::new();
+        let mut reader = None;
+        for _ in 0..100 {
+            let client = connect(&config);
+            client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+            let mut attempt = BufReader::new(client);
+            writeln!(attempt.get_ref(), "SET key value").unwrap();
+            response.clear();
+            attempt.read_line(&mut response).unwrap();
+            if response.trim() != "ERR max number of clients reached" {
+                reader = Some(attempt);
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(response.trim(), "OK");
+
+        drop(reader);
+        let _ = flood.join().unwrap();
+        drop(stalled);
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    fn test_connections_over_the_limit_are_rejected(async_server: bool) {
+        let mut config = test_config("max_clients", async_server);
+        config.max_connections = 2;
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone());
+        let max_clients = server.max_clients();
+        let server = server.start().unwrap();
+
+        let mut readers = Vec::new();
+        let mut response = String::new();
+        for _ in 0..2 {
+            let mut reader = BufReader::new(connect(&config));
+            writeln!(reader.get_ref(), "SET key value").unwrap();
+            response.clear();
+            reader.read_line(&mut response).unwrap();
+            assert_eq!(response.trim(), "OK");
+            readers.push(reader);
+        }
+
+        // The third client is told why and closed instead of hanging
+        let mut rejected = BufReader::new(connect(&config));
+        response.clear();
+        rejected.read_line(&mut response).unwrap();
+        assert_eq!(response.trim(), "ERR max number of clients reached");
+        response.clear();
+        assert_eq!(rejected.read_line(&mut response).unwrap(), 0);
+
+        // Raising the limit at runtime lets the next client in
+        max_clients.store(3, Ordering::Relaxed);
+        let mut reader = BufReader::new(connect(&config));
+        writeln!(reader.get_ref(), "GET key").unwrap();
+        response.clear();
+        reader.read_line(&mut response).unwrap();
+        assert_eq!(response.trim(), "value");
+
+        writeln!(reader.get_ref(), "INFO clients").unwrap();
+        let mut info = String::new();
+        for _ in 0..3 {
+            reader.read_line(&mut info).unwrap();
+        }
+        assert!(info.ends_with("connected_clients:3\r\n"), "{}", info);
+        writeln!(reader.get_ref(), "INFO stats").unwrap();
+        info.clear();
+        for _ in 0..13 {
+            reader.read_line(&mut info).unwrap();
+        }
+        assert!(info.contains("total_connections_received:3\r\n"), "{}", info);
+        assert!(info.contains("rejected_connections:1\r\n"), "{}", info);
+
+        drop(reader);
+        drop(readers);
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    fn test_publish_reaches_subscriber(async_server: bool) {
+        let config = test_config("pubsub", async_server);
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+
+        let send = |reader: &mut BufReader<TcpStream>, command: &str| {
+            writeln!(reader.get_ref(), "{}", command).unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim().to_string()
+        };
+        let mut subscriber = BufReader::new(connect(&config));
+        subscriber.get_ref().set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let mut publisher = BufReader::new(connect(&config));
+
+        writeln!(subscriber.get_ref(), "SUBSCRIBE news").unwrap();
+        expect_bytes(&mut subscriber, "*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n");
+        assert_eq!(send(&mut publisher, "PUBLISH news hello world"), "1");
+        assert_eq!(send(&mut publisher, "PUBLISH sports goal"), "0");
+        expect_bytes(&mut subscriber, "*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$11\r\nhello world\r\n");
+
+        // Subscriber mode only accepts Pub/Sub commands and PING
+        assert_eq!(send(&mut subscriber, "GET key"), "ERR Command not allowed inside a subscribed context");
+        writeln!(subscriber.get_ref(), "PING").unwrap();
+        expect_bytes(&mut subscriber, "*3\r\n$4\r\npong\r\n$0\r\n\r\n:0\r\n");
+
+        // A channel and an overlapping pattern each deliver their own frame
+        writeln!(subscriber.get_ref(), "PSUBSCRIBE n*").unwrap();
+        expect_bytes(&mut subscriber, "*3\r\n$10\r\npsubscribe\r\n$2\r\nn*\r\n:2\r\n");
+        assert_eq!(send(&mut publisher, "PUBLISH news twice"), "2");
+        expect_bytes(&mut subscriber, "*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\ntwice\r\n");
+        expect_bytes(&mut subscriber, "*4\r\n$8\r\npmessage\r\n$2\r\nn*\r\n$4\r\nnews\r\n$5\r\ntwice\r\n");
+        writeln!(subscriber.get_ref(), "PUNSUBSCRIBE").unwrap();
+        expect_bytes(&mut subscriber, "*3\r\n$12\r\npunsubscribe\r\n$2\r\nn*\r\n:1\r\n");
+
+        writeln!(subscriber.get_ref(), "UNSUBSCRIBE").unwrap();
+        expect_bytes(&mut subscriber, "*3\r\n$11\r\nunsubscribe\r\n$4\r\nnews\r\n:0\r\n");
+        assert_eq!(send(&mut subscriber, "SET key value"), "OK");
+        assert_eq!(send(&mut publisher, "PUBLISH news again"), "0");
+
+        drop(subscriber);
+        drop(publisher);
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    fn test_subscribed_context_rejects_other_commands(async_server: bool) {
+        let config = test_config("pubsub_context", async_server);
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+
+        let send = |reader: &mut BufReader<TcpStream>, command: &str| {
+            writeln!(reader.get_ref(), "{}", command).unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim().to_string()
+        };
+        let mut subscriber = BufReader::new(connect(&config));
+        subscriber.get_ref().set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let mut other = BufReader::new(connect(&config));
+
+        assert_eq!(send(&mut subscriber, "CLIENT SETNAME listener"), "OK");
+        writeln!(subscriber.get_ref(), "SUBSCRIBE news").unwrap();
+        expect_bytes(&mut subscriber, "*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n");
+
+        for command in ["SET key value", "GET key", "PUBLISH news hello", "MULTI", "CLIENT GETNAME", "ECHO hi"] {
+            assert_eq!(send(&mut subscriber, command), "ERR Command not allowed inside a subscribed context", "{}", command);
+        }
+        // The rejected SET never ran
+        assert_eq!(send(&mut other, "GET key"), "(nil)");
+
+        writeln!(subscriber.get_ref(), "PING hello").unwrap();
+        expect_bytes(&mut subscriber, "*3\r\n$4\r\npong\r\n$5\r\nhello\r\n:0\r\n");
+
+        // The client stays subscribed while a pattern is left
+        writeln!(subscriber.get_ref(), "PSUBSCRIBE n*").unwrap();
+        expect_bytes(&mut subscriber, "*3\r\n$10\r\npsubscribe\r\n$2\r\nn*\r\n:2\r\n");
+        writeln!(subscriber.get_ref(), "UNSUBSCRIBE").unwrap();
+        expect_bytes(&mut subscriber, "*3\r\n$11\r\nunsubscribe\r\n$4\r\nnews\r\n:1\r\n");
+        assert_eq!(send(&mut subscriber, "GET key"), "ERR Command not allowed inside a subscribed context");
+
+        // RESET leaves the subscribed context and clears the session
+        assert_eq!(send(&mut subscriber, "RESET"), "RESET");
+        assert_eq!(send(&mut other, "PUBLISH news again"), "0");
+        assert_eq!(send(&mut subscriber, "CLIENT GETNAME"), "(nil)");
+        assert_eq!(send(&mut subscriber, "SET key value"), "OK");
+        assert_eq!(send(&mut subscriber, "PING"), "PONG");
+
+        drop(subscriber);
+        drop(other);
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    fn test_pubsub_frames_are_byte_exact_resp(async_server: bool) {
+        let config = test_config("pubsub_resp", async_server);
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+
+        let mut subscriber = connect(&config);
+        subscriber.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let mut publisher = BufReader::new(connect(&config));
+
+        // One array per channel, each carrying the running subscription count
+        subscriber.write_all(b"SUBSCRIBE first second\r\n").unwrap();
+        expect_bytes(&mut subscriber, concat!(
+            "*3\r\n$9\r\nsubscribe\r\n$5\r\nfirst\r\n:1\r\n",
+            "*3\r\n$9\r\nsubscribe\r\n$6\r\nsecond\r\n:2\r\n",
+        ));
+
+        writeln!(publisher.get_ref(), "PUBLISH second héllo there").unwrap();
+        let mut response = String::new();
+        publisher.read_line(&mut response).unwrap();
+        assert_eq!(response, "1\r\n");
+        // Bulk lengths count bytes, not characters
+        expect_bytes(&mut subscriber, "*3\r\n$7\r\nmessage\r\n$6\r\nsecond\r\n$12\r\nhéllo there\r\n");
+
+        subscriber.write_all(b"UNSUBSCRIBE\r\n").unwrap();
+        expect_bytes(&mut subscriber, concat!(
+            "*3\r\n$11\r\nunsubscribe\r\n$5\r\nfirst\r\n:1\r\n",
+            "*3\r\n$11\r\nunsubscribe\r\n$6\r\nsecond\r\n:0\r\n",
+        ));
+        subscriber.write_all(b"UNSUBSCRIBE\r\n").unwrap();
+        expect_bytes(&mut subscriber, "*3\r\n$11\r\nunsubscribe\r\n$-1\r\n:0\r\n");
+
+        // Back in command mode
+        subscriber.write_all(b"ECHO done\r\n").unwrap();
+        expect_bytes(&mut subscriber, "done\r\n");
+
+        drop(subscriber);
+        drop(publisher);
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    fn test_subscriber_that_never_reads_is_disconnected(async_server: bool) {
+        let mut config = test_config("output_limit", async_server);
+        config.client_output_buffer_limit_pubsub = OutputBufferLimit {
+            hard_limit_bytes: 64 * 1024,
+            ..OutputBufferLimit::default()
+        };
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+
+        // One write per command; split writes stall behind Nagle and delayed ACKs
+        let send = |reader: &mut BufReader<TcpStream>, command: &str| {
+            reader.get_ref().write_all(format!("{}\r\n", command).as_bytes()).unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim().to_string()
+        };
+        let mut subscriber = connect(&config);
+        subscriber.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        subscriber.write_all(b"SUBSCRIBE flood\r\n").unwrap();
+        expect_bytes(&mut subscriber, "*3\r\n$9\r\nsubscribe\r\n$5\r\nflood\r\n:1\r\n");
+
+        // The subscriber never reads again; once the socket buffers are full
+        // its backlog grows until the server gives up on it
+        let mut publisher = BufReader::new(connect(&config));
+        let message = "x".repeat(1024);
+        let mut published = 0;
+        while send(&mut publisher, &format!("PUBLISH flood {}", message)) == "1" {
+            published += 1;
+            assert!(published < 100_000, "subscriber was never disconnected");
+        }
+        assert_eq!(send(&mut publisher, "INFO stats"), "*13");
+        let mut stats: [String; 13] = Default::default();
+        for line in stats.iter_mut() {
+            publisher.read_line(line).unwrap();
+        }
+        assert_eq!(stats[1].trim(), "client_output_buffer_limit_disconnections:1");
+
+        // The subscriber's socket was closed; whatever made it out ends in EOF
+        let mut drained = Vec::new();
+        subscriber.read_to_end(&mut drained).unwrap();
+        assert_eq!(send(&mut publisher, "PUBLISH flood again"), "0");
+
+        drop(publisher);
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    fn test_slot_assignments_are_shared_by_connections(async_server: bool) {
+        let config = test_config("slots", async_server);
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+
+        let send = |reader: &mut BufReader<TcpStream>, command: &str| {
+            writeln!(reader.get_ref(), "{}", command).unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim().to_string()
+        };
+        let mut first = BufReader::new(connect(&config));
+        let mut second = BufReader::new(connect(&config));
+
+        assert_eq!(send(&mut first, "CLUSTER ADDSLOTSRANGE 0 9"), "OK");
+        assert_eq!(send(&mut second, "CLUSTER ADDSLOTS 10 5"), "ERR Slot 5 is already busy");
+        assert_eq!(send(&mut second, "CLUSTER DELSLOTS 0 1"), "OK");
+        assert_eq!(send(&mut first, "CLUSTER INFO"), "*7");
+        let mut lines = [String::new(), String::new()];
+        for line in lines.iter_mut() {
+            first.read_line(line).unwrap();
+        }
+        assert_eq!(lines[0].trim(), "cluster_state:fail");
+        assert_eq!(lines[1].trim(), "cluster_slots_assigned:8");
+
+        drop(first);
+        drop(second);
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    fn test_restart_serves_the_saved_snapshot(async_server: bool) {
+        let mut config = test_config("restart", async_server);
+        config.save_conditions = vec![SaveCondition { dirty_threshold: 1, seconds: 0 }];
+        let snapshot_path = config.snapshot_path.clone();
+        let _ = std::fs::remove_file(&snapshot_path);
+        let server = Server::new(config.clone()).start().unwrap();
+
+        let mut reader = BufReader::new(connect(&config));
+        let mut response = String::new();
+        writeln!(reader.get_ref(), "SET greeting hello").unwrap();
+        reader.read_line(&mut response).unwrap();
+        // Only the maintenance thread can write the file; NOSAVE skips the final save
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !std::path::Path::new(&snapshot_path).exists() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+        writeln!(reader.get_ref(), "SHUTDOWN NOSAVE").unwrap();
+        response.clear();
+        assert_eq!(reader.read_line(&mut response).unwrap(), 0);
+        server.join().unwrap();
+
+        // Restart the way main does: load the snapshot, then serve that storage
+        let mut config = test_config("restart", async_server);
+        config.snapshot_path = snapshot_path.clone();
+        let mut storage = MemoryStorage::new();
+        storage.load_snapshot(&snapshot_path).unwrap();
+        let server = Server::with_storage(config.clone(), Arc::new(Mutex::new(storage))).start().unwrap();
+
+        let mut reader = BufReader::new(connect(&config));
+        writeln!(reader.get_ref(), "GET greeting").unwrap();
+        response.clear();
+        reader.read_line(&mut response).unwrap();
+        assert_eq!(response.trim(), "hello");
+
+        drop(reader);
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    fn test_handle_reports_its_address_and_frees_the_port(async_server: bool) {
+        let mut config = test_config("handle", async_server);
+        config.port = 0;
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config).start().unwrap();
+        let address = server.local_addr();
+        assert_ne!(address.port(), 0);
+
+        // The listener is bound by the time start returns, so no retries
+        let mut reader = BufReader::new(TcpStream::connect(address).unwrap());
+        writeln!(reader.get_ref(), "SET key value").unwrap();
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        assert_eq!(response.trim(), "OK");
+
+        // Shutting down disconnects the client, saves and releases the port
+        server.shutdown().unwrap();
+        response.clear();
+        assert_eq!(reader.read_line(&mut response).unwrap(), 0);
+        assert!(std::fs::read_to_string(&snapshot_path).unwrap().contains("STRING key value"));
+        TcpListener::bind(address).unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    fn test_blpop_waits_for_push_and_shutdown_releases_it(async_server: bool) {
+        let config = test_config("blpop", async_server);
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+
+        let mut consumer = BufReader::new(connect(&config));
+        consumer.get_ref().set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let mut producer = BufReader::new(connect(&config));
+        let mut response = String::new();
+
+        writeln!(consumer.get_ref(), "BLPOP jobs 0").unwrap();
+        thread::sleep(Duration::from_millis(100));
+        writeln!(producer.get_ref(), "RPUSH jobs job1").unwrap();
+        producer.read_line(&mut response).unwrap();
+        assert_eq!(response.trim(), "1");
+        // A count header, then the key and the value
+        let mut reply = [String::new(), String::new(), String::new()];
+        for line in reply.iter_mut() {
+            consumer.read_line(line).unwrap();
+        }
+        assert_eq!(reply[0].trim(), "*2");
+        assert_eq!(reply[1].trim(), "jobs");
+        assert_eq!(reply[2].trim(), "job1");
+
+        // A client blocked forever must not hold up shutdown
+        writeln!(consumer.get_ref(), "BLPOP jobs 0").unwrap();
+        thread::sleep(Duration::from_millis(100));
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    fn test_too_big_inline_request_closes_only_that_client(async_server: bool) {
+        let mut config = test_config("inline_limit", async_server);
+        config.proto_max_inline_size = 1024;
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+
+        let mut flooder = BufReader::new(connect(&config));
+        flooder.get_ref().set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let mut writer = flooder.get_ref().try_clone().unwrap();
+        let sender = thread::spawn(move || {
+            let chunk = vec![b'x'; 64 * 1024];
+            for _ in 0..1600 {
+                if writer.write_all(&chunk).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut response = String::new();
+        flooder.read_line(&mut response).unwrap();
+        assert_eq!(response.trim(), "ERR Protocol error: too big inline request");
+        sender.join().unwrap();
+
+        let mut other = BufReader::new(connect(&config));
+        writeln!(other.get_ref(), "PING").unwrap();
+        response.clear();
+        other.read_line(&mut response).unwrap();
+        assert_eq!(response.trim(), "PONG");
+
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    fn test_acl_read_only_user(async_server: bool) {
+        let mut config = test_config("acl", async_server);
+        let user = |password: &str, permissions: &[&str]| AclUser {
+            password: password.to_string(),
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+        };
+        config.acl.insert("admin".to_string(), user("secret", &["+@all"]));
+        config.acl.insert("reader".to_string(), user("readpass", &["+@read"]));
+        config.acl.insert("setter".to_string(), user("setpass", &["+get", "+set"]));
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+
+        let send = |reader: &mut BufReader<TcpStream>, command: &str| {
+            writeln!(reader.get_ref(), "{}", command).unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim().to_string()
+        };
+        let mut client = BufReader::new(connect(&config));
+
+        // Without a passwordless default user, nothing runs before AUTH
+        assert_eq!(send(&mut client, "GET key"), "NOAUTH Authentication required.");
+        assert_eq!(send(&mut client, "AUTH reader wrong"), "WRONGPASS invalid username-password pair or user is disabled.");
+        assert_eq!(send(&mut client, "AUTH admin secret"), "OK");
+        assert_eq!(send(&mut client, "SET key value"), "OK");
+
+        assert_eq!(send(&mut client, "AUTH reader readpass"), "OK");
+        assert_eq!(send(&mut client, "ACL WHOAMI"), "reader");
+        assert_eq!(send(&mut client, "GET key"), "value");
+        assert_eq!(send(&mut client, "SET key other"), "NOPERM User reader has no permissions to run the 'set' command");
+        assert_eq!(send(&mut client, "FLUSHALL"), "NOPERM User reader has no permissions to run the 'flushall' command");
+        assert!(send(&mut client, "ACL LIST").starts_with("NOPERM"));
+        assert_eq!(send(&mut client, "PING"), "PONG");
+
+        assert_eq!(send(&mut client, "AUTH setter setpass"), "OK");
+        assert_eq!(send(&mut client, "SET key other"), "OK");
+        assert!(send(&mut client, "DEL key").starts_with("NOPERM"));
+
+        assert_eq!(send(&mut client, "AUTH admin secret"), "OK");
+        assert_eq!(send(&mut client, "ACL LIST"), "*3");
+        let mut lines = [String::new(), String::new(), String::new()];
+        for line in lines.iter_mut() {
+            client.read_line(line).unwrap();
+        }
+        assert_eq!(lines[0].trim(), "user admin on >*** +@all");
+        assert_eq!(lines[1].trim(), "user reader on >*** +@read");
+        assert_eq!(send(&mut client, "FLUSHALL"), "OK");
+        assert_eq!(send(&mut client, "GET key"), "(nil)");
+
+        drop(client);
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    /// Kills the server process when the test ends, even on panic
+    struct ServerProcess(std::process::Child);
+
+    impl Drop for ServerProcess {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    // The server runs as its own process so that client and server sockets
+    // don't share one file descriptor limit
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_async_server_holds_idle_connections_without_threads() {
+        const IDLE_CLIENTS: usize = 10_000;
+        let config = test_config("async_idle", true);
+        let config_path = std::env::temp_dir().join(format!("async_idle_{}.toml", config.port));
+        std::fs::write(
+            &config_path,
+            format!(
+                "host = \"{}\"\nport = {}\nmax_connections = {}\nasync_server = true\nlog_level = \"error\"\nsnapshot_path = \"{}\"\n",
+                config.host, config.port, IDLE_CLIENTS + 10, config.snapshot_path
+            ),
+        )
+        .unwrap();
+        let server = ServerProcess(
+            std::process::Command::new(env!("CARGO_BIN_EXE_rust-redis-imitate"))
+                .arg(&config_path)
+                .spawn()
+                .unwrap(),
+        );
+
+        let idle: Vec<TcpStream> = (0..IDLE_CLIENTS).map(|_| connect(&config)).collect();
+
+        // Ids are handed out in accept order, so this proves every idle client was accepted
+        let mut reader = BufReader::new(connect(&config));
+        let mut response = String::new();
+        writeln!(reader.get_ref(), "CLIENT ID").unwrap();
+        reader.read_line(&mut response).unwrap();
+        assert!(response.trim().parse::<usize>().unwrap() > IDLE_CLIENTS);
+        writeln!(reader.get_ref(), "SET key value").unwrap();
+        response.clear();
+        reader.read_line(&mut response).unwrap();
+        assert_eq!(response.trim(), "OK");
+
+        let status = std::fs::read_to_string(format!("/proc/{}/status", server.0.id())).unwrap();
+        let threads: usize = status
+            .lines()
+            .find_map(|line| line.strip_prefix("Threads:"))
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        assert!(threads < 100, "server used {} threads for {} clients", threads, IDLE_CLIENTS);
+
+        drop(idle);
+        drop(server);
+        let _ = std::fs::remove_file(&config_path);
+    }
+
+    fn test_maintenance_tasks_run_until_shutdown(async_server: bool) {
+        let mut config = test_config("maintenance", async_server);
+        config.maintenance_tick_ms = 10;
+        let mut server = Server::new(config.clone());
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&runs);
+        server.register_maintenance_task("counting", move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+        });
+        let server = server.start().unwrap();
+
+        thread::sleep(Duration::from_millis(300));
+        let started = Instant::now();
+        server.shutdown().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        let after_shutdown = runs.load(Ordering::SeqCst);
+        assert!((10..=31).contains(&after_shutdown), "ran {} times", after_shutdown);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(runs.load(Ordering::SeqCst), after_shutdown);
+        let _ = std::fs::remove_file(&config.snapshot_path);
+    }
+
+    fn test_replica_follows_master(async_server: bool) {
+        let master_config = test_config("replication_master", async_server);
+        let replica_config = test_config("replication_replica", async_server);
+        let master = Server::new(master_config.clone()).start().unwrap();
+        let replica = Server::new(replica_config.clone()).start().unwrap();
+
+        let send = |reader: &mut BufReader<TcpStream>, command: &str| {
+            writeln!(reader.get_ref(), "{}", command).unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim().to_string()
+        };
+        let info = |reader: &mut BufReader<TcpStream>| {
+            writeln!(reader.get_ref(), "INFO replication").unwrap();
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let count: usize = header.trim().strip_prefix('*').unwrap().parse().unwrap();
+            let mut lines = String::new();
+            for _ in 0..count {
+                reader.read_line(&mut lines).unwrap();
+            }
+            lines
+        };
+        let wait_for = |reader: &mut BufReader<TcpStream>, command: &str, expected: &str| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                let response = send(reader, command);
+                if response == expected || Instant::now() > deadline {
+                    return response;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+        let mut on_master = BufReader::new(connect(&master_config));
+        let mut on_replica = BufReader::new(connect(&replica_config));
+
+        // Data written before the replica attaches arrives with the full sync
+        assert_eq!(send(&mut on_master, "SET before sync"), "OK");
+        assert_eq!(send(&mut on_master, "RPUSH list a"), "1");
+        assert_eq!(send(&mut on_replica, "SET stale value"), "OK");
+        let replicaof = format!("REPLICAOF {} {}", master_config.host, master_config.port);
+        assert_eq!(send(&mut on_replica, &replicaof), "OK");
+        assert_eq!(send(&mut on_replica, &replicaof), "OK Already connected to specified master");
+        assert_eq!(wait_for(&mut on_replica, "GET before", "sync"), "sync");
+        assert_eq!(send(&mut on_replica, "GET stale"), "(nil)");
+        assert_eq!(send(&mut on_replica, "LLEN list"), "1");
+
+        // Then every write the master executes
+        assert_eq!(send(&mut on_master, "SET key value"), "OK");
+        assert_eq!(send(&mut on_master, "HINCRBYFLOAT hash field 1.5"), "1.5");
+        writeln!(on_master.get_ref(), "BLPOP list 1").unwrap();
+        let mut popped = String::new();
+        for _ in 0..3 {
+            on_master.read_line(&mut popped).unwrap();
+        }
+        assert_eq!(popped, "*2\r\nlist\r\na\r\n");
+        assert_eq!(wait_for(&mut on_replica, "GET key", "value"), "value");
+        assert_eq!(wait_for(&mut on_replica, "LLEN list", "0"), "0");
+        assert_eq!(send(&mut on_replica, "TYPE hash"), "hash");
+
+        assert_eq!(send(&mut on_replica, "SET key other"), "READONLY You can't write against a read only replica.");
+        let replica_info = info(&mut on_replica);
+        assert!(replica_info.contains("role:slave"), "{}", replica_info);
+        assert!(replica_info.contains(&format!("master_port:{}", master_config.port)));
+        assert!(replica_info.contains("master_link_status:up"));
+        let master_info = info(&mut on_master);
+        assert!(master_info.contains("role:master"), "{}", master_info);
+        assert!(master_info.contains("connected_slaves:1"));
+        assert!(master_info.contains("state=online"));
+
+        // Once promoted, the replica keeps its data and takes writes again
+        assert_eq!(send(&mut on_replica, "REPLICAOF NO ONE"), "OK");
+        assert_eq!(send(&mut on_replica, "SET key other"), "OK");
+        assert!(info(&mut on_replica).contains("role:master"));
+        assert_eq!(send(&mut on_master, "SET later write"), "OK");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !info(&mut on_master).contains("connected_slaves:0") {
+            assert!(Instant::now() < deadline, "master still lists the replica");
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(send(&mut on_replica, "GET later"), "(nil)");
+
+        drop(on_master);
+        drop(on_replica);
+        replica.shutdown().unwrap();
+        master.shutdown().unwrap();
+        let _ = std::fs::remove_file(&master_config.snapshot_path);
+        let _ = std::fs::remove_file(&replica_config.snapshot_path);
+    }
+
+    fn test_replica_resumes_with_partial_resync(async_server: bool) {
+        let master_config = test_config("psync_master", async_server);
+        let replica_config = test_config("psync_replica", async_server);
+        let master = Server::new(master_config.clone()).start().unwrap();
+        let replica = Server::new(replica_config.clone()).start().unwrap();
+
+        // The replica reaches the master through this proxy, so the test can
+        // cut the link and keep it down while the master takes writes
+        let proxy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy_port = proxy.local_addr().unwrap().port();
+        let open = Arc::new(AtomicBool::new(true));
+        let links: Arc<Mutex<Vec<TcpStream>>> = Arc::default();
+        {
+            let (open, links) = (Arc::clone(&open), Arc::clone(&links));
+            let master_addr = (master_config.host.clone(), master_config.port);
+            thread::spawn(move || {
+                for client in proxy.incoming() {
+                    let Ok(client) = client else { break };
+                    if !open.load(Ordering::SeqCst) {
+                        continue;
+                    }
+                    let upstream = TcpStream::connect(master_addr.clone()).unwrap();
+                    links.lock().unwrap().extend([client.try_clone().unwrap(), upstream.try_clone().unwrap()]);
+                    for (mut from, mut to) in [(client.try_clone().unwrap(), upstream.try_clone().unwrap()), (upstream, client)] {
+                        thread::spawn(move || {
+                            let _ = std::io::copy(&mut from, &mut to);
+                            let _ = to.shutdown(Shutdown::Both);
+                        });
+                    }
+                }
+            });
+        }
+
+        let send = |reader: &mut BufReader<TcpStream>, command: &str| {
+            writeln!(reader.get_ref(), "{}", command).unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim().to_string()
+        };
+        let info = |reader: &mut BufReader<TcpStream>, section: &str| {
+            writeln!(reader.get_ref(), "INFO {}", section).unwrap();
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let count: usize = header.trim().strip_prefix('*').unwrap().parse().unwrap();
+            let mut lines = String::new();
+            for _ in 0..count {
+                reader.read_line(&mut lines).unwrap();
+            }
+            lines
+        };
+        let wait_until = |reader: &mut BufReader<TcpStream>, section: &str, expected: &str| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                let lines = info(reader, section);
+                if lines.contains(expected) {
+                    return;
+                }
+                assert!(Instant::now() < deadline, "INFO {} never showed {}:\n{}", section, expected, lines);
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+        let mut on_master = BufReader::new(connect(&master_config));
+        let mut on_replica = BufReader::new(connect(&replica_config));
+
+        assert_eq!(send(&mut on_master, "SET before link"), "OK");
+        assert_eq!(send(&mut on_replica, &format!("REPLICAOF 127.0.0.1 {}", proxy_port)), "OK");
+        wait_until(&mut on_replica, "replication", "master_link_status:up");
+        // WAIT counts the replica once it acknowledges the write
+        assert_eq!(send(&mut on_master, "SET streamed 1"), "OK");
+        assert_eq!(send(&mut on_master, "WAIT 1 5000"), "1");
+
+        open.store(false, Ordering::SeqCst);
+        for link in links.lock().unwrap().drain(..) {
+            let _ = link.shutdown(Shutdown::Both);
+        }
+        wait_until(&mut on_replica, "replication", "master_link_status:down");
+        for i in 0..3 {
+            assert_eq!(send(&mut on_master, &format!("SET missed{} {}", i, i)), "OK");
+        }
+        assert_eq!(send(&mut on_master, "DEL before"), "1");
+        open.store(true, Ordering::SeqCst);
+
+        // The replica continues from its offset instead of syncing the dataset again
+        wait_until(&mut on_master, "stats", "sync_partial_ok:1");
+        let stats = info(&mut on_master, "stats");
+        assert!(stats.contains("sync_full:1"), "{}", stats);
+        assert!(stats.contains("sync_partial_err:0"), "{}", stats);
+        assert_eq!(send(&mut on_master, "WAIT 1 5000"), "1");
+        for i in 0..3 {
+            assert_eq!(send(&mut on_replica, &format!("GET missed{}", i)), i.to_string());
+        }
+        assert_eq!(send(&mut on_replica, "GET before"), "(nil)");
+        assert_eq!(send(&mut on_replica, "GET streamed"), "1");
+        let master_info = info(&mut on_master, "replication");
+        let offset = master_info.lines().find(|line| line.starts_with("master_repl_offset:")).unwrap();
+        let replica_info = info(&mut on_replica, "replication");
+        assert!(replica_info.contains(&offset.replace("master_repl_offset", "slave_repl_offset")), "{}", replica_info);
+
+        assert_eq!(send(&mut on_replica, "REPLICAOF NO ONE"), "OK");
+        drop(on_master);
+        drop(on_replica);
+        replica.shutdown().unwrap();
+        master.shutdown().unwrap();
+        let _ = std::fs::remove_file(&master_config.snapshot_path);
+        let _ = std::fs::remove_file(&replica_config.snapshot_path);
+    }
+
+    fn test_full_sync_sends_snapshot_while_master_takes_writes(async_server: bool) {
+        const KEYS: usize = 200_000;
+        let master_config = test_config("snapshot_sync_master", async_server);
+        let replica_config = test_config("snapshot_sync_replica", async_server);
+        let mut storage = MemoryStorage::new();
+        for i in 0..KEYS {
+            storage.set(format!("key:{}", i), format!("value:{}", i));
+        }
+        let master = Server::with_storage(master_config.clone(), Arc::new(Mutex::new(storage))).start().unwrap();
+        let replica = Server::new(replica_config.clone()).start().unwrap();
+
+        let send = |reader: &mut BufReader<TcpStream>, command: &str| {
+            writeln!(reader.get_ref(), "{}", command).unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim().to_string()
+        };
+        let info = |reader: &mut BufReader<TcpStream>| {
+            writeln!(reader.get_ref(), "INFO replication").unwrap();
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let count: usize = header.trim().strip_prefix('*').unwrap().parse().unwrap();
+            let mut lines = String::new();
+            for _ in 0..count {
+                reader.read_line(&mut lines).unwrap();
+            }
+            lines
+        };
+
+        // Keeps the master busy with writes from before the sync starts until it is done
+        let writing = Arc::new(AtomicBool::new(true));
+        let writer = {
+            let (writing, config) = (Arc::clone(&writing), master_config.clone());
+            thread::spawn(move || {
+                let mut on_master = BufReader::new(connect(&config));
+                let mut writes = 0;
+                while writing.load(Ordering::SeqCst) {
+                    writeln!(on_master.get_ref(), "INCR counter").unwrap();
+                    let mut response = String::new();
+                    on_master.read_line(&mut response).unwrap();
+                    writes += 1;
+                    assert_eq!(response.trim(), writes.to_string());
+                }
+                writes
+            })
+        };
+        let mut on_replica = BufReader::new(connect(&replica_config));
+        let replicaof = format!("REPLICAOF {} {}", master_config.host, master_config.port);
+        assert_eq!(send(&mut on_replica, &replicaof), "OK");
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let synced = loop {
+            let lines = info(&mut on_replica);
+            if lines.contains("master_link_status:up") {
+                break lines;
+            }
+            assert!(Instant::now() < deadline, "full sync never finished:\n{}", lines);
+            thread::sleep(Duration::from_millis(10));
+        };
+        writing.store(false, Ordering::SeqCst);
+        let writes = writer.join().unwrap();
+
+        let field = |name: &str| {
+            let prefix = format!("{}:", name);
+            let line = synced.lines().find(|line| line.starts_with(&prefix)).unwrap();
+            line[prefix.len()..].parse::<u64>().unwrap()
+        };
+        assert!(synced.contains("master_sync_in_progress:0"), "{}", synced);
+        assert!(field("master_sync_total_bytes") > (KEYS * 20) as u64);
+        assert_eq!(field("master_sync_read_bytes"), field("master_sync_total_bytes"));
+        assert!(synced.contains("master_sync_last_duration_ms:"));
+
+        // Writes made while the snapshot was encoded and sent follow it, so nothing is lost or applied twice
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while send(&mut on_replica, "GET counter") != writes.to_string() {
+            assert!(Instant::now() < deadline, "replica never caught up with {} writes", writes);
+            thread::sleep(Duration::from_millis(10));
+        }
+        for i in [0, KEYS / 2, KEYS - 1] {
+            assert_eq!(send(&mut on_replica, &format!("GET key:{}", i)), format!("value:{}", i));
+        }
+
+        assert_eq!(send(&mut on_replica, "REPLICAOF NO ONE"), "OK");
+        drop(on_replica);
+        replica.shutdown().unwrap();
+        master.shutdown().unwrap();
+        let _ = std::fs::remove_file(&master_config.snapshot_path);
+        let _ = std::fs::remove_file(&replica_config.snapshot_path);
+    }
+
+    fn test_listens_on_every_bind_address(async_server: bool) {
+        assert_eq!(format_address("127.0.0.1", 6379), "127.0.0.1:6379");
+        assert_eq!(format_address("::1", 6379), "[::1]:6379");
+        assert_eq!(format_address("[::1]", 6379), "[::1]:6379");
+
+        let mut config = test_config("bind", async_server);
+        config.bind = vec!["127.0.0.1".to_string()];
+        // Not every sandbox has an IPv6 loopback
+        let ipv6 = TcpListener::bind("[::1]:0").is_ok();
+        if ipv6 {
+            config.bind.push("::1".to_string());
+        }
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+        assert_eq!(server.local_addrs().len(), config.bind.len());
+
+        let send = |reader: &mut BufReader<TcpStream>, command: &str| {
+            writeln!(reader.get_ref(), "{}", command).unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim().to_string()
+        };
+        let mut ipv4 = BufReader::new(TcpStream::connect(("127.0.0.1", config.port)).unwrap());
+        assert_eq!(send(&mut ipv4, "SET key value"), "OK");
+        if ipv6 {
+            let mut ipv6 = BufReader::new(TcpStream::connect(("::1", config.port)).unwrap());
+            assert_eq!(send(&mut ipv6, "GET key"), "value");
+        }
+
+        // One address that can't be bound fails the whole startup, naming it
+        let mut taken = test_config("bind_taken", async_server);
+        taken.port = config.port;
+        taken.bind = vec!["127.0.0.2".to_string(), "127.0.0.1".to_string()];
+        let error = Server::new(taken).start().err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+        assert!(error.to_string().starts_with(&format!("failed to bind 127.0.0.1:{}", config.port)), "{}", error);
+
+        // Port 0 puts every address on the one port the OS picked first
+        let mut ephemeral = test_config("bind_ephemeral", async_server);
+        ephemeral.port = 0;
+        ephemeral.bind = vec!["127.0.0.1".to_string(), "127.0.0.2".to_string()];
+        let ephemeral_snapshot = ephemeral.snapshot_path.clone();
+        let other = Server::new(ephemeral).start().unwrap();
+        let ports: Vec<u16> = other.local_addrs().iter().map(|addr| addr.port()).collect();
+        assert_ne!(ports[0], 0);
+        assert_eq!(ports, vec![ports[0]; 2]);
+        let mut client = BufReader::new(TcpStream::connect(("127.0.0.2", ports[0])).unwrap());
+        assert_eq!(send(&mut client, "PING"), "PONG");
+        drop(client);
+        other.shutdown().unwrap();
+        let _ = std::fs::remove_file(&ephemeral_snapshot);
+
+        drop(ipv4);
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    // Makes every client look remote while `remote` is set
+    struct FakePeers {
+        remote: Arc<AtomicBool>,
+    }
+
+    impl PeerCheck for FakePeers {
+        fn is_loopback(&self, _peer: &std::net::SocketAddr) -> bool {
+            !self.remote.load(Ordering::Relaxed)
+        }
+    }
+
+    fn test_protected_mode_turns_away_remote_clients(async_server: bool) {
+        let loopback = LoopbackCheck;
+        assert!(loopback.is_loopback(&"127.0.0.1:6379".parse().unwrap()));
+        assert!(loopback.is_loopback(&"[::1]:6379".parse().unwrap()));
+        assert!(loopback.is_loopback(&"[::ffff:127.0.0.1]:6379".parse().unwrap()));
+        assert!(!loopback.is_loopback(&"10.0.0.1:6379".parse().unwrap()));
+
+        let config = test_config("protected_mode", async_server);
+        let snapshot_path = config.snapshot_path.clone();
+        let remote = Arc::new(AtomicBool::new(false));
+        let mut server = Server::new(config.clone());
+        server.set_peer_check(FakePeers { remote: Arc::clone(&remote) });
+        let server = server.start().unwrap();
+
+        let send = |reader: &mut BufReader<TcpStream>, command: &str| {
+            writeln!(reader.get_ref(), "{}", command).unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim().to_string()
+        };
+        let mut local = BufReader::new(connect(&config));
+        assert_eq!(send(&mut local, "SET key value"), "OK");
+
+        // A remote client gets the explanation, then the connection closes
+        remote.store(true, Ordering::Relaxed);
+        let mut denied = BufReader::new(connect(&config));
+        let mut response = String::new();
+        denied.read_line(&mut response).unwrap();
+        assert!(response.starts_with("DENIED Redis is running in protected mode"), "{}", response);
+        assert!(response.contains("CONFIG SET protected-mode no"));
+        response.clear();
+        assert_eq!(denied.read_line(&mut response).unwrap(), 0);
+
+        // Turning it off from the loopback client lets remote clients in
+        assert_eq!(send(&mut local, "CONFIG SET protected-mode no"), "OK");
+        let mut admitted = BufReader::new(connect(&config));
+        assert_eq!(send(&mut admitted, "GET key"), "value");
+        drop(admitted);
+        drop(local);
+        server.shutdown().unwrap();
+
+        // Configured ACL users switch the check off too
+        let mut config = test_config("protected_mode_acl", async_server);
+        config.acl.insert(
+            "default".to_string(),
+            AclUser { password: String::new(), permissions: vec!["+@all".to_string()] },
+        );
+        let mut server = Server::new(config.clone());
+        server.set_peer_check(FakePeers { remote });
+        let server = server.start().unwrap();
+        let mut admitted = BufReader::new(connect(&config));
+        assert_eq!(send(&mut admitted, "PING"), "PONG");
+        drop(admitted);
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+        let _ = std::fs::remove_file(&config.snapshot_path);
+    }
+
+    fn test_snapshot_follows_save_rules(async_server: bool) {
+        let mut config = test_config("save_rules", async_server);
+        config.save_conditions = vec![SaveCondition { dirty_threshold: 1, seconds: 1 }];
+        config.maintenance_tick_ms = 10;
+        let snapshot_path = config.snapshot_path.clone();
+        let _ = std::fs::remove_file(&snapshot_path);
+        let server = Server::new(config.clone()).start().unwrap();
+
+        let mut reader = BufReader::new(connect(&config));
+        let info = |reader: &mut BufReader<TcpStream>| {
+            writeln!(reader.get_ref(), "INFO persistence").unwrap();
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let lines: usize = header.trim().trim_start_matches('*').parse().unwrap();
+            let mut section = String::new();
+            for _ in 0..lines {
+                reader.read_line(&mut section).unwrap();
+            }
+            section
+        };
+
+        // An idle server never saves, however long the rule's interval has passed
+        thread::sleep(Duration::from_millis(1300));
+        assert!(!std::path::Path::new(&snapshot_path).exists());
+        assert!(info(&mut reader).contains("rdb_changes_since_last_save:0"));
+
+        writeln!(reader.get_ref(), "SET key value").unwrap();
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !std::path::Path::new(&snapshot_path).exists() {
+            assert!(Instant::now() < deadline, "no snapshot after a write");
+            thread::sleep(Duration::from_millis(10));
+        }
+        let deadline = Instant::now() + Duration::from_secs(2);
+        loop {
+            let section = info(&mut reader);
+            if section.contains("rdb_changes_since_last_save:0") && section.contains("rdb_bgsave_in_progress:0") {
+                break;
+            }
+            assert!(Instant::now() < deadline, "{}", section);
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(std::fs::read_to_string(&snapshot_path).unwrap().contains("STRING key value"));
+
+        drop(reader);
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    fn test_acceptor_threads_share_one_address(async_server: bool) {
+        let mut config = test_config("acceptors", async_server);
+        config.acceptor_threads = 4;
+        config.max_connections = 64;
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+        assert_eq!(server.local_addrs().len(), 1);
+
+        let mut clients: Vec<BufReader<TcpStream>> = (0..32).map(|_| BufReader::new(connect(&config))).collect();
+        for client in &mut clients {
+            writeln!(client.get_ref(), "PING").unwrap();
+            let mut response = String::new();
+            client.read_line(&mut response).unwrap();
+            assert_eq!(response.trim(), "PONG");
+        }
+        drop(clients);
+
+        // Every acceptor stops, so the port is free again right after shutdown
+        let started = Instant::now();
+        server.shutdown().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        TcpListener::bind(("127.0.0.1", config.port)).unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    // Opens `connections` short-lived connections from a few client threads
+    // and returns how long they took
+    fn churn(config: &Config, connections: usize) -> Duration {
+        let threads = 8;
+        let started = Instant::now();
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                let port = config.port;
+                thread::spawn(move || {
+                    for _ in 0..connections / threads {
+                        let mut client = BufReader::new(TcpStream::connect(("127.0.0.1", port)).unwrap());
+                        writeln!(client.get_ref(), "PING").unwrap();
+                        let mut response = String::new();
+                        client.read_line(&mut response).unwrap();
+                        assert_eq!(response.trim(), "PONG");
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        started.elapsed()
+    }
+
+    fn test_keyspace_notifications_follow_config_set(async_server: bool) {
+        let config = test_config("keyspace_events", async_server);
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+
+        let send = |reader: &mut BufReader<TcpStream>, command: &str| {
+            writeln!(reader.get_ref(), "{}", command).unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim().to_string()
+        };
+        let mut subscriber = connect(&config);
+        subscriber.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let mut client = BufReader::new(connect(&config));
+        client.get_ref().set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        subscriber.write_all(b"PSUBSCRIBE __key*__:*\r\n").unwrap();
+        expect_bytes(&mut subscriber, "*3\r\n$10\r\npsubscribe\r\n$10\r\n__key*__:*\r\n:1\r\n");
+
+        // Off by default: this write publishes nothing, so the first frames
+        // the subscriber sees are the next write's
+        assert_eq!(send(&mut client, "SET quiet value"), "OK");
+        assert_eq!(
+            send(&mut client, "CONFIG SET notify-keyspace-events KEX"),
+            "ERR Invalid argument 'X' for CONFIG SET 'notify-keyspace-events'"
+        );
+        assert_eq!(send(&mut client, "CONFIG SET notify-keyspace-events KEA"), "OK");
+        writeln!(client.get_ref(), "CONFIG GET notify-keyspace-events").unwrap();
+        expect_bytes(&mut client, "*2\r\nnotify-keyspace-events\r\nAKE\r\n");
+
+        assert_eq!(send(&mut client, "LPUSH jobs one"), "1");
+        expect_bytes(&mut subscriber, concat!(
+            "*4\r\n$8\r\npmessage\r\n$10\r\n__key*__:*\r\n$19\r\n__keyspace@0__:jobs\r\n$5\r\nlpush\r\n",
+            "*4\r\n$8\r\npmessage\r\n$10\r\n__key*__:*\r\n$20\r\n__keyevent@0__:lpush\r\n$4\r\njobs\r\n",
+        ));
+
+        // Only the keyevent channel, and only generic events
+        assert_eq!(send(&mut client, "CONFIG SET notify-keyspace-events Eg"), "OK");
+        assert_eq!(send(&mut client, "LPUSH jobs two"), "2");
+        assert_eq!(send(&mut client, "DEL jobs"), "1");
+        expect_bytes(&mut subscriber, "*4\r\n$8\r\npmessage\r\n$10\r\n__key*__:*\r\n$18\r\n__keyevent@0__:del\r\n$4\r\njobs\r\n");
+
+        drop(subscriber);
+        drop(client);
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    fn test_stalled_clients_are_disconnected(async_server: bool) {
+        let mut config = test_config("stalled_clients", async_server);
+        config.client_query_timeout_ms = 200;
+        config.maintenance_tick_ms = 10;
+        let snapshot_path = config.snapshot_path.clone();
+        let server = Server::new(config.clone()).start().unwrap();
+        let send = |reader: &mut BufReader<TcpStream>, command: &str| {
+            writeln!(reader.get_ref(), "{}", command).unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim().to_string()
+        };
+
+        // Half a command, then nothing: the connection is reaped and the
+        // half-sent SET never runs
+        let mut idle = BufReader::new(connect(&config));
+        let mut stalled = connect(&config);
+        stalled.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write!(stalled, "SET half").unwrap();
+        let started = Instant::now();
+        assert_eq!(stalled.read(&mut [0; 64]).unwrap(), 0);
+        let reaped_after = started.elapsed();
+        assert!(reaped_after >= Duration::from_millis(200), "{:?}", reaped_after);
+        assert!(reaped_after < Duration::from_secs(2), "{:?}", reaped_after);
+
+        // A client idle between commands is left alone
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(send(&mut idle, "EXISTS half"), "0");
+        writeln!(idle.get_ref(), "INFO stats").unwrap();
+        let mut info = String::new();
+        while !info.contains("stalled_clients_disconnected:") {
+            idle.read_line(&mut info).unwrap();
+        }
+        assert!(info.trim_end().ends_with("stalled_clients_disconnected:1"), "{}", info);
+
+        drop(idle);
+        server.shutdown().unwrap();
+        let _ = std::fs::remove_file(&snapshot_path);
+    }
+
+    #[test]
+    #[ignore = "stress test; run with --ignored"]
+    fn stress_accept_throughput_with_one_and_four_acceptors() {
+        for acceptors in [1, 4] {
+            let mut config = test_config(&format!("accept_stress_{}", acceptors), false);
+            config.acceptor_threads = acceptors;
+            config.max_connections = 1000;
+            let snapshot_path = config.snapshot_path.clone();
+            let server = Server::new(config.clone()).start().unwrap();
+            let elapsed = churn(&config, 4000);
+            println!(
+                "{} acceptor(s): 4000 connections in {:?} ({:.0}/s)",
+                acceptors,
+                elapsed,
+                4000.0 / elapsed.as_secs_f64()
+            );
+            server.shutdown().unwrap();
+            let _ = std::fs::remove_file(&snapshot_path);
+        }
+    }
+}
\ No newline at end of file
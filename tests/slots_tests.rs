@@ -0,0 +1,102 @@
+use redis_imitate::cluster::message::RaftMessage;
+use redis_imitate::cluster::slots::{hash_slot, SlotTable, CLUSTER_SLOTS};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info_field(table: &SlotTable, field: &str) -> String {
+        let prefix = format!("{}:", field);
+        table.info().lines().find_map(|line| line.strip_prefix(&prefix).map(str::to_string)).unwrap()
+    }
+
+    #[test]
+    fn test_add_and_delete_slots() {
+        let table = SlotTable::new();
+        assert_eq!(table.add_slots(&[1, 2, 3]), Ok(()));
+        assert_eq!(table.assigned_count(), 3);
+
+        // A failing request assigns none of its slots
+        assert_eq!(table.add_slots(&[4, 2]), Err("ERR Slot 2 is already busy".to_string()));
+        assert_eq!(table.add_slots(&[4, 4]), Err("ERR Slot 4 specified multiple times".to_string()));
+        assert_eq!(table.add_slots(&[4, CLUSTER_SLOTS]), Err("ERR Invalid or out of range slot".to_string()));
+        assert_eq!(table.assigned_count(), 3);
+
+        assert_eq!(table.del_slots(&[1, 9]), Err("ERR Slot 9 is already unassigned".to_string()));
+        assert_eq!(table.del_slots(&[1, 3]), Ok(()));
+        assert_eq!(table.assigned_count(), 1);
+        assert_eq!(table.add_slots(&[1]), Ok(()));
+    }
+
+    #[test]
+    fn test_add_slot_ranges() {
+        let table = SlotTable::new();
+        assert_eq!(table.add_slot_ranges(&[(0, 99), (200, 200)]), Ok(()));
+        assert_eq!(table.assigned_count(), 101);
+        assert_eq!(
+            table.add_slot_ranges(&[(300, 299)]),
+            Err("ERR start slot number 300 is greater than end slot number 299".to_string())
+        );
+        assert_eq!(table.add_slot_ranges(&[(150, 250)]), Err("ERR Slot 200 is already busy".to_string()));
+        assert_eq!(table.assigned_count(), 101);
+    }
+
+    #[test]
+    fn test_info_reports_assigned_slots() {
+        let table = SlotTable::new();
+        assert_eq!(info_field(&table, "cluster_state"), "fail");
+        assert_eq!(info_field(&table, "cluster_slots_assigned"), "0");
+        assert_eq!(info_field(&table, "cluster_known_nodes"), "1");
+        assert_eq!(info_field(&table, "cluster_size"), "0");
+
+        table.add_slot_ranges(&[(0, CLUSTER_SLOTS - 1)]).unwrap();
+        assert_eq!(info_field(&table, "cluster_state"), "ok");
+        assert_eq!(info_field(&table, "cluster_slots_assigned"), "16384");
+        assert_eq!(info_field(&table, "cluster_size"), "1");
+    }
+
+    #[test]
+    fn test_announcements_update_peers() {
+        let ours = SlotTable::new();
+        let peer = SlotTable::new();
+        ours.add_slots(&[5, 1]).unwrap();
+        peer.add_slots(&[7]).unwrap();
+
+        let announcement = ours.announcement();
+        match &announcement {
+            RaftMessage::SlotAssignments { node_id, slots } => {
+                assert_eq!(node_id, ours.node_id());
+                assert_eq!(slots, &vec![1, 5]);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+        peer.apply(&announcement);
+        assert_eq!(peer.assigned_count(), 3);
+        assert_eq!(peer.add_slots(&[5]), Err("ERR Slot 5 is already busy".to_string()));
+        assert_eq!(info_field(&peer, "cluster_known_nodes"), "2");
+
+        // A later announcement replaces what the node held before
+        ours.del_slots(&[1]).unwrap();
+        peer.apply(&ours.announcement());
+        assert_eq!(peer.assigned_count(), 2);
+        assert_eq!(peer.add_slots(&[1]), Ok(()));
+
+        // Our own announcement coming back changes nothing
+        peer.apply(&peer.announcement());
+        assert_eq!(peer.assigned_count(), 3);
+    }
+    #[test]
+    fn test_hash_slot() {
+        // Values from Redis's CLUSTER KEYSLOT
+        assert_eq!(hash_slot("foo"), 12182);
+        assert_eq!(hash_slot("bar"), 5061);
+        assert_eq!(hash_slot("hello"), 866);
+        assert_eq!(hash_slot(""), 0);
+
+        assert_eq!(hash_slot("{user1000}.following"), hash_slot("user1000"));
+        // An empty tag hashes the whole key
+        assert_ne!(hash_slot("foo{}{bar}"), hash_slot("bar"));
+        assert_eq!(hash_slot("foo{{bar}}zap"), hash_slot("{bar"));
+        assert_eq!(hash_slot("foo{bar}{zap}"), hash_slot("bar"));
+    }
+}
@@ -0,0 +1,47 @@
+use redis_imitate::network::stats::{ServerStats, OPS_SAMPLES};
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ops_per_sec_averages_recent_samples() {
+        let stats = ServerStats::default();
+        let start = Instant::now();
+        stats.sample_ops(start);
+        assert_eq!(stats.instantaneous_ops_per_sec(), 0);
+
+        // 50 commands in each 100ms tick is 500 ops/sec
+        for tick in 1..=3 {
+            for _ in 0..50 {
+                stats.record_command();
+            }
+            stats.sample_ops(start + Duration::from_millis(100 * tick));
+        }
+        assert_eq!(stats.instantaneous_ops_per_sec(), 500);
+
+        // Once the server goes idle the old rates age out of the ring
+        for tick in 4..4 + OPS_SAMPLES as u64 {
+            stats.sample_ops(start + Duration::from_millis(100 * tick));
+        }
+        assert_eq!(stats.instantaneous_ops_per_sec(), 0);
+        assert!(stats.info().contains("total_commands_processed:150\n"));
+
+        stats.reset();
+        assert!(stats.info().starts_with("total_connections_received:0\ntotal_commands_processed:0\n"));
+    }
+
+    #[test]
+    fn test_connections_are_counted_until_they_close() {
+        let stats = ServerStats::default();
+        stats.connected();
+        stats.connected();
+        stats.rejected();
+        stats.disconnected();
+        assert_eq!(stats.connected_clients(), 1);
+        let info = stats.info();
+        assert!(info.contains("total_connections_received:2\n"));
+        assert!(info.contains("rejected_connections:1\n"));
+    }
+}
@@ -1,4 +1,12 @@
-use redis_imitate::storage::memory::MemoryStorage;
+use redis_imitate::storage::memory::{MemoryStorage, ACTIVE_EXPIRE_SAMPLE};
+use redis_imitate::storage::encoding::EncodingLimits;
+use redis_imitate::storage::notify::NotifyFlags;
+use redis_imitate::storage::stream::{StreamEntryId, XAddId};
+use std::sync::{Arc, Mutex};
+use redis_imitate::storage::eviction::MaxMemoryPolicy;
+use redis_imitate::storage::geo::{self, GeoAddOptions, GeoMatch, GeoOrigin, GeoSearchParams, GeoShape, GeoStoreMode, GeoUnit};
+use redis_imitate::storage::zset::{AggType, LexBound, ScoreBound, SortedSet, ZAddOptions};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 #[cfg(test)]
 mod tests {
@@ -20,6 +28,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_delete_operation() {
         let mut storage = MemoryStorage::new();
         
@@ -38,18 +47,31 @@ mod tests {
     fn test_increment_decrement() {
         let mut storage = MemoryStorage::new();
         
-        assert_eq!(storage.incr("counter"), 1);
+        assert_eq!(storage.incr("counter"), Ok(1));
         
-        assert_eq!(storage.incr("counter"), 2);
+        assert_eq!(storage.incr("counter"), Ok(2));
         
-        assert_eq!(storage.decr("counter"), 1);
+        assert_eq!(storage.decr("counter"), Ok(1));
         
-        assert_eq!(storage.decr("counter"), 0);
-        assert_eq!(storage.decr("counter"), -1);
+        assert_eq!(storage.decr("counter"), Ok(0));
+        assert_eq!(storage.decr("counter"), Ok(-1));
         
         storage.set("non_numeric".to_string(), "abc".to_string());
-        assert_eq!(storage.incr("non_numeric"), 1);
-        assert_eq!(storage.decr("non_numeric"), 0);
+        assert_eq!(storage.incr("non_numeric"), Ok(1));
+        assert_eq!(storage.decr("non_numeric"), Ok(0));
+    }
+
+    #[test]
+    fn test_increment_decrement_overflow() {
+        let mut storage = MemoryStorage::new();
+
+        storage.set("max".to_string(), i64::MAX.to_string());
+        assert_eq!(storage.incr("max"), Err("ERR increment or decrement would overflow".to_string()));
+        assert_eq!(storage.get("max"), Some(i64::MAX.to_string()));
+
+        storage.set("min".to_string(), i64::MIN.to_string());
+        assert_eq!(storage.decr("min"), Err("ERR increment or decrement would overflow".to_string()));
+        assert_eq!(storage.get("min"), Some(i64::MIN.to_string()));
     }
 
     #[test]
@@ -160,4 +182,1037 @@ mod tests {
         assert_eq!(storage.get("key3"), None);
     }
 
+    #[test]
+    fn test_smove() {
+        let mut storage = MemoryStorage::new();
+        let members = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        assert_eq!(storage.sadd("src", &members(&["a", "b"])), Ok(2));
+        assert_eq!(storage.sadd("src", &members(&["a"])), Ok(0));
+
+        assert_eq!(storage.smove("src", "dst", "a"), Ok(true));
+        assert_eq!(storage.smembers("src"), members(&["b"]));
+        assert_eq!(storage.smembers("dst"), members(&["a"]));
+        assert_eq!(storage.smove("src", "dst", "missing"), Ok(false));
+        assert_eq!(storage.smove("nosuchset", "dst", "a"), Ok(false));
+
+        // The source set disappears once its last member is moved out
+        assert_eq!(storage.smove("SRC", "dst", "b"), Ok(true));
+        assert_eq!(storage.key_type("src"), "none");
+        assert_eq!(storage.smembers("dst"), members(&["a", "b"]));
+        assert_eq!(storage.smove("dst", "dst", "a"), Ok(true));
+        assert_eq!(storage.scard("dst"), 2);
+
+        storage.set("string".to_string(), "value".to_string());
+        storage.rpush("list", "item".to_string());
+        assert!(storage.smove("dst", "string", "a").unwrap_err().starts_with("WRONGTYPE"));
+        assert!(storage.smove("list", "dst", "item").unwrap_err().starts_with("WRONGTYPE"));
+        assert!(storage.sadd("string", &members(&["a"])).unwrap_err().starts_with("WRONGTYPE"));
+        assert_eq!(storage.smembers("dst"), members(&["a", "b"]));
+    }
+
+    #[test]
+    fn test_smove_in_transaction() {
+        let mut storage = MemoryStorage::new();
+        storage.sadd("src", &["a".to_string()]).unwrap();
+
+        storage.start_transaction();
+        assert_eq!(storage.smove("src", "dst", "a"), Ok(true));
+        assert_eq!(storage.key_type("src"), "none");
+        assert_eq!(storage.smembers("dst"), vec!["a".to_string()]);
+        storage.rollback_transaction().unwrap();
+        assert_eq!(storage.smembers("src"), vec!["a".to_string()]);
+        assert_eq!(storage.key_type("dst"), "none");
+
+        storage.start_transaction();
+        storage.smove("src", "dst", "a").unwrap();
+        storage.commit_transaction().unwrap();
+        assert_eq!(storage.key_type("src"), "none");
+        assert_eq!(storage.smembers("dst"), vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_sets_survive_snapshot() {
+        let path = std::env::temp_dir().join("storage_sets.snapshot");
+        let path = path.to_str().unwrap();
+        let mut storage = MemoryStorage::new();
+        storage.sadd("colors", &["red".to_string(), "blue".to_string()]).unwrap();
+        storage.save_snapshot(path).unwrap();
+
+        let mut restored = MemoryStorage::new();
+        restored.load_snapshot(path).unwrap();
+        assert_eq!(restored.smembers("colors"), vec!["blue".to_string(), "red".to_string()]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_lmove() {
+        let mut storage = MemoryStorage::new();
+        storage.rpush("src", "a".to_string());
+        storage.rpush("src", "b".to_string());
+
+        assert_eq!(storage.lmove("src", "dst", false, true), Ok(Some("b".to_string())));
+        assert_eq!(storage.lmove("src", "src", true, false), Ok(Some("a".to_string())));
+        assert_eq!(storage.list_values("src"), vec!["a".to_string()]);
+        assert_eq!(storage.lmove("src", "dst", true, false), Ok(Some("a".to_string())));
+        assert_eq!(storage.list_values("dst"), vec!["b".to_string(), "a".to_string()]);
+        assert_eq!(storage.lmove("src", "dst", true, true), Ok(None));
+    }
+
+    #[test]
+    fn test_set_store_operations() {
+        let mut storage = MemoryStorage::new();
+        let keys = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        storage.sadd("a", &keys(&["1", "2", "3"])).unwrap();
+        storage.sadd("b", &keys(&["2", "3", "4"])).unwrap();
+
+        assert_eq!(storage.sdiffstore("diff", &keys(&["a", "b"])), Ok(1));
+        assert_eq!(storage.smembers("diff"), keys(&["1"]));
+        assert_eq!(storage.sunionstore("union", &keys(&["a", "b", "missing"])), Ok(4));
+        assert_eq!(storage.smembers("union"), keys(&["1", "2", "3", "4"]));
+        assert_eq!(storage.sinterstore("inter", &keys(&["a", "b"])), Ok(2));
+        assert_eq!(storage.smembers("inter"), keys(&["2", "3"]));
+
+        // The destination may be one of the sources
+        assert_eq!(storage.sinterstore("a", &keys(&["a", "b"])), Ok(2));
+        assert_eq!(storage.smembers("a"), keys(&["2", "3"]));
+
+        // An empty result deletes the destination, whatever it held
+        storage.set("string".to_string(), "value".to_string());
+        assert_eq!(storage.sinterstore("string", &keys(&["a", "missing"])), Ok(0));
+        assert_eq!(storage.key_type("string"), "none");
+        assert_eq!(storage.sunionstore("list", &keys(&["b"])), Ok(3));
+        assert_eq!(storage.key_type("list"), "set");
+
+        storage.rpush("queue", "x".to_string());
+        assert!(storage.sdiffstore("diff", &keys(&["a", "queue"])).unwrap_err().starts_with("WRONGTYPE"));
+        assert_eq!(storage.smembers("diff"), keys(&["1"]));
+    }
+
+    #[test]
+    fn test_sintercard() {
+        let mut storage = MemoryStorage::new();
+        let keys = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        let big: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+        let evens: Vec<String> = (0..100).step_by(2).map(|i| i.to_string()).collect();
+        storage.sadd("big", &big).unwrap();
+        storage.sadd("evens", &evens).unwrap();
+
+        assert_eq!(storage.sintercard(&keys(&["big", "evens"]), 0), Ok(50));
+        assert_eq!(storage.sintercard(&keys(&["big", "evens"]), 10), Ok(10));
+        assert_eq!(storage.sintercard(&keys(&["big", "evens"]), 500), Ok(50));
+        assert_eq!(storage.sintercard(&keys(&["big"]), 3), Ok(3));
+        assert_eq!(storage.sintercard(&keys(&["big", "missing"]), 0), Ok(0));
+
+        storage.set("string".to_string(), "1".to_string());
+        assert!(storage.sintercard(&keys(&["big", "string"]), 0).unwrap_err().starts_with("WRONGTYPE"));
+    }
+
+    #[test]
+    fn test_zrangebyscore_limit() {
+        let mut storage = MemoryStorage::new();
+        let pairs: Vec<(f64, String)> = (0..10).map(|i| (i as f64, format!("m{}", i))).collect();
+        storage.zadd("board", &pairs, &ZAddOptions::default()).unwrap();
+        let members = |found: Vec<(String, f64)>| found.into_iter().map(|(member, _)| member).collect::<Vec<_>>();
+
+        let all = storage.zrangebyscore("board", ScoreBound::Inclusive(2.0), ScoreBound::Exclusive(8.0), 0, -1);
+        assert_eq!(all.len(), 6);
+        assert_eq!(all[0], ("m2".to_string(), 2.0));
+        assert_eq!(members(storage.zrangebyscore("BOARD", ScoreBound::NegInf, ScoreBound::PosInf, 3, 2)), vec!["m3", "m4"]);
+        assert_eq!(members(storage.zrangebyscore("board", ScoreBound::NegInf, ScoreBound::PosInf, 8, 5)), vec!["m8", "m9"]);
+        assert_eq!(members(storage.zrangebyscore("board", ScoreBound::NegInf, ScoreBound::PosInf, 8, -3)), vec!["m8", "m9"]);
+        assert!(storage.zrangebyscore("board", ScoreBound::NegInf, ScoreBound::PosInf, -1, 5).is_empty());
+        assert!(storage.zrangebyscore("board", ScoreBound::NegInf, ScoreBound::PosInf, 20, 5).is_empty());
+        assert!(storage.zrangebyscore("missing", ScoreBound::NegInf, ScoreBound::PosInf, 0, -1).is_empty());
+
+        assert_eq!(
+            members(storage.zrevrangebyscore("board", ScoreBound::Inclusive(7.0), ScoreBound::Exclusive(2.0), 1, 3)),
+            vec!["m6", "m5", "m4"]
+        );
+    }
+
+    #[test]
+    fn test_zpopmin_and_zpopmax() {
+        let mut storage = MemoryStorage::new();
+        let pairs: Vec<(f64, String)> = (1..=5).map(|i| (i as f64, format!("m{}", i))).collect();
+        storage.zadd("board", &pairs, &ZAddOptions::default()).unwrap();
+
+        assert_eq!(storage.zpopmin("board", 2), vec![("m1".to_string(), 1.0), ("m2".to_string(), 2.0)]);
+        assert_eq!(storage.zpopmax("BOARD", 1), vec![("m5".to_string(), 5.0)]);
+        assert!(storage.zpopmin("board", 0).is_empty());
+        assert_eq!(storage.zpopmax("board", 10), vec![("m4".to_string(), 4.0), ("m3".to_string(), 3.0)]);
+
+        // Popping the last member deletes the key
+        assert_eq!(storage.key_type("board"), "none");
+        assert!(storage.zpopmin("board", 1).is_empty());
+    }
+
+    #[test]
+    fn test_zset_algebra_checks_types() {
+        let mut storage = MemoryStorage::new();
+        let keys = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        storage.zadd("a", &[(1.0, "x".to_string()), (2.0, "y".to_string())], &ZAddOptions::default()).unwrap();
+        storage.zadd("b", &[(5.0, "y".to_string())], &ZAddOptions::default()).unwrap();
+
+        assert_eq!(storage.zdiff(&keys(&["A", "b"])), Ok(vec![("x".to_string(), 1.0)]));
+        assert_eq!(storage.zinter(&keys(&["a", "b"]), &[], AggType::Max), Ok(vec![("y".to_string(), 5.0)]));
+        assert_eq!(storage.zunion(&keys(&["a", "missing"]), &[], AggType::Sum).unwrap().len(), 2);
+
+        storage.set("string".to_string(), "1".to_string());
+        assert!(storage.zunion(&keys(&["a", "string"]), &[], AggType::Sum).unwrap_err().starts_with("WRONGTYPE"));
+        assert!(storage.zdiff(&keys(&["string"])).unwrap_err().starts_with("WRONGTYPE"));
+    }
+
+    #[test]
+    fn test_zrangebylex_and_zlexcount() {
+        let mut storage = MemoryStorage::new();
+        let pairs: Vec<(f64, String)> = ["a", "b", "c", "d", "e"].iter().map(|m| (0.0, m.to_string())).collect();
+        storage.zadd("letters", &pairs, &ZAddOptions::default()).unwrap();
+        let b = LexBound::Inclusive("b".to_string());
+        let d = LexBound::Exclusive("d".to_string());
+
+        assert_eq!(storage.zrangebylex("letters", &b, &d, 0, -1, false), vec!["b", "c"]);
+        assert_eq!(storage.zrangebylex("letters", &LexBound::NegInf, &LexBound::PosInf, 1, 2, false), vec!["b", "c"]);
+        assert_eq!(storage.zrangebylex("LETTERS", &b, &LexBound::PosInf, 0, 2, true), vec!["e", "d"]);
+        assert!(storage.zrangebylex("missing", &LexBound::NegInf, &LexBound::PosInf, 0, -1, false).is_empty());
+        assert_eq!(storage.zlexcount("letters", &b, &d), 2);
+        assert_eq!(storage.zlexcount("letters", &LexBound::NegInf, &LexBound::PosInf), 5);
+        assert_eq!(storage.zlexcount("missing", &LexBound::NegInf, &LexBound::PosInf), 0);
+    }
+
+    #[test]
+    fn test_dirty_count_tracks_writes() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(storage.dirty_count(), 0);
+
+        storage.set("a".to_string(), "1".to_string());
+        storage.incr("a").unwrap();
+        storage.rpush("list", "x".to_string());
+        storage.sadd("set", &["m".to_string()]).unwrap();
+        assert_eq!(storage.dirty_count(), 4);
+
+        storage.get("a");
+        storage.llen("list");
+        storage.smembers("set");
+        assert!(storage.lpop("missing").is_none());
+        assert!(!storage.del("absent"));
+        storage.sadd("set", &["m".to_string()]).unwrap();
+        assert_eq!(storage.dirty_count(), 4);
+
+        let counter = storage.dirty_counter();
+        storage.del("a");
+        assert_eq!(counter.load(std::sync::atomic::Ordering::Relaxed), 5);
+
+        storage.mark_saved();
+        assert_eq!(storage.dirty_count(), 0);
+        assert!(storage.since_last_save() < std::time::Duration::from_secs(1));
+    }
+    #[test]
+    fn test_slot_index() {
+        use redis_imitate::cluster::slots::hash_slot;
+
+        let mut storage = MemoryStorage::new();
+        storage.set("before".to_string(), "1".to_string());
+        assert!(!storage.slot_index_enabled());
+        assert!(storage.cluster_getkeysinslot(hash_slot("before"), 10).is_empty());
+
+        storage.enable_slot_index();
+        assert_eq!(storage.cluster_getkeysinslot(hash_slot("before"), 10), vec!["before".to_string()]);
+
+        // Hash tags put these in one slot, whatever their type
+        let slot = hash_slot("{user1}");
+        storage.set("{user1}.name".to_string(), "ann".to_string());
+        storage.rpush("{user1}.visits", "home".to_string());
+        storage.sadd("{user1}.tags", &["a".to_string()]).unwrap();
+        storage.zadd("{user1}.scores", &[(1.0, "x".to_string())], &ZAddOptions::default()).unwrap();
+        storage.incr("{USER1}.count").unwrap();
+        let mut keys = storage.cluster_getkeysinslot(slot, 10);
+        keys.sort();
+        assert_eq!(keys, vec!["{user1}.count", "{user1}.name", "{user1}.scores", "{user1}.tags", "{user1}.visits"]);
+        assert_eq!(storage.cluster_getkeysinslot(slot, 2).len(), 2);
+
+        // Deleted, emptied and expired keys drop out
+        storage.del("{user1}.name");
+        storage.lpop("{user1}.visits");
+        storage.expire("{user1}.count", 0);
+        let mut keys = storage.cluster_getkeysinslot(slot, 10);
+        keys.sort();
+        assert_eq!(keys, vec!["{user1}.scores", "{user1}.tags"]);
+
+        storage.flushall();
+        assert!(storage.cluster_getkeysinslot(slot, 10).is_empty());
+    }
+
+    #[test]
+    fn test_purge_expired_sample() {
+        let mut storage = MemoryStorage::new();
+        for i in 0..ACTIVE_EXPIRE_SAMPLE + 5 {
+            storage.set(format!("gone{}", i), "x".to_string());
+            storage.expire(&format!("gone{}", i), 0);
+        }
+        storage.set("kept".to_string(), "x".to_string());
+        storage.expire("kept", 100);
+
+        assert_eq!(storage.purge_expired_sample(), ACTIVE_EXPIRE_SAMPLE);
+        assert_eq!(storage.purge_expired_sample(), 5);
+        assert_eq!(storage.purge_expired_sample(), 0);
+        assert_eq!(storage.get("kept"), Some("x".to_string()));
+    }
+
+    #[test]
+    fn test_hset_and_hsetnx() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(storage.hset("user", &[("name".to_string(), "ada".to_string()), ("Name".to_string(), "Ada".to_string())]), Ok(2));
+        assert_eq!(storage.hset("user", &[("name".to_string(), "grace".to_string()), ("lang".to_string(), "cobol".to_string())]), Ok(1));
+        assert_eq!(storage.hget("USER", "name"), Some("grace".to_string()));
+        assert_eq!(storage.hlen("user"), 3);
+        assert_eq!(storage.key_type("user"), "hash");
+
+        assert_eq!(storage.hsetnx("user", "lang", "fortran"), Ok(false));
+        assert_eq!(storage.hget("user", "lang"), Some("cobol".to_string()));
+        assert_eq!(storage.hsetnx("user", "born", "1906"), Ok(true));
+        assert_eq!(storage.hlen("user"), 4);
+
+        storage.rpush("list", "a".to_string());
+        assert!(storage.hset("list", &[("f".to_string(), "v".to_string())]).unwrap_err().starts_with("WRONGTYPE"));
+        assert!(storage.hsetnx("list", "f", "v").unwrap_err().starts_with("WRONGTYPE"));
+        assert!(storage.sadd("user", &["x".to_string()]).unwrap_err().starts_with("WRONGTYPE"));
+
+        assert!(storage.del("user"));
+        assert_eq!(storage.key_type("user"), "none");
+    }
+
+    #[test]
+    fn test_hashes_in_transactions_and_snapshots() {
+        let mut storage = MemoryStorage::new();
+        storage.start_transaction();
+        storage.hset("h", &[("f".to_string(), "v".to_string())]).unwrap();
+        storage.rollback_transaction().unwrap();
+        assert_eq!(storage.hlen("h"), 0);
+
+        storage.start_transaction();
+        storage.hset("h", &[("f".to_string(), "v".to_string())]).unwrap();
+        assert_eq!(storage.hget("h", "f"), Some("v".to_string()));
+        storage.commit_transaction().unwrap();
+        assert_eq!(storage.hget("h", "f"), Some("v".to_string()));
+
+        let path = std::env::temp_dir().join(format!("storage_hashes_{}.snapshot", std::process::id()));
+        let path = path.to_str().unwrap();
+        storage.hset("h", &[("g".to_string(), "w".to_string())]).unwrap();
+        storage.save_snapshot(path).unwrap();
+        let mut restored = MemoryStorage::new();
+        restored.load_snapshot(path).unwrap();
+        assert_eq!(restored.hget("h", "f"), Some("v".to_string()));
+        assert_eq!(restored.hget("h", "g"), Some("w".to_string()));
+        std::fs::remove_file(path).unwrap();
+
+        storage.flushall();
+        assert_eq!(storage.key_type("h"), "none");
+    }
+
+    #[test]
+    fn test_hincrby_and_hincrbyfloat() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(storage.hincrby("user:1", "visits", 1), Ok(1));
+        assert_eq!(storage.hincrby("user:1", "visits", -5), Ok(-4));
+        assert_eq!(storage.hget("user:1", "visits"), Some("-4".to_string()));
+        assert_eq!(storage.hincrby("user:1", "visits", i64::MIN), Err("ERR increment or decrement would overflow".to_string()));
+
+        storage.hset("user:1", &[("name".to_string(), "ada".to_string()), ("price".to_string(), "10.50".to_string())]).unwrap();
+        assert_eq!(storage.hincrby("user:1", "name", 1), Err("ERR hash value is not an integer".to_string()));
+        assert_eq!(storage.hincrbyfloat("user:1", "name", 1.0), Err("ERR hash value is not a float".to_string()));
+        assert_eq!(storage.hincrbyfloat("user:1", "price", 0.1), Ok(10.6));
+        assert_eq!(storage.hget("user:1", "price"), Some("10.6".to_string()));
+        assert_eq!(storage.hincrbyfloat("user:1", "price", -0.6), Ok(10.0));
+        assert_eq!(storage.hget("user:1", "price"), Some("10".to_string()));
+        assert_eq!(storage.hincrbyfloat("user:1", "price", f64::MAX), Ok(f64::MAX));
+        assert_eq!(
+            storage.hincrbyfloat("user:1", "price", f64::MAX),
+            Err("ERR increment would produce NaN or Infinity".to_string())
+        );
+        assert_eq!(storage.hincrbyfloat("fresh", "ratio", 0.5), Ok(0.5));
+
+        storage.set("plain".to_string(), "1".to_string());
+        assert!(storage.hincrby("plain", "f", 1).unwrap_err().starts_with("WRONGTYPE"));
+        assert!(storage.hincrbyfloat("plain", "f", 1.0).unwrap_err().starts_with("WRONGTYPE"));
+    }
+
+    #[test]
+    fn test_scans_visit_every_element_once() {
+        let mut storage = MemoryStorage::new();
+        let fields: Vec<(String, String)> = (0..50).map(|i| (format!("field{}", i), i.to_string())).collect();
+        storage.hset("hash", &fields).unwrap();
+        let mut seen = Vec::new();
+        let mut cursor = 0;
+        let mut calls = 0;
+        loop {
+            let (next, pairs) = storage.hscan("hash", cursor, None, 7);
+            assert!(pairs.len() <= 8);
+            seen.extend(pairs);
+            calls += 1;
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+            // Changes between calls don't disturb the rest of the iteration
+            storage.hset("hash", &[("added".to_string(), "x".to_string())]).unwrap();
+        }
+        assert!(calls >= 8);
+        seen.retain(|(field, _)| field != "added");
+        seen.sort();
+        let mut expected = fields.clone();
+        expected.sort();
+        assert_eq!(seen, expected);
+
+        let (next, matching) = storage.hscan("hash", 0, Some("field1*"), 1000);
+        assert_eq!(next, 0);
+        assert_eq!(matching.len(), 11);
+
+        storage.sadd("set", &["a".to_string(), "b".to_string(), "c".to_string()]).unwrap();
+        let (next, mut members) = storage.sscan("set", 0, None, 10);
+        members.sort();
+        assert_eq!((next, members), (0, vec!["a".to_string(), "b".to_string(), "c".to_string()]));
+
+        storage.zadd("zset", &[(1.5, "one".to_string()), (2.0, "two".to_string())], &ZAddOptions::default()).unwrap();
+        let (next, members) = storage.zscan("zset", 0, Some("t*"), 10);
+        assert_eq!((next, members), (0, vec![("two".to_string(), 2.0)]));
+
+        assert_eq!(storage.hscan("missing", 0, None, 10), (0, Vec::new()));
+        assert_eq!(storage.sscan("hash", 0, None, 10), (0, Vec::new()));
+    }
+
+    #[test]
+    fn test_snapshot_stream_replaces_data_and_keeps_deadlines() {
+        let mut storage = MemoryStorage::new();
+        storage.set("session".to_string(), "abc".to_string());
+        storage.expire("session", 100);
+        storage.rpush("queue", "job".to_string());
+        let mut snapshot = Vec::new();
+        storage.frozen().write_snapshot(&mut snapshot).unwrap();
+
+        let mut replica = MemoryStorage::new();
+        replica.set("stale".to_string(), "x".to_string());
+        replica.set("queue".to_string(), "old".to_string());
+        replica.expire("queue", 5);
+        replica.load_snapshot_from(&snapshot[..]).unwrap();
+        assert_eq!(replica.get("stale"), None);
+        assert_eq!(replica.ttl("queue"), -1);
+        assert_eq!(replica.lpop("queue"), Some("job".to_string()));
+        assert_eq!(replica.get("session"), Some("abc".to_string()));
+        // Deadlines travel as whole Unix seconds, rounded up
+        let ttl = replica.ttl("session");
+        assert!(ttl > 90 && ttl <= 101, "{}", ttl);
+    }
+
+    #[test]
+    fn test_get_after_incr_sees_the_new_value() {
+        let mut storage = MemoryStorage::new();
+        storage.set("counter".to_string(), "1".to_string());
+        assert_eq!(storage.get("counter"), Some("1".to_string()));
+        storage.incr("counter").unwrap();
+        assert_eq!(storage.get("counter"), Some("2".to_string()));
+        storage.decr("counter").unwrap();
+        assert_eq!(storage.get("counter"), Some("1".to_string()));
+    }
+
+    #[test]
+    fn test_copy_carries_the_source_expiration() {
+        let mut storage = MemoryStorage::new();
+        storage.set("src".to_string(), "v".to_string());
+        storage.expire("src", 100);
+        assert_eq!(storage.copy("src", "dst", false), Ok(true));
+        assert_eq!(storage.get("dst"), Some("v".to_string()));
+        assert_eq!(storage.ttl("dst"), 100);
+        // The copy's expiration is its own
+        storage.persist("src");
+        assert_eq!(storage.ttl("dst"), 100);
+
+        // REPLACE drops the destination's expiration along with its value
+        storage.rpush("list", "a".to_string());
+        storage.expire("dst", 5);
+        assert_eq!(storage.copy("list", "dst", false), Ok(false));
+        assert_eq!(storage.copy("list", "dst", true), Ok(true));
+        assert_eq!(storage.list_values("dst"), vec!["a".to_string()]);
+        assert_eq!(storage.get("dst"), None);
+        assert_eq!(storage.ttl("dst"), -1);
+        storage.expire("list", 50);
+        assert_eq!(storage.copy("list", "dst", true), Ok(true));
+        assert_eq!(storage.ttl("dst"), 50);
+
+        // An expired source is gone even if nothing reaped it yet
+        storage.set_active_expire(false);
+        storage.hset("hash", &[("f".to_string(), "v".to_string())]).unwrap();
+        storage.expire("hash", 0);
+        assert_eq!(storage.copy("hash", "copy", false), Ok(false));
+        assert_eq!(storage.key_type("copy"), "none");
+        assert_eq!(storage.copy("dst", "dst", true), Err("ERR source and destination objects are the same".to_string()));
+    }
+
+    #[test]
+    fn test_object_encoding_of_strings() {
+        let mut storage = MemoryStorage::new();
+        let mut encoding = |value: String| {
+            storage.set("k".to_string(), value);
+            storage.object_encoding("k")
+        };
+        assert_eq!(encoding("12345".to_string()), Some("int"));
+        assert_eq!(encoding("-1".to_string()), Some("int"));
+        assert_eq!(encoding(i64::MAX.to_string()), Some("int"));
+        assert_eq!(encoding(i64::MIN.to_string()), Some("int"));
+        // One past the i64 range, or not an integer at all
+        assert_eq!(encoding("9223372036854775808".to_string()), Some("embstr"));
+        assert_eq!(encoding("9999999999999999999999".to_string()), Some("embstr"));
+        assert_eq!(encoding("1.5".to_string()), Some("embstr"));
+        assert_eq!(encoding("hello".to_string()), Some("embstr"));
+        assert_eq!(encoding("a".repeat(44)), Some("embstr"));
+        assert_eq!(encoding("a".repeat(45)), Some("raw"));
+        // A long digit string is too big for i64, so its length decides
+        assert_eq!(encoding("1".repeat(45)), Some("raw"));
+        assert_eq!(storage.object_encoding("missing"), None);
+    }
+
+    #[test]
+    fn test_volatile_eviction_only_takes_keys_with_a_ttl() {
+        let mut storage = MemoryStorage::new();
+        storage.set("kept".to_string(), "value".to_string());
+        storage.set("soon".to_string(), "value".to_string());
+        storage.set("later".to_string(), "value".to_string());
+        storage.expire("soon", 10);
+        storage.expire("later", 1000);
+
+        let mut evicted = Vec::new();
+        assert!(!storage.evict(0, MaxMemoryPolicy::VolatileTtl, &mut evicted));
+        assert_eq!(evicted, vec!["soon".to_string(), "later".to_string()]);
+        assert!(storage.exists("kept"));
+
+        let mut evicted = Vec::new();
+        assert!(!storage.evict(0, MaxMemoryPolicy::NoEviction, &mut evicted));
+        assert!(evicted.is_empty());
+        assert!(storage.evict(0, MaxMemoryPolicy::AllKeysLfu, &mut evicted));
+        assert_eq!(storage.used_memory(), 0);
+    }
+
+    #[test]
+    fn test_sscan_survives_a_set_growing_mid_scan() {
+        let mut storage = MemoryStorage::new();
+        let numbers: Vec<String> = (0..20).map(|n| n.to_string()).collect();
+        storage.sadd("set", &numbers).unwrap();
+
+        // Page through while the set outgrows anything intset-shaped
+        let mut seen = Vec::new();
+        let mut cursor = 0;
+        let mut pages = 0;
+        loop {
+            let (next, members) = storage.sscan("set", cursor, None, 3);
+            seen.extend(members);
+            if pages == 1 {
+                let words: Vec<String> = (0..200).map(|n| format!("member{}", n)).collect();
+                storage.sadd("set", &words).unwrap();
+            }
+            pages += 1;
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        // Every original member once; some of the new ones may show up too
+        seen.retain(|member| !member.starts_with("member"));
+        seen.sort();
+        let mut expected = numbers;
+        expected.sort();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn test_lpos_with_rank_count_and_maxlen() {
+        let mut storage = MemoryStorage::new();
+        for item in ["a", "b", "c", "a", "b", "a"] {
+            storage.rpush("list", item.to_string());
+        }
+        assert_eq!(storage.lpos("list", "a", 1, 1, 0), vec![0]);
+        assert_eq!(storage.lpos("list", "a", 2, 0, 0), vec![3, 5]);
+        assert_eq!(storage.lpos("list", "a", -1, 0, 0), vec![5, 3, 0]);
+        assert_eq!(storage.lpos("list", "b", -2, 1, 0), vec![1]);
+
+        // MAXLEN bounds the comparisons, not the matches
+        assert_eq!(storage.lpos("list", "a", 1, 0, 4), vec![0, 3]);
+        assert_eq!(storage.lpos("list", "a", -1, 0, 3), vec![5, 3]);
+        assert_eq!(storage.lpos("list", "a", -1, 0, 2), vec![5]);
+        assert_eq!(storage.lpos("list", "c", -1, 1, 3), Vec::<usize>::new());
+        assert_eq!(storage.lpos("list", "missing", 1, 0, 0), Vec::<usize>::new());
+        assert_eq!(storage.lpos("nothing", "a", 1, 0, 0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_geoadd_options() {
+        let mut storage = MemoryStorage::new();
+        let palermo = (13.361389, 38.115556, "Palermo".to_string());
+        let catania = (15.087269, 37.502669, "Catania".to_string());
+        let add = |nx, xx, ch| GeoAddOptions { nx, xx, ch };
+
+        assert_eq!(storage.geoadd("sicily", &[palermo.clone(), catania.clone()], &add(false, false, false)), Ok(2));
+        // The same scores a real server stores
+        assert_eq!(storage.zscore("sicily", "Palermo"), Some(3479099956230698.0));
+        assert_eq!(storage.zscore("sicily", "Catania"), Some(3479447370796909.0));
+
+        let moved = (13.583333, 37.316667, "Palermo".to_string());
+        let agrigento = (13.583333, 37.316667, "Agrigento".to_string());
+        assert_eq!(storage.geoadd("sicily", std::slice::from_ref(&moved), &add(true, false, false)), Ok(0));
+        assert_eq!(storage.zscore("sicily", "Palermo"), Some(3479099956230698.0));
+        assert_eq!(storage.geoadd("sicily", std::slice::from_ref(&agrigento), &add(false, true, false)), Ok(0));
+        assert_eq!(storage.zscore("sicily", "Agrigento"), None);
+        assert_eq!(storage.geoadd("sicily", &[moved.clone(), agrigento.clone()], &add(false, false, false)), Ok(1));
+        assert_eq!(storage.geoadd("sicily", &[palermo, agrigento, catania], &add(false, false, true)), Ok(1));
+
+        // One bad pair fails the whole command
+        let far = (181.0, 0.0, "far".to_string());
+        let new = (0.0, 0.0, "new".to_string());
+        assert_eq!(
+            storage.geoadd("sicily", &[new, far], &add(false, false, false)),
+            Err("ERR invalid longitude,latitude pair 181.000000,0.000000".to_string())
+        );
+        assert_eq!(storage.zcard("sicily"), 3);
+        assert!(storage.geoadd("sicily", &[(0.0, 85.06, "pole".to_string())], &add(false, false, false)).is_err());
+        assert_eq!(storage.geoadd("sicily", &[(180.0, -85.05112878, "corner".to_string())], &add(false, false, false)), Ok(1));
+    }
+
+    #[test]
+    fn test_geo_decode_and_distance() {
+        let (longitude, latitude) = geo::decode(geo::encode(13.361389, 38.115556));
+        assert!((longitude - 13.361389).abs() < 1e-5 && (latitude - 38.115556).abs() < 1e-5);
+        // GEODIST Sicily Palermo Catania on a real server
+        let meters = geo::distance(13.361389, 38.115556, 15.087269, 37.502669);
+        assert!((meters - 166274.1516).abs() < 0.5, "{}", meters);
+    }
+
+    #[test]
+    fn test_geosearch_finds_what_a_full_scan_would() {
+        // Clusters straddling the antimeridian, near both latitude limits and elsewhere
+        let centres = [(13.361389, 38.115556), (179.9, 0.0), (-179.9, 10.0), (0.0, 84.9), (120.0, -84.9), (0.0, 0.0)];
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut zset = SortedSet::new();
+        for i in 0..3000 {
+            let (longitude, latitude) = centres[i % centres.len()];
+            let longitude = (longitude + rng.gen_range(-3.0..3.0f64) + 540.0) % 360.0 - 180.0;
+            let latitude = (latitude + rng.gen_range(-3.0..3.0f64)).clamp(geo::LATITUDE_RANGE.0, geo::LATITUDE_RANGE.1);
+            zset.insert(&format!("member{}", i), geo::encode(longitude, latitude));
+        }
+
+        // What scanning every member finds
+        let inside = |(longitude, latitude): (f64, f64), shape: GeoShape, hash: f64| {
+            let (member_longitude, member_latitude) = geo::decode(hash);
+            match shape {
+                GeoShape::Radius(radius) => geo::distance(longitude, latitude, member_longitude, member_latitude) <= radius * 1000.0,
+                GeoShape::Box(width, height) => {
+                    geo::distance(member_longitude, latitude, member_longitude, member_latitude) <= height * 500.0
+                        && geo::distance(longitude, member_latitude, member_longitude, member_latitude) <= width * 500.0
+                }
+            }
+        };
+        let shapes = [
+            GeoShape::Radius(1.0),
+            GeoShape::Radius(50.0),
+            GeoShape::Radius(400.0),
+            GeoShape::Radius(5000.0),
+            GeoShape::Radius(25000.0),
+            GeoShape::Box(2.0, 2.0),
+            GeoShape::Box(300.0, 100.0),
+            GeoShape::Box(100.0, 3000.0),
+            GeoShape::Box(30000.0, 500.0),
+        ];
+        for centre in centres {
+            for shape in shapes {
+                let params = GeoSearchParams {
+                    origin: GeoOrigin::LonLat(centre.0, centre.1),
+                    shape,
+                    unit: GeoUnit::Kilometers,
+                    descending: None,
+                    count: None,
+                    any: false,
+                    with_coord: false,
+                    with_dist: false,
+                    with_hash: false,
+                };
+                let mut found: Vec<String> = geo::search(&zset, &params).unwrap().into_iter().map(|found| found.member).collect();
+                let mut expected: Vec<String> =
+                    zset.iter().filter(|(_, hash)| inside(centre, shape, *hash)).map(|(member, _)| member.to_string()).collect();
+                found.sort();
+                expected.sort();
+                assert_eq!(found, expected, "{:?} around {:?}", shape, centre);
+
+                // ANY takes the first matches the cells turn up, all of them inside
+                let any = geo::search(&zset, &GeoSearchParams { count: Some(3), any: true, ..params }).unwrap();
+                assert_eq!(any.len(), expected.len().min(3));
+                assert!(any.iter().all(|found| expected.contains(&found.member)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_sort_geo_results_keeps_ties_in_the_order_found() {
+        let found = |member: &str, distance: f64| GeoMatch {
+            member: member.to_string(),
+            distance,
+            hash: 0.0,
+            longitude: 0.0,
+            latitude: 0.0,
+        };
+        let sorted = |descending: Option<bool>, count: Option<(usize, bool)>| {
+            let mut results = vec![found("b", 2.0), found("tie1", 1.0), found("a", 3.0), found("tie2", 1.0), found("tie3", 1.0)];
+            geo::sort_geo_results(&mut results, descending, count);
+            results.into_iter().map(|result| result.member).collect::<Vec<_>>()
+        };
+
+        assert_eq!(sorted(Some(false), None), ["tie1", "tie2", "tie3", "b", "a"]);
+        assert_eq!(sorted(Some(true), None), ["a", "b", "tie1", "tie2", "tie3"]);
+        assert_eq!(sorted(None, None), ["b", "tie1", "a", "tie2", "tie3"]);
+        // COUNT alone keeps the closest; with ANY, the first found
+        assert_eq!(sorted(None, Some((2, false))), ["tie1", "tie2"]);
+        assert_eq!(sorted(Some(true), Some((2, false))), ["a", "b"]);
+        assert_eq!(sorted(None, Some((2, true))), ["b", "tie1"]);
+        assert_eq!(sorted(Some(false), Some((2, true))), ["tie1", "b"]);
+    }
+
+    #[test]
+    fn test_geosearchstore_keeps_destination_on_error() {
+        let mut storage = MemoryStorage::new();
+        storage.geoadd("sicily", &[(13.361389, 38.115556, "Palermo".to_string())], &GeoAddOptions::default()).unwrap();
+        storage.set("near".to_string(), "kept".to_string());
+        let params = GeoSearchParams {
+            origin: GeoOrigin::Member("Rome".to_string()),
+            shape: GeoShape::Radius(100.0),
+            unit: GeoUnit::Kilometers,
+            descending: None,
+            count: None,
+            any: false,
+            with_coord: false,
+            with_dist: false,
+            with_hash: false,
+        };
+        assert!(storage.geosearchstore("sicily", "near", &params, GeoStoreMode::Geohash).is_err());
+        assert_eq!(storage.get("near"), Some("kept".to_string()));
+
+        let params = GeoSearchParams { origin: GeoOrigin::Member("Palermo".to_string()), ..params };
+        assert_eq!(storage.geosearchstore("sicily", "near", &params, GeoStoreMode::Distance), Ok(1));
+        assert_eq!(storage.zscore("near", "Palermo"), Some(0.0));
+        assert_eq!(storage.key_type("near"), "zset");
+    }
+
+    #[test]
+    fn test_memory_usage_for() {
+        let mut storage = MemoryStorage::new();
+        storage.set("greeting".to_string(), "hello".to_string());
+        // Value, name and the per-key overhead
+        assert_eq!(storage.memory_usage_for("GREETING", 5), Some(5 + 8 + 64));
+        assert_eq!(storage.memory_usage_for("missing", 5), None);
+
+        // Two 1-byte elements sampled, scaled to all four: 4 * (1 + 16)
+        for value in ["a", "b", "ccc", "ddd"] {
+            storage.rpush("list", value.to_string());
+        }
+        assert_eq!(storage.memory_usage_for("list", 2), Some(4 * 17 + 4 + 128));
+        assert_eq!(storage.memory_usage_for("list", 0), Some((2 * 17 + 2 * 19) + 4 + 128));
+
+        storage.sadd("set", &["abcd".to_string(), "efgh".to_string()]).unwrap();
+        assert_eq!(storage.memory_usage_for("set", 5), Some((8.0_f64 * 1.3).ceil() as usize + 3 + 128));
+        storage.hset("hash", &[("field".to_string(), "value".to_string())]).unwrap();
+        assert_eq!(storage.memory_usage_for("hash", 5), Some(15 + 4 + 128));
+        storage.zadd("zset", &[(1.0, "member".to_string())], &ZAddOptions::default()).unwrap();
+        assert_eq!(storage.memory_usage_for("zset", 5), Some(6 + 8 + 24 + 4 + 128));
+
+        storage.set("soon".to_string(), "gone".to_string());
+        storage.expire("soon", 0);
+        assert_eq!(storage.memory_usage_for("soon", 5), None);
+    }
+
+    #[test]
+    fn test_collections_upgrade_at_exactly_the_threshold() {
+        let mut storage = MemoryStorage::new();
+        storage.set_encoding_limits(EncodingLimits {
+            list_max_listpack_size: 3,
+            hash_max_listpack_entries: 3,
+            set_max_intset_entries: 3,
+            set_max_listpack_entries: 4,
+            zset_max_listpack_entries: 3,
+            ..EncodingLimits::default()
+        });
+        for i in 0..3 {
+            storage.rpush("list", i.to_string());
+            storage.hset("hash", &[(i.to_string(), "v".to_string())]).unwrap();
+            storage.sadd("ints", &[i.to_string()]).unwrap();
+            storage.zadd("zset", &[(i as f64, i.to_string())], &ZAddOptions::default()).unwrap();
+        }
+        assert_eq!(storage.object_encoding("list"), Some("listpack"));
+        assert_eq!(storage.object_encoding("hash"), Some("listpack"));
+        assert_eq!(storage.object_encoding("ints"), Some("intset"));
+        assert_eq!(storage.object_encoding("zset"), Some("listpack"));
+
+        storage.rpush("list", "3".to_string());
+        storage.hset("hash", &[("3".to_string(), "v".to_string())]).unwrap();
+        storage.sadd("ints", &["3".to_string()]).unwrap();
+        storage.zadd("zset", &[(3.0, "3".to_string())], &ZAddOptions::default()).unwrap();
+        assert_eq!(storage.object_encoding("list"), Some("quicklist"));
+        assert_eq!(storage.object_encoding("hash"), Some("hashtable"));
+        assert_eq!(storage.object_encoding("ints"), Some("listpack"));
+        assert_eq!(storage.object_encoding("zset"), Some("skiplist"));
+        storage.sadd("ints", &["4".to_string()]).unwrap();
+        assert_eq!(storage.object_encoding("ints"), Some("hashtable"));
+
+        // A non-integer member ends the intset however small the set is
+        storage.sadd("words", &["1".to_string()]).unwrap();
+        assert_eq!(storage.object_encoding("words"), Some("intset"));
+        storage.sadd("words", &["one".to_string()]).unwrap();
+        assert_eq!(storage.object_encoding("words"), Some("listpack"));
+        // So does an element longer than the value limit
+        storage.hset("wide", &[("field".to_string(), "x".repeat(65))]).unwrap();
+        assert_eq!(storage.object_encoding("wide"), Some("hashtable"));
+    }
+
+    #[test]
+    fn test_upgrades_stick_until_the_key_is_deleted() {
+        let mut storage = MemoryStorage::new();
+        storage.set_list_encoding_limits(2, 64);
+        for i in 0..3 {
+            storage.rpush("list", i.to_string());
+        }
+        storage.rpop("list");
+        assert_eq!(storage.llen("list"), 2);
+        assert_eq!(storage.object_encoding("list"), Some("quicklist"));
+
+        // Raised limits leave the upgraded list alone, and only apply to
+        // other lists from their next write
+        let mut limits = storage.encoding_limits();
+        limits.list_max_listpack_size = 10;
+        storage.set_encoding_limits(limits);
+        storage.rpush("list", "2".to_string());
+        assert_eq!(storage.object_encoding("list"), Some("quicklist"));
+
+        storage.del("list");
+        storage.rpush("list", "0".to_string());
+        assert_eq!(storage.object_encoding("list"), Some("listpack"));
+    }
+
+    #[test]
+    fn test_notify_flags_parse_and_format() {
+        assert_eq!(NotifyFlags::parse(""), Ok(NotifyFlags::default()));
+        assert_eq!(NotifyFlags::parse("KEA").unwrap().to_string(), "AKE");
+        assert_eq!(NotifyFlags::parse("g$lshzxetd").unwrap(), NotifyFlags::ALL);
+        assert_eq!(NotifyFlags::parse("Elg").unwrap().to_string(), "glE");
+        assert_eq!(NotifyFlags::parse("Km").unwrap().to_string(), "Km");
+        assert_eq!(NotifyFlags::parse("KEq"), Err('q'));
+        // Classes alone publish nowhere
+        assert!(!NotifyFlags::parse("A").unwrap().publishes(NotifyFlags::STRING));
+        assert!(NotifyFlags::parse("K$").unwrap().publishes(NotifyFlags::STRING));
+        assert!(!NotifyFlags::parse("K$").unwrap().publishes(NotifyFlags::LIST));
+    }
+
+    #[test]
+    fn test_writes_publish_keyspace_events() {
+        let mut storage = MemoryStorage::new();
+        let published = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&published);
+        storage.set_notifier(move |channel, message| sink.lock().unwrap().push(format!("{} {}", channel, message)));
+        let events = |published: &Arc<Mutex<Vec<String>>>| std::mem::take(&mut *published.lock().unwrap());
+
+        // Nothing is published until flags name a channel
+        storage.set("k".to_string(), "v".to_string());
+        assert!(events(&published).is_empty());
+
+        storage.set_notify_flags(NotifyFlags::parse("KEA").unwrap());
+        storage.set("k".to_string(), "v".to_string());
+        assert_eq!(events(&published), vec!["__keyspace@0__:k set", "__keyevent@0__:set k"]);
+
+        storage.set_notify_flags(NotifyFlags::parse("Egxe").unwrap());
+        storage.rpush("list", "a".to_string());
+        storage.expire("list", 60);
+        storage.copy("list", "copy", false).unwrap();
+        storage.expire("k", 0);
+        assert!(storage.expire_if_needed("k"));
+        storage.evict(0, MaxMemoryPolicy::AllKeysRandom, &mut Vec::new());
+        let published = events(&published);
+        assert_eq!(&published[..3], ["__keyevent@0__:expire list", "__keyevent@0__:copy_to copy", "__keyevent@0__:expire k"]);
+        assert_eq!(published[3], "__keyevent@0__:expired k");
+        assert_eq!(published.len(), 6);
+        assert!(published[4..].iter().all(|event| event.starts_with("__keyevent@0__:evicted ")));
+    }
+
+    #[test]
+    fn test_xadd_ids_and_ranges() {
+        let mut storage = MemoryStorage::new();
+        let id = |ms, seq| StreamEntryId { ms, seq };
+        let fields = |value: &str| vec![("kind".to_string(), value.to_string())];
+        assert_eq!(
+            storage.xadd("events", XAddId::Explicit(id(0, 0)), &fields("x")),
+            Err("ERR The ID specified in XADD must be greater than 0-0".to_string())
+        );
+        // The rejected ID didn't leave an empty stream behind
+        assert_eq!(storage.key_type("events"), "none");
+
+        assert_eq!(storage.xadd("Events", XAddId::Explicit(id(5, 1)), &fields("click")), Ok(id(5, 1)));
+        assert_eq!(storage.xadd("events", XAddId::AutoSeq(5), &fields("view")), Ok(id(5, 2)));
+        assert_eq!(storage.xadd("events", XAddId::AutoSeq(7), &fields("scroll")), Ok(id(7, 0)));
+        assert!(storage.xadd("events", XAddId::Explicit(id(7, 0)), &fields("x")).unwrap_err().contains("equal or smaller"));
+        assert!(storage.xadd("events", XAddId::AutoSeq(6), &fields("x")).unwrap_err().contains("equal or smaller"));
+        let generated = storage.xadd("events", XAddId::Auto, &fields("now")).unwrap();
+        assert!(generated > id(7, 0));
+        assert_eq!(storage.xlen("events"), 4);
+        assert_eq!(storage.key_type("events"), "stream");
+        assert_eq!(storage.object_encoding("events"), Some("stream"));
+
+        let range = storage.xrange("events", id(5, 0), id(5, u64::MAX), None);
+        assert_eq!(range, vec![(id(5, 1), fields("click")), (id(5, 2), fields("view"))]);
+        assert_eq!(storage.xrange("events", StreamEntryId::MIN, StreamEntryId::MAX, Some(1)).len(), 1);
+        assert!(storage.xrange("events", id(9, 0), id(1, 0), None).is_empty());
+
+        storage.rpush("list", "a".to_string());
+        assert!(storage.xadd("list", XAddId::Auto, &fields("x")).unwrap_err().starts_with("WRONGTYPE"));
+        assert!(storage.sadd("events", &["x".to_string()]).unwrap_err().starts_with("WRONGTYPE"));
+        assert!(storage.del("events"));
+        assert_eq!(storage.key_type("events"), "none");
+    }
+
+    #[test]
+    fn test_stream_groups_in_transactions_and_snapshots() {
+        let mut storage = MemoryStorage::new();
+        let id = |ms, seq| StreamEntryId { ms, seq };
+        let fields = vec![("kind".to_string(), "click".to_string())];
+        assert!(storage.xgroup_create("events", "readers", None, false).unwrap_err().contains("MKSTREAM"));
+        assert_eq!(storage.xgroup_create("events", "readers", Some(StreamEntryId::MIN), true), Ok(()));
+        // An empty stream made by MKSTREAM still exists
+        assert_eq!(storage.key_type("events"), "stream");
+        assert_eq!(storage.xlen("events"), 0);
+        assert_eq!(
+            storage.xgroup_create("events", "readers", None, false),
+            Err("BUSYGROUP Consumer Group name already exists".to_string())
+        );
+
+        storage.start_transaction();
+        storage.xadd("events", XAddId::Explicit(id(1, 0)), &fields).unwrap();
+        storage.rollback_transaction().unwrap();
+        assert_eq!(storage.xlen("events"), 0);
+        storage.start_transaction();
+        storage.xadd("events", XAddId::Explicit(id(1, 0)), &fields).unwrap();
+        storage.xadd("events", XAddId::Explicit(id(2, 0)), &fields).unwrap();
+        storage.commit_transaction().unwrap();
+        assert_eq!(storage.xlen("events"), 2);
+
+        let read = storage.xreadgroup("events", "readers", "alice", None, Some(1), false).unwrap();
+        assert_eq!(read, vec![(id(1, 0), fields.clone())]);
+        assert_eq!(storage.xreadgroup("events", "readers", "bob", None, None, true).unwrap(), vec![(id(2, 0), fields.clone())]);
+        assert!(storage.xreadgroup("events", "readers", "carol", None, None, false).unwrap().is_empty());
+        // Rereading returns only alice's own pending entries
+        assert_eq!(
+            storage.xreadgroup("events", "readers", "alice", Some(StreamEntryId::MIN), None, false).unwrap(),
+            vec![(id(1, 0), fields.clone())]
+        );
+        assert!(storage.xreadgroup("events", "readers", "bob", Some(StreamEntryId::MIN), None, false).unwrap().is_empty());
+        assert!(storage.xreadgroup("events", "nobody", "alice", None, None, false).unwrap_err().starts_with("NOGROUP"));
+
+        let stream = storage.xinfo("events").unwrap().clone();
+        let group = &stream.groups["readers"];
+        assert_eq!(group.last_delivered_id, id(2, 0));
+        assert_eq!(group.entries_read, Some(2));
+        assert_eq!(stream.lag(group), Some(0));
+        assert_eq!(group.pel.keys().copied().collect::<Vec<_>>(), vec![id(1, 0)]);
+        assert_eq!(group.pel[&id(1, 0)].consumer, "alice");
+        assert_eq!(group.consumers.keys().collect::<Vec<_>>(), ["alice", "bob", "carol"]);
+
+        let path = std::env::temp_dir().join(format!("storage_streams_{}.snapshot", std::process::id()));
+        let path = path.to_str().unwrap();
+        storage.save_snapshot(path).unwrap();
+        let mut restored = MemoryStorage::new();
+        restored.load_snapshot(path).unwrap();
+        let restored_stream = restored.xinfo("events").unwrap();
+        assert_eq!(restored_stream.entries, stream.entries);
+        assert_eq!(restored_stream.last_id, stream.last_id);
+        assert_eq!(restored_stream.entries_added, 2);
+        let restored_group = &restored_stream.groups["readers"];
+        assert_eq!(restored_group.last_delivered_id, id(2, 0));
+        assert_eq!(restored_group.entries_read, Some(2));
+        assert_eq!(restored_group.pel.keys().copied().collect::<Vec<_>>(), vec![id(1, 0)]);
+        assert_eq!(restored_group.consumers.len(), 3);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(storage.xinfo("missing").unwrap_err(), "ERR no such key");
+        storage.flushall();
+        assert_eq!(storage.key_type("events"), "none");
+    }
+
+    #[test]
+    fn test_xautoclaim_scans_the_pel_with_a_cursor() {
+        let mut storage = MemoryStorage::new();
+        let id = |ms, seq| StreamEntryId { ms, seq };
+        let fields = vec![("kind".to_string(), "click".to_string())];
+        for ms in 1..=3 {
+            storage.xadd("events", XAddId::Explicit(id(ms, 0)), &fields).unwrap();
+        }
+        assert!(storage.xautoclaim("events", "readers", "bob", 0, StreamEntryId::MIN, 10).unwrap_err().starts_with("NOGROUP"));
+        storage.xgroup_create("events", "readers", Some(StreamEntryId::MIN), false).unwrap();
+        storage.xreadgroup("events", "readers", "alice", None, None, false).unwrap();
+
+        // Nothing has been idle for an hour
+        assert_eq!(
+            storage.xautoclaim("events", "readers", "bob", 3_600_000, StreamEntryId::MIN, 10),
+            Ok(("0-0".to_string(), Vec::new(), Vec::new()))
+        );
+        assert_eq!(
+            storage.xautoclaim("Events", "readers", "bob", 0, StreamEntryId::MIN, 2),
+            Ok(("3-0".to_string(), vec![(id(1, 0), fields.clone()), (id(2, 0), fields.clone())], Vec::new()))
+        );
+        assert_eq!(
+            storage.xautoclaim("events", "readers", "bob", 0, id(3, 0), 2),
+            Ok(("0-0".to_string(), vec![(id(3, 0), fields.clone())], Vec::new()))
+        );
+
+        let group = &storage.xinfo("events").unwrap().groups["readers"];
+        assert!(group.pel.values().all(|pending| pending.consumer == "bob" && pending.delivery_count == 2));
+        assert_eq!(group.pending_for("alice"), 0);
+        assert!(group.consumers["bob"].active_time.is_some());
+
+        storage.rpush("list", "a".to_string());
+        assert!(storage.xautoclaim("list", "readers", "bob", 0, StreamEntryId::MIN, 10).unwrap_err().starts_with("WRONGTYPE"));
+    }
+
+    #[test]
+    fn test_xdel_keeps_pending_entries_until_claimed() {
+        let mut storage = MemoryStorage::new();
+        let id = |ms, seq| StreamEntryId { ms, seq };
+        let fields = vec![("kind".to_string(), "click".to_string())];
+        for ms in 1..=3 {
+            storage.xadd("events", XAddId::Explicit(id(ms, 0)), &fields).unwrap();
+        }
+        storage.xgroup_create("events", "readers", Some(StreamEntryId::MIN), false).unwrap();
+        storage.xreadgroup("events", "readers", "alice", None, Some(2), false).unwrap();
+
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        assert_eq!(storage.xdel("Events", &ids(&["2-0", "3", "9-0"])), 2);
+        assert_eq!(storage.xdel("events", &ids(&["2-0"])), 0);
+        assert_eq!(storage.xdel("missing", &ids(&["1-0"])), 0);
+        assert_eq!(storage.xlen("events"), 1);
+        assert_eq!(storage.xrange("events", StreamEntryId::MIN, StreamEntryId::MAX, None), vec![(id(1, 0), fields.clone())]);
+
+        let stream = storage.xinfo("events").unwrap();
+        assert_eq!(stream.max_deleted_entry_id, id(3, 0));
+        assert_eq!(stream.last_id, id(3, 0));
+        assert_eq!(stream.entries_added, 3);
+        // 3-0 was never read, and with it gone there's no telling what is left
+        assert_eq!(stream.lag(&stream.groups["readers"]), None);
+        assert_eq!(stream.groups["readers"].pel.len(), 2);
+        // A deleted ID can't be added again
+        assert!(storage.xadd("events", XAddId::Explicit(id(3, 0)), &fields).is_err());
+
+        assert_eq!(
+            storage.xreadgroup("events", "readers", "alice", Some(StreamEntryId::MIN), None, false).unwrap(),
+            vec![(id(1, 0), fields.clone()), (id(2, 0), Vec::new())]
+        );
+        assert_eq!(
+            storage.xautoclaim("events", "readers", "bob", 0, StreamEntryId::MIN, 10),
+            Ok(("0-0".to_string(), vec![(id(1, 0), fields.clone())], vec!["2-0".to_string()]))
+        );
+        assert_eq!(storage.xinfo("events").unwrap().groups["readers"].pel.keys().copied().collect::<Vec<_>>(), vec![id(1, 0)]);
+
+        // Emptied by XDEL, the stream is still there
+        assert_eq!(storage.xdel("events", &ids(&["1-0"])), 1);
+        assert_eq!(storage.key_type("events"), "stream");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,92 @@
+use redis_imitate::cluster::message::RaftMessage;
+use redis_imitate::cluster::transport::{RaftTransport, Transport, MAX_FRAME_SIZE, PROTOCOL_VERSION};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn free_addr() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    /// Starts a transport on a free loopback port whose callback forwards every message
+    async fn listening_transport(node_id: &str) -> (RaftTransport, String, mpsc::UnboundedReceiver<RaftMessage>) {
+        let addr = free_addr().await;
+        let (tx, rx) = mpsc::unbounded_channel();
+        let callback = Arc::new(move |msg| {
+            tx.send(msg).unwrap();
+            Ok(())
+        });
+        let transport = RaftTransport::with_listen_addr(node_id.to_string(), addr.clone(), callback);
+        transport.start().await.unwrap();
+        (transport, addr, rx)
+    }
+
+    /// Waits for the transport to hang up; closing with unread bytes resets the connection
+    async fn assert_closed(stream: &mut TcpStream) {
+        let mut byte = [0u8; 1];
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut byte)).await.unwrap();
+        assert!(!matches!(read, Ok(n) if n > 0), "{:?}", read);
+    }
+
+    #[tokio::test]
+    async fn test_transports_exchange_request_votes() {
+        let (_receiver, addr, mut received) = listening_transport("b").await;
+        let sender = RaftTransport::with_listen_addr("a".to_string(), free_addr().await, Arc::new(|_| Ok(())));
+        sender.add_node("b".to_string(), addr).await.unwrap();
+
+        for term in [1, 2] {
+            let vote = RaftMessage::RequestVote {
+                term,
+                candidate_id: "a".to_string(),
+                last_log_index: 7,
+                last_log_term: 3,
+            };
+            sender.send("b", vote).await.unwrap();
+        }
+
+        // Both frames arrive whole and in order
+        for expected in [1, 2] {
+            let msg = tokio::time::timeout(Duration::from_secs(5), received.recv()).await.unwrap().unwrap();
+            match msg {
+                RaftMessage::RequestVote { term, candidate_id, last_log_index, last_log_term } => {
+                    assert_eq!(term, expected);
+                    assert_eq!(candidate_id, "a");
+                    assert_eq!((last_log_index, last_log_term), (7, 3));
+                }
+                other => panic!("unexpected message {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_closes_the_connection() {
+        let (_receiver, addr, mut received) = listening_transport("b").await;
+        let mut stream = TcpStream::connect(&addr).await.unwrap();
+        stream.write_all(&[PROTOCOL_VERSION]).await.unwrap();
+        stream.write_all(&((MAX_FRAME_SIZE + 1) as u32).to_be_bytes()).await.unwrap();
+
+        assert_closed(&mut stream).await;
+        assert!(received.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_other_protocol_version_closes_the_connection() {
+        let (_receiver, addr, mut received) = listening_transport("b").await;
+        let vote = RaftMessage::RequestVoteResponse { from: "a".to_string(), term: 1, vote_granted: true };
+        let payload = bincode::serialize(&vote).unwrap();
+        let mut stream = TcpStream::connect(&addr).await.unwrap();
+        stream.write_all(&[PROTOCOL_VERSION - 1]).await.unwrap();
+        stream.write_all(&(payload.len() as u32).to_be_bytes()).await.unwrap();
+        stream.write_all(&payload).await.unwrap();
+
+        assert_closed(&mut stream).await;
+        assert!(received.try_recv().is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use redis_imitate::storage::zset::{AggType, LexBound, ScoreBound, SortedSet, ZAddOptions};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(items: &[(f64, &str)]) -> Vec<(f64, String)> {
+        items.iter().map(|(score, member)| (*score, member.to_string())).collect()
+    }
+
+    fn members(zset: &SortedSet) -> Vec<(String, f64)> {
+        zset.iter().map(|(member, score)| (member.to_string(), score)).collect()
+    }
+
+    #[test]
+    fn test_members_are_ordered_by_score_then_member() {
+        let mut zset = SortedSet::new();
+        assert_eq!(zset.add(&pairs(&[(2.0, "b"), (1.0, "z"), (2.0, "a")]), &ZAddOptions::default()), 3);
+        assert_eq!(
+            members(&zset),
+            vec![("z".to_string(), 1.0), ("a".to_string(), 2.0), ("b".to_string(), 2.0)]
+        );
+
+        assert_eq!(zset.insert("z", 3.0), Some(1.0));
+        assert_eq!(zset.iter().next_back(), Some(("z", 3.0)));
+        assert_eq!(zset.remove("a"), Some(2.0));
+        assert_eq!(zset.remove("a"), None);
+        assert_eq!(zset.len(), 2);
+    }
+
+    #[test]
+    fn test_add_options() {
+        let mut zset = SortedSet::new();
+        zset.add(&pairs(&[(5.0, "a")]), &ZAddOptions::default());
+
+        let nx = ZAddOptions { nx: true, ..ZAddOptions::default() };
+        assert_eq!(zset.add(&pairs(&[(1.0, "a"), (1.0, "b")]), &nx), 1);
+        assert_eq!(zset.score("a"), Some(5.0));
+
+        let xx = ZAddOptions { xx: true, ch: true, ..ZAddOptions::default() };
+        assert_eq!(zset.add(&pairs(&[(6.0, "a"), (1.0, "c")]), &xx), 1);
+        assert_eq!(zset.score("a"), Some(6.0));
+        assert_eq!(zset.score("c"), None);
+
+        // GT and LT only restrict updates; new members are still added
+        let gt = ZAddOptions { gt: true, ch: true, ..ZAddOptions::default() };
+        assert_eq!(zset.add(&pairs(&[(4.0, "a"), (2.0, "d")]), &gt), 1);
+        assert_eq!(zset.score("a"), Some(6.0));
+        assert_eq!(zset.add(&pairs(&[(7.0, "a")]), &gt), 1);
+        let lt = ZAddOptions { lt: true, ..ZAddOptions::default() };
+        assert_eq!(zset.add(&pairs(&[(1.0, "a")]), &lt), 0);
+        assert_eq!(zset.score("a"), Some(1.0));
+
+        // Without CH only additions count, and an unchanged score is no change
+        let ch = ZAddOptions { ch: true, ..ZAddOptions::default() };
+        assert_eq!(zset.add(&pairs(&[(1.0, "a")]), &ch), 0);
+    }
+
+    #[test]
+    fn test_increment() {
+        let mut zset = SortedSet::new();
+        let options = ZAddOptions { incr: true, ..ZAddOptions::default() };
+        assert_eq!(zset.increment("a", 2.5, &options), Ok(Some(2.5)));
+        assert_eq!(zset.increment("a", -1.0, &options), Ok(Some(1.5)));
+
+        let nx = ZAddOptions { incr: true, nx: true, ..ZAddOptions::default() };
+        assert_eq!(zset.increment("a", 1.0, &nx), Ok(None));
+        let gt = ZAddOptions { incr: true, gt: true, ..ZAddOptions::default() };
+        assert_eq!(zset.increment("a", -1.0, &gt), Ok(None));
+        assert_eq!(zset.score("a"), Some(1.5));
+
+        zset.insert("inf", f64::INFINITY);
+        assert!(zset.increment("inf", f64::NEG_INFINITY, &options).is_err());
+    }
+
+    #[test]
+    fn test_range_by_score_bounds() {
+        let mut zset = SortedSet::new();
+        zset.add(&pairs(&[(1.0, "a"), (2.0, "b"), (2.0, "c"), (3.0, "d"), (f64::INFINITY, "e"), (f64::NEG_INFINITY, "z")]), &ZAddOptions::default());
+        let range = |min, max| zset.range_by_score(min, max).map(|(member, _)| member.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(range(ScoreBound::NegInf, ScoreBound::PosInf), vec!["z", "a", "b", "c", "d", "e"]);
+        assert_eq!(range(ScoreBound::Inclusive(2.0), ScoreBound::Inclusive(2.0)), vec!["b", "c"]);
+        assert_eq!(range(ScoreBound::Exclusive(1.0), ScoreBound::Exclusive(3.0)), vec!["b", "c"]);
+        assert_eq!(range(ScoreBound::Exclusive(2.0), ScoreBound::Inclusive(3.0)), vec!["d"]);
+        assert_eq!(range(ScoreBound::Exclusive(f64::NEG_INFINITY), ScoreBound::Exclusive(f64::INFINITY)), vec!["a", "b", "c", "d"]);
+        assert_eq!(range(ScoreBound::PosInf, ScoreBound::PosInf), vec!["e"]);
+        assert_eq!(range(ScoreBound::Exclusive(f64::INFINITY), ScoreBound::PosInf), Vec::<String>::new());
+
+        // Empty and inverted ranges match nothing rather than panicking
+        assert_eq!(range(ScoreBound::Exclusive(2.0), ScoreBound::Exclusive(2.0)), Vec::<String>::new());
+        assert_eq!(range(ScoreBound::Inclusive(3.0), ScoreBound::Inclusive(1.0)), Vec::<String>::new());
+        assert_eq!(range(ScoreBound::Inclusive(-0.0), ScoreBound::Exclusive(1.0)), Vec::<String>::new());
+
+        let reversed: Vec<&str> = zset.range_by_score(ScoreBound::Inclusive(1.0), ScoreBound::Inclusive(2.0)).rev().map(|(member, _)| member).collect();
+        assert_eq!(reversed, vec!["c", "b", "a"]);
+    }
+
+    #[test]
+    fn test_pop_min_and_max() {
+        let mut zset = SortedSet::new();
+        zset.add(&pairs(&[(2.0, "b"), (1.0, "a"), (3.0, "c")]), &ZAddOptions::default());
+        assert_eq!(zset.pop_min(), Some(("a".to_string(), 1.0)));
+        assert_eq!(zset.pop_max(), Some(("c".to_string(), 3.0)));
+        assert_eq!(zset.score("a"), None);
+        assert_eq!(members(&zset), vec![("b".to_string(), 2.0)]);
+        assert_eq!(zset.pop_max(), Some(("b".to_string(), 2.0)));
+        assert!(zset.is_empty());
+        assert_eq!(zset.pop_min(), None);
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let mut a = SortedSet::new();
+        a.add(&pairs(&[(1.0, "x"), (2.0, "y"), (3.0, "z")]), &ZAddOptions::default());
+        let mut b = SortedSet::new();
+        b.add(&pairs(&[(10.0, "y"), (20.0, "z"), (30.0, "w")]), &ZAddOptions::default());
+
+        let as_pairs = |zset: SortedSet| members(&zset);
+        assert_eq!(as_pairs(SortedSet::diff(&[Some(&a), Some(&b)])), vec![("x".to_string(), 1.0)]);
+        assert_eq!(as_pairs(SortedSet::diff(&[Some(&a), None])).len(), 3);
+        assert!(SortedSet::diff(&[None, Some(&b)]).is_empty());
+
+        assert_eq!(
+            as_pairs(SortedSet::union(&[Some(&a), Some(&b)], &[], AggType::Sum)),
+            vec![("x".to_string(), 1.0), ("y".to_string(), 12.0), ("z".to_string(), 23.0), ("w".to_string(), 30.0)]
+        );
+        assert_eq!(
+            as_pairs(SortedSet::union(&[Some(&a), None, Some(&b)], &[2.0, 5.0, 0.5], AggType::Max)),
+            vec![("x".to_string(), 2.0), ("y".to_string(), 5.0), ("z".to_string(), 10.0), ("w".to_string(), 15.0)]
+        );
+
+        assert_eq!(
+            as_pairs(SortedSet::inter(&[Some(&a), Some(&b)], &[], AggType::Min)),
+            vec![("y".to_string(), 2.0), ("z".to_string(), 3.0)]
+        );
+        assert_eq!(
+            as_pairs(SortedSet::inter(&[Some(&b), Some(&a)], &[1.0, -1.0], AggType::Sum)),
+            vec![("y".to_string(), 8.0), ("z".to_string(), 17.0)]
+        );
+        assert!(SortedSet::inter(&[Some(&a), None], &[], AggType::Sum).is_empty());
+
+        // inf - inf and 0 * inf would be NaN; both count as 0
+        let mut infinite = SortedSet::new();
+        infinite.insert("y", f64::INFINITY);
+        let mut negative = SortedSet::new();
+        negative.insert("y", f64::NEG_INFINITY);
+        assert_eq!(SortedSet::union(&[Some(&infinite), Some(&negative)], &[], AggType::Sum).score("y"), Some(0.0));
+        assert_eq!(SortedSet::union(&[Some(&infinite)], &[0.0], AggType::Sum).score("y"), Some(0.0));
+    }
+
+    #[test]
+    fn test_range_by_lex_bounds() {
+        let mut zset = SortedSet::new();
+        zset.add(&pairs(&[(0.0, "a"), (0.0, "aa"), (0.0, "b"), (0.0, "c")]), &ZAddOptions::default());
+        let inclusive = |member: &str| LexBound::Inclusive(member.to_string());
+        let exclusive = |member: &str| LexBound::Exclusive(member.to_string());
+        let range = |min: &LexBound, max: &LexBound| zset.range_by_lex(min, max).map(|(member, _)| member).collect::<Vec<_>>();
+
+        // "[a" takes "a" itself, "(a" starts right after it, at "aa"
+        assert_eq!(range(&inclusive("a"), &LexBound::PosInf), vec!["a", "aa", "b", "c"]);
+        assert_eq!(range(&exclusive("a"), &LexBound::PosInf), vec!["aa", "b", "c"]);
+        assert_eq!(range(&LexBound::NegInf, &exclusive("b")), vec!["a", "aa"]);
+        assert_eq!(range(&LexBound::NegInf, &inclusive("b")), vec!["a", "aa", "b"]);
+        assert_eq!(range(&inclusive("ab"), &inclusive("bz")), vec!["b"]);
+        assert!(range(&LexBound::PosInf, &LexBound::NegInf).is_empty());
+        assert!(range(&inclusive("c"), &inclusive("a")).is_empty());
+
+        let reversed: Vec<&str> = zset.rev_range_by_lex(&exclusive("c"), &inclusive("aa")).map(|(member, _)| member).collect();
+        assert_eq!(reversed, vec!["b", "aa"]);
+    }
+}